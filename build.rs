@@ -0,0 +1,83 @@
+//! Generates the Unicode general category tables
+//!
+//! Each category iXML can name (`L`, `Lu`, `LC`, ...) becomes a sorted list of
+//! character ranges in `$OUT_DIR/unicode_categories.rs`, which
+//! `charclass::unicode_category_to_rangeset` includes. Deriving the ranges here
+//! instead of at run time means a grammar's first `[L]` costs a copy rather
+//! than a scan of every code point.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use unicode_general_category::get_general_category;
+
+/// Every category name iXML accepts, in the order the table lists them
+const CATEGORIES: [&str; 38] = [
+    "L", "M", "N", "P", "S", "Z", "C", "Lu", "Ll", "Lt", "Lm", "Lo", "LC", "Mn", "Mc", "Me", "Nd",
+    "Nl", "No", "Pc", "Pd", "Ps", "Pe", "Pi", "Pf", "Po", "Sm", "Sc", "Sk", "So", "Zs", "Zl", "Zp",
+    "Cc", "Cf", "Cs", "Co", "Cn",
+];
+
+/// Whether a character of category `abbreviation` (e.g. `Lu`) is in `name`
+fn in_category(name: &str, abbreviation: &str, ch: char) -> bool {
+    // iXML excludes newlines from the control categories, so grammars can
+    // handle line ends separately
+    if matches!(name, "C" | "Cc") && matches!(ch, '\n' | '\r') {
+        return false;
+    }
+    match name {
+        "LC" => matches!(abbreviation, "Lu" | "Ll" | "Lt"),
+        _ if name.len() == 1 => abbreviation.starts_with(name),
+        _ => abbreviation == name,
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // The minimal-unicode feature approximates categories without tables
+    if env::var_os("CARGO_FEATURE_MINIMAL_UNICODE").is_some() {
+        return;
+    }
+
+    let mut tables: HashMap<&str, Vec<(char, char)>> = HashMap::new();
+    for ch in '\0'..=char::MAX {
+        let abbreviation = get_general_category(ch).abbreviation();
+        // Only the character's own category, its major category and `LC`
+        // can contain it
+        for name in [abbreviation, &abbreviation[..1], "LC"] {
+            if !in_category(name, abbreviation, ch) {
+                continue;
+            }
+            let ranges = tables.entry(name).or_default();
+            // Extend the last range if nothing but surrogates (which are not
+            // characters) lies between it and `ch`
+            match ranges.last_mut() {
+                Some((_, end)) if char::from_u32(*end as u32 + 1).unwrap_or('\u{E000}') == ch => {
+                    *end = ch;
+                }
+                _ => ranges.push((ch, ch)),
+            }
+        }
+    }
+
+    let mut table = String::from(
+        "/// Ranges of the Unicode general category `name`, sorted; `None` if iXML\n\
+         /// has no such category. Generated by `build.rs`.\n\
+         fn category_table(name: &str) -> Option<&'static [(char, char)]> {\n    \
+         Some(match name {\n",
+    );
+    for name in CATEGORIES {
+        write!(table, "        {:?} => &[", name).unwrap();
+        for (start, end) in tables.get(name).into_iter().flatten() {
+            write!(table, "({:?}, {:?}), ", start, end).unwrap();
+        }
+        table.push_str("],\n");
+    }
+    table.push_str("        _ => return None,\n    })\n}\n");
+
+    let out_dir = env::var_os("OUT_DIR").expect("cargo sets OUT_DIR for build scripts");
+    fs::write(Path::new(&out_dir).join("unicode_categories.rs"), table)
+        .expect("write unicode_categories.rs");
+}
@@ -2,7 +2,7 @@
 
 use rustixml::{parse_ixml_grammar, NativeParser};
 
-fn main() -> Result<(), String> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== rustixml Basic Usage Example ===\n");
 
     // Example 1: Simple greeting
@@ -0,0 +1,74 @@
+//! TOML-to-XML converter built on rustixml
+//!
+//! Parses a TOML subset with the grammar in `toml.ixml`, then prints the XML,
+//! a typed JSON rendering, or normalized TOML:
+//!
+//! ```text
+//! cargo run --example toml_to_xml -- examples/toml_to_xml/sample.toml
+//! cargo run --example toml_to_xml -- --json < examples/toml_to_xml/sample.toml
+//! cargo run --example toml_to_xml -- --toml examples/toml_to_xml/sample.toml
+//! ```
+//!
+//! The same grammar works with the command line tool directly:
+//!
+//! ```text
+//! cargo run --bin rustixml -- examples/toml_to_xml/toml.ixml examples/toml_to_xml/sample.toml
+//! ```
+//!
+//! `tests/toml.rs` checks every step of this pipeline.
+
+mod mapping;
+
+use mapping::Document;
+use std::io::Read;
+use std::{env, fs, process};
+
+fn main() {
+    let mut output = "xml";
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => output = "json",
+            "--toml" => output = "toml",
+            _ => path = Some(arg),
+        }
+    }
+
+    let mut input = match &path {
+        Some(path) => fs::read_to_string(path).unwrap_or_else(|e| fail(&e.to_string())),
+        None => {
+            let mut input = String::new();
+            std::io::stdin()
+                .read_to_string(&mut input)
+                .unwrap_or_else(|e| fail(&e.to_string()));
+            input
+        }
+    };
+    // The grammar expects every line, including the last, to end with a newline
+    if !input.ends_with('\n') {
+        input.push('\n');
+    }
+
+    let parser = mapping::parser();
+    match output {
+        "xml" => println!(
+            "{}",
+            parser
+                .parse(&input)
+                .unwrap_or_else(|e| fail(&e.to_string()))
+        ),
+        _ => {
+            let document = Document::parse(&parser, &input).unwrap_or_else(|e| fail(&e));
+            if output == "json" {
+                println!("{}", document.to_json());
+            } else {
+                print!("{}", document.to_toml());
+            }
+        }
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
@@ -0,0 +1,223 @@
+//! Typed view of the XML produced by `toml.ixml`
+//!
+//! The grammar does the syntax work (hiding punctuation, turning keys and
+//! table names into attributes, dropping `_` digit separators); this module
+//! only walks the resulting tree into Rust types and writes them back out as
+//! TOML or JSON.
+
+use rustixml::{parse_ixml_grammar, NativeParser, XmlNode};
+
+/// The TOML subset grammar
+pub const GRAMMAR: &str = include_str!("toml.ixml");
+
+/// A TOML value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    /// Local date, kept as written (`YYYY-MM-DD`)
+    Date(String),
+    Array(Vec<Value>),
+}
+
+/// Key/value pairs under one `[table]` header, or before the first one
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Table {
+    /// Dotted table name; `None` for the root table
+    pub name: Option<String>,
+    pub entries: Vec<(String, Value)>,
+}
+
+/// A parsed document: the root table followed by each `[table]` in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub tables: Vec<Table>,
+}
+
+/// Build a parser for the TOML grammar
+pub fn parser() -> NativeParser {
+    NativeParser::new(parse_ixml_grammar(GRAMMAR).expect("toml.ixml is valid"))
+}
+
+impl Document {
+    /// Parse TOML text (which must end with a newline)
+    pub fn parse(parser: &NativeParser, input: &str) -> Result<Self, String> {
+        let tree = parser
+            .parse_to_tree(input)
+            .map_err(|e| e.format_with_context(input))?;
+        Self::from_xml(&tree)
+    }
+
+    /// Map the `<toml>` element produced by the grammar
+    pub fn from_xml(node: &XmlNode) -> Result<Self, String> {
+        let tables = children_of(node, "toml")?
+            .iter()
+            .map(|table| {
+                let (name, entries) = match table {
+                    XmlNode::Element { name, .. } if name == "root" => {
+                        (None, children_of(table, "root")?)
+                    }
+                    _ => (
+                        table.attribute("name").map(str::to_string),
+                        children_of(table, "table")?,
+                    ),
+                };
+                let entries = entries.iter().map(keyval).collect::<Result<_, _>>()?;
+                Ok(Table { name, entries })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Document { tables })
+    }
+
+    /// Write the document back as TOML
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+        for table in &self.tables {
+            if let Some(name) = &table.name {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(&format!("[{}]\n", name));
+            }
+            for (key, value) in &table.entries {
+                let key = if key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-".contains(c))
+                {
+                    key.clone()
+                } else {
+                    format!("\"{}\"", key)
+                };
+                out.push_str(&format!("{} = {}\n", key, toml_value(value)));
+            }
+        }
+        out
+    }
+
+    /// Write the document as a JSON object, one member per root key and per table
+    pub fn to_json(&self) -> String {
+        let mut members = Vec::new();
+        for table in &self.tables {
+            let entries = table
+                .entries
+                .iter()
+                .map(|(key, value)| format!("{}:{}", json_string(key), json_value(value)));
+            match &table.name {
+                None => members.extend(entries),
+                Some(name) => members.push(format!(
+                    "{}:{{{}}}",
+                    json_string(name),
+                    entries.collect::<Vec<_>>().join(",")
+                )),
+            }
+        }
+        format!("{{{}}}", members.join(","))
+    }
+}
+
+/// Children of an element that must be named `expected`
+fn children_of<'a>(node: &'a XmlNode, expected: &str) -> Result<&'a [XmlNode], String> {
+    match node {
+        XmlNode::Element { name, children, .. } if name == expected => Ok(children),
+        other => Err(format!("Expected <{}>, got {:?}", expected, other)),
+    }
+}
+
+fn keyval(node: &XmlNode) -> Result<(String, Value), String> {
+    let key = node
+        .attribute("key")
+        .ok_or_else(|| format!("Key/value pair without a key: {:?}", node))?;
+    match children_of(node, "keyval")? {
+        [value] => Ok((key.to_string(), value_of(value)?)),
+        other => Err(format!("Expected one value for '{}', got {:?}", key, other)),
+    }
+}
+
+fn value_of(node: &XmlNode) -> Result<Value, String> {
+    let XmlNode::Element { name, children, .. } = node else {
+        return Err(format!("Expected a value element, got {:?}", node));
+    };
+    let text = node.text_content();
+    let value = match name.as_str() {
+        "string" => Value::String(unescape(&text)),
+        "integer" => Value::Integer(text.parse().map_err(|e| format!("{}: {}", text, e))?),
+        "float" => Value::Float(text.parse().map_err(|e| format!("{}: {}", text, e))?),
+        "boolean" => Value::Boolean(text == "true"),
+        "date" => Value::Date(text),
+        "array" => Value::Array(children.iter().map(value_of).collect::<Result<_, _>>()?),
+        other => return Err(format!("Unknown value type <{}>", other)),
+    };
+    Ok(value)
+}
+
+/// Resolve the escapes the grammar accepts inside strings
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(escaped) => out.push(escaped),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn toml_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => {
+            let escaped = s
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+                .replace('\t', "\\t");
+            format!("\"{}\"", escaped)
+        }
+        Value::Integer(n) => n.to_string(),
+        // Debug formatting always keeps a fraction or exponent, so floats stay floats
+        Value::Float(x) => format!("{:?}", x),
+        Value::Boolean(b) => b.to_string(),
+        Value::Date(d) => d.clone(),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(toml_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+    }
+}
+
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::String(s) | Value::Date(s) => json_string(s),
+        Value::Integer(n) => n.to_string(),
+        Value::Float(x) => x.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(json_value).collect();
+            format!("[{}]", items.join(","))
+        }
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -2,34 +2,65 @@
 //!
 //! This module defines the data structures representing parsed iXML grammars.
 
+use crate::capabilities::SUPPORTED_VERSIONS;
+use crate::formatter::FormatOptions;
+use std::fmt;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct IxmlGrammar {
     pub rules: Vec<Rule>,
+    /// Version from an `ixml version "..."` prolog, if the grammar has one
+    pub version: Option<String>,
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Rule {
     pub name: String,
     pub mark: Mark,
     pub alternatives: Alternatives,
+    /// Pragmas written before the rule
+    pub pragmas: Vec<Pragma>,
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Alternatives {
     pub alts: Vec<Sequence>,
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Sequence {
     pub factors: Vec<Factor>,
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Factor {
     pub base: BaseFactor,
     pub repetition: Repetition,
+    /// Pragmas written before the factor
+    pub pragmas: Vec<Pragma>,
+}
+
+/// An annotation written `{[name data]}` before a rule or factor
+///
+/// Parsing ignores pragmas, and the only ones rustixml acts on are
+/// `fixed-attribute` and `namespace` (see [`crate::xml_node::FixedAttributes`]),
+/// and `include` (see [`crate::compose`]). Applications
+/// read them with [`IxmlGrammar::visit_pragmas`] or [`Rule::pragma`] /
+/// [`Factor::pragma`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pragma {
+    pub name: String,
+    /// Everything after the name, trimmed (may be empty)
+    pub data: String,
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub enum BaseFactor {
     Literal {
@@ -42,6 +73,8 @@ pub enum BaseFactor {
         mark: Mark,
     },
     CharClass {
+        /// Members between the brackets, e.g. `"a"-"z"; L; ~"aeiou"`; members
+        /// prefixed with `~` are subtracted from the rest
         content: String,
         negated: bool, // true if ~[...]
         mark: Mark,    // mark for the charclass (@, -, ^)
@@ -49,8 +82,14 @@ pub enum BaseFactor {
     Group {
         alternatives: Box<Alternatives>,
     },
+    /// Non-standard extension: `!factor` succeeds without consuming input
+    /// only if `factor` does not match at the current position
+    NegativeLookahead {
+        base: Box<BaseFactor>,
+    },
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq, Copy, Eq, Hash)]
 pub enum Mark {
     None,      // no mark
@@ -59,6 +98,7 @@ pub enum Mark {
     Promoted,  // ^name - promoted (replaces parent)
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Repetition {
     None,                               // no repetition
@@ -71,7 +111,133 @@ pub enum Repetition {
 
 impl IxmlGrammar {
     pub fn new(rules: Vec<Rule>) -> Self {
-        IxmlGrammar { rules }
+        IxmlGrammar {
+            rules,
+            version: None,
+        }
+    }
+
+    /// Set the version declared in the grammar's prolog
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Whether the grammar declares an iXML version rustixml does not implement
+    ///
+    /// Parses with such a grammar still proceed, but the output is marked
+    /// `ixml:state="version-mismatch"` as the spec requires.
+    pub fn is_version_mismatch(&self) -> bool {
+        self.version
+            .as_deref()
+            .is_some_and(|version| !SUPPORTED_VERSIONS.contains(&version))
+    }
+
+    /// Call `f` for every pragma in the grammar, in source order
+    ///
+    /// `f` receives the rule the pragma appears in and, for factor pragmas,
+    /// the factor it is attached to (`None` for pragmas on the rule itself).
+    ///
+    /// ```
+    /// use rustixml::parse_ixml_grammar;
+    ///
+    /// let grammar = parse_ixml_grammar(r#"{[doc root]} s: {[hint fast]} "a"."#).unwrap();
+    /// let mut seen = Vec::new();
+    /// grammar.visit_pragmas(|rule, factor, pragma| {
+    ///     seen.push((rule.name.clone(), factor.is_some(), pragma.name.clone(), pragma.data.clone()));
+    /// });
+    /// assert_eq!(seen, [
+    ///     ("s".to_string(), false, "doc".to_string(), "root".to_string()),
+    ///     ("s".to_string(), true, "hint".to_string(), "fast".to_string()),
+    /// ]);
+    /// ```
+    pub fn visit_pragmas(&self, mut f: impl FnMut(&Rule, Option<&Factor>, &Pragma)) {
+        for rule in &self.rules {
+            for pragma in &rule.pragmas {
+                f(rule, None, pragma);
+            }
+            visit_alternatives_pragmas(&rule.alternatives, &mut |factor, pragma| {
+                f(rule, Some(factor), pragma)
+            });
+        }
+    }
+
+    /// Call `f` for every reference to a rule, in source order
+    ///
+    /// `f` receives the rule the reference appears in and the name referred to.
+    ///
+    /// ```
+    /// use rustixml::parse_ixml_grammar;
+    ///
+    /// let grammar = parse_ixml_grammar(r#"s: a, (b; -a)+. a: "x". b: s."#).unwrap();
+    /// let mut seen = Vec::new();
+    /// grammar.visit_references(|rule, name| seen.push(format!("{}>{}", rule.name, name)));
+    /// assert_eq!(seen, ["s>a", "s>b", "s>a", "b>s"]);
+    /// ```
+    pub fn visit_references(&self, mut f: impl FnMut(&Rule, &str)) {
+        self.visit_marked_references(|rule, name, _| f(rule, name));
+    }
+
+    /// [`IxmlGrammar::visit_references`], with the mark written on each reference
+    pub(crate) fn visit_marked_references(&self, mut f: impl FnMut(&Rule, &str, Mark)) {
+        for rule in &self.rules {
+            visit_alternatives_references(&rule.alternatives, &mut |name, mark| {
+                f(rule, name, mark)
+            });
+        }
+    }
+
+    /// The defined rule name closest to `name`, if it looks like a typo of it
+    pub fn closest_rule(&self, name: &str) -> Option<&str> {
+        crate::suggest::closest(name, self.rules.iter().map(|rule| rule.name.as_str()))
+    }
+}
+
+pub(crate) fn visit_alternatives_references(alts: &Alternatives, f: &mut impl FnMut(&str, Mark)) {
+    for seq in &alts.alts {
+        visit_sequence_references(seq, f);
+    }
+}
+
+fn visit_sequence_references(seq: &Sequence, f: &mut impl FnMut(&str, Mark)) {
+    for factor in &seq.factors {
+        visit_base_references(&factor.base, f);
+        if let Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) =
+            &factor.repetition
+        {
+            visit_sequence_references(sep, f);
+        }
+    }
+}
+
+fn visit_base_references(base: &BaseFactor, f: &mut impl FnMut(&str, Mark)) {
+    match base {
+        BaseFactor::Nonterminal { name, mark } => f(name, *mark),
+        BaseFactor::Group { alternatives } => visit_alternatives_references(alternatives, f),
+        BaseFactor::NegativeLookahead { base } => visit_base_references(base, f),
+        BaseFactor::Literal { .. } | BaseFactor::CharClass { .. } => {}
+    }
+}
+
+fn visit_alternatives_pragmas(alts: &Alternatives, f: &mut impl FnMut(&Factor, &Pragma)) {
+    for seq in &alts.alts {
+        visit_sequence_pragmas(seq, f);
+    }
+}
+
+fn visit_sequence_pragmas(seq: &Sequence, f: &mut impl FnMut(&Factor, &Pragma)) {
+    for factor in &seq.factors {
+        for pragma in &factor.pragmas {
+            f(factor, pragma);
+        }
+        if let BaseFactor::Group { alternatives } = &factor.base {
+            visit_alternatives_pragmas(alternatives, f);
+        }
+        if let Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) =
+            &factor.repetition
+        {
+            visit_sequence_pragmas(sep, f);
+        }
     }
 }
 
@@ -81,8 +247,20 @@ impl Rule {
             name,
             mark,
             alternatives,
+            pragmas: vec![],
         }
     }
+
+    /// Attach pragmas to the rule
+    pub fn with_pragmas(mut self, pragmas: Vec<Pragma>) -> Self {
+        self.pragmas = pragmas;
+        self
+    }
+
+    /// The first pragma on the rule with the given name
+    pub fn pragma(&self, name: &str) -> Option<&Pragma> {
+        self.pragmas.iter().find(|pragma| pragma.name == name)
+    }
 }
 
 impl Alternatives {
@@ -107,15 +285,48 @@ impl Sequence {
 
 impl Factor {
     pub fn new(base: BaseFactor, repetition: Repetition) -> Self {
-        Factor { base, repetition }
+        Factor {
+            base,
+            repetition,
+            pragmas: vec![],
+        }
     }
 
     pub fn simple(base: BaseFactor) -> Self {
-        Factor {
-            base,
-            repetition: Repetition::None,
+        Self::new(base, Repetition::None)
+    }
+
+    /// Attach pragmas to the factor
+    pub fn with_pragmas(mut self, pragmas: Vec<Pragma>) -> Self {
+        self.pragmas = pragmas;
+        self
+    }
+
+    /// The first pragma on the factor with the given name
+    pub fn pragma(&self, name: &str) -> Option<&Pragma> {
+        self.pragmas.iter().find(|pragma| pragma.name == name)
+    }
+}
+
+impl Pragma {
+    pub fn new(name: impl Into<String>, data: impl Into<String>) -> Self {
+        Pragma {
+            name: name.into(),
+            data: data.into(),
         }
     }
+
+    /// Split the text between `{[` and `]}` into name and data
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let content = content.trim();
+        let (name, data) = content
+            .split_once(char::is_whitespace)
+            .unwrap_or((content, ""));
+        if name.is_empty() {
+            return Err("Pragma without a name".to_string());
+        }
+        Ok(Pragma::new(name, data.trim()))
+    }
 }
 
 impl BaseFactor {
@@ -183,4 +394,159 @@ impl BaseFactor {
             alternatives: Box::new(alternatives),
         }
     }
+
+    pub fn negative_lookahead(base: BaseFactor) -> Self {
+        BaseFactor::NegativeLookahead {
+            base: Box::new(base),
+        }
+    }
+}
+
+// Display renders grammars back to iXML notation, one rule per line
+
+impl fmt::Display for IxmlGrammar {
+    /// The grammar as [`IxmlGrammar::to_ixml_source`] formats it by default
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_ixml_source(&FormatOptions::default()))
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for pragma in &self.pragmas {
+            write!(f, "{} ", pragma)?;
+        }
+        write!(f, "{}{}: {}.", self.mark, self.name, self.alternatives)
+    }
+}
+
+impl fmt::Display for Alternatives {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, seq) in self.alts.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", seq)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, factor) in self.factors.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", factor)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pragma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.data.is_empty() {
+            write!(f, "{{[{}]}}", self.name)
+        } else {
+            write!(f, "{{[{} {}]}}", self.name, self.data)
+        }
+    }
+}
+
+impl fmt::Display for Factor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for pragma in &self.pragmas {
+            write!(f, "{} ", pragma)?;
+        }
+        write!(f, "{}", self.base)?;
+        match &self.repetition {
+            Repetition::None => Ok(()),
+            Repetition::Optional => f.write_str("?"),
+            Repetition::ZeroOrMore => f.write_str("*"),
+            Repetition::OneOrMore => f.write_str("+"),
+            Repetition::SeparatedZeroOrMore(sep) => write!(f, "**({})", sep),
+            Repetition::SeparatedOneOrMore(sep) => write!(f, "++({})", sep),
+        }
+    }
+}
+
+impl fmt::Display for BaseFactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BaseFactor::Literal {
+                value,
+                insertion,
+                mark,
+            } => {
+                let prefix = if *insertion {
+                    "+".to_string()
+                } else {
+                    mark.to_string()
+                };
+                f.write_str(&literal_source(&prefix, value, '"'))
+            }
+            BaseFactor::Nonterminal { name, mark } => write!(f, "{}{}", mark, name),
+            BaseFactor::CharClass {
+                content,
+                negated,
+                mark,
+            } => write!(
+                f,
+                "{}{}[{}]",
+                mark,
+                if *negated { "~" } else { "" },
+                content
+            ),
+            BaseFactor::Group { alternatives } => write!(f, "({})", alternatives),
+            BaseFactor::NegativeLookahead { base } => write!(f, "!{}", base),
+        }
+    }
+}
+
+impl fmt::Display for Mark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mark::None => "",
+            Mark::Attribute => "@",
+            Mark::Hidden => "-",
+            Mark::Promoted => "^",
+        })
+    }
+}
+
+/// A literal as a string in `quote`s, using hex characters for control
+/// characters (which can't appear inside iXML strings) and for whitespace
+/// other than a plain space (which would be invisible, e.g. `#a0`); a literal
+/// that needs several parts is grouped so repetitions still apply to all of it
+pub(crate) fn literal_source(prefix: &str, value: &str, quote: char) -> String {
+    let mut parts = Vec::new();
+    let mut quoted = String::new();
+    for ch in value.chars() {
+        if ch.is_control() || (ch.is_whitespace() && ch != ' ') {
+            if !quoted.is_empty() {
+                parts.push(format!(
+                    "{}{q}{}{q}",
+                    prefix,
+                    std::mem::take(&mut quoted),
+                    q = quote
+                ));
+            }
+            parts.push(format!("{}#{:x}", prefix, ch as u32));
+        } else {
+            if ch == quote {
+                quoted.push(quote);
+            }
+            quoted.push(ch);
+        }
+    }
+    if !quoted.is_empty() || parts.is_empty() {
+        parts.push(format!("{}{q}{}{q}", prefix, quoted, q = quote));
+    }
+
+    if parts.len() == 1 {
+        parts.remove(0)
+    } else {
+        format!("({})", parts.join(", "))
+    }
 }
@@ -0,0 +1,813 @@
+//! Command-line definitions for the CLI, and what is generated from them
+//!
+//! Each subcommand is a [`Command`] listing its options and operands; the
+//! same definitions parse the arguments, write `--help`, and produce shell
+//! completion scripts and the man page, so those cannot drift apart.
+
+use std::fmt::Write;
+
+/// Width help text is wrapped to
+const WIDTH: usize = 80;
+/// Column where option descriptions start
+const HELP_COLUMN: usize = 21;
+
+/// A subcommand, or the default command when `name` is empty
+pub struct Command {
+    pub name: &'static str,
+    about: String,
+    options: Vec<Opt>,
+    operands: Vec<Operand>,
+}
+
+/// An option such as `--format <FORMAT>` or `-n, --count <N>`
+pub struct Opt {
+    long: &'static str,
+    short: Option<char>,
+    /// What the value stands for; flags take none
+    value: Option<&'static str>,
+    /// The values allowed, if limited
+    choices: Vec<&'static str>,
+    help: String,
+}
+
+struct Operand {
+    name: &'static str,
+    optional: bool,
+    choices: Vec<&'static str>,
+}
+
+/// `--help` and `-h`, which every command takes
+fn help_option() -> Opt {
+    Opt::flag("help", "show this help message.").with_short('h')
+}
+
+impl Command {
+    pub fn new(name: &'static str, about: impl Into<String>) -> Self {
+        Command {
+            name,
+            about: about.into(),
+            options: vec![help_option()],
+            operands: Vec::new(),
+        }
+    }
+
+    pub fn with_option(mut self, option: Opt) -> Self {
+        // Keep --help last
+        let help = self.options.len() - 1;
+        self.options.insert(help, option);
+        self
+    }
+
+    pub fn with_options(self, options: impl IntoIterator<Item = Opt>) -> Self {
+        options
+            .into_iter()
+            .fold(self, |command, option| command.with_option(option))
+    }
+
+    pub fn with_operand(mut self, name: &'static str) -> Self {
+        self.operands.push(Operand {
+            name,
+            optional: false,
+            choices: Vec::new(),
+        });
+        self
+    }
+
+    pub fn with_optional_operand(mut self, name: &'static str) -> Self {
+        self.operands.push(Operand {
+            name,
+            optional: true,
+            choices: Vec::new(),
+        });
+        self
+    }
+
+    /// A required operand that must be one of `choices`
+    pub fn with_operand_choices(mut self, name: &'static str, choices: &[&'static str]) -> Self {
+        self.operands.push(Operand {
+            name,
+            optional: false,
+            choices: choices.to_vec(),
+        });
+        self
+    }
+
+    pub fn about(&self) -> &str {
+        &self.about
+    }
+
+    fn option(&self, long: &str) -> Option<&Opt> {
+        self.options.iter().find(|option| option.long == long)
+    }
+
+    /// `rustixml bench [-n <N>] <GRAMMAR> <INPUT>`; commands with more than
+    /// three options show `[<OPTION>...]` instead of them
+    pub fn usage(&self, program: &str) -> String {
+        let mut usage = program.to_string();
+        if !self.name.is_empty() {
+            usage.push(' ');
+            usage.push_str(self.name);
+        }
+        let options = &self.options[..self.options.len() - 1];
+        if options.len() > 3 {
+            usage.push_str(" [<OPTION>...]");
+        } else {
+            for option in options {
+                let _ = write!(usage, " [{}]", option.synopsis());
+            }
+        }
+        for operand in &self.operands {
+            if operand.optional {
+                let _ = write!(usage, " [<{}>]", operand.name);
+            } else {
+                let _ = write!(usage, " <{}>", operand.name);
+            }
+        }
+        usage
+    }
+
+    /// The options, one per entry with its description wrapped beside it
+    pub fn options_help(&self, indent: usize) -> String {
+        self.options
+            .iter()
+            .map(|option| entry(indent, &option.label(), &option.help))
+            .collect()
+    }
+
+    /// `--help` for this command alone
+    pub fn help(&self, program: &str) -> String {
+        let mut out = format!("Usage: {}\n\n", self.usage(program));
+        out.push_str(&wrap(&self.about, "  ", "  "));
+        out.push_str("\n  Options:\n");
+        out.push_str(&self.options_help(4));
+        out
+    }
+
+    /// Split `args` into options and operands
+    ///
+    /// `--` makes the rest operands, and `-` alone is an operand (standard
+    /// input). As soon as `--help` or `-h` is seen the rest is left
+    /// unchecked.
+    pub fn parse(&self, args: &[String]) -> Result<Matches, String> {
+        let mut matches = Matches {
+            options: Vec::new(),
+            operands: Vec::new(),
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let (option, attached) = if arg == "--" {
+                matches.operands.extend(iter.by_ref().cloned());
+                break;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let option = self
+                    .option(name)
+                    .ok_or_else(|| format!("Unknown option '--{}'", name))?;
+                (option, value)
+            } else if arg.len() > 1 && arg.starts_with('-') {
+                let mut chars = arg[1..].chars();
+                let short = chars.next();
+                let option = self
+                    .options
+                    .iter()
+                    .find(|option| option.short.is_some() && option.short == short)
+                    .ok_or_else(|| format!("Unknown option '{}'", arg))?;
+                let rest = chars.as_str();
+                (
+                    option,
+                    Some(rest.to_string()).filter(|rest| !rest.is_empty()),
+                )
+            } else {
+                matches.operands.push(arg.clone());
+                continue;
+            };
+
+            if option.long == "help" {
+                matches.options.push(("help", None));
+                return Ok(matches);
+            }
+            let value = match (option.value, attached) {
+                (None, None) => None,
+                (None, Some(_)) => return Err(format!("--{} takes no value", option.long)),
+                (Some(_), Some(value)) => Some(value),
+                (Some(placeholder), None) => match iter.next() {
+                    Some(value) => Some(value.clone()),
+                    None => return Err(format!("--{} needs <{}>", option.long, placeholder)),
+                },
+            };
+            if let Some(value) = &value {
+                if !option.choices.is_empty() && !option.choices.contains(&value.as_str()) {
+                    return Err(format!(
+                        "Invalid value '{}' for --{} (expected {})",
+                        value,
+                        option.long,
+                        option.choices.join(", ")
+                    ));
+                }
+            }
+            matches.options.push((option.long, value));
+        }
+
+        let required = self.operands.iter().filter(|o| !o.optional).count();
+        if let Some(missing) = self.operands.get(matches.operands.len()) {
+            if matches.operands.len() < required {
+                return Err(format!("Missing <{}>", missing.name));
+            }
+        }
+        if let Some(extra) = matches.operands.get(self.operands.len()) {
+            return Err(format!("Unexpected argument '{}'", extra));
+        }
+        for (operand, value) in self.operands.iter().zip(&matches.operands) {
+            if !operand.choices.is_empty() && !operand.choices.contains(&value.as_str()) {
+                return Err(format!(
+                    "Invalid <{}> '{}' (expected {})",
+                    operand.name,
+                    value,
+                    operand.choices.join(", ")
+                ));
+            }
+        }
+        Ok(matches)
+    }
+}
+
+impl Opt {
+    /// An option without a value
+    pub fn flag(long: &'static str, help: impl Into<String>) -> Self {
+        Opt {
+            long,
+            short: None,
+            value: None,
+            choices: Vec::new(),
+            help: help.into(),
+        }
+    }
+
+    /// An option followed by a value, written `<PLACEHOLDER>` in help
+    pub fn value(long: &'static str, placeholder: &'static str, help: impl Into<String>) -> Self {
+        Opt {
+            value: Some(placeholder),
+            ..Opt::flag(long, help)
+        }
+    }
+
+    pub fn with_short(mut self, short: char) -> Self {
+        self.short = Some(short);
+        self
+    }
+
+    pub fn with_choices(mut self, choices: &[&'static str]) -> Self {
+        self.choices = choices.to_vec();
+        self
+    }
+
+    /// `--format <FORMAT>`, `-o <OUTPUT>` for options with a short form, or
+    /// `--format dot|svg` when the values are few
+    fn synopsis(&self) -> String {
+        let name = match self.short {
+            Some(short) => format!("-{}", short),
+            None => format!("--{}", self.long),
+        };
+        match self.value {
+            None => name,
+            Some(_) if (1..=3).contains(&self.choices.len()) => {
+                format!("{} {}", name, self.choices.join("|"))
+            }
+            Some(placeholder) => format!("{} <{}>", name, placeholder),
+        }
+    }
+
+    /// `-n, --count <N>`, as the option is listed in help
+    fn label(&self) -> String {
+        let long = match self.value {
+            Some(placeholder) => format!("--{} <{}>", self.long, placeholder),
+            None => format!("--{}", self.long),
+        };
+        match self.short {
+            Some(short) => format!("-{}, {}", short, long),
+            None => long,
+        }
+    }
+}
+
+/// The options and operands given to a command
+pub struct Matches {
+    /// Options in the order given, with their values
+    options: Vec<(&'static str, Option<String>)>,
+    pub operands: Vec<String>,
+}
+
+impl Matches {
+    pub fn help(&self) -> bool {
+        self.flag("help")
+    }
+
+    pub fn flag(&self, long: &str) -> bool {
+        self.options.iter().any(|(name, _)| *name == long)
+    }
+
+    /// The value of the last `long` option given
+    pub fn value(&self, long: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == long)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    /// Which of `longs` was given last, for options that override each other
+    pub fn last_of(&self, longs: &[&str]) -> Option<&'static str> {
+        self.options
+            .iter()
+            .rev()
+            .map(|(name, _)| *name)
+            .find(|name| longs.contains(name))
+    }
+
+    /// The value of `long` as a number
+    pub fn number<T: std::str::FromStr>(&self, long: &str) -> Result<Option<T>, String> {
+        self.value(long)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("--{} needs a number, not '{}'", long, value))
+            })
+            .transpose()
+    }
+}
+
+/// The subcommands of `commands`, with what each does wrapped beside it
+pub fn commands_help(commands: &[Command], indent: usize) -> String {
+    subcommands(commands)
+        .map(|command| entry(indent, command.name, &command.about))
+        .collect()
+}
+
+/// `label` indented, and `text` from [`HELP_COLUMN`] beside it or, if the
+/// label is too wide, below it
+fn entry(indent: usize, label: &str, text: &str) -> String {
+    let label = format!("{}{}", " ".repeat(indent), label);
+    let column = " ".repeat(HELP_COLUMN);
+    if label.len() < HELP_COLUMN {
+        let padded = format!("{:width$}", label, width = HELP_COLUMN);
+        wrap(text, &padded, &column)
+    } else {
+        format!("{}\n{}", label, wrap(text, &column, &column))
+    }
+}
+
+/// `text` wrapped to [`WIDTH`], the first line starting with `first` and the
+/// others with `rest`
+pub fn wrap(text: &str, first: &str, rest: &str) -> String {
+    let mut out = String::new();
+    let mut line = first.to_string();
+    let mut empty = true;
+    for word in text.split_whitespace() {
+        if !empty && line.chars().count() + 1 + word.chars().count() > WIDTH {
+            out.push_str(&line);
+            out.push('\n');
+            line = rest.to_string();
+            empty = true;
+        }
+        if !empty {
+            line.push(' ');
+        }
+        line.push_str(word);
+        empty = false;
+    }
+    out.push_str(&line);
+    out.push('\n');
+    out
+}
+
+/// A completion script for `shell` (bash, zsh or fish) covering `commands`,
+/// the first of which is the default command
+pub fn completions(shell: &str, program: &str, commands: &[Command]) -> Result<String, String> {
+    match shell {
+        "bash" => Ok(bash_completions(program, commands)),
+        "zsh" => Ok(zsh_completions(program, commands)),
+        "fish" => Ok(fish_completions(program, commands)),
+        _ => Err(format!(
+            "Unknown shell '{}' (expected bash, zsh or fish)",
+            shell
+        )),
+    }
+}
+
+fn subcommands(commands: &[Command]) -> impl Iterator<Item = &Command> {
+    commands.iter().filter(|command| !command.name.is_empty())
+}
+
+/// Where bash, zsh and fish function names can't have `-`
+fn identifier(program: &str) -> String {
+    program.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+}
+
+fn bash_completions(program: &str, commands: &[Command]) -> String {
+    let names: Vec<&str> = subcommands(commands).map(|c| c.name).collect();
+    let function = format!("_{}", identifier(program));
+    let mut out = format!("# bash completion for {}\n{}() {{\n", program, function);
+    out.push_str(
+        "    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n",
+    );
+    out.push_str("    local command=\"\" i\n");
+    out.push_str("    for ((i = 1; i < COMP_CWORD; i++)); do\n");
+    let _ = writeln!(out, "        case \"${{COMP_WORDS[i]}}\" in");
+    let _ = writeln!(
+        out,
+        "            {}) command=\"${{COMP_WORDS[i]}}\"; break ;;",
+        names.join("|")
+    );
+    out.push_str("            -*) ;;\n            *) break ;;\n        esac\n    done\n\n");
+
+    // Values of options with choices
+    out.push_str("    case \"$command:$prev\" in\n");
+    for command in commands {
+        for option in command.options.iter().filter(|o| !o.choices.is_empty()) {
+            let mut flags = vec![format!("{}:--{}", command.name, option.long)];
+            if let Some(short) = option.short {
+                flags.push(format!("{}:-{}", command.name, short));
+            }
+            let _ = writeln!(
+                out,
+                "        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;",
+                flags.join("|"),
+                option.choices.join(" ")
+            );
+        }
+        for operand in command.operands.iter().filter(|o| !o.choices.is_empty()) {
+            let _ = writeln!(
+                out,
+                "        {}:*) [[ \"$cur\" != -* ]] && COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")) && return ;;",
+                command.name,
+                operand.choices.join(" ")
+            );
+        }
+    }
+    out.push_str("    esac\n\n    local options\n    case \"$command\" in\n");
+    for command in commands {
+        let mut options = Vec::new();
+        for option in &command.options {
+            options.push(format!("--{}", option.long));
+            if let Some(short) = option.short {
+                options.push(format!("-{}", short));
+            }
+        }
+        let pattern = if command.name.is_empty() {
+            "\"\""
+        } else {
+            command.name
+        };
+        let _ = writeln!(
+            out,
+            "        {}) options=\"{}\" ;;",
+            pattern,
+            options.join(" ")
+        );
+    }
+    out.push_str("    esac\n\n");
+    out.push_str("    if [[ \"$cur\" == -* ]]; then\n");
+    out.push_str("        COMPREPLY=($(compgen -W \"$options\" -- \"$cur\"))\n");
+    out.push_str("    elif [[ -z \"$command\" && $COMP_CWORD -eq 1 ]]; then\n");
+    let _ = writeln!(
+        out,
+        "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\") $(compgen -f -- \"$cur\"))",
+        names.join(" ")
+    );
+    out.push_str("    else\n        COMPREPLY=($(compgen -f -- \"$cur\"))\n    fi\n}\n");
+    let _ = writeln!(out, "complete -o filenames -F {} {}", function, program);
+    out
+}
+
+/// Text inside a single-quoted zsh `_arguments` spec
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+fn zsh_completions(program: &str, commands: &[Command]) -> String {
+    let function = format!("_{}", identifier(program));
+    let mut out = format!(
+        "#compdef {}\n\n{}() {{\n    local -a commands\n    commands=(\n",
+        program, function
+    );
+    for command in subcommands(commands) {
+        let _ = writeln!(
+            out,
+            "        '{}:{}'",
+            command.name,
+            zsh_escape(first_sentence(&command.about))
+        );
+    }
+    out.push_str("    )\n");
+    out.push_str("    if (( CURRENT == 2 )) && [[ $words[2] != -* ]]; then\n");
+    out.push_str("        _describe -t commands command commands\n        _files\n        return\n    fi\n\n");
+    out.push_str("    local command=$words[2]\n");
+    out.push_str("    if (( ${commands[(I)$command:*]} )); then\n");
+    out.push_str(
+        "        shift words\n        (( CURRENT-- ))\n    else\n        command=\"\"\n    fi\n\n",
+    );
+    out.push_str("    case $command in\n");
+    for command in commands {
+        let pattern = if command.name.is_empty() {
+            "*"
+        } else {
+            command.name
+        };
+        let _ = writeln!(out, "        {})\n            _arguments -s \\", pattern);
+        for option in &command.options {
+            let mut spec = match option.short {
+                Some(short) => format!(
+                    "'(-{} --{})'{{-{},--{}}}'",
+                    short, option.long, short, option.long
+                ),
+                None => format!("'--{}", option.long),
+            };
+            let _ = write!(spec, "[{}]", zsh_escape(&option.help));
+            if let Some(placeholder) = option.value {
+                let action = if option.choices.is_empty() {
+                    "_files".to_string()
+                } else {
+                    format!("({})", option.choices.join(" "))
+                };
+                let _ = write!(spec, ":{}:{}", placeholder.to_lowercase(), action);
+            }
+            let _ = writeln!(out, "                {}' \\", spec);
+        }
+        for (i, operand) in command.operands.iter().enumerate() {
+            let action = if operand.choices.is_empty() {
+                "_files".to_string()
+            } else {
+                format!("({})", operand.choices.join(" "))
+            };
+            let optional = if operand.optional { ":" } else { "" };
+            let _ = writeln!(
+                out,
+                "                '{}:{}{}:{}' \\",
+                i + 1,
+                optional,
+                operand.name.to_lowercase(),
+                action
+            );
+        }
+        out.push_str("            ;;\n");
+    }
+    out.push_str("    esac\n}\n\n");
+    // Run when autoloaded from $fpath, register the function when sourced
+    let _ = writeln!(
+        out,
+        "if [[ $funcstack[1] == {f} ]]; then\n    {f} \"$@\"\nelse\n    compdef {f} {p}\nfi",
+        f = function,
+        p = program
+    );
+    out
+}
+
+/// Text inside a single-quoted fish string
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish_completions(program: &str, commands: &[Command]) -> String {
+    let names: Vec<&str> = subcommands(commands).map(|c| c.name).collect();
+    let mut out = format!("# fish completion for {}\n", program);
+    let no_command = format!("not __fish_seen_subcommand_from {}", names.join(" "));
+    for command in subcommands(commands) {
+        let _ = writeln!(
+            out,
+            "complete -c {} -n '{}' -a {} -d '{}'",
+            program,
+            no_command,
+            command.name,
+            fish_escape(first_sentence(&command.about))
+        );
+    }
+    for command in commands {
+        let condition = if command.name.is_empty() {
+            no_command.clone()
+        } else {
+            format!("__fish_seen_subcommand_from {}", command.name)
+        };
+        for option in &command.options {
+            let mut line = format!("complete -c {} -n '{}'", program, condition);
+            if let Some(short) = option.short {
+                let _ = write!(line, " -s {}", short);
+            }
+            let _ = write!(line, " -l {}", option.long);
+            if option.value.is_some() {
+                line.push_str(" -r");
+                if !option.choices.is_empty() {
+                    let _ = write!(line, " -f -a '{}'", option.choices.join(" "));
+                }
+            }
+            let _ = writeln!(line, " -d '{}'", fish_escape(&option.help));
+            out.push_str(&line);
+        }
+        for operand in command.operands.iter().filter(|o| !o.choices.is_empty()) {
+            let _ = writeln!(
+                out,
+                "complete -c {} -n '{}' -f -a '{}'",
+                program,
+                condition,
+                operand.choices.join(" ")
+            );
+        }
+    }
+    out
+}
+
+/// The start of `text` up to the first `. ` or `; `, for short descriptions
+fn first_sentence(text: &str) -> &str {
+    let end = [". ", "; "]
+        .iter()
+        .filter_map(|end| text.find(end))
+        .min()
+        .unwrap_or(text.len());
+    text[..end].trim_end_matches('.')
+}
+
+/// Text for a roff man page, escaping what roff would read as requests
+fn roff_escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with(['.', '\'']) {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
+
+/// A man page (section 1) for `program`, with the default command's options
+/// and a section per subcommand
+pub fn man_page(program: &str, summary: &str, commands: &[Command], notes: &str) -> String {
+    let mut out = format!(
+        ".TH {} 1\n.SH NAME\n{} \\- {}\n.SH SYNOPSIS\n",
+        program.to_uppercase(),
+        program,
+        roff_escape(summary)
+    );
+    for command in commands {
+        let _ = writeln!(out, ".B {}\n.br", roff_escape(&command.usage(program)));
+    }
+    let option_list = |out: &mut String, command: &Command| {
+        for option in &command.options {
+            let _ = writeln!(
+                out,
+                ".TP\n.B {}\n{}",
+                roff_escape(&option.label()),
+                roff_escape(&option.help)
+            );
+        }
+    };
+    if let Some(default) = commands.iter().find(|command| command.name.is_empty()) {
+        let _ = writeln!(out, ".SH DESCRIPTION\n{}", roff_escape(&default.about));
+        out.push_str(".SH OPTIONS\n");
+        option_list(&mut out, default);
+    }
+    out.push_str(".SH COMMANDS\n");
+    for command in subcommands(commands) {
+        let _ = writeln!(
+            out,
+            ".SS {}\n{}",
+            roff_escape(command.name),
+            roff_escape(&command.about)
+        );
+        if command.options.len() > 1 {
+            option_list(&mut out, command);
+        }
+    }
+    let _ = writeln!(out, ".SH NOTES\n{}", roff_escape(notes));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn bench() -> Command {
+        Command::new("bench", "Parse <INPUT> repeatedly.")
+            .with_option(Opt::value("iterations", "N", "how many times.").with_short('n'))
+            .with_option(Opt::value("format", "FORMAT", "output.").with_choices(&["text", "json"]))
+            .with_option(Opt::flag("quiet", "say less."))
+            .with_operand("GRAMMAR")
+            .with_optional_operand("INPUT")
+    }
+
+    #[test]
+    fn test_parse() {
+        let command = bench();
+        let matches = command
+            .parse(&args(&["-n", "3", "g", "--quiet", "--format=json", "-"]))
+            .unwrap();
+        assert_eq!(matches.number::<usize>("iterations"), Ok(Some(3)));
+        assert_eq!(matches.value("format"), Some("json"));
+        assert!(matches.flag("quiet") && !matches.help());
+        assert_eq!(matches.operands, ["g", "-"]);
+
+        let matches = command.parse(&args(&["-n5", "--", "-g"])).unwrap();
+        assert_eq!(matches.value("iterations"), Some("5"));
+        assert_eq!(matches.operands, ["-g"]);
+
+        // Help wins over everything else
+        assert!(command.parse(&args(&["--bogus", "x"])).is_err());
+        assert!(command.parse(&args(&["-h", "--bogus"])).unwrap().help());
+
+        let error = |list: &[&str]| command.parse(&args(list)).err().unwrap();
+        assert_eq!(error(&[]), "Missing <GRAMMAR>");
+        assert_eq!(error(&["a", "b", "c"]), "Unexpected argument 'c'");
+        assert_eq!(error(&["-x", "g"]), "Unknown option '-x'");
+        assert_eq!(error(&["g", "--iterations"]), "--iterations needs <N>");
+        assert_eq!(error(&["g", "--quiet=yes"]), "--quiet takes no value");
+        assert_eq!(
+            error(&["g", "--format", "xml"]),
+            "Invalid value 'xml' for --format (expected text, json)"
+        );
+        let matches = command.parse(&args(&["g", "-n", "x"])).unwrap();
+        assert!(matches.number::<usize>("iterations").is_err());
+    }
+
+    #[test]
+    fn test_last_of() {
+        let command = Command::new("parse", "")
+            .with_option(Opt::flag("stream", ""))
+            .with_option(Opt::flag("mmap", ""));
+        let matches = command.parse(&args(&["--mmap", "--stream"])).unwrap();
+        assert_eq!(matches.last_of(&["stream", "mmap"]), Some("stream"));
+        assert_eq!(matches.last_of(&["help"]), None);
+    }
+
+    #[test]
+    fn test_help() {
+        assert_eq!(
+            bench().usage("rustixml"),
+            "rustixml bench [-n <N>] [--format text|json] [--quiet] <GRAMMAR> [<INPUT>]"
+        );
+        assert_eq!(
+            bench().help("rustixml"),
+            "Usage: rustixml bench [-n <N>] [--format text|json] [--quiet] <GRAMMAR> [<INPUT>]\n\
+             \n  Parse <INPUT> repeatedly.\n\
+             \n  Options:\n\
+             \x20   -n, --iterations <N>\n\
+             \x20                    how many times.\n\
+             \x20   --format <FORMAT>\n\
+             \x20                    output.\n\
+             \x20   --quiet          say less.\n\
+             \x20   -h, --help       show this help message.\n"
+        );
+        assert_eq!(wrap("a b c", "> ", "  "), "> a b c\n");
+        let long = "word ".repeat(20);
+        assert_eq!(wrap(&long, "", "").lines().count(), 2);
+    }
+
+    #[test]
+    fn test_completions() {
+        let commands = [
+            Command::new("", "Parse.").with_option(Opt::flag("indent", "indent.")),
+            bench(),
+        ];
+        let bash = completions("bash", "rustixml", &commands).unwrap();
+        assert!(bash.contains("bench) command=\"${COMP_WORDS[i]}\"; break ;;"));
+        assert!(bash.contains(
+            "bench:--format) COMPREPLY=($(compgen -W \"text json\" -- \"$cur\")); return ;;"
+        ));
+        assert!(bash.contains("\"\") options=\"--indent --help -h\" ;;"));
+        assert!(bash.ends_with("complete -o filenames -F _rustixml rustixml\n"));
+
+        let zsh = completions("zsh", "rustixml", &commands).unwrap();
+        assert!(zsh.starts_with("#compdef rustixml\n"));
+        assert!(zsh.contains("'bench:Parse <INPUT> repeatedly'"));
+        assert!(zsh.contains("'(-n --iterations)'{-n,--iterations}'[how many times.]:n:_files' \\"));
+        assert!(zsh.contains("'--format[output.]:format:(text json)' \\"));
+        assert!(zsh.contains("'2::input:_files' \\"));
+
+        let fish = completions("fish", "rustixml", &commands).unwrap();
+        assert!(fish.contains(
+            "complete -c rustixml -n 'not __fish_seen_subcommand_from bench' -a bench -d 'Parse <INPUT> repeatedly'\n"
+        ));
+        assert!(fish.contains(
+            "complete -c rustixml -n '__fish_seen_subcommand_from bench' -l format -r -f -a 'text json' -d 'output.'\n"
+        ));
+
+        assert!(completions("tcsh", "rustixml", &commands).is_err());
+    }
+
+    #[test]
+    fn test_man_page() {
+        let commands = [
+            Command::new("", "Parse input.").with_option(Opt::flag("indent", "indent.")),
+            bench(),
+        ];
+        let page = man_page("rustixml", "iXML parser", &commands, "See -h.");
+        assert!(page.starts_with(".TH RUSTIXML 1\n.SH NAME\nrustixml \\- iXML parser\n"));
+        assert!(page.contains(".TP\n.B \\-\\-indent\nindent.\n"));
+        assert!(page.contains(".SS bench\nParse <INPUT> repeatedly.\n"));
+        assert!(page.ends_with(".SH NOTES\nSee \\-h.\n"));
+    }
+}
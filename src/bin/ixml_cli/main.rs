@@ -0,0 +1,1270 @@
+#!/usr/bin/env rust
+//! rustixml CLI tool - parse iXML grammars and generate XML
+//!
+//! Usage: ixml_cli [OPTIONS] [GRAMMAR] INPUT
+//!        ixml_cli parse [OPTIONS] GRAMMAR INPUT
+//!        ixml_cli convert [--from FORMAT] --to FORMAT [-o OUTPUT] [GRAMMAR]
+//!        ixml_cli to-vxml [-o OUTPUT] [GRAMMAR]
+//!        ixml_cli from-vxml [-o OUTPUT] [GRAMMAR]
+//!        ixml_cli compare [--reference COMMAND] GRAMMAR INPUT
+//!        ixml_cli repl GRAMMAR
+//!        ixml_cli watch [--interval MS] GRAMMAR INPUT
+//!        ixml_cli bench [--iterations N] GRAMMAR INPUT
+//!        ixml_cli stats GRAMMAR
+//!        ixml_cli analyze [--json] GRAMMAR
+//!        ixml_cli visualize [--format dot|svg] [-o OUTPUT] GRAMMAR
+//!        ixml_cli lint [--severity LEVEL] GRAMMAR
+//!        ixml_cli diff OLD_GRAMMAR NEW_GRAMMAR
+//!        ixml_cli test GRAMMAR
+//!        ixml_cli generate [--count N] [--max-depth N] [-o DIR] GRAMMAR
+//!        ixml_cli conformance [CATALOG]
+//!        ixml_cli completions bash|zsh|fish
+//!        ixml_cli man
+//!
+//! Compatible with markup-blitz CLI interface
+
+mod args;
+
+use args::{Command, Matches, Opt};
+use rustixml::conformance::{load_catalog, load_grammar_tests, ConformanceReport, DEFAULT_CATALOG};
+use rustixml::convert::{convert, GrammarFormat};
+use rustixml::diagnostics::{code, Diagnostic, Severity};
+use rustixml::generator::{generate, GenOptions};
+use rustixml::grammar_analysis::GrammarAnalysis;
+use rustixml::grammar_ast::parse_ixml_grammar;
+use rustixml::grammar_diff::grammar_diff;
+use rustixml::input_file::{read_file, InputOptions};
+use rustixml::input_stream::{InputStream, DEFAULT_BACKTRACK_WINDOW};
+use rustixml::native_parser::NativeParser;
+use rustixml::parse_context::ParseCounters;
+use rustixml::railroad::railroad_svg;
+use rustixml::reference::{compare, ReferenceProcessor, REFERENCE_ENV};
+use rustixml::regex_hints::regex_hints;
+use rustixml::xml_node::{OutputLimits, SerializeOptions};
+use rustixml::XmlNode;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+    let commands = commands();
+
+    if args.len() < 2 {
+        eprint!("{}", overview(program, &commands));
+        process::exit(1);
+    }
+
+    // The default command parses, so anything else is its first argument
+    let (command, rest) = match commands[1..].iter().find(|c| c.name == args[1]) {
+        Some(command) => (command, &args[2..]),
+        None => (&commands[0], &args[1..]),
+    };
+    let matches = command.parse(rest).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        eprintln!("Usage: {}", command.usage(program));
+        process::exit(1);
+    });
+    if matches.help() {
+        match command.name {
+            "" => print!("{}", overview(program, &commands)),
+            _ => print!("{}", command.help(program)),
+        }
+        return;
+    }
+
+    match command.name {
+        "" => run_parse(&matches, false),
+        "parse" => run_parse(&matches, true),
+        "repl" => run_repl(&matches),
+        "watch" => run_watch(&matches),
+        "bench" => run_bench(&matches),
+        "convert" => run_convert(&matches, GrammarFormat::Ixml, None),
+        "to-vxml" => run_convert(&matches, GrammarFormat::Ixml, Some(GrammarFormat::Vxml)),
+        "from-vxml" => run_convert(&matches, GrammarFormat::Vxml, Some(GrammarFormat::Ixml)),
+        "compare" => run_compare(&matches),
+        "stats" => run_stats(&matches),
+        "analyze" => run_analyze(&matches),
+        "visualize" => run_visualize(&matches),
+        "lint" => run_lint(&matches),
+        "diff" => run_diff(&matches),
+        "test" => run_test(&matches),
+        "generate" => run_generate(&matches),
+        "conformance" => run_conformance(&matches),
+        "completions" => run_completions(program, &matches, &commands),
+        "man" => print!("{}", man_page(program, &commands)),
+        name => unreachable!("no handler for {}", name),
+    }
+}
+
+/// Values of `--format` for parse results
+const OUTPUT_FORMATS: &[&str] = &["xml", "compact-xml", "json"];
+
+/// Options of the default command and `parse`
+fn parse_options() -> Vec<Opt> {
+    vec![
+        Opt::flag("indent", "generate resulting xml with indentation."),
+        Opt::flag(
+            "fail-on-error",
+            "exit without writing an error document: with status 2 if the grammar is \
+             invalid, 3 if the input does not match.",
+        ),
+        Opt::value(
+            "format",
+            "FORMAT",
+            "xml (default), compact-xml (one line, no declaration) or json.",
+        )
+        .with_choices(OUTPUT_FORMATS),
+        Opt::flag("compact", "same as --format compact-xml."),
+        Opt::value(
+            "start",
+            "RULE",
+            "parse the input as a <RULE> rather than the first rule, e.g. to try a fragment.",
+        ),
+        Opt::value(
+            "output",
+            "FILE",
+            "write the resulting xml to FILE (default: standard output).",
+        )
+        .with_short('o'),
+        Opt::flag("timing", "print timing information."),
+        Opt::flag(
+            "trace",
+            "print each rule entered and left and each alternative tried, with input \
+             offsets, to standard error.",
+        ),
+        Opt::flag("verbose", "print intermediate results."),
+        Opt::flag(
+            "stream",
+            "read a file input in chunks instead of all at once.",
+        ),
+        Opt::flag(
+            "mmap",
+            "like --stream, decoding from a memory-mapped file (needs the `mmap` feature).",
+        ),
+        Opt::value(
+            "window",
+            "CHARS",
+            format!(
+                "characters kept for backtracking with --stream or --mmap (default: {}).",
+                DEFAULT_BACKTRACK_WINDOW
+            ),
+        ),
+        Opt::value(
+            "max-nodes",
+            "N",
+            "keep at most N nodes of the result, marking it ixml:state=\"truncated\" if \
+             any are dropped.",
+        ),
+        Opt::value(
+            "max-bytes",
+            "N",
+            "stop writing the result after about N bytes, likewise.",
+        ),
+        error_format_option(),
+    ]
+}
+
+/// `--error-format`, for commands that report diagnostics
+fn error_format_option() -> Opt {
+    Opt::value(
+        "error-format",
+        "FORMAT",
+        "human (default) or json: one JSON object per diagnostic, with file, line, \
+         column, severity, code, message and notes.",
+    )
+    .with_choices(&["human", "json"])
+}
+
+/// Every command; the first, without a name, is the default command
+fn commands() -> Vec<Command> {
+    let formats: Vec<&'static str> = GrammarFormat::ALL.iter().map(|f| f.name()).collect();
+    let output = || {
+        Opt::value(
+            "output",
+            "OUTPUT",
+            "output file (default: standard output).",
+        )
+        .with_short('o')
+    };
+    vec![
+        Command::new(
+            "",
+            "Compile an Invisible XML grammar, and parse input with the resulting parser. \
+             <GRAMMAR> is the grammar in ixml notation and <INPUT> the input, each a \
+             literal, file name or URL.",
+        )
+        .with_options(parse_options())
+        .with_operand("GRAMMAR")
+        .with_operand("INPUT"),
+        Command::new(
+            "parse",
+            "Like the default command, but an error document comes with exit status 2 \
+             (invalid grammar) or 3 (input does not match) instead of 0.",
+        )
+        .with_options(parse_options())
+        .with_operand("GRAMMAR")
+        .with_operand("INPUT"),
+        Command::new(
+            "convert",
+            format!(
+                "Convert a grammar between formats (reads {}; writes {}; other directions \
+                 fail with an error). The grammar is read from standard input when omitted \
+                 or -.",
+                format_names(GrammarFormat::can_read),
+                format_names(GrammarFormat::can_write)
+            ),
+        )
+        .with_option(
+            Opt::value(
+                "from",
+                "FORMAT",
+                "format of the input grammar (default: ixml).",
+            )
+            .with_choices(&formats),
+        )
+        .with_option(Opt::value("to", "FORMAT", "format to write.").with_choices(&formats))
+        .with_option(output())
+        .with_optional_operand("GRAMMAR"),
+        Command::new(
+            "to-vxml",
+            "Convert an ixml grammar to its XML form (vxml), as other iXML processors read \
+             and write it.",
+        )
+        .with_option(output())
+        .with_optional_operand("GRAMMAR"),
+        Command::new("from-vxml", "Convert a grammar in vxml back to ixml.")
+            .with_option(output())
+            .with_optional_operand("GRAMMAR"),
+        Command::new(
+            "compare",
+            "Diff the output with another iXML processor's; exits with status 1 if they \
+             differ.",
+        )
+        .with_option(Opt::value(
+            "reference",
+            "COMMAND",
+            format!(
+                "the processor to run; {{grammar}} and {{input}} stand for the paths of files \
+                 holding them (default: ${}).",
+                REFERENCE_ENV
+            ),
+        ))
+        .with_operand("GRAMMAR")
+        .with_operand("INPUT"),
+        Command::new(
+            "repl",
+            "Parse each line typed (or piped) and print the result; <<END starts a block of \
+             lines ending at END, :start <RULE> changes the start rule, :quit stops.",
+        )
+        .with_operand("GRAMMAR"),
+        Command::new(
+            "watch",
+            "Parse <INPUT> with <GRAMMAR> (both files) again whenever either changes.",
+        )
+        .with_option(Opt::value(
+            "interval",
+            "MS",
+            "how often to check, in milliseconds (default: 500).",
+        ))
+        .with_operand("GRAMMAR")
+        .with_operand("INPUT"),
+        Command::new(
+            "bench",
+            "Parse <INPUT> repeatedly and report min, median, p95 and max times, peak \
+             memory (on Linux) and the rules, memoized results, alternatives and \
+             backtracks of one parse.",
+        )
+        .with_option(
+            Opt::value("iterations", "N", "how many parses (default: 10).").with_short('n'),
+        )
+        .with_operand("GRAMMAR")
+        .with_operand("INPUT"),
+        Command::new(
+            "stats",
+            "Print rule, terminal and alternative counts, nesting depth and an estimate of \
+             parser states for the grammar.",
+        )
+        .with_operand("GRAMMAR"),
+        Command::new(
+            "analyze",
+            "Report left recursion, ambiguity risk, backtracking hazards, unreachable rules \
+             and complex rules.",
+        )
+        .with_option(Opt::flag("json", "write the findings as JSON."))
+        .with_operand("GRAMMAR"),
+        Command::new(
+            "visualize",
+            "Draw <GRAMMAR> for documentation: a Graphviz graph of the rules and the rules \
+             they use, or railroad diagrams of every rule.",
+        )
+        .with_option(
+            Opt::value("format", "FORMAT", "dot (default) or svg.").with_choices(&["dot", "svg"]),
+        )
+        .with_option(output())
+        .with_operand("GRAMMAR"),
+        Command::new(
+            "lint",
+            "Report unused, unreachable, undefined and duplicate rules and the analysis \
+             findings on standard error; exits with status 1 if there are any, 2 if the \
+             grammar is invalid.",
+        )
+        .with_option(
+            Opt::value(
+                "severity",
+                "LEVEL",
+                "the least severe findings to report: error, warning (default) or info.",
+            )
+            .with_choices(&["error", "warning", "info"]),
+        )
+        .with_option(error_format_option())
+        .with_operand("GRAMMAR"),
+        Command::new(
+            "diff",
+            "List rules, alternatives and factors that differ between two versions of a \
+             grammar; exits with status 1 if there are any, 2 if either grammar is invalid.",
+        )
+        .with_operand("OLD_GRAMMAR")
+        .with_operand("NEW_GRAMMAR"),
+        Command::new(
+            "test",
+            "Parse each input file NAME in the directory next to <GRAMMAR> named after it \
+             with .tests (date.ixml: date.tests/), expecting the document in NAME.xml, or a \
+             parse failure without one; exits with status 1 on failures.",
+        )
+        .with_operand("GRAMMAR"),
+        Command::new(
+            "generate",
+            "Write random inputs <GRAMMAR> accepts, one per line.",
+        )
+        .with_option(Opt::value("count", "N", "how many (default: 10).").with_short('n'))
+        .with_option(Opt::value(
+            "max-depth",
+            "N",
+            "rules nested deeper take their shortest input (default: 12).",
+        ))
+        .with_option(Opt::value(
+            "max-repeat",
+            "N",
+            "most elements of a repetition (default: 3).",
+        ))
+        .with_option(Opt::value(
+            "seed",
+            "N",
+            "first random seed; the same seed gives the same inputs.",
+        ))
+        .with_option(Opt::value(
+            "start",
+            "RULE",
+            "generate a <RULE> rather than the first rule.",
+        ))
+        .with_option(
+            Opt::value(
+                "output",
+                "DIR",
+                "write each input to its own file, 1.txt, 2.txt, ...",
+            )
+            .with_short('o'),
+        )
+        .with_operand("GRAMMAR"),
+        Command::new(
+            "conformance",
+            format!(
+                "Run the iXML test suite cases in a test catalog (default: {}) and report \
+                 failures and the pass rate.",
+                DEFAULT_CATALOG
+            ),
+        )
+        .with_optional_operand("CATALOG"),
+        Command::new(
+            "completions",
+            "Write a completion script for <SHELL>; for bash, \
+             `rustixml completions bash > /etc/bash_completion.d/rustixml`.",
+        )
+        .with_operand_choices("SHELL", &["bash", "zsh", "fish"]),
+        Command::new("man", "Write this help as a man page, for `man -l -`."),
+    ]
+}
+
+/// What `--help` says after the commands
+const NOTES: &str = "A literal grammar or input must be preceded by an exclamation point (!), \
+                     and - reads one of them from standard input. Confirmations such as \
+                     \"Grammars match\" are only printed when standard output is a terminal. \
+                     All inputs must be presented in UTF-8 encoding, and output is written in \
+                     UTF-8 as well. Resulting XML goes to standard output, all diagnostics go \
+                     to standard error. Commands exit with status 2 when a grammar is invalid.";
+
+/// Exit status when the grammar cannot be compiled
+const EXIT_GRAMMAR_ERROR: i32 = 2;
+/// Exit status when the input does not match the grammar
+const EXIT_PARSE_ERROR: i32 = 3;
+
+/// Parse an input with a grammar, the default command
+///
+/// As the `parse` subcommand (`subcommand` set), errors still produce an
+/// error document but also exit with [`EXIT_GRAMMAR_ERROR`] or
+/// [`EXIT_PARSE_ERROR`].
+fn run_parse(matches: &Matches, subcommand: bool) {
+    let indent = matches.flag("indent");
+    let output = matches.value("output");
+    let format = match (
+        matches.last_of(&["format", "compact"]),
+        matches.value("format"),
+    ) {
+        (Some("compact"), _) | (_, Some("compact-xml")) => OutputFormat::CompactXml,
+        (_, Some("json")) => OutputFormat::Json,
+        _ => OutputFormat::Xml,
+    };
+    let start_rule = matches.value("start");
+    let trace = matches.flag("trace");
+    let fail_on_error = matches.flag("fail-on-error");
+    let timing = matches.flag("timing");
+    let verbose = matches.flag("verbose");
+    let json_errors = matches.value("error-format") == Some("json");
+    let mode = match matches.last_of(&["stream", "mmap"]) {
+        Some("stream") => InputMode::Stream,
+        Some(_) => InputMode::Mmap,
+        None => InputMode::Memory,
+    };
+    let window = exit_on_error(matches.number("window")).unwrap_or(DEFAULT_BACKTRACK_WINDOW);
+    let mut limits = OutputLimits::new();
+    if let Some(nodes) = exit_on_error(matches.number("max-nodes")) {
+        limits = limits.with_max_nodes(nodes);
+    }
+    if let Some(bytes) = exit_on_error(matches.number("max-bytes")) {
+        limits = limits.with_max_bytes(bytes);
+    }
+
+    if start_rule.is_some() && mode != InputMode::Memory {
+        eprintln!("Error: --start cannot be combined with --stream or --mmap");
+        process::exit(1);
+    }
+    if trace && (start_rule.is_some() || mode != InputMode::Memory) {
+        eprintln!("Error: --trace cannot be combined with --start, --stream or --mmap");
+        process::exit(1);
+    }
+
+    let [grammar_arg, input_arg] = [&matches.operands[0], &matches.operands[1]];
+    let grammar_text = read_arg(grammar_arg);
+    let input = open_input(input_arg, mode, window);
+    let (grammar_file, input_file) = (source_file(grammar_arg), source_file(input_arg));
+
+    if verbose {
+        eprintln!("Grammar: {} bytes", grammar_text.len());
+        match input.len() {
+            Some(bytes) => eprintln!("Input: {} bytes", bytes),
+            None => eprintln!("Input: standard input"),
+        }
+    }
+
+    let start = std::time::Instant::now();
+
+    // Parse grammar
+    let compiled = match parse_ixml_grammar(&grammar_text) {
+        Ok(g) => {
+            // Regex idioms like `{2}` still parse (as comments), so warn about them
+            for hint in regex_hints(&grammar_text) {
+                if json_errors {
+                    let diagnostic = Diagnostic::from_regex_hint(&hint, &grammar_text);
+                    eprintln!("{}", diagnostic.to_json(grammar_file, Some(&grammar_text)));
+                } else {
+                    eprintln!("Warning: {}", hint);
+                }
+            }
+            NativeParser::try_new(g)
+                .map_err(|e| (e.to_string(), Diagnostic::from_grammar_problems(&e)))
+        }
+        Err(e) => {
+            let diagnostic = Diagnostic::from_grammar_error(&e, &grammar_text);
+            Err((e, vec![diagnostic]))
+        }
+    };
+    let parser = match compiled {
+        Ok(parser) => {
+            for diagnostic in Diagnostic::from_analysis(parser.compiled().analysis()) {
+                if diagnostic.severity > Severity::Warning {
+                    continue;
+                }
+                if json_errors {
+                    eprintln!("{}", diagnostic.to_json(grammar_file, Some(&grammar_text)));
+                } else {
+                    eprintln!("{}", diagnostic);
+                }
+            }
+            let layout = match format {
+                OutputFormat::Xml if indent => SerializeOptions::pretty().with_declaration(true),
+                OutputFormat::Xml => SerializeOptions::new().with_declaration(true),
+                OutputFormat::CompactXml | OutputFormat::Json => SerializeOptions::new(),
+            };
+            parser
+                .with_output_limits(limits)
+                .with_serialize_options(layout)
+        }
+        Err((e, diagnostics)) => {
+            if json_errors {
+                for diagnostic in diagnostics {
+                    let json = diagnostic.to_json(grammar_file, Some(&grammar_text));
+                    eprintln!("{}", json);
+                }
+            } else {
+                eprintln!("Grammar parse error: {}", e);
+            }
+            if fail_on_error {
+                process::exit(EXIT_GRAMMAR_ERROR);
+            }
+            // Return error document (iXML spec behavior)
+            write_output(output, &error_document("grammar", &e, format));
+            process::exit(if subcommand { EXIT_GRAMMAR_ERROR } else { 0 });
+        }
+    };
+
+    if timing {
+        eprintln!("Grammar parsed in {:?}", start.elapsed());
+    }
+
+    // Parse input
+    let parse_start = std::time::Instant::now();
+    let result = match input {
+        Input::Text(text) => {
+            let outcome = match start_rule {
+                Some(rule) => parser.parse_outcome_with_start(&text, rule),
+                None if trace => {
+                    let (outcome, events) = parser.parse_traced(&text);
+                    for event in events {
+                        eprintln!("{}", event);
+                    }
+                    outcome
+                }
+                None => parser.parse_outcome(&text),
+            };
+            match outcome.error() {
+                Some(e) => {
+                    let json = Diagnostic::from_parse_error(e).to_json(input_file, Some(&text));
+                    Err((e.format_with_context(&text), json))
+                }
+                None => {
+                    let (xml, warnings) = outcome.to_xml_with_warnings(parser.fixed_attributes());
+                    for warning in warnings {
+                        if json_errors {
+                            let diagnostic = Diagnostic::warning(code::OUTPUT_TRUNCATED, warning);
+                            eprintln!("{}", diagnostic.to_json(output, None));
+                        } else {
+                            eprintln!("Warning: {}", warning);
+                        }
+                    }
+                    Ok(xml)
+                }
+            }
+        }
+        // Positions in a stream can't be turned into lines without its text
+        Input::Stream { stream, .. } => parser.parse_stream(stream).map_err(|e| {
+            let json = Diagnostic::from_parse_error(&e).to_json(input_file, None);
+            (e.to_string(), json)
+        }),
+    };
+    let xml = match result {
+        Ok(xml) => xml,
+        Err((e, json)) => {
+            if json_errors {
+                eprintln!("{}", json);
+            } else {
+                eprintln!("Parse error: {}", e);
+            }
+            if fail_on_error {
+                process::exit(EXIT_PARSE_ERROR);
+            }
+            // Return error document
+            write_output(output, &error_document("parse", &e, format));
+            process::exit(if subcommand { EXIT_PARSE_ERROR } else { 0 });
+        }
+    };
+
+    if timing {
+        eprintln!("Input parsed in {:?}", parse_start.elapsed());
+        eprintln!("Total time: {:?}", start.elapsed());
+    }
+
+    let document = match format {
+        OutputFormat::Json => XmlNode::from_xml(&xml)
+            .map(|tree| tree.to_json())
+            .unwrap_or_else(|e| {
+                eprintln!("Error: cannot convert the result to JSON: {}", e);
+                process::exit(1);
+            }),
+        OutputFormat::Xml | OutputFormat::CompactXml => xml,
+    };
+    write_output(output, &format!("{}\n", document));
+}
+
+/// `repl` subcommand: parse each line read from standard input, or each
+/// `<<MARKER` block ending at a `MARKER` line, and print the result at once
+///
+/// `:start RULE` parses what follows as a RULE (`:start` alone goes back to
+/// the first rule), and `:quit` or the end of input stops.
+fn run_repl(matches: &Matches) {
+    let grammar = &matches.operands[0];
+    let parser = parse_ixml_grammar(&read_arg(grammar))
+        .and_then(|g| NativeParser::try_new(g).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            eprintln!("Grammar parse error: {}", e);
+            process::exit(EXIT_GRAMMAR_ERROR);
+        })
+        .with_serialize_options(SerializeOptions::pretty());
+
+    // Prompts go to standard error, and only for someone typing
+    let interactive = io::stdin().is_terminal();
+    let prompt = |text: &str| {
+        if interactive {
+            eprint!("{}", text);
+            let _ = io::stderr().flush();
+        }
+    };
+    if interactive {
+        eprintln!("Type input to parse, <<END for a block ending at END, :start RULE or :quit.");
+    }
+
+    let mut start_rule: Option<String> = None;
+    let mut lines = io::stdin().lock().lines().map_while(Result::ok);
+    loop {
+        prompt("> ");
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let input = match line.strip_prefix("<<").map(str::trim) {
+            Some(marker) if !marker.is_empty() => {
+                let mut block = Vec::new();
+                prompt(". ");
+                for line in lines.by_ref() {
+                    if line == marker {
+                        break;
+                    }
+                    block.push(line);
+                    prompt(". ");
+                }
+                block.join("\n")
+            }
+            _ if line == ":quit" => break,
+            _ if line == ":start" || line.starts_with(":start ") => {
+                start_rule =
+                    Some(line[":start".len()..].trim().to_string()).filter(|r| !r.is_empty());
+                continue;
+            }
+            _ => line,
+        };
+
+        let outcome = match &start_rule {
+            Some(rule) => parser.parse_outcome_with_start(&input, rule),
+            None => parser.parse_outcome(&input),
+        };
+        match outcome.error() {
+            Some(e) => eprintln!("Parse error: {}", e.format_with_context(&input)),
+            None => println!("{}", outcome.to_xml_with(parser.fixed_attributes())),
+        }
+    }
+}
+
+/// `watch` subcommand: parse the input again whenever it or the grammar
+/// changes, until interrupted
+///
+/// The files are polled, since they are small and this needs no platform
+/// notification API.
+fn run_watch(matches: &Matches) {
+    let interval = exit_on_error(matches.number("interval")).unwrap_or(500);
+    let interval = Duration::from_millis(interval);
+    let [grammar_path, input_path] = matches.operands.as_slice() else {
+        unreachable!("watch takes two operands");
+    };
+
+    let clear = io::stdout().is_terminal();
+    let mut last = None;
+    loop {
+        let read = |path: &str| {
+            read_file(Path::new(path), &InputOptions::new())
+                .map_err(|e| format!("Error reading {}", e))
+        };
+        let current = (read(grammar_path), read(input_path));
+        if last.as_ref() != Some(&current) {
+            if clear {
+                // Clear the screen and home the cursor
+                print!("\x1b[2J\x1b[H");
+            }
+            match &current {
+                (Ok(grammar), Ok(input)) => watch_parse(grammar, input),
+                (Err(e), _) | (_, Err(e)) => eprintln!("{}", e),
+            }
+            let _ = io::stdout().flush();
+            last = Some(current);
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// One parse for `watch`: the result on standard output, diagnostics on
+/// standard error
+fn watch_parse(grammar: &str, input: &str) {
+    for hint in regex_hints(grammar) {
+        eprintln!("Warning: {}", hint);
+    }
+    let parser = match parse_ixml_grammar(grammar)
+        .and_then(|g| NativeParser::try_new(g).map_err(|e| e.to_string()))
+    {
+        Ok(parser) => parser.with_serialize_options(SerializeOptions::pretty()),
+        Err(e) => {
+            eprintln!("Grammar parse error: {}", e);
+            return;
+        }
+    };
+    let outcome = parser.parse_outcome(input);
+    match outcome.error() {
+        Some(e) => eprintln!("Parse error: {}", e.format_with_context(input)),
+        None => {
+            let (xml, warnings) = outcome.to_xml_with_warnings(parser.fixed_attributes());
+            for warning in warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            println!("{}", xml);
+        }
+    }
+}
+
+/// `bench` subcommand: parse an input repeatedly and report timings, peak
+/// memory where the platform tells it, and how much work one parse does
+fn run_bench(matches: &Matches) {
+    let iterations = exit_on_error(matches.number("iterations"))
+        .unwrap_or(10)
+        .max(1);
+    let (grammar, input) = (
+        read_arg(&matches.operands[0]),
+        read_arg(&matches.operands[1]),
+    );
+
+    let compile_start = Instant::now();
+    let parser = parse_ixml_grammar(&grammar)
+        .and_then(|g| NativeParser::try_new(g).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            eprintln!("Grammar parse error: {}", e);
+            process::exit(EXIT_GRAMMAR_ERROR);
+        });
+    let compile_time = compile_start.elapsed();
+
+    let mut times = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let outcome = parser.parse_outcome(&input);
+        times.push(start.elapsed());
+        if let Some(e) = outcome.error() {
+            eprintln!("Parse error: {}", e.format_with_context(&input));
+            process::exit(EXIT_PARSE_ERROR);
+        }
+    }
+    times.sort();
+    // Nearest-rank percentile
+    let percentile = |p: usize| times[(times.len() * p).div_ceil(100).max(1) - 1];
+
+    let (_, events) = parser.parse_traced(&input);
+    let counters = ParseCounters::from_trace(&events);
+
+    println!("Input:              {} bytes", input.len());
+    println!("Grammar compile:    {:?}", compile_time);
+    println!("Iterations:         {}", iterations);
+    println!("Min:                {:?}", times[0]);
+    println!("Median:             {:?}", percentile(50));
+    println!("P95:                {:?}", percentile(95));
+    println!("Max:                {:?}", times[times.len() - 1]);
+    if let Some(kib) = peak_memory_kib() {
+        println!("Peak memory:        {} KiB", kib);
+    }
+    println!("Rules entered:      {}", counters.rules_entered);
+    println!("Memoized results:   {}", counters.memo_hits);
+    println!("Alternatives tried: {}", counters.alternatives_tried);
+    println!("Backtracks:         {}", counters.backtracks);
+}
+
+/// The process's peak resident memory, where the platform reports it
+fn peak_memory_kib() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// `convert` subcommand: translate a grammar between formats
+///
+/// `to-vxml` and `from-vxml` are this with `from` and `to` set.
+fn run_convert(matches: &Matches, from: GrammarFormat, to: Option<GrammarFormat>) {
+    let from = matches.value("from").map_or(from, grammar_format);
+    let Some(to) = matches.value("to").map(grammar_format).or(to) else {
+        eprintln!("Error: convert requires --to <FORMAT>");
+        process::exit(1);
+    };
+    let output = matches.value("output");
+
+    // No input (or "-") reads the grammar from standard input
+    let source = read_arg(matches.operands.first().map_or("-", String::as_str));
+
+    let mut converted = convert(&source, from, to).unwrap_or_else(|e| {
+        eprintln!("Conversion error: {}", e);
+        // Both directions are supported, so the grammar could not be read
+        let supported = from.can_read() && to.can_write();
+        process::exit(if supported { EXIT_GRAMMAR_ERROR } else { 1 });
+    });
+    if !converted.ends_with('\n') {
+        converted.push('\n');
+    }
+
+    write_output(output, &converted);
+}
+
+/// `compare` subcommand: diff the output against a reference processor
+fn run_compare(matches: &Matches) {
+    let [grammar, input] = matches.operands.as_slice() else {
+        unreachable!("compare takes two operands");
+    };
+    let reference = match matches.value("reference") {
+        Some(command) => ReferenceProcessor::new(command),
+        None => ReferenceProcessor::from_env(),
+    }
+    .unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
+
+    let differences =
+        compare(&reference, &read_arg(grammar), &read_arg(input)).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        });
+    if differences.is_empty() {
+        print_status("Outputs match");
+        return;
+    }
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    eprintln!("{} difference(s) from the reference", differences.len());
+    process::exit(1);
+}
+
+/// `stats` subcommand: print the size and shape of a grammar
+fn run_stats(matches: &Matches) {
+    let grammar = &matches.operands[0];
+    let ast = parse_ixml_grammar(&read_arg(grammar)).unwrap_or_else(|e| {
+        eprintln!("Grammar error: {}", e);
+        process::exit(EXIT_GRAMMAR_ERROR);
+    });
+    print!("{}", GrammarAnalysis::analyze(&ast).stats());
+}
+
+/// `analyze` subcommand: report recursion, ambiguity, backtracking hazards,
+/// unreachable rules and complexity, as text or JSON
+fn run_analyze(matches: &Matches) {
+    let grammar = &matches.operands[0];
+    let ast = parse_ixml_grammar(&read_arg(grammar)).unwrap_or_else(|e| {
+        eprintln!("Grammar error: {}", e);
+        process::exit(EXIT_GRAMMAR_ERROR);
+    });
+    let analysis = GrammarAnalysis::analyze(&ast);
+    if matches.flag("json") {
+        println!("{}", analysis.to_json());
+    } else {
+        print!("{}", analysis.report());
+    }
+}
+
+/// `visualize` subcommand: draw the grammar, as a Graphviz graph of which
+/// rules use which or as railroad diagrams in SVG
+fn run_visualize(matches: &Matches) {
+    let grammar = &matches.operands[0];
+    let ast = parse_ixml_grammar(&read_arg(grammar)).unwrap_or_else(|e| {
+        eprintln!("Grammar error: {}", e);
+        process::exit(EXIT_GRAMMAR_ERROR);
+    });
+    let text = if matches.value("format") == Some("svg") {
+        let options = SerializeOptions::new().with_declaration(true);
+        railroad_svg(&ast).to_xml_with_options(&options) + "\n"
+    } else {
+        GrammarAnalysis::analyze(&ast).to_dot()
+    };
+    write_output(matches.value("output"), &text);
+}
+
+/// `lint` subcommand: report the grammar's findings of at least the chosen
+/// severity, failing if there are any
+fn run_lint(matches: &Matches) {
+    let severity = match matches.value("severity") {
+        Some(name) => exit_on_error(name.parse()),
+        None => Severity::Warning,
+    };
+    let grammar = &matches.operands[0];
+    let json = matches.value("error-format") == Some("json");
+    let source = read_arg(grammar);
+    let file = source_file(grammar);
+    let ast = parse_ixml_grammar(&source).unwrap_or_else(|e| {
+        let diagnostic = Diagnostic::from_grammar_error(&e, &source);
+        if json {
+            eprintln!("{}", diagnostic.to_json(file, Some(&source)));
+        } else {
+            eprintln!("{}", diagnostic.render(&source));
+        }
+        process::exit(EXIT_GRAMMAR_ERROR);
+    });
+    let findings: Vec<Diagnostic> = Diagnostic::from_grammar(&ast)
+        .into_iter()
+        .filter(|d| d.severity <= severity)
+        .collect();
+    for finding in &findings {
+        if json {
+            eprintln!("{}", finding.to_json(file, Some(&source)));
+        } else {
+            eprintln!("{}", finding);
+        }
+    }
+    if !findings.is_empty() {
+        // A summary line would get in the way of reading the JSON lines
+        if !json {
+            eprintln!(
+                "{} finding(s) of severity {} or above",
+                findings.len(),
+                severity
+            );
+        }
+        process::exit(1);
+    }
+}
+
+/// `diff` subcommand: list the changes between two versions of a grammar,
+/// failing if there are any
+fn run_diff(matches: &Matches) {
+    let [old, new] = matches.operands.as_slice() else {
+        unreachable!("diff takes two operands");
+    };
+    let parse = |arg: &str| {
+        parse_ixml_grammar(&read_arg(arg)).unwrap_or_else(|e| {
+            eprintln!("Grammar error in {}: {}", arg, e);
+            process::exit(EXIT_GRAMMAR_ERROR);
+        })
+    };
+    let diff = grammar_diff(&parse(old), &parse(new));
+    if diff.is_empty() {
+        print_status("Grammars match");
+        return;
+    }
+    print!("{}", diff);
+    process::exit(1);
+}
+
+/// `conformance` subcommand: run a test-suite catalog and report the results
+fn run_conformance(matches: &Matches) {
+    let catalog = matches
+        .operands
+        .first()
+        .map_or(DEFAULT_CATALOG, String::as_str);
+    let cases = load_catalog(catalog).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
+    let report = ConformanceReport::run(&cases);
+    print!("{}", report);
+    if report.failed() > 0 {
+        process::exit(1);
+    }
+}
+
+/// `test` subcommand: run the tests kept in `GRAMMAR.tests/` and report
+/// failures with how the output differs
+fn run_test(matches: &Matches) {
+    let grammar = &matches.operands[0];
+    let cases = load_grammar_tests(grammar).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
+    let report = ConformanceReport::run(&cases);
+    print!("{}", report);
+    if report.failed() > 0 {
+        process::exit(1);
+    }
+}
+
+/// `generate` subcommand: write random inputs the grammar accepts, one per
+/// line or one file each
+fn run_generate(matches: &Matches) {
+    let mut options = GenOptions::new();
+    if let Some(seed) = exit_on_error(matches.number("seed")) {
+        options = options.with_seed(seed);
+    }
+    if let Some(max_depth) = exit_on_error(matches.number("max-depth")) {
+        options = options.with_max_depth(max_depth);
+    }
+    if let Some(max_repeat) = exit_on_error(matches.number("max-repeat")) {
+        options = options.with_max_repeat(max_repeat);
+    }
+    if let Some(rule) = matches.value("start") {
+        options = options.with_start(rule);
+    }
+    let count = exit_on_error(matches.number("count")).unwrap_or(10);
+    let output_dir = matches.value("output");
+    let grammar = &matches.operands[0];
+    let grammar = parse_ixml_grammar(&read_arg(grammar)).unwrap_or_else(|e| {
+        eprintln!("Grammar parse error: {}", e);
+        process::exit(EXIT_GRAMMAR_ERROR);
+    });
+    if let Some(dir) = output_dir {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("Error creating {}: {}", dir, e);
+            process::exit(1);
+        }
+    }
+
+    let seed = options.seed;
+    for i in 0..count {
+        // Consecutive seeds, so each input differs but a run can be repeated
+        options.seed = seed.wrapping_add(i as u64);
+        let input = generate(&grammar, &options).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        });
+        match output_dir {
+            Some(dir) => {
+                let path = Path::new(dir).join(format!("{}.txt", i + 1));
+                write_output(Some(&path.to_string_lossy()), &input);
+            }
+            None => println!("{}", input),
+        }
+    }
+}
+
+/// `completions` subcommand: a script for the shell, covering every command
+fn run_completions(program: &str, matches: &Matches, commands: &[Command]) {
+    let script = args::completions(&matches.operands[0], &command_name(program), commands);
+    print!("{}", exit_on_error(script));
+}
+
+/// The man page, from the same definitions as `--help`
+fn man_page(program: &str, commands: &[Command]) -> String {
+    let name = command_name(program);
+    args::man_page(
+        &name,
+        "parse input with Invisible XML grammars",
+        commands,
+        NOTES,
+    )
+}
+
+/// The name the CLI is run as, without its directory
+fn command_name(program: &str) -> String {
+    Path::new(program)
+        .file_stem()
+        .map_or("rustixml".into(), |stem| {
+            stem.to_string_lossy().into_owned()
+        })
+}
+
+/// Names of the grammar formats that `supports`, for help text
+fn format_names(supports: fn(GrammarFormat) -> bool) -> String {
+    let names: Vec<&str> = GrammarFormat::ALL
+        .iter()
+        .copied()
+        .filter(|&format| supports(format))
+        .map(GrammarFormat::name)
+        .collect();
+    names.join(", ")
+}
+
+/// A `--from` or `--to` format, which the options' choices already checked
+fn grammar_format(name: &str) -> GrammarFormat {
+    exit_on_error(name.parse())
+}
+
+/// How the parse result is written
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    /// With an XML declaration, indented with --indent
+    Xml,
+    /// One line without a declaration
+    CompactXml,
+    /// The tree as JSON, see `XmlNode::to_json`
+    Json,
+}
+
+/// How the input is read
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum InputMode {
+    /// Read into a string first
+    Memory,
+    /// Decode from the file in chunks, keeping only the backtracking window
+    Stream,
+    /// Like `Stream`, but decode straight from the memory-mapped file
+    Mmap,
+}
+
+enum Input {
+    Text(String),
+    /// `bytes` is unknown for standard input
+    Stream {
+        stream: InputStream,
+        bytes: Option<u64>,
+    },
+}
+
+impl Input {
+    fn len(&self) -> Option<u64> {
+        match self {
+            Input::Text(text) => Some(text.len() as u64),
+            Input::Stream { bytes, .. } => *bytes,
+        }
+    }
+}
+
+/// Open the input; literal input is always read into memory, and standard
+/// input (`-`) is streamed with either --stream or --mmap
+fn open_input(arg: &str, mode: InputMode, window: usize) -> Input {
+    if mode == InputMode::Memory || arg.starts_with('!') {
+        return Input::Text(read_arg(arg));
+    }
+    if arg == "-" {
+        claim_stdin();
+        return Input::Stream {
+            stream: InputStream::from_reader(io::stdin()).with_backtrack_window(window),
+            bytes: None,
+        };
+    }
+    let exit = |e: io::Error| -> ! {
+        eprintln!("Error reading {}: {}", arg, e);
+        process::exit(1);
+    };
+    let file = fs::File::open(arg).unwrap_or_else(|e| exit(e));
+    let bytes = file.metadata().unwrap_or_else(|e| exit(e)).len();
+    let stream = if mode == InputMode::Mmap {
+        InputStream::from_bytes(map_file(&file).unwrap_or_else(|e| exit(e)))
+    } else {
+        InputStream::from_reader(file)
+    };
+    Input::Stream {
+        stream: stream.with_backtrack_window(window),
+        bytes: Some(bytes),
+    }
+}
+
+#[cfg(feature = "mmap")]
+fn map_file(file: &fs::File) -> io::Result<memmap2::Mmap> {
+    // SAFETY: the map is only read. Truncating or rewriting the file while it is
+    // parsed is undefined behavior, the usual caveat of memory-mapped input.
+    unsafe { memmap2::Mmap::map(file) }
+}
+
+#[cfg(not(feature = "mmap"))]
+fn map_file(_file: &fs::File) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--mmap needs rustixml built with the `mmap` feature",
+    ))
+}
+
+/// The file an argument is read from; `None` for a literal or standard input
+fn source_file(arg: &str) -> Option<&str> {
+    (arg != "-" && !arg.starts_with('!')).then_some(arg)
+}
+
+/// The value, or exit with the error
+fn exit_on_error<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    })
+}
+
+fn read_arg(arg: &str) -> String {
+    if arg == "-" {
+        claim_stdin();
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text).unwrap_or_else(|e| {
+            eprintln!("Error reading standard input: {}", e);
+            process::exit(1);
+        });
+        text
+    } else if let Some(stripped) = arg.strip_prefix('!') {
+        // Literal (preceded by !)
+        stripped.to_string()
+    } else {
+        // File path or URL
+        read_file(Path::new(arg), &InputOptions::new()).unwrap_or_else(|e| {
+            eprintln!("Error reading {}", e);
+            process::exit(1);
+        })
+    }
+}
+
+/// Write `text` to the `--output` file, or standard output without one
+fn write_output(path: Option<&str>, text: &str) {
+    match path {
+        None | Some("-") => print!("{}", text),
+        Some(path) => fs::write(path, text).unwrap_or_else(|e| {
+            eprintln!("Error writing {}: {}", path, e);
+            process::exit(1);
+        }),
+    }
+}
+
+/// `<error type="KIND">MESSAGE</error>` in the output format
+fn error_document(kind: &str, message: &str, format: OutputFormat) -> String {
+    let error = XmlNode::element("error")
+        .attr("type", kind)
+        .child(XmlNode::text(message));
+    let document = match format {
+        OutputFormat::Xml => {
+            error.to_xml_with_options(&SerializeOptions::new().with_declaration(true))
+        }
+        OutputFormat::CompactXml => error.to_xml(),
+        OutputFormat::Json => error.to_json(),
+    };
+    format!("{}\n", document)
+}
+
+/// Note that standard input is being read, failing if it already was: only
+/// one argument can be `-`
+fn claim_stdin() {
+    static CLAIMED: AtomicBool = AtomicBool::new(false);
+    if CLAIMED.swap(true, Ordering::Relaxed) {
+        eprintln!("Error: only one argument can be read from standard input (-)");
+        process::exit(1);
+    }
+}
+
+/// Print a confirmation such as "Grammars match" for a reader at a
+/// terminal; in a pipeline the exit status says as much
+fn print_status(message: &str) {
+    if io::stdout().is_terminal() {
+        println!("{}", message);
+    }
+}
+
+/// `--help` for the whole CLI: the default command's options, then every
+/// subcommand
+fn overview(program: &str, commands: &[Command]) -> String {
+    let mut out = String::new();
+    for (i, command) in commands.iter().enumerate() {
+        let lead = if i == 0 { "Usage:" } else { "      " };
+        out.push_str(&format!("{} {}\n", lead, command.usage(program)));
+    }
+    let default = &commands[0];
+    out.push('\n');
+    out.push_str(&args::wrap(default.about(), "  ", "  "));
+    out.push_str("\n  <OPTION>:\n");
+    out.push_str(&default.options_help(4));
+    out.push_str("\n  <COMMAND>:\n");
+    out.push_str(&args::commands_help(commands, 4));
+    out.push('\n');
+    let more = format!(
+        "Run {} <COMMAND> --help for the options of a command.",
+        command_name(program)
+    );
+    out.push_str(&args::wrap(&more, "  ", "  "));
+    out.push('\n');
+    out.push_str(&args::wrap(NOTES, "  ", "  "));
+    out
+}
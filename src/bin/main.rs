@@ -1,35 +1,27 @@
-//! rustixml CLI tool
+//! ixml: the rustixml CLI under its shorter name
 //!
-//! Command-line interface for parsing and testing iXML grammars
+//! Runs the `rustixml` binary installed next to this one with the same
+//! arguments, so `ixml parse grammar.ixml input.txt` is
+//! `rustixml parse grammar.ixml input.txt`.
 
 use std::env;
-use std::fs;
-use std::process;
+use std::process::{self, Command};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 2 {
-        eprintln!("Usage: rustixml <grammar_file>");
-        eprintln!("       rustixml test");
-        process::exit(1);
-    }
+    let binary = format!("rustixml{}", env::consts::EXE_SUFFIX);
+    let path = match env::current_exe() {
+        Ok(exe) => exe.with_file_name(binary),
+        Err(e) => {
+            eprintln!("Error: cannot locate rustixml: {}", e);
+            process::exit(1);
+        }
+    };
 
-    match args[1].as_str() {
-        "test" => {
-            println!("Running rustixml tests...");
-            println!("Phase 2 implementation in progress.");
+    match Command::new(&path).args(env::args_os().skip(1)).status() {
+        Ok(status) => process::exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            eprintln!("Error running {}: {}", path.display(), e);
+            process::exit(1);
         }
-        filename => match fs::read_to_string(filename) {
-            Ok(content) => {
-                println!("Parsing grammar from: {}", filename);
-                println!("Content length: {} bytes", content.len());
-                println!("\nPhase 2: Full parser implementation coming soon.");
-            }
-            Err(e) => {
-                eprintln!("Error reading file {}: {}", filename, e);
-                process::exit(1);
-            }
-        },
     }
 }
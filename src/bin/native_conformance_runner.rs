@@ -2,8 +2,11 @@
 //!
 //! Runs all tests from ixml_tests/ against the native interpreter
 
+use rustixml::capabilities::{capabilities, Support};
 use rustixml::grammar_ast::parse_ixml_grammar;
 use rustixml::native_parser::NativeParser;
+use rustixml::reference::diff;
+use rustixml::XmlNode;
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -120,7 +123,7 @@ fn run_test(test: &TestCase) -> TestResult {
                 let result_norm = result.split_whitespace().collect::<Vec<_>>().join("");
                 let expected_norm = expected.split_whitespace().collect::<Vec<_>>().join("");
 
-                if result_norm == expected_norm {
+                if result_norm == expected_norm || same_document(&expected, &result) {
                     TestResult::Pass
                 } else {
                     // Find first difference for debugging
@@ -156,6 +159,14 @@ fn run_test(test: &TestCase) -> TestResult {
     }
 }
 
+/// Compare as XML trees, so escaping choices (`>` vs `&gt;`) do not matter
+fn same_document(expected: &str, result: &str) -> bool {
+    match (XmlNode::from_xml(expected), XmlNode::from_xml(result)) {
+        (Ok(expected), Ok(result)) => diff(&expected, &result).is_empty(),
+        _ => false,
+    }
+}
+
 fn main() {
     println!("Native iXML Interpreter Conformance Test Runner");
     println!("==============================================\n");
@@ -233,6 +244,23 @@ fn main() {
     println!("Input errors:    {}", input_error_count);
     println!("Duration:        {:.2}s", duration.as_secs_f64());
 
+    // Failures in these areas are expected until the features are complete
+    let caps = capabilities();
+    let incomplete: Vec<String> = caps
+        .features
+        .iter()
+        .filter(|feature| feature.support != Support::Full)
+        .map(|feature| format!("{} ({})", feature.id, feature.support))
+        .collect();
+    println!("Incomplete:      {}", incomplete.join(", "));
+
+    if (pass_count, test_cases.len()) != (caps.conformance.passed, caps.conformance.total) {
+        println!(
+            "\nNote: capabilities::CONFORMANCE records {}/{}; update it to match this run",
+            caps.conformance.passed, caps.conformance.total
+        );
+    }
+
     // Print failures for debugging
     if fail_count > 0 {
         println!("\n=== Failed Tests ===\n");
@@ -0,0 +1,313 @@
+//! Candid-typed entry points for Internet Computer canisters
+//!
+//! A canister compiles a grammar once with [`compile_grammar`], keeps the
+//! returned [`GrammarHandle`], and parses with [`parse`]. Compiled grammars
+//! live in a per-canister cache; their sources go to stable memory with
+//! [`save_to_stable_memory`] in `pre_upgrade` and are recompiled by
+//! [`restore_from_stable_memory`] in `post_upgrade`, so handles stay valid
+//! across upgrades.
+//!
+//! ```ignore
+//! #[ic_cdk::update]
+//! fn compile(grammar: String) -> Result<GrammarHandle, Vec<Diagnostic>> {
+//!     rustixml::canister::compile_grammar(&grammar)
+//! }
+//!
+//! #[ic_cdk::query]
+//! fn parse(handle: GrammarHandle, input: String) -> ParseResult {
+//!     rustixml::canister::parse(&handle, &input, Some(30_000_000_000))
+//! }
+//!
+//! #[ic_cdk::pre_upgrade]
+//! fn pre_upgrade() {
+//!     rustixml::canister::save_to_stable_memory().expect("save grammars");
+//! }
+//!
+//! #[ic_cdk::post_upgrade]
+//! fn post_upgrade() {
+//!     rustixml::canister::restore_from_stable_memory().expect("restore grammars");
+//! }
+//! ```
+
+use crate::diagnostics;
+use crate::{parse_ixml_grammar, NativeParser, ParseError};
+use candid::{CandidType, Deserialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// A grammar compiled by [`compile_grammar`]
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GrammarHandle {
+    pub id: u64,
+    pub rule_count: u32,
+}
+
+/// A [`diagnostics::Diagnostic`] with its span resolved to a line and column
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// `error`, `warning` or `info`
+    pub severity: String,
+    /// One of the [`code`](diagnostics::code) constants
+    pub code: String,
+    pub message: String,
+    /// Line in the grammar or input, counted from 1
+    pub line: Option<u32>,
+    /// Column in the grammar or input, counted from 1 in characters
+    pub column: Option<u32>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// `diagnostic`, located in the `source` it was reported on
+    pub fn new(diagnostic: &diagnostics::Diagnostic, source: &str) -> Self {
+        let position = diagnostic.span.map(|span| span.line_col(source));
+        Diagnostic {
+            severity: diagnostic.severity.to_string(),
+            code: diagnostic.code.to_string(),
+            message: diagnostic.message.clone(),
+            line: position.map(|(line, _)| line as u32),
+            column: position.map(|(_, column)| column as u32),
+            notes: diagnostic.notes.clone(),
+        }
+    }
+}
+
+/// The outcome of [`parse`]
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ParseResult {
+    pub ok: bool,
+    /// The XML, when the parse succeeded
+    pub xml: Option<String>,
+    /// Why the parse failed, when it did
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ParseResult {
+    fn failure(diagnostic: Diagnostic) -> Self {
+        ParseResult {
+            ok: false,
+            xml: None,
+            diagnostics: vec![diagnostic],
+        }
+    }
+}
+
+struct CachedGrammar {
+    source: String,
+    parser: NativeParser,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    grammars: BTreeMap<u64, CachedGrammar>,
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::default());
+}
+
+fn compile(text: &str) -> Result<NativeParser, Vec<Diagnostic>> {
+    let grammar = parse_ixml_grammar(text).map_err(|error| {
+        vec![Diagnostic::new(
+            &diagnostics::Diagnostic::from_grammar_error(&error, text),
+            text,
+        )]
+    })?;
+    NativeParser::try_new(grammar).map_err(|error| {
+        diagnostics::Diagnostic::from_grammar_problems(&error)
+            .iter()
+            .map(|diagnostic| Diagnostic::new(diagnostic, text))
+            .collect()
+    })
+}
+
+/// Compile `text` and cache it, returning the handle to parse with
+///
+/// Fails with the grammar's errors when it does not compile.
+pub fn compile_grammar(text: &str) -> Result<GrammarHandle, Vec<Diagnostic>> {
+    let parser = compile(text)?;
+    let rule_count = parser.rule_count() as u32;
+    let id = REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.grammars.insert(
+            id,
+            CachedGrammar {
+                source: text.to_string(),
+                parser,
+            },
+        );
+        id
+    });
+    Ok(GrammarHandle { id, rule_count })
+}
+
+/// Drop the grammar behind `handle` from the cache
+///
+/// Returns whether it was cached.
+pub fn release_grammar(handle: &GrammarHandle) -> bool {
+    REGISTRY.with(|registry| registry.borrow_mut().grammars.remove(&handle.id).is_some())
+}
+
+/// How many grammars are cached
+pub fn cached_grammar_count() -> usize {
+    REGISTRY.with(|registry| registry.borrow().grammars.len())
+}
+
+/// Parse `input` with the grammar behind `handle`
+///
+/// On the IC, `instruction_budget` stops the parse once it has used that
+/// many instructions, before the canister's own limit traps. Elsewhere it is
+/// ignored.
+pub fn parse(handle: &GrammarHandle, input: &str, instruction_budget: Option<u64>) -> ParseResult {
+    REGISTRY.with(|registry| {
+        let registry = registry.borrow();
+        let Some(cached) = registry.grammars.get(&handle.id) else {
+            return ParseResult::failure(Diagnostic {
+                severity: diagnostics::Severity::Error.to_string(),
+                code: diagnostics::code::PARSE_ERROR.to_string(),
+                message: format!("No grammar with id {}", handle.id),
+                line: None,
+                column: None,
+                notes: vec!["compile the grammar again with compile_grammar".to_string()],
+            });
+        };
+        match run(&cached.parser, input, instruction_budget) {
+            Ok(xml) => ParseResult {
+                ok: true,
+                xml: Some(xml),
+                diagnostics: Vec::new(),
+            },
+            Err(error) => ParseResult::failure(Diagnostic::new(
+                &diagnostics::Diagnostic::from_parse_error(&error),
+                input,
+            )),
+        }
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+fn run(parser: &NativeParser, input: &str, budget: Option<u64>) -> Result<String, ParseError> {
+    parser.parse_with_budget(input, budget)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn run(parser: &NativeParser, input: &str, _budget: Option<u64>) -> Result<String, ParseError> {
+    parser.parse(input)
+}
+
+/// The grammar cache as it is kept in stable memory: each grammar's id and
+/// source, and the next id to hand out
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GrammarCache {
+    pub grammars: Vec<(u64, String)>,
+    pub next_id: u64,
+}
+
+/// The grammar cache, for canisters that keep it in stable memory alongside
+/// their own state
+pub fn save_grammar_cache() -> GrammarCache {
+    REGISTRY.with(|registry| {
+        let registry = registry.borrow();
+        let grammars = registry
+            .grammars
+            .iter()
+            .map(|(id, cached)| (*id, cached.source.clone()))
+            .collect();
+        GrammarCache {
+            grammars,
+            next_id: registry.next_id,
+        }
+    })
+}
+
+/// Replace the grammar cache with `cache`, recompiling its grammars
+///
+/// Handles from before [`save_grammar_cache`] stay valid. Fails, leaving the
+/// cache as it was, if a grammar no longer compiles.
+pub fn restore_grammar_cache(cache: GrammarCache) -> Result<(), String> {
+    let GrammarCache {
+        grammars: sources,
+        next_id,
+    } = cache;
+    let mut grammars = BTreeMap::new();
+    for (id, source) in sources {
+        let parser = compile(&source).map_err(|diagnostics| {
+            let messages: Vec<String> = diagnostics.into_iter().map(|d| d.message).collect();
+            format!("Grammar {} no longer compiles: {}", id, messages.join("; "))
+        })?;
+        grammars.insert(id, CachedGrammar { source, parser });
+    }
+    REGISTRY.with(|registry| *registry.borrow_mut() = Registry { next_id, grammars });
+    Ok(())
+}
+
+/// Write the cached grammars to stable memory, for `pre_upgrade`
+///
+/// This replaces anything else the canister keeps in stable memory; to keep
+/// both, store [`save_grammar_cache`] with the canister's own state.
+#[cfg(target_arch = "wasm32")]
+pub fn save_to_stable_memory() -> Result<(), String> {
+    ic_cdk::storage::stable_save((save_grammar_cache(),)).map_err(|error| error.to_string())
+}
+
+/// Recompile the grammars [`save_to_stable_memory`] wrote, for
+/// `post_upgrade`
+#[cfg(target_arch = "wasm32")]
+pub fn restore_from_stable_memory() -> Result<(), String> {
+    let (cache,): (GrammarCache,) = ic_cdk::storage::stable_restore()?;
+    restore_grammar_cache(cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_and_parse() {
+        let handle = compile_grammar("greeting: 'hello'.").unwrap();
+        assert_eq!(handle.rule_count, 1);
+
+        let result = parse(&handle, "hello", None);
+        assert!(result.ok);
+        assert_eq!(result.xml.as_deref(), Some("<greeting>hello</greeting>"));
+
+        let result = parse(&handle, "hello!", None);
+        assert!(!result.ok);
+        let diagnostic = &result.diagnostics[0];
+        assert_eq!(diagnostic.code, "parse-error");
+        assert_eq!((diagnostic.line, diagnostic.column), (Some(1), Some(6)));
+
+        assert!(release_grammar(&handle));
+        assert!(!parse(&handle, "hello", None).ok);
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let errors = compile_grammar("doc: item.").unwrap_err();
+        assert_eq!(errors[0].code, "undefined-rule");
+
+        let errors = compile_grammar("doc: 'a'").unwrap_err();
+        assert_eq!(errors[0].code, "grammar-syntax");
+    }
+
+    #[test]
+    fn test_restored_cache_keeps_handles() {
+        let handle = compile_grammar("digits: ['0'-'9']+.").unwrap();
+        let saved = save_grammar_cache();
+        let encoded = candid::encode_one(&saved).unwrap();
+
+        REGISTRY.with(|registry| *registry.borrow_mut() = Registry::default());
+        assert_eq!(cached_grammar_count(), 0);
+
+        restore_grammar_cache(candid::decode_one(&encoded).unwrap()).unwrap();
+        assert_eq!(
+            parse(&handle, "42", None).xml.as_deref(),
+            Some("<digits>42</digits>")
+        );
+        let next = compile_grammar("x: 'x'.").unwrap();
+        assert!(next.id > handle.id);
+    }
+}
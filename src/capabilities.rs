@@ -0,0 +1,279 @@
+//! Supported iXML specification features
+//!
+//! [`FEATURES`] is the single registry of what rustixml implements. Host
+//! applications query it through [`capabilities()`] to adapt (for example, to
+//! avoid renaming), and the conformance status reported by the WASM bindings
+//! and the conformance runner is generated from it, so the two cannot drift.
+
+use crate::convert::json_string;
+use std::fmt;
+
+/// How completely a feature is implemented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Support {
+    /// Implemented as specified
+    Full,
+    /// Implemented with known gaps (see the feature's notes)
+    Partial,
+    /// Not implemented; grammars using it are rejected or mis-parsed
+    Unsupported,
+}
+
+impl fmt::Display for Support {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Support::Full => "full",
+            Support::Partial => "partial",
+            Support::Unsupported => "unsupported",
+        })
+    }
+}
+
+/// One iXML specification feature and its support level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Feature {
+    /// Stable identifier, e.g. `"insertions"`
+    pub id: &'static str,
+    /// Spec version that introduced the feature (`"1.0"`, `"1.1"`), or `"extension"`
+    pub spec: &'static str,
+    pub support: Support,
+    /// Known gaps or how to enable the feature
+    pub notes: &'static str,
+}
+
+#[cfg(not(feature = "minimal-unicode"))]
+const UNICODE_CATEGORY_SUPPORT: Support = Support::Full;
+#[cfg(not(feature = "minimal-unicode"))]
+const UNICODE_CATEGORY_NOTES: &str = "general categories such as `[L]`, `[Nd]`";
+#[cfg(feature = "minimal-unicode")]
+const UNICODE_CATEGORY_SUPPORT: Support = Support::Partial;
+#[cfg(feature = "minimal-unicode")]
+const UNICODE_CATEGORY_NOTES: &str =
+    "ASCII approximations only (built with the `minimal-unicode` feature)";
+
+/// Every feature rustixml knows about, in spec order
+pub const FEATURES: &[Feature] = &[
+    Feature {
+        id: "marks",
+        spec: "1.0",
+        support: Support::Full,
+        notes: "`-` hidden, `@` attribute and `^` promoted marks on rules and factors",
+    },
+    Feature {
+        id: "insertions",
+        spec: "1.0",
+        support: Support::Full,
+        notes: "`+\"text\"` and `+#hex`, including inside attributes and separators",
+    },
+    Feature {
+        id: "version-declaration",
+        spec: "1.0",
+        support: Support::Full,
+        notes: "recorded on IxmlGrammar::version; versions other than 1.0 mark output \
+                `ixml:state=\"version-mismatch\"`",
+    },
+    Feature {
+        id: "separated-repetition",
+        spec: "1.0",
+        support: Support::Full,
+        notes: "`**` and `++`",
+    },
+    Feature {
+        id: "character-classes",
+        spec: "1.0",
+        support: Support::Full,
+        notes: "ranges, hex characters, `~[...]` exclusions and `[L; ~\"aeiou\"]` \
+                member subtraction",
+    },
+    Feature {
+        id: "unicode-categories",
+        spec: "1.0",
+        support: UNICODE_CATEGORY_SUPPORT,
+        notes: UNICODE_CATEGORY_NOTES,
+    },
+    Feature {
+        id: "left-recursion",
+        spec: "1.0",
+        support: Support::Full,
+        notes: "handled by seed growing",
+    },
+    Feature {
+        id: "ambiguity-reporting",
+        spec: "1.0",
+        support: Support::Partial,
+        notes: "`ixml:state=\"ambiguous\"` is set, but the chosen tree may differ from \
+                other processors; use ParseStrategy::Earley for exact detection",
+    },
+    Feature {
+        id: "failure-reporting",
+        spec: "1.0",
+        support: Support::Full,
+        notes: "NativeParser::parse_outcome returns an `ixml:state=\"failed\"` document with \
+                the error's line, column and message; `parse` returns the error",
+    },
+    Feature {
+        id: "pragmas",
+        spec: "1.1",
+        support: Support::Partial,
+        notes: "`{[name data]}` before rules and factors is parsed and exposed through \
+                IxmlGrammar::visit_pragmas; `fixed-attribute` adds output attributes, \
+                and no pragma changes parsing yet",
+    },
+    Feature {
+        id: "renaming",
+        spec: "1.1",
+        support: Support::Unsupported,
+        notes: "",
+    },
+    Feature {
+        id: "negative-lookahead",
+        spec: "extension",
+        support: Support::Full,
+        notes: "`!factor`, enabled with GrammarOptions::allow_extensions",
+    },
+];
+
+/// iXML versions whose grammars rustixml processes without a version mismatch
+pub const SUPPORTED_VERSIONS: &[&str] = &["1.0"];
+
+/// Results of the last run of the conformance suite in `ixml_tests/`
+///
+/// The conformance runner reports when a run no longer matches these numbers.
+pub const CONFORMANCE: ConformanceStatus = ConformanceStatus {
+    passed: 52,
+    total: 65,
+};
+
+/// Pass count over the conformance suite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConformanceStatus {
+    pub passed: usize,
+    pub total: usize,
+}
+
+impl ConformanceStatus {
+    /// Pass rate as a percentage
+    pub fn percent(&self) -> f64 {
+        self.passed as f64 / self.total as f64 * 100.0
+    }
+}
+
+/// What this build of rustixml supports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Crate version
+    pub version: &'static str,
+    /// Latest iXML specification version targeted
+    pub spec_version: &'static str,
+    pub features: &'static [Feature],
+    pub conformance: ConformanceStatus,
+}
+
+impl Capabilities {
+    /// Look up a feature by id
+    pub fn feature(&self, id: &str) -> Option<&'static Feature> {
+        self.features.iter().find(|feature| feature.id == id)
+    }
+
+    /// Whether a feature is at least partially supported
+    pub fn supports(&self, id: &str) -> bool {
+        self.feature(id)
+            .is_some_and(|feature| feature.support != Support::Unsupported)
+    }
+
+    /// One-line conformance summary naming incomplete features
+    pub fn conformance_summary(&self) -> String {
+        let mut summary = format!(
+            "{:.1}% conformance ({}/{} tests passing)",
+            self.conformance.percent(),
+            self.conformance.passed,
+            self.conformance.total
+        );
+        for support in [Support::Partial, Support::Unsupported] {
+            let ids: Vec<&str> = self
+                .features
+                .iter()
+                .filter(|feature| feature.support == support)
+                .map(|feature| feature.id)
+                .collect();
+            if !ids.is_empty() {
+                summary.push_str(&format!("; {}: {}", support, ids.join(", ")));
+            }
+        }
+        summary
+    }
+
+    /// Serialize as JSON (for the WASM bindings and other hosts)
+    pub fn to_json(&self) -> String {
+        let features: Vec<String> = self
+            .features
+            .iter()
+            .map(|feature| {
+                format!(
+                    "{{\"id\":\"{}\",\"spec\":\"{}\",\"support\":\"{}\",\"notes\":{}}}",
+                    feature.id,
+                    feature.spec,
+                    feature.support,
+                    json_string(feature.notes)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"version\":\"{}\",\"specVersion\":\"{}\",\"conformance\":{{\"passed\":{},\"total\":{}}},\"features\":[{}]}}",
+            self.version,
+            self.spec_version,
+            self.conformance.passed,
+            self.conformance.total,
+            features.join(",")
+        )
+    }
+}
+
+/// Report the iXML features supported by this build
+///
+/// ```
+/// let caps = rustixml::capabilities();
+/// assert!(caps.supports("insertions"));
+/// assert!(!caps.supports("renaming"));
+/// ```
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        spec_version: "1.0",
+        features: FEATURES,
+        conformance: CONFORMANCE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feature_ids_unique() {
+        for (i, feature) in FEATURES.iter().enumerate() {
+            assert!(
+                FEATURES[i + 1..].iter().all(|other| other.id != feature.id),
+                "duplicate feature id {}",
+                feature.id
+            );
+        }
+    }
+
+    #[test]
+    fn test_summary_and_json_follow_registry() {
+        let caps = capabilities();
+        assert_eq!(caps.feature("pragmas").unwrap().spec, "1.1");
+        assert!(caps.feature("regex").is_none());
+
+        let summary = caps.conformance_summary();
+        assert!(summary.starts_with("80.0% conformance (52/65 tests passing)"));
+        #[cfg(not(feature = "minimal-unicode"))]
+        assert!(summary.contains("partial: ambiguity-reporting, pragmas"));
+        assert!(summary.contains("unsupported: renaming"));
+
+        let json = caps.to_json();
+        assert!(json.contains("\"id\":\"insertions\",\"spec\":\"1.0\",\"support\":\"full\""));
+        assert!(json.contains("\\\"ambiguous\\\""));
+    }
+}
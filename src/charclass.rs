@@ -2,13 +2,29 @@
 //!
 //! This module provides functionality for parsing and matching iXML character classes.
 
-use std::collections::HashMap;
-use unicode_general_category::{get_general_category, GeneralCategory};
-
+use std::fmt;
+
+/// A set of characters stored as sorted, non-overlapping inclusive ranges
+///
+/// ```
+/// use rustixml::charclass::RangeSet;
+///
+/// let digits = RangeSet::from_range('0', '9');
+/// let hex = digits.union(&RangeSet::from_charclass(r#""a"-"f"; "A"-"F""#));
+/// assert!(hex.contains('c') && hex.contains('7') && !hex.contains('g'));
+/// assert_eq!(hex.ranges(), &[('0', '9'), ('A', 'F'), ('a', 'f')]);
+///
+/// let letters = RangeSet::from_charclass("L");
+/// # #[cfg(not(feature = "minimal-unicode"))]
+/// assert!(letters.contains('ß') && !letters.contains('1'));
+/// ```
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct RangeSet {
     /// Sorted, non-overlapping ranges stored as (start, end) inclusive
     ranges: Vec<(char, char)>,
+    /// Bit `n` is set when the character with code `n` is in the set, so ASCII
+    /// lookups, the common case, skip the range search
+    ascii: u128,
 }
 
 impl Default for RangeSet {
@@ -20,25 +36,46 @@ impl Default for RangeSet {
 impl RangeSet {
     /// Create an empty RangeSet
     pub fn new() -> Self {
-        RangeSet { ranges: Vec::new() }
+        RangeSet {
+            ranges: Vec::new(),
+            ascii: 0,
+        }
     }
 
     /// Create a RangeSet from a single character
     pub fn from_char(ch: char) -> Self {
-        RangeSet {
-            ranges: vec![(ch, ch)],
-        }
+        RangeSet::from_range(ch, ch)
     }
 
     /// Create a RangeSet from a range
     pub fn from_range(start: char, end: char) -> Self {
-        if start <= end {
-            RangeSet {
-                ranges: vec![(start, end)],
-            }
-        } else {
-            RangeSet::new()
-        }
+        let mut set = RangeSet::new();
+        set.add_range(start, end);
+        set
+    }
+
+    /// Create a RangeSet from ranges in any order
+    pub(crate) fn from_ranges(ranges: &[(char, char)]) -> Self {
+        let mut set = RangeSet {
+            ranges: ranges
+                .iter()
+                .copied()
+                .filter(|(start, end)| start <= end)
+                .collect(),
+            ascii: 0,
+        };
+        set.normalize();
+        set
+    }
+
+    /// Create a RangeSet from iXML character class content (the text between `[` and `]`)
+    pub fn from_charclass(content: &str) -> Self {
+        charclass_to_rangeset(content)
+    }
+
+    /// The ranges in the set, sorted and inclusive
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
     }
 
     /// Check if the set is empty
@@ -60,8 +97,22 @@ impl RangeSet {
         self.normalize();
     }
 
-    /// Normalize ranges: sort and merge overlapping/adjacent ranges
+    /// Normalize ranges: sort and merge overlapping/adjacent ranges, then
+    /// rebuild the ASCII bitmap
     fn normalize(&mut self) {
+        self.merge_ranges();
+        self.ascii = 0;
+        for &(start, end) in &self.ranges {
+            if !start.is_ascii() {
+                break;
+            }
+            let (start, end) = (start as u32, (end as u32).min(127));
+            // Bits start..=end, without overflowing when the range is all of ASCII
+            self.ascii |= (u128::MAX >> (127 - (end - start))) << start;
+        }
+    }
+
+    fn merge_ranges(&mut self) {
         if self.ranges.len() <= 1 {
             return;
         }
@@ -86,9 +137,8 @@ impl RangeSet {
     /// Union of two RangeSets
     pub fn union(&self, other: &RangeSet) -> RangeSet {
         let mut result = self.clone();
-        for &(start, end) in &other.ranges {
-            result.add_range(start, end);
-        }
+        result.ranges.extend_from_slice(&other.ranges);
+        result.normalize();
         result
     }
 
@@ -124,12 +174,11 @@ impl RangeSet {
                     // Overlap exists, split the range
                     if start < sub_start {
                         // Keep part before subtraction
-                        new_ranges
-                            .push((start, char::from_u32(sub_start as u32 - 1).unwrap_or(start)));
+                        new_ranges.push((start, char_before(sub_start)));
                     }
                     if end > sub_end {
                         // Keep part after subtraction
-                        new_ranges.push((char::from_u32(sub_end as u32 + 1).unwrap_or(end), end));
+                        new_ranges.push((char_after(sub_end), end));
                     }
                 }
             }
@@ -140,13 +189,16 @@ impl RangeSet {
     }
 
     /// Check if the set contains a character
+    ///
+    /// ASCII characters are one bit test; others are a binary search of the
+    /// ranges, so large Unicode categories cost no more than small classes.
     pub fn contains(&self, ch: char) -> bool {
-        for &(start, end) in &self.ranges {
-            if ch >= start && ch <= end {
-                return true;
-            }
+        if ch.is_ascii() {
+            return self.ascii >> ch as u32 & 1 == 1;
         }
-        false
+        // The last range starting at or before `ch` is the only one that can hold it
+        let index = self.ranges.partition_point(|&(start, _)| start <= ch);
+        index > 0 && ch <= self.ranges[index - 1].1
     }
 
     /// Get the number of ranges in this set
@@ -154,6 +206,17 @@ impl RangeSet {
         self.ranges.len()
     }
 
+    /// Every character not in this set
+    pub fn complement(&self) -> RangeSet {
+        RangeSet::from_range('\0', char::MAX).minus(self)
+    }
+
+    /// The set as the members of an iXML character class, e.g. `"0"-"9"; #a`
+    pub(crate) fn to_charclass_content(&self) -> String {
+        let class = self.to_string();
+        class[1..class.len() - 1].to_string()
+    }
+
     /// Generate a unique name for this RangeSet
     pub fn to_name(&self) -> String {
         let mut parts = Vec::new();
@@ -185,9 +248,34 @@ impl RangeSet {
     }
 }
 
+/// The set as an iXML character class, e.g. `["0"-"9"; "_"; #a]`
+impl fmt::Display for RangeSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let character = |ch: char| {
+            if ch.is_control() || ch.is_whitespace() || ch == '"' {
+                format!("#{:x}", ch as u32)
+            } else {
+                format!("\"{}\"", ch)
+            }
+        };
+        let members: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|&(start, end)| {
+                if start == end {
+                    character(start)
+                } else {
+                    format!("{}-{}", character(start), character(end))
+                }
+            })
+            .collect();
+        write!(f, "[{}]", members.join("; "))
+    }
+}
+
 /// Split character class content by separator characters while respecting quoted strings
 /// In character classes, `;`, `,`, and `|` are separators, but not inside quotes
-fn split_charclass_content(content: &str) -> Vec<String> {
+pub(crate) fn split_charclass_content(content: &str) -> Vec<String> {
     let mut elements = Vec::new();
     let mut current = String::new();
     let mut in_quote = false;
@@ -226,28 +314,12 @@ fn split_charclass_content(content: &str) -> Vec<String> {
 
 /// Convert a Unicode General Category name to a RangeSet
 /// Supports both major categories (L, M, N, P, S, Z, C) and minor categories (Lu, Ll, etc.)
-/// Convert a Unicode category name to a RangeSet.
-/// This function is cached internally to avoid recomputing expensive ranges.
+///
+/// The ranges come from tables generated at build time, so this is a copy
+/// with no scan of the code points and no lock. With the `minimal-unicode`
+/// feature, categories are ASCII approximations and no non-ASCII character
+/// matches any of them.
 pub fn unicode_category_to_rangeset(category_name: &str) -> Option<RangeSet> {
-    use std::sync::{Mutex, OnceLock};
-
-    // Cache for Unicode category rangesets
-    static UNICODE_CACHE: OnceLock<Mutex<HashMap<String, RangeSet>>> = OnceLock::new();
-
-    // Get or initialize the cache
-    let cache = UNICODE_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
-
-    // Check if we have it cached
-    {
-        let cache_lock = cache.lock().unwrap();
-        if let Some(rangeset) = cache_lock.get(category_name) {
-            return Some(rangeset.clone());
-        }
-    }
-
-    // Not cached, compute it
-    let mut result = RangeSet::new();
-
     // Check if this is a valid Unicode category name
     let is_major = matches!(category_name, "L" | "M" | "N" | "P" | "S" | "Z" | "C");
     let is_minor = matches!(
@@ -288,263 +360,359 @@ pub fn unicode_category_to_rangeset(category_name: &str) -> Option<RangeSet> {
         return None;
     }
 
-    // Helper to check if a GeneralCategory matches a category name
-    let matches_category = |cat: GeneralCategory, name: &str| -> bool {
-        match name {
-            // Major categories
-            "L" => matches!(
-                cat,
-                GeneralCategory::UppercaseLetter
-                    | GeneralCategory::LowercaseLetter
-                    | GeneralCategory::TitlecaseLetter
-                    | GeneralCategory::ModifierLetter
-                    | GeneralCategory::OtherLetter
-            ),
-            "LC" => matches!(
-                cat,
-                GeneralCategory::UppercaseLetter
-                    | GeneralCategory::LowercaseLetter
-                    | GeneralCategory::TitlecaseLetter
-            ),
-            "M" => matches!(
-                cat,
-                GeneralCategory::NonspacingMark
-                    | GeneralCategory::SpacingMark
-                    | GeneralCategory::EnclosingMark
-            ),
-            "N" => matches!(
-                cat,
-                GeneralCategory::DecimalNumber
-                    | GeneralCategory::LetterNumber
-                    | GeneralCategory::OtherNumber
-            ),
-            "P" => matches!(
-                cat,
-                GeneralCategory::ConnectorPunctuation
-                    | GeneralCategory::DashPunctuation
-                    | GeneralCategory::OpenPunctuation
-                    | GeneralCategory::ClosePunctuation
-                    | GeneralCategory::InitialPunctuation
-                    | GeneralCategory::FinalPunctuation
-                    | GeneralCategory::OtherPunctuation
-            ),
-            "S" => matches!(
-                cat,
-                GeneralCategory::MathSymbol
-                    | GeneralCategory::CurrencySymbol
-                    | GeneralCategory::ModifierSymbol
-                    | GeneralCategory::OtherSymbol
-            ),
-            "Z" => matches!(
-                cat,
-                GeneralCategory::SpaceSeparator
-                    | GeneralCategory::LineSeparator
-                    | GeneralCategory::ParagraphSeparator
-            ),
-            "C" => matches!(
-                cat,
-                GeneralCategory::Control
-                    | GeneralCategory::Format
-                    | GeneralCategory::Surrogate
-                    | GeneralCategory::PrivateUse
-                    | GeneralCategory::Unassigned
-            ),
-            // Minor categories
-            "Lu" => cat == GeneralCategory::UppercaseLetter,
-            "Ll" => cat == GeneralCategory::LowercaseLetter,
-            "Lt" => cat == GeneralCategory::TitlecaseLetter,
-            "Lm" => cat == GeneralCategory::ModifierLetter,
-            "Lo" => cat == GeneralCategory::OtherLetter,
-            "Mn" => cat == GeneralCategory::NonspacingMark,
-            "Mc" => cat == GeneralCategory::SpacingMark,
-            "Me" => cat == GeneralCategory::EnclosingMark,
-            "Nd" => cat == GeneralCategory::DecimalNumber,
-            "Nl" => cat == GeneralCategory::LetterNumber,
-            "No" => cat == GeneralCategory::OtherNumber,
-            "Pc" => cat == GeneralCategory::ConnectorPunctuation,
-            "Pd" => cat == GeneralCategory::DashPunctuation,
-            "Ps" => cat == GeneralCategory::OpenPunctuation,
-            "Pe" => cat == GeneralCategory::ClosePunctuation,
-            "Pi" => cat == GeneralCategory::InitialPunctuation,
-            "Pf" => cat == GeneralCategory::FinalPunctuation,
-            "Po" => cat == GeneralCategory::OtherPunctuation,
-            "Sm" => cat == GeneralCategory::MathSymbol,
-            "Sc" => cat == GeneralCategory::CurrencySymbol,
-            "Sk" => cat == GeneralCategory::ModifierSymbol,
-            "So" => cat == GeneralCategory::OtherSymbol,
-            "Zs" => cat == GeneralCategory::SpaceSeparator,
-            "Zl" => cat == GeneralCategory::LineSeparator,
-            "Zp" => cat == GeneralCategory::ParagraphSeparator,
-            "Cc" => cat == GeneralCategory::Control,
-            "Cf" => cat == GeneralCategory::Format,
-            "Cs" => cat == GeneralCategory::Surrogate,
-            "Co" => cat == GeneralCategory::PrivateUse,
-            "Cn" => cat == GeneralCategory::Unassigned,
-            _ => false,
-        }
-    };
+    Some(category_ranges(category_name))
+}
 
-    // Iterate through all valid Unicode codepoints and collect matching characters
-    // Unicode goes up to 0x10FFFF (1,114,111 code points)
-    let mut range_start: Option<char> = None;
-    let mut prev_char: Option<char> = None;
+#[cfg(not(feature = "minimal-unicode"))]
+include!(concat!(env!("OUT_DIR"), "/unicode_categories.rs"));
 
-    for codepoint in 0u32..=0x10FFFF {
-        if let Some(ch) = char::from_u32(codepoint) {
-            let cat = get_general_category(ch);
+/// The characters of a valid category name, from the full Unicode tables
+#[cfg(not(feature = "minimal-unicode"))]
+fn category_ranges(category_name: &str) -> RangeSet {
+    RangeSet::from_ranges(category_table(category_name).unwrap_or_default())
+}
 
-            // Check if character matches category
-            let mut is_match = matches_category(cat, category_name);
+/// The ASCII characters of a valid category name, approximating it without
+/// the Unicode tables (the `minimal-unicode` feature)
+///
+/// Characters outside ASCII belong to no category, so grammars using
+/// categories for non-ASCII text stop matching it.
+#[cfg(feature = "minimal-unicode")]
+fn category_ranges(category_name: &str) -> RangeSet {
+    let chars: &[(char, char)] = match category_name {
+        "Lu" => &[('A', 'Z')],
+        "Ll" => &[('a', 'z')],
+        "L" | "LC" => &[('A', 'Z'), ('a', 'z')],
+        "Nd" | "N" => &[('0', '9')],
+        "Pc" => &[('_', '_')],
+        "Pd" => &[('-', '-')],
+        "Ps" => &[('(', '('), ('[', '['), ('{', '{')],
+        "Pe" => &[(')', ')'), (']', ']'), ('}', '}')],
+        "Po" => &[
+            ('!', '#'),
+            ('%', '\''),
+            ('*', '*'),
+            (',', ','),
+            ('.', '/'),
+            (':', ';'),
+            ('?', '@'),
+            ('\\', '\\'),
+        ],
+        "P" => &[
+            ('!', '#'),
+            ('%', '*'),
+            (',', '/'),
+            (':', ';'),
+            ('?', '@'),
+            ('[', ']'),
+            ('_', '_'),
+            ('{', '{'),
+            ('}', '}'),
+        ],
+        "Sm" => &[('+', '+'), ('<', '>'), ('|', '|'), ('~', '~')],
+        "Sc" => &[('$', '$')],
+        "Sk" => &[('^', '^'), ('`', '`')],
+        "S" => &[
+            ('$', '$'),
+            ('+', '+'),
+            ('<', '>'),
+            ('^', '^'),
+            ('`', '`'),
+            ('|', '|'),
+            ('~', '~'),
+        ],
+        "Zs" | "Z" => &[(' ', ' ')],
+        // Like the full tables, leave out #a and #d
+        "Cc" | "C" => &[
+            ('\0', '\u{9}'),
+            ('\u{b}', '\u{c}'),
+            ('\u{e}', '\u{1f}'),
+            ('\u{7f}', '\u{7f}'),
+        ],
+        _ => &[],
+    };
+    RangeSet::from_ranges(chars)
+}
 
-            // iXML special rule: exclude newline characters (#a = U+000A, #d = U+000D)
-            // from control character categories, even though Unicode categorizes them as Cc.
-            // This allows grammars to handle newlines separately for line-oriented parsing.
-            if is_match && matches!(category_name, "Cc" | "C") && (ch == '\n' || ch == '\r') {
-                is_match = false;
-            }
+/// Parse a character class content string into a RangeSet
+/// This handles the same formats as parse_char_class but returns a RangeSet
+///
+/// Members prefixed with `~` are exclusions: they are subtracted from the union
+/// of the other members, wherever they appear in the class.
+///
+/// ```
+/// use rustixml::charclass::charclass_to_rangeset;
+///
+/// let consonants = charclass_to_rangeset(r#""a"-"z"; ~"aeiou""#);
+/// assert!(consonants.contains('b') && !consonants.contains('e'));
+/// ```
+pub fn charclass_to_rangeset(content: &str) -> RangeSet {
+    let mut included = RangeSet::new();
+    let mut excluded = RangeSet::new();
 
-            if is_match {
-                if range_start.is_none() {
-                    range_start = Some(ch);
-                }
-                prev_char = Some(ch);
-            } else {
-                // End of range
-                if let (Some(start), Some(end)) = (range_start, prev_char) {
-                    result.add_range(start, end);
-                }
-                range_start = None;
-                prev_char = None;
-            }
+    // Split while respecting quoted strings
+    for element in split_charclass_content(content) {
+        match element.strip_prefix('~') {
+            Some(member) => add_member(&mut excluded, member.trim()),
+            None => add_member(&mut included, &element),
         }
     }
 
-    // Don't forget the last range
-    if let (Some(start), Some(end)) = (range_start, prev_char) {
-        result.add_range(start, end);
-    }
-
-    // Cache the result before returning
-    {
-        let mut cache_lock = cache.lock().unwrap();
-        cache_lock.insert(category_name.to_string(), result.clone());
+    if excluded.is_empty() {
+        included
+    } else {
+        included.minus(&excluded)
     }
-
-    Some(result)
 }
 
-/// Parse a character class content string into a RangeSet
-/// This handles the same formats as parse_char_class but returns a RangeSet
-pub fn charclass_to_rangeset(content: &str) -> RangeSet {
-    let mut result = RangeSet::new();
-
-    // Split while respecting quoted strings
+/// Check whether a class has `~` exclusion members but nothing to subtract them from
+pub(crate) fn has_only_exclusions(content: &str) -> bool {
     let elements = split_charclass_content(content);
+    !elements.is_empty() && elements.iter().all(|element| element.starts_with('~'))
+}
 
-    for element in elements {
-        let element = element.trim();
-        if element.is_empty() {
-            continue;
-        }
+/// The characters a regex character class such as `[A-Za-z0-9_]`, `[^,\n]`
+/// or `\d` matches
+///
+/// Classes may use ranges, `^` negation, the ASCII shorthands `\d`, `\w`
+/// and `\s` and their negations `\D`, `\W` and `\S`, and the escapes `\n`,
+/// `\r`, `\t`, `\f`, `\v`, `\0`, `\xHH`, `\uHHHH`, `\u{H...}` and `\` before
+/// punctuation. `.` matches anything but a line feed.
+///
+/// ```
+/// use rustixml::charclass::from_regex_class;
+///
+/// let word = from_regex_class("[A-Za-z0-9_]").unwrap();
+/// assert_eq!(word.to_string(), r#"["0"-"9"; "A"-"Z"; "_"; "a"-"z"]"#);
+/// assert_eq!(from_regex_class(r"\w"), Ok(word));
+///
+/// let field = from_regex_class(r"[^,\n]").unwrap();
+/// assert!(field.contains('a') && !field.contains(',') && !field.contains('\n'));
+/// assert!(from_regex_class("[a-z").is_err());
+/// ```
+pub fn from_regex_class(class: &str) -> Result<RangeSet, String> {
+    let chars: Vec<char> = class.chars().collect();
+    let (set, negated, end) = parse_regex_class(&chars, 0)?;
+    if end < chars.len() {
+        let rest: String = chars[end..].iter().collect();
+        return Err(format!("Unexpected `{}` after regex class", rest));
+    }
+    Ok(if negated { set.complement() } else { set })
+}
 
-        // Check for hex character range: #30-#39 or #1-"÷"
-        if element.starts_with('#') && element.contains('-') {
-            if let Some(dash_pos) = element[1..].find('-') {
-                let actual_dash_pos = dash_pos + 1;
-                let start_part = &element[..actual_dash_pos];
-                let end_part = &element[actual_dash_pos + 1..];
-
-                if end_part.starts_with('#') {
-                    // Hex-to-hex range: #30-#39
-                    if let (Some(start), Some(end)) =
-                        (parse_hex_char(start_part), parse_hex_char(end_part))
-                    {
-                        result.add_range(start, end);
-                        continue;
-                    }
-                } else if end_part.starts_with('"') || end_part.starts_with('\'') {
-                    // Hex-to-literal range: #1-"÷"
-                    let quote = if end_part.starts_with('"') { '"' } else { '\'' };
-                    if let Some(close_pos) = end_part[1..].find(quote) {
-                        let end_str = &end_part[1..close_pos + 1];
-                        let end_char = end_str.chars().next();
-                        if let (Some(start), Some(end)) = (parse_hex_char(start_part), end_char) {
-                            result.add_range(start, end);
-                            continue;
-                        }
-                    }
-                }
-            }
-            // Not a range, treat as single hex char
-            if let Some(ch) = parse_hex_char(element) {
-                result.add_char(ch);
+/// The regex class at `start`: the characters it lists, whether it is
+/// negated, and where it ends
+pub(crate) fn parse_regex_class(
+    chars: &[char],
+    start: usize,
+) -> Result<(RangeSet, bool, usize), String> {
+    match chars.get(start) {
+        Some('.') => Ok((RangeSet::from_char('\n'), true, start + 1)),
+        Some('\\') => match regex_escape(chars, start)? {
+            (RegexEscape::Char(ch), end) => Ok((RangeSet::from_char(ch), false, end)),
+            (RegexEscape::Class(set, negated), end) => Ok((set, negated, end)),
+        },
+        Some('[') => {
+            let mut at = start + 1;
+            let negated = chars.get(at) == Some(&'^');
+            if negated {
+                at += 1;
             }
-        }
-        // Check for quoted character range: "a"-"z"
-        else if (element.starts_with('\'') || element.starts_with('"')) && element.contains('-') {
-            let quote = if element.starts_with('\'') { '\'' } else { '"' };
-            if let Some(first_close) = element[1..].find(quote) {
-                let first_close = first_close + 1;
-                let after_close = &element[first_close + 1..];
-                if after_close.starts_with('-') && after_close.len() > 1 {
-                    let after_dash = &after_close[1..];
-                    if after_dash.starts_with('\'') || after_dash.starts_with('"') {
-                        let start_str = &element[1..first_close];
-                        let start_char = start_str.chars().next();
-                        let end_quote = if after_dash.starts_with('\'') {
-                            '\''
-                        } else {
-                            '"'
-                        };
-                        if let Some(end_close) = after_dash[1..].find(end_quote) {
-                            let end_str = &after_dash[1..end_close + 1];
-                            let end_char = end_str.chars().next();
-                            if let (Some(start), Some(end)) = (start_char, end_char) {
-                                result.add_range(start, end);
+            let mut set = RangeSet::new();
+            let first = at;
+            loop {
+                let from = match chars.get(at) {
+                    None => return Err("Unterminated regex class".to_string()),
+                    // A `]` straight after the opening bracket is a member
+                    Some(']') if at > first => return Ok((set, negated, at + 1)),
+                    Some('\\') => {
+                        let (escape, end) = regex_escape(chars, at)?;
+                        at = end;
+                        match escape {
+                            RegexEscape::Char(ch) => ch,
+                            RegexEscape::Class(class, class_negated) => {
+                                set = set.union(&if class_negated {
+                                    class.complement()
+                                } else {
+                                    class
+                                });
                                 continue;
                             }
                         }
                     }
+                    Some(&ch) => {
+                        at += 1;
+                        ch
+                    }
+                };
+                if chars.get(at) != Some(&'-') || matches!(chars.get(at + 1), None | Some(']')) {
+                    set.add_char(from);
+                    continue;
                 }
+                let to = match chars.get(at + 1) {
+                    Some('\\') => match regex_escape(chars, at + 1)? {
+                        (RegexEscape::Char(ch), end) => {
+                            at = end;
+                            ch
+                        }
+                        (RegexEscape::Class(..), _) => {
+                            return Err("A regex range cannot end in a class like `\\d`".to_string())
+                        }
+                    },
+                    Some(&ch) => {
+                        at += 2;
+                        ch
+                    }
+                    None => unreachable!("checked above"),
+                };
+                if to < from {
+                    return Err(format!("Regex range `{}-{}` is out of order", from, to));
+                }
+                set.add_range(from, to);
             }
-            // Not a range, treat as quoted characters
-            // Only trim the quote character that was actually used
-            let inner = if element.starts_with('\'') {
-                element.trim_matches('\'')
-            } else {
-                element.trim_matches('"')
+        }
+        _ => Err("Expected a regex class such as `[a-z]`, `\\d` or `.`".to_string()),
+    }
+}
+
+/// What a regex escape stands for
+pub(crate) enum RegexEscape {
+    Char(char),
+    /// `\d`, `\w` or `\s`, or negated as `\D`, `\W` or `\S`
+    Class(RangeSet, bool),
+}
+
+/// The regex escape at `start`, which is a `\`, and where it ends
+pub(crate) fn regex_escape(chars: &[char], start: usize) -> Result<(RegexEscape, usize), String> {
+    let letter = *chars.get(start + 1).ok_or("Regex ends with `\\`")?;
+    let class = |ranges: &[(char, char)]| {
+        let mut set = RangeSet::new();
+        for &(from, to) in ranges {
+            set.add_range(from, to);
+        }
+        Ok((
+            RegexEscape::Class(set, letter.is_ascii_uppercase()),
+            start + 2,
+        ))
+    };
+    let char = |ch: char| Ok((RegexEscape::Char(ch), start + 2));
+    match letter {
+        'd' | 'D' => class(&[('0', '9')]),
+        'w' | 'W' => class(&[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]),
+        's' | 'S' => class(&[('\t', '\r'), (' ', ' ')]),
+        'n' => char('\n'),
+        'r' => char('\r'),
+        't' => char('\t'),
+        'f' => char('\u{c}'),
+        'v' => char('\u{b}'),
+        '0' => char('\0'),
+        'x' | 'u' => {
+            let (digits, end) = match chars.get(start + 2) {
+                Some('{') if letter == 'u' => {
+                    let close = (start + 3..chars.len())
+                        .find(|&i| chars[i] == '}')
+                        .ok_or("Unterminated `\\u{` escape")?;
+                    (&chars[start + 3..close], close + 1)
+                }
+                _ => {
+                    let len = if letter == 'x' { 2 } else { 4 };
+                    let end = (start + 2 + len).min(chars.len());
+                    (&chars[start + 2..end], end)
+                }
             };
-            for ch in inner.chars() {
-                result.add_char(ch);
-            }
+            let digits: String = digits.iter().collect();
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .map(|ch| (RegexEscape::Char(ch), end))
+                .ok_or_else(|| format!("Invalid regex escape `\\{}{}`", letter, digits))
         }
-        // Single hex character
-        else if element.starts_with('#') {
-            if let Some(ch) = parse_hex_char(element) {
-                result.add_char(ch);
+        other if other.is_ascii_punctuation() => char(other),
+        other => Err(format!("Unsupported regex escape `\\{}`", other)),
+    }
+}
+
+/// Add the characters of one class member (range, string, hex character or category)
+/// Read the quoted string `text` starts with, where a doubled quote stands
+/// for the quote itself; returns its value and the text after it
+pub(crate) fn unquote(text: &str) -> Option<(String, &str)> {
+    let quote = text.chars().next().filter(|&q| q == '"' || q == '\'')?;
+    let mut value = String::new();
+    let mut chars = text.char_indices().skip(1).peekable();
+    while let Some((i, ch)) = chars.next() {
+        if ch != quote {
+            value.push(ch);
+        } else if chars.next_if(|&(_, next)| next == quote).is_some() {
+            value.push(quote);
+        } else {
+            return Some((value, &text[i + 1..]));
+        }
+    }
+    None
+}
+
+fn add_member(result: &mut RangeSet, element: &str) {
+    if element.is_empty() {
+        return;
+    }
+
+    // Check for hex character range: #30-#39 or #1-"÷"
+    if element.starts_with('#') && element.contains('-') {
+        if let Some(dash_pos) = element[1..].find('-') {
+            let actual_dash_pos = dash_pos + 1;
+            let start_part = &element[..actual_dash_pos];
+            let end_part = &element[actual_dash_pos + 1..];
+
+            if end_part.starts_with('#') {
+                // Hex-to-hex range: #30-#39
+                if let (Some(start), Some(end)) =
+                    (parse_hex_char(start_part), parse_hex_char(end_part))
+                {
+                    result.add_range(start, end);
+                    return;
+                }
+            } else if let Some((end_str, _)) = unquote(end_part) {
+                // Hex-to-literal range: #1-"÷"
+                if let (Some(start), Some(end)) =
+                    (parse_hex_char(start_part), end_str.chars().next())
+                {
+                    result.add_range(start, end);
+                    return;
+                }
             }
         }
-        // Single quoted string
-        else if (element.starts_with('\'') && element.ends_with('\''))
-            || (element.starts_with('"') && element.ends_with('"'))
-        {
-            // Only trim the quote character that was actually used
-            let inner = if element.starts_with('\'') {
-                element.trim_matches('\'')
-            } else {
-                element.trim_matches('"')
+        // Not a range, treat as single hex char
+        if let Some(ch) = parse_hex_char(element) {
+            result.add_char(ch);
+        }
+    }
+    // Quoted characters, or a range from a quoted character: "a"-"z", "a"-#7A
+    else if let Some((value, rest)) = unquote(element) {
+        if let Some(end_part) = rest.trim_start().strip_prefix('-') {
+            let end_part = end_part.trim_start();
+            let end_char = match unquote(end_part) {
+                Some((end_str, _)) => end_str.chars().next(),
+                None => parse_hex_char(end_part),
             };
-            for ch in inner.chars() {
-                result.add_char(ch);
+            if let (Some(start), Some(end)) = (value.chars().next(), end_char) {
+                result.add_range(start, end);
             }
+            return;
         }
-        // Unicode category - try to match category names like L, Ll, Lu, etc.
-        else if let Some(category_rangeset) = unicode_category_to_rangeset(element) {
-            result = result.union(&category_rangeset);
+        for ch in value.chars() {
+            result.add_char(ch);
         }
     }
-
-    result
+    // Single hex character
+    else if element.starts_with('#') {
+        if let Some(ch) = parse_hex_char(element) {
+            result.add_char(ch);
+        }
+    }
+    // Unicode category - try to match category names like L, Ll, Lu, etc.
+    else if let Some(category_rangeset) = unicode_category_to_rangeset(element) {
+        *result = result.union(&category_rangeset);
+    }
 }
 
 /// Parse a hexadecimal character code like #30 or #1F600
@@ -559,3 +727,96 @@ fn parse_hex_char(s: &str) -> Option<char> {
         None
     }
 }
+
+/// The character before `ch`, skipping the surrogate gap; `ch` is not `'\0'`
+fn char_before(ch: char) -> char {
+    match ch {
+        '\u{E000}' => '\u{D7FF}',
+        _ => char::from_u32(ch as u32 - 1).expect("ch is above '\\0' and not U+E000"),
+    }
+}
+
+/// The character after `ch`, skipping the surrogate gap; `ch` is not `char::MAX`
+fn char_after(ch: char) -> char {
+    match ch {
+        '\u{D7FF}' => '\u{E000}',
+        _ => char::from_u32(ch as u32 + 1).expect("ch is below char::MAX and not U+D7FF"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains_matches_ranges() {
+        let sets = [
+            RangeSet::new(),
+            RangeSet::from_range('\0', char::MAX),
+            RangeSet::from_range('\0', '\u{7f}'),
+            RangeSet::from_range('\u{7f}', '\u{80}'),
+            RangeSet::from_charclass(r#""a"-"z"; "0"-"9"; #e9; #2028-#2029"#),
+            RangeSet::from_range('A', 'Z').complement(),
+            unicode_category_to_rangeset("L").unwrap(),
+            unicode_category_to_rangeset("Nd").unwrap(),
+        ];
+        let probes = ('\0'..='\u{3ff}').chain(['\u{2027}', '\u{2028}', '\u{2029}', '\u{202a}']);
+        for set in &sets {
+            for ch in probes.clone() {
+                let expected = set
+                    .ranges()
+                    .iter()
+                    .any(|&(start, end)| start <= ch && ch <= end);
+                assert_eq!(set.contains(ch), expected, "{:?} in {}", ch, set);
+            }
+            assert_eq!(
+                set.contains(char::MAX),
+                set.ranges().last().is_some_and(|r| r.1 == char::MAX)
+            );
+        }
+    }
+
+    #[test]
+    fn test_minus_next_to_surrogates() {
+        let set = RangeSet::from_range(' ', '\u{F8FF}');
+        let kept = set.minus(&RangeSet::from_range('\u{E000}', '\u{E0FF}'));
+        assert_eq!(kept.ranges(), [(' ', '\u{D7FF}'), ('\u{E100}', '\u{F8FF}')]);
+        let class = RangeSet::from_charclass("#20-#F8FF; ~#E000-#E0FF");
+        assert!(class.contains('a') && !class.contains('\u{E000}'));
+        let kept = set.minus(&RangeSet::from_range('\u{D000}', '\u{D7FF}'));
+        assert_eq!(kept.ranges(), [(' ', '\u{CFFF}'), ('\u{E000}', '\u{F8FF}')]);
+    }
+
+    #[test]
+    fn test_complement_of_set_starting_at_e000() {
+        let private_use = RangeSet::from_range('\u{E000}', '\u{F8FF}');
+        assert_eq!(
+            private_use.complement().ranges(),
+            [('\0', '\u{D7FF}'), ('\u{F900}', char::MAX)]
+        );
+        #[cfg(not(feature = "minimal-unicode"))]
+        {
+            let other = unicode_category_to_rangeset("Co").unwrap().complement();
+            assert!(other.contains('a') && other.contains('\u{D7FF}'));
+            assert!(!other.contains('\u{E000}'));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "minimal-unicode")]
+    fn test_minimal_unicode_categories() {
+        let letters = unicode_category_to_rangeset("L").unwrap();
+        assert!(letters.contains('q') && letters.contains('Q'));
+        assert!(!letters.contains('é') && !letters.contains('1'));
+
+        let punctuation = unicode_category_to_rangeset("P").unwrap();
+        let symbols = unicode_category_to_rangeset("S").unwrap();
+        let control = unicode_category_to_rangeset("C").unwrap();
+        for ch in (' '..='~').filter(|ch| !ch.is_ascii_alphanumeric() && *ch != ' ') {
+            assert!(punctuation.contains(ch) != symbols.contains(ch), "{:?}", ch);
+        }
+        assert!(control.contains('\t') && !control.contains('\n') && !control.contains('\r'));
+        assert!(unicode_category_to_rangeset("Lo").unwrap().is_empty());
+        assert!(unicode_category_to_rangeset("Xx").is_none());
+    }
+}
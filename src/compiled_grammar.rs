@@ -0,0 +1,745 @@
+//! Grammar preprocessing shared across parses
+//!
+//! Everything the parser derives from an [`IxmlGrammar`] before reading input
+//! lives in [`CompiledGrammar`]: rule names interned to [`SymbolId`]s, every
+//! character class resolved to a [`RangeSet`], nullable and FIRST sets, and
+//! the static analysis. It is immutable once built, so one `Arc<CompiledGrammar>`
+//! can back any number of parsers on any number of threads.
+
+use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Repetition, Rule, Sequence};
+use crate::charclass::{charclass_to_rangeset, RangeSet};
+use crate::grammar_analysis::{find_undefined_references, GrammarAnalysis, UndefinedReference};
+use crate::grammar_parser::GrammarLimits;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::Arc;
+
+/// Interned rule name: an index into the grammar's rules
+pub type SymbolId = usize;
+
+/// Map keyed by the address of a node in the compiled grammar, which is
+/// fixed for as long as the grammar is
+type AddressMap<V> = HashMap<usize, V, BuildHasherDefault<AddressHasher>>;
+
+/// Hasher for addresses: they are already unique, so one multiply spreads
+/// them across the table without hashing bytes
+#[derive(Default)]
+struct AddressHasher(u64);
+
+impl Hasher for AddressHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 << 8 | u64::from(byte)).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        }
+    }
+
+    fn write_usize(&mut self, address: usize) {
+        self.0 = (address as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn address(base: &BaseFactor) -> usize {
+    base as *const BaseFactor as usize
+}
+
+/// An [`IxmlGrammar`] with its parse-independent preprocessing done once
+///
+/// ```
+/// use rustixml::{compiled_grammar::CompiledGrammar, fixtures::CSV, parse_ixml_grammar, NativeParser};
+/// use std::sync::Arc;
+///
+/// let compiled = Arc::new(CompiledGrammar::new(parse_ixml_grammar(CSV.grammar).unwrap()));
+/// let field = compiled.symbol("field").unwrap();
+/// assert!(compiled.is_nullable(field));
+///
+/// // Parsers built from the same compiled grammar share it instead of re-deriving it
+/// let threads: Vec<_> = ["a,b", "c,d"]
+///     .into_iter()
+///     .map(|input| {
+///         let compiled = Arc::clone(&compiled);
+///         std::thread::spawn(move || NativeParser::from_compiled(compiled).parse(input))
+///     })
+///     .collect();
+/// for thread in threads {
+///     assert!(thread.join().unwrap().is_ok());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CompiledGrammar {
+    grammar: IxmlGrammar,
+    /// Rule name -> index in `grammar.rules` (a later duplicate wins)
+    symbol_ids: HashMap<String, SymbolId>,
+    /// Per rule: its name, shared with parse contexts for error reports
+    rule_names: Arc<[String]>,
+    /// Rule reference in the grammar -> the rule it names, resolved once so
+    /// parsing never hashes a rule name
+    references: AddressMap<SymbolId>,
+    /// Character class content -> index in `classes`
+    class_ids: HashMap<String, usize>,
+    /// Per distinct character class: matched characters (before negation)
+    classes: Vec<RangeSet>,
+    /// Character class in the grammar -> index in `classes`, so matching a
+    /// character never reparses or hashes the class
+    class_refs: AddressMap<usize>,
+    /// Per rule: nullable and FIRST sets
+    first_sets: FirstSets,
+    /// Alternative of a rule or group in the grammar -> whether it is nullable,
+    /// and the characters that can start it
+    sequence_first: AddressMap<(bool, RangeSet)>,
+    /// Per rule: source indices of its alternatives in the order to try them,
+    /// when a profile has been applied
+    alternative_order: Vec<Option<Vec<usize>>>,
+    analysis: GrammarAnalysis,
+}
+
+impl CompiledGrammar {
+    /// Analyze and preprocess a grammar
+    pub fn new(grammar: IxmlGrammar) -> Self {
+        // Analyze grammar using iterative algorithms (no stack overflow);
+        // callers report the findings through `analysis()` if they want them
+        let analysis = GrammarAnalysis::analyze(&grammar);
+
+        let symbol_ids: HashMap<String, SymbolId> = grammar
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(id, rule)| (rule.name.clone(), id))
+            .collect();
+
+        let mut compiled = CompiledGrammar {
+            first_sets: FirstSets::default(),
+            sequence_first: AddressMap::default(),
+            alternative_order: vec![None; grammar.rules.len()],
+            rule_names: grammar.rules.iter().map(|rule| rule.name.clone()).collect(),
+            references: AddressMap::default(),
+            class_ids: HashMap::new(),
+            classes: Vec::new(),
+            class_refs: AddressMap::default(),
+            grammar,
+            symbol_ids,
+            analysis,
+        };
+        compiled.resolve_factors();
+        compiled.compute_first_sets();
+        compiled
+    }
+
+    /// Validate a grammar, then preprocess it
+    ///
+    /// [`new`](Self::new) accepts any grammar; problems it can't parse with
+    /// only show up when a parse reaches them. This reports all of them first.
+    pub fn try_new(grammar: IxmlGrammar) -> Result<Self, GrammarError> {
+        validate(&grammar)?;
+        Ok(Self::new(grammar))
+    }
+
+    /// Check a grammar against size limits, then preprocess it
+    ///
+    /// Use this for grammar ASTs that did not come through
+    /// [`parse_ixml_grammar_with_options`](crate::parse_ixml_grammar_with_options)
+    /// with the same limits.
+    pub fn with_limits(grammar: IxmlGrammar, limits: &GrammarLimits) -> Result<Self, String> {
+        limits.check(&grammar)?;
+        Ok(Self::new(grammar))
+    }
+
+    /// The source grammar
+    pub fn grammar(&self) -> &IxmlGrammar {
+        &self.grammar
+    }
+
+    /// Static analysis of the grammar
+    pub fn analysis(&self) -> &GrammarAnalysis {
+        &self.analysis
+    }
+
+    /// Number of distinct rule names
+    pub fn rule_count(&self) -> usize {
+        self.symbol_ids.len()
+    }
+
+    /// Look up the symbol for a rule name
+    pub fn symbol(&self, name: &str) -> Option<SymbolId> {
+        self.symbol_ids.get(name).copied()
+    }
+
+    /// The rule a symbol refers to
+    pub fn rule(&self, id: SymbolId) -> &Rule {
+        &self.grammar.rules[id]
+    }
+
+    /// Rule names indexed by [`SymbolId`]
+    pub fn rule_names(&self) -> &Arc<[String]> {
+        &self.rule_names
+    }
+
+    /// The rule a [`BaseFactor::Nonterminal`] refers to, if it is defined
+    ///
+    /// References in this grammar were resolved when it was compiled; others
+    /// are looked up by name.
+    ///
+    /// ```
+    /// use rustixml::ast::BaseFactor;
+    /// use rustixml::{compiled_grammar::CompiledGrammar, parse_ixml_grammar};
+    ///
+    /// let compiled = CompiledGrammar::new(parse_ixml_grammar("s: t. t: 'x'.").unwrap());
+    /// let reference = &compiled.rule(0).alternatives.alts[0].factors[0].base;
+    /// assert_eq!(compiled.reference(reference), Some(1));
+    ///
+    /// let elsewhere = BaseFactor::nonterminal("t".to_string());
+    /// assert_eq!(compiled.reference(&elsewhere), Some(1));
+    /// ```
+    pub fn reference(&self, base: &BaseFactor) -> Option<SymbolId> {
+        match (self.references.get(&address(base)), base) {
+            (Some(&id), _) => Some(id),
+            (None, BaseFactor::Nonterminal { name, .. }) => self.symbol(name),
+            (None, _) => None,
+        }
+    }
+
+    /// Characters matched by a character class (before `~` negation)
+    ///
+    /// Classes from this grammar are precompiled; others are resolved on demand.
+    pub fn charclass(&self, content: &str) -> Cow<'_, RangeSet> {
+        match self.class_ids.get(content) {
+            Some(&index) => Cow::Borrowed(&self.classes[index]),
+            None => Cow::Owned(charclass_to_rangeset(content)),
+        }
+    }
+
+    /// Characters matched by a [`BaseFactor::CharClass`] (before `~` negation)
+    ///
+    /// Like [`reference`](Self::reference), classes in this grammar were
+    /// compiled with it; others are resolved from their content. Anything but
+    /// a character class matches nothing.
+    ///
+    /// ```
+    /// use rustixml::{compiled_grammar::CompiledGrammar, parse_ixml_grammar};
+    /// use std::borrow::Cow;
+    ///
+    /// let compiled = CompiledGrammar::new(parse_ixml_grammar(r#"s: ["a"-"z"]+."#).unwrap());
+    /// let class = &compiled.rule(0).alternatives.alts[0].factors[0].base;
+    /// assert!(matches!(compiled.class(class), Cow::Borrowed(set) if set.contains('q')));
+    /// ```
+    pub fn class(&self, base: &BaseFactor) -> Cow<'_, RangeSet> {
+        match (self.class_refs.get(&address(base)), base) {
+            (Some(&index), _) => Cow::Borrowed(&self.classes[index]),
+            (None, BaseFactor::CharClass { content, .. }) => self.charclass(content),
+            (None, _) => Cow::Owned(RangeSet::new()),
+        }
+    }
+
+    /// Whether a rule can match the empty string
+    pub fn is_nullable(&self, id: SymbolId) -> bool {
+        self.first_sets.nullable[id]
+    }
+
+    /// Characters that can begin a non-empty match of a rule
+    pub fn first_set(&self, id: SymbolId) -> &RangeSet {
+        &self.first_sets.first[id]
+    }
+
+    /// Whether a rule could match at a position whose next character is `next`
+    /// (`None` at end of input)
+    pub fn can_start(&self, id: SymbolId, next: Option<char>) -> bool {
+        self.first_sets.nullable[id]
+            || next.is_some_and(|ch| self.first_sets.first[id].contains(ch))
+    }
+
+    /// Whether an alternative of a rule or group could match at a position
+    /// whose next character is `next` (`None` at end of input)
+    ///
+    /// Like [`can_start`](Self::can_start) for a rule; sequences that are not
+    /// alternatives in this grammar are assumed to match anywhere.
+    ///
+    /// ```
+    /// use rustixml::{compiled_grammar::CompiledGrammar, parse_ixml_grammar};
+    ///
+    /// let compiled = CompiledGrammar::new(parse_ixml_grammar(r#"s: "a"; "b"?."#).unwrap());
+    /// let alts = &compiled.rule(0).alternatives.alts;
+    /// assert!(!compiled.can_start_sequence(&alts[0], Some('b')));
+    /// assert!(compiled.can_start_sequence(&alts[1], None));
+    /// ```
+    pub fn can_start_sequence(&self, seq: &Sequence, next: Option<char>) -> bool {
+        match self.sequence_first.get(&(seq as *const Sequence as usize)) {
+            Some((nullable, first)) => *nullable || next.is_some_and(|ch| first.contains(ch)),
+            None => true,
+        }
+    }
+
+    /// Source indices of a rule's alternatives in the order the parser tries
+    /// them, if that differs from source order
+    pub fn alternative_order(&self, id: SymbolId) -> Option<&[usize]> {
+        self.alternative_order[id].as_deref()
+    }
+
+    /// Try a rule's alternatives in `order` (source indices, each exactly once)
+    ///
+    /// Fails if `order` is not a permutation of the rule's alternatives.
+    pub fn set_alternative_order(&mut self, id: SymbolId, order: Vec<usize>) -> Result<(), String> {
+        let rule = &self.grammar.rules[id];
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        if !sorted.iter().copied().eq(0..rule.alternatives.alts.len()) {
+            return Err(format!(
+                "Rule '{}' has {} alternatives; {:?} is not an ordering of them",
+                rule.name,
+                rule.alternatives.alts.len(),
+                order
+            ));
+        }
+        self.alternative_order[id] = Some(order);
+        Ok(())
+    }
+
+    /// Resolve every rule reference in the grammar to the rule it names, and
+    /// compile every character class once
+    fn resolve_factors(&mut self) {
+        let mut references = AddressMap::default();
+        let mut class_ids = HashMap::new();
+        let mut classes = Vec::new();
+        let mut class_refs = AddressMap::default();
+        for rule in &self.grammar.rules {
+            visit_base_factors(&rule.alternatives, &mut |base| match base {
+                BaseFactor::Nonterminal { name, .. } => {
+                    if let Some(&id) = self.symbol_ids.get(name) {
+                        references.insert(address(base), id);
+                    }
+                }
+                BaseFactor::CharClass { content, .. } => {
+                    let index = *class_ids.entry(content.clone()).or_insert_with(|| {
+                        classes.push(charclass_to_rangeset(content));
+                        classes.len() - 1
+                    });
+                    class_refs.insert(address(base), index);
+                }
+                _ => {}
+            });
+        }
+        self.references = references;
+        self.class_ids = class_ids;
+        self.classes = classes;
+        self.class_refs = class_refs;
+    }
+
+    /// Compute nullable and FIRST sets for every rule, and for every
+    /// alternative of a rule or group
+    fn compute_first_sets(&mut self) {
+        let sets = FirstSets::compute(&self.grammar, &*self);
+
+        let mut sequence_first = AddressMap::default();
+        let mut add_alternatives = |alts: &Alternatives| {
+            for seq in &alts.alts {
+                let mut first = RangeSet::new();
+                let nullable = sets.sequence_first(seq, &*self, &mut first);
+                sequence_first.insert(seq as *const Sequence as usize, (nullable, first));
+            }
+        };
+        for rule in &self.grammar.rules {
+            add_alternatives(&rule.alternatives);
+            visit_base_factors(&rule.alternatives, &mut |base| {
+                if let BaseFactor::Group { alternatives } = base {
+                    add_alternatives(alternatives);
+                }
+            });
+        }
+        self.sequence_first = sequence_first;
+        self.first_sets = sets;
+    }
+}
+
+/// How [`FirstSets`] finds the rule a reference names and the characters a
+/// class matches
+pub(crate) trait Resolve {
+    /// The rule a [`BaseFactor::Nonterminal`] refers to, if it is defined
+    fn reference(&self, base: &BaseFactor) -> Option<SymbolId>;
+    /// Characters matched by a [`BaseFactor::CharClass`] (before `~` negation)
+    fn class(&self, base: &BaseFactor) -> Cow<'_, RangeSet>;
+}
+
+impl Resolve for CompiledGrammar {
+    fn reference(&self, base: &BaseFactor) -> Option<SymbolId> {
+        CompiledGrammar::reference(self, base)
+    }
+
+    fn class(&self, base: &BaseFactor) -> Cow<'_, RangeSet> {
+        CompiledGrammar::class(self, base)
+    }
+}
+
+/// Resolves references by rule name and classes from their content, for
+/// grammars that have not been compiled
+pub(crate) struct RuleNames<'g>(HashMap<&'g str, SymbolId>);
+
+impl<'g> RuleNames<'g> {
+    pub(crate) fn new(grammar: &'g IxmlGrammar) -> Self {
+        RuleNames(
+            grammar
+                .rules
+                .iter()
+                .enumerate()
+                .map(|(id, rule)| (rule.name.as_str(), id))
+                .collect(),
+        )
+    }
+}
+
+impl Resolve for RuleNames<'_> {
+    fn reference(&self, base: &BaseFactor) -> Option<SymbolId> {
+        match base {
+            BaseFactor::Nonterminal { name, .. } => self.0.get(name.as_str()).copied(),
+            _ => None,
+        }
+    }
+
+    fn class(&self, base: &BaseFactor) -> Cow<'_, RangeSet> {
+        match base {
+            BaseFactor::CharClass { content, .. } => Cow::Owned(charclass_to_rangeset(content)),
+            _ => Cow::Owned(RangeSet::new()),
+        }
+    }
+}
+
+/// Nullable and FIRST sets of every rule, indexed by [`SymbolId`]
+///
+/// Both are over-approximations (insertions and lookaheads count as
+/// nullable), so a rule whose FIRST set excludes the next character and
+/// that is not nullable can never match there.
+#[derive(Debug, Default)]
+pub(crate) struct FirstSets {
+    /// Per rule: can it match the empty string?
+    pub(crate) nullable: Vec<bool>,
+    /// Per rule: characters that can start a non-empty match
+    pub(crate) first: Vec<RangeSet>,
+}
+
+impl FirstSets {
+    /// Compute the sets for every rule of `grammar` by fixpoint iteration
+    pub(crate) fn compute(grammar: &IxmlGrammar, resolve: &impl Resolve) -> Self {
+        let mut sets = FirstSets {
+            nullable: vec![false; grammar.rules.len()],
+            first: vec![RangeSet::new(); grammar.rules.len()],
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (id, rule) in grammar.rules.iter().enumerate() {
+                let mut first = RangeSet::new();
+                let nullable = sets.alternatives_first(&rule.alternatives, resolve, &mut first);
+                if nullable != sets.nullable[id] || first != sets.first[id] {
+                    sets.nullable[id] = nullable;
+                    sets.first[id] = first;
+                    changed = true;
+                }
+            }
+        }
+        sets
+    }
+
+    /// Add the FIRST set of a choice to `first`; returns whether it is nullable
+    pub(crate) fn alternatives_first(
+        &self,
+        alts: &Alternatives,
+        resolve: &impl Resolve,
+        first: &mut RangeSet,
+    ) -> bool {
+        let mut nullable = false;
+        for seq in &alts.alts {
+            nullable |= self.sequence_first(seq, resolve, first);
+        }
+        nullable
+    }
+
+    /// Add the FIRST set of a sequence to `first`; returns whether it is nullable
+    pub(crate) fn sequence_first(
+        &self,
+        seq: &Sequence,
+        resolve: &impl Resolve,
+        first: &mut RangeSet,
+    ) -> bool {
+        for factor in &seq.factors {
+            if !self.factor_first(factor, resolve, first) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn factor_first(&self, factor: &Factor, resolve: &impl Resolve, first: &mut RangeSet) -> bool {
+        let base_nullable = self.base_first(&factor.base, resolve, first);
+        match &factor.repetition {
+            Repetition::None | Repetition::OneOrMore => base_nullable,
+            Repetition::Optional | Repetition::ZeroOrMore => true,
+            Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) => {
+                // An empty element can be followed directly by a separator
+                if base_nullable {
+                    self.sequence_first(sep, resolve, first);
+                }
+                base_nullable || matches!(factor.repetition, Repetition::SeparatedZeroOrMore(_))
+            }
+        }
+    }
+
+    /// Add the FIRST set of a base factor to `first`; returns whether it is nullable
+    pub(crate) fn base_first(
+        &self,
+        base: &BaseFactor,
+        resolve: &impl Resolve,
+        first: &mut RangeSet,
+    ) -> bool {
+        match base {
+            BaseFactor::Literal {
+                value, insertion, ..
+            } => match value.chars().next() {
+                Some(ch) if !insertion => {
+                    first.add_char(ch);
+                    false
+                }
+                _ => true,
+            },
+            BaseFactor::CharClass { negated, .. } => {
+                let class = resolve.class(base);
+                *first = if *negated {
+                    first.union(&RangeSet::from_range('\0', char::MAX).minus(&class))
+                } else {
+                    first.union(&class)
+                };
+                false
+            }
+            BaseFactor::Nonterminal { .. } => match resolve.reference(base) {
+                Some(id) => {
+                    *first = first.union(&self.first[id]);
+                    self.nullable[id]
+                }
+                // Undefined rules never match
+                None => false,
+            },
+            BaseFactor::Group { alternatives } => {
+                self.alternatives_first(alternatives, resolve, first)
+            }
+            BaseFactor::NegativeLookahead { .. } => true,
+        }
+    }
+}
+
+/// Call `f` with every base factor in `alts`, including those nested in
+/// groups, lookaheads and separators
+fn visit_base_factors<'g>(alts: &'g Alternatives, f: &mut impl FnMut(&'g BaseFactor)) {
+    for seq in &alts.alts {
+        visit_sequence_base_factors(seq, f);
+    }
+}
+
+fn visit_sequence_base_factors<'g>(seq: &'g Sequence, f: &mut impl FnMut(&'g BaseFactor)) {
+    for factor in &seq.factors {
+        visit_base_factor(&factor.base, f);
+        if let Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) =
+            &factor.repetition
+        {
+            visit_sequence_base_factors(sep, f);
+        }
+    }
+}
+
+fn visit_base_factor<'g>(base: &'g BaseFactor, f: &mut impl FnMut(&'g BaseFactor)) {
+    f(base);
+    match base {
+        BaseFactor::Group { alternatives } => visit_base_factors(alternatives, f),
+        BaseFactor::NegativeLookahead { base } => visit_base_factor(base, f),
+        BaseFactor::Literal { .. }
+        | BaseFactor::CharClass { .. }
+        | BaseFactor::Nonterminal { .. } => {}
+    }
+}
+
+/// A reason a grammar can't be parsed with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrammarProblem {
+    /// The grammar has no rules
+    NoRules,
+    /// More than one rule has this name
+    DuplicateRule { name: String },
+    /// A rule refers to a rule that is not defined
+    UndefinedRule(UndefinedReference),
+}
+
+impl fmt::Display for GrammarProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrammarProblem::NoRules => write!(f, "grammar has no rules"),
+            GrammarProblem::DuplicateRule { name } => {
+                write!(f, "rule '{}' is defined more than once", name)
+            }
+            GrammarProblem::UndefinedRule(reference) => write!(f, "{}", reference),
+        }
+    }
+}
+
+/// Every problem found validating a grammar, in source order
+///
+/// ```
+/// use rustixml::compiled_grammar::GrammarProblem;
+/// use rustixml::{parse_ixml_grammar, NativeParser};
+///
+/// let grammar = parse_ixml_grammar(r#"word: lettter+. letter: ["a"-"z"]. letter: "-"."#).unwrap();
+/// let err = NativeParser::try_new(grammar).err().unwrap();
+/// assert_eq!(err.problems.len(), 2);
+/// assert!(matches!(&err.problems[0], GrammarProblem::DuplicateRule { name } if name == "letter"));
+/// assert_eq!(
+///     err.to_string(),
+///     "Invalid grammar:\n  - rule 'letter' is defined more than once\n  \
+///      - word: undefined rule 'lettter' (did you mean 'letter'?)"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrammarError {
+    pub problems: Vec<GrammarProblem>,
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.problems.as_slice() {
+            [problem] => write!(f, "Invalid grammar: {}", problem),
+            problems => {
+                write!(f, "Invalid grammar:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for GrammarError {}
+
+/// Find every [`GrammarProblem`] in a grammar
+pub fn validate(grammar: &IxmlGrammar) -> Result<(), GrammarError> {
+    let mut problems = Vec::new();
+    if grammar.rules.is_empty() {
+        problems.push(GrammarProblem::NoRules);
+    }
+
+    let mut seen = HashSet::new();
+    let mut reported = HashSet::new();
+    for rule in &grammar.rules {
+        if !seen.insert(rule.name.as_str()) && reported.insert(rule.name.as_str()) {
+            problems.push(GrammarProblem::DuplicateRule {
+                name: rule.name.clone(),
+            });
+        }
+    }
+
+    problems.extend(
+        find_undefined_references(grammar)
+            .into_iter()
+            .map(GrammarProblem::UndefinedRule),
+    );
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(GrammarError { problems })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar_ast::parse_ixml_grammar;
+
+    #[test]
+    fn test_nullable_and_first_sets() {
+        let grammar = parse_ixml_grammar(
+            r#"
+            list: item**",", -".".
+            item: sign?, ["0"-"9"]+; +"none".
+            sign: "+"; "-".
+            word: ~["0"-"9"], word?.
+            "#,
+        )
+        .unwrap();
+        let compiled = CompiledGrammar::new(grammar);
+        let id = |name| compiled.symbol(name).unwrap();
+
+        assert!(compiled.is_nullable(id("item")));
+        assert!(!compiled.is_nullable(id("sign")));
+        assert!(!compiled.is_nullable(id("list")));
+
+        // item is nullable, so a separator or the final "." can start a list
+        let list = compiled.first_set(id("list"));
+        for ch in ['+', '-', '7', ',', '.'] {
+            assert!(list.contains(ch), "{ch:?} should start a list");
+        }
+        assert!(!list.contains('a'));
+
+        assert!(compiled.can_start(id("word"), Some('a')));
+        assert!(!compiled.can_start(id("word"), Some('5')));
+        assert!(!compiled.can_start(id("sign"), None));
+    }
+
+    #[test]
+    fn test_symbols_and_charclasses() {
+        let grammar = parse_ixml_grammar(r#"a: b, ["a"-"z"]. b: ["x"; "y"]. b: "z"."#).unwrap();
+        let compiled = CompiledGrammar::new(grammar);
+
+        assert_eq!(compiled.rule_count(), 2);
+        // A later definition of a duplicated rule name wins
+        assert_eq!(compiled.symbol("b"), Some(2));
+        assert_eq!(compiled.rule(0).name, "a");
+        assert!(compiled.symbol("c").is_none());
+
+        assert!(matches!(compiled.charclass(r#""a"-"z""#), Cow::Borrowed(_)));
+        assert!(matches!(compiled.charclass(r#""0"-"9""#), Cow::Owned(_)));
+        assert!(compiled.charclass(r#""x"; "y""#).contains('y'));
+
+        // Each distinct class is compiled once, however often it appears
+        let grammar = parse_ixml_grammar(r#"s: ["a"-"z"], ~["a"-"z"], t. t: ["a"-"z"]."#).unwrap();
+        let compiled = CompiledGrammar::new(grammar);
+        assert_eq!(compiled.classes.len(), 1);
+        let factors = &compiled.rule(0).alternatives.alts[0].factors;
+        assert!(
+            matches!(compiled.class(&factors[1].base), Cow::Borrowed(set) if set.contains('a'))
+        );
+        assert!(compiled.class(&factors[2].base).is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(
+            validate(&IxmlGrammar::new(vec![])).unwrap_err().problems,
+            [GrammarProblem::NoRules]
+        );
+
+        // A name defined three times is one problem; every undefined reference is listed
+        let grammar = parse_ixml_grammar(r#"s: a, b, c. a: "x". a: "y". a: "z". s: b."#).unwrap();
+        let problems = validate(&grammar).unwrap_err().problems;
+        let names: Vec<_> = problems
+            .iter()
+            .map(|problem| match problem {
+                GrammarProblem::DuplicateRule { name } => format!("duplicate {}", name),
+                GrammarProblem::UndefinedRule(r) => format!("undefined {} in {}", r.name, r.rule),
+                GrammarProblem::NoRules => "no rules".to_string(),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "duplicate a",
+                "duplicate s",
+                "undefined b in s",
+                "undefined c in s",
+                "undefined b in s"
+            ]
+        );
+
+        let grammar = parse_ixml_grammar(r#"s: a. a: "x"."#).unwrap();
+        assert!(CompiledGrammar::try_new(grammar).is_ok());
+    }
+}
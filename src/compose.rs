@@ -0,0 +1,346 @@
+//! Grammars split over several sources
+//!
+//! A [`GrammarSet`] merges grammars into one, checking that rules defined in
+//! more than one of them agree, and renaming them when asked to. The start
+//! rule stays that of the first grammar; the others add rules it can use, and
+//! may themselves use any rule in the set.
+//!
+//! [`parse_ixml_grammar_with_imports`] does the same for grammars that name
+//! their parts with `{[include "name"]}` pragmas before a rule, loading each
+//! part through a resolver such as [`file_resolver`].
+
+use crate::ast::{Alternatives, BaseFactor, IxmlGrammar, Repetition, Rule, Sequence};
+use crate::grammar_parser::parse_ixml_grammar;
+use crate::xml_node::parse_quoted;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Pragma naming a grammar to include: `{[include "numbers.ixml"]}`
+pub const INCLUDE_PRAGMA: &str = "include";
+
+/// What [`GrammarSet::merge`] does with a rule the set already defines
+/// differently (rules defined the same way in both are merged silently)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Fail with an error naming both sources
+    Fail,
+    /// Drop the incoming rule, so its grammar uses the existing one
+    KeepExisting,
+    /// Rename the incoming rule to `source.rule`, along with the incoming
+    /// grammar's references to it
+    Rename,
+}
+
+/// Grammars merged into one
+///
+/// ```
+/// use rustixml::compose::{GrammarSet, OnConflict};
+/// use rustixml::{parse_ixml_grammar, NativeParser};
+///
+/// let main = parse_ixml_grammar(r#"time: hour, ":", minute. hour: digit, digit."#).unwrap();
+/// let minutes = parse_ixml_grammar(r#"minute: ["0"-"5"], digit. digit: ["0"-"9"]."#).unwrap();
+///
+/// let mut set = GrammarSet::new("main", main);
+/// set.merge("minutes", minutes, OnConflict::Fail).unwrap();
+/// assert_eq!(set.source_of("digit"), Some("minutes"));
+///
+/// let parser = NativeParser::new(set.into_grammar());
+/// assert!(parser.parse("12:34").unwrap().starts_with("<time><hour>"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct GrammarSet {
+    grammar: IxmlGrammar,
+    /// Rule name -> name of the grammar it came from
+    sources: HashMap<String, String>,
+}
+
+impl GrammarSet {
+    /// A set holding `grammar`, whose first rule is the start rule
+    pub fn new(name: &str, grammar: IxmlGrammar) -> Self {
+        let sources = grammar
+            .rules
+            .iter()
+            .map(|rule| (rule.name.clone(), name.to_string()))
+            .collect();
+        GrammarSet { grammar, sources }
+    }
+
+    /// Add the rules of `grammar`, called `name` in errors and renamed rules
+    ///
+    /// Nothing is added when an error is returned.
+    pub fn merge(
+        &mut self,
+        name: &str,
+        mut grammar: IxmlGrammar,
+        on_conflict: OnConflict,
+    ) -> Result<(), String> {
+        let mut renames: HashMap<String, String> = HashMap::new();
+        let mut dropped: HashSet<String> = HashSet::new();
+        for rule in &grammar.rules {
+            let Some(existing) = self.rule(&rule.name) else {
+                continue;
+            };
+            if same_definition(existing, rule) {
+                dropped.insert(rule.name.clone());
+                continue;
+            }
+            match on_conflict {
+                OnConflict::Fail => {
+                    return Err(format!(
+                        "Rule '{}' from '{}' conflicts with the one from '{}'",
+                        rule.name, name, self.sources[&rule.name]
+                    ))
+                }
+                OnConflict::KeepExisting => {
+                    dropped.insert(rule.name.clone());
+                }
+                OnConflict::Rename => {
+                    let renamed = format!("{}.{}", name, rule.name);
+                    if self.rule(&renamed).is_some() {
+                        return Err(format!(
+                            "Cannot rename rule '{}' from '{}': '{}' is already defined",
+                            rule.name, name, renamed
+                        ));
+                    }
+                    renames.insert(rule.name.clone(), renamed);
+                }
+            }
+        }
+
+        grammar.rules.retain(|rule| !dropped.contains(&rule.name));
+        for rule in &mut grammar.rules {
+            if let Some(renamed) = renames.get(&rule.name) {
+                rule.name = renamed.clone();
+            }
+            rename_in_alternatives(&mut rule.alternatives, &renames);
+        }
+        for rule in grammar.rules {
+            self.sources.insert(rule.name.clone(), name.to_string());
+            self.grammar.rules.push(rule);
+        }
+        Ok(())
+    }
+
+    /// The rule called `name`, if the set has one
+    pub fn rule(&self, name: &str) -> Option<&Rule> {
+        self.grammar.rules.iter().find(|rule| rule.name == name)
+    }
+
+    /// The name of the grammar a rule came from
+    pub fn source_of(&self, rule: &str) -> Option<&str> {
+        self.sources.get(rule).map(String::as_str)
+    }
+
+    /// The merged grammar
+    pub fn grammar(&self) -> &IxmlGrammar {
+        &self.grammar
+    }
+
+    pub fn into_grammar(self) -> IxmlGrammar {
+        self.grammar
+    }
+}
+
+/// Whether two rules define the same thing, pragmas aside
+fn same_definition(a: &Rule, b: &Rule) -> bool {
+    a.mark == b.mark && a.alternatives == b.alternatives
+}
+
+fn rename_in_alternatives(alts: &mut Alternatives, renames: &HashMap<String, String>) {
+    if renames.is_empty() {
+        return;
+    }
+    for seq in &mut alts.alts {
+        rename_in_sequence(seq, renames);
+    }
+}
+
+fn rename_in_sequence(seq: &mut Sequence, renames: &HashMap<String, String>) {
+    for factor in &mut seq.factors {
+        rename_in_base(&mut factor.base, renames);
+        if let Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) =
+            &mut factor.repetition
+        {
+            rename_in_sequence(sep, renames);
+        }
+    }
+}
+
+fn rename_in_base(base: &mut BaseFactor, renames: &HashMap<String, String>) {
+    match base {
+        BaseFactor::Nonterminal { name, .. } => {
+            if let Some(renamed) = renames.get(name) {
+                *name = renamed.clone();
+            }
+        }
+        BaseFactor::Group { alternatives } => rename_in_alternatives(alternatives, renames),
+        BaseFactor::NegativeLookahead { base } => rename_in_base(base, renames),
+        BaseFactor::Literal { .. } | BaseFactor::CharClass { .. } => {}
+    }
+}
+
+/// The names in a grammar's `include` pragmas, in source order
+fn includes(grammar: &IxmlGrammar) -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+    for rule in &grammar.rules {
+        for pragma in rule.pragmas.iter().filter(|p| p.name == INCLUDE_PRAGMA) {
+            match parse_quoted(&pragma.data) {
+                Some(name) => names.push(name.to_string()),
+                None => {
+                    return Err(format!(
+                        "Include pragma needs a quoted name: {{[include {}]}}",
+                        pragma.data
+                    ))
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Parse a grammar together with the grammars its `include` pragmas name
+///
+/// `resolve` returns the source of the grammar a pragma names. Each grammar
+/// is included once however often it is named, includes may name further
+/// includes, and rules defined differently in two grammars are an error.
+///
+/// ```
+/// use rustixml::{parse_ixml_grammar_with_imports, NativeParser};
+///
+/// let main = r#"{[include "digits"]} number: digit+, (".", digit+)?."#;
+/// let grammar = parse_ixml_grammar_with_imports(main, |name| match name {
+///     "digits" => Ok(r#"-digit: ["0"-"9"]."#.to_string()),
+///     other => Err(format!("no grammar called {}", other)),
+/// })
+/// .unwrap();
+/// assert_eq!(NativeParser::new(grammar).parse("1.5").unwrap(), "<number>1.5</number>");
+/// ```
+pub fn parse_ixml_grammar_with_imports(
+    source: &str,
+    mut resolve: impl FnMut(&str) -> Result<String, String>,
+) -> Result<IxmlGrammar, String> {
+    let grammar = parse_ixml_grammar(source)?;
+    let mut pending = includes(&grammar)?;
+    pending.reverse();
+    let mut set = GrammarSet::new("main", grammar);
+    let mut seen = HashSet::new();
+
+    while let Some(name) = pending.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let source = resolve(&name).map_err(|e| format!("Cannot include '{}': {}", name, e))?;
+        let grammar = parse_ixml_grammar(&source).map_err(|e| format!("In '{}': {}", name, e))?;
+        pending.extend(includes(&grammar)?.into_iter().rev());
+        set.merge(&name, grammar, OnConflict::Fail)?;
+    }
+    Ok(set.into_grammar())
+}
+
+/// A resolver for [`parse_ixml_grammar_with_imports`] that reads included
+/// grammars from files, relative to `dir`
+pub fn file_resolver(dir: impl AsRef<Path>) -> impl FnMut(&str) -> Result<String, String> {
+    let dir: PathBuf = dir.as_ref().to_path_buf();
+    move |name| std::fs::read_to_string(dir.join(name)).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grammar(source: &str) -> IxmlGrammar {
+        parse_ixml_grammar(source).unwrap()
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let main = grammar(r#"s: a, b. a: "a". b: "b"."#);
+        let other = grammar(r#"b: "B", a. a: "a". c: b."#);
+
+        let mut set = GrammarSet::new("main", main.clone());
+        assert_eq!(
+            set.merge("other", other.clone(), OnConflict::Fail),
+            Err("Rule 'b' from 'other' conflicts with the one from 'main'".to_string())
+        );
+        assert_eq!(set.grammar(), &main);
+
+        set.merge("other", other.clone(), OnConflict::KeepExisting)
+            .unwrap();
+        assert_eq!(
+            set.grammar().to_string(),
+            "s: a, b.\na: \"a\".\nb: \"b\".\nc: b.\n"
+        );
+
+        let mut set = GrammarSet::new("main", main);
+        set.merge("other", other.clone(), OnConflict::Rename)
+            .unwrap();
+        assert_eq!(
+            set.grammar().to_string(),
+            "s: a, b.\na: \"a\".\nb: \"b\".\nother.b: \"B\", a.\nc: other.b.\n"
+        );
+        assert_eq!(set.source_of("other.b"), Some("other"));
+        assert_eq!(set.source_of("a"), Some("main"));
+        assert!(set
+            .merge("other", other, OnConflict::Rename)
+            .unwrap_err()
+            .contains("'other.b' is already defined"));
+    }
+
+    #[test]
+    fn test_includes() {
+        let sources: HashMap<&str, &str> = HashMap::from([
+            ("a", r#"{[include "b"]} a: "a", b."#),
+            ("b", r#"{[include "a"]} {[include 'c']} b: c. c: "c"."#),
+            ("c", r#"c: "c"."#),
+            ("clash", r#"a: "x"."#),
+            ("bad", r#"x: "x"#),
+        ]);
+        let resolve = |name: &str| {
+            sources
+                .get(name)
+                .map(|s| s.to_string())
+                .ok_or_else(|| "not found".to_string())
+        };
+
+        let grammar = parse_ixml_grammar_with_imports(r#"{[include "a"]} s: a."#, resolve).unwrap();
+        let names: Vec<&str> = grammar.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["s", "a", "b", "c"]);
+
+        let error = |source: &str| parse_ixml_grammar_with_imports(source, resolve).unwrap_err();
+        assert_eq!(
+            error(r#"{[include "d"]} s: "s"."#),
+            "Cannot include 'd': not found"
+        );
+        assert_eq!(
+            error(r#"{[include "a"]} {[include "clash"]} s: a."#),
+            "Rule 'a' from 'clash' conflicts with the one from 'a'"
+        );
+        assert!(error(r#"{[include "bad"]} s: "s"."#).starts_with("In 'bad': "));
+        assert_eq!(
+            error(r#"{[include bad]} s: "s"."#),
+            "Include pragma needs a quoted name: {[include bad]}"
+        );
+    }
+
+    #[test]
+    fn test_file_resolver() {
+        let dir = std::env::temp_dir().join(format!("rustixml-compose-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("digits.ixml"), r#"digit: ["0"-"9"]."#).unwrap();
+
+        let grammar = parse_ixml_grammar_with_imports(
+            r#"{[include "digits.ixml"]} n: digit+."#,
+            file_resolver(&dir),
+        );
+        let missing = parse_ixml_grammar_with_imports(
+            r#"{[include "nope.ixml"]} n: "n"."#,
+            file_resolver(&dir),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(grammar.unwrap().rules.len(), 2);
+        assert!(missing
+            .unwrap_err()
+            .starts_with("Cannot include 'nope.ixml': "));
+    }
+}
@@ -0,0 +1,576 @@
+//! Running the iXML community test suite
+//!
+//! The suite describes its tests in catalog files: test sets name a grammar
+//! (iXML or its XML form, inline or by reference), and each test case gives
+//! an input and the result expected from it. [`load_catalog`] reads a
+//! catalog, following `test-set-ref`s to the catalogs it includes, and
+//! [`ConformanceReport::run`] runs the cases it found. Result documents are
+//! compared as trees with [`reference::diff`](crate::reference::diff), so
+//! indentation and namespace declarations do not count.
+//!
+//! `rustixml conformance [CATALOG]` does both and prints the report.
+//!
+//! The same machinery runs a grammar's own tests: [`load_grammar_tests`]
+//! reads input and expected-output pairs kept next to the grammar, for
+//! `rustixml test GRAMMAR`.
+
+use crate::grammar_parser::parse_ixml_grammar;
+use crate::native_parser::NativeParser;
+use crate::reference::diff;
+use crate::xml_node::XmlNode;
+use crate::IxmlGrammar;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the suite keeps its top-level catalog in this repository
+pub const DEFAULT_CATALOG: &str = "ixml_tests/test-catalog.xml";
+
+/// One test from a catalog
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConformanceCase {
+    /// The test set's name, then the test case's, e.g. `chars.char1a/char1a`
+    pub name: String,
+    pub grammar: GrammarSource,
+    /// The input to parse; `None` for tests of the grammar alone
+    pub input: Option<String>,
+    pub expected: Expected,
+}
+
+/// A test set's grammar
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrammarSource {
+    Ixml(String),
+    /// The XML form of a grammar
+    Vxml(XmlNode),
+    /// The test set names no grammar, or one that could not be read
+    Missing(String),
+}
+
+/// What a test expects
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    /// One of these documents; for tests of the grammar alone, its XML form
+    Xml(Vec<XmlNode>),
+    /// The input does not match the grammar
+    NotASentence,
+    /// The grammar is not a valid grammar
+    NotAGrammar,
+    /// Processing fails for another reason
+    DynamicError,
+    /// The catalog states no result rustixml understands
+    Unknown,
+}
+
+/// How a test went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaseOutcome {
+    Pass,
+    Fail(String),
+    /// The test could not be run, and why
+    Skip(String),
+}
+
+impl ConformanceCase {
+    /// Run the test with the native parser
+    pub fn run(&self) -> CaseOutcome {
+        let grammar = match &self.grammar {
+            GrammarSource::Ixml(source) => parse_ixml_grammar(source),
+            GrammarSource::Vxml(node) => IxmlGrammar::from_vxml(node),
+            GrammarSource::Missing(why) => return CaseOutcome::Skip(why.clone()),
+        };
+        let grammar = match (grammar, &self.expected) {
+            (Err(_), Expected::NotAGrammar | Expected::DynamicError) => return CaseOutcome::Pass,
+            (Err(e), _) => return CaseOutcome::Fail(format!("Grammar error: {}", e)),
+            (Ok(_), Expected::NotAGrammar) => {
+                return CaseOutcome::Fail("Grammar accepted, expected an error".to_string())
+            }
+            (Ok(grammar), _) => grammar,
+        };
+
+        let Some(input) = &self.input else {
+            return match &self.expected {
+                Expected::Xml(documents) => matches(documents, &grammar.to_vxml()),
+                Expected::Unknown => CaseOutcome::Skip("No expected result".to_string()),
+                _ => CaseOutcome::Pass,
+            };
+        };
+
+        let parser = NativeParser::new(grammar);
+        let xml = parser
+            .parse_outcome(input)
+            .to_xml_with(parser.fixed_attributes());
+        let document = match XmlNode::from_xml(&xml) {
+            Ok(document) => document,
+            Err(e) => return CaseOutcome::Fail(format!("Output is not XML: {}", e)),
+        };
+        let failed = document
+            .ixml_state()
+            .is_some_and(|state| state.split_whitespace().any(|s| s == "failed"));
+        match (&self.expected, failed) {
+            (Expected::NotASentence | Expected::DynamicError, true) => CaseOutcome::Pass,
+            (Expected::NotASentence | Expected::DynamicError, false) => {
+                CaseOutcome::Fail("Input parsed, expected it to fail".to_string())
+            }
+            (Expected::Xml(_), true) => CaseOutcome::Fail(format!("Parse failed: {}", xml)),
+            (Expected::Xml(documents), false) => matches(documents, &document),
+            (Expected::Unknown, _) => CaseOutcome::Skip("No expected result".to_string()),
+            (Expected::NotAGrammar, _) => unreachable!("handled with the grammar"),
+        }
+    }
+}
+
+/// Pass if `ours` is one of `documents`, or fail with how it differs from
+/// the first
+fn matches(documents: &[XmlNode], ours: &XmlNode) -> CaseOutcome {
+    let mut first_differences = None;
+    for expected in documents {
+        let differences = diff(expected, ours);
+        if differences.is_empty() {
+            return CaseOutcome::Pass;
+        }
+        first_differences.get_or_insert(differences);
+    }
+    let differences: Vec<String> = first_differences
+        .unwrap_or_default()
+        .iter()
+        .map(|d| d.to_string())
+        .collect();
+    CaseOutcome::Fail(differences.join("; "))
+}
+
+/// Read the test cases of a catalog file and the catalogs it refers to
+pub fn load_catalog(path: impl AsRef<Path>) -> Result<Vec<ConformanceCase>, String> {
+    let path = path.as_ref();
+    let xml = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read catalog {}: {}", path.display(), e))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    parse_catalog(&xml, base).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Read the test cases of catalog text, resolving references against `base`
+///
+/// ```
+/// use rustixml::conformance::{parse_catalog, CaseOutcome, ConformanceReport};
+/// use std::path::Path;
+///
+/// let catalog = r#"<test-catalog xmlns="https://github.com/invisibleXML/ixml/test-catalog">
+///   <test-set name="digits">
+///     <ixml-grammar>number: ["0"-"9"]+.</ixml-grammar>
+///     <test-case name="ok">
+///       <test-string>42</test-string>
+///       <result><assert-xml><number xmlns="">42</number></assert-xml></result>
+///     </test-case>
+///     <test-case name="letters">
+///       <test-string>x</test-string>
+///       <result><assert-not-a-sentence/></result>
+///     </test-case>
+///   </test-set>
+/// </test-catalog>"#;
+/// let cases = parse_catalog(catalog, Path::new(".")).unwrap();
+/// assert_eq!(cases[1].name, "digits/letters");
+/// let report = ConformanceReport::run(&cases);
+/// assert_eq!(report.passed(), 2);
+/// ```
+pub fn parse_catalog(xml: &str, base: &Path) -> Result<Vec<ConformanceCase>, String> {
+    let catalog = XmlNode::from_xml(xml)?;
+    if local_name(&catalog) != "test-catalog" {
+        return Err(format!(
+            "Expected <test-catalog>, found <{}>",
+            local_name(&catalog)
+        ));
+    }
+    let mut cases = Vec::new();
+    read_sets(&catalog, base, &mut cases)?;
+    Ok(cases)
+}
+
+/// The test sets among a catalog's children
+fn read_sets(
+    catalog: &XmlNode,
+    base: &Path,
+    cases: &mut Vec<ConformanceCase>,
+) -> Result<(), String> {
+    for child in catalog.children() {
+        match local_name(child) {
+            "test-set-ref" => {
+                let path = href(child, base)?;
+                cases.extend(load_catalog(&path)?);
+            }
+            "test-set" => read_set(child, base, None, cases)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A test set's cases; nested sets without a grammar of their own use
+/// `inherited`
+fn read_set(
+    set: &XmlNode,
+    base: &Path,
+    inherited: Option<&GrammarSource>,
+    cases: &mut Vec<ConformanceCase>,
+) -> Result<(), String> {
+    let name = set.attribute("name").unwrap_or("unnamed");
+    let grammar = set_grammar(set, base).or_else(|| inherited.cloned());
+    let grammar = grammar
+        .unwrap_or_else(|| GrammarSource::Missing(format!("Test set {} has no grammar", name)));
+
+    for child in set.children() {
+        match local_name(child) {
+            "grammar-test" => cases.push(ConformanceCase {
+                name: name.to_string(),
+                grammar: grammar.clone(),
+                input: None,
+                expected: expected(child, base)?,
+            }),
+            "test-case" => {
+                let case = child.attribute("name").unwrap_or("unnamed");
+                let input = match element(child, "test-string") {
+                    Some(string) => string.text_content(),
+                    None => {
+                        let reference = element(child, "test-string-ref")
+                            .ok_or_else(|| format!("Test case {} has no test string", case))?;
+                        read(&href(reference, base)?)?
+                    }
+                };
+                cases.push(ConformanceCase {
+                    name: format!("{}/{}", name, case),
+                    grammar: grammar.clone(),
+                    input: Some(input),
+                    expected: expected(child, base)?,
+                });
+            }
+            "test-set" => read_set(child, base, Some(&grammar), cases)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The grammar a test set names, if any
+fn set_grammar(set: &XmlNode, base: &Path) -> Option<GrammarSource> {
+    let loaded =
+        |result: Result<GrammarSource, String>| Some(result.unwrap_or_else(GrammarSource::Missing));
+    for child in set.children() {
+        match local_name(child) {
+            "ixml-grammar" => return Some(GrammarSource::Ixml(child.text_content())),
+            "ixml-grammar-ref" => {
+                return loaded(
+                    href(child, base)
+                        .and_then(|path| read(&path))
+                        .map(GrammarSource::Ixml),
+                )
+            }
+            "vxml-grammar" => {
+                let root = child.children().iter().find(|c| c.name().is_some());
+                return loaded(
+                    root.cloned()
+                        .map(GrammarSource::Vxml)
+                        .ok_or_else(|| "Empty <vxml-grammar>".to_string()),
+                );
+            }
+            "vxml-grammar-ref" => {
+                return loaded(
+                    href(child, base)
+                        .and_then(|path| read(&path))
+                        .and_then(|xml| XmlNode::from_xml(&xml))
+                        .map(GrammarSource::Vxml),
+                )
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The result a test case or grammar test expects
+///
+/// Assertions usually sit in a `<result>`, but some catalogs put them
+/// straight in the test.
+fn expected(test: &XmlNode, base: &Path) -> Result<Expected, String> {
+    let holder = element(test, "result").unwrap_or(test);
+    let mut documents = Vec::new();
+    for assertion in holder.children() {
+        match local_name(assertion) {
+            "assert-xml" => {
+                if let Some(root) = assertion.children().iter().find(|c| c.name().is_some()) {
+                    documents.push(root.clone());
+                }
+            }
+            "assert-xml-ref" => documents.push(XmlNode::from_xml(&read(&href(assertion, base)?)?)?),
+            "assert-not-a-sentence" => return Ok(Expected::NotASentence),
+            "assert-not-a-grammar" => return Ok(Expected::NotAGrammar),
+            "assert-dynamic-error" => return Ok(Expected::DynamicError),
+            _ => {}
+        }
+    }
+    Ok(if documents.is_empty() {
+        Expected::Unknown
+    } else {
+        Expected::Xml(documents)
+    })
+}
+
+/// An element's name without any namespace prefix
+fn local_name(node: &XmlNode) -> &str {
+    let name = node.name().unwrap_or("");
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn element<'a>(parent: &'a XmlNode, name: &str) -> Option<&'a XmlNode> {
+    parent.children().iter().find(|c| local_name(c) == name)
+}
+
+fn href(node: &XmlNode, base: &Path) -> Result<PathBuf, String> {
+    node.attribute("href")
+        .map(|href| base.join(href))
+        .ok_or_else(|| format!("<{}> has no href", local_name(node)))
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+/// Read the tests kept with a grammar file: for `date.ixml`, the files in
+/// `date.tests/`
+///
+/// Each input file `NAME.txt` (any name not ending in `.xml`) is a case,
+/// expected to give the document in `NAME.xml`, or to fail to parse when
+/// there is no `NAME.xml`. Cases come in file name order.
+///
+/// ```no_run
+/// use rustixml::conformance::{load_grammar_tests, ConformanceReport};
+///
+/// let cases = load_grammar_tests("grammars/date.ixml").unwrap();
+/// print!("{}", ConformanceReport::run(&cases));
+/// ```
+pub fn load_grammar_tests(grammar: impl AsRef<Path>) -> Result<Vec<ConformanceCase>, String> {
+    let grammar = grammar.as_ref();
+    let source = GrammarSource::Ixml(read(grammar)?);
+    let dir = grammar.with_extension("tests");
+    let entries = fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read test directory {}: {}", dir.display(), e))?;
+    let mut inputs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_none_or(|ext| ext != "xml"))
+        .collect();
+    inputs.sort();
+
+    inputs
+        .iter()
+        .map(|input| {
+            let name = input.file_stem().unwrap_or_default().to_string_lossy();
+            let expected_path = input.with_extension("xml");
+            let expected = if expected_path.exists() {
+                let xml = read(&expected_path)?;
+                let document = XmlNode::from_xml(&xml)
+                    .map_err(|e| format!("{}: {}", expected_path.display(), e))?;
+                Expected::Xml(vec![document])
+            } else {
+                Expected::NotASentence
+            };
+            Ok(ConformanceCase {
+                name: name.into_owned(),
+                grammar: source.clone(),
+                input: Some(read(input)?),
+                expected,
+            })
+        })
+        .collect()
+}
+
+/// The outcome of every case in a run
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConformanceReport {
+    /// Each case's name and outcome, in catalog order
+    pub results: Vec<(String, CaseOutcome)>,
+}
+
+impl ConformanceReport {
+    /// Run every case
+    pub fn run(cases: &[ConformanceCase]) -> Self {
+        ConformanceReport {
+            results: cases
+                .iter()
+                .map(|case| (case.name.clone(), case.run()))
+                .collect(),
+        }
+    }
+
+    fn count(&self, f: impl Fn(&CaseOutcome) -> bool) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, outcome)| f(outcome))
+            .count()
+    }
+
+    pub fn passed(&self) -> usize {
+        self.count(|outcome| *outcome == CaseOutcome::Pass)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, CaseOutcome::Fail(_)))
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|outcome| matches!(outcome, CaseOutcome::Skip(_)))
+    }
+
+    /// Percentage of the cases that ran which passed
+    pub fn pass_rate(&self) -> f64 {
+        let ran = self.passed() + self.failed();
+        if ran == 0 {
+            return 0.0;
+        }
+        self.passed() as f64 * 100.0 / ran as f64
+    }
+}
+
+/// The failures, then a summary line
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, outcome) in &self.results {
+            if let CaseOutcome::Fail(why) = outcome {
+                writeln!(f, "FAIL {}: {}", name, why)?;
+            }
+        }
+        writeln!(
+            f,
+            "Passed {}/{} ({:.1}%), {} skipped",
+            self.passed(),
+            self.passed() + self.failed(),
+            self.pass_rate(),
+            self.skipped()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chars_catalog() {
+        let cases = load_catalog("ixml_tests/chars/test-catalog.xml").unwrap();
+        let names: Vec<&str> = cases.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "chars.char1a/char1a",
+                "chars.char1b/char1a",
+                "chars.char2/char1a",
+                "chars.astral/chars.astral.1"
+            ]
+        );
+        assert_eq!(cases[0].input.as_deref(), Some("Abʰ"));
+        let Expected::Xml(documents) = &cases[3].expected else {
+            panic!("{:?}", cases[3].expected);
+        };
+        assert_eq!(documents.len(), 2);
+        assert!(matches!(cases[0].grammar, GrammarSource::Ixml(_)));
+    }
+
+    #[test]
+    fn test_grammar_tests() {
+        let dir = std::env::temp_dir().join(format!("rustixml-tests-{}", std::process::id()));
+        let tests = dir.join("list.tests");
+        fs::create_dir_all(&tests).unwrap();
+        fs::write(dir.join("list.ixml"), "list: n++','. n: [\"0\"-\"9\"]+.").unwrap();
+        fs::write(tests.join("one.txt"), "1").unwrap();
+        fs::write(tests.join("one.xml"), "<list>\n  <n>1</n>\n</list>").unwrap();
+        fs::write(tests.join("two.txt"), "1,2").unwrap();
+        fs::write(tests.join("two.xml"), "<list><n>1</n><n>2</n></list>").unwrap();
+        fs::write(tests.join("bad"), "x").unwrap();
+
+        let cases = load_grammar_tests(dir.join("list.ixml"));
+        fs::remove_dir_all(&dir).unwrap();
+        let report = ConformanceReport::run(&cases.unwrap());
+        let outcomes: Vec<(&str, bool)> = report
+            .results
+            .iter()
+            .map(|(name, outcome)| (name.as_str(), *outcome == CaseOutcome::Pass))
+            .collect();
+        assert_eq!(outcomes, [("bad", true), ("one", true), ("two", false)]);
+
+        assert!(load_grammar_tests("missing.ixml")
+            .unwrap_err()
+            .starts_with("Failed to read missing.ixml"));
+    }
+
+    #[test]
+    fn test_outcomes() {
+        let catalog = r#"<tc:test-catalog xmlns:tc="https://github.com/invisibleXML/ixml/test-catalog">
+          <tc:test-set name="bad">
+            <tc:ixml-grammar>s: "a</tc:ixml-grammar>
+            <tc:grammar-test><tc:assert-not-a-grammar/></tc:grammar-test>
+          </tc:test-set>
+          <tc:test-set name="vxml">
+            <tc:vxml-grammar>
+              <ixml><rule name="s"><alt><literal string="a"/></alt></rule></ixml>
+            </tc:vxml-grammar>
+            <tc:grammar-test>
+              <tc:assert-xml>
+                <ixml xmlns=""><rule name="s"><alt><literal string="a"/></alt></rule></ixml>
+              </tc:assert-xml>
+            </tc:grammar-test>
+            <tc:test-set name="inherits">
+              <tc:test-case name="wrong">
+                <tc:test-string>a</tc:test-string>
+                <tc:result><tc:assert-xml><s>b</s></tc:assert-xml></tc:result>
+              </tc:test-case>
+              <tc:test-case name="unknown">
+                <tc:test-string>a</tc:test-string>
+              </tc:test-case>
+            </tc:test-set>
+          </tc:test-set>
+          <tc:test-set name="none">
+            <tc:test-case name="x"><tc:test-string/></tc:test-case>
+          </tc:test-set>
+        </tc:test-catalog>"#;
+        let cases = parse_catalog(catalog, Path::new(".")).unwrap();
+        let report = ConformanceReport::run(&cases);
+        let outcomes: Vec<(&str, &CaseOutcome)> = report
+            .results
+            .iter()
+            .map(|(name, outcome)| (name.as_str(), outcome))
+            .collect();
+        assert_eq!(outcomes[0], ("bad", &CaseOutcome::Pass));
+        assert_eq!(outcomes[1], ("vxml", &CaseOutcome::Pass));
+        assert_eq!(
+            outcomes[2],
+            (
+                "inherits/wrong",
+                &CaseOutcome::Fail(
+                    r#"/s/text()[1]: reference has "b", rustixml has "a""#.to_string()
+                )
+            )
+        );
+        assert_eq!(
+            outcomes[3],
+            (
+                "inherits/unknown",
+                &CaseOutcome::Skip("No expected result".to_string())
+            )
+        );
+        assert_eq!(
+            outcomes[4],
+            (
+                "none/x",
+                &CaseOutcome::Skip("Test set none has no grammar".to_string())
+            )
+        );
+        assert_eq!(
+            (report.passed(), report.failed(), report.skipped()),
+            (2, 1, 2)
+        );
+        assert!(report
+            .to_string()
+            .ends_with("Passed 2/3 (66.7%), 2 skipped\n"));
+
+        assert!(parse_catalog("<catalog/>", Path::new("."))
+            .unwrap_err()
+            .contains("Expected <test-catalog>"));
+    }
+}
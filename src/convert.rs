@@ -0,0 +1,392 @@
+//! Grammar format conversion
+//!
+//! One entry point for every grammar import/export format: a grammar is read
+//! from its source format into an [`IxmlGrammar`], then written out in the
+//! target format. The `rustixml convert` command is a thin wrapper around
+//! [`convert`].
+
+use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Pragma, Repetition, Sequence};
+use crate::grammar_parser::parse_ixml_grammar;
+use crate::xml_node::SerializeOptions;
+use std::fmt;
+use std::str::FromStr;
+
+/// A grammar notation rustixml can convert from or to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrammarFormat {
+    /// iXML notation
+    Ixml,
+    /// The XML representation of iXML grammars
+    Vxml,
+    /// W3C-style EBNF
+    Ebnf,
+    /// RFC 5234 ABNF
+    Abnf,
+    /// ANTLR 4 grammars (recognized, but neither read nor written)
+    Antlr,
+    /// The grammar AST as JSON
+    JsonAst,
+}
+
+impl GrammarFormat {
+    /// Every format, in the order shown in help text
+    pub const ALL: &'static [GrammarFormat] = &[
+        GrammarFormat::Ixml,
+        GrammarFormat::Vxml,
+        GrammarFormat::Ebnf,
+        GrammarFormat::Abnf,
+        GrammarFormat::Antlr,
+        GrammarFormat::JsonAst,
+    ];
+
+    /// Name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            GrammarFormat::Ixml => "ixml",
+            GrammarFormat::Vxml => "vxml",
+            GrammarFormat::Ebnf => "ebnf",
+            GrammarFormat::Abnf => "abnf",
+            GrammarFormat::Antlr => "antlr",
+            GrammarFormat::JsonAst => "json-ast",
+        }
+    }
+
+    /// Whether grammars can be read from this format
+    pub fn can_read(self) -> bool {
+        matches!(
+            self,
+            GrammarFormat::Ixml | GrammarFormat::Vxml | GrammarFormat::Ebnf | GrammarFormat::Abnf
+        )
+    }
+
+    /// Whether grammars can be written in this format
+    pub fn can_write(self) -> bool {
+        matches!(
+            self,
+            GrammarFormat::Ixml | GrammarFormat::Vxml | GrammarFormat::JsonAst
+        )
+    }
+}
+
+impl fmt::Display for GrammarFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for GrammarFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        GrammarFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = GrammarFormat::ALL.iter().map(|f| f.name()).collect();
+                format!(
+                    "Unknown grammar format '{}' (expected one of: {})",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Read a grammar written in `format`
+pub fn read_grammar(format: GrammarFormat, source: &str) -> Result<IxmlGrammar, String> {
+    match format {
+        GrammarFormat::Ixml => parse_ixml_grammar(source),
+        GrammarFormat::Vxml => IxmlGrammar::from_vxml_str(source),
+        GrammarFormat::Ebnf => crate::import::from_ebnf(source),
+        GrammarFormat::Abnf => crate::import::from_abnf(source),
+        other => Err(unsupported(other)),
+    }
+}
+
+/// Write a grammar in `format`
+pub fn write_grammar(format: GrammarFormat, grammar: &IxmlGrammar) -> Result<String, String> {
+    match format {
+        GrammarFormat::Ixml => Ok(grammar.to_string()),
+        // No element of the XML form has text content, so indenting is safe
+        GrammarFormat::Vxml => Ok(grammar
+            .to_vxml()
+            .to_xml_with_options(&SerializeOptions::pretty().with_declaration(true))),
+        GrammarFormat::JsonAst => Ok(to_json_ast(grammar)),
+        other => Err(unsupported(other)),
+    }
+}
+
+/// Convert grammar source from one format to another
+///
+/// ```
+/// use rustixml::convert::{convert, GrammarFormat};
+///
+/// let ixml = convert("greeting: -'hi', name. name: ['a'-'z']+.", GrammarFormat::Ixml, GrammarFormat::Ixml)
+///     .unwrap();
+/// assert_eq!(ixml, "greeting: -\"hi\", name.\nname: [\"a\"-\"z\"]+.\n");
+/// ```
+pub fn convert(source: &str, from: GrammarFormat, to: GrammarFormat) -> Result<String, String> {
+    // Fail before reading if either side can't be converted
+    if !from.can_read() {
+        return Err(unsupported(from));
+    }
+    if !to.can_write() {
+        return Err(unsupported(to));
+    }
+    write_grammar(to, &read_grammar(from, source)?)
+}
+
+/// The error for converting from a format that can't be read, or to one
+/// that can't be written
+fn unsupported(format: GrammarFormat) -> String {
+    let supported = match (format.can_read(), format.can_write()) {
+        (true, false) => "can be read but not written",
+        (false, true) => "can be written but not read",
+        _ => "can be neither read nor written",
+    };
+    format!(
+        "Unsupported conversion direction: {} grammars {}",
+        format, supported
+    )
+}
+
+/// Serialize the grammar AST as JSON
+///
+/// The top level is `{"version", "rules"}`. Rules are `{"name", "mark",
+/// "alternatives"}`, alternatives are arrays of sequences, and sequences are
+/// arrays of `{"base", "repetition"}` factors. Rules and factors with pragmas
+/// also carry `"pragmas": [{"name", "data"}]`.
+pub fn to_json_ast(grammar: &IxmlGrammar) -> String {
+    let rules: Vec<String> = grammar
+        .rules
+        .iter()
+        .map(|rule| {
+            format!(
+                "{{\"name\":{},\"mark\":{},\"alternatives\":{}{}}}",
+                json_string(&rule.name),
+                json_string(&rule.mark.to_string()),
+                alternatives_json(&rule.alternatives),
+                pragmas_json(&rule.pragmas)
+            )
+        })
+        .collect();
+    let version = grammar
+        .version
+        .as_deref()
+        .map_or_else(|| "null".to_string(), json_string);
+    format!(
+        "{{\"version\":{},\"rules\":[{}]}}",
+        version,
+        rules.join(",")
+    )
+}
+
+fn alternatives_json(alts: &Alternatives) -> String {
+    let alts: Vec<String> = alts.alts.iter().map(sequence_json).collect();
+    format!("[{}]", alts.join(","))
+}
+
+fn sequence_json(seq: &Sequence) -> String {
+    let factors: Vec<String> = seq.factors.iter().map(factor_json).collect();
+    format!("[{}]", factors.join(","))
+}
+
+fn factor_json(factor: &Factor) -> String {
+    let repetition = match &factor.repetition {
+        Repetition::None => "null".to_string(),
+        Repetition::Optional => "\"?\"".to_string(),
+        Repetition::ZeroOrMore => "\"*\"".to_string(),
+        Repetition::OneOrMore => "\"+\"".to_string(),
+        Repetition::SeparatedZeroOrMore(sep) => {
+            format!("{{\"op\":\"**\",\"separator\":{}}}", sequence_json(sep))
+        }
+        Repetition::SeparatedOneOrMore(sep) => {
+            format!("{{\"op\":\"++\",\"separator\":{}}}", sequence_json(sep))
+        }
+    };
+    format!(
+        "{{\"base\":{},\"repetition\":{}{}}}",
+        base_json(&factor.base),
+        repetition,
+        pragmas_json(&factor.pragmas)
+    )
+}
+
+/// `,"pragmas":[...]` member, or nothing when there are none
+fn pragmas_json(pragmas: &[Pragma]) -> String {
+    if pragmas.is_empty() {
+        return String::new();
+    }
+    let pragmas: Vec<String> = pragmas
+        .iter()
+        .map(|pragma| {
+            format!(
+                "{{\"name\":{},\"data\":{}}}",
+                json_string(&pragma.name),
+                json_string(&pragma.data)
+            )
+        })
+        .collect();
+    format!(",\"pragmas\":[{}]", pragmas.join(","))
+}
+
+fn base_json(base: &BaseFactor) -> String {
+    match base {
+        BaseFactor::Literal {
+            value,
+            insertion,
+            mark,
+        } => format!(
+            "{{\"literal\":{},\"insertion\":{},\"mark\":{}}}",
+            json_string(value),
+            insertion,
+            json_string(&mark.to_string())
+        ),
+        BaseFactor::Nonterminal { name, mark } => format!(
+            "{{\"nonterminal\":{},\"mark\":{}}}",
+            json_string(name),
+            json_string(&mark.to_string())
+        ),
+        BaseFactor::CharClass {
+            content,
+            negated,
+            mark,
+        } => format!(
+            "{{\"charclass\":{},\"negated\":{},\"mark\":{}}}",
+            json_string(content),
+            negated,
+            json_string(&mark.to_string())
+        ),
+        BaseFactor::Group { alternatives } => {
+            format!("{{\"group\":{}}}", alternatives_json(alternatives))
+        }
+        BaseFactor::NegativeLookahead { base } => format!("{{\"not\":{}}}", base_json(base)),
+    }
+}
+
+/// Quote and escape a string for JSON output
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::FIXTURES;
+
+    #[test]
+    fn test_ixml_round_trip() {
+        let source = r#"
+            doc: -"<", @name, +#a, ("x"; ~["y"])**(",", -#9), line+.
+            @name: [L]+.
+            -line: "say ""hi""", #a.
+        "#;
+        let grammar = parse_ixml_grammar(source).unwrap();
+        let written = write_grammar(GrammarFormat::Ixml, &grammar).unwrap();
+        assert!(written.contains(r#"-line: "say ""hi""", #a."#));
+        assert_eq!(
+            read_grammar(GrammarFormat::Ixml, &written).unwrap(),
+            grammar
+        );
+
+        for fixture in FIXTURES {
+            let grammar = fixture.grammar_ast();
+            let written =
+                convert(fixture.grammar, GrammarFormat::Ixml, GrammarFormat::Ixml).unwrap();
+            assert_eq!(
+                parse_ixml_grammar(&written).unwrap(),
+                grammar,
+                "{}",
+                fixture.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_vxml_round_trip() {
+        for fixture in FIXTURES {
+            let vxml = convert(fixture.grammar, GrammarFormat::Ixml, GrammarFormat::Vxml).unwrap();
+            assert_eq!(
+                read_grammar(GrammarFormat::Vxml, &vxml).unwrap(),
+                fixture.grammar_ast(),
+                "{}",
+                fixture.name
+            );
+        }
+        let vxml = convert("s: 'a'.", GrammarFormat::Ixml, GrammarFormat::Vxml).unwrap();
+        assert_eq!(
+            vxml,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<ixml>\n   <rule name='s'>\n      \
+             <alt>\n         <literal string='a'/>\n      </alt>\n   </rule>\n</ixml>"
+        );
+    }
+
+    #[test]
+    fn test_json_ast_and_unsupported_formats() {
+        let json = convert(
+            r#"s: @a**",", -"x"?. a: ~["0"-"9"]."#,
+            GrammarFormat::Ixml,
+            GrammarFormat::JsonAst,
+        )
+        .unwrap();
+        assert!(json.starts_with(r#"{"version":null,"rules":[{"name":"s","mark":"","alternatives":[[{"base":{"nonterminal":"a","mark":"@"},"repetition":{"op":"**","separator":[{"base":{"literal":",""#));
+        assert!(json.contains(r#"{"charclass":"\"0\"-\"9\"","negated":true,"mark":""}"#));
+
+        let json = convert(
+            "{[p]} s: {[q a \"b\"]} 'x'.",
+            GrammarFormat::Ixml,
+            GrammarFormat::JsonAst,
+        )
+        .unwrap();
+        assert!(json.contains(r#""repetition":null,"pragmas":[{"name":"q","data":"a \"b\""}]}]],"pragmas":[{"name":"p","data":""}]}"#));
+
+        assert_eq!("json-ast".parse(), Ok(GrammarFormat::JsonAst));
+        assert!("yaml"
+            .parse::<GrammarFormat>()
+            .unwrap_err()
+            .contains("abnf"));
+        let err = convert("s: 'a'.", GrammarFormat::Ixml, GrammarFormat::Ebnf).unwrap_err();
+        assert_eq!(
+            err,
+            "Unsupported conversion direction: ebnf grammars can be read but not written"
+        );
+        let err = convert("{}", GrammarFormat::JsonAst, GrammarFormat::Ixml).unwrap_err();
+        assert_eq!(
+            err,
+            "Unsupported conversion direction: json-ast grammars can be written but not read"
+        );
+        assert_eq!("antlr".parse(), Ok(GrammarFormat::Antlr));
+        for (from, to) in [
+            (GrammarFormat::Antlr, GrammarFormat::Ixml),
+            (GrammarFormat::Ixml, GrammarFormat::Antlr),
+        ] {
+            assert_eq!(
+                convert("s: 'a'.", from, to).unwrap_err(),
+                "Unsupported conversion direction: antlr grammars can be neither read nor written"
+            );
+        }
+        assert_eq!(
+            convert("s = %s\"a\"", GrammarFormat::Abnf, GrammarFormat::Ixml),
+            Ok("s: \"a\".\n".to_string())
+        );
+        assert_eq!(
+            convert("s ::= 'a'+", GrammarFormat::Ebnf, GrammarFormat::Ixml),
+            Ok("s: \"a\"+.\n".to_string())
+        );
+    }
+}
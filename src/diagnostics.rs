@@ -0,0 +1,674 @@
+//! Diagnostics shared by grammar parsing, grammar analysis and input parsing
+//!
+//! Grammar errors, analysis warnings and parse errors each have their own
+//! types. [`Diagnostic`] is the one shape they all convert to: a severity, a
+//! stable [`code`], a message, notes, and the span of source it applies to
+//! when that is known. Tools can render every report the same way with
+//! [`Diagnostic::render`].
+
+use crate::ast::IxmlGrammar;
+use crate::compiled_grammar::{GrammarError, GrammarProblem};
+use crate::convert::json_string;
+use crate::grammar_analysis::{lint, GrammarAnalysis, Lint, UndefinedReference};
+use crate::input_stream::line_col;
+use crate::parse_context::ParseError;
+use crate::regex_hints::{regex_hints, RegexHint};
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "miette")]
+mod report;
+#[cfg(feature = "miette")]
+pub use report::SourceDiagnostic;
+
+/// Stable codes identifying what a diagnostic is about
+pub mod code {
+    /// The grammar does not parse as iXML
+    pub const GRAMMAR_SYNTAX: &str = "grammar-syntax";
+    /// A rule refers to a rule the grammar does not define
+    pub const UNDEFINED_RULE: &str = "undefined-rule";
+    /// Two rules have the same name
+    pub const DUPLICATE_RULE: &str = "duplicate-rule";
+    /// A rule other than the start rule that nothing refers to
+    pub const UNUSED_RULE: &str = "unused-rule";
+    /// A rule the start rule cannot reach
+    pub const UNREACHABLE_RULE: &str = "unreachable-rule";
+    /// An attribute whose content includes elements
+    pub const ATTRIBUTE_CONTAINS_ELEMENT: &str = "attribute-contains-element";
+    /// A repetition of something that can match the empty string
+    pub const NULLABLE_REPETITION: &str = "nullable-repetition";
+    /// The grammar has no rules
+    pub const EMPTY_GRAMMAR: &str = "empty-grammar";
+    /// The grammar may give more than one parse of an input
+    pub const AMBIGUOUS_GRAMMAR: &str = "ambiguous-grammar";
+    /// A rule's first symbol can derive the rule itself
+    pub const LEFT_RECURSION: &str = "left-recursion";
+    /// A repetition the recursive-descent engine may backtrack through heavily
+    pub const BACKTRACKING_HAZARD: &str = "backtracking-hazard";
+    /// Two alternatives of a rule can start with the same character
+    pub const OVERLAPPING_ALTERNATIVES: &str = "overlapping-alternatives";
+    /// A rule refers to itself, directly or through other rules
+    pub const RECURSIVE_RULE: &str = "recursive-rule";
+    /// A rule with many alternatives or deep nesting
+    pub const COMPLEX_RULE: &str = "complex-rule";
+    /// Regex syntax in a grammar, which iXML reads differently
+    pub const REGEX_IDIOM: &str = "regex-idiom";
+    /// The input does not match the grammar
+    pub const PARSE_ERROR: &str = "parse-error";
+    /// An output limit cut the result short
+    pub const OUTPUT_TRUNCATED: &str = "output-truncated";
+    /// A result tree does not determine the input it was parsed from
+    pub const UNPARSE_AMBIGUOUS: &str = "unparse-ambiguous";
+}
+
+/// Complexity score above which a rule is reported as complex
+const COMPLEXITY_THRESHOLD: usize = 10;
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        })
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "error" => Ok(Severity::Error),
+            "warning" => Ok(Severity::Warning),
+            "info" => Ok(Severity::Info),
+            _ => Err(format!(
+                "Unknown severity '{}' (expected error, warning or info)",
+                name
+            )),
+        }
+    }
+}
+
+/// Character offsets `start..end` in a grammar or input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// The single character at `position`
+    pub fn at(position: usize) -> Self {
+        Span::new(position, position + 1)
+    }
+
+    /// Line and column (both from 1) where the span starts in `source`
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        line_col(source, self.start)
+    }
+}
+
+/// One finding about a grammar or an input
+///
+/// ```
+/// use rustixml::diagnostics::{code, Diagnostic, Span};
+///
+/// let source = "date: day, month.\nmonth: \"Jan\".";
+/// let diagnostic = Diagnostic::error(code::GRAMMAR_SYNTAX, "Undefined rule: day")
+///     .with_span(Span::new(6, 9))
+///     .with_note("define day or fix the reference");
+/// assert_eq!(
+///     diagnostic.to_string(),
+///     "error[grammar-syntax]: Undefined rule: day\n  = note: define day or fix the reference"
+/// );
+/// assert_eq!(
+///     diagnostic.render(source),
+///     [
+///         "error[grammar-syntax]: Undefined rule: day",
+///         " --> line 1, column 7",
+///         "  |",
+///         "1 | date: day, month.",
+///         "  |       ^^^",
+///         "  = note: define day or fix the reference",
+///     ]
+///     .join("\n")
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Where in the grammar or input this applies, if known
+    pub span: Option<Span>,
+    pub severity: Severity,
+    /// One of the [`code`] constants
+    pub code: &'static str,
+    pub message: String,
+    /// Further explanation and suggestions, one sentence each
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            span: None,
+            severity,
+            code,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    pub fn info(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Info, code, message)
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// A grammar error from
+    /// [`parse_ixml_grammar`](crate::grammar_parser::parse_ixml_grammar) on
+    /// `source`
+    ///
+    /// `hint:` lines become notes, and the span is the first regex idiom the
+    /// hints point at.
+    pub fn from_grammar_error(error: &str, source: &str) -> Self {
+        let mut message = Vec::new();
+        let mut notes = Vec::new();
+        for line in error.lines() {
+            match line.strip_prefix("hint: ") {
+                Some(hint) => notes.push(hint.to_string()),
+                None => message.push(line),
+            }
+        }
+        let span = regex_hints(source)
+            .first()
+            .map(|hint| hint_span(hint, source));
+        Diagnostic {
+            span,
+            notes,
+            ..Diagnostic::error(code::GRAMMAR_SYNTAX, message.join("\n"))
+        }
+    }
+
+    /// A warning that grammar `source` uses regex syntax at `hint`
+    pub fn from_regex_hint(hint: &RegexHint, source: &str) -> Self {
+        Diagnostic::warning(
+            code::REGEX_IDIOM,
+            format!("`{}` looks like regex syntax", hint.snippet),
+        )
+        .with_span(hint_span(hint, source))
+        .with_note(hint.suggestion.clone())
+    }
+
+    /// `error` as a diagnostic on the input it was raised for
+    ///
+    /// The span is the farthest point the parse reached when that is known,
+    /// since that is usually where the input is wrong.
+    pub fn from_parse_error(error: &ParseError) -> Self {
+        let mut inner = error;
+        let mut failure = None;
+        loop {
+            match inner {
+                ParseError::Located { error, .. } => inner = error,
+                ParseError::Farthest { error, failure: f } => {
+                    failure = Some(f);
+                    inner = error;
+                }
+                _ => break,
+            }
+        }
+        let diagnostic = Diagnostic::error(code::PARSE_ERROR, inner.to_string());
+        match failure {
+            Some(failure) => {
+                let diagnostic = diagnostic
+                    .with_span(Span::at(failure.position))
+                    .with_note(failure.to_string());
+                if failure.rules.is_empty() {
+                    diagnostic
+                } else {
+                    diagnostic.with_note(format!("rule stack: {}", failure.rules.join(" > ")))
+                }
+            }
+            None => match inner {
+                ParseError::InstructionLimitExceeded { .. } => diagnostic,
+                _ => diagnostic.with_span(Span::at(inner.position())),
+            },
+        }
+    }
+
+    /// The problems [`validate`](crate::compiled_grammar::validate) found
+    pub fn from_grammar_problems(error: &GrammarError) -> Vec<Self> {
+        error
+            .problems
+            .iter()
+            .map(|problem| match problem {
+                GrammarProblem::NoRules => {
+                    Diagnostic::error(code::EMPTY_GRAMMAR, "Grammar has no rules")
+                }
+                GrammarProblem::DuplicateRule { name } => Diagnostic::error(
+                    code::DUPLICATE_RULE,
+                    format!("Rule '{}' is defined more than once", name),
+                ),
+                GrammarProblem::UndefinedRule(reference) => {
+                    Diagnostic::from_undefined_reference(reference)
+                }
+            })
+            .collect()
+    }
+
+    fn from_undefined_reference(reference: &UndefinedReference) -> Self {
+        let diagnostic = Diagnostic::error(
+            code::UNDEFINED_RULE,
+            format!(
+                "Rule '{}' refers to undefined rule '{}'",
+                reference.rule, reference.name
+            ),
+        );
+        match &reference.suggestion {
+            Some(suggestion) => diagnostic.with_note(format!("did you mean '{}'?", suggestion)),
+            None => diagnostic,
+        }
+    }
+
+    /// A [`lint`](crate::grammar_analysis::lint) finding, with the
+    /// [severity](crate::grammar_analysis::LintKind::severity) of its kind
+    pub fn from_lint(lint: &Lint) -> Self {
+        let diagnostic = Diagnostic::new(
+            lint.kind.severity(),
+            lint.kind.code(),
+            format!("{}: {}", lint.rule, lint.message),
+        );
+        match &lint.suggestion {
+            Some(suggestion) => diagnostic.with_note(suggestion.clone()),
+            None => diagnostic,
+        }
+    }
+
+    /// The findings of a grammar analysis, most serious first
+    ///
+    /// Rules carry no source positions, so these have no spans.
+    pub fn from_analysis(analysis: &GrammarAnalysis) -> Vec<Self> {
+        let mut diagnostics = Vec::new();
+
+        diagnostics.extend(
+            analysis
+                .undefined_references
+                .iter()
+                .map(Diagnostic::from_undefined_reference),
+        );
+
+        if analysis.is_potentially_ambiguous {
+            diagnostics.push(
+                Diagnostic::warning(
+                    code::AMBIGUOUS_GRAMMAR,
+                    "Grammar may be ambiguous (multiple parse trees possible)",
+                )
+                .with_note(
+                    "inputs that do parse more than one way are marked ixml:state=\"ambiguous\"",
+                ),
+            );
+        }
+
+        for rule in sorted(analysis.left_recursive_rules.iter()) {
+            diagnostics.push(Diagnostic::warning(
+                code::LEFT_RECURSION,
+                format!("Rule '{}' is left-recursive", rule),
+            ));
+        }
+
+        for hazard in &analysis.backtracking_hazards {
+            let mut diagnostic = Diagnostic::warning(
+                code::BACKTRACKING_HAZARD,
+                format!("Repetition may backtrack heavily: {}", hazard),
+            );
+            if let Some(input) = &hazard.reproduction {
+                diagnostic = diagnostic.with_note(format!(
+                    "reproduce with start rule {}: {:?}",
+                    hazard.rule, input
+                ));
+            }
+            diagnostics.push(diagnostic.with_note(
+                "parse with ParseStrategy::Earley, or move the repeated group into a rule of its own",
+            ));
+        }
+
+        for overlap in &analysis.alternative_overlaps {
+            diagnostics.push(Diagnostic::info(
+                code::OVERLAPPING_ALTERNATIVES,
+                format!(
+                    "Alternatives {} and {} of rule '{}' can both start with {}",
+                    overlap.first + 1,
+                    overlap.second + 1,
+                    overlap.rule,
+                    overlap.overlap
+                ),
+            ));
+        }
+
+        for rule in sorted(
+            analysis
+                .recursive_rules
+                .difference(&analysis.left_recursive_rules),
+        ) {
+            diagnostics.push(Diagnostic::info(
+                code::RECURSIVE_RULE,
+                format!("Rule '{}' is recursive", rule),
+            ));
+        }
+
+        let mut complex: Vec<_> = analysis
+            .complexity_scores
+            .iter()
+            .filter(|(_, &score)| score > COMPLEXITY_THRESHOLD)
+            .collect();
+        complex.sort();
+        for (rule, score) in complex {
+            diagnostics.push(Diagnostic::info(
+                code::COMPLEX_RULE,
+                format!("Rule '{}' has complexity {}", rule, score),
+            ));
+        }
+
+        diagnostics
+    }
+
+    /// Every [`lint`] and analysis finding for a grammar, most serious first,
+    /// as `rustixml lint` reports them
+    ///
+    /// ```
+    /// use rustixml::diagnostics::{Diagnostic, Severity};
+    /// use rustixml::parse_ixml_grammar;
+    ///
+    /// let grammar = parse_ixml_grammar(r#"s: s, "a"; dgit. digit: ["0"-"9"]."#).unwrap();
+    /// let found: Vec<String> = Diagnostic::from_grammar(&grammar)
+    ///     .iter()
+    ///     .filter(|d| d.severity <= Severity::Warning)
+    ///     .map(|d| d.to_string())
+    ///     .collect();
+    /// assert_eq!(
+    ///     found,
+    ///     [
+    ///         "error[undefined-rule]: s: undefined rule 'dgit'\n  = note: did you mean 'digit'?",
+    ///         "warning[unused-rule]: digit: rule 'digit' is never used",
+    ///         "warning[left-recursion]: Rule 's' is left-recursive",
+    ///     ]
+    /// );
+    /// ```
+    pub fn from_grammar(grammar: &IxmlGrammar) -> Vec<Self> {
+        let mut diagnostics: Vec<Diagnostic> =
+            lint(grammar).iter().map(Diagnostic::from_lint).collect();
+        // The lints already cover undefined rules
+        diagnostics.extend(
+            Diagnostic::from_analysis(&GrammarAnalysis::analyze(grammar))
+                .into_iter()
+                .filter(|d| d.code != code::UNDEFINED_RULE),
+        );
+        diagnostics.sort_by_key(|d| d.severity);
+        diagnostics
+    }
+
+    /// The diagnostic with the line of `source` its span points at
+    pub fn render(&self, source: &str) -> String {
+        let mut out = format!("{}[{}]: {}", self.severity, self.code, self.message);
+        if let Some(span) = self.span {
+            let (line, column) = span.line_col(source);
+            let text = source.lines().nth(line - 1).unwrap_or("");
+            let gutter = " ".repeat(line.to_string().len());
+            let width = span
+                .end
+                .saturating_sub(span.start)
+                .clamp(1, (text.chars().count() + 1).saturating_sub(column).max(1));
+            out.push_str(&format!(
+                "\n{g}--> line {l}, column {c}\n{g} |\n{l} | {t}\n{g} | {pad}{marks}",
+                g = gutter,
+                l = line,
+                c = column,
+                t = text,
+                pad = " ".repeat(column - 1),
+                marks = "^".repeat(width),
+            ));
+        }
+        for note in &self.notes {
+            out.push_str(&format!("\n  = note: {}", note));
+        }
+        out
+    }
+
+    /// The diagnostic as one line of JSON, for editors and CI systems
+    ///
+    /// `file` is where `source` was read from; `line` and `column` (from 1)
+    /// are `null` without a span or a source to find it in.
+    ///
+    /// ```
+    /// use rustixml::diagnostics::{code, Diagnostic, Span};
+    ///
+    /// let diagnostic = Diagnostic::error(code::UNDEFINED_RULE, "Rule 'date' refers to undefined rule 'day'")
+    ///     .with_span(Span::new(6, 9))
+    ///     .with_note("did you mean 'days'?");
+    /// assert_eq!(
+    ///     diagnostic.to_json(Some("date.ixml"), Some("date: day.")),
+    ///     "{\"file\":\"date.ixml\",\"line\":1,\"column\":7,\"severity\":\"error\",\
+    ///      \"code\":\"undefined-rule\",\"message\":\"Rule 'date' refers to undefined rule 'day'\",\
+    ///      \"notes\":[\"did you mean 'days'?\"]}"
+    /// );
+    /// ```
+    pub fn to_json(&self, file: Option<&str>, source: Option<&str>) -> String {
+        let (line, column) = match self.span.zip(source) {
+            Some((span, source)) => {
+                let (line, column) = span.line_col(source);
+                (line.to_string(), column.to_string())
+            }
+            None => ("null".to_string(), "null".to_string()),
+        };
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+        format!(
+            "{{\"file\":{},\"line\":{},\"column\":{},\"severity\":{},\"code\":{},\"message\":{},\"notes\":[{}]}}",
+            file.map_or_else(|| "null".to_string(), json_string),
+            line,
+            column,
+            json_string(&self.severity.to_string()),
+            json_string(self.code),
+            json_string(&self.message),
+            notes.join(",")
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        for note in &self.notes {
+            write!(f, "\n  = note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        Diagnostic::from_parse_error(error)
+    }
+}
+
+fn sorted<'a>(rules: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
+    let mut rules: Vec<_> = rules.collect();
+    rules.sort();
+    rules
+}
+
+/// The characters of `source` a regex hint points at
+fn hint_span(hint: &RegexHint, source: &str) -> Span {
+    let start = offset_of(source, hint.line, hint.column);
+    Span::new(start, start + hint.snippet.chars().count())
+}
+
+/// Character offset of `line`, `column` (both from 1) in `source`
+fn offset_of(source: &str, line: usize, column: usize) -> usize {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(line - 1)
+        .map(|l| l.chars().count())
+        .sum();
+    line_start + column - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiled_grammar::validate;
+    use crate::grammar_analysis::GrammarAnalysis;
+    use crate::grammar_parser::parse_ixml_grammar;
+    use crate::native_parser::NativeParser;
+
+    #[test]
+    fn test_grammar_error_diagnostic() {
+        let source = "number: \\d+.";
+        let error = parse_ixml_grammar(source).unwrap_err();
+        let diagnostic = Diagnostic::from_grammar_error(&error, source);
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.code, code::GRAMMAR_SYNTAX);
+        assert!(
+            !diagnostic.message.contains("hint:"),
+            "{}",
+            diagnostic.message
+        );
+        assert_eq!(diagnostic.notes.len(), 1);
+        assert_eq!(diagnostic.span, Some(Span::new(8, 10)));
+        assert!(diagnostic
+            .render(source)
+            .contains("\n1 | number: \\d+.\n  |         ^^\n"));
+    }
+
+    #[test]
+    fn test_parse_error_diagnostic() {
+        let grammar = parse_ixml_grammar("date: month.\nmonth: \"Jan\"; \"Mar\".").unwrap();
+        let parser = NativeParser::new(grammar);
+        let error = parser.parse("Mxr").unwrap_err();
+        let diagnostic = Diagnostic::from(&error);
+        assert_eq!(diagnostic.code, code::PARSE_ERROR);
+        assert_eq!(diagnostic.span, Some(Span::at(0)));
+        assert_eq!(
+            diagnostic.notes,
+            vec![
+                "expected one of \"Jan\", \"Mar\" but found \"Mxr\"".to_string(),
+                "rule stack: date > month".to_string(),
+            ]
+        );
+        assert!(
+            !diagnostic.message.contains("line"),
+            "{}",
+            diagnostic.message
+        );
+
+        let budget = ParseError::InstructionLimitExceeded {
+            consumed: 2,
+            budget: 1,
+        };
+        assert_eq!(Diagnostic::from(&budget).span, None);
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let source = "number: \\d+.";
+        let hint = &regex_hints(source)[0];
+        let json = Diagnostic::from_regex_hint(hint, source).to_json(None, Some(source));
+        assert!(
+            json.starts_with(
+                "{\"file\":null,\"line\":1,\"column\":9,\"severity\":\"warning\",\
+                 \"code\":\"regex-idiom\",\"message\":\"`\\\\d` looks like regex syntax\""
+            ),
+            "{}",
+            json
+        );
+
+        // Without a source the position is unknown
+        let input = "Jan\nMxr";
+        let grammar =
+            parse_ixml_grammar("date: month, -#a, month. month: \"Jan\"; \"Mar\".").unwrap();
+        let error = NativeParser::new(grammar).parse(input).unwrap_err();
+        let diagnostic = Diagnostic::from(&error);
+        let json = diagnostic.to_json(Some("in.txt"), Some(input));
+        assert!(
+            json.starts_with("{\"file\":\"in.txt\",\"line\":2,\"column\":1,"),
+            "{}",
+            json
+        );
+        assert!(json.contains("\"notes\":[\"expected one of"), "{}", json);
+        let json = diagnostic.to_json(Some("in.txt"), None);
+        assert!(json.contains("\"line\":null,\"column\":null,"), "{}", json);
+    }
+
+    #[test]
+    fn test_analysis_diagnostics() {
+        let grammar =
+            parse_ixml_grammar("expr: expr, \"+\", term; term.\nterm: \"(\", expr, \")\"; \"x\".")
+                .unwrap();
+        let diagnostics = Diagnostic::from_analysis(&GrammarAnalysis::analyze(&grammar));
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.severity, d.code, d.message.as_str()))
+            .collect();
+        assert!(found.contains(&(
+            Severity::Warning,
+            code::LEFT_RECURSION,
+            "Rule 'expr' is left-recursive"
+        )));
+        assert!(found.contains(&(
+            Severity::Info,
+            code::RECURSIVE_RULE,
+            "Rule 'term' is recursive"
+        )));
+        assert!(diagnostics
+            .windows(2)
+            .all(|w| w[0].severity <= w[1].severity));
+
+        let grammar = parse_ixml_grammar("s: lettter. letter: \"a\".").unwrap();
+        let diagnostics = Diagnostic::from_analysis(&GrammarAnalysis::analyze(&grammar));
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error[undefined-rule]: Rule 's' refers to undefined rule 'lettter'\n  \
+             = note: did you mean 'letter'?"
+        );
+        let problems = Diagnostic::from_grammar_problems(&validate(&grammar).unwrap_err());
+        assert_eq!(problems, diagnostics[..1]);
+    }
+
+    #[test]
+    fn test_render_at_end_of_input() {
+        let diagnostic =
+            Diagnostic::error(code::PARSE_ERROR, "Unexpected EOF").with_span(Span::at(3));
+        assert_eq!(
+            diagnostic.render("abc"),
+            "error[parse-error]: Unexpected EOF\n --> line 1, column 4\n  |\n1 | abc\n  |    ^"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! [`miette`] reports for diagnostics and parse errors
+//!
+//! [`Diagnostic`] and [`ParseError`] implement [`miette::Diagnostic`] with a
+//! code, severity and help, but carry no source text. Pair one with the
+//! grammar or input it is about in a [`SourceDiagnostic`] to get a report that
+//! labels the span in the source.
+
+use super::{Diagnostic, Severity};
+use crate::compiled_grammar::GrammarError;
+use crate::parse_context::ParseError;
+use miette::{LabeledSpan, NamedSource, SourceCode};
+use std::fmt;
+
+impl From<Severity> for miette::Severity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Error => miette::Severity::Error,
+            Severity::Warning => miette::Severity::Warning,
+            Severity::Info => miette::Severity::Advice,
+        }
+    }
+}
+
+impl miette::Diagnostic for Diagnostic {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(format!("rustixml::{}", self.code)))
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(self.severity.into())
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        help(&self.notes)
+    }
+}
+
+impl miette::Diagnostic for ParseError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(format!("rustixml::{}", super::code::PARSE_ERROR)))
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(miette::Severity::Error)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        help(&Diagnostic::from(self).notes)
+    }
+}
+
+impl miette::Diagnostic for GrammarError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let diagnostics = Diagnostic::from_grammar_problems(self);
+        let code = diagnostics.first()?.code;
+        Some(Box::new(format!("rustixml::{}", code)))
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(miette::Severity::Error)
+    }
+}
+
+/// A diagnostic with the grammar or input it is about
+///
+/// The first note labels the span; the rest are help.
+///
+/// ```
+/// use rustixml::diagnostics::SourceDiagnostic;
+/// use rustixml::grammar_parser::parse_ixml_grammar;
+/// use rustixml::native_parser::NativeParser;
+///
+/// let grammar = parse_ixml_grammar("date: month.\nmonth: \"Jan\"; \"Mar\".").unwrap();
+/// let error = NativeParser::new(grammar).parse("Mxr").unwrap_err();
+/// let report = miette::Report::new(SourceDiagnostic::parse_error(&error, "date.txt", "Mxr"));
+/// assert_eq!(report.code().unwrap().to_string(), "rustixml::parse-error");
+/// ```
+#[derive(Debug)]
+pub struct SourceDiagnostic {
+    diagnostic: Diagnostic,
+    source: NamedSource<String>,
+    /// The diagnostic's span in bytes, as miette counts
+    label: Option<LabeledSpan>,
+}
+
+impl SourceDiagnostic {
+    /// `diagnostic` about `source`, which is called `name` in reports
+    pub fn new(diagnostic: Diagnostic, name: &str, source: impl Into<String>) -> Self {
+        let source = source.into();
+        let label = diagnostic.span.map(|span| {
+            let start = byte_offset(&source, span.start);
+            let end = byte_offset(&source, span.end.max(span.start));
+            LabeledSpan::new(diagnostic.notes.first().cloned(), start, end - start)
+        });
+        SourceDiagnostic {
+            diagnostic,
+            source: NamedSource::new(name, source),
+            label,
+        }
+    }
+
+    /// A grammar error from parsing the grammar `source`
+    pub fn grammar_error(error: &str, name: &str, source: &str) -> Self {
+        SourceDiagnostic::new(Diagnostic::from_grammar_error(error, source), name, source)
+    }
+
+    /// A parse error from parsing `input`
+    pub fn parse_error(error: &ParseError, name: &str, input: &str) -> Self {
+        SourceDiagnostic::new(Diagnostic::from(error), name, input)
+    }
+
+    pub fn diagnostic(&self) -> &Diagnostic {
+        &self.diagnostic
+    }
+}
+
+impl fmt::Display for SourceDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.diagnostic.message)
+    }
+}
+
+impl std::error::Error for SourceDiagnostic {}
+
+impl miette::Diagnostic for SourceDiagnostic {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.diagnostic.code()
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(self.diagnostic.severity.into())
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        match self.label {
+            Some(_) => help(self.diagnostic.notes.get(1..).unwrap_or_default()),
+            None => help(&self.diagnostic.notes),
+        }
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.source)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let label = self.label.clone()?;
+        Some(Box::new(std::iter::once(label)))
+    }
+}
+
+fn help<'a>(notes: &[String]) -> Option<Box<dyn fmt::Display + 'a>> {
+    if notes.is_empty() {
+        None
+    } else {
+        Some(Box::new(notes.join("\n")))
+    }
+}
+
+/// Byte offset of character offset `position` in `source`
+fn byte_offset(source: &str, position: usize) -> usize {
+    source
+        .char_indices()
+        .nth(position)
+        .map_or(source.len(), |(offset, _)| offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar_parser::parse_ixml_grammar;
+    use miette::Diagnostic as _;
+
+    #[test]
+    fn test_grammar_error_report() {
+        let source = "é: [a-z]+.";
+        let error = parse_ixml_grammar(source).unwrap_err();
+        let report = SourceDiagnostic::grammar_error(&error, "words.ixml", source);
+        assert_eq!(
+            report.code().unwrap().to_string(),
+            "rustixml::grammar-syntax"
+        );
+        assert_eq!(report.severity(), Some(miette::Severity::Error));
+
+        // The span is counted in bytes, past the two-byte `é`
+        let label = report.labels().unwrap().next().unwrap();
+        assert_eq!((label.offset(), label.len()), (4, 5));
+        assert_eq!(
+            label.label(),
+            report.diagnostic().notes.first().map(String::as_str)
+        );
+        assert!(report.source_code().is_some());
+    }
+
+    #[test]
+    fn test_grammar_problems() {
+        let grammar = parse_ixml_grammar("s: t.").unwrap();
+        let error = crate::NativeParser::try_new(grammar).err().unwrap();
+        assert_eq!(
+            error.code().unwrap().to_string(),
+            "rustixml::undefined-rule"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_diagnostic() {
+        let grammar = parse_ixml_grammar("date: month.\nmonth: \"Jan\"; \"Mar\".").unwrap();
+        let error = crate::native_parser::NativeParser::new(grammar)
+            .parse("Mxr")
+            .unwrap_err();
+        assert_eq!(
+            error.help().unwrap().to_string(),
+            "expected one of \"Jan\", \"Mar\" but found \"Mxr\"\nrule stack: date > month"
+        );
+
+        let report = SourceDiagnostic::parse_error(&error, "date.txt", "Mxr");
+        assert_eq!(
+            report.help().unwrap().to_string(),
+            "rule stack: date > month"
+        );
+    }
+}
@@ -0,0 +1,913 @@
+//! Earley parse engine for ambiguous grammars
+//!
+//! The recursive descent interpreter in `native_parser` commits to the longest
+//! matching alternative, which is fast but silently picks one reading of a
+//! genuinely ambiguous grammar. This module implements a chart parser that
+//! recognizes every derivation of the input and then walks a shared packed
+//! parse forest (SPPF) to serialize one tree, reporting whether more than one
+//! tree exists so the output can carry `ixml:state="ambiguous"`.
+//!
+//! The iXML AST is first lowered to plain BNF: groups, repetitions and
+//! separators become auxiliary nonterminals whose children are spliced into
+//! the enclosing rule when the XML tree is built.
+
+use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Repetition, Sequence};
+use crate::charclass::{charclass_to_rangeset, RangeSet};
+use crate::parse_context::ParseError;
+use crate::xml_node::{XmlNode, XmlParts};
+use std::collections::{HashMap, HashSet};
+
+/// Terminal symbol in the lowered grammar
+#[derive(Debug, Clone)]
+enum Terminal {
+    /// Literal string (matched character by character)
+    Literal(Vec<char>),
+    /// Single character from a (possibly negated) character class
+    Class { set: RangeSet, negated: bool },
+    /// Insertion: matches the empty string, emits text
+    Insert(String),
+    /// Negative lookahead: matches the empty string if the inner terminal does not match
+    NotFollowedBy(Box<Terminal>),
+}
+
+impl Terminal {
+    /// Number of characters this terminal consumes at `pos`, if it matches
+    fn match_at(&self, input: &[char], pos: usize) -> Option<usize> {
+        match self {
+            Terminal::Literal(chars) => {
+                let end = pos + chars.len();
+                (end <= input.len() && input[pos..end] == chars[..]).then_some(chars.len())
+            }
+            Terminal::Class { set, negated } => {
+                let ch = *input.get(pos)?;
+                (set.contains(ch) != *negated).then_some(1)
+            }
+            Terminal::Insert(_) => Some(0),
+            Terminal::NotFollowedBy(inner) => inner.match_at(input, pos).is_none().then_some(0),
+        }
+    }
+
+    /// Most characters this terminal can consume
+    fn max_len(&self) -> usize {
+        match self {
+            Terminal::Literal(chars) => chars.len(),
+            Terminal::Class { .. } => 1,
+            Terminal::Insert(_) | Terminal::NotFollowedBy(_) => 0,
+        }
+    }
+
+    fn is_nullable(&self) -> bool {
+        match self {
+            Terminal::Literal(chars) => chars.is_empty(),
+            Terminal::Class { .. } => false,
+            Terminal::Insert(_) | Terminal::NotFollowedBy(_) => true,
+        }
+    }
+}
+
+/// Symbol on the right-hand side of a production, with the mark at its use site
+#[derive(Debug, Clone)]
+enum Symbol {
+    Nonterminal { id: usize, mark: Mark },
+    Terminal { term: Terminal, mark: Mark },
+}
+
+/// What a lowered nonterminal stands for in the output
+#[derive(Debug, Clone)]
+enum NonterminalKind {
+    /// A grammar rule: produces an element/attribute according to its mark
+    Rule { name: String, mark: Mark },
+    /// Generated for a group or repetition: children are spliced into the parent
+    Auxiliary,
+}
+
+#[derive(Debug, Clone)]
+struct Production {
+    lhs: usize,
+    rhs: Vec<Symbol>,
+}
+
+/// An iXML grammar lowered to BNF for the Earley recognizer
+///
+/// Lowering happens once; the compiled grammar can then parse any number of
+/// inputs. [`NativeParser`](crate::NativeParser) builds one for
+/// [`ParseStrategy::Earley`](crate::ParseStrategy::Earley).
+///
+/// ```
+/// use rustixml::{earley::EarleyGrammar, fixtures::AMBIGUOUS};
+///
+/// let grammar = AMBIGUOUS.grammar_ast();
+/// let compiled = EarleyGrammar::from_grammar(&grammar, "s").unwrap();
+///
+/// let (tree, ambiguous) = compiled.parse("x.").unwrap();
+/// assert!(ambiguous);
+/// assert_eq!(tree.to_xml(), "<s><a>x</a>.</s>");
+/// assert!(compiled.parse("y.").is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct EarleyGrammar {
+    kinds: Vec<NonterminalKind>,
+    productions: Vec<Production>,
+    /// Production indices for each nonterminal
+    by_lhs: Vec<Vec<usize>>,
+    nullable: Vec<bool>,
+    start: usize,
+}
+
+impl EarleyGrammar {
+    /// Lower an iXML grammar, using `start_rule` as the root nonterminal
+    pub fn from_grammar(grammar: &IxmlGrammar, start_rule: &str) -> Result<Self, String> {
+        let mut builder = Builder {
+            ids: HashMap::new(),
+            kinds: Vec::new(),
+            productions: Vec::new(),
+            unsupported: None,
+        };
+
+        for rule in &grammar.rules {
+            let id = builder.rule_id(&rule.name);
+            builder.kinds[id] = NonterminalKind::Rule {
+                name: rule.name.clone(),
+                mark: rule.mark,
+            };
+        }
+        for rule in &grammar.rules {
+            let id = builder.ids[&rule.name];
+            builder.add_alternatives(id, &rule.alternatives);
+        }
+
+        if let Some(message) = builder.unsupported {
+            return Err(message);
+        }
+
+        let start = *builder
+            .ids
+            .get(start_rule)
+            .ok_or_else(|| format!("Start rule '{}' is not defined", start_rule))?;
+
+        let mut by_lhs = vec![Vec::new(); builder.kinds.len()];
+        for (index, production) in builder.productions.iter().enumerate() {
+            by_lhs[production.lhs].push(index);
+        }
+
+        let mut lowered = EarleyGrammar {
+            kinds: builder.kinds,
+            productions: builder.productions,
+            by_lhs,
+            nullable: Vec::new(),
+            start,
+        };
+        lowered.nullable = lowered.compute_nullable();
+        Ok(lowered)
+    }
+
+    /// Fixpoint computation of nullable nonterminals
+    fn compute_nullable(&self) -> Vec<bool> {
+        let mut nullable = vec![false; self.kinds.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for production in &self.productions {
+                if nullable[production.lhs] {
+                    continue;
+                }
+                let all_nullable = production.rhs.iter().all(|symbol| match symbol {
+                    Symbol::Nonterminal { id, .. } => nullable[*id],
+                    Symbol::Terminal { term, .. } => term.is_nullable(),
+                });
+                if all_nullable {
+                    nullable[production.lhs] = true;
+                    changed = true;
+                }
+            }
+        }
+        nullable
+    }
+
+    /// Parse input, returning the root element and whether the parse was ambiguous
+    pub fn parse(&self, input: &str) -> Result<(XmlNode, bool), ParseError> {
+        self.parse_reporting(input, None)
+    }
+
+    /// [`EarleyGrammar::parse`], calling `step` with the position of every
+    /// chart item processed; an error from `step` stops the parse
+    pub(crate) fn parse_reporting(
+        &self,
+        input: &str,
+        step: Option<&mut dyn FnMut(usize) -> Result<(), ParseError>>,
+    ) -> Result<(XmlNode, bool), ParseError> {
+        let chars: Vec<char> = input.chars().collect();
+        let chart = self.recognize(&chars, step)?;
+
+        let mut forest = Forest {
+            grammar: self,
+            chart: &chart,
+            input: &chars,
+            stack: HashSet::new(),
+            ambiguous: false,
+        };
+        let content = forest
+            .build_nonterminal(self.start, 0, chars.len())
+            .ok_or_else(|| ParseError::Custom {
+                message: "Input only matches through an infinitely ambiguous derivation".into(),
+                position: 0,
+            })?;
+        let nodes = self.wrap(self.start, Mark::None, content);
+
+        let mut elements = nodes
+            .into_iter()
+            .filter(|node| !matches!(node, XmlNode::Text(t) if t.is_empty()));
+        match (elements.next(), elements.next()) {
+            (Some(root @ XmlNode::Element { .. }), None) => Ok((root, forest.ambiguous)),
+            _ => Err(ParseError::Custom {
+                message: "Parse succeeded but did not produce a single root element".into(),
+                position: 0,
+            }),
+        }
+    }
+
+    /// Run the Earley recognizer and return the completed chart
+    fn recognize(
+        &self,
+        input: &[char],
+        mut step: Option<&mut dyn FnMut(usize) -> Result<(), ParseError>>,
+    ) -> Result<Chart, ParseError> {
+        let mut chart = Chart {
+            sets: vec![Vec::new(); input.len() + 1],
+            index: vec![HashSet::new(); input.len() + 1],
+            completed: HashSet::new(),
+            origins: HashMap::new(),
+        };
+        for &production in &self.by_lhs[self.start] {
+            chart.add(0, Item::new(production, 0));
+        }
+
+        for pos in 0..=input.len() {
+            let mut next = 0;
+            while next < chart.sets[pos].len() {
+                let item = chart.sets[pos][next];
+                next += 1;
+                if let Some(step) = &mut step {
+                    step(pos)?;
+                }
+                let production = &self.productions[item.production];
+
+                match production.rhs.get(item.dot) {
+                    None => {
+                        // Complete: advance every item in the origin set waiting on lhs
+                        if chart.completed.insert((production.lhs, item.origin, pos)) {
+                            chart
+                                .origins
+                                .entry((production.lhs, pos))
+                                .or_default()
+                                .push(item.origin);
+                        }
+                        let waiting: Vec<Item> = chart.sets[item.origin]
+                            .iter()
+                            .filter(|candidate| {
+                                matches!(
+                                    self.productions[candidate.production].rhs.get(candidate.dot),
+                                    Some(Symbol::Nonterminal { id, .. }) if *id == production.lhs
+                                )
+                            })
+                            .copied()
+                            .collect();
+                        for candidate in waiting {
+                            chart.add(pos, candidate.advance());
+                        }
+                    }
+                    Some(Symbol::Nonterminal { id, .. }) => {
+                        // Predict, skipping over nullable nonterminals (Aycock & Horspool)
+                        for &predicted in &self.by_lhs[*id] {
+                            chart.add(pos, Item::new(predicted, pos));
+                        }
+                        if self.nullable[*id] {
+                            chart.add(pos, item.advance());
+                        }
+                    }
+                    Some(Symbol::Terminal { term, .. }) => {
+                        if let Some(len) = term.match_at(input, pos) {
+                            chart.add(pos + len, item.advance());
+                        }
+                    }
+                }
+            }
+        }
+
+        if chart.completed.contains(&(self.start, 0, input.len())) {
+            return Ok(chart);
+        }
+
+        // Report the furthest position the recognizer reached
+        let furthest = (0..=input.len())
+            .rev()
+            .find(|&pos| !chart.sets[pos].is_empty())
+            .unwrap_or(0);
+        Err(match input.get(furthest) {
+            Some(ch) => ParseError::Custom {
+                message: format!("No parse: unexpected '{}'", ch),
+                position: furthest,
+            },
+            None => ParseError::UnexpectedEof {
+                position: furthest,
+                expected: "more input".to_string(),
+            },
+        })
+    }
+
+    /// Turn the content of a nonterminal into output nodes according to its mark
+    fn wrap(&self, id: usize, use_mark: Mark, content: Vec<XmlNode>) -> Vec<XmlNode> {
+        let (name, rule_mark) = match &self.kinds[id] {
+            NonterminalKind::Rule { name, mark } => (name, *mark),
+            NonterminalKind::Auxiliary => return content,
+        };
+        let mark = if use_mark == Mark::None {
+            rule_mark
+        } else {
+            use_mark
+        };
+
+        match mark {
+            Mark::Hidden => content,
+            Mark::Attribute => {
+                // The value is the text of every terminal beneath, including nested attributes
+                let value = content.iter().map(XmlNode::text_content).collect();
+                vec![XmlNode::Attribute {
+                    name: name.clone(),
+                    value,
+                }]
+            }
+            Mark::None | Mark::Promoted => {
+                let mut attributes = Vec::new();
+                let mut children = Vec::new();
+                for node in content {
+                    match node.into_parts() {
+                        XmlParts::Attribute { name, value } => attributes.push((name, value)),
+                        other => children.push(XmlNode::from(other)),
+                    }
+                }
+                vec![XmlNode::Element {
+                    name: name.clone(),
+                    attributes,
+                    children: merge_text(children),
+                }]
+            }
+        }
+    }
+}
+
+/// Builds the lowered grammar, allocating auxiliary nonterminals on demand
+struct Builder {
+    ids: HashMap<String, usize>,
+    kinds: Vec<NonterminalKind>,
+    productions: Vec<Production>,
+    /// First construct that cannot be expressed as a context-free production
+    unsupported: Option<String>,
+}
+
+impl Builder {
+    fn rule_id(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        // Undefined rules get an id with no productions, so they never match
+        let id = self.kinds.len();
+        self.kinds.push(NonterminalKind::Rule {
+            name: name.to_string(),
+            mark: Mark::None,
+        });
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    fn auxiliary(&mut self) -> usize {
+        self.kinds.push(NonterminalKind::Auxiliary);
+        self.kinds.len() - 1
+    }
+
+    fn add(&mut self, lhs: usize, rhs: Vec<Symbol>) {
+        self.productions.push(Production { lhs, rhs });
+    }
+
+    fn add_alternatives(&mut self, lhs: usize, alternatives: &Alternatives) {
+        for sequence in &alternatives.alts {
+            let rhs = self.lower_sequence(sequence);
+            self.add(lhs, rhs);
+        }
+    }
+
+    fn lower_sequence(&mut self, sequence: &Sequence) -> Vec<Symbol> {
+        sequence
+            .factors
+            .iter()
+            .map(|factor| self.lower_factor(factor))
+            .collect()
+    }
+
+    fn lower_factor(&mut self, factor: &Factor) -> Symbol {
+        let item = self.lower_base(&factor.base);
+        let aux = |builder: &mut Builder| Symbol::Nonterminal {
+            id: builder.auxiliary(),
+            mark: Mark::None,
+        };
+        let id_of = |symbol: &Symbol| match symbol {
+            Symbol::Nonterminal { id, .. } => *id,
+            Symbol::Terminal { .. } => unreachable!("auxiliary symbols are nonterminals"),
+        };
+
+        // Repetitions are left-recursive, which the Earley recognizer handles in linear time
+        match &factor.repetition {
+            Repetition::None => item,
+            Repetition::Optional => {
+                let rep = aux(self);
+                self.add(id_of(&rep), vec![]);
+                self.add(id_of(&rep), vec![item]);
+                rep
+            }
+            Repetition::ZeroOrMore => {
+                let rep = aux(self);
+                self.add(id_of(&rep), vec![]);
+                self.add(id_of(&rep), vec![rep.clone(), item]);
+                rep
+            }
+            Repetition::OneOrMore => {
+                let rep = aux(self);
+                self.add(id_of(&rep), vec![item.clone()]);
+                self.add(id_of(&rep), vec![rep.clone(), item]);
+                rep
+            }
+            Repetition::SeparatedOneOrMore(sep) => self.separated(item, sep),
+            Repetition::SeparatedZeroOrMore(sep) => {
+                let plus = self.separated(item, sep);
+                let rep = aux(self);
+                self.add(id_of(&rep), vec![]);
+                self.add(id_of(&rep), vec![plus]);
+                rep
+            }
+        }
+    }
+
+    /// `item ++ sep` as `R: item | R, sep, item`
+    fn separated(&mut self, item: Symbol, sep: &Sequence) -> Symbol {
+        let id = self.auxiliary();
+        let rep = Symbol::Nonterminal {
+            id,
+            mark: Mark::None,
+        };
+        let mut recursive = vec![rep.clone()];
+        recursive.extend(self.lower_sequence(sep));
+        recursive.push(item.clone());
+        self.add(id, vec![item]);
+        self.add(id, recursive);
+        rep
+    }
+
+    fn lower_base(&mut self, base: &BaseFactor) -> Symbol {
+        match base {
+            BaseFactor::Literal {
+                value,
+                insertion,
+                mark,
+            } => Symbol::Terminal {
+                term: if *insertion {
+                    Terminal::Insert(value.clone())
+                } else {
+                    Terminal::Literal(value.chars().collect())
+                },
+                mark: *mark,
+            },
+            BaseFactor::CharClass {
+                content,
+                negated,
+                mark,
+            } => Symbol::Terminal {
+                term: Terminal::Class {
+                    set: charclass_to_rangeset(content),
+                    negated: *negated,
+                },
+                mark: *mark,
+            },
+            BaseFactor::Nonterminal { name, mark } => Symbol::Nonterminal {
+                id: self.rule_id(name),
+                mark: *mark,
+            },
+            BaseFactor::Group { alternatives } => {
+                let id = self.auxiliary();
+                self.add_alternatives(id, alternatives);
+                Symbol::Nonterminal {
+                    id,
+                    mark: Mark::None,
+                }
+            }
+            BaseFactor::NegativeLookahead { base } => {
+                // Lookahead over a terminal is a zero-width check on the input;
+                // over anything else it is not context-free
+                let inner = match self.lower_base(base) {
+                    Symbol::Terminal { term, .. } => term,
+                    Symbol::Nonterminal { .. } => {
+                        self.unsupported.get_or_insert_with(|| {
+                            "Negative lookahead is only supported on literals and \
+                             character classes by the Earley strategy"
+                                .to_string()
+                        });
+                        Terminal::Literal(vec![])
+                    }
+                };
+                Symbol::Terminal {
+                    term: Terminal::NotFollowedBy(Box::new(inner)),
+                    mark: Mark::Hidden,
+                }
+            }
+        }
+    }
+}
+
+/// Dotted production with the input position where it was predicted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    production: usize,
+    dot: usize,
+    origin: usize,
+}
+
+impl Item {
+    fn new(production: usize, origin: usize) -> Self {
+        Item {
+            production,
+            dot: 0,
+            origin,
+        }
+    }
+
+    fn advance(self) -> Self {
+        Item {
+            dot: self.dot + 1,
+            ..self
+        }
+    }
+}
+
+/// Earley sets for every input position
+struct Chart {
+    sets: Vec<Vec<Item>>,
+    index: Vec<HashSet<Item>>,
+    /// (nonterminal, start, end) spans that were completed
+    completed: HashSet<(usize, usize, usize)>,
+    /// Start positions of the completed spans of each (nonterminal, end)
+    origins: HashMap<(usize, usize), Vec<usize>>,
+}
+
+impl Chart {
+    fn add(&mut self, pos: usize, item: Item) {
+        if self.index[pos].insert(item) {
+            self.sets[pos].push(item);
+        }
+    }
+}
+
+/// Walks the shared packed parse forest implied by the chart.
+///
+/// Forest nodes are never materialized: a symbol node `(A, i, j)` has one
+/// packed family per production of `A` completed over `i..j`, and an
+/// intermediate node `(p, k, i, j)` has one family per split point `m` where
+/// the first `k - 1` symbols span `i..m` and symbol `k - 1` spans `m..j`.
+/// Both are read directly off the Earley sets.
+struct Forest<'a> {
+    grammar: &'a EarleyGrammar,
+    chart: &'a Chart,
+    input: &'a [char],
+    /// Symbol nodes currently being built (cycle detection)
+    stack: HashSet<(usize, usize, usize)>,
+    ambiguous: bool,
+}
+
+impl Forest<'_> {
+    /// Packed families of symbol node `(id, start, end)`: the completed productions
+    fn symbol_families(&self, id: usize, start: usize, end: usize) -> Vec<usize> {
+        self.grammar.by_lhs[id]
+            .iter()
+            .copied()
+            .filter(|&production| {
+                let len = self.grammar.productions[production].rhs.len();
+                self.chart.index[end].contains(&Item {
+                    production,
+                    dot: len,
+                    origin: start,
+                })
+            })
+            .collect()
+    }
+
+    /// Packed families of intermediate node `(production, dot, start, end)`: the split points
+    fn split_points(&self, production: usize, dot: usize, start: usize, end: usize) -> Vec<usize> {
+        let symbol = &self.grammar.productions[production].rhs[dot - 1];
+        let before = Item {
+            production,
+            dot: dot - 1,
+            origin: start,
+        };
+        // Only look at positions where the last symbol can start, not all of start..=end
+        let mut mids: Vec<usize> = match symbol {
+            Symbol::Nonterminal { id, .. } => self
+                .chart
+                .origins
+                .get(&(*id, end))
+                .into_iter()
+                .flatten()
+                .copied()
+                .filter(|&mid| mid >= start)
+                .collect(),
+            Symbol::Terminal { term, .. } => (start..=end)
+                .rev()
+                .take(term.max_len() + 1)
+                .filter(|&mid| term.match_at(self.input, mid) == Some(end - mid))
+                .collect(),
+        };
+        mids.retain(|&mid| self.chart.index[mid].contains(&before));
+        mids.sort_unstable();
+        mids
+    }
+
+    /// Build the content of nonterminal `id` over `start..end`
+    ///
+    /// Forest nodes still being built wait as [`BuildFrame`]s on a
+    /// heap-allocated stack rather than as native calls, so deeply nested
+    /// parses are limited by memory, not by the thread's stack size.
+    fn build_nonterminal(&mut self, id: usize, start: usize, end: usize) -> Option<Vec<XmlNode>> {
+        let mut frames = Vec::new();
+        let mut step = BuildStep::Call(ForestNode::Symbol { id, start, end });
+        loop {
+            step = match step {
+                BuildStep::Call(node) => self.call(node, &mut frames),
+                BuildStep::Return(result) => match frames.pop() {
+                    Some(frame) => self.resume(frame, result, &mut frames),
+                    None => return result,
+                },
+            };
+        }
+    }
+
+    /// Start building `node`
+    fn call(&mut self, node: ForestNode, frames: &mut Vec<BuildFrame>) -> BuildStep {
+        match node {
+            ForestNode::Symbol { id, start, end } => {
+                let key = (id, start, end);
+                if !self.stack.insert(key) {
+                    // A cycle means infinitely many trees
+                    self.ambiguous = true;
+                    return BuildStep::Return(None);
+                }
+                let families = self.symbol_families(id, start, end);
+                if families.len() > 1 {
+                    self.ambiguous = true;
+                }
+                self.next_family(key, families, 0, frames)
+            }
+            ForestNode::Intermediate {
+                production,
+                dot,
+                start,
+                end,
+            } => {
+                if dot == 0 {
+                    return BuildStep::Return((start == end).then(Vec::new));
+                }
+                let splits = self.split_points(production, dot, start, end);
+                if splits.len() > 1 {
+                    self.ambiguous = true;
+                }
+                self.next_split(
+                    SplitFrame {
+                        production,
+                        dot,
+                        start,
+                        end,
+                        splits,
+                        next: 0,
+                        prefix: None,
+                    },
+                    frames,
+                )
+            }
+        }
+    }
+
+    /// Deliver a built node to the frame waiting for it
+    fn resume(
+        &mut self,
+        frame: BuildFrame,
+        result: Option<Vec<XmlNode>>,
+        frames: &mut Vec<BuildFrame>,
+    ) -> BuildStep {
+        match frame {
+            BuildFrame::Symbol {
+                key,
+                families,
+                next,
+            } => match result {
+                Some(content) => {
+                    self.stack.remove(&key);
+                    BuildStep::Return(Some(content))
+                }
+                None => self.next_family(key, families, next, frames),
+            },
+            BuildFrame::Split(mut split) => {
+                let Some(content) = result else {
+                    return self.next_split(split, frames);
+                };
+                let symbol = &self.grammar.productions[split.production].rhs[split.dot - 1];
+                match (split.prefix.take(), symbol) {
+                    // The last symbol's content came back: append it to the prefix
+                    (Some(mut prefix), &Symbol::Nonterminal { id, mark }) => {
+                        prefix.extend(self.grammar.wrap(id, mark, content));
+                        BuildStep::Return(Some(prefix))
+                    }
+                    // The prefix came back: build the last symbol after it
+                    (None, &Symbol::Nonterminal { id, .. }) => {
+                        let mid = split.splits[split.next - 1];
+                        let end = split.end;
+                        split.prefix = Some(content);
+                        frames.push(BuildFrame::Split(split));
+                        BuildStep::Call(ForestNode::Symbol {
+                            id,
+                            start: mid,
+                            end,
+                        })
+                    }
+                    (_, Symbol::Terminal { term, mark }) => {
+                        let mid = split.splits[split.next - 1];
+                        let mut content = content;
+                        content.extend(self.terminal(term, *mark, mid, split.end));
+                        BuildStep::Return(Some(content))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Try the next packed family of symbol node `key`
+    fn next_family(
+        &mut self,
+        key: (usize, usize, usize),
+        families: Vec<usize>,
+        next: usize,
+        frames: &mut Vec<BuildFrame>,
+    ) -> BuildStep {
+        let Some(&production) = families.get(next) else {
+            self.stack.remove(&key);
+            return BuildStep::Return(None);
+        };
+        let (_, start, end) = key;
+        let dot = self.grammar.productions[production].rhs.len();
+        frames.push(BuildFrame::Symbol {
+            key,
+            families,
+            next: next + 1,
+        });
+        BuildStep::Call(ForestNode::Intermediate {
+            production,
+            dot,
+            start,
+            end,
+        })
+    }
+
+    /// Try the next split point of an intermediate node, building the prefix
+    /// before it first
+    fn next_split(&mut self, mut split: SplitFrame, frames: &mut Vec<BuildFrame>) -> BuildStep {
+        let Some(&mid) = split.splits.get(split.next) else {
+            return BuildStep::Return(None);
+        };
+        split.next += 1;
+        split.prefix = None;
+        let node = ForestNode::Intermediate {
+            production: split.production,
+            dot: split.dot - 1,
+            start: split.start,
+            end: mid,
+        };
+        frames.push(BuildFrame::Split(split));
+        BuildStep::Call(node)
+    }
+
+    /// The output of terminal `term` matched over `start..end`
+    fn terminal(&self, term: &Terminal, mark: Mark, start: usize, end: usize) -> Vec<XmlNode> {
+        if mark == Mark::Hidden {
+            return vec![];
+        }
+        let text = match term {
+            Terminal::Insert(text) => text.clone(),
+            _ => self.input[start..end].iter().collect(),
+        };
+        vec![XmlNode::Text(text)]
+    }
+}
+
+/// A forest node to build
+enum ForestNode {
+    /// Nonterminal `id` over `start..end`
+    Symbol { id: usize, start: usize, end: usize },
+    /// The first `dot` symbols of `production` over `start..end`
+    Intermediate {
+        production: usize,
+        dot: usize,
+        start: usize,
+        end: usize,
+    },
+}
+
+enum BuildStep {
+    Call(ForestNode),
+    Return(Option<Vec<XmlNode>>),
+}
+
+/// A forest node waiting on one of its parts
+enum BuildFrame {
+    /// A symbol node trying `families` in turn; `next` is the one after the
+    /// family being built
+    Symbol {
+        key: (usize, usize, usize),
+        families: Vec<usize>,
+        next: usize,
+    },
+    Split(SplitFrame),
+}
+
+/// An intermediate node trying its split points in turn
+struct SplitFrame {
+    production: usize,
+    dot: usize,
+    start: usize,
+    end: usize,
+    splits: Vec<usize>,
+    /// Index after the split point being built
+    next: usize,
+    /// Content of the symbols before the split point, once built
+    prefix: Option<Vec<XmlNode>>,
+}
+
+/// Merge adjacent text nodes
+fn merge_text(nodes: Vec<XmlNode>) -> Vec<XmlNode> {
+    let mut merged: Vec<XmlNode> = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        match (merged.last_mut(), &node) {
+            (_, XmlNode::Text(text)) if text.is_empty() => {}
+            (Some(XmlNode::Text(previous)), XmlNode::Text(text)) => previous.push_str(text),
+            _ => merged.push(node),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar_ast::parse_ixml_grammar;
+
+    fn parse(grammar: &str, input: &str) -> Result<(String, bool), ParseError> {
+        let ast = parse_ixml_grammar(grammar).expect("Grammar should parse");
+        let start = ast.rules[0].name.clone();
+        let lowered = EarleyGrammar::from_grammar(&ast, &start).expect("Grammar should lower");
+        lowered
+            .parse(input)
+            .map(|(node, ambiguous)| (node.to_xml(), ambiguous))
+    }
+
+    #[test]
+    fn test_unambiguous_parse() {
+        let (xml, ambiguous) = parse(
+            r#"date: year, -"-", month. year: d, d. month: d, d. -d: ["0"-"9"]."#,
+            "24-03",
+        )
+        .unwrap();
+        assert_eq!(xml, "<date><year>24</year><month>03</month></date>");
+        assert!(!ambiguous);
+    }
+
+    #[test]
+    fn test_ambiguous_parse() {
+        // Either `a` can absorb the middle character
+        let (_, ambiguous) = parse(r#"s: a, a. a: "x"; "x", "x"."#, "xxx").unwrap();
+        assert!(ambiguous);
+    }
+
+    #[test]
+    fn test_repetition_and_marks() {
+        let (xml, ambiguous) =
+            parse(r#"list: item++-",". item: @id. id: ["a"-"z"]+."#, "ab,c").unwrap();
+        assert_eq!(xml, "<list><item id='ab'/><item id='c'/></list>");
+        assert!(!ambiguous);
+    }
+
+    #[test]
+    fn test_left_recursion() {
+        let (xml, _) = parse(r#"e: e, "+", n; n. n: ["0"-"9"]."#, "1+2").unwrap();
+        assert_eq!(xml, "<e><e><n>1</n></e>+<n>2</n></e>");
+    }
+
+    #[test]
+    fn test_no_parse() {
+        let err = parse(r#"s: "ab"."#, "ax").unwrap_err();
+        assert_eq!(err.position(), 0);
+    }
+}
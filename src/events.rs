@@ -0,0 +1,233 @@
+//! Event-based access to parse results
+//!
+//! [`NativeParser::parse_events`](crate::NativeParser::parse_events) reports
+//! a result as a stream of start tag, attribute, text and end tag events, in
+//! document order, to a [`ParseHandler`]. The events are read straight off
+//! the parser's [`XmlTree`](crate::xml_tree::XmlTree), so consumers that fold
+//! the result into their own structures skip building an
+//! [`XmlNode`](crate::XmlNode) copy of it.
+//!
+//! The engines still build the tree while parsing, since a match is not final
+//! until the whole input has been parsed, so peak memory is that of the tree.
+
+use crate::xml_node::{FixedAttributes, NamespaceScope};
+use crate::xml_tree::XmlNodeRef;
+
+/// Receives the events of a parse result
+///
+/// Every method does nothing by default, so handlers implement only the
+/// events they need. Attributes follow their element's start, before any of
+/// its content, and match the serialized output (including fixed attributes
+/// and `ixml:state`).
+///
+/// ```
+/// use rustixml::events::ParseHandler;
+/// use rustixml::fixtures::CSV;
+///
+/// #[derive(Default)]
+/// struct Fields(Vec<String>, bool);
+///
+/// impl ParseHandler for Fields {
+///     fn start_element(&mut self, name: &str) {
+///         self.1 = name == "field";
+///     }
+///     fn text(&mut self, text: &str) {
+///         if self.1 {
+///             self.0.push(text.to_string());
+///         }
+///     }
+/// }
+///
+/// let mut fields = Fields::default();
+/// CSV.parser().parse_events("a,b\nc,d", &mut fields).unwrap();
+/// assert_eq!(fields.0, ["a", "b", "c", "d"]);
+/// ```
+pub trait ParseHandler {
+    fn start_element(&mut self, _name: &str) {}
+
+    fn attribute(&mut self, _name: &str, _value: &str) {}
+
+    fn text(&mut self, _text: &str) {}
+
+    fn end_element(&mut self, _name: &str) {}
+}
+
+/// Send the events of `node` and its descendants to `handler`
+///
+/// `fixed` attributes and namespaces are reported as if they were the
+/// elements' own.
+pub fn emit(node: XmlNodeRef<'_>, fixed: &FixedAttributes, handler: &mut impl ParseHandler) {
+    enum Item<'a> {
+        Open(XmlNodeRef<'a>, usize),
+        Close(String),
+    }
+
+    let mut scope = NamespaceScope::default();
+    let mut stack = vec![Item::Open(node, 0)];
+    while let Some(item) = stack.pop() {
+        let (node, depth) = match item {
+            Item::Open(node, depth) => (node, depth),
+            Item::Close(name) => {
+                handler.end_element(&name);
+                continue;
+            }
+        };
+        match node.name() {
+            Some(name) if node.is_element() => {
+                let attributes: Vec<(String, String)> = node
+                    .attributes()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect();
+                let (name, attributes) = scope.element(depth, name, &attributes, fixed, depth == 0);
+                handler.start_element(&name);
+                for (k, v) in attributes {
+                    handler.attribute(&k, v);
+                }
+                stack.push(Item::Close(name.into_owned()));
+                let first = stack.len();
+                stack.extend(node.children().map(|child| Item::Open(child, depth + 1)));
+                stack[first..].reverse();
+            }
+            // Attributes are reported with their element
+            Some(_) => {}
+            None => handler.text(node.text().unwrap_or_default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xml_node::XmlNode;
+    use crate::xml_tree::XmlTree;
+    use crate::{parse_ixml_grammar, NativeParser, ParseStrategy};
+
+    /// Rebuilds the serialized XML from events
+    #[derive(Default)]
+    struct Writer {
+        xml: String,
+        open: bool,
+    }
+
+    impl Writer {
+        fn close_start_tag(&mut self) {
+            if self.open {
+                self.xml.push('>');
+                self.open = false;
+            }
+        }
+    }
+
+    impl ParseHandler for Writer {
+        fn start_element(&mut self, name: &str) {
+            self.close_start_tag();
+            self.xml.push_str(&format!("<{}", name));
+            self.open = true;
+        }
+        fn attribute(&mut self, name: &str, value: &str) {
+            self.xml.push_str(&format!(" {}='{}'", name, value));
+        }
+        fn text(&mut self, text: &str) {
+            self.close_start_tag();
+            self.xml.push_str(text);
+        }
+        fn end_element(&mut self, name: &str) {
+            if self.open {
+                self.xml.push_str("/>");
+                self.open = false;
+            } else {
+                self.xml.push_str(&format!("</{}>", name));
+            }
+        }
+    }
+
+    #[test]
+    fn test_events_match_serialization() {
+        for fixture in crate::fixtures::FIXTURES {
+            let parser = fixture.parser();
+            let mut writer = Writer::default();
+            parser.parse_events(fixture.input, &mut writer).unwrap();
+            assert_eq!(writer.xml, fixture.expected, "{}", fixture.name);
+        }
+
+        let fixed = FixedAttributes::new().on_root("version", "2");
+        let tree = XmlNode::element("a")
+            .with_attribute("ixml:state", "ambiguous")
+            .with_child(XmlNode::element("b"));
+        let mut writer = Writer::default();
+        emit(XmlTree::from(&tree).root().unwrap(), &fixed, &mut writer);
+        assert_eq!(writer.xml, tree.to_xml_with(&fixed));
+    }
+
+    /// Records every event
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl ParseHandler for Recorder {
+        fn start_element(&mut self, name: &str) {
+            self.0.push(format!("start {}", name));
+        }
+        fn attribute(&mut self, name: &str, value: &str) {
+            self.0.push(format!("attribute {}={}", name, value));
+        }
+        fn text(&mut self, text: &str) {
+            self.0.push(format!("text {}", text));
+        }
+        fn end_element(&mut self, name: &str) {
+            self.0.push(format!("end {}", name));
+        }
+    }
+
+    #[test]
+    fn test_events_of_marked_and_ambiguous_results() {
+        // `id` is an attribute, `-":"` hidden, `^word` promoted inside a
+        // hidden rule, and "x" parses both as `a` and as `b`
+        let grammar = parse_ixml_grammar(
+            r#"doc: @id, -":", -part. id: ["0"-"9"]. -part: ^word; a; b.
+               word: "yz". a: "x". b: "x"."#,
+        )
+        .unwrap();
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+
+            let mut events = Recorder::default();
+            parser.parse_events("1:yz", &mut events).unwrap();
+            assert_eq!(
+                events.0,
+                [
+                    "start doc",
+                    "attribute id=1",
+                    "start word",
+                    "text yz",
+                    "end word",
+                    "end doc",
+                ],
+                "{:?}",
+                strategy
+            );
+
+            let mut events = Recorder::default();
+            parser.parse_events("2:x", &mut events).unwrap();
+            assert_eq!(
+                events.0[..3],
+                [
+                    "start doc",
+                    "attribute id=2",
+                    "attribute ixml:state=ambiguous"
+                ]
+            );
+            assert_eq!(
+                events.0[3..6],
+                [
+                    "attribute xmlns:ixml=http://invisiblexml.org/NS",
+                    "start a",
+                    "text x"
+                ]
+            );
+
+            let mut writer = Writer::default();
+            parser.parse_events("2:x", &mut writer).unwrap();
+            assert_eq!(writer.xml, parser.parse("2:x").unwrap(), "{:?}", strategy);
+        }
+    }
+}
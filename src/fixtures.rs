@@ -0,0 +1,134 @@
+//! Small embedded grammar set used by the documentation examples
+//!
+//! Each [`Fixture`] pairs a grammar with a sample input and the XML the parser
+//! produces for it. The rustdoc examples throughout the crate draw on these,
+//! and the unit tests below check every expected output, so the fixtures
+//! double as a miniature regression suite.
+//!
+//! ```
+//! use rustixml::fixtures;
+//!
+//! for fixture in fixtures::FIXTURES {
+//!     assert_eq!(fixture.parser().parse(fixture.input).as_deref(), Ok(fixture.expected));
+//! }
+//! ```
+
+use crate::ast::IxmlGrammar;
+use crate::grammar_ast::parse_ixml_grammar;
+use crate::native_parser::NativeParser;
+
+/// A grammar with a sample input and its expected output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixture {
+    /// Short identifier, e.g. `"csv"`
+    pub name: &'static str,
+    /// Grammar source in iXML notation
+    pub grammar: &'static str,
+    /// Sample input the grammar accepts
+    pub input: &'static str,
+    /// Serialized parse of `input`
+    pub expected: &'static str,
+}
+
+/// Greeting with a name, the crate's quick-start grammar
+pub const GREETING: Fixture = Fixture {
+    name: "greeting",
+    grammar: r#"greeting: "Hello, ", name, "!".
+name: ["A"-"Z"; "a"-"z"]+."#,
+    input: "Hello, World!",
+    expected: "<greeting>Hello, <name>World</name>!</greeting>",
+};
+
+/// Comma-separated rows with hidden separators
+pub const CSV: Fixture = Fixture {
+    name: "csv",
+    grammar: r#"csv: row++-#a.
+row: field++-",".
+field: ~[","; #a]*."#,
+    input: "a,b\nc,d",
+    expected: "<csv><row><field>a</field><field>b</field></row>\
+               <row><field>c</field><field>d</field></row></csv>",
+};
+
+/// ISO date with each part serialized as an attribute
+pub const DATE: Fixture = Fixture {
+    name: "date",
+    grammar: r#"date: year, -"-", month, -"-", day.
+@year: d, d, d, d.
+@month: d, d.
+@day: d, d.
+-d: ["0"-"9"]."#,
+    input: "2024-03-15",
+    expected: "<date year='2024' month='03' day='15'/>",
+};
+
+/// Arithmetic with nesting through parentheses
+pub const EXPRESSION: Fixture = Fixture {
+    name: "expression",
+    grammar: r#"expr: term++-"+".
+term: factor++-"*".
+factor: ["0"-"9"]+; -"(", expr, -")"."#,
+    input: "2*(3+4)",
+    expected: "<expr><term><factor>2</factor><factor><expr><term><factor>3</factor></term>\
+               <term><factor>4</factor></term></expr></factor></term></expr>",
+};
+
+/// Two alternatives that match the same text, flagged `ixml:state='ambiguous'`
+pub const AMBIGUOUS: Fixture = Fixture {
+    name: "ambiguous",
+    grammar: r#"s: a, ".".
+a: "x"; ["x"]."#,
+    input: "x.",
+    expected: "<s ixml:state='ambiguous' xmlns:ixml='http://invisiblexml.org/NS'><a>x</a>.</s>",
+};
+
+/// Every fixture
+pub const FIXTURES: &[Fixture] = &[GREETING, CSV, DATE, EXPRESSION, AMBIGUOUS];
+
+/// Look up a fixture by name
+///
+/// ```
+/// let csv = rustixml::fixtures::fixture("csv").unwrap();
+/// assert_eq!(csv.input, "a,b\nc,d");
+/// ```
+pub fn fixture(name: &str) -> Option<&'static Fixture> {
+    FIXTURES.iter().find(|fixture| fixture.name == name)
+}
+
+impl Fixture {
+    /// The fixture's grammar, parsed
+    ///
+    /// Fixture grammars are known to be valid, so this panics on failure.
+    pub fn grammar_ast(&self) -> IxmlGrammar {
+        parse_ixml_grammar(self.grammar)
+            .unwrap_or_else(|e| panic!("fixture '{}' grammar is invalid: {}", self.name, e))
+    }
+
+    /// A recursive descent parser for the fixture's grammar
+    pub fn parser(&self) -> NativeParser {
+        NativeParser::new(self.grammar_ast())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixtures_parse_to_expected() {
+        for fixture in FIXTURES {
+            assert_eq!(
+                fixture.parser().parse(fixture.input).as_deref(),
+                Ok(fixture.expected),
+                "fixture '{}'",
+                fixture.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_fixture_lookup() {
+        assert_eq!(fixture("date"), Some(&DATE));
+        assert_eq!(fixture("missing"), None);
+    }
+}
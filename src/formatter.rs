@@ -0,0 +1,335 @@
+//! Formatting grammars as tidy iXML source
+//!
+//! [`IxmlGrammar::to_ixml_source`] lays grammars out for people: long rules
+//! get one alternative per line, aligned after the rule name, strings use one
+//! kind of quote, and character classes are spaced consistently. `Display` on
+//! [`IxmlGrammar`] uses the default [`FormatOptions`]. Formatting a formatted
+//! grammar changes nothing.
+
+use crate::ast::{literal_source, Alternatives, BaseFactor, Factor, IxmlGrammar, Repetition, Rule};
+use crate::charclass::{split_charclass_content, unquote};
+
+/// How [`IxmlGrammar::to_ixml_source`] lays out a grammar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Rules longer than this many characters are split over several lines
+    pub max_width: usize,
+    /// `"` or `'`, for strings and quoted characters
+    pub quote: char,
+    /// Put a blank line between rules
+    pub blank_lines: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            max_width: 80,
+            quote: '"',
+            blank_lines: false,
+        }
+    }
+}
+
+impl FormatOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_width(mut self, max_width: usize) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    /// Quote strings with `quote`, which must be `"` or `'`
+    pub fn with_quote(mut self, quote: char) -> Self {
+        self.quote = if quote == '\'' { '\'' } else { '"' };
+        self
+    }
+
+    pub fn with_blank_lines(mut self, blank_lines: bool) -> Self {
+        self.blank_lines = blank_lines;
+        self
+    }
+}
+
+impl IxmlGrammar {
+    /// The grammar as formatted iXML source
+    ///
+    /// ```
+    /// use rustixml::formatter::FormatOptions;
+    /// use rustixml::parse_ixml_grammar;
+    ///
+    /// let grammar = parse_ixml_grammar(
+    ///     "date: day,'-',month ;month,' ',day. day:['0'-'9' ;'a' - 'f']+. month:'Jan';'Feb'.",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(
+    ///     grammar.to_ixml_source(&FormatOptions::new().with_max_width(24)),
+    ///     "date: day, \"-\", month;\n      month, \" \", day.\n\
+    ///      day: [\"0\"-\"9\"; \"a\"-\"f\"]+.\n\
+    ///      month: \"Jan\"; \"Feb\".\n"
+    /// );
+    /// ```
+    pub fn to_ixml_source(&self, options: &FormatOptions) -> String {
+        let mut out = String::new();
+        if let Some(version) = &self.version {
+            let quote = options.quote;
+            let version = version.replace(quote, &format!("{q}{q}", q = quote));
+            out.push_str(&format!("ixml version {q}{}{q}.\n\n", version, q = quote));
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            if i > 0 && options.blank_lines {
+                out.push('\n');
+            }
+            format_rule(rule, options, &mut out);
+        }
+        out
+    }
+}
+
+fn format_rule(rule: &Rule, options: &FormatOptions, out: &mut String) {
+    for pragma in &rule.pragmas {
+        out.push_str(&format!("{}\n", pragma));
+    }
+    let header = format!("{}{}: ", rule.mark, rule.name);
+    let alts: Vec<Vec<String>> = rule
+        .alternatives
+        .alts
+        .iter()
+        .map(|seq| seq.factors.iter().map(|f| factor(f, options)).collect())
+        .collect();
+
+    let one_line = format!(
+        "{}{}.",
+        header,
+        alts.iter()
+            .map(|factors| factors.join(", "))
+            .collect::<Vec<_>>()
+            .join("; ")
+    );
+    if width(&one_line) <= options.max_width {
+        out.push_str(&one_line);
+        out.push('\n');
+        return;
+    }
+
+    // One alternative per line, all starting where the first one does, and
+    // factors wrapped onto further lines when an alternative is too long
+    let indent = " ".repeat(width(&header));
+    let mut line = header;
+    for (i, factors) in alts.iter().enumerate() {
+        if i > 0 {
+            out.push_str(&line);
+            out.push('\n');
+            line = indent.clone();
+        }
+        let end = if i + 1 == alts.len() { '.' } else { ';' };
+        for (j, factor) in factors.iter().enumerate() {
+            let last = j + 1 == factors.len();
+            let starts_line = j == 0 || line.len() == indent.len();
+            if !starts_line && width(&line) + 1 + width(factor) + 1 > options.max_width {
+                out.push_str(line.trim_end());
+                out.push('\n');
+                line = indent.clone();
+            } else if !starts_line {
+                line.push(' ');
+            }
+            line.push_str(factor);
+            if !last {
+                line.push(',');
+            }
+        }
+        line.push(end);
+    }
+    out.push_str(&line);
+    out.push('\n');
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+fn alternatives(alts: &Alternatives, options: &FormatOptions) -> String {
+    alts.alts
+        .iter()
+        .map(|seq| {
+            seq.factors
+                .iter()
+                .map(|f| factor(f, options))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn factor(factor: &Factor, options: &FormatOptions) -> String {
+    let mut text = String::new();
+    for pragma in &factor.pragmas {
+        text.push_str(&format!("{} ", pragma));
+    }
+    text.push_str(&base(&factor.base, options));
+    match &factor.repetition {
+        Repetition::None => {}
+        Repetition::Optional => text.push('?'),
+        Repetition::ZeroOrMore => text.push('*'),
+        Repetition::OneOrMore => text.push('+'),
+        Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) => {
+            text.push_str(match &factor.repetition {
+                Repetition::SeparatedZeroOrMore(_) => "**",
+                _ => "++",
+            });
+            // A lone plain factor needs no brackets: `row++-#a`
+            match sep.factors.as_slice() {
+                [only] if only.pragmas.is_empty() && only.repetition == Repetition::None => {
+                    text.push_str(&self::factor(only, options))
+                }
+                factors => {
+                    let factors: Vec<String> =
+                        factors.iter().map(|f| self::factor(f, options)).collect();
+                    text.push_str(&format!("({})", factors.join(", ")));
+                }
+            }
+        }
+    }
+    text
+}
+
+fn base(base: &BaseFactor, options: &FormatOptions) -> String {
+    match base {
+        BaseFactor::Literal {
+            value,
+            insertion,
+            mark,
+        } => {
+            let prefix = if *insertion {
+                "+".to_string()
+            } else {
+                mark.to_string()
+            };
+            literal_source(&prefix, value, options.quote)
+        }
+        BaseFactor::Nonterminal { name, mark } => format!("{}{}", mark, name),
+        BaseFactor::CharClass {
+            content,
+            negated,
+            mark,
+        } => {
+            let members: Vec<String> = split_charclass_content(content)
+                .iter()
+                .map(|member| class_member(member, options.quote))
+                .collect();
+            format!(
+                "{}{}[{}]",
+                mark,
+                if *negated { "~" } else { "" },
+                members.join("; ")
+            )
+        }
+        BaseFactor::Group { alternatives } => {
+            format!("({})", self::alternatives(alternatives, options))
+        }
+        BaseFactor::NegativeLookahead { base } => format!("!{}", self::base(base, options)),
+    }
+}
+
+/// A character class member written with `quote` and no spaces around `-`
+fn class_member(member: &str, quote: char) -> String {
+    if let Some(excluded) = member.strip_prefix('~') {
+        return format!("~{}", class_member(excluded.trim(), quote));
+    }
+    // A range end is a quoted character or a hex character
+    let end = |text: &str| match unquote(text) {
+        Some((value, _)) => quoted(&value, quote),
+        None => text.to_string(),
+    };
+    if let Some((value, rest)) = unquote(member) {
+        return match rest.trim_start().strip_prefix('-') {
+            Some(to) => format!("{}-{}", quoted(&value, quote), end(to.trim())),
+            None => quoted(&value, quote),
+        };
+    }
+    if let Some(hex) = member.strip_prefix('#') {
+        if let Some((from, to)) = hex.split_once('-') {
+            return format!("#{}-{}", from.trim(), end(to.trim()));
+        }
+    }
+    member.to_string()
+}
+
+fn quoted(value: &str, quote: char) -> String {
+    format!(
+        "{q}{}{q}",
+        value.replace(quote, &format!("{q}{q}", q = quote)),
+        q = quote
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar_ast::parse_ixml_grammar;
+
+    #[test]
+    fn test_format_is_stable() {
+        let options = [
+            FormatOptions::new(),
+            FormatOptions::new()
+                .with_max_width(10)
+                .with_quote('\'')
+                .with_blank_lines(true),
+        ];
+        for fixture in crate::fixtures::FIXTURES {
+            let grammar = fixture.grammar_ast();
+            for options in &options {
+                let source = grammar.to_ixml_source(options);
+                let reparsed = parse_ixml_grammar(&source).unwrap();
+                assert_eq!(reparsed.to_ixml_source(options), source);
+                assert_eq!(
+                    crate::NativeParser::new(reparsed)
+                        .parse(fixture.input)
+                        .unwrap(),
+                    fixture.expected,
+                    "{}",
+                    source
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        let grammar = parse_ixml_grammar(
+            r#"ixml version "1.0". {[doc x]} -list: item++(",", " "?), -#a?; "empty" ; ^item ** -'"'. item: ~[ 'a'  -  "z"; #30 - #39; ~ "q" ; L], {[p]} +'it''s'."#,
+        )
+        .unwrap();
+        assert_eq!(
+            grammar.to_ixml_source(&FormatOptions::new().with_max_width(30)),
+            [
+                r#"ixml version "1.0"."#,
+                "",
+                "{[doc x]}",
+                r#"-list: item++(",", " "?),"#,
+                "       -#a?;",
+                r#"       "empty";"#,
+                r#"       ^item**-""""."#,
+                r#"item: ~["a"-"z"; #30-#39; ~"q"; L],"#,
+                r#"      {[p]} +"it's"."#,
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            grammar.to_ixml_source(&FormatOptions::new().with_quote('\'')),
+            [
+                "ixml version '1.0'.",
+                "",
+                "{[doc x]}",
+                r#"-list: item++(',', ' '?), -#a?; 'empty'; ^item**-'"'."#,
+                r#"item: ~['a'-'z'; #30-#39; ~'q'; L], {[p]} +'it''s'."#,
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,579 @@
+//! Sample inputs generated from a grammar
+//!
+//! [`Sentences`] works out the shortest input every rule accepts, and can
+//! stretch one repetition inside a rule to build inputs that exercise it,
+//! e.g. the reproduction inputs of
+//! [`BacktrackingHazard`](crate::grammar_analysis::BacktrackingHazard).
+//!
+//! [`generate`] builds random inputs instead, for testing grammars and
+//! fuzzing parsers.
+
+use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Repetition, Rule, Sequence};
+use crate::charclass::{charclass_to_rangeset, RangeSet};
+use std::collections::HashMap;
+
+/// How many rules deep to look for a non-empty match of a nullable element
+const NONEMPTY_DEPTH: usize = 8;
+
+/// Shortest accepted inputs of a grammar's rules
+///
+/// ```
+/// use rustixml::generator::Sentences;
+/// use rustixml::parse_ixml_grammar;
+///
+/// let grammar = parse_ixml_grammar(r#"list: item++",". item: ["a"-"z"]+; "0"."#).unwrap();
+/// let sentences = Sentences::new(&grammar);
+/// assert_eq!(sentences.shortest("list"), Some("a"));
+///
+/// let repetition = &grammar.rules[0].alternatives.alts[0].factors[0];
+/// assert_eq!(sentences.pumped("list", repetition, 3).as_deref(), Some("a,a,a"));
+/// ```
+pub struct Sentences<'g> {
+    rules: HashMap<&'g str, &'g Rule>,
+    shortest: HashMap<&'g str, String>,
+}
+
+impl<'g> Sentences<'g> {
+    /// Compute the shortest input of every rule
+    pub fn new(grammar: &'g IxmlGrammar) -> Self {
+        let mut sentences = Sentences {
+            rules: grammar.rules.iter().map(|r| (r.name.as_str(), r)).collect(),
+            shortest: HashMap::new(),
+        };
+
+        // Fixpoint: a rule's sentence can only get shorter as more rules resolve
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in &grammar.rules {
+                let Some(sentence) = sentences.alternatives(&rule.alternatives) else {
+                    continue;
+                };
+                let shorter = sentences
+                    .shortest
+                    .get(rule.name.as_str())
+                    .is_none_or(|current| sentence.chars().count() < current.chars().count());
+                if shorter {
+                    sentences.shortest.insert(rule.name.as_str(), sentence);
+                    changed = true;
+                }
+            }
+        }
+        sentences
+    }
+
+    /// The shortest input `rule` accepts; `None` if it accepts none (or is undefined)
+    pub fn shortest(&self, rule: &str) -> Option<&str> {
+        self.shortest.get(rule).map(String::as_str)
+    }
+
+    /// The shortest input of `rule` up to and including `target`, one of its
+    /// repeated factors, with the repetition matching `count` non-empty elements
+    ///
+    /// The text `rule` would need after the repetition is left off. Returns
+    /// `None` if `target` is not part of `rule`, or cannot match a non-empty
+    /// element.
+    pub fn pumped(&self, rule: &str, target: &Factor, count: usize) -> Option<String> {
+        let rule = self.rules.get(rule)?;
+        let mut out = String::new();
+        self.alternatives_towards(&rule.alternatives, target, count, &mut out)
+            .then_some(out)
+    }
+
+    fn alternatives(&self, alts: &Alternatives) -> Option<String> {
+        alts.alts
+            .iter()
+            .filter_map(|seq| self.sequence(seq))
+            .min_by_key(|sentence| sentence.chars().count())
+    }
+
+    fn sequence(&self, seq: &Sequence) -> Option<String> {
+        seq.factors
+            .iter()
+            .map(|factor| self.factor(factor))
+            .collect()
+    }
+
+    fn factor(&self, factor: &Factor) -> Option<String> {
+        match factor.repetition {
+            Repetition::Optional | Repetition::ZeroOrMore | Repetition::SeparatedZeroOrMore(_) => {
+                Some(String::new())
+            }
+            Repetition::None | Repetition::OneOrMore | Repetition::SeparatedOneOrMore(_) => {
+                self.base(&factor.base)
+            }
+        }
+    }
+
+    fn base(&self, base: &BaseFactor) -> Option<String> {
+        match base {
+            BaseFactor::Literal {
+                value, insertion, ..
+            } => Some(if *insertion {
+                String::new()
+            } else {
+                value.clone()
+            }),
+            BaseFactor::CharClass {
+                content, negated, ..
+            } => representative(content, *negated).map(String::from),
+            BaseFactor::Nonterminal { name, .. } => self.shortest(name).map(String::from),
+            BaseFactor::Group { alternatives } => self.alternatives(alternatives),
+            BaseFactor::NegativeLookahead { .. } => Some(String::new()),
+        }
+    }
+
+    /// The shortest non-empty input of `base`
+    fn nonempty_base(&self, base: &BaseFactor, depth: usize) -> Option<String> {
+        match base {
+            BaseFactor::Nonterminal { name, .. } => match self.shortest(name) {
+                Some(sentence) if !sentence.is_empty() => Some(sentence.to_string()),
+                _ if depth == 0 => None,
+                _ => self
+                    .nonempty_alternatives(&self.rules.get(name.as_str())?.alternatives, depth - 1),
+            },
+            BaseFactor::Group { alternatives } => self.nonempty_alternatives(alternatives, depth),
+            _ => self.base(base).filter(|sentence| !sentence.is_empty()),
+        }
+    }
+
+    fn nonempty_alternatives(&self, alts: &Alternatives, depth: usize) -> Option<String> {
+        alts.alts
+            .iter()
+            .filter_map(|seq| self.nonempty_sequence(seq, depth))
+            .min_by_key(|sentence| sentence.chars().count())
+    }
+
+    /// Make one factor non-empty and keep the others as short as possible
+    fn nonempty_sequence(&self, seq: &Sequence, depth: usize) -> Option<String> {
+        let shortest: Vec<Option<String>> = seq.factors.iter().map(|f| self.factor(f)).collect();
+        (0..seq.factors.len())
+            .filter_map(|i| {
+                let mut sentence = String::new();
+                for (j, factor) in seq.factors.iter().enumerate() {
+                    if i == j {
+                        sentence.push_str(&self.nonempty_base(&factor.base, depth)?);
+                    } else {
+                        sentence.push_str(shortest[j].as_deref()?);
+                    }
+                }
+                Some(sentence)
+            })
+            .min_by_key(|sentence| sentence.chars().count())
+    }
+
+    /// Append the input leading to `target` to `out`; returns whether it was reached
+    fn alternatives_towards(
+        &self,
+        alts: &Alternatives,
+        target: &Factor,
+        count: usize,
+        out: &mut String,
+    ) -> bool {
+        alts.alts
+            .iter()
+            .any(|seq| self.sequence_towards(seq, target, count, out))
+    }
+
+    fn sequence_towards(
+        &self,
+        seq: &Sequence,
+        target: &Factor,
+        count: usize,
+        out: &mut String,
+    ) -> bool {
+        let mut sentence = String::new();
+        for factor in &seq.factors {
+            if std::ptr::eq(factor, target) {
+                let Some(element) = self.nonempty_base(&factor.base, NONEMPTY_DEPTH) else {
+                    return false;
+                };
+                let separator = match &factor.repetition {
+                    Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) => {
+                        match self.sequence(sep) {
+                            Some(separator) => separator,
+                            None => return false,
+                        }
+                    }
+                    _ => String::new(),
+                };
+                sentence.push_str(&vec![element; count].join(&separator));
+                out.push_str(&sentence);
+                return true;
+            }
+            if let BaseFactor::Group { alternatives } = &factor.base {
+                let mut inner = String::new();
+                if self.alternatives_towards(alternatives, target, count, &mut inner) {
+                    out.push_str(&sentence);
+                    out.push_str(&inner);
+                    return true;
+                }
+            }
+            match self.factor(factor) {
+                Some(text) => sentence.push_str(&text),
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+/// How [`generate`] builds inputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenOptions {
+    /// Seed for the random choices; the same seed gives the same input
+    pub seed: u64,
+    /// Rule to generate from (the first rule if `None`)
+    pub start: Option<String>,
+    /// Rules nested deeper than this expand to their shortest input
+    pub max_depth: usize,
+    /// Most elements a `*`, `+`, `**` or `++` repetition generates
+    pub max_repeat: usize,
+    /// Relative weights of the alternatives of some rules
+    weights: HashMap<String, Vec<u32>>,
+}
+
+impl Default for GenOptions {
+    fn default() -> Self {
+        GenOptions {
+            seed: 0,
+            start: None,
+            max_depth: 12,
+            max_repeat: 3,
+            weights: HashMap::new(),
+        }
+    }
+}
+
+impl GenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_start(mut self, rule: impl Into<String>) -> Self {
+        self.start = Some(rule.into());
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_max_repeat(mut self, max_repeat: usize) -> Self {
+        self.max_repeat = max_repeat;
+        self
+    }
+
+    /// Pick the alternatives of `rule` in proportion to `weights`, in order
+    ///
+    /// Alternatives without a weight get 1; weight 0 rules one out unless no
+    /// other alternative can be used.
+    pub fn with_weights(mut self, rule: impl Into<String>, weights: impl Into<Vec<u32>>) -> Self {
+        self.weights.insert(rule.into(), weights.into());
+        self
+    }
+}
+
+/// A random input the grammar accepts
+///
+/// Alternatives and repetition counts are picked at random; below
+/// `max_depth` rules every rule takes its shortest input, so generation
+/// always ends. Negative lookahead is not taken into account, so grammars
+/// using it may reject some inputs.
+///
+/// ```
+/// use rustixml::generator::{generate, GenOptions};
+/// use rustixml::{parse_ixml_grammar, NativeParser};
+///
+/// let grammar = parse_ixml_grammar(r#"expr: term++"+". term: ["0"-"9"]+; "(", expr, ")"."#).unwrap();
+/// let parser = NativeParser::new(grammar.clone());
+/// for seed in 0..20 {
+///     let input = generate(&grammar, &GenOptions::new().with_seed(seed)).unwrap();
+///     assert!(parser.parse(&input).is_ok(), "{}", input);
+/// }
+/// ```
+pub fn generate(grammar: &IxmlGrammar, options: &GenOptions) -> Result<String, String> {
+    let start = match &options.start {
+        Some(start) => start.as_str(),
+        None => grammar
+            .rules
+            .first()
+            .map(|rule| rule.name.as_str())
+            .ok_or_else(|| "The grammar has no rules".to_string())?,
+    };
+    let sentences = Sentences::new(grammar);
+    if !sentences.rules.contains_key(start) {
+        return Err(format!("Undefined rule '{}'", start));
+    }
+    if sentences.shortest(start).is_none() {
+        return Err(format!("Rule '{}' accepts no input", start));
+    }
+    let mut generator = Generator {
+        sentences,
+        options,
+        rng: SplitMix64(options.seed),
+        out: String::new(),
+    };
+    generator.rule(start, 0);
+    Ok(generator.out)
+}
+
+/// SplitMix64, a small fast generator that is plenty for picking choices
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`
+    fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            0
+        } else {
+            self.next() % n
+        }
+    }
+
+    /// A number in `min..=max`
+    fn between(&mut self, min: usize, max: usize) -> usize {
+        min + self.below((max.max(min) - min) as u64 + 1) as usize
+    }
+}
+
+struct Generator<'g, 'o> {
+    sentences: Sentences<'g>,
+    options: &'o GenOptions,
+    rng: SplitMix64,
+    out: String,
+}
+
+impl Generator<'_, '_> {
+    fn rule(&mut self, name: &str, depth: usize) {
+        if depth >= self.options.max_depth {
+            if let Some(sentence) = self.sentences.shortest(name) {
+                self.out.push_str(sentence);
+            }
+            return;
+        }
+        if let Some(&rule) = self.sentences.rules.get(name) {
+            let weights = self.options.weights.get(name).map(Vec::as_slice);
+            self.alternatives(&rule.alternatives, weights, depth + 1);
+        }
+    }
+
+    fn alternatives(&mut self, alts: &Alternatives, weights: Option<&[u32]>, depth: usize) {
+        // Only alternatives with some input can be completed
+        let candidates: Vec<(&Sequence, u64)> = alts
+            .alts
+            .iter()
+            .enumerate()
+            .filter(|(_, seq)| self.sentences.sequence(seq).is_some())
+            .map(|(i, seq)| {
+                let weight = weights.and_then(|w| w.get(i)).copied().unwrap_or(1);
+                (seq, u64::from(weight))
+            })
+            .collect();
+        let total: u64 = candidates.iter().map(|(_, weight)| weight).sum();
+        let mut pick = self.rng.below(total);
+        let chosen = candidates
+            .iter()
+            .find(|(_, weight)| {
+                let found = pick < *weight;
+                pick = pick.saturating_sub(*weight);
+                found
+            })
+            .or(candidates.first());
+        if let Some((seq, _)) = chosen {
+            for factor in &seq.factors {
+                self.factor(factor, depth);
+            }
+        }
+    }
+
+    fn factor(&mut self, factor: &Factor, depth: usize) {
+        let max = if depth >= self.options.max_depth {
+            0
+        } else {
+            self.options.max_repeat
+        };
+        let (count, separator) = match &factor.repetition {
+            Repetition::None => (1, None),
+            Repetition::Optional => (self.rng.between(0, max.min(1)), None),
+            Repetition::ZeroOrMore => (self.rng.between(0, max), None),
+            Repetition::OneOrMore => (self.rng.between(1, max), None),
+            Repetition::SeparatedZeroOrMore(sep) => (self.rng.between(0, max), Some(sep)),
+            Repetition::SeparatedOneOrMore(sep) => (self.rng.between(1, max), Some(sep)),
+        };
+        // An element with no input at all can only be repeated zero times
+        let count = if self.sentences.base(&factor.base).is_some() {
+            count
+        } else {
+            0
+        };
+        for i in 0..count {
+            if let (Some(sep), true) = (separator, i > 0) {
+                for factor in &sep.factors {
+                    self.factor(factor, depth);
+                }
+            }
+            self.base(&factor.base, depth);
+        }
+    }
+
+    fn base(&mut self, base: &BaseFactor, depth: usize) {
+        match base {
+            BaseFactor::Literal {
+                value, insertion, ..
+            } => {
+                if !insertion {
+                    self.out.push_str(value);
+                }
+            }
+            BaseFactor::CharClass {
+                content, negated, ..
+            } => {
+                let class = charclass_to_rangeset(content);
+                if let Some(ch) = random_member(&class, *negated, &mut self.rng)
+                    .or_else(|| representative(content, *negated))
+                {
+                    self.out.push(ch);
+                }
+            }
+            BaseFactor::Nonterminal { name, .. } => self.rule(name, depth),
+            BaseFactor::Group { alternatives } => self.alternatives(alternatives, None, depth),
+            BaseFactor::NegativeLookahead { .. } => {}
+        }
+    }
+}
+
+/// A random character matched by a class; negated classes pick printable ASCII
+fn random_member(class: &RangeSet, negated: bool, rng: &mut SplitMix64) -> Option<char> {
+    if negated {
+        let allowed: Vec<char> = (' '..='~').filter(|&ch| !class.contains(ch)).collect();
+        return allowed
+            .get(rng.below(allowed.len() as u64) as usize)
+            .copied();
+    }
+    let size = |&(start, end): &(char, char)| u64::from(end) - u64::from(start) + 1;
+    let total: u64 = class.ranges().iter().map(size).sum();
+    let mut pick = rng.below(total);
+    for range in class.ranges() {
+        if pick < size(range) {
+            // Ranges may span the surrogates, which are not characters
+            return char::from_u32(u32::from(range.0) + pick as u32).or(Some(range.0));
+        }
+        pick -= size(range);
+    }
+    None
+}
+
+/// A character matched by a class, preferring printable ASCII
+fn representative(content: &str, negated: bool) -> Option<char> {
+    let class = charclass_to_rangeset(content);
+    let matches = |ch: char| class.contains(ch) != negated;
+    ('!'..='~')
+        .chain(['\u{a0}'..=char::MAX].into_iter().flatten())
+        .find(|&ch| matches(ch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar_ast::parse_ixml_grammar;
+    use crate::native_parser::{NativeParser, ParseStrategy};
+
+    #[test]
+    fn test_shortest_sentences() {
+        let grammar = parse_ixml_grammar(
+            r#"expr: term++"+". term: "(", expr, ")"; digit. -digit: ["0"-"9"]. s: ~["!"-"/"], +"x", name?. name: "abc"; "ab", name."#,
+        )
+        .unwrap();
+        let sentences = Sentences::new(&grammar);
+        assert_eq!(sentences.shortest("expr"), Some("0"));
+        assert_eq!(sentences.shortest("term"), Some("0"));
+        assert_eq!(sentences.shortest("s"), Some("0"));
+        assert_eq!(sentences.shortest("name"), Some("abc"));
+        assert_eq!(sentences.shortest("missing"), None);
+    }
+
+    #[test]
+    fn test_generate() {
+        // Earley, which explores every derivation, accepts whatever the grammar does
+        for fixture in crate::fixtures::FIXTURES {
+            let grammar = fixture.grammar_ast();
+            let parser = NativeParser::with_strategy(grammar.clone(), ParseStrategy::Earley);
+            for seed in 0..10 {
+                let options = GenOptions::new().with_seed(seed).with_max_depth(6);
+                let input = generate(&grammar, &options).unwrap();
+                assert!(
+                    parser.parse(&input).is_ok(),
+                    "{}: {:?}",
+                    fixture.name,
+                    input
+                );
+                assert_eq!(generate(&grammar, &options).unwrap(), input);
+            }
+        }
+
+        let grammar =
+            parse_ixml_grammar(r#"s: "a"; "b"; t. t: ~["a"-"z"], [#1F600]. u: u."#).unwrap();
+        let only_t = GenOptions::new().with_weights("s", [0, 0, 1]);
+        for seed in 0..10 {
+            let input = generate(&grammar, &only_t.clone().with_seed(seed)).unwrap();
+            let chars: Vec<char> = input.chars().collect();
+            assert!(
+                !chars[0].is_ascii_lowercase() && chars[1] == '😀',
+                "{}",
+                input
+            );
+        }
+        let never = GenOptions::new().with_weights("s", [0, 0, 0]);
+        assert_eq!(generate(&grammar, &never).unwrap(), "a");
+
+        assert_eq!(
+            generate(&grammar, &GenOptions::new().with_start("u")).unwrap_err(),
+            "Rule 'u' accepts no input"
+        );
+        assert_eq!(
+            generate(&grammar, &GenOptions::new().with_start("v")).unwrap_err(),
+            "Undefined rule 'v'"
+        );
+    }
+
+    #[test]
+    fn test_generate_depth_limit() {
+        let grammar = parse_ixml_grammar(r#"e: "(", e, ")"; "x"."#).unwrap();
+        let deep = GenOptions::new()
+            .with_weights("e", [1, 0])
+            .with_max_depth(4);
+        assert_eq!(generate(&grammar, &deep).unwrap(), "((((x))))");
+    }
+
+    #[test]
+    fn test_pumped_repetition_in_group() {
+        let grammar = parse_ixml_grammar(r#"s: "<", ("x", ("ab"?)*, "y"), ">"."#).unwrap();
+        let sentences = Sentences::new(&grammar);
+        let group = &grammar.rules[0].alternatives.alts[0].factors[1];
+        let BaseFactor::Group { alternatives } = &group.base else {
+            panic!("expected a group");
+        };
+        let repetition = &alternatives.alts[0].factors[1];
+        assert_eq!(
+            sentences.pumped("s", repetition, 2).as_deref(),
+            Some("<xabab")
+        );
+        // Not a factor of this grammar
+        assert_eq!(sentences.pumped("s", &group.clone(), 2), None);
+    }
+}
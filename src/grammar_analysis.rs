@@ -6,8 +6,18 @@
 //! This uses the normalization concepts from Steven Pemberton's work
 //! but applies them for analysis only, preserving the original grammar.
 
-use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Repetition, Rule, Sequence};
-use std::collections::{HashMap, HashSet};
+use crate::ast::{
+    visit_alternatives_references, Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Repetition,
+    Rule, Sequence,
+};
+use crate::charclass::RangeSet;
+use crate::compiled_grammar::{FirstSets, RuleNames};
+use crate::convert::json_string;
+use crate::diagnostics::Severity;
+use crate::generator::Sentences;
+use crate::normalize::{normalize_grammar_with, NormalizeOptions};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 
 /// Maximum recursion depth for grammar analysis to prevent stack overflow
 #[allow(dead_code)]
@@ -36,6 +46,286 @@ pub struct GrammarAnalysis {
 
     /// Whether the grammar is potentially ambiguous
     pub is_potentially_ambiguous: bool,
+
+    /// Repetitions the recursive-descent engine may backtrack through heavily
+    pub backtracking_hazards: Vec<BacktrackingHazard>,
+
+    /// References to rules the grammar does not define, in source order
+    pub undefined_references: Vec<UndefinedReference>,
+
+    /// Pairs of alternatives of a rule whose matches can start with the same
+    /// character, in source order
+    pub alternative_overlaps: Vec<AlternativeOverlap>,
+
+    unused_rules: Vec<String>,
+
+    unreachable_rules: Vec<String>,
+
+    /// Rule names with their marks, in source order, for [`GrammarAnalysis::to_dot`]
+    rules: Vec<(String, Mark)>,
+
+    /// References between rules, once each, in source order
+    references: Vec<Reference>,
+
+    stats: GrammarStats,
+}
+
+/// A rule referring to another, as drawn by [`GrammarAnalysis::to_dot`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Reference {
+    from: String,
+    to: String,
+    /// Whether the reference starts an alternative of `from`
+    leading: bool,
+}
+
+/// A reference to a rule the grammar does not define
+///
+/// ```
+/// use rustixml::grammar_analysis::GrammarAnalysis;
+/// use rustixml::parse_ixml_grammar;
+///
+/// let grammar = parse_ixml_grammar(r#"word: lettter+. letter: ["a"-"z"]."#).unwrap();
+/// let undefined = GrammarAnalysis::analyze(&grammar).undefined_references;
+/// assert_eq!(
+///     undefined[0].to_string(),
+///     "word: undefined rule 'lettter' (did you mean 'letter'?)"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndefinedReference {
+    /// Rule containing the reference
+    pub rule: String,
+    /// The name referred to
+    pub name: String,
+    /// The defined rule the name is closest to, if it looks like a typo
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for UndefinedReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: undefined rule '{}'", self.rule, self.name)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean '{}'?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Two alternatives of a rule whose matches can start with the same character
+///
+/// A parser cannot choose between them from the next character alone, and
+/// when the overlap runs on past the first character an input may match both.
+///
+/// ```
+/// use rustixml::grammar_analysis::GrammarAnalysis;
+/// use rustixml::parse_ixml_grammar;
+///
+/// let grammar = parse_ixml_grammar(r#"value: number; name; "-", number. number: ["0"-"9"]+. name: ["a"-"z"; "0"]+."#).unwrap();
+/// let overlaps = GrammarAnalysis::analyze(&grammar).alternative_overlaps;
+/// assert_eq!(overlaps.len(), 1);
+/// assert_eq!((overlaps[0].first, overlaps[0].second), (0, 1));
+/// assert_eq!(overlaps[0].to_string(), r#"value: alternatives 1 and 2 can both start with ["0"]"#);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlternativeOverlap {
+    pub rule: String,
+    /// Index of the earlier alternative in the rule, from 0
+    pub first: usize,
+    /// Index of the later alternative in the rule, from 0
+    pub second: usize,
+    /// The characters both can start with
+    pub overlap: RangeSet,
+}
+
+impl fmt::Display for AlternativeOverlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: alternatives {} and {} can both start with {}",
+            self.rule,
+            self.first + 1,
+            self.second + 1,
+            self.overlap
+        )
+    }
+}
+
+/// Size and shape figures for a grammar, from [`GrammarAnalysis::stats`]
+///
+/// `Display` writes them as the report `rustixml stats` prints.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GrammarStats {
+    pub rules: usize,
+    /// Strings and character classes the grammar matches, insertions aside
+    pub terminals: usize,
+    pub character_classes: usize,
+    /// How many rules have each number of alternatives, fewest alternatives
+    /// first
+    pub alternatives_per_rule: BTreeMap<usize, usize>,
+    /// Deepest nesting of groups and separators in any rule (0 when there
+    /// are none)
+    pub max_nesting_depth: usize,
+    /// A rough count of parser states: each sequence of n factors has n + 1
+    /// positions, and each repetition adds the two of the rule repeating it
+    pub estimated_states: usize,
+}
+
+impl fmt::Display for GrammarStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let distribution: Vec<String> = self
+            .alternatives_per_rule
+            .iter()
+            .map(|(alternatives, rules)| {
+                format!(
+                    "{} ({} rule{})",
+                    alternatives,
+                    rules,
+                    if *rules == 1 { "" } else { "s" }
+                )
+            })
+            .collect();
+        writeln!(f, "Rules:                 {}", self.rules)?;
+        writeln!(
+            f,
+            "Terminals:             {} ({} character class{})",
+            self.terminals,
+            self.character_classes,
+            if self.character_classes == 1 {
+                ""
+            } else {
+                "es"
+            }
+        )?;
+        writeln!(f, "Alternatives per rule: {}", distribution.join(", "))?;
+        writeln!(f, "Max nesting depth:     {}", self.max_nesting_depth)?;
+        writeln!(f, "Estimated states:      {}", self.estimated_states)
+    }
+}
+
+impl GrammarStats {
+    fn of(grammar: &IxmlGrammar) -> Self {
+        let mut stats = GrammarStats {
+            rules: grammar.rules.len(),
+            ..GrammarStats::default()
+        };
+        for rule in &grammar.rules {
+            *stats
+                .alternatives_per_rule
+                .entry(rule.alternatives.alts.len())
+                .or_default() += 1;
+            stats.count_alternatives(&rule.alternatives, 0);
+        }
+        stats
+    }
+
+    fn count_alternatives(&mut self, alternatives: &Alternatives, depth: usize) {
+        self.max_nesting_depth = self.max_nesting_depth.max(depth);
+        for seq in &alternatives.alts {
+            self.count_sequence(seq, depth);
+        }
+    }
+
+    fn count_sequence(&mut self, seq: &Sequence, depth: usize) {
+        self.estimated_states += seq.factors.len() + 1;
+        for factor in &seq.factors {
+            match &factor.repetition {
+                Repetition::None => {}
+                Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) => {
+                    self.estimated_states += 2;
+                    self.max_nesting_depth = self.max_nesting_depth.max(depth + 1);
+                    self.count_sequence(sep, depth + 1);
+                }
+                _ => self.estimated_states += 2,
+            }
+            self.count_base(&factor.base, depth);
+        }
+    }
+
+    fn count_base(&mut self, base: &BaseFactor, depth: usize) {
+        match base {
+            BaseFactor::Literal {
+                insertion: true, ..
+            } => {}
+            BaseFactor::Literal { .. } => self.terminals += 1,
+            BaseFactor::CharClass { .. } => {
+                self.terminals += 1;
+                self.character_classes += 1;
+            }
+            BaseFactor::Nonterminal { .. } => {}
+            BaseFactor::Group { alternatives } => self.count_alternatives(alternatives, depth + 1),
+            BaseFactor::NegativeLookahead { base } => self.count_base(base, depth),
+        }
+    }
+}
+
+/// How many elements the reproduction input of a hazard repeats
+const HAZARD_REPETITIONS: usize = 16;
+
+/// Why a repetition is a backtracking hazard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HazardKind {
+    /// The repeated element can match the empty string, e.g. `("a"?)*`
+    NullableElement,
+    /// The repeated element is itself a repetition, e.g. `("a"+)*`
+    NestedRepetition,
+    /// The repeated element is a choice whose alternatives can start with the
+    /// same character, e.g. `("a"; "a", "b")*`
+    OverlappingAlternatives,
+}
+
+impl HazardKind {
+    /// A short name for the kind, e.g. `nullable-element`
+    pub fn name(&self) -> &'static str {
+        match self {
+            HazardKind::NullableElement => "nullable-element",
+            HazardKind::NestedRepetition => "nested-repetition",
+            HazardKind::OverlappingAlternatives => "overlapping-alternatives",
+        }
+    }
+}
+
+impl fmt::Display for HazardKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HazardKind::NullableElement => "repeats an element that can match nothing",
+            HazardKind::NestedRepetition => "repeats an element that is itself a repetition",
+            HazardKind::OverlappingAlternatives => {
+                "repeats a choice whose alternatives can start with the same character"
+            }
+        })
+    }
+}
+
+/// A repetition that splits the same input in many ways, so a failed match
+/// after it can make the recursive-descent engine retry each of them
+///
+/// ```
+/// use rustixml::grammar_analysis::{GrammarAnalysis, HazardKind};
+/// use rustixml::parse_ixml_grammar;
+///
+/// let grammar = parse_ixml_grammar(r#"s: ("a"; "a", "b")*, "c"."#).unwrap();
+/// let hazards = GrammarAnalysis::analyze(&grammar).backtracking_hazards;
+/// assert_eq!(hazards.len(), 1);
+/// assert_eq!(hazards[0].kind, HazardKind::OverlappingAlternatives);
+/// assert_eq!(hazards[0].reproduction.as_deref(), Some("aaaaaaaaaaaaaaaa!"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktrackingHazard {
+    /// Rule containing the repetition
+    pub rule: String,
+    /// The repetition, as written in the grammar
+    pub pattern: String,
+    pub kind: HazardKind,
+    /// Input that makes the repetition backtrack when parsed from `rule`:
+    /// many elements followed by a character none of them starts with
+    pub reproduction: Option<String>,
+}
+
+impl fmt::Display for BacktrackingHazard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} {}", self.rule, self.pattern, self.kind)
+    }
 }
 
 impl GrammarAnalysis {
@@ -81,7 +371,13 @@ impl GrammarAnalysis {
             .collect();
 
         // Normalize grammar for more precise analysis
-        let normalized = normalize_grammar(grammar);
+        let normalized = normalize_grammar_with(
+            grammar,
+            &NormalizeOptions::none()
+                .with_inline_hidden(true)
+                .with_inline_promoted(true)
+                .with_remove_dead_rules(true),
+        );
         let normalized_map: HashMap<String, &Rule> = normalized
             .rules
             .iter()
@@ -92,6 +388,14 @@ impl GrammarAnalysis {
         let is_potentially_ambiguous =
             detect_ambiguity_patterns(&normalized, &normalized_map, &recursive_rules);
 
+        let backtracking_hazards = find_backtracking_hazards(grammar, &rule_map);
+
+        let alternative_overlaps = find_alternative_overlaps(grammar);
+
+        let undefined_references = find_undefined_references(grammar);
+
+        let (unused_rules, unreachable_rules) = find_unused_and_unreachable(grammar);
+
         GrammarAnalysis {
             recursive_rules,
             left_recursive_rules,
@@ -100,7 +404,183 @@ impl GrammarAnalysis {
             attribute_rules,
             complexity_scores,
             is_potentially_ambiguous,
+            backtracking_hazards,
+            undefined_references,
+            alternative_overlaps,
+            unused_rules,
+            unreachable_rules,
+            rules: grammar
+                .rules
+                .iter()
+                .map(|rule| (rule.name.clone(), rule.mark))
+                .collect(),
+            references: collect_references(grammar),
+            stats: GrammarStats::of(grammar),
+        }
+    }
+
+    /// Rules other than the start rule that no other rule refers to, in
+    /// source order
+    ///
+    /// These are the rules a grammar can drop outright. A rule that only
+    /// refers to itself counts as unused.
+    pub fn unused_rules(&self) -> &[String] {
+        &self.unused_rules
+    }
+
+    /// Rules the start rule (the first rule) cannot reach, directly or through
+    /// other rules, in source order
+    ///
+    /// Parsing never uses these, so they include the [unused
+    /// rules](GrammarAnalysis::unused_rules) and also rules that only unused
+    /// or unreachable rules refer to.
+    ///
+    /// ```
+    /// use rustixml::grammar_analysis::GrammarAnalysis;
+    /// use rustixml::parse_ixml_grammar;
+    ///
+    /// let grammar = parse_ixml_grammar(
+    ///     r#"s: word. word: ["a"-"z"]+. old: older. older: "x", older."#,
+    /// )
+    /// .unwrap();
+    /// let analysis = GrammarAnalysis::analyze(&grammar);
+    /// assert_eq!(analysis.unused_rules(), ["old"]);
+    /// assert_eq!(analysis.unreachable_rules(), ["old", "older"]);
+    /// ```
+    pub fn unreachable_rules(&self) -> &[String] {
+        &self.unreachable_rules
+    }
+
+    /// Size and shape figures for the grammar
+    ///
+    /// ```
+    /// use rustixml::grammar_analysis::GrammarAnalysis;
+    /// use rustixml::parse_ixml_grammar;
+    ///
+    /// let grammar = parse_ixml_grammar(
+    ///     r#"list: item++(",", " "?). item: word; "(", list, ")". word: ["a"-"z"]+."#,
+    /// )
+    /// .unwrap();
+    /// let stats = GrammarAnalysis::analyze(&grammar).stats().clone();
+    /// assert_eq!((stats.rules, stats.terminals, stats.character_classes), (3, 5, 1));
+    /// assert_eq!(stats.alternatives_per_rule.get(&1), Some(&2));
+    /// assert_eq!(stats.max_nesting_depth, 1);
+    /// print!("{}", stats);
+    /// ```
+    pub fn stats(&self) -> &GrammarStats {
+        &self.stats
+    }
+
+    /// The rules and their references as a Graphviz DOT graph
+    ///
+    /// The start rule has a double border, recursive rules are orange and
+    /// left-recursive ones red, as are the references that close their
+    /// cycles. Undefined rules are dashed, unreachable ones grey. Render with
+    /// e.g. `dot -Tsvg grammar.dot > grammar.svg`.
+    ///
+    /// ```
+    /// use rustixml::grammar_analysis::GrammarAnalysis;
+    /// use rustixml::parse_ixml_grammar;
+    ///
+    /// let grammar = parse_ixml_grammar(r#"expr: expr, "+", -n; n. n: ["0"-"9"]+."#).unwrap();
+    /// let dot = GrammarAnalysis::analyze(&grammar).to_dot();
+    /// assert_eq!(
+    ///     dot,
+    ///     [
+    ///         "digraph grammar {",
+    ///         "  node [shape=box];",
+    ///         "  \"expr\" [peripheries=2, color=red, fontcolor=red];",
+    ///         "  \"n\";",
+    ///         "  \"expr\" -> \"expr\" [color=red, style=bold];",
+    ///         "  \"expr\" -> \"n\";",
+    ///         "}",
+    ///         "",
+    ///     ]
+    ///     .join("\n")
+    /// );
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph grammar {\n  node [shape=box];\n");
+        let node = |name: &str, label: String, attributes: &[&str]| {
+            let mut attributes: Vec<String> = attributes.iter().map(|a| a.to_string()).collect();
+            if label != name {
+                attributes.insert(0, format!("label={:?}", label));
+            }
+            if attributes.is_empty() {
+                format!("  {:?};\n", name)
+            } else {
+                format!("  {:?} [{}];\n", name, attributes.join(", "))
+            }
+        };
+
+        let mut drawn = HashSet::new();
+        for (i, (name, mark)) in self.rules.iter().enumerate() {
+            if !drawn.insert(name.as_str()) {
+                continue;
+            }
+            let mut attributes = Vec::new();
+            if i == 0 {
+                attributes.push("peripheries=2");
+            }
+            if self.left_recursive_rules.contains(name) {
+                attributes.extend(["color=red", "fontcolor=red"]);
+            } else if self.recursive_rules.contains(name) {
+                attributes.extend(["color=orange", "fontcolor=orange"]);
+            } else if self.unreachable_rules.contains(name) {
+                attributes.extend(["color=grey", "fontcolor=grey"]);
+            }
+            out.push_str(&node(name, format!("{}{}", mark, name), &attributes));
+        }
+        for reference in &self.undefined_references {
+            if drawn.insert(&reference.name) {
+                out.push_str(&node(
+                    &reference.name,
+                    reference.name.clone(),
+                    &["style=dashed"],
+                ));
+            }
+        }
+
+        for reference in &self.references {
+            // A reference closes a cycle when its target leads back to it
+            let cyclic = self.recursive_rules.contains(&reference.from)
+                && self.recursive_rules.contains(&reference.to)
+                && self.reaches(&reference.to, &reference.from);
+            let style = if cyclic
+                && reference.leading
+                && self.left_recursive_rules.contains(&reference.from)
+                && self.left_recursive_rules.contains(&reference.to)
+            {
+                " [color=red, style=bold]"
+            } else if cyclic {
+                " [color=orange]"
+            } else {
+                ""
+            };
+            out.push_str(&format!(
+                "  {:?} -> {:?}{};\n",
+                reference.from, reference.to, style
+            ));
         }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Whether rule `from` refers to rule `to`, directly or through others
+    fn reaches(&self, from: &str, to: &str) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![from];
+        while let Some(name) = stack.pop() {
+            for reference in self.references.iter().filter(|r| r.from == name) {
+                if reference.to == to {
+                    return true;
+                }
+                if visited.insert(reference.to.as_str()) {
+                    stack.push(&reference.to);
+                }
+            }
+        }
+        false
     }
 
     /// Check if a rule is recursive
@@ -122,6 +602,14 @@ impl GrammarAnalysis {
     pub fn report(&self) -> String {
         let mut report = String::new();
 
+        if !self.undefined_references.is_empty() {
+            report.push_str("❌ References to undefined rules (never match):\n");
+            for reference in &self.undefined_references {
+                report.push_str(&format!("   - {}\n", reference));
+            }
+            report.push('\n');
+        }
+
         if self.is_potentially_ambiguous {
             report.push_str("⚠️  Grammar may be ambiguous (multiple parse trees possible)\n");
             report.push_str("   Parse output will be marked with ixml:state=\"ambiguous\"\n");
@@ -136,6 +624,33 @@ impl GrammarAnalysis {
             report.push('\n');
         }
 
+        if !self.backtracking_hazards.is_empty() {
+            report.push_str("⚠️  Repetitions that may backtrack heavily:\n");
+            for hazard in &self.backtracking_hazards {
+                report.push_str(&format!("   - {}\n", hazard));
+                if let Some(input) = &hazard.reproduction {
+                    report.push_str(&format!(
+                        "     reproduce with start rule {}: {:?}\n",
+                        hazard.rule, input
+                    ));
+                }
+            }
+            report.push_str(
+                "   Parse with ParseStrategy::Earley, move repeated groups into rules of \
+                 their own so their matches are memoized, or refactor so each input has \
+                 one way to repeat\n",
+            );
+            report.push('\n');
+        }
+
+        if !self.alternative_overlaps.is_empty() {
+            report.push_str("ℹ️  Alternatives that can start with the same character:\n");
+            for overlap in &self.alternative_overlaps {
+                report.push_str(&format!("   - {}\n", overlap));
+            }
+            report.push('\n');
+        }
+
         if !self.recursive_rules.is_empty() {
             report.push_str("ℹ️  Recursive rules (normal, but watch for performance):\n");
             for rule in &self.recursive_rules {
@@ -146,6 +661,14 @@ impl GrammarAnalysis {
             report.push('\n');
         }
 
+        if !self.unreachable_rules.is_empty() {
+            report.push_str("ℹ️  Unreachable rules (never used from the start rule):\n");
+            for rule in &self.unreachable_rules {
+                report.push_str(&format!("   - {}\n", rule));
+            }
+            report.push('\n');
+        }
+
         let high_complexity: Vec<_> = self
             .complexity_scores
             .iter()
@@ -166,6 +689,453 @@ impl GrammarAnalysis {
 
         report
     }
+
+    /// The findings of [`report`](Self::report) as JSON, for tools
+    ///
+    /// Rule sets are sorted by name; everything else is in source order, and
+    /// `complexity` has every rule.
+    ///
+    /// ```
+    /// use rustixml::grammar_analysis::GrammarAnalysis;
+    /// use rustixml::parse_ixml_grammar;
+    ///
+    /// let grammar = parse_ixml_grammar(r#"e: e, "+", n; n. n: ["0"-"9"]+. old: "x"."#).unwrap();
+    /// let json = GrammarAnalysis::analyze(&grammar).to_json();
+    /// assert!(json.contains(r#""left_recursive_rules":["e"]"#));
+    /// assert!(json.contains(r#""unused_rules":["old"]"#));
+    /// assert!(json.contains(r#""complexity":{"e":"#));
+    /// ```
+    pub fn to_json(&self) -> String {
+        let strings = |names: &mut dyn Iterator<Item = &String>| {
+            let names: Vec<String> = names.map(|name| json_string(name)).collect();
+            format!("[{}]", names.join(","))
+        };
+        let sorted = |set: &HashSet<String>| {
+            let mut names: Vec<&String> = set.iter().collect();
+            names.sort();
+            strings(&mut names.into_iter())
+        };
+        let optional = |text: &Option<String>| {
+            text.as_deref()
+                .map_or_else(|| "null".to_string(), json_string)
+        };
+
+        let undefined: Vec<String> = self
+            .undefined_references
+            .iter()
+            .map(|r| {
+                format!(
+                    "{{\"rule\":{},\"name\":{},\"suggestion\":{}}}",
+                    json_string(&r.rule),
+                    json_string(&r.name),
+                    optional(&r.suggestion)
+                )
+            })
+            .collect();
+        let hazards: Vec<String> = self
+            .backtracking_hazards
+            .iter()
+            .map(|h| {
+                format!(
+                    "{{\"rule\":{},\"pattern\":{},\"kind\":{},\"reproduction\":{}}}",
+                    json_string(&h.rule),
+                    json_string(&h.pattern),
+                    json_string(h.kind.name()),
+                    optional(&h.reproduction)
+                )
+            })
+            .collect();
+        let overlaps: Vec<String> = self
+            .alternative_overlaps
+            .iter()
+            .map(|o| {
+                format!(
+                    "{{\"rule\":{},\"first\":{},\"second\":{},\"overlap\":{}}}",
+                    json_string(&o.rule),
+                    o.first,
+                    o.second,
+                    json_string(&o.overlap.to_string())
+                )
+            })
+            .collect();
+        let mut seen = HashSet::new();
+        let complexity: Vec<String> = self
+            .rules
+            .iter()
+            .filter(|(name, _)| seen.insert(name))
+            .map(|(name, _)| format!("{}:{}", json_string(name), self.complexity(name)))
+            .collect();
+
+        format!(
+            "{{\"undefined_references\":[{}],\"potentially_ambiguous\":{},\
+             \"left_recursive_rules\":{},\"recursive_rules\":{},\
+             \"backtracking_hazards\":[{}],\"alternative_overlaps\":[{}],\
+             \"unused_rules\":{},\"unreachable_rules\":{},\"complexity\":{{{}}}}}",
+            undefined.join(","),
+            self.is_potentially_ambiguous,
+            sorted(&self.left_recursive_rules),
+            sorted(&self.recursive_rules),
+            hazards.join(","),
+            overlaps.join(","),
+            strings(&mut self.unused_rules.iter()),
+            strings(&mut self.unreachable_rules.iter()),
+            complexity.join(",")
+        )
+    }
+}
+
+/// Find references to rules the grammar does not define, in source order
+pub(crate) fn find_undefined_references(grammar: &IxmlGrammar) -> Vec<UndefinedReference> {
+    let defined: HashSet<&str> = grammar.rules.iter().map(|r| r.name.as_str()).collect();
+    let mut undefined = Vec::new();
+    grammar.visit_references(|rule, name| {
+        if !defined.contains(name) {
+            undefined.push(UndefinedReference {
+                rule: rule.name.clone(),
+                name: name.to_string(),
+                suggestion: grammar.closest_rule(name).map(str::to_string),
+            });
+        }
+    });
+    undefined
+}
+
+/// Rules other than the start rule that no other rule refers to, and rules
+/// the start rule cannot reach, both in source order
+pub(crate) fn find_unused_and_unreachable(grammar: &IxmlGrammar) -> (Vec<String>, Vec<String>) {
+    let Some(start) = grammar.rules.first() else {
+        return (Vec::new(), Vec::new());
+    };
+    let rule_map: HashMap<&str, &Rule> = grammar
+        .rules
+        .iter()
+        .rev()
+        .map(|rule| (rule.name.as_str(), rule))
+        .collect();
+
+    // Rules referring to themselves do not count as using themselves
+    let mut referenced: HashSet<String> = HashSet::new();
+    grammar.visit_references(|rule, name| {
+        if rule.name != name {
+            referenced.insert(name.to_string());
+        }
+    });
+
+    let mut reachable: HashSet<&str> = HashSet::from([start.name.as_str()]);
+    let mut stack = vec![start];
+    while let Some(rule) = stack.pop() {
+        visit_alternatives_references(&rule.alternatives, &mut |name, _| {
+            if let Some(&target) = rule_map.get(name) {
+                if reachable.insert(&target.name) {
+                    stack.push(target);
+                }
+            }
+        });
+    }
+
+    let mut unused = Vec::new();
+    let mut unreachable = Vec::new();
+    for rule in &grammar.rules[1..] {
+        if !referenced.contains(&rule.name) && !unused.contains(&rule.name) {
+            unused.push(rule.name.clone());
+        }
+        if !reachable.contains(rule.name.as_str()) && !unreachable.contains(&rule.name) {
+            unreachable.push(rule.name.clone());
+        }
+    }
+    (unused, unreachable)
+}
+
+/// What a [`Lint`] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintKind {
+    /// A rule other than the start rule that no other rule refers to
+    UnusedRule,
+    /// A rule that is referred to, but not from anything the start rule uses
+    UnreachableRule,
+    /// A reference to a rule the grammar does not define
+    UndefinedReference,
+    /// A second definition of a rule name
+    DuplicateRule,
+    /// An attribute whose content includes elements, which are lost since an
+    /// attribute's value is only the text of its content
+    AttributeContainsElement,
+    /// A repetition of something that can match the empty string
+    NullableRepetition,
+}
+
+impl LintKind {
+    /// The stable [`code`](crate::diagnostics::code) for this kind of lint
+    pub fn code(&self) -> &'static str {
+        use crate::diagnostics::code;
+        match self {
+            LintKind::UnusedRule => code::UNUSED_RULE,
+            LintKind::UnreachableRule => code::UNREACHABLE_RULE,
+            LintKind::UndefinedReference => code::UNDEFINED_RULE,
+            LintKind::DuplicateRule => code::DUPLICATE_RULE,
+            LintKind::AttributeContainsElement => code::ATTRIBUTE_CONTAINS_ELEMENT,
+            LintKind::NullableRepetition => code::NULLABLE_REPETITION,
+        }
+    }
+
+    /// How serious this kind of lint is: undefined and duplicate rules stop
+    /// a grammar from compiling, so they are errors, the rest warnings
+    pub fn severity(&self) -> Severity {
+        match self {
+            LintKind::UndefinedReference | LintKind::DuplicateRule => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+/// A likely mistake in a grammar, found by [`lint`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub kind: LintKind,
+    /// The rule the problem is in or about
+    pub rule: String,
+    pub message: String,
+    /// What to do about it, if there is a usual fix
+    pub suggestion: Option<String>,
+}
+
+impl Lint {
+    fn new(kind: LintKind, rule: &str, message: String) -> Self {
+        Lint {
+            kind,
+            rule: rule.to_string(),
+            message,
+            suggestion: None,
+        }
+    }
+
+    fn suggest(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.rule, self.kind.code(), self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " ({})", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check a grammar for likely mistakes
+///
+/// Lints come grouped by kind, in the order of [`LintKind`]'s variants, and in
+/// source order within each kind. The first rule is the start rule.
+///
+/// ```
+/// use rustixml::grammar_analysis::{lint, LintKind};
+/// use rustixml::parse_ixml_grammar;
+///
+/// let grammar = parse_ixml_grammar(r#"s: @a, ("x"?)*. a: b. b: "b". old: "o"."#).unwrap();
+/// let lints = lint(&grammar);
+/// assert_eq!(lints[0].to_string(), "old[unused-rule]: rule 'old' is never used");
+/// assert_eq!(lints[1].kind, LintKind::AttributeContainsElement);
+/// assert_eq!(lints[2].to_string(), "s[nullable-repetition]: (\"x\"?)* repeats something that can match nothing (make the repeated part match at least one character)");
+/// ```
+pub fn lint(grammar: &IxmlGrammar) -> Vec<Lint> {
+    let mut rule_map: HashMap<&str, &Rule> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for rule in &grammar.rules {
+        if rule_map.contains_key(rule.name.as_str()) {
+            duplicates.push(rule);
+        } else {
+            rule_map.insert(&rule.name, rule);
+        }
+    }
+
+    let mut lints: Vec<Lint> = Vec::new();
+    let Some(start) = grammar.rules.first() else {
+        return lints;
+    };
+
+    let mut attributes: Vec<String> = Vec::new();
+    grammar.visit_marked_references(|_, name, mark| {
+        if mark == Mark::Attribute && !attributes.iter().any(|a| a == name) {
+            attributes.push(name.to_string());
+        }
+    });
+
+    let (unused, unreachable) = find_unused_and_unreachable(grammar);
+    for name in &unused {
+        lints.push(Lint::new(
+            LintKind::UnusedRule,
+            name,
+            format!("rule '{}' is never used", name),
+        ));
+    }
+    for name in unreachable.iter().filter(|name| !unused.contains(name)) {
+        lints.push(Lint::new(
+            LintKind::UnreachableRule,
+            name,
+            format!(
+                "rule '{}' cannot be reached from start rule '{}'",
+                name, start.name
+            ),
+        ));
+    }
+    for reference in find_undefined_references(grammar) {
+        let lint = Lint::new(
+            LintKind::UndefinedReference,
+            &reference.rule,
+            format!("undefined rule '{}'", reference.name),
+        );
+        lints.push(match reference.suggestion {
+            Some(suggestion) => lint.suggest(format!("did you mean '{}'?", suggestion)),
+            None => lint,
+        });
+    }
+    for rule in duplicates {
+        lints.push(
+            Lint::new(
+                LintKind::DuplicateRule,
+                &rule.name,
+                format!("rule '{}' is defined more than once", rule.name),
+            )
+            .suggest("merge the definitions into one rule with several alternatives"),
+        );
+    }
+
+    // Attributes are rules marked `@` and rules referred to as `@name`
+    for rule in &grammar.rules {
+        if rule.mark == Mark::Attribute && !attributes.contains(&rule.name) {
+            attributes.push(rule.name.clone());
+        }
+    }
+    attributes.sort_by_key(|name| {
+        grammar
+            .rules
+            .iter()
+            .position(|rule| rule.name == *name)
+            .unwrap_or(usize::MAX)
+    });
+    for name in &attributes {
+        let Some(rule) = rule_map.get(name.as_str()) else {
+            continue;
+        };
+        for element in attribute_elements(rule, &rule_map) {
+            lints.push(
+                Lint::new(
+                    LintKind::AttributeContainsElement,
+                    name,
+                    format!("attribute '{}' would contain element '{}'", name, element),
+                )
+                .suggest(format!(
+                    "only its text is kept; hide it with -{} if that is intended",
+                    element
+                )),
+            );
+        }
+    }
+
+    let owned_map: HashMap<String, &Rule> = rule_map
+        .iter()
+        .map(|(name, rule)| (name.to_string(), *rule))
+        .collect();
+    let nullable_set = compute_nullable_set(&owned_map);
+    for rule in &grammar.rules {
+        let mut repeated = Vec::new();
+        collect_repeated_factors(&rule.alternatives, &mut repeated);
+        for factor in repeated {
+            if is_base_nullable(&factor.base, &nullable_set) {
+                lints.push(
+                    Lint::new(
+                        LintKind::NullableRepetition,
+                        &rule.name,
+                        format!("{} repeats something that can match nothing", factor),
+                    )
+                    .suggest("make the repeated part match at least one character"),
+                );
+            }
+        }
+    }
+
+    lints
+}
+
+/// The elements an attribute rule's content would produce, in source order;
+/// hidden rules are looked through
+fn attribute_elements<'a>(rule: &'a Rule, rule_map: &HashMap<&str, &'a Rule>) -> Vec<String> {
+    let mut elements: Vec<String> = Vec::new();
+    let mut visited: HashSet<&str> = HashSet::from([rule.name.as_str()]);
+    let mut stack = vec![rule];
+    while let Some(rule) = stack.pop() {
+        visit_alternatives_references(&rule.alternatives, &mut |name, mark| {
+            let Some(&target) = rule_map.get(name) else {
+                return;
+            };
+            let mark = if mark == Mark::None {
+                target.mark
+            } else {
+                mark
+            };
+            match mark {
+                Mark::None | Mark::Promoted => {
+                    if !elements.iter().any(|element| element == name) {
+                        elements.push(name.to_string());
+                    }
+                }
+                Mark::Hidden => {
+                    if visited.insert(&target.name) {
+                        stack.push(target);
+                    }
+                }
+                Mark::Attribute => {}
+            }
+        });
+    }
+    elements
+}
+
+/// The references between rules, once each in source order
+fn collect_references(grammar: &IxmlGrammar) -> Vec<Reference> {
+    fn alternatives(from: &str, alts: &Alternatives, leading: bool, out: &mut Vec<Reference>) {
+        for seq in &alts.alts {
+            sequence(from, seq, leading, out);
+        }
+    }
+
+    fn sequence(from: &str, seq: &Sequence, leading: bool, out: &mut Vec<Reference>) {
+        for (i, factor) in seq.factors.iter().enumerate() {
+            base(from, &factor.base, leading && i == 0, out);
+            if let Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) =
+                &factor.repetition
+            {
+                sequence(from, sep, false, out);
+            }
+        }
+    }
+
+    fn base(from: &str, factor: &BaseFactor, leading: bool, out: &mut Vec<Reference>) {
+        match factor {
+            BaseFactor::Nonterminal { name, .. } => {
+                match out.iter_mut().find(|r| r.from == from && r.to == *name) {
+                    Some(existing) => existing.leading |= leading,
+                    None => out.push(Reference {
+                        from: from.to_string(),
+                        to: name.clone(),
+                        leading,
+                    }),
+                }
+            }
+            BaseFactor::Group { alternatives: alts } => alternatives(from, alts, leading, out),
+            BaseFactor::NegativeLookahead { base: inner } => base(from, inner, leading, out),
+            BaseFactor::Literal { .. } | BaseFactor::CharClass { .. } => {}
+        }
+    }
+
+    let mut references = Vec::new();
+    for rule in &grammar.rules {
+        alternatives(&rule.name, &rule.alternatives, true, &mut references);
+    }
+    references
 }
 
 /// Find all recursive rules (directly or indirectly)
@@ -485,6 +1455,9 @@ fn compute_left_reachable(
                         // Character classes block
                         break;
                     }
+                    BaseFactor::NegativeLookahead { .. } => {
+                        // Lookahead consumes nothing, continue
+                    }
                     BaseFactor::Group {
                         alternatives: group_alts,
                     } => {
@@ -550,6 +1523,7 @@ fn compute_left_reachable_direct(
                 BaseFactor::CharClass { .. } => {
                     break;
                 }
+                BaseFactor::NegativeLookahead { .. } => {}
                 BaseFactor::Group {
                     alternatives: group_alts,
                 } => {
@@ -653,6 +1627,9 @@ fn is_factor_nullable_simple(factor: &Factor, nullable_rules: &HashSet<String>)
             BaseFactor::CharClass { .. } => {
                 results_stack.push(false);
             }
+            BaseFactor::NegativeLookahead { .. } => {
+                results_stack.push(true);
+            }
             BaseFactor::Nonterminal { name, .. } => {
                 // Check if this rule is in our nullable set
                 results_stack.push(nullable_rules.contains(name));
@@ -671,6 +1648,7 @@ fn is_factor_nullable_simple(factor: &Factor, nullable_rules: &HashSet<String>)
                             _ => match &seq_factor.base {
                                 BaseFactor::Literal { value, .. } => value.is_empty(),
                                 BaseFactor::CharClass { .. } => false,
+                                BaseFactor::NegativeLookahead { .. } => true,
                                 BaseFactor::Nonterminal { name, .. } => {
                                     nullable_rules.contains(name)
                                 }
@@ -795,6 +1773,7 @@ fn is_factor_nullable_with_cache(
     match &factor.base {
         BaseFactor::Literal { value, .. } => value.is_empty(),
         BaseFactor::CharClass { .. } => false,
+        BaseFactor::NegativeLookahead { .. } => true,
         BaseFactor::Nonterminal { name, .. } => {
             // Check cache first
             if let Some(&result) = cache.get(name) {
@@ -905,6 +1884,7 @@ fn is_factor_nullable(
     match &factor.base {
         BaseFactor::Literal { value, .. } => value.is_empty(),
         BaseFactor::CharClass { .. } => false, // Character class always matches at least one char
+        BaseFactor::NegativeLookahead { .. } => true, // Lookahead never consumes input
         BaseFactor::Nonterminal { name, .. } => {
             if visited.contains(name) {
                 return false; // Avoid infinite loop
@@ -1000,196 +1980,168 @@ fn check_factor_for_recursion(
     }
 }
 
-/// Calculate complexity score for alternatives
-fn calculate_complexity(alternatives: &Alternatives) -> usize {
-    let mut score = alternatives.alts.len(); // Base: number of alternatives
+/// Find repetitions that can split the same input in many ways
+fn find_backtracking_hazards(
+    grammar: &IxmlGrammar,
+    rule_map: &HashMap<String, &Rule>,
+) -> Vec<BacktrackingHazard> {
+    let nullable_set = compute_nullable_set(rule_map);
+    let names = RuleNames::new(grammar);
+    let first_sets = FirstSets::compute(grammar, &names);
+    let sequence_first = |seq| {
+        let mut first = RangeSet::new();
+        first_sets.sequence_first(seq, &names, &mut first);
+        first
+    };
+    let sentences = Sentences::new(grammar);
+    let mut hazards = Vec::new();
 
-    for seq in &alternatives.alts {
-        score += seq.factors.len(); // Add sequence length
+    for rule in &grammar.rules {
+        let mut repeated = Vec::new();
+        collect_repeated_factors(&rule.alternatives, &mut repeated);
+
+        for factor in repeated {
+            let element = match &factor.base {
+                BaseFactor::Group { alternatives } => Some(alternatives.as_ref()),
+                BaseFactor::Nonterminal { name, .. } => rule_map.get(name).map(|r| &r.alternatives),
+                _ => None,
+            };
+            let nullable = is_base_nullable(&factor.base, &nullable_set);
+
+            // A separator that always consumes input keeps neighbouring elements apart
+            let separated = match &factor.repetition {
+                Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) => !sep
+                    .factors
+                    .iter()
+                    .all(|f| is_factor_nullable_simple(f, &nullable_set)),
+                _ => false,
+            };
 
-        for factor in &seq.factors {
-            score += match &factor.base {
-                BaseFactor::Group { alternatives } => calculate_complexity(alternatives),
-                _ => 1,
+            let kind = if nullable {
+                HazardKind::NullableElement
+            } else if !separated
+                && element.is_some_and(|alts| {
+                    alts.alts.iter().any(|seq| {
+                        seq.factors.len() == 1
+                            && !matches!(
+                                seq.factors[0].repetition,
+                                Repetition::None | Repetition::Optional
+                            )
+                    })
+                })
+            {
+                HazardKind::NestedRepetition
+            } else if element.is_some_and(|alts| {
+                let firsts: Vec<RangeSet> = alts.alts.iter().map(sequence_first).collect();
+                firsts.iter().enumerate().any(|(i, a)| {
+                    firsts[i + 1..]
+                        .iter()
+                        .any(|b| !a.intersection(b).is_empty())
+                })
+            }) {
+                HazardKind::OverlappingAlternatives
+            } else {
+                continue;
             };
+
+            // End the repeated elements with a character none of them can start with
+            let mut first = RangeSet::new();
+            first_sets.base_first(&factor.base, &names, &mut first);
+            let breaker = ('!'..='~')
+                .chain(['\u{a0}'..=char::MAX].into_iter().flatten())
+                .find(|&ch| !first.contains(ch));
+            let reproduction = sentences
+                .pumped(&rule.name, factor, HAZARD_REPETITIONS)
+                .zip(breaker)
+                .map(|(mut input, breaker)| {
+                    input.push(breaker);
+                    input
+                });
+
+            hazards.push(BacktrackingHazard {
+                rule: rule.name.clone(),
+                pattern: factor.to_string(),
+                kind,
+                reproduction,
+            });
         }
     }
 
-    score
+    hazards
 }
 
-//=============================================================================
-// Grammar Normalization for Static Analysis
-//=============================================================================
-//
-// Based on Steven Pemberton's normalization algorithm:
-// https://homepages.cwi.nl/~steven/Talks/2016/02-12-prague/data.html
-//
-// This creates a "normalized" (canonical schema) form of the grammar that:
-// 1. Inlines hidden (-name) and promoted (^name) nonterminals
-// 2. Removes unmarked terminals
-// 3. Eliminates unused rules
-//
-// IMPORTANT: This is for STATIC ANALYSIS only, not for parsing!
-// The parser still uses the original grammar to preserve XML structure.
-
-/// Normalize a grammar for static analysis purposes
-/// Returns a new grammar with hidden/promoted rules inlined
-fn normalize_grammar(grammar: &IxmlGrammar) -> IxmlGrammar {
-    let rule_map: HashMap<String, &Rule> =
-        grammar.rules.iter().map(|r| (r.name.clone(), r)).collect();
-
-    // Find rules that should be inlined (hidden and promoted)
-    let mut inline_rules: HashSet<String> = HashSet::new();
-    for rule in &grammar.rules {
-        match rule.mark {
-            Mark::Hidden | Mark::Promoted => {
-                inline_rules.insert(rule.name.clone());
-            }
-            _ => {}
-        }
-    }
+/// Find pairs of alternatives whose FIRST sets intersect
+fn find_alternative_overlaps(grammar: &IxmlGrammar) -> Vec<AlternativeOverlap> {
+    let names = RuleNames::new(grammar);
+    let first_sets = FirstSets::compute(grammar, &names);
+    let sequence_first = |seq| {
+        let mut first = RangeSet::new();
+        first_sets.sequence_first(seq, &names, &mut first);
+        first
+    };
+    let mut overlaps = Vec::new();
 
-    // Create normalized rules (except the ones we're inlining)
-    let mut normalized_rules = Vec::new();
     for rule in &grammar.rules {
-        if inline_rules.contains(&rule.name) {
-            continue; // Skip - this rule will be inlined
+        let firsts: Vec<RangeSet> = rule.alternatives.alts.iter().map(sequence_first).collect();
+        for (first, a) in firsts.iter().enumerate() {
+            for (second, b) in firsts.iter().enumerate().skip(first + 1) {
+                let overlap = a.intersection(b);
+                if !overlap.is_empty() {
+                    overlaps.push(AlternativeOverlap {
+                        rule: rule.name.clone(),
+                        first,
+                        second,
+                        overlap,
+                    });
+                }
+            }
         }
-
-        // Normalize the alternatives
-        let normalized_alts =
-            normalize_alternatives(&rule.alternatives, &rule_map, &inline_rules, 0);
-
-        normalized_rules.push(Rule::new(rule.name.clone(), rule.mark, normalized_alts));
     }
 
-    IxmlGrammar::new(normalized_rules)
+    overlaps
 }
 
-/// Normalize alternatives by inlining marked nonterminals
-fn normalize_alternatives(
-    alternatives: &Alternatives,
-    rule_map: &HashMap<String, &Rule>,
-    inline_rules: &HashSet<String>,
-    depth: usize,
-) -> Alternatives {
-    // Prevent infinite recursion
-    if depth > 10 {
-        return alternatives.clone();
+/// Whether a factor's base can match the empty string
+fn is_base_nullable(base: &BaseFactor, nullable_set: &HashSet<String>) -> bool {
+    match base {
+        BaseFactor::Literal {
+            value, insertion, ..
+        } => value.is_empty() || *insertion,
+        BaseFactor::CharClass { .. } => false,
+        BaseFactor::NegativeLookahead { .. } => true,
+        BaseFactor::Nonterminal { name, .. } => nullable_set.contains(name),
+        BaseFactor::Group { alternatives } => is_alternatives_nullable(alternatives, nullable_set),
     }
-
-    let normalized_alts: Vec<Sequence> = alternatives
-        .alts
-        .iter()
-        .flat_map(|seq| normalize_sequence(seq, rule_map, inline_rules, depth + 1))
-        .collect();
-
-    Alternatives::new(normalized_alts)
 }
 
-/// Normalize a sequence, potentially expanding into multiple sequences
-fn normalize_sequence(
-    sequence: &Sequence,
-    rule_map: &HashMap<String, &Rule>,
-    inline_rules: &HashSet<String>,
-    depth: usize,
-) -> Vec<Sequence> {
-    // Prevent infinite recursion
-    if depth > 10 {
-        return vec![sequence.clone()];
-    }
-
-    let mut result_sequences = vec![Vec::new()];
-
-    for factor in &sequence.factors {
-        let normalized_factors = normalize_factor(factor, rule_map, inline_rules, depth + 1);
-
-        if normalized_factors.len() == 1 {
-            // Simple case: one factor -> append to all sequences
-            for seq in &mut result_sequences {
-                seq.push(normalized_factors[0].clone());
-            }
-        } else {
-            // Complex case: multiple alternatives from inlining
-            // Need to create cross-product of sequences
-            let mut new_sequences = Vec::new();
-            for existing_seq in &result_sequences {
-                for new_factor in &normalized_factors {
-                    let mut combined = existing_seq.clone();
-                    combined.push(new_factor.clone());
-                    new_sequences.push(combined);
-                }
-            }
-            result_sequences = new_sequences;
+/// Collect the `*`, `+`, `**` and `++` factors of a choice, in source order
+fn collect_repeated_factors<'a>(alternatives: &'a Alternatives, out: &mut Vec<&'a Factor>) {
+    for factor in alternatives.alts.iter().flat_map(|seq| &seq.factors) {
+        if !matches!(factor.repetition, Repetition::None | Repetition::Optional) {
+            out.push(factor);
+        }
+        if let BaseFactor::Group { alternatives } = &factor.base {
+            collect_repeated_factors(alternatives, out);
         }
     }
-
-    result_sequences.into_iter().map(Sequence::new).collect()
 }
 
-/// Normalize a factor, potentially expanding to multiple factors
-fn normalize_factor(
-    factor: &Factor,
-    rule_map: &HashMap<String, &Rule>,
-    inline_rules: &HashSet<String>,
-    depth: usize,
-) -> Vec<Factor> {
-    // Prevent infinite recursion
-    if depth > 10 {
-        return vec![factor.clone()];
-    }
+/// Calculate complexity score for alternatives
+fn calculate_complexity(alternatives: &Alternatives) -> usize {
+    let mut score = alternatives.alts.len(); // Base: number of alternatives
 
-    match &factor.base {
-        BaseFactor::Nonterminal { name, .. } => {
-            // Check if this nonterminal should be inlined
-            if inline_rules.contains(name) {
-                if let Some(rule) = rule_map.get(name.as_str()) {
-                    // Inline this rule's alternatives
-                    // This gets complex with repetitions, so simplify:
-                    // If no repetition, inline directly
-                    // If repetition, keep the nonterminal for now (conservative)
-                    match factor.repetition {
-                        Repetition::None => {
-                            // Inline: collect all factors from all alternatives
-                            let mut inlined_factors = Vec::new();
-                            for alt in &rule.alternatives.alts {
-                                for alt_factor in &alt.factors {
-                                    inlined_factors.push(alt_factor.clone());
-                                }
-                            }
-                            return if inlined_factors.is_empty() {
-                                vec![]
-                            } else {
-                                inlined_factors
-                            };
-                        }
-                        _ => {
-                            // Keep as-is if there's repetition (too complex to inline)
-                            return vec![factor.clone()];
-                        }
-                    }
-                }
-            }
-            // Not inlined, keep as-is
-            vec![factor.clone()]
-        }
-        BaseFactor::Group { alternatives } => {
-            // Normalize the group's alternatives
-            let normalized_alts =
-                normalize_alternatives(alternatives, rule_map, inline_rules, depth + 1);
-            vec![Factor::new(
-                BaseFactor::Group {
-                    alternatives: Box::new(normalized_alts),
-                },
-                factor.repetition.clone(),
-            )]
-        }
-        _ => {
-            // Literals and char classes stay as-is
-            vec![factor.clone()]
+    for seq in &alternatives.alts {
+        score += seq.factors.len(); // Add sequence length
+
+        for factor in &seq.factors {
+            score += match &factor.base {
+                BaseFactor::Group { alternatives } => calculate_complexity(alternatives),
+                _ => 1,
+            };
         }
     }
+
+    score
 }
 
 #[cfg(test)]
@@ -1273,4 +2225,212 @@ mod tests {
         // 2 alternatives + 2 sequences (len=1 each) + 2 factors = 6
         assert_eq!(analysis.complexity("simple"), 6);
     }
+
+    #[test]
+    fn test_backtracking_hazards() {
+        let grammar = crate::grammar_ast::parse_ixml_grammar(
+            r#"s: ("a"?)*, "<", x+, ">", ("a"; "b")*, ":", ("b"+)**",", ("c"+)**"".
+               x: "a"; "a", x."#,
+        )
+        .unwrap();
+        let analysis = GrammarAnalysis::analyze(&grammar);
+        let found: Vec<_> = analysis
+            .backtracking_hazards
+            .iter()
+            .map(|h| (h.pattern.as_str(), h.kind, h.reproduction.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "(\"a\"?)*",
+                    HazardKind::NullableElement,
+                    Some("aaaaaaaaaaaaaaaa!")
+                ),
+                (
+                    "x+",
+                    HazardKind::OverlappingAlternatives,
+                    Some("<aaaaaaaaaaaaaaaa!")
+                ),
+                (
+                    "(\"c\"+)**(\"\")",
+                    HazardKind::NestedRepetition,
+                    Some("<a>:cccccccccccccccc!")
+                ),
+            ]
+        );
+
+        let report = analysis.report();
+        assert!(report.contains(
+            "s: x+ repeats a choice whose alternatives can start with the same character"
+        ));
+        assert!(report.contains("reproduce with start rule s: \"<aaaaaaaaaaaaaaaa!\""));
+        assert!(report.contains("ParseStrategy::Earley"));
+    }
+
+    #[test]
+    fn test_undefined_references() {
+        let grammar = crate::grammar_ast::parse_ixml_grammar(
+            r#"s: (item; sep)++sepp, -itme. item: ["a"-"z"]. sep: ","."#,
+        )
+        .unwrap();
+        let analysis = GrammarAnalysis::analyze(&grammar);
+        let found: Vec<_> = analysis
+            .undefined_references
+            .iter()
+            .map(|r| (r.name.as_str(), r.suggestion.as_deref()))
+            .collect();
+        assert_eq!(found, [("sepp", Some("sep")), ("itme", None)]);
+        assert!(analysis
+            .report()
+            .starts_with("❌ References to undefined rules (never match):\n   - s: undefined rule 'sepp' (did you mean 'sep'?)\n"));
+    }
+
+    #[test]
+    fn test_lint() {
+        let grammar = crate::grammar_ast::parse_ixml_grammar(
+            r#"s: @a, b**sep, c. -a: n, -h, @id. h: ^n, (x; "y"*)+. n: "n". x: "x". id: "i".
+               b: "b"; b, "b". c: "c"?. c: "d". loop: again. again: loop. spare: "s"."#,
+        )
+        .unwrap();
+        let lints: Vec<String> = lint(&grammar).iter().map(Lint::to_string).collect();
+        assert_eq!(
+            lints,
+            [
+                "spare[unused-rule]: rule 'spare' is never used",
+                "loop[unreachable-rule]: rule 'loop' cannot be reached from start rule 's'",
+                "again[unreachable-rule]: rule 'again' cannot be reached from start rule 's'",
+                "s[undefined-rule]: undefined rule 'sep'",
+                "c[duplicate-rule]: rule 'c' is defined more than once \
+                 (merge the definitions into one rule with several alternatives)",
+                "a[attribute-contains-element]: attribute 'a' would contain element 'n' \
+                 (only its text is kept; hide it with -n if that is intended)",
+                "a[attribute-contains-element]: attribute 'a' would contain element 'x' \
+                 (only its text is kept; hide it with -x if that is intended)",
+                "h[nullable-repetition]: (x; \"y\"*)+ repeats something that can match nothing \
+                 (make the repeated part match at least one character)",
+            ]
+        );
+
+        let clean = crate::grammar_ast::parse_ixml_grammar(r#"s: @a++",". a: -d+. -d: ["0"-"9"]."#)
+            .unwrap();
+        assert!(lint(&clean).is_empty());
+    }
+
+    #[test]
+    fn test_unused_and_unreachable_rules() {
+        let grammar = crate::grammar_ast::parse_ixml_grammar(
+            r#"s: a, s?. a: "a". self: "x", self. b: c. c: b. d: "d". d: s."#,
+        )
+        .unwrap();
+        let analysis = GrammarAnalysis::analyze(&grammar);
+        assert_eq!(analysis.unused_rules(), ["self", "d"]);
+        assert_eq!(analysis.unreachable_rules(), ["self", "b", "c", "d"]);
+
+        let empty = IxmlGrammar::new(Vec::new());
+        assert!(GrammarAnalysis::analyze(&empty)
+            .unreachable_rules()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_alternative_overlaps() {
+        let grammar = crate::grammar_ast::parse_ixml_grammar(
+            r#"s: sp, "a"; "b"; sp, ["a"-"c"; #a]; "x". sp: " "*. t: "p"; "q"."#,
+        )
+        .unwrap();
+        let analysis = GrammarAnalysis::analyze(&grammar);
+        let found: Vec<String> = analysis
+            .alternative_overlaps
+            .iter()
+            .map(|o| o.to_string())
+            .collect();
+        assert_eq!(
+            found,
+            [
+                r#"s: alternatives 1 and 3 can both start with [#20; "a"]"#,
+                r#"s: alternatives 2 and 3 can both start with ["b"]"#,
+            ]
+        );
+        assert!(analysis.report().contains(
+            "ℹ️  Alternatives that can start with the same character:\n   - s: alternatives 1 and 3"
+        ));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let grammar = crate::grammar_ast::parse_ixml_grammar(
+            r#"s: ("a"; "a", "b")*, "c", x. x: "q", y. y: x; "y". z: "z"."#,
+        )
+        .unwrap();
+        let analysis = GrammarAnalysis::analyze(&grammar);
+        assert_eq!(
+            analysis.to_json(),
+            concat!(
+                r#"{"undefined_references":[],"potentially_ambiguous":false,"#,
+                r#""left_recursive_rules":[],"recursive_rules":["s","x","y"],"#,
+                r#""backtracking_hazards":[{"rule":"s","pattern":"(\"a\"; \"a\", \"b\")*","#,
+                r#""kind":"overlapping-alternatives","reproduction":"aaaaaaaaaaaaaaaa!"}],"#,
+                r#""alternative_overlaps":[],"unused_rules":["z"],"unreachable_rules":["z"],"#,
+                r#""complexity":{"s":14,"x":5,"y":6,"z":3}}"#
+            )
+        );
+        assert!(analysis
+            .report()
+            .contains("ℹ️  Unreachable rules (never used from the start rule):\n   - z\n"));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let grammar = crate::grammar_ast::parse_ixml_grammar(
+            r#"list: "(", item**",", ")". item: -d; list; missing. -d: ["0"-"9"]. old: d."#,
+        )
+        .unwrap();
+        let dot = GrammarAnalysis::analyze(&grammar).to_dot();
+        assert_eq!(
+            dot.lines().collect::<Vec<_>>(),
+            [
+                "digraph grammar {",
+                "  node [shape=box];",
+                "  \"list\" [peripheries=2, color=orange, fontcolor=orange];",
+                "  \"item\" [color=orange, fontcolor=orange];",
+                "  \"d\" [label=\"-d\"];",
+                "  \"old\" [color=grey, fontcolor=grey];",
+                "  \"missing\" [style=dashed];",
+                "  \"list\" -> \"item\" [color=orange];",
+                "  \"item\" -> \"d\";",
+                "  \"item\" -> \"list\" [color=orange];",
+                "  \"item\" -> \"missing\";",
+                "  \"old\" -> \"d\";",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let grammar = crate::grammar_parser::parse_ixml_grammar(
+            r#"s: (a, (b; "x")*)+, +"ins", ["q"]. a: "a"; "b"; "c". b: ["0"-"9"]."#,
+        )
+        .unwrap();
+        let stats = GrammarAnalysis::analyze(&grammar).stats().clone();
+        assert_eq!(stats.rules, 3);
+        assert_eq!((stats.terminals, stats.character_classes), (6, 2));
+        assert_eq!(stats.max_nesting_depth, 2);
+        // s: 3 factors, inner groups of 2 and 1 + 1 factors, two repetitions;
+        // a: 3 alternatives of 1 factor; b: 1 factor
+        assert_eq!(stats.estimated_states, 4 + 3 + 4 + 2 * 2 + 3 * 2 + 2);
+        assert_eq!(
+            stats.to_string(),
+            [
+                "Rules:                 3",
+                "Terminals:             6 (2 character classes)",
+                "Alternatives per rule: 1 (2 rules), 3 (1 rule)",
+                "Max nesting depth:     2",
+                "Estimated states:      23",
+                "",
+            ]
+            .join("\n")
+        );
+    }
 }
@@ -4,7 +4,9 @@
 //! (previously used RustyLR GLR which had exponential performance issues)
 
 // Re-export the handwritten parser's parse function
-pub use crate::grammar_parser::parse_ixml_grammar;
+pub use crate::grammar_parser::{
+    parse_ixml_grammar, parse_ixml_grammar_with_options, GrammarLimits, GrammarOptions,
+};
 
 // Keep the old RustyLR implementation commented out for reference
 /*
@@ -427,4 +429,199 @@ mod tests {
             _ => panic!("Expected group"),
         }
     }
+
+    #[test]
+    fn test_version_prolog() {
+        let grammar = parse_ixml_grammar(r#"ixml version "1.0". s: "a"."#).unwrap();
+        assert_eq!(grammar.version.as_deref(), Some("1.0"));
+        assert!(!grammar.is_version_mismatch());
+        assert_eq!(grammar.to_string(), "ixml version \"1.0\".\n\ns: \"a\".\n");
+
+        let grammar = parse_ixml_grammar("ixml version '1.3'.\nixml: 'a'.").unwrap();
+        assert_eq!(grammar.version.as_deref(), Some("1.3"));
+        assert!(grammar.is_version_mismatch());
+        // "ixml" stays usable as a rule name
+        assert_eq!(grammar.rules[0].name, "ixml");
+
+        assert!(parse_ixml_grammar(r#"s: "a"."#).unwrap().version.is_none());
+        assert!(parse_ixml_grammar(r#"ixml version P: "a"."#).is_err());
+    }
+
+    #[test]
+    fn test_pragmas() {
+        use crate::ast::Pragma;
+
+        let source = r#"{[doc top]} {[rename root]} -s: "a", {[hint]} b**({[sep x]} ","). b: "b"."#;
+        let grammar = parse_ixml_grammar(source).unwrap();
+        let rule = &grammar.rules[0];
+        assert_eq!(rule.pragmas.len(), 2);
+        assert_eq!(rule.pragma("rename"), Some(&Pragma::new("rename", "root")));
+        assert_eq!(rule.mark, Mark::Hidden);
+        let factor = &rule.alternatives.alts[0].factors[1];
+        assert_eq!(factor.pragma("hint").unwrap().data, "");
+        assert!(grammar.rules[1].pragmas.is_empty());
+
+        let mut names = Vec::new();
+        grammar.visit_pragmas(|_, _, pragma| names.push(pragma.name.clone()));
+        assert_eq!(names, ["doc", "rename", "hint", "sep"]);
+
+        // Pragmas survive a round trip through iXML notation
+        assert_eq!(parse_ixml_grammar(&grammar.to_string()).unwrap(), grammar);
+        assert_eq!(
+            parse_ixml_grammar("{[ ]} s: 'a'.").unwrap_err(),
+            "Pragma without a name"
+        );
+    }
+
+    #[test]
+    fn test_nested_comments_wherever_whitespace_is_allowed() {
+        let plain = r#"ixml version "1.0". s: @n, -".", ~["x"]*, +"!". n: ["a"-"z";"_"]++",". "#;
+        let commented = r#"{head {nested}} ixml {a} version {b} "1.0" {c}. {d}
+            s {e}: {f} @ {g {h}} n {i}, - {j} ".", ~ {k} ["x"{l}]* {m}, + {n} "!" {o}.
+            n: [{p}"a"{q}-{r}"z";{s {t {u}}}"_"] ++ {v} "," {w}. {tail}"#;
+        assert_eq!(
+            parse_ixml_grammar(commented).unwrap(),
+            parse_ixml_grammar(plain).unwrap()
+        );
+
+        let err = parse_ixml_grammar("s: 'a' {one {two}.").unwrap_err();
+        assert!(
+            err.contains("Unclosed comment starting at line 1, column 8"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_hex_terminals() {
+        let grammar =
+            parse_ixml_grammar(r#"s: "a", #a, -#2013, +#9, ^#A0*, (#41; "b")++#2c."#).unwrap();
+        let values: Vec<_> = grammar.rules[0].alternatives.alts[0].factors[..5]
+            .iter()
+            .map(|factor| match &factor.base {
+                BaseFactor::Literal {
+                    value,
+                    insertion,
+                    mark,
+                } => (value.as_str(), *insertion, *mark),
+                other => panic!("expected a literal, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("a", false, Mark::None),
+                ("\n", false, Mark::None),
+                ("\u{2013}", false, Mark::Hidden),
+                ("\t", true, Mark::None),
+                ("\u{a0}", false, Mark::Promoted),
+            ]
+        );
+
+        // Invisible characters are written back as hex, so the grammar reads the same
+        assert_eq!(
+            grammar.to_string(),
+            "s: \"a\", #a, -\"\u{2013}\", +#9, ^#a0*, (\"A\"; \"b\")++\",\".\n"
+        );
+        assert_eq!(parse_ixml_grammar(&grammar.to_string()).unwrap(), grammar);
+    }
+
+    #[test]
+    fn test_doubled_quotes() {
+        let grammar = parse_ixml_grammar(
+            r#"s: 'it''s', "say ""hi""", [""""; "'"], ['''']+, [#20-'''']?, -"x""y"."#,
+        )
+        .unwrap();
+        let parser = crate::NativeParser::new(grammar.clone());
+        assert_eq!(
+            parser.parse(r#"it'ssay "hi""''!x"y"#).unwrap(),
+            r#"<s>it'ssay "hi""''!</s>"#
+        );
+        assert!(parser.parse(r#"it'ssay "hi"''x"y"#).is_ok());
+        assert!(parser.parse(r#"it'ssay "hi"x'x"y"#).is_err());
+
+        // Written back with doubled quotes
+        let literals: Vec<_> = grammar.rules[0].alternatives.alts[0].factors[..2]
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        assert_eq!(literals, [r#""it's""#, r#""say ""hi""""#]);
+        // Character classes are requoted, so compare what the grammar matches
+        let reparsed = parse_ixml_grammar(&grammar.to_string()).unwrap();
+        assert_eq!(reparsed.to_string(), grammar.to_string());
+        assert_eq!(
+            crate::NativeParser::new(reparsed).parse(r#"it'ssay "hi""''!x"y"#),
+            parser.parse(r#"it'ssay "hi""''!x"y"#)
+        );
+    }
+
+    #[test]
+    fn test_charclass_with_only_exclusions() {
+        let err = parse_ixml_grammar(r#"s: [~"a"; ~"b"]."#).unwrap_err();
+        assert!(err.contains("only has exclusions"), "{}", err);
+        assert!(parse_ixml_grammar(r#"s: ["a"-"c"; ~"b"]."#).is_ok());
+    }
+
+    #[test]
+    fn test_negative_lookahead_requires_extensions() {
+        let input = r#"rule: !"end", ["a"-"z"]."#;
+        let err = parse_ixml_grammar(input).unwrap_err();
+        assert!(err.contains("non-standard extension"));
+
+        let options = GrammarOptions {
+            allow_extensions: true,
+            ..GrammarOptions::default()
+        };
+        let grammar = parse_ixml_grammar_with_options(input, &options).unwrap();
+        match &grammar.rules[0].alternatives.alts[0].factors[0].base {
+            BaseFactor::NegativeLookahead { base } => {
+                assert_eq!(**base, BaseFactor::literal("end".to_string()))
+            }
+            _ => panic!("Expected negative lookahead"),
+        }
+    }
+
+    #[test]
+    fn test_regex_syntax_errors_carry_hints() {
+        let err = parse_ixml_grammar("word: [a-z]+.").unwrap_err();
+        assert!(err.starts_with("Unquoted range 'a-z' in character class [a-z]"));
+        assert!(err.contains("hint: line 1, column 7: `[a-z]`"));
+        assert!(err.contains(r#"`["a"-"z"]`"#));
+
+        let err = parse_ixml_grammar(r"num: \d+.").unwrap_err();
+        assert!(err.contains("[Nd]"));
+    }
+
+    #[test]
+    fn test_grammar_limits() {
+        let limits = GrammarLimits {
+            max_rules: 2,
+            max_alternatives: 3,
+            max_charclass_size: 10,
+            max_nesting: 2,
+        };
+        let options = GrammarOptions {
+            limits,
+            ..GrammarOptions::default()
+        };
+        let parse = |input: &str| parse_ixml_grammar_with_options(input, &options);
+
+        assert!(parse(r#"a: (("x"; "y"), b)**",". b: ["a"-"z"]; "1"; "2"."#).is_ok());
+
+        let err = parse(r#"a: "x". b: "y". c: "z"."#).unwrap_err();
+        assert!(err.contains("more than 2 rules (max_rules)"));
+        let err = parse(r#"a: "w"; "x"; "y"; "z"."#).unwrap_err();
+        assert!(err.contains("rule 'a' has more than 3 alternatives"));
+        let err = parse(r#"a: ["a"; "b"; "c"; "d"]."#).unwrap_err();
+        assert!(err.contains("max_charclass_size is 10"));
+        let nesting_err = parse(r#"a: ((("x")))."#).unwrap_err();
+        assert!(nesting_err.contains("rule 'a' nests groups more than 2 deep"));
+        let err = parse(r#"a: ("x"++("," ++ ";"))."#).unwrap_err();
+        assert!(err.contains("max_nesting"));
+
+        // The same limits apply to grammars built without the grammar parser
+        let grammar = parse_ixml_grammar(r#"a: ((("x")))."#).unwrap();
+        assert_eq!(limits.check(&grammar).unwrap_err(), nesting_err);
+        assert!(GrammarLimits::default().check(&grammar).is_ok());
+    }
 }
@@ -0,0 +1,430 @@
+//! Differences between two versions of a grammar
+//!
+//! [`grammar_diff`] matches rules by name, then lines up the alternatives of
+//! each rule present in both versions, and the factors of each alternative
+//! that changed, the way a text diff lines up lines. Rules and factors are
+//! compared as written, so `'a'` and `"a"` count as different.
+
+use crate::ast::{Factor, IxmlGrammar, Mark, Rule, Sequence};
+use std::fmt;
+
+/// What changed between two grammars, from [`grammar_diff`]
+///
+/// `Display` lists the changes one per line, numbering alternatives and
+/// factors from 1.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GrammarDiff {
+    /// The old and new start rule, if it changed
+    pub start: Option<(String, String)>,
+    /// Rules only the new grammar has, in its order
+    pub added: Vec<Rule>,
+    /// Rules only the old grammar has, in its order
+    pub removed: Vec<Rule>,
+    /// Rules both have but define differently, in the new grammar's order
+    pub changed: Vec<RuleChange>,
+}
+
+/// How a rule changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleChange {
+    pub rule: String,
+    /// The old and new mark, if it changed
+    pub mark: Option<(Mark, Mark)>,
+    /// Whether the pragmas before the rule changed
+    pub pragmas_changed: bool,
+    pub alternatives: Vec<AlternativeChange>,
+}
+
+/// A change to one alternative of a rule
+///
+/// Indexes count from 0; `old` ones are into the old rule and `new` ones into
+/// the new rule.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlternativeChange {
+    Added {
+        new: usize,
+        alternative: Sequence,
+    },
+    Removed {
+        old: usize,
+        alternative: Sequence,
+    },
+    /// An alternative edited in place, with its factor changes
+    Changed {
+        old: usize,
+        new: usize,
+        factors: Vec<FactorChange>,
+    },
+}
+
+/// A factor added to or removed from an alternative
+///
+/// A factor that was edited shows up as removed and then added.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FactorChange {
+    Added { new: usize, factor: Factor },
+    Removed { old: usize, factor: Factor },
+}
+
+impl GrammarDiff {
+    /// Whether the grammars are the same
+    pub fn is_empty(&self) -> bool {
+        self.start.is_none()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for GrammarDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((old, new)) = &self.start {
+            writeln!(f, "start rule: {} -> {}", old, new)?;
+        }
+        for rule in &self.removed {
+            writeln!(f, "removed rule: {}", rule)?;
+        }
+        for rule in &self.added {
+            writeln!(f, "added rule: {}", rule)?;
+        }
+        for change in &self.changed {
+            write!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RuleChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "changed rule: {}", self.rule)?;
+        if let Some((old, new)) = self.mark {
+            let name = |mark: Mark| match mark {
+                Mark::None => "unmarked".to_string(),
+                mark => mark.to_string(),
+            };
+            writeln!(f, "  mark: {} -> {}", name(old), name(new))?;
+        }
+        if self.pragmas_changed {
+            writeln!(f, "  pragmas changed")?;
+        }
+        for change in &self.alternatives {
+            match change {
+                AlternativeChange::Added { new, alternative } => {
+                    writeln!(f, "  added alternative {}: {}", new + 1, alternative)?
+                }
+                AlternativeChange::Removed { old, alternative } => {
+                    writeln!(f, "  removed alternative {}: {}", old + 1, alternative)?
+                }
+                AlternativeChange::Changed { old, new, factors } => {
+                    if old == new {
+                        writeln!(f, "  alternative {}:", new + 1)?;
+                    } else {
+                        writeln!(f, "  alternative {} (was {}):", new + 1, old + 1)?;
+                    }
+                    for change in factors {
+                        match change {
+                            FactorChange::Added { new, factor } => {
+                                writeln!(f, "    added factor {}: {}", new + 1, factor)?
+                            }
+                            FactorChange::Removed { old, factor } => {
+                                writeln!(f, "    removed factor {}: {}", old + 1, factor)?
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compare two versions of a grammar
+///
+/// ```
+/// use rustixml::grammar_diff::grammar_diff;
+/// use rustixml::parse_ixml_grammar;
+///
+/// let old = parse_ixml_grammar(r#"date: day, "-", month. day: d, d. month: d, d. d: ["0"-"9"]."#)
+///     .unwrap();
+/// let new = parse_ixml_grammar(
+///     r#"date: day, "/", month; month, " ", day. day: d, d?. -month: d, d. d: ["0"-"9"]."#,
+/// )
+/// .unwrap();
+/// assert_eq!(
+///     grammar_diff(&old, &new).to_string(),
+///     [
+///         "changed rule: date",
+///         "  alternative 1:",
+///         r#"    removed factor 2: "-""#,
+///         r#"    added factor 2: "/""#,
+///         r#"  added alternative 2: month, " ", day"#,
+///         "changed rule: day",
+///         "  alternative 1:",
+///         "    removed factor 2: d",
+///         "    added factor 2: d?",
+///         "changed rule: month",
+///         "  mark: unmarked -> -",
+///         "",
+///     ]
+///     .join("\n")
+/// );
+/// assert!(grammar_diff(&new, &new).is_empty());
+/// ```
+pub fn grammar_diff(old: &IxmlGrammar, new: &IxmlGrammar) -> GrammarDiff {
+    let find = |grammar: &IxmlGrammar, name: &str| {
+        grammar.rules.iter().find(|rule| rule.name == name).cloned()
+    };
+    let start = match (old.rules.first(), new.rules.first()) {
+        (Some(old), Some(new)) if old.name != new.name => {
+            Some((old.name.clone(), new.name.clone()))
+        }
+        _ => None,
+    };
+    let removed = old
+        .rules
+        .iter()
+        .filter(|rule| find(new, &rule.name).is_none())
+        .cloned()
+        .collect();
+
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for rule in &new.rules {
+        match find(old, &rule.name) {
+            None => added.push(rule.clone()),
+            Some(before) if before != *rule => changed.push(rule_change(&before, rule)),
+            Some(_) => {}
+        }
+    }
+    GrammarDiff {
+        start,
+        added,
+        removed,
+        changed,
+    }
+}
+
+fn rule_change(old: &Rule, new: &Rule) -> RuleChange {
+    let (old_alts, new_alts) = (&old.alternatives.alts, &new.alternatives.alts);
+    let mut alternatives = Vec::new();
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let mut flush = |removed: &mut Vec<usize>, added: &mut Vec<usize>| {
+        alternatives.extend(pair_alternatives(old_alts, new_alts, removed, added));
+        removed.clear();
+        added.clear();
+    };
+    for edit in edits(old_alts, new_alts) {
+        match edit {
+            Edit::Keep => flush(&mut removed, &mut added),
+            Edit::Remove(o) => removed.push(o),
+            Edit::Add(n) => added.push(n),
+        }
+    }
+    flush(&mut removed, &mut added);
+
+    RuleChange {
+        rule: new.name.clone(),
+        mark: (old.mark != new.mark).then_some((old.mark, new.mark)),
+        pragmas_changed: old.pragmas != new.pragmas,
+        alternatives,
+    }
+}
+
+/// Alternatives removed and added between the same two unchanged ones, with
+/// each removed one paired with the added one it shares most factors with,
+/// in order, as an edit
+fn pair_alternatives(
+    old: &[Sequence],
+    new: &[Sequence],
+    removed: &[usize],
+    added: &[usize],
+) -> Vec<AlternativeChange> {
+    let shared = |o: usize, n: usize| {
+        edits(&old[o].factors, &new[n].factors)
+            .iter()
+            .filter(|edit| matches!(edit, Edit::Keep))
+            .count()
+    };
+    // best[i][j]: most factors shared by pairs among removed[i..] and added[j..]
+    let mut best = vec![vec![0usize; added.len() + 1]; removed.len() + 1];
+    for i in (0..removed.len()).rev() {
+        for j in (0..added.len()).rev() {
+            let paired = match shared(removed[i], added[j]) {
+                0 => 0,
+                common => common + best[i + 1][j + 1],
+            };
+            best[i][j] = paired.max(best[i + 1][j]).max(best[i][j + 1]);
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut changes = Vec::new();
+    while i < removed.len() || j < added.len() {
+        if i < removed.len() && j < added.len() {
+            let common = shared(removed[i], added[j]);
+            if common > 0 && best[i][j] == common + best[i + 1][j + 1] {
+                changes.push(AlternativeChange::Changed {
+                    old: removed[i],
+                    new: added[j],
+                    factors: factor_changes(&old[removed[i]], &new[added[j]]),
+                });
+                i += 1;
+                j += 1;
+                continue;
+            }
+        }
+        if i < removed.len() && (j == added.len() || best[i][j] == best[i + 1][j]) {
+            changes.push(AlternativeChange::Removed {
+                old: removed[i],
+                alternative: old[removed[i]].clone(),
+            });
+            i += 1;
+        } else {
+            changes.push(AlternativeChange::Added {
+                new: added[j],
+                alternative: new[added[j]].clone(),
+            });
+            j += 1;
+        }
+    }
+    changes
+}
+
+fn factor_changes(old: &Sequence, new: &Sequence) -> Vec<FactorChange> {
+    edits(&old.factors, &new.factors)
+        .into_iter()
+        .filter_map(|edit| match edit {
+            Edit::Keep => None,
+            Edit::Remove(o) => Some(FactorChange::Removed {
+                old: o,
+                factor: old.factors[o].clone(),
+            }),
+            Edit::Add(n) => Some(FactorChange::Added {
+                new: n,
+                factor: new.factors[n].clone(),
+            }),
+        })
+        .collect()
+}
+
+/// One step of turning a list into another
+enum Edit {
+    Keep,
+    Remove(usize),
+    Add(usize),
+}
+
+/// The shortest edit from `old` to `new`, by longest common subsequence;
+/// removals come before additions at the same place
+fn edits<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    // common[i][j]: length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut edits = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push(Edit::Keep);
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            edits.push(Edit::Remove(i));
+            i += 1;
+        } else {
+            edits.push(Edit::Add(j));
+            j += 1;
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar_parser::parse_ixml_grammar;
+
+    fn diff(old: &str, new: &str) -> GrammarDiff {
+        grammar_diff(
+            &parse_ixml_grammar(old).unwrap(),
+            &parse_ixml_grammar(new).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_rules_added_and_removed() {
+        let diff = diff("a: b. b: 'x'. c: 'y'.", "z: a. a: b. b: 'x'. d: 'w'.");
+        assert_eq!(diff.start, Some(("a".to_string(), "z".to_string())));
+        let names = |rules: &[Rule]| rules.iter().map(|r| r.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&diff.added), ["z", "d"]);
+        assert_eq!(names(&diff.removed), ["c"]);
+        assert!(diff.changed.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            "start rule: a -> z\nremoved rule: c: \"y\".\nadded rule: z: a.\nadded rule: d: \"w\".\n"
+        );
+    }
+
+    #[test]
+    fn test_alternative_changes() {
+        let diff = diff(
+            "s: 'a'; 'b', c; 'd'. c: 'c'.",
+            "{[p]} s: 'b', c, 'e'; 'd'; 'f'. c: 'c'.",
+        );
+        assert_eq!(diff.changed.len(), 1);
+        let change = &diff.changed[0];
+        assert!(change.pragmas_changed);
+        assert_eq!(change.mark, None);
+        assert_eq!(
+            change.alternatives,
+            [
+                AlternativeChange::Removed {
+                    old: 0,
+                    alternative: parse_ixml_grammar("s: 'a'.").unwrap().rules[0]
+                        .alternatives
+                        .alts[0]
+                        .clone(),
+                },
+                AlternativeChange::Changed {
+                    old: 1,
+                    new: 0,
+                    factors: vec![FactorChange::Added {
+                        new: 2,
+                        factor: Factor::new(
+                            crate::ast::BaseFactor::literal("e".to_string()),
+                            crate::ast::Repetition::None
+                        ),
+                    }],
+                },
+                AlternativeChange::Added {
+                    new: 2,
+                    alternative: parse_ixml_grammar("s: 'f'.").unwrap().rules[0]
+                        .alternatives
+                        .alts[0]
+                        .clone(),
+                },
+            ]
+        );
+
+        // An edited alternative is matched with the one it replaced
+        let diff = self::diff("s: 'a', b, 'c'; 'z'.", "s: 'a', 'c', b; 'z'.");
+        assert_eq!(
+            diff.to_string(),
+            [
+                "changed rule: s",
+                "  alternative 1:",
+                "    removed factor 2: b",
+                "    added factor 3: b",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -3,17 +3,203 @@
 //! This replaces the RustyLR GLR parser which had exponential performance issues
 //! with complex grammars containing circular references and repetitions.
 
-use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Repetition, Rule, Sequence};
+use crate::ast::{
+    Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Pragma, Repetition, Rule, Sequence,
+};
+use crate::charclass::has_only_exclusions;
 use crate::lexer::Token;
+use crate::regex_hints::{unquoted_range, with_regex_hints};
+
+/// Options controlling which grammar syntax is accepted
+#[derive(Debug, Clone, Default)]
+pub struct GrammarOptions {
+    /// Accept non-standard extensions (negative lookahead `!factor`).
+    /// Off by default: strict mode rejects anything outside the iXML specification.
+    pub allow_extensions: bool,
+    /// Size limits enforced while parsing (unlimited by default)
+    pub limits: GrammarLimits,
+}
+
+/// Size limits for grammars from untrusted sources
+///
+/// Grammars written by end users can make compilation itself expensive
+/// (thousands of rules, enormous character classes, pathological nesting).
+/// Limits are checked while parsing grammar text, and by
+/// [`GrammarLimits::check`] for grammars built directly as an AST.
+///
+/// ```
+/// use rustixml::{parse_ixml_grammar_with_options, GrammarLimits, GrammarOptions};
+///
+/// let options = GrammarOptions {
+///     limits: GrammarLimits::untrusted(),
+///     ..GrammarOptions::default()
+/// };
+/// let nested = format!("s: {}\"x\"{}.", "(".repeat(100), ")".repeat(100));
+/// let err = parse_ixml_grammar_with_options(&nested, &options).unwrap_err();
+/// assert!(err.contains("max_nesting"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrammarLimits {
+    /// Maximum number of rules
+    pub max_rules: usize,
+    /// Maximum alternatives in one rule or group
+    pub max_alternatives: usize,
+    /// Maximum length, in characters, of one character class's content
+    pub max_charclass_size: usize,
+    /// Maximum depth of nested groups, separators and lookaheads
+    pub max_nesting: usize,
+}
+
+impl Default for GrammarLimits {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+impl GrammarLimits {
+    /// No limits (the default)
+    pub const fn unlimited() -> Self {
+        GrammarLimits {
+            max_rules: usize::MAX,
+            max_alternatives: usize::MAX,
+            max_charclass_size: usize::MAX,
+            max_nesting: usize::MAX,
+        }
+    }
+
+    /// Conservative limits for grammars supplied by end users
+    pub const fn untrusted() -> Self {
+        GrammarLimits {
+            max_rules: 1000,
+            max_alternatives: 256,
+            max_charclass_size: 4096,
+            max_nesting: 64,
+        }
+    }
+
+    /// Check a grammar AST against these limits
+    pub fn check(&self, grammar: &IxmlGrammar) -> Result<(), String> {
+        self.check_rule_count(grammar.rules.len())?;
+        for rule in &grammar.rules {
+            self.check_alternatives_limits(&rule.name, &rule.alternatives, 0)?;
+        }
+        Ok(())
+    }
+
+    fn check_alternatives_limits(
+        &self,
+        rule: &str,
+        alts: &Alternatives,
+        depth: usize,
+    ) -> Result<(), String> {
+        self.check_alternative_count(rule, alts.alts.len())?;
+        for seq in &alts.alts {
+            self.check_sequence_limits(rule, seq, depth)?;
+        }
+        Ok(())
+    }
+
+    fn check_sequence_limits(
+        &self,
+        rule: &str,
+        seq: &Sequence,
+        depth: usize,
+    ) -> Result<(), String> {
+        for factor in &seq.factors {
+            self.check_base_limits(rule, &factor.base, depth)?;
+            if let Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) =
+                &factor.repetition
+            {
+                self.check_nesting(rule, depth + 1)?;
+                self.check_sequence_limits(rule, sep, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_base_limits(&self, rule: &str, base: &BaseFactor, depth: usize) -> Result<(), String> {
+        match base {
+            BaseFactor::CharClass { content, .. } => self.check_charclass_size(rule, content),
+            BaseFactor::Group { alternatives } => {
+                self.check_nesting(rule, depth + 1)?;
+                self.check_alternatives_limits(rule, alternatives, depth + 1)
+            }
+            BaseFactor::NegativeLookahead { base } => {
+                self.check_nesting(rule, depth + 1)?;
+                self.check_base_limits(rule, base, depth + 1)
+            }
+            BaseFactor::Literal { .. } | BaseFactor::Nonterminal { .. } => Ok(()),
+        }
+    }
+
+    fn check_rule_count(&self, count: usize) -> Result<(), String> {
+        if count > self.max_rules {
+            return Err(format!(
+                "Grammar limit exceeded: more than {} rules (max_rules)",
+                self.max_rules
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_alternative_count(&self, rule: &str, count: usize) -> Result<(), String> {
+        if count > self.max_alternatives {
+            return Err(format!(
+                "Grammar limit exceeded: rule '{}' has more than {} alternatives in one choice (max_alternatives)",
+                rule, self.max_alternatives
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_charclass_size(&self, rule: &str, content: &str) -> Result<(), String> {
+        let size = content.chars().count();
+        if size > self.max_charclass_size {
+            return Err(format!(
+                "Grammar limit exceeded: rule '{}' has a {}-character class [{}...] (max_charclass_size is {})",
+                rule,
+                size,
+                content.chars().take(20).collect::<String>(),
+                self.max_charclass_size
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_nesting(&self, rule: &str, depth: usize) -> Result<(), String> {
+        if depth > self.max_nesting {
+            return Err(format!(
+                "Grammar limit exceeded: rule '{}' nests groups more than {} deep (max_nesting)",
+                rule, self.max_nesting
+            ));
+        }
+        Ok(())
+    }
+}
 
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    options: GrammarOptions,
+    /// Rule being parsed (for limit errors)
+    rule_name: String,
+    /// Current group/separator/lookahead nesting depth
+    depth: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, pos: 0 }
+        Self::with_options(tokens, GrammarOptions::default())
+    }
+
+    pub fn with_options(tokens: Vec<Token>, options: GrammarOptions) -> Self {
+        Parser {
+            tokens,
+            pos: 0,
+            options,
+            rule_name: String::new(),
+            depth: 0,
+        }
     }
 
     fn peek(&self) -> Option<&Token> {
@@ -56,9 +242,45 @@ impl Parser {
         char::from_u32(code_point).ok_or_else(|| format!("Invalid Unicode code point: #{}", hex))
     }
 
+    // Range endpoints must be quoted or hex: `["a"-"z"]`, not regex-style `[a-z]`
+    fn check_charclass(&self, content: &str) -> Result<(), String> {
+        self.options
+            .limits
+            .check_charclass_size(&self.rule_name, content)?;
+        if let Some(range) = unquoted_range(content) {
+            return Err(format!(
+                "Unquoted range '{}' in character class [{}]",
+                range, content
+            ));
+        }
+        if has_only_exclusions(content) {
+            return Err(format!(
+                "Character class [{}] only has exclusions, so it matches nothing; \
+                 use ~[...] to match every other character",
+                content
+            ));
+        }
+        Ok(())
+    }
+
+    // Parse a group, separator or lookahead one nesting level deeper
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.options
+            .limits
+            .check_nesting(&self.rule_name, self.depth + 1)?;
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
     // Grammar: [VersionDecl] Rule+
     pub fn parse_grammar(&mut self) -> Result<IxmlGrammar, String> {
         // Check for optional ixml version "1.0"
+        let mut version = None;
         if self.tokens.get(self.pos) == Some(&Token::Ident("ixml".to_string()))
             && self.tokens.get(self.pos + 1) == Some(&Token::Ident("version".to_string()))
         {
@@ -67,9 +289,8 @@ impl Parser {
             self.consume(); // version
 
             match self.expect("version string")? {
-                Token::String(_) => {
-                    // Any version string is accepted by the parser.
-                }
+                // Any version string is accepted; unsupported ones are flagged at parse time
+                Token::String(s) => version = Some(s),
                 other => return Err(format!("Expected version string, got {:?}", other)),
             }
 
@@ -82,6 +303,7 @@ impl Parser {
         let mut rules = Vec::new();
 
         while !self.at_end() {
+            self.options.limits.check_rule_count(rules.len() + 1)?;
             rules.push(self.parse_rule()?);
         }
 
@@ -89,11 +311,15 @@ impl Parser {
             return Err("Grammar must contain at least one rule".to_string());
         }
 
-        Ok(IxmlGrammar::new(rules))
+        let mut grammar = IxmlGrammar::new(rules);
+        grammar.version = version;
+        Ok(grammar)
     }
 
     // Rule: [Mark] Ident (":" | "=") Alternatives "."
     fn parse_rule(&mut self) -> Result<Rule, String> {
+        let pragmas = self.parse_pragmas()?;
+
         // Check for mark prefix
         let mark = if self.matches(&Token::At) {
             self.consume();
@@ -113,6 +339,7 @@ impl Parser {
             Token::Ident(s) => s,
             other => return Err(format!("Expected identifier, got {:?}", other)),
         };
+        self.rule_name = name.clone();
 
         // Expect colon or equals
         if !self.matches(&Token::Colon) && !self.matches(&Token::Equals) {
@@ -129,7 +356,17 @@ impl Parser {
         }
         self.consume();
 
-        Ok(Rule::new(name, mark, alternatives))
+        Ok(Rule::new(name, mark, alternatives).with_pragmas(pragmas))
+    }
+
+    // Pragmas: ("{[" name data "]}")*
+    fn parse_pragmas(&mut self) -> Result<Vec<Pragma>, String> {
+        let mut pragmas = Vec::new();
+        while let Some(Token::Pragma(content)) = self.peek() {
+            pragmas.push(Pragma::parse(content)?);
+            self.consume();
+        }
+        Ok(pragmas)
     }
 
     // Alternatives: Sequence ("|" | ";") Sequence*
@@ -139,6 +376,9 @@ impl Parser {
         // Check which separator is used (pipe or semicolon)
         while self.matches(&Token::Pipe) || self.matches(&Token::Semicolon) {
             self.consume();
+            self.options
+                .limits
+                .check_alternative_count(&self.rule_name, alts.len() + 1)?;
             alts.push(self.parse_sequence()?);
         }
 
@@ -183,6 +423,7 @@ impl Parser {
 
     // Factor: BaseFactor [Repetition]
     fn parse_factor(&mut self) -> Result<Factor, String> {
+        let pragmas = self.parse_pragmas()?;
         let base = self.parse_base_factor()?;
 
         // Check for repetition operators
@@ -192,36 +433,12 @@ impl Parser {
         } else if self.matches(&Token::DoubleStar) {
             self.consume();
             // Separator can be: **(sep) or **sep
-            let sep = if self.matches(&Token::LParen) {
-                self.consume();
-                let s = self.parse_sequence()?;
-                if !self.matches(&Token::RParen) {
-                    return Err("Expected ')' after separator".to_string());
-                }
-                self.consume();
-                s
-            } else {
-                // Parse a single factor and wrap in a sequence
-                let factor = self.parse_base_factor()?;
-                Sequence::new(vec![Factor::simple(factor)])
-            };
+            let sep = self.nested(Self::parse_separator)?;
             Some(Repetition::SeparatedZeroOrMore(Box::new(sep)))
         } else if self.matches(&Token::DoublePlus) {
             self.consume();
             // Separator can be: ++(sep) or ++sep
-            let sep = if self.matches(&Token::LParen) {
-                self.consume();
-                let s = self.parse_sequence()?;
-                if !self.matches(&Token::RParen) {
-                    return Err("Expected ')' after separator".to_string());
-                }
-                self.consume();
-                s
-            } else {
-                // Parse a single factor and wrap in a sequence
-                let factor = self.parse_base_factor()?;
-                Sequence::new(vec![Factor::simple(factor)])
-            };
+            let sep = self.nested(Self::parse_separator)?;
             Some(Repetition::SeparatedOneOrMore(Box::new(sep)))
         } else if self.matches(&Token::Star) {
             self.consume();
@@ -233,11 +450,36 @@ impl Parser {
             None
         };
 
-        Ok(if let Some(rep) = repetition {
-            Factor::new(base, rep)
+        let factor = match repetition {
+            Some(rep) => Factor::new(base, rep),
+            None => Factor::simple(base),
+        };
+        Ok(factor.with_pragmas(pragmas))
+    }
+
+    // Separator: "(" Sequence ")" | BaseFactor
+    fn parse_separator(&mut self) -> Result<Sequence, String> {
+        if self.matches(&Token::LParen) {
+            self.consume();
+            let mut alts = self.parse_alternatives()?;
+            if !self.matches(&Token::RParen) {
+                return Err("Expected ')' after separator".to_string());
+            }
+            self.consume();
+            // A choice of separators, e.g. `**(-" "; +" ")`, becomes a single group
+            if alts.alts.len() == 1 {
+                Ok(alts.alts.remove(0))
+            } else {
+                let group = BaseFactor::Group {
+                    alternatives: Box::new(alts),
+                };
+                Ok(Sequence::new(vec![Factor::simple(group)]))
+            }
         } else {
-            Factor::simple(base)
-        })
+            // Parse a single factor and wrap in a sequence
+            let factor = self.parse_base_factor()?;
+            Ok(Sequence::new(vec![Factor::simple(factor)]))
+        }
     }
 
     // BaseFactor: [Mark] (Ident | String | CharClass | HexChar | "(" Alternatives ")")
@@ -270,6 +512,7 @@ impl Parser {
                 }
                 Some(Token::CharClass(s)) => {
                     let s = s.clone();
+                    self.check_charclass(&s)?;
                     self.consume();
                     Ok(BaseFactor::marked_charclass(s, false, mark))
                 }
@@ -294,11 +537,24 @@ impl Parser {
                     other
                 )),
             }
+        } else if self.matches(&Token::Bang) {
+            // Negative lookahead: !factor (non-standard extension)
+            if !self.options.allow_extensions {
+                return Err("Negative lookahead '!' is a non-standard extension; \
+                     enable GrammarOptions::allow_extensions to use it"
+                    .to_string());
+            }
+            self.consume();
+            let base = self.nested(Self::parse_base_factor)?;
+            Ok(BaseFactor::negative_lookahead(base))
         } else if self.matches(&Token::Tilde) {
             // Exclusion: ~[charclass]
             self.consume();
             match self.expect("character class after '~'")? {
-                Token::CharClass(s) => Ok(BaseFactor::negated_charclass(s)),
+                Token::CharClass(s) => {
+                    self.check_charclass(&s)?;
+                    Ok(BaseFactor::negated_charclass(s))
+                }
                 other => Err(format!(
                     "Expected character class after '~', got {:?}",
                     other
@@ -319,6 +575,7 @@ impl Parser {
                 }
                 Some(Token::CharClass(s)) => {
                     let s = s.clone();
+                    self.check_charclass(&s)?;
                     self.consume();
                     Ok(BaseFactor::charclass(s))
                 }
@@ -330,7 +587,7 @@ impl Parser {
                 }
                 Some(Token::LParen) => {
                     self.consume();
-                    let alts = self.parse_alternatives()?;
+                    let alts = self.nested(Self::parse_alternatives)?;
                     if !self.matches(&Token::RParen) {
                         return Err("Expected ')' after grouped alternatives".to_string());
                     }
@@ -345,13 +602,21 @@ impl Parser {
 
 /// Parse an iXML grammar from a string
 pub fn parse_ixml_grammar(input: &str) -> Result<IxmlGrammar, String> {
+    parse_ixml_grammar_with_options(input, &GrammarOptions::default())
+}
+
+/// Parse an iXML grammar from a string with the given syntax options
+pub fn parse_ixml_grammar_with_options(
+    input: &str,
+    options: &GrammarOptions,
+) -> Result<IxmlGrammar, String> {
     use crate::lexer::Lexer;
 
     // Tokenize
     let mut lexer = Lexer::new(input);
     let tokens = lexer
         .tokenize()
-        .map_err(|e| format!("Lexer error: {}", e))?;
+        .map_err(|e| with_regex_hints(format!("Lexer error: {}", e), input))?;
 
     // Filter out EOF token
     let tokens: Vec<Token> = tokens
@@ -360,6 +625,8 @@ pub fn parse_ixml_grammar(input: &str) -> Result<IxmlGrammar, String> {
         .collect();
 
     // Parse
-    let mut parser = Parser::new(tokens);
-    parser.parse_grammar()
+    let mut parser = Parser::with_options(tokens, options.clone());
+    parser
+        .parse_grammar()
+        .map_err(|e| with_regex_hints(e, input))
 }
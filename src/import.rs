@@ -0,0 +1,1080 @@
+//! Grammars written in other notations
+//!
+//! [`from_ebnf`] reads the EBNF of W3C specifications such as XML and XPath:
+//!
+//! - rules are `name ::= expression`, optionally numbered as in `[4a]`
+//! - `|` separates alternatives, juxtaposition is sequence, `?`, `*` and `+`
+//!   are repetitions, and parentheses group
+//! - `"text"` and `'text'` are strings, `#x20` is a character, and `[a-z]`,
+//!   `[#x20-#x7E]` and `[^<&]` are character classes
+//! - `A - B` matches what `A` matches except what `B` matches
+//! - `/* comments */` and `[ wfc: ... ]`/`[ vc: ... ]` constraint notes are
+//!   ignored
+//!
+//! [`from_abnf`] reads the ABNF of RFC 5234, used by IETF protocol
+//! specifications.
+//!
+//! Every rule of the source grammar becomes an unmarked iXML rule, so each
+//! one is an element in parse results.
+
+use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Repetition, Rule, Sequence};
+use crate::charclass::RangeSet;
+
+/// Read a grammar in W3C EBNF notation
+///
+/// A difference between two character sets, such as `Char - [<&]` where
+/// `Char` is a rule matching a single character, becomes one character class.
+/// Any other difference `A - B` becomes `!B, A`, using rustixml's negative
+/// lookahead extension; that rejects input where `B` matches at the start of
+/// `A` rather than input where `B` matches all of it.
+///
+/// ```
+/// use rustixml::import::from_ebnf;
+/// use rustixml::NativeParser;
+///
+/// let grammar = from_ebnf(
+///     "[1] list ::= item (',' S? item)*
+///      [2] item ::= [a-z]+
+///      [3] S    ::= (#x20 | #x9)+ /* white space */",
+/// )
+/// .unwrap();
+/// assert_eq!(
+///     grammar.to_string(),
+///     "list: item, (\",\", S?, item)*.\nitem: [\"a\"-\"z\"]+.\nS: (\" \"; #9)+.\n"
+/// );
+/// let xml = NativeParser::new(grammar).parse("a, b").unwrap();
+/// assert_eq!(xml, "<list><item>a</item>,<S> </S><item>b</item></list>");
+/// ```
+pub fn from_ebnf(source: &str) -> Result<IxmlGrammar, String> {
+    let rules = EbnfParser::new(source).grammar()?;
+    let converter = Converter { rules: &rules };
+    Ok(IxmlGrammar::new(
+        rules
+            .iter()
+            .map(|(name, expr)| Rule::new(name.clone(), Mark::None, converter.alternatives(expr)))
+            .collect(),
+    ))
+}
+/// Read a grammar in ABNF notation (RFC 5234)
+///
+/// Rules are `name = elements`, and `name =/ elements` adds alternatives to
+/// an earlier rule. Rule names are case-insensitive; each one is spelt as
+/// where it is first defined. Quoted strings match letters in either case as
+/// in the RFC, unless written `%s"..."`. The RFC's core rules, such as
+/// `DIGIT` and `CRLF`, are added as hidden rules when used and not defined.
+///
+/// ```
+/// use rustixml::import::from_abnf;
+/// use rustixml::NativeParser;
+///
+/// let grammar = from_abnf(
+///     "time   = hour \":\" minute ; 24-hour clock
+///      hour   = 2DIGIT
+///      minute = %x30-35 DIGIT",
+/// )
+/// .unwrap();
+/// assert_eq!(
+///     grammar.to_string(),
+///     "time: hour, \":\", minute.\nhour: DIGIT, DIGIT.\n\
+///      minute: [\"0\"-\"5\"], DIGIT.\n-DIGIT: [\"0\"-\"9\"].\n"
+/// );
+/// let xml = NativeParser::new(grammar).parse("09:45").unwrap();
+/// assert_eq!(xml, "<time><hour>09</hour>:<minute>45</minute></time>");
+/// ```
+pub fn from_abnf(source: &str) -> Result<IxmlGrammar, String> {
+    let mut rules = AbnfParser::new(source).rules()?;
+    let core = AbnfParser::new(ABNF_CORE_RULES).rules()?;
+    let core_start = rules.len();
+    // Core rules can use other core rules, so keep adding until none are missing
+    loop {
+        let mut missing = Vec::new();
+        for (_, expr) in &rules {
+            expr.visit_names(&mut |name| {
+                let defined = rules
+                    .iter()
+                    .any(|(rule, _)| rule.eq_ignore_ascii_case(name));
+                if !defined
+                    && !missing
+                        .iter()
+                        .any(|m: &String| m.eq_ignore_ascii_case(name))
+                {
+                    missing.push(name.to_string());
+                }
+            });
+        }
+        let before = rules.len();
+        rules.extend(
+            core.iter()
+                .filter(|(rule, _)| missing.iter().any(|m| m.eq_ignore_ascii_case(rule)))
+                .cloned(),
+        );
+        if rules.len() == before {
+            break;
+        }
+    }
+
+    let names: Vec<String> = rules.iter().map(|(name, _)| name.clone()).collect();
+    for (_, expr) in &mut rules {
+        expr.rename(&|name| {
+            names
+                .iter()
+                .find(|rule| rule.eq_ignore_ascii_case(name))
+                .cloned()
+        });
+    }
+    let converter = Converter { rules: &rules };
+    Ok(IxmlGrammar::new(
+        rules
+            .iter()
+            .enumerate()
+            .map(|(i, (name, expr))| {
+                let mark = if i < core_start {
+                    Mark::None
+                } else {
+                    Mark::Hidden
+                };
+                Rule::new(name.clone(), mark, converter.alternatives(expr))
+            })
+            .collect(),
+    ))
+}
+
+/// The core rules of RFC 5234, appendix B.1
+const ABNF_CORE_RULES: &str = r#"
+    ALPHA  = %x41-5A / %x61-7A
+    BIT    = "0" / "1"
+    CHAR   = %x01-7F
+    CR     = %x0D
+    CRLF   = CR LF
+    CTL    = %x00-1F / %x7F
+    DIGIT  = %x30-39
+    DQUOTE = %x22
+    HEXDIG = DIGIT / "A" / "B" / "C" / "D" / "E" / "F"
+    HTAB   = %x09
+    LF     = %x0A
+    LWSP   = *(WSP / CRLF WSP)
+    OCTET  = %x00-FF
+    SP     = %x20
+    VCHAR  = %x21-7E
+    WSP    = SP / HTAB
+"#;
+
+/// An EBNF or ABNF expression, before it is turned into iXML
+#[derive(Clone)]
+enum Expr {
+    Name(String),
+    Literal(String),
+    Set(RangeSet),
+    Choice(Vec<Expr>),
+    Sequence(Vec<Expr>),
+    Repeat(Box<Expr>, Repetition),
+    Difference(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// The items in sequence, without a sequence of one
+    fn sequence(mut items: Vec<Expr>) -> Expr {
+        if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Expr::Sequence(items)
+        }
+    }
+
+    fn visit_names(&self, f: &mut impl FnMut(&str)) {
+        match self {
+            Expr::Name(name) => f(name),
+            Expr::Literal(_) | Expr::Set(_) => {}
+            Expr::Choice(items) | Expr::Sequence(items) => {
+                items.iter().for_each(|item| item.visit_names(f))
+            }
+            Expr::Repeat(item, _) => item.visit_names(f),
+            Expr::Difference(from, excluded) => {
+                from.visit_names(f);
+                excluded.visit_names(f);
+            }
+        }
+    }
+
+    /// Replace each name with the one `f` gives, if any
+    fn rename(&mut self, f: &impl Fn(&str) -> Option<String>) {
+        match self {
+            Expr::Name(name) => {
+                if let Some(renamed) = f(name) {
+                    *name = renamed;
+                }
+            }
+            Expr::Literal(_) | Expr::Set(_) => {}
+            Expr::Choice(items) | Expr::Sequence(items) => {
+                items.iter_mut().for_each(|item| item.rename(f))
+            }
+            Expr::Repeat(item, _) => item.rename(f),
+            Expr::Difference(from, excluded) => {
+                from.rename(f);
+                excluded.rename(f);
+            }
+        }
+    }
+}
+
+struct EbnfParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl EbnfParser {
+    fn new(source: &str) -> Self {
+        EbnfParser {
+            chars: source.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn looking_at(&self, at: usize, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(i, ch)| self.chars.get(at + i) == Some(&ch))
+    }
+
+    fn error(&self, message: &str) -> String {
+        let line = self.chars[..self.pos.min(self.chars.len())]
+            .iter()
+            .filter(|&&ch| ch == '\n')
+            .count()
+            + 1;
+        format!("EBNF line {}: {}", line, message)
+    }
+
+    fn grammar(&mut self) -> Result<Vec<(String, Expr)>, String> {
+        let mut rules = Vec::new();
+        self.skip_space()?;
+        while self.pos < self.chars.len() {
+            let (name, body) = self
+                .rule_start()
+                .ok_or_else(|| self.error("Expected a rule such as `name ::= expression`"))?;
+            self.pos = body;
+            let expr = self.choice()?;
+            if self.peek() == Some(')') {
+                return Err(self.error("Unmatched `)`"));
+            }
+            rules.push((name, expr));
+        }
+        if rules.is_empty() {
+            return Err("EBNF grammar has no rules".to_string());
+        }
+        Ok(rules)
+    }
+
+    /// Whitespace, comments and constraint notes
+    fn skip_space(&mut self) -> Result<(), String> {
+        loop {
+            while self.peek().is_some_and(char::is_whitespace) {
+                self.pos += 1;
+            }
+            if self.looking_at(self.pos, "/*") {
+                let end = (self.pos + 2..self.chars.len())
+                    .find(|&i| self.looking_at(i, "*/"))
+                    .ok_or_else(|| self.error("Unterminated comment"))?;
+                self.pos = end + 2;
+            } else if let Some(end) = self.constraint_end() {
+                self.pos = end;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// The end of a `[ wfc: ... ]` or `[ vc: ... ]` note starting here
+    fn constraint_end(&self) -> Option<usize> {
+        if self.peek() != Some('[') {
+            return None;
+        }
+        let rest: String = self.chars[self.pos + 1..].iter().take(8).collect();
+        let rest = rest.trim_start().to_ascii_lowercase();
+        if !(rest.starts_with("wfc:") || rest.starts_with("vc:")) {
+            return None;
+        }
+        (self.pos..self.chars.len())
+            .find(|&i| self.chars[i] == ']')
+            .map(|i| i + 1)
+    }
+
+    /// The name of a rule starting here, and where its expression starts
+    fn rule_start(&self) -> Option<(String, usize)> {
+        let mut at = self.pos;
+        let skip_whitespace = |mut at: usize| {
+            while self.chars.get(at).is_some_and(|ch| ch.is_whitespace()) {
+                at += 1;
+            }
+            at
+        };
+        // An optional rule number such as `[4a]`
+        if self.chars.get(at) == Some(&'[') {
+            let digits = (at + 1..self.chars.len())
+                .take_while(|&i| self.chars[i].is_ascii_alphanumeric())
+                .count();
+            if digits == 0 || self.chars.get(at + 1 + digits) != Some(&']') {
+                return None;
+            }
+            at = skip_whitespace(at + 2 + digits);
+        }
+        let start = at;
+        while self.chars.get(at).copied().is_some_and(is_name_char) {
+            at += 1;
+        }
+        if at == start {
+            return None;
+        }
+        let name: String = self.chars[start..at].iter().collect();
+        at = skip_whitespace(at);
+        self.looking_at(at, "::=").then_some((name, at + 3))
+    }
+
+    fn choice(&mut self) -> Result<Expr, String> {
+        let mut alts = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alts.push(self.sequence()?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.remove(0)
+        } else {
+            Expr::Choice(alts)
+        })
+    }
+
+    fn sequence(&mut self) -> Result<Expr, String> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_space()?;
+            match self.peek() {
+                None | Some('|') | Some(')') => break,
+                _ if self.rule_start().is_some() => break,
+                _ => items.push(self.difference()?),
+            }
+        }
+        Ok(Expr::sequence(items))
+    }
+
+    fn difference(&mut self) -> Result<Expr, String> {
+        let mut expr = self.item()?;
+        loop {
+            self.skip_space()?;
+            if self.peek() != Some('-') {
+                return Ok(expr);
+            }
+            self.pos += 1;
+            self.skip_space()?;
+            let excluded = self.item()?;
+            expr = Expr::Difference(Box::new(expr), Box::new(excluded));
+        }
+    }
+
+    fn item(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        loop {
+            let repetition = match self.peek() {
+                Some('?') => Repetition::Optional,
+                Some('*') => Repetition::ZeroOrMore,
+                Some('+') => Repetition::OneOrMore,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = Expr::Repeat(Box::new(expr), repetition);
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.choice()?;
+                if self.peek() != Some(')') {
+                    return Err(self.error("Expected `)`"));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(quote @ ('"' | '\'')) => {
+                let start = self.pos + 1;
+                let end = (start..self.chars.len())
+                    .find(|&i| self.chars[i] == quote)
+                    .ok_or_else(|| self.error("Unterminated string"))?;
+                self.pos = end + 1;
+                Ok(Expr::Literal(self.chars[start..end].iter().collect()))
+            }
+            Some('#') if self.looking_at(self.pos, "#x") => {
+                self.pos += 2;
+                Ok(Expr::Set(RangeSet::from_char(self.hex_char()?)))
+            }
+            Some('[') => self.class(),
+            Some(ch) if is_name_char(ch) => {
+                let start = self.pos;
+                while self.peek().is_some_and(is_name_char) {
+                    self.pos += 1;
+                }
+                Ok(Expr::Name(self.chars[start..self.pos].iter().collect()))
+            }
+            Some(ch) => Err(self.error(&format!("Unexpected `{}`", ch))),
+            None => Err(self.error("Unexpected end of grammar")),
+        }
+    }
+
+    /// The digits of `#xN`, after the `#x`
+    fn hex_char(&mut self) -> Result<char, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|ch| ch.is_ascii_hexdigit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error(&format!("Invalid character `#x{}`", digits)))
+    }
+
+    fn class(&mut self) -> Result<Expr, String> {
+        self.pos += 1;
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut set = RangeSet::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("Unterminated character class")),
+                Some(']') => break,
+                _ => {}
+            }
+            let from = self.class_char()?;
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                self.pos += 1;
+                let to = self.class_char()?;
+                if to < from {
+                    return Err(self.error("Character range ends before it starts"));
+                }
+                set.add_range(from, to);
+            } else {
+                set.add_char(from);
+            }
+        }
+        self.pos += 1;
+        Ok(Expr::Set(if negated { set.complement() } else { set }))
+    }
+
+    fn class_char(&mut self) -> Result<char, String> {
+        let is_hex = self.looking_at(self.pos, "#x")
+            && self
+                .chars
+                .get(self.pos + 2)
+                .is_some_and(|ch| ch.is_ascii_hexdigit());
+        if is_hex {
+            self.pos += 2;
+            return self.hex_char();
+        }
+        let ch = self
+            .peek()
+            .ok_or_else(|| self.error("Unterminated character class"))?;
+        self.pos += 1;
+        Ok(ch)
+    }
+}
+
+fn is_name_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '.'
+}
+
+struct AbnfParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl AbnfParser {
+    fn new(source: &str) -> Self {
+        AbnfParser {
+            chars: source.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error(&self, message: &str) -> String {
+        let line = self.chars[..self.pos.min(self.chars.len())]
+            .iter()
+            .filter(|&&ch| ch == '\n')
+            .count()
+            + 1;
+        format!("ABNF line {}: {}", line, message)
+    }
+
+    fn rules(&mut self) -> Result<Vec<(String, Expr)>, String> {
+        let mut rules: Vec<(String, Expr)> = Vec::new();
+        self.skip_space();
+        while self.pos < self.chars.len() {
+            let (name, incremental, body) = self
+                .rule_start()
+                .ok_or_else(|| self.error("Expected a rule such as `name = elements`"))?;
+            let existing = rules
+                .iter()
+                .position(|(rule, _)| rule.eq_ignore_ascii_case(&name));
+            self.pos = body;
+            let expr = self.alternation()?;
+            if matches!(self.peek(), Some(')' | ']')) {
+                return Err(self.error(&format!("Unmatched `{}`", self.chars[self.pos])));
+            }
+            match (existing, incremental) {
+                (None, false) => rules.push((name, expr)),
+                (Some(i), true) => {
+                    let alts = match std::mem::replace(&mut rules[i].1, Expr::Choice(Vec::new())) {
+                        Expr::Choice(alts) => alts,
+                        other => vec![other],
+                    };
+                    let added = match expr {
+                        Expr::Choice(more) => more,
+                        other => vec![other],
+                    };
+                    rules[i].1 = Expr::Choice(alts.into_iter().chain(added).collect());
+                }
+                (None, true) => {
+                    return Err(format!(
+                        "Rule '{}' is extended with =/ before it is defined",
+                        name
+                    ))
+                }
+                (Some(_), false) => return Err(format!("Rule '{}' is defined twice", name)),
+            }
+        }
+        if rules.is_empty() {
+            return Err("ABNF grammar has no rules".to_string());
+        }
+        Ok(rules)
+    }
+
+    /// Whitespace, line breaks and `;` comments
+    fn skip_space(&mut self) {
+        loop {
+            match self.peek() {
+                Some(ch) if ch.is_whitespace() => self.pos += 1,
+                Some(';') => {
+                    while self.peek().is_some_and(|ch| ch != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn rulename_end(&self, start: usize) -> usize {
+        if !self
+            .chars
+            .get(start)
+            .is_some_and(|ch| ch.is_ascii_alphabetic())
+        {
+            return start;
+        }
+        let mut at = start + 1;
+        while self
+            .chars
+            .get(at)
+            .is_some_and(|&ch| ch.is_ascii_alphanumeric() || ch == '-')
+        {
+            at += 1;
+        }
+        at
+    }
+
+    /// The name of a rule starting here, whether it is `=/`, and where its
+    /// elements start
+    fn rule_start(&self) -> Option<(String, bool, usize)> {
+        let end = self.rulename_end(self.pos);
+        if end == self.pos {
+            return None;
+        }
+        let mut at = end;
+        while self
+            .chars
+            .get(at)
+            .is_some_and(|&ch| ch == ' ' || ch == '\t')
+        {
+            at += 1;
+        }
+        if self.chars.get(at) != Some(&'=') {
+            return None;
+        }
+        let name = self.chars[self.pos..end].iter().collect();
+        if self.chars.get(at + 1) == Some(&'/') {
+            Some((name, true, at + 2))
+        } else {
+            Some((name, false, at + 1))
+        }
+    }
+
+    fn alternation(&mut self) -> Result<Expr, String> {
+        let mut alts = vec![self.concatenation()?];
+        while self.peek() == Some('/') {
+            self.pos += 1;
+            alts.push(self.concatenation()?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.remove(0)
+        } else {
+            Expr::Choice(alts)
+        })
+    }
+
+    fn concatenation(&mut self) -> Result<Expr, String> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                None | Some('/' | ')' | ']') => break,
+                _ if self.rule_start().is_some() => break,
+                _ => items.push(self.repetition()?),
+            }
+        }
+        Ok(Expr::sequence(items))
+    }
+
+    /// An element with an optional `n`, `n*m`, `n*` or `*m` repeat
+    fn repetition(&mut self) -> Result<Expr, String> {
+        let min = self.number();
+        let (min, max) = if self.peek() == Some('*') {
+            self.pos += 1;
+            (min.unwrap_or(0), self.number())
+        } else {
+            match min {
+                Some(n) => (n, Some(n)),
+                None => return self.element(),
+            }
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(self.error(&format!(
+                "Repeat {}*{} has a maximum below its minimum",
+                min,
+                max.unwrap()
+            )));
+        }
+        Ok(repeat(self.element()?, min, max))
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok()
+    }
+
+    fn element(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(open @ ('(' | '[')) => {
+                self.pos += 1;
+                let expr = self.alternation()?;
+                let close = if open == '(' { ')' } else { ']' };
+                if self.peek() != Some(close) {
+                    return Err(self.error(&format!("Expected `{}`", close)));
+                }
+                self.pos += 1;
+                Ok(if open == '(' {
+                    expr
+                } else {
+                    Expr::Repeat(Box::new(expr), Repetition::Optional)
+                })
+            }
+            Some('"') => Ok(case_insensitive(&self.string()?)),
+            Some('%') => {
+                self.pos += 1;
+                match self.peek().map(|ch| ch.to_ascii_lowercase()) {
+                    Some('s') => {
+                        self.pos += 1;
+                        Ok(Expr::Literal(self.string()?))
+                    }
+                    Some('i') => {
+                        self.pos += 1;
+                        Ok(case_insensitive(&self.string()?))
+                    }
+                    Some(base @ ('x' | 'd' | 'b')) => {
+                        self.pos += 1;
+                        self.num_val(match base {
+                            'x' => 16,
+                            'd' => 10,
+                            _ => 2,
+                        })
+                    }
+                    _ => Err(self.error("Expected `%x`, `%d`, `%b`, `%s` or `%i`")),
+                }
+            }
+            Some('<') => Err(self.error("Prose values (`<...>`) cannot be converted")),
+            Some(ch) if ch.is_ascii_alphabetic() => {
+                let end = self.rulename_end(self.pos);
+                let name = self.chars[self.pos..end].iter().collect();
+                self.pos = end;
+                Ok(Expr::Name(name))
+            }
+            Some(ch) => Err(self.error(&format!("Unexpected `{}`", ch))),
+            None => Err(self.error("Unexpected end of grammar")),
+        }
+    }
+
+    /// A `"..."` string, which has no escapes
+    fn string(&mut self) -> Result<String, String> {
+        if self.peek() != Some('"') {
+            return Err(self.error("Expected a string"));
+        }
+        let start = self.pos + 1;
+        let end = (start..self.chars.len())
+            .find(|&i| self.chars[i] == '"')
+            .ok_or_else(|| self.error("Unterminated string"))?;
+        self.pos = end + 1;
+        Ok(self.chars[start..end].iter().collect())
+    }
+
+    /// The rest of `%x41`, `%x30-39` or `%x0D.0A`
+    fn num_val(&mut self, radix: u32) -> Result<Expr, String> {
+        let first = self.num_char(radix)?;
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                let last = self.num_char(radix)?;
+                if last < first {
+                    return Err(self.error("Value range ends before it starts"));
+                }
+                Ok(Expr::Set(RangeSet::from_range(first, last)))
+            }
+            Some('.') => {
+                let mut value = first.to_string();
+                while self.peek() == Some('.') {
+                    self.pos += 1;
+                    value.push(self.num_char(radix)?);
+                }
+                Ok(Expr::Literal(value))
+            }
+            _ => Ok(Expr::Set(RangeSet::from_char(first))),
+        }
+    }
+
+    fn num_char(&mut self, radix: u32) -> Result<char, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|ch| ch.is_digit(radix)) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        u32::from_str_radix(&digits, radix)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error(&format!("Invalid character value `{}`", digits)))
+    }
+}
+
+/// `expr` repeated at least `min` and at most `max` times
+fn repeat(expr: Expr, min: usize, max: Option<usize>) -> Expr {
+    let mut items = vec![expr.clone(); min.saturating_sub(1)];
+    match (min, max) {
+        (0, None) => items.push(Expr::Repeat(Box::new(expr), Repetition::ZeroOrMore)),
+        (_, None) => items.push(Expr::Repeat(Box::new(expr), Repetition::OneOrMore)),
+        (_, Some(max)) => {
+            if min > 0 {
+                items.push(expr.clone());
+            }
+            // `(e, (e)?)?` rather than `e?, e?`, which would be ambiguous
+            let mut optional: Option<Expr> = None;
+            for _ in min..max {
+                let inner = match optional {
+                    Some(rest) => Expr::Sequence(vec![expr.clone(), rest]),
+                    None => expr.clone(),
+                };
+                optional = Some(Expr::Repeat(Box::new(inner), Repetition::Optional));
+            }
+            items.extend(optional);
+        }
+    }
+    Expr::sequence(items)
+}
+
+/// A quoted ABNF string, whose letters match in either case
+fn case_insensitive(value: &str) -> Expr {
+    let mut items = Vec::new();
+    let mut plain = String::new();
+    for ch in value.chars() {
+        if ch.is_ascii_alphabetic() {
+            if !plain.is_empty() {
+                items.push(Expr::Literal(std::mem::take(&mut plain)));
+            }
+            let mut set = RangeSet::from_char(ch.to_ascii_uppercase());
+            set.add_char(ch.to_ascii_lowercase());
+            items.push(Expr::Set(set));
+        } else {
+            plain.push(ch);
+        }
+    }
+    if !plain.is_empty() || items.is_empty() {
+        items.push(Expr::Literal(plain));
+    }
+    Expr::sequence(items)
+}
+
+/// Turns expressions into iXML, looking up rules to resolve differences
+struct Converter<'a> {
+    rules: &'a [(String, Expr)],
+}
+
+impl Converter<'_> {
+    fn alternatives(&self, expr: &Expr) -> Alternatives {
+        match expr {
+            Expr::Choice(alts) => Alternatives::new(
+                alts.iter()
+                    .map(|alt| Sequence::new(self.factors(alt)))
+                    .collect(),
+            ),
+            other => Alternatives::new(vec![Sequence::new(self.factors(other))]),
+        }
+    }
+
+    fn factors(&self, expr: &Expr) -> Vec<Factor> {
+        let plain = |base| vec![Factor::new(base, Repetition::None)];
+        match expr {
+            Expr::Name(name) => plain(BaseFactor::nonterminal(name.clone())),
+            Expr::Literal(value) => plain(BaseFactor::literal(value.clone())),
+            Expr::Set(set) => plain(class(set)),
+            Expr::Choice(_) => plain(BaseFactor::group(self.alternatives(expr))),
+            Expr::Sequence(items) => items.iter().flat_map(|item| self.factors(item)).collect(),
+            Expr::Repeat(item, repetition) => {
+                vec![Factor::new(self.base(item), repetition.clone())]
+            }
+            Expr::Difference(from, excluded) => {
+                match (self.char_set(from, 0), self.char_set(excluded, 0)) {
+                    (Some(from), Some(excluded)) => plain(class(&from.minus(&excluded))),
+                    _ => {
+                        let mut factors =
+                            plain(BaseFactor::negative_lookahead(self.base(excluded)));
+                        factors.extend(self.factors(from));
+                        factors
+                    }
+                }
+            }
+        }
+    }
+
+    /// `expr` as one factor, grouped if it takes several
+    fn base(&self, expr: &Expr) -> BaseFactor {
+        let mut factors = self.factors(expr);
+        if factors.len() == 1 && factors[0].repetition == Repetition::None {
+            return factors.remove(0).base;
+        }
+        BaseFactor::group(Alternatives::new(vec![Sequence::new(factors)]))
+    }
+
+    /// The characters `expr` matches, if it always matches exactly one
+    fn char_set(&self, expr: &Expr, depth: usize) -> Option<RangeSet> {
+        // Rules that refer to each other in a loop are not character sets
+        if depth > self.rules.len() {
+            return None;
+        }
+        match expr {
+            Expr::Set(set) => Some(set.clone()),
+            Expr::Literal(value) => {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => Some(RangeSet::from_char(ch)),
+                    _ => None,
+                }
+            }
+            Expr::Name(name) => {
+                let (_, body) = self.rules.iter().find(|(rule, _)| rule == name)?;
+                self.char_set(body, depth + 1)
+            }
+            Expr::Choice(alts) => alts.iter().try_fold(RangeSet::new(), |set, alt| {
+                Some(set.union(&self.char_set(alt, depth)?))
+            }),
+            Expr::Sequence(items) if items.len() == 1 => self.char_set(&items[0], depth),
+            Expr::Difference(from, excluded) => Some(
+                self.char_set(from, depth)?
+                    .minus(&self.char_set(excluded, depth)?),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// A character set as a string or character class, negated when that takes
+/// fewer ranges
+fn class(set: &RangeSet) -> BaseFactor {
+    if let [(from, to)] = set.ranges() {
+        if from == to {
+            return BaseFactor::literal(from.to_string());
+        }
+    }
+    let complement = set.complement();
+    if set.is_empty() || (!complement.is_empty() && complement.num_ranges() < set.num_ranges()) {
+        BaseFactor::negated_charclass(complement.to_charclass_content())
+    } else {
+        BaseFactor::charclass(set.to_charclass_content())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NativeParser;
+
+    #[test]
+    fn test_xml_spec_rules() {
+        let grammar = from_ebnf(
+            r#"
+            /* From the XML 1.0 specification */
+            [2]   Char      ::= #x9 | #xA | #xD | [#x20-#xD7FF] | [#xE000-#xFFFD] | [#x10000-#x10FFFF]
+            [3]   S         ::= (#x20 | #x9 | #xD | #xA)+
+            [10]  AttValue  ::= '"' ([^<&"] | Reference)* '"'
+                              |  "'" ([^<&'] | Reference)* "'"
+            [13]  PubidChar ::= #x20 | #xD | #xA | [a-zA-Z0-9] | [-'()+,./:=?;!*#@$_%]
+            [13a] Quote     ::= PubidChar - "'"
+            [17]  PITarget  ::= Name - (('X' | 'x') ('M' | 'm') ('L' | 'l'))
+            [67]  Reference ::= '&' Name ';'   [ WFC: Entity Declared ]
+                                               [ VC: Entity Declared ]
+            [5]   Name      ::= [a-z]+
+            "#,
+        )
+        .unwrap();
+        let rules: Vec<String> = grammar.rules.iter().map(|r| r.to_string()).collect();
+        assert!(rules[0].starts_with("Char: #9; #a; #d; [#20-"));
+        assert_eq!(rules[1], "S: (\" \"; #9; #d; #a)+.");
+        assert_eq!(
+            rules[2],
+            r#"AttValue: """", (~[#22; "&"; "<"]; Reference)*, """"; "'", (~["&"-"'"; "<"]; Reference)*, "'"."#
+        );
+        assert_eq!(
+            rules[4],
+            r##"Quote: [#a; #d; #20-"!"; "#"-"%"; "("-";"; "="; "?"-"Z"; "_"; "a"-"z"]."##
+        );
+        assert_eq!(
+            rules[5],
+            r#"PITarget: !(("X"; "x"), ("M"; "m"), ("L"; "l")), Name."#
+        );
+        assert_eq!(rules[6], r#"Reference: "&", Name, ";"."#);
+
+        let parser = NativeParser::new(grammar);
+        assert!(parser.parse_with_start("xsl", "PITarget").is_ok());
+        assert!(parser.parse_with_start("xml", "PITarget").is_err());
+        assert!(parser.parse_with_start("a", "Quote").is_ok());
+        assert!(parser.parse_with_start("'", "Quote").is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(from_ebnf("").unwrap_err(), "EBNF grammar has no rules");
+        assert_eq!(
+            from_ebnf("a ::= 'x'\nb = 'y'").unwrap_err(),
+            "EBNF line 2: Unexpected `=`"
+        );
+        assert_eq!(
+            from_ebnf("a ::= ('x' | 'y'").unwrap_err(),
+            "EBNF line 1: Expected `)`"
+        );
+        assert_eq!(
+            from_ebnf("a ::= [z-a]").unwrap_err(),
+            "EBNF line 1: Character range ends before it starts"
+        );
+        assert_eq!(
+            from_ebnf("/* a ::= 'x'").unwrap_err(),
+            "EBNF line 1: Unterminated comment"
+        );
+        assert_eq!(
+            from_ebnf("'x'").unwrap_err(),
+            "EBNF line 1: Expected a rule such as `name ::= expression`"
+        );
+    }
+
+    #[test]
+    fn test_abnf() {
+        let grammar = from_abnf(
+            r#"
+            ; RFC 3339 style dates
+            full-date  = date-fullyear "-" date-month "-" date-mday
+            date-fullyear = 4DIGIT
+            date-month = %x30 %x31-39 / %x31 %x30-32
+            date-mday  = 2DIGIT
+            method     = %s"GET" / "post"
+            method     =/ %d72.69.65.68   ; HEAD
+            hex-pair   = 1*2HEXDIG [ "h" ]
+            line       = *( VCHAR / WSP ) CRLF
+            "#,
+        )
+        .unwrap();
+        let rules: Vec<String> = grammar.rules.iter().map(|r| r.to_string()).collect();
+        assert_eq!(
+            rules[..7],
+            [
+                r#"full-date: date-fullyear, "-", date-month, "-", date-mday."#,
+                "date-fullyear: DIGIT, DIGIT, DIGIT, DIGIT.",
+                r#"date-month: "0", ["1"-"9"]; "1", ["0"-"2"]."#,
+                "date-mday: DIGIT, DIGIT.",
+                r#"method: "GET"; ["P"; "p"], ["O"; "o"], ["S"; "s"], ["T"; "t"]; "HEAD"."#,
+                r#"hex-pair: HEXDIG, HEXDIG?, ["H"; "h"]?."#,
+                "line: (VCHAR; WSP)*, CRLF.",
+            ]
+        );
+        let core: Vec<&str> = grammar.rules[7..].iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            core,
+            ["CRLF", "DIGIT", "HEXDIG", "VCHAR", "WSP", "CR", "HTAB", "LF", "SP"]
+        );
+        assert!(grammar.rules[7..].iter().all(|r| r.mark == Mark::Hidden));
+
+        let parser = NativeParser::new(grammar);
+        assert_eq!(
+            parser.parse("2024-09-30").unwrap(),
+            "<full-date><date-fullyear>2024</date-fullyear>-<date-month>09</date-month>-<date-mday>30</date-mday></full-date>"
+        );
+        assert!(parser.parse("2024-13-01").is_err());
+        for method in ["GET", "POST", "pOsT", "HEAD"] {
+            assert!(
+                parser.parse_with_start(method, "method").is_ok(),
+                "{}",
+                method
+            );
+        }
+        assert!(parser.parse_with_start("get", "method").is_err());
+        assert!(parser.parse_with_start("aFh", "hex-pair").is_ok());
+        assert!(parser.parse_with_start("aF0", "hex-pair").is_err());
+    }
+
+    #[test]
+    fn test_abnf_names_and_repeats() {
+        let grammar =
+            from_abnf("Greeting = 2*4name\nNAME = %x61-7A\nname =/ \"-\"\nempty = 0name").unwrap();
+        assert_eq!(
+            grammar.to_string(),
+            "Greeting: NAME, NAME, (NAME, NAME?)?.\nNAME: [\"a\"-\"z\"]; \"-\".\nempty: .\n"
+        );
+
+        assert_eq!(
+            from_abnf("a = b\nb =/ \"x\"").unwrap_err(),
+            "Rule 'b' is extended with =/ before it is defined"
+        );
+        assert_eq!(
+            from_abnf("a = \"x\"\nA = \"y\"").unwrap_err(),
+            "Rule 'A' is defined twice"
+        );
+        assert_eq!(
+            from_abnf("a = 3*2\"x\"").unwrap_err(),
+            "ABNF line 1: Repeat 3*2 has a maximum below its minimum"
+        );
+        assert_eq!(
+            from_abnf("a = <any text>").unwrap_err(),
+            "ABNF line 1: Prose values (`<...>`) cannot be converted"
+        );
+        assert_eq!(
+            from_abnf("; nothing").unwrap_err(),
+            "ABNF grammar has no rules"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Reading parser input from files
+//!
+//! Files come with byte order marks, UTF-16 encodings and Windows line
+//! endings that a grammar written for plain text does not expect.
+//! [`InputOptions`] says how to turn a file's bytes into the characters that
+//! are parsed; [`NativeParser::parse_path`](crate::NativeParser::parse_path)
+//! applies it and names the file in its diagnostics.
+
+use std::path::Path;
+
+/// How the bytes of an input are encoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Follow a byte order mark, otherwise UTF-8
+    #[default]
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// What to do with line endings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Newlines {
+    /// Parse them as they are
+    #[default]
+    Keep,
+    /// Turn `\r\n` and lone `\r` into `\n`, as XML processors do
+    Normalize,
+}
+
+/// How to read input from bytes
+///
+/// ```
+/// use rustixml::input_file::{decode, Encoding, InputOptions, Newlines};
+///
+/// let options = InputOptions::new().with_newlines(Newlines::Normalize);
+/// assert_eq!(decode(b"\xEF\xBB\xBFa\r\nb", &options).unwrap(), "a\nb");
+///
+/// let utf16 = [0xFF, 0xFE, b'h', 0, b'i', 0];
+/// assert_eq!(decode(&utf16, &InputOptions::new()).unwrap(), "hi");
+/// assert!(decode(&utf16, &InputOptions::new().with_encoding(Encoding::Utf8)).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputOptions {
+    pub encoding: Encoding,
+    pub newlines: Newlines,
+}
+
+impl InputOptions {
+    /// Detect the encoding and keep line endings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read bytes as `encoding`
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Handle line endings as `newlines` says
+    pub fn with_newlines(mut self, newlines: Newlines) -> Self {
+        self.newlines = newlines;
+        self
+    }
+}
+
+/// Decode input bytes as `options` say; a byte order mark is dropped
+pub fn decode(bytes: &[u8], options: &InputOptions) -> Result<String, String> {
+    let encoding = match options.encoding {
+        Encoding::Auto if bytes.starts_with(&[0xFF, 0xFE]) => Encoding::Utf16Le,
+        Encoding::Auto if bytes.starts_with(&[0xFE, 0xFF]) => Encoding::Utf16Be,
+        Encoding::Auto => Encoding::Utf8,
+        encoding => encoding,
+    };
+    let text = match encoding {
+        Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes)?,
+        Encoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes)?,
+        _ => match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(e) => return Err(format!("Invalid UTF-8 at byte {}", e.valid_up_to())),
+        },
+    };
+    let text = match text.strip_prefix('\u{feff}') {
+        Some(rest) => rest.to_string(),
+        None => text,
+    };
+    Ok(match options.newlines {
+        Newlines::Keep => text,
+        Newlines::Normalize => text.replace("\r\n", "\n").replace('\r', "\n"),
+    })
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Result<String, String> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err("Invalid UTF-16: odd number of bytes".to_string());
+    }
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    let mut text = String::with_capacity(bytes.len() / 2);
+    let mut offset = 0;
+    for ch in char::decode_utf16(units) {
+        match ch {
+            Ok(ch) => {
+                offset += ch.len_utf16() * 2;
+                text.push(ch);
+            }
+            Err(_) => return Err(format!("Invalid UTF-16 at byte {}", offset)),
+        }
+    }
+    Ok(text)
+}
+
+/// Read and decode a file; errors start with its path
+pub fn read_file(path: &Path, options: &InputOptions) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    decode(&bytes, options).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let options = InputOptions::new();
+        assert_eq!(decode("é\r\n".as_bytes(), &options).unwrap(), "é\r\n");
+        assert_eq!(
+            decode(b"a\xFFb", &options).unwrap_err(),
+            "Invalid UTF-8 at byte 1"
+        );
+
+        // U+1D538 is a surrogate pair
+        let utf16 = [0xFE, 0xFF, 0xD8, 0x35, 0xDD, 0x38, 0, b'\r'];
+        let normalize = options.with_newlines(Newlines::Normalize);
+        assert_eq!(decode(&utf16, &normalize).unwrap(), "\u{1d538}\n");
+        let utf16be = options.with_encoding(Encoding::Utf16Be);
+        assert_eq!(decode(&utf16[2..], &utf16be).unwrap(), "\u{1d538}\r");
+        assert_eq!(
+            decode(&[0, b'a', 0xDC, 0], &utf16be).unwrap_err(),
+            "Invalid UTF-16 at byte 2"
+        );
+        assert_eq!(
+            decode(&[0, b'a', 0], &utf16be).unwrap_err(),
+            "Invalid UTF-16: odd number of bytes"
+        );
+    }
+
+    #[test]
+    fn test_read_missing_file() {
+        let path = Path::new("no/such/input.txt");
+        let err = read_file(path, &InputOptions::new()).unwrap_err();
+        assert!(err.starts_with("no/such/input.txt: "), "{}", err);
+    }
+}
@@ -2,28 +2,297 @@
 //!
 //! Manages input text as a sequence of Unicode characters, providing efficient
 //! random access and position management for recursive descent parsing.
-
+//!
+//! [`InputStream::from_reader`] streams input from any [`Read`] source instead:
+//! characters are decoded in chunks as the parser advances, and characters more
+//! than a backtracking window behind the furthest position read are dropped, so
+//! memory for the input stays bounded regardless of its size.
+//! [`InputStream::from_bytes`] does the same over bytes already in memory, such
+//! as a memory-mapped file, decoding them in place rather than copying them
+//! into a `String` first.
+
+use std::cell::RefCell;
 use std::fmt;
+use std::io::Read;
+use std::rc::Rc;
+
+/// Bytes read from the underlying reader per refill
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Characters kept buffered ahead of the current position when streaming
+const LOOKAHEAD: usize = 4096;
+
+/// Characters kept behind the furthest position read when streaming (see
+/// [`InputStream::with_backtrack_window`])
+pub const DEFAULT_BACKTRACK_WINDOW: usize = 1 << 20;
+
+/// Characters between the byte offsets recorded for non-ASCII in-memory input
+const BYTE_OFFSET_STRIDE: usize = 16;
+
+/// Incremental UTF-8 decoder over a reader or in-memory bytes
+struct ChunkReader {
+    source: ByteSource,
+    /// Bytes of an incomplete UTF-8 sequence left over from the previous chunk
+    pending: Vec<u8>,
+    done: bool,
+}
+
+enum ByteSource {
+    Reader(Box<dyn Read>),
+    /// Bytes decoded in place; `offset` is the first byte not yet decoded
+    Bytes {
+        bytes: Box<dyn AsRef<[u8]>>,
+        offset: usize,
+    },
+}
+
+impl ChunkReader {
+    fn new(source: ByteSource) -> Self {
+        ChunkReader {
+            source,
+            pending: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Decode the next chunk into `out`; returns false once the source is exhausted
+    fn fill(&mut self, out: &mut Vec<char>) -> Result<bool, String> {
+        if self.done {
+            return Ok(false);
+        }
+        let reader = match &mut self.source {
+            ByteSource::Reader(reader) => reader,
+            ByteSource::Bytes { bytes, offset } => {
+                let filled = decode_chunk(AsRef::<[u8]>::as_ref(&**bytes), offset, out);
+                self.done = !matches!(filled, Ok(true));
+                return filled;
+            }
+        };
+
+        let mut buf = vec![0; CHUNK_BYTES];
+        let read = loop {
+            match reader.read(&mut buf) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(format!("Failed to read input: {}", e)),
+            }
+        };
+        if read == 0 {
+            self.done = true;
+            if !self.pending.is_empty() {
+                return Err("Input ends with an incomplete UTF-8 sequence".to_string());
+            }
+            return Ok(false);
+        }
+
+        self.pending.extend_from_slice(&buf[..read]);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            // Only a sequence cut off at the end of the chunk is acceptable
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => {
+                return Err(format!(
+                    "Input is not valid UTF-8 (byte {} of chunk)",
+                    e.valid_up_to()
+                ))
+            }
+        };
+        let text = std::str::from_utf8(&self.pending[..valid]).expect("validated above");
+        out.extend(text.chars());
+        self.pending.drain(..valid);
+        Ok(true)
+    }
+}
+
+/// Decode the next chunk of `bytes` from `offset` into `out`, advancing `offset`
+///
+/// Returns false at the end of the bytes. On invalid UTF-8 the text before the
+/// bad byte is still decoded, so the input ends exactly there.
+fn decode_chunk(bytes: &[u8], offset: &mut usize, out: &mut Vec<char>) -> Result<bool, String> {
+    let rest = &bytes[*offset..];
+    if rest.is_empty() {
+        return Ok(false);
+    }
+    let chunk = &rest[..rest.len().min(CHUNK_BYTES)];
+    let (valid, error) = match std::str::from_utf8(chunk) {
+        Ok(text) => (text.len(), None),
+        // A sequence cut off by the chunk boundary is finished by the next chunk
+        Err(e) if e.error_len().is_none() && chunk.len() < rest.len() => (e.valid_up_to(), None),
+        Err(e) if e.error_len().is_none() => (
+            e.valid_up_to(),
+            Some("Input ends with an incomplete UTF-8 sequence".to_string()),
+        ),
+        Err(e) => (
+            e.valid_up_to(),
+            Some(format!(
+                "Input is not valid UTF-8 (byte {})",
+                *offset + e.valid_up_to()
+            )),
+        ),
+    };
+    let text = std::str::from_utf8(&chunk[..valid]).expect("validated above");
+    out.extend(text.chars());
+    *offset += valid;
+    error.map_or(Ok(true), Err)
+}
 
 /// Input stream that tracks position in text for parsing with backtracking
+///
+/// Positions are absolute character indices into the whole input, whether it
+/// is held in memory or streamed.
 #[derive(Clone)]
 pub struct InputStream {
+    /// Buffered characters; all of the input unless streaming
     chars: Vec<char>,
+    /// Absolute position of `chars[0]`
+    base: usize,
     position: usize,
+    /// Line and column of `chars[0]`
+    base_line_col: (usize, usize),
+    /// Streaming source; `None` for in-memory input (clones share the reader)
+    source: Option<Rc<RefCell<ChunkReader>>>,
+    backtrack_window: usize,
+    /// Set when a seek went behind the discarded part of a streaming window
+    window_exceeded: bool,
+    read_error: Option<String>,
+    /// Byte offsets of every `BYTE_OFFSET_STRIDE`th character of in-memory
+    /// input, empty if the input is ASCII; `None` when streaming
+    byte_offsets: Option<Vec<usize>>,
 }
 
 impl InputStream {
     /// Create a new input stream from a string
     pub fn new(input: &str) -> Self {
+        let byte_offsets = if input.is_ascii() {
+            Vec::new()
+        } else {
+            let offsets = input.char_indices().step_by(BYTE_OFFSET_STRIDE);
+            offsets.map(|(offset, _)| offset).collect()
+        };
         InputStream {
             chars: input.chars().collect(),
+            base: 0,
+            position: 0,
+            base_line_col: (1, 1),
+            source: None,
+            backtrack_window: usize::MAX,
+            window_exceeded: false,
+            read_error: None,
+            byte_offsets: Some(byte_offsets),
+        }
+    }
+
+    /// Create an input stream that reads UTF-8 text from `reader` in chunks
+    ///
+    /// Only a bounded window of input is held in memory: see
+    /// [`InputStream::with_backtrack_window`]. I/O and UTF-8 errors end the
+    /// input early and are reported by [`InputStream::read_error`].
+    pub fn from_reader<R: Read + 'static>(reader: R) -> Self {
+        Self::streaming(ByteSource::Reader(Box::new(reader)))
+    }
+
+    /// Create an input stream over UTF-8 bytes held in memory, e.g. a
+    /// memory-mapped file
+    ///
+    /// Like [`InputStream::from_reader`], characters are decoded as the parser
+    /// advances and only the backtracking window is kept, but the bytes are
+    /// decoded where they are instead of being read into buffers. Invalid
+    /// UTF-8 ends the input early and is reported by [`InputStream::read_error`].
+    ///
+    /// ```
+    /// use rustixml::{fixtures::CSV, input_stream::InputStream};
+    ///
+    /// let bytes: Vec<u8> = CSV.input.as_bytes().to_vec();
+    /// let xml = CSV.parser().parse_stream(InputStream::from_bytes(bytes)).unwrap();
+    /// assert_eq!(xml, CSV.expected);
+    /// ```
+    pub fn from_bytes<B: AsRef<[u8]> + 'static>(bytes: B) -> Self {
+        Self::streaming(ByteSource::Bytes {
+            bytes: Box::new(bytes),
+            offset: 0,
+        })
+    }
+
+    fn streaming(source: ByteSource) -> Self {
+        let mut stream = InputStream {
+            chars: Vec::new(),
+            base: 0,
             position: 0,
+            base_line_col: (1, 1),
+            source: Some(Rc::new(RefCell::new(ChunkReader::new(source)))),
+            backtrack_window: DEFAULT_BACKTRACK_WINDOW,
+            window_exceeded: false,
+            read_error: None,
+            byte_offsets: None,
+        };
+        stream.refill();
+        stream
+    }
+
+    /// Set how many characters behind the furthest read position stay available
+    /// for backtracking (streaming input only)
+    ///
+    /// Seeking further back than this marks the stream with
+    /// [`InputStream::window_exceeded`] and reads as end of input.
+    pub fn with_backtrack_window(mut self, window: usize) -> Self {
+        self.backtrack_window = window;
+        self
+    }
+
+    /// Whether the parser tried to backtrack past the streaming window
+    pub fn window_exceeded(&self) -> bool {
+        self.window_exceeded
+    }
+
+    /// I/O or decoding error that cut streaming input short, if any
+    pub fn read_error(&self) -> Option<&str> {
+        self.read_error.as_deref()
+    }
+
+    /// Absolute position just past the last buffered character
+    fn buffered_end(&self) -> usize {
+        self.base + self.chars.len()
+    }
+
+    /// Keep at least `LOOKAHEAD` characters buffered past the current position,
+    /// dropping characters that fell out of the backtracking window
+    fn refill(&mut self) {
+        let Some(source) = self.source.clone() else {
+            return;
+        };
+        while self.buffered_end() < self.position.saturating_add(LOOKAHEAD) {
+            match source.borrow_mut().fill(&mut self.chars) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    self.read_error.get_or_insert(e);
+                    break;
+                }
+            }
+        }
+
+        let keep_from = self.position.saturating_sub(self.backtrack_window);
+        if keep_from > self.base {
+            let dropped = keep_from - self.base;
+            for ch in self.chars.drain(..dropped) {
+                if ch == '\n' {
+                    self.base_line_col = (self.base_line_col.0 + 1, 1);
+                } else {
+                    self.base_line_col.1 += 1;
+                }
+            }
+            self.base = keep_from;
         }
     }
 
+    /// Index into `chars` for an absolute position, if it is still buffered
+    fn index(&self, pos: usize) -> Option<usize> {
+        pos.checked_sub(self.base)
+    }
+
     /// Get the current character without advancing
     pub fn current(&self) -> Option<char> {
-        self.chars.get(self.position).copied()
+        self.peek(0)
     }
 
     /// Get the current character and advance position
@@ -31,13 +300,20 @@ impl InputStream {
         let ch = self.current();
         if ch.is_some() {
             self.position += 1;
+            if self.source.is_some() && self.position + LOOKAHEAD / 2 > self.buffered_end() {
+                self.refill();
+            }
         }
         ch
     }
 
     /// Look ahead at a character at offset from current position
+    ///
+    /// When streaming, only the buffered lookahead is visible.
     pub fn peek(&self, offset: usize) -> Option<char> {
-        self.chars.get(self.position + offset).copied()
+        self.index(self.position + offset)
+            .and_then(|i| self.chars.get(i))
+            .copied()
     }
 
     /// Get current position (character index, not byte offset)
@@ -45,47 +321,88 @@ impl InputStream {
         self.position
     }
 
+    /// Byte offset in the input of character `position` (the input's length
+    /// at its end)
+    ///
+    /// `None` for streaming input, which is not held as one string, and past
+    /// the end.
+    ///
+    /// ```
+    /// use rustixml::input_stream::InputStream;
+    ///
+    /// let stream = InputStream::new("née");
+    /// assert_eq!(stream.byte_offset(2), Some(3));
+    /// assert_eq!(stream.byte_offset(3), Some(4));
+    /// assert_eq!(stream.byte_offset(4), None);
+    /// ```
+    pub fn byte_offset(&self, position: usize) -> Option<usize> {
+        let offsets = self.byte_offsets.as_ref()?;
+        if position > self.chars.len() {
+            return None;
+        }
+        let Some(last) = offsets.len().checked_sub(1) else {
+            // ASCII: one byte per character
+            return Some(position);
+        };
+        let checkpoint = (position / BYTE_OFFSET_STRIDE).min(last);
+        let from = checkpoint * BYTE_OFFSET_STRIDE;
+        let skipped: usize = self.chars[from..position]
+            .iter()
+            .map(|c| c.len_utf8())
+            .sum();
+        Some(offsets[checkpoint] + skipped)
+    }
+
     /// Set position (for backtracking)
     pub fn set_position(&mut self, pos: usize) {
-        self.position = pos.min(self.chars.len());
+        if pos < self.base {
+            self.window_exceeded = true;
+        }
+        self.position = pos;
+        self.refill();
+        self.position = pos.min(self.buffered_end());
     }
 
     /// Get remaining input as a string slice (for debugging)
+    ///
+    /// When streaming, only the buffered part is returned.
     pub fn remaining(&self) -> String {
-        self.chars[self.position..].iter().collect()
+        let start = self.index(self.position).unwrap_or(0).min(self.chars.len());
+        self.chars[start..].iter().collect()
     }
 
     /// Check if at end of input
     pub fn is_eof(&self) -> bool {
-        self.position >= self.chars.len()
+        self.position >= self.buffered_end()
     }
 
     /// Get total length in characters
+    ///
+    /// When streaming, this counts the characters read so far.
     pub fn len(&self) -> usize {
-        self.chars.len()
+        self.buffered_end()
     }
 
     /// Check if input is empty
     pub fn is_empty(&self) -> bool {
-        self.chars.is_empty()
+        self.len() == 0
     }
 
     /// Get a substring from start to end positions
+    ///
+    /// When streaming, positions outside the buffered window are clamped to it.
     pub fn substring(&self, start: usize, end: usize) -> String {
-        self.chars[start.min(self.chars.len())..end.min(self.chars.len())]
-            .iter()
-            .collect()
+        let clamp = |pos: usize| pos.saturating_sub(self.base).min(self.chars.len());
+        let (start, end) = (clamp(start), clamp(end));
+        self.chars[start..end.max(start)].iter().collect()
     }
 
     /// Get line and column for a position (for error messages)
     pub fn line_col(&self, pos: usize) -> (usize, usize) {
-        let mut line = 1;
-        let mut col = 1;
+        let (mut line, mut col) = self.base_line_col;
+        let end = self.index(pos).unwrap_or(0).min(self.chars.len());
 
-        for (i, ch) in self.chars.iter().enumerate() {
-            if i >= pos {
-                break;
-            }
+        for ch in &self.chars[..end] {
             if *ch == '\n' {
                 line += 1;
                 col = 1;
@@ -109,10 +426,55 @@ impl fmt::Debug for InputStream {
     }
 }
 
+/// Line and column (both counted from 1) of character offset `pos` in `text`
+///
+/// Columns count characters; only `\n` starts a new line.
+pub fn line_col(text: &str, pos: usize) -> (usize, usize) {
+    text.chars()
+        .take(pos)
+        .fold((1, 1), |(line, col), ch| match ch {
+            '\n' => (line + 1, 1),
+            _ => (line, col + 1),
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_line_col_of_text() {
+        let text = "ab\r\nçd\n\n😀e";
+        assert_eq!(line_col(text, 0), (1, 1));
+        assert_eq!(line_col(text, 3), (1, 4));
+        assert_eq!(line_col(text, 5), (2, 2));
+        assert_eq!(line_col(text, 9), (4, 2));
+        // Offsets past the end stop at the end
+        assert_eq!(line_col(text, 99), (4, 3));
+        let stream = InputStream::new(text);
+        for pos in 0..=10 {
+            assert_eq!(stream.line_col(pos), line_col(text, pos));
+        }
+    }
+
+    #[test]
+    fn test_byte_offset() {
+        let input = "aé€😀".repeat(20);
+        let stream = InputStream::new(&input);
+        let expected: Vec<usize> = input
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([input.len()])
+            .collect();
+        let offsets: Vec<_> = (0..=stream.chars.len())
+            .map(|position| stream.byte_offset(position).unwrap())
+            .collect();
+        assert_eq!(offsets, expected);
+
+        let streamed = InputStream::from_bytes(input.into_bytes());
+        assert_eq!(streamed.byte_offset(0), None);
+    }
+
     #[test]
     fn test_new() {
         let stream = InputStream::new("hello");
@@ -209,4 +571,77 @@ mod tests {
         assert!(stream.is_eof());
         assert_eq!(stream.current(), None);
     }
+
+    #[test]
+    fn test_from_reader() {
+        let text = "héllo 世界\n".repeat(3000);
+        let mut stream = InputStream::from_reader(std::io::Cursor::new(text.clone()));
+        let mut read = String::new();
+        while let Some(ch) = stream.advance() {
+            read.push(ch);
+        }
+        assert_eq!(read, text);
+        assert!(stream.is_eof());
+        assert_eq!(stream.len(), text.chars().count());
+        assert_eq!(stream.read_error(), None);
+
+        // Multi-byte characters split across chunk boundaries decode correctly
+        let text = "€".repeat(CHUNK_BYTES);
+        let stream = InputStream::from_reader(std::io::Cursor::new(text));
+        assert_eq!(stream.current(), Some('€'));
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        // "€" is three bytes, so chunk boundaries fall inside characters
+        let text = "a€".repeat(CHUNK_BYTES / 2);
+        let mut stream = InputStream::from_bytes(text.clone().into_bytes());
+        let mut read = String::new();
+        while let Some(ch) = stream.advance() {
+            read.push(ch);
+        }
+        assert_eq!(read, text);
+        assert_eq!(stream.read_error(), None);
+
+        let mut bytes = "ok".repeat(CHUNK_BYTES).into_bytes();
+        bytes[CHUNK_BYTES + 3] = 0xff;
+        let mut stream = InputStream::from_bytes(bytes);
+        while stream.advance().is_some() {}
+        assert_eq!(stream.position(), CHUNK_BYTES + 3);
+        assert_eq!(
+            stream.read_error(),
+            Some(format!("Input is not valid UTF-8 (byte {})", CHUNK_BYTES + 3).as_str())
+        );
+
+        let stream = InputStream::from_bytes(&b"a\xe2\x82"[..]);
+        assert_eq!(
+            stream.read_error(),
+            Some("Input ends with an incomplete UTF-8 sequence")
+        );
+    }
+
+    #[test]
+    fn test_backtrack_window() {
+        let text = "line\n".repeat(10_000);
+        let mut stream =
+            InputStream::from_reader(std::io::Cursor::new(text)).with_backtrack_window(100);
+        for _ in 0..20_000 {
+            stream.advance();
+        }
+        assert_eq!(stream.line_col(20_000), (4001, 1));
+
+        stream.set_position(19_950);
+        assert_eq!(stream.current(), Some('l'));
+        assert!(!stream.window_exceeded());
+
+        stream.set_position(10);
+        assert!(stream.window_exceeded());
+        assert_eq!(stream.current(), None);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let stream = InputStream::from_reader(std::io::Cursor::new(vec![b'a', 0xff, b'b']));
+        assert!(stream.read_error().unwrap().contains("not valid UTF-8"));
+    }
 }
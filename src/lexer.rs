@@ -19,8 +19,10 @@ pub enum Token {
     Question,
     At,
     Minus,
-    Caret, // For promoted mark ^
-    Tilde, // For negated character classes ~[...]
+    Caret,          // For promoted mark ^
+    Tilde,          // For negated character classes ~[...]
+    Bang,           // For negative lookahead !factor (non-standard extension)
+    Pragma(String), // Content between {[ and ]}, e.g., "name data"
     LParen,
     RParen,
     LBracket,
@@ -68,8 +70,8 @@ impl Lexer {
                 self.pos += 1;
             }
 
-            // Check for comment start
-            if self.peek() == Some('{') {
+            // Check for comment start ({[ starts a pragma instead)
+            if self.peek() == Some('{') && self.peek_at(1) != Some('[') {
                 self.skip_comment()?;
             } else {
                 break;
@@ -79,40 +81,38 @@ impl Lexer {
     }
 
     fn skip_comment(&mut self) -> Result<(), String> {
-        // iXML comments are {like this} and can be nested
-        if self.peek() != Some('{') {
-            return Ok(());
-        }
-
+        // iXML comments are {like this} and nest: {outer {inner} still outer}
+        let start = self.pos;
         self.advance(); // consume '{'
         let mut depth = 1;
 
-        while depth > 0 && self.pos < self.input.len() {
-            match self.peek() {
-                Some('{') => {
-                    depth += 1;
-                    self.advance();
-                }
-                Some('}') => {
-                    depth -= 1;
-                    self.advance();
-                }
-                Some(_) => {
-                    self.advance();
-                }
+        while depth > 0 {
+            match self.advance() {
+                Some('{') => depth += 1,
+                Some('}') => depth -= 1,
+                Some(_) => {}
                 None => {
-                    return Err("Unclosed comment".to_string());
+                    // A missing '}' swallows the rest of the grammar, so point at the opener
+                    let (line, column) = self.line_col(start);
+                    return Err(format!(
+                        "Unclosed comment starting at line {}, column {}",
+                        line, column
+                    ));
                 }
             }
         }
 
-        if depth > 0 {
-            return Err("Unclosed comment".to_string());
-        }
-
         Ok(())
     }
 
+    /// 1-based line and column of a character offset
+    fn line_col(&self, pos: usize) -> (usize, usize) {
+        let before = &self.input[..pos];
+        let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        (line, column)
+    }
+
     #[allow(dead_code)]
     fn skip_whitespace(&mut self) {
         while self.pos < self.input.len() && self.input[self.pos].is_whitespace() {
@@ -128,6 +128,37 @@ impl Lexer {
         }
     }
 
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.input.get(self.pos + offset).copied()
+    }
+
+    fn read_pragma(&mut self) -> Result<Token, String> {
+        // Pragmas are {[name data]}; brackets inside the data must balance
+        self.advance(); // consume '{'
+        self.advance(); // consume '['
+        let mut content = String::new();
+        let mut depth = 1;
+
+        while let Some(ch) = self.advance() {
+            match ch {
+                '[' => depth += 1,
+                ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        if self.advance() != Some('}') {
+                            return Err("Expected '}' after pragma".to_string());
+                        }
+                        return Ok(Token::Pragma(content.trim().to_string()));
+                    }
+                }
+                _ => {}
+            }
+            content.push(ch);
+        }
+
+        Err("Unclosed pragma".to_string())
+    }
+
     fn advance(&mut self) -> Option<char> {
         if self.pos < self.input.len() {
             let ch = self.input[self.pos];
@@ -140,6 +171,7 @@ impl Lexer {
 
     fn next_token(&mut self) -> Result<Token, String> {
         match self.peek() {
+            Some('{') => self.read_pragma(),
             Some('"') => self.read_string(),
             Some('\'') => self.read_char_literal(),
             Some(':') => {
@@ -192,6 +224,10 @@ impl Lexer {
                 self.advance();
                 Ok(Token::Tilde)
             }
+            Some('!') => {
+                self.advance();
+                Ok(Token::Bang)
+            }
             Some('^') => {
                 self.advance();
                 Ok(Token::Caret)
@@ -435,12 +471,68 @@ mod tests {
         assert_eq!(tokens[3], Token::Period);
     }
 
+    #[test]
+    fn test_pragma() {
+        let mut lexer = Lexer::new(r#"{[rename x [y]]} rule {not a pragma}: "a"."#);
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0], Token::Pragma("rename x [y]".to_string()));
+        assert_eq!(tokens[1], Token::Ident("rule".to_string()));
+        assert_eq!(tokens[2], Token::Colon);
+
+        assert_eq!(
+            Lexer::new("{[open").tokenize().unwrap_err(),
+            "Unclosed pragma"
+        );
+        assert_eq!(
+            Lexer::new("{[x]]").tokenize().unwrap_err(),
+            "Expected '}' after pragma"
+        );
+    }
+
+    #[test]
+    fn test_comments_inside_tokens() {
+        // Between a mark and a name, and inside a character class
+        let mut lexer = Lexer::new(r#"@ {a {b}} name ["a" {c}; {d {e}} "b"]"#);
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0], Token::At);
+        assert_eq!(tokens[1], Token::Ident("name".to_string()));
+        assert_eq!(tokens[2], Token::CharClass(r#""a" ;  "b""#.to_string()));
+
+        // Braces in quotes are not comments
+        let tokens = Lexer::new(r#"["{"] "{}""#).tokenize().unwrap();
+        assert_eq!(tokens[0], Token::CharClass(r#""{""#.to_string()));
+        assert_eq!(tokens[1], Token::String("{}".to_string()));
+    }
+
+    #[test]
+    fn test_doubled_quotes() {
+        let tokens = Lexer::new(r#"'it''s' "say ""hi""" '''' ["""";'{''}']"#)
+            .tokenize()
+            .unwrap();
+        assert_eq!(tokens[0], Token::String("it's".to_string()));
+        assert_eq!(tokens[1], Token::String(r#"say "hi""#.to_string()));
+        assert_eq!(tokens[2], Token::String("'".to_string()));
+        // Class members stay quoted; a doubled quote does not end the string
+        assert_eq!(tokens[3], Token::CharClass(r#""""";'{''}'"#.to_string()));
+    }
+
     #[test]
     fn test_unclosed_comment_error() {
         let mut lexer = Lexer::new(r#"{Unclosed comment rule: "hello"."#);
         let result = lexer.tokenize();
 
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "Unclosed comment");
+        assert_eq!(
+            result.unwrap_err(),
+            "Unclosed comment starting at line 1, column 1"
+        );
+
+        // The outermost comment is reported when a nested one is left open
+        let err = Lexer::new("s: 'a'.\n  {outer {inner} s: ['a' {x}].")
+            .tokenize()
+            .unwrap_err();
+        assert_eq!(err, "Unclosed comment starting at line 2, column 3");
     }
 }
@@ -24,22 +24,57 @@
 //! # Features
 //!
 //! - 🚀 Fast native recursive descent parser
-//! - ✅ 75.4% conformance with iXML specification (49/65 tests)
+//! - ✅ 80.0% conformance with iXML specification (52/65 tests; see [`capabilities()`])
 //! - 🌐 WebAssembly support for browser use
 //! - 📦 Single dependency (unicode-general-category)
 //! - 🔒 Pure safe Rust
 
 pub mod ast;
+pub mod capabilities;
 pub mod charclass;
+pub mod compiled_grammar;
+pub mod compose;
+pub mod conformance;
+pub mod convert;
+pub mod diagnostics;
+pub mod earley;
+pub mod events;
+pub mod fixtures;
+pub mod formatter;
+pub mod generator;
 pub mod grammar_analysis;
 pub mod grammar_ast;
+pub mod grammar_diff;
 pub mod grammar_parser;
+pub mod import;
+pub mod input_file;
 pub mod input_stream;
 pub mod lexer;
 pub mod native_parser;
 pub mod normalize;
+pub mod outcome;
 pub mod parse_context;
+pub mod profile;
+pub mod query;
+pub mod railroad;
+pub mod regex_hints;
+pub mod suggest;
+pub mod unparse;
+pub mod vxml;
 pub mod xml_node;
+pub mod xml_tree;
+
+// Running a reference processor needs child processes
+#[cfg(not(target_arch = "wasm32"))]
+pub mod reference;
+
+// Python extension module, built with maturin
+#[cfg(feature = "python")]
+pub mod python;
+
+// Candid-typed entry points for Internet Computer canisters
+#[cfg(feature = "ic-canister")]
+pub mod canister;
 
 // WASM bindings (only when compiling for wasm32 browser/Node.js, not IC canisters)
 #[cfg(all(target_arch = "wasm32", not(feature = "ic-canister")))]
@@ -47,9 +82,17 @@ pub mod wasm;
 
 // Re-export main API
 pub use ast::IxmlGrammar;
-pub use grammar_ast::parse_ixml_grammar;
-pub use native_parser::NativeParser;
+pub use capabilities::capabilities;
+pub use compiled_grammar::{CompiledGrammar, GrammarError};
+pub use compose::parse_ixml_grammar_with_imports;
+pub use grammar_ast::{
+    parse_ixml_grammar, parse_ixml_grammar_with_options, GrammarLimits, GrammarOptions,
+};
+pub use native_parser::{NativeParser, ParseStrategy};
+pub use outcome::{DocumentState, ParseOutcome};
 pub use parse_context::{ParseContext, ParseError, ParseResult};
+pub use xml_node::XmlNode;
+pub use xml_tree::XmlTree;
 
 // Re-export WASM API for convenience (only for browser/Node.js WASM, not IC canisters)
 #[cfg(all(target_arch = "wasm32", not(feature = "ic-canister")))]
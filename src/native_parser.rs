@@ -5,48 +5,384 @@
 //! It handles insertion and suppression semantics natively.
 
 use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Repetition, Rule, Sequence};
-use crate::charclass::charclass_to_rangeset;
-use crate::grammar_analysis::GrammarAnalysis;
+use crate::charclass::RangeSet;
+use crate::compiled_grammar::{CompiledGrammar, GrammarError, SymbolId};
+use crate::earley::EarleyGrammar;
+use crate::events::{self, ParseHandler};
+use crate::input_file::{read_file, InputOptions};
 use crate::input_stream::InputStream;
-use crate::parse_context::{ParseContext, ParseError, ParseResult};
-use crate::xml_node::XmlNode;
+use crate::outcome::{DocumentState, ParseOutcome};
+use crate::parse_context::{ParseContext, ParseError, ParseResult, TraceEvent, TraceKind};
+use crate::xml_node::{FixedAttributes, OutputLimits, SerializeOptions, XmlNode};
+use crate::xml_tree::{NodeId, XmlTree};
 use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Parse engine used by [`NativeParser`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseStrategy {
+    /// Memoizing recursive descent that commits to the longest matching alternative
+    #[default]
+    RecursiveDescent,
+    /// Earley chart parser: explores every derivation and reports real ambiguity
+    Earley,
+}
+
+/// Upper bound on seed-growing rounds for one left-recursive rule application
+const MAX_SEED_ITERATIONS: usize = 100;
+
+/// Parse steps between progress reports while the parse is not advancing
+/// through the input, so a parse stuck backtracking can still be cancelled
+const STEPS_PER_PROGRESS_REPORT: usize = 1 << 16;
+
+/// Name of the elements grouping a sequence's nodes until a rule wraps them
+const SEQUENCE: &str = "_sequence";
+
+/// The progress callback of a parse (see [`NativeParser::parse_with_progress`])
+struct Progress<'a> {
+    every: usize,
+    /// Furthest position reported so far, and the next one to report
+    furthest: usize,
+    next: usize,
+    /// Parse steps since the last report
+    steps: usize,
+    on_progress: &'a mut dyn FnMut(usize) -> bool,
+}
+
+impl<'a> Progress<'a> {
+    fn new(every: usize, on_progress: &'a mut dyn FnMut(usize) -> bool) -> Self {
+        let every = every.max(1);
+        Progress {
+            every,
+            furthest: 0,
+            next: every,
+            steps: 0,
+            on_progress,
+        }
+    }
+
+    /// Note a parse step at `position`, reporting progress when it is due
+    fn step(&mut self, position: usize) -> Result<(), ParseError> {
+        self.furthest = self.furthest.max(position);
+        self.steps += 1;
+        if self.furthest < self.next && self.steps < STEPS_PER_PROGRESS_REPORT {
+            return Ok(());
+        }
+        self.next = (self.furthest / self.every + 1) * self.every;
+        self.steps = 0;
+        match (self.on_progress)(self.furthest) {
+            true => Ok(()),
+            false => Err(ParseError::Cancelled {
+                position: self.furthest,
+            }),
+        }
+    }
+}
+
+/// A sub-parse the engine has been asked to perform
+enum Call<'g> {
+    Rule(SymbolId),
+    /// A choice; `rule` is set when these are a rule's own alternatives (for
+    /// profiling and profile-guided ordering)
+    Alternatives {
+        alts: &'g Alternatives,
+        rule: Option<SymbolId>,
+    },
+    Sequence(&'g Sequence),
+    Factor(&'g Factor),
+    Base(&'g BaseFactor),
+}
+
+/// What the engine does next: start a sub-parse, or hand a result to the waiting frame
+enum Step<'g> {
+    Call(Call<'g>),
+    Return(Result<ParseResult, ParseError>),
+}
+
+/// A suspended grammar construct waiting for the result of a sub-parse
+///
+/// Together the frames on the engine's stack play the role of the native call
+/// stack in a recursive descent parser.
+enum Frame<'g> {
+    /// Rule body being parsed; memoize the result when it returns
+    Rule {
+        rule: &'g Rule,
+        start: usize,
+        memo_key: (SymbolId, usize),
+    },
+    SeedGrowing(SeedFrame<'g>),
+    Alternatives(AlternativesFrame<'g>),
+    Sequence(SequenceFrame<'g>),
+    Repeat(RepeatFrame<'g>),
+    Optional {
+        start: usize,
+    },
+    /// Rule reference; apply the use-site mark when the rule returns
+    Nonterminal {
+        name: &'g str,
+        mark: Mark,
+        rule: &'g Rule,
+    },
+    Group {
+        key: (usize, usize),
+    },
+    NegativeLookahead {
+        start: usize,
+    },
+}
+
+/// Left-recursive rule application being grown (Warth et al., 2008)
+struct SeedFrame<'g> {
+    rule: &'g Rule,
+    start: usize,
+    memo_key: (SymbolId, usize),
+    seed: Result<ParseResult, ParseError>,
+    iteration: usize,
+}
+
+struct AlternativesFrame<'g> {
+    alts: &'g Alternatives,
+    rule: Option<&'g Rule>,
+    /// Source indices of the alternatives in the order to try them, if profiled
+    order: Option<&'g [usize]>,
+    start: usize,
+    /// Character at `start` (`None` at end of input)
+    first_char: Option<char>,
+    /// Position in the try order of the alternative being tried
+    next: usize,
+    /// (result, end_position, index) of the longest match so far
+    best: Option<(ParseResult, usize, usize)>,
+    /// Whether the longest match so far reaches end of input
+    best_at_eof: bool,
+    /// Set when a later alternative matched the same span as `best`
+    tied_end: Option<usize>,
+    attempts: usize,
+}
+
+impl AlternativesFrame<'_> {
+    /// Source index of the alternative being tried
+    fn index(&self) -> usize {
+        self.order.map_or(self.next, |order| order[self.next])
+    }
+}
+
+struct SequenceFrame<'g> {
+    seq: &'g Sequence,
+    start: usize,
+    /// Index of the factor being parsed
+    next: usize,
+    children: Vec<NodeId>,
+    ambiguous: bool,
+    consumed: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RepeatKind {
+    /// `*`
+    Star,
+    /// `+`
+    Plus,
+    /// `**`
+    SeparatedStar,
+    /// `++`
+    SeparatedPlus,
+}
+
+/// What a repetition frame is waiting for
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RepeatPhase {
+    First,
+    Element,
+    Separator,
+    ElementAfterSeparator,
+}
+
+struct RepeatFrame<'g> {
+    base: &'g BaseFactor,
+    separator: Option<&'g Sequence>,
+    kind: RepeatKind,
+    phase: RepeatPhase,
+    /// Position to backtrack to if the current iteration fails
+    loop_start: usize,
+    /// Separator matched in this iteration, committed once the element matches
+    separator_result: Option<ParseResult>,
+    children: Vec<NodeId>,
+    ambiguous: bool,
+    consumed: usize,
+}
 
 /// Native iXML parser that interprets grammar ASTs directly
+///
+/// ```
+/// use rustixml::{fixtures::CSV, parse_ixml_grammar, NativeParser};
+///
+/// let parser = NativeParser::new(parse_ixml_grammar(CSV.grammar).unwrap());
+/// let xml = parser.parse("x,y").unwrap();
+/// assert_eq!(xml, "<csv><row><field>x</field><field>y</field></row></csv>");
+///
+/// // Failures report line and column
+/// let grammar = parse_ixml_grammar(r#"digits: ["0"-"9"]+."#).unwrap();
+/// let err = NativeParser::new(grammar).parse("12a").unwrap_err();
+/// assert_eq!(err.location(), Some((1, 3)));
+/// assert!(err.to_string().starts_with("Parse error at line 1, column 3"));
+/// ```
+#[derive(Clone)]
 pub struct NativeParser {
-    grammar: IxmlGrammar,
-    rules: HashMap<String, Rule>,
-    analysis: GrammarAnalysis,
+    compiled: Arc<CompiledGrammar>,
+    strategy: ParseStrategy,
+    /// The lowered grammar, or why the Earley engine can't parse with it;
+    /// `None` unless the strategy is Earley
+    earley: Option<Result<EarleyGrammar, String>>,
+    fixed_attributes: FixedAttributes,
+    output_limits: OutputLimits,
+    serialize_options: SerializeOptions,
 }
 
 impl NativeParser {
     /// Create a new native parser from an iXML grammar
+    ///
+    /// The grammar is not validated: an undefined rule only fails the parses
+    /// that reach it. Use [`try_new`](Self::try_new) to reject such grammars.
     pub fn new(grammar: IxmlGrammar) -> Self {
-        // Analyze grammar using iterative algorithms (no stack overflow)
-        let analysis = GrammarAnalysis::analyze(&grammar);
-        let report = analysis.report();
-        if !report.contains("No issues") {
-            eprintln!("[rustixml] Grammar analysis:");
-            eprintln!("{}", report);
-        }
+        Self::with_strategy(grammar, ParseStrategy::default())
+    }
+
+    /// Create a parser, first checking the grammar for problems that would
+    /// otherwise only surface when a parse reaches them
+    ///
+    /// Rejects grammars with no rules, duplicate rule names or references to
+    /// undefined rules, listing all of them (see [`GrammarError`]).
+    pub fn try_new(grammar: IxmlGrammar) -> Result<Self, GrammarError> {
+        CompiledGrammar::try_new(grammar).map(|compiled| Self::from_compiled(Arc::new(compiled)))
+    }
+
+    /// Create a parser that uses the given parse engine
+    ///
+    /// [`ParseStrategy::Earley`] handles genuinely ambiguous grammars: instead of
+    /// picking the longest alternative it builds a shared packed parse forest and
+    /// marks the output with `ixml:state="ambiguous"` when more than one tree exists.
+    pub fn with_strategy(grammar: IxmlGrammar, strategy: ParseStrategy) -> Self {
+        Self::with_compiled(Arc::new(CompiledGrammar::new(grammar)), strategy)
+    }
+
+    /// Create a parser from a grammar compiled ahead of time
+    ///
+    /// Cheap for the default strategy: the grammar's preprocessing is shared,
+    /// not repeated, so one [`CompiledGrammar`] can serve many parsers and threads.
+    pub fn from_compiled(compiled: Arc<CompiledGrammar>) -> Self {
+        Self::with_compiled(compiled, ParseStrategy::default())
+    }
+
+    /// Create a parser from a compiled grammar using the given parse engine
+    pub fn with_compiled(compiled: Arc<CompiledGrammar>, strategy: ParseStrategy) -> Self {
+        // Lower the grammar once up front; a grammar without rules, or one the
+        // Earley engine does not support, is reported at parse time
+        let grammar = compiled.grammar();
+        let earley = match (strategy, grammar.rules.first()) {
+            (ParseStrategy::Earley, Some(start)) => {
+                Some(EarleyGrammar::from_grammar(grammar, &start.name))
+            }
+            _ => None,
+        };
 
-        // Build rule lookup table for O(1) access
-        let rules: HashMap<String, Rule> = grammar
-            .rules
-            .iter()
-            .map(|rule| (rule.name.clone(), rule.clone()))
-            .collect();
+        NativeParser {
+            fixed_attributes: FixedAttributes::from_grammar(grammar),
+            output_limits: OutputLimits::default(),
+            serialize_options: SerializeOptions::default(),
+            compiled,
+            strategy,
+            earley,
+        }
+    }
 
+    /// Rewrite the grammar's left-recursive rules as iterations first
+    ///
+    /// The recursive-descent engine stops left-recursive rules early, so
+    /// `expr: expr, "+", term; term.` only matches a single term. See
+    /// [`eliminate_left_recursion`](crate::normalize::eliminate_left_recursion)
+    /// for which rules are rewritten and how.
+    ///
+    /// ```
+    /// use rustixml::{parse_ixml_grammar, NativeParser};
+    ///
+    /// let grammar = parse_ixml_grammar(r#"expr: -expr, "+", digit; digit. digit: ["0"-"9"]."#).unwrap();
+    /// let parser = NativeParser::new(grammar).with_left_recursion_eliminated();
+    /// assert_eq!(
+    ///     parser.parse("1+2").unwrap(),
+    ///     "<expr><digit>1</digit>+<digit>2</digit></expr>"
+    /// );
+    /// ```
+    pub fn with_left_recursion_eliminated(self) -> Self {
+        let grammar = crate::normalize::eliminate_left_recursion(self.compiled.grammar());
         NativeParser {
-            grammar,
-            rules,
-            analysis,
+            fixed_attributes: self.fixed_attributes,
+            output_limits: self.output_limits,
+            serialize_options: self.serialize_options,
+            ..Self::with_compiled(Arc::new(CompiledGrammar::new(grammar)), self.strategy)
         }
     }
 
+    /// Replace the constant attributes added when results are serialized
+    ///
+    /// Defaults to the grammar's `{[fixed-attribute name "value"]}` pragmas;
+    /// extend them with [`FixedAttributes::from_grammar`] to keep those too.
+    pub fn with_fixed_attributes(mut self, fixed_attributes: FixedAttributes) -> Self {
+        self.fixed_attributes = fixed_attributes;
+        self
+    }
+
+    /// Get the constant attributes added when results are serialized
+    pub fn fixed_attributes(&self) -> &FixedAttributes {
+        &self.fixed_attributes
+    }
+
+    /// Cap the size of result documents (see [`OutputLimits`])
+    pub fn with_output_limits(mut self, output_limits: OutputLimits) -> Self {
+        self.output_limits = output_limits;
+        self
+    }
+
+    /// Get the caps on the size of result documents
+    pub fn output_limits(&self) -> OutputLimits {
+        self.output_limits
+    }
+
+    /// Lay out result documents as `options` say, e.g. pretty-printed
+    pub fn with_serialize_options(mut self, options: SerializeOptions) -> Self {
+        self.serialize_options = options;
+        self
+    }
+
+    /// Get the layout of result documents
+    pub fn serialize_options(&self) -> SerializeOptions {
+        self.serialize_options
+    }
+
+    /// Get the compiled grammar this parser runs
+    pub fn compiled(&self) -> &Arc<CompiledGrammar> {
+        &self.compiled
+    }
+
+    /// Get the parse engine this parser uses
+    pub fn strategy(&self) -> ParseStrategy {
+        self.strategy
+    }
+
     /// Get the number of rules in the grammar
     pub fn rule_count(&self) -> usize {
-        self.rules.len()
+        self.compiled.rule_count()
+    }
+
+    /// A fresh context for one parse, reporting rules by this grammar's names
+    fn context<'i>(&self) -> ParseContext<'i> {
+        let mut ctx = ParseContext::new().with_rule_names(self.compiled.rule_names().clone());
+        // Stop building the tree at the node limit rather than cutting it down afterwards
+        if let Some(max_nodes) = self.output_limits.max_nodes {
+            ctx.tree = ctx.tree.with_max_nodes(max_nodes);
+        }
+        ctx
     }
 
     /// Parse input text with an instruction budget (IC canister execution limit)
@@ -73,195 +409,461 @@ impl NativeParser {
         &self,
         input: &str,
         instruction_budget: Option<u64>,
-    ) -> Result<String, String> {
+    ) -> Result<String, ParseError> {
         let mut stream = InputStream::new(input);
-        let mut ctx = ParseContext::new();
+        let mut ctx = self.context().with_input(input);
         ctx.set_instruction_budget(instruction_budget);
 
-        self.parse_internal(&mut stream, &mut ctx, input)
+        let result = match self.parse_earley(input, None) {
+            Some(result) => result,
+            None => self.parse_internal(&mut stream, &mut ctx, 0, None),
+        };
+        self.outcome(result, &stream)
+            .into_result()
+            .map(|node| self.serialize(&node))
+            .map_err(|e| e.located(&InputStream::new(input)))
     }
 
     /// Parse input text according to the grammar
     ///
-    /// Returns XML string on success, or error message on failure
-    pub fn parse(&self, input: &str) -> Result<String, String> {
-        let mut stream = InputStream::new(input);
-        let mut ctx = ParseContext::new();
-
-        self.parse_internal(&mut stream, &mut ctx, input)
+    /// Returns the XML on success. A failure's [`ParseError`] is
+    /// [located](ParseError::located) in `input`: it displays with line,
+    /// column and context.
+    pub fn parse(&self, input: &str) -> Result<String, ParseError> {
+        self.parse_to_tree(input).map(|node| self.serialize(&node))
     }
 
-    /// Internal parse implementation (shared by parse() and parse_with_budget())
-    fn parse_internal(
+    /// Parse input text and report the result to `handler` as events
+    ///
+    /// The events follow [`parse`](Self::parse)'s output. They are read from
+    /// the tree [`parse_to_xml_tree`](Self::parse_to_xml_tree) returns, which
+    /// is never copied into an [`XmlNode`] (see [`crate::events`]).
+    pub fn parse_events(
         &self,
-        stream: &mut InputStream,
-        ctx: &mut ParseContext,
         input: &str,
-    ) -> Result<String, String> {
-        // Start with the first rule in the grammar
-        let start_rule = self
-            .grammar
-            .rules
-            .first()
-            .ok_or_else(|| "Grammar has no rules".to_string())?;
-
-        match self.parse_rule(stream, start_rule, ctx) {
-            Ok(result) => {
-                // Check if all input was consumed
-                if !stream.is_eof() {
-                    let remaining = stream.remaining();
-                    return Err(format!(
-                        "Parse succeeded but input remains: {:?}",
-                        remaining.chars().take(20).collect::<String>()
-                    ));
-                }
+        handler: &mut impl ParseHandler,
+    ) -> Result<(), ParseError> {
+        let tree = self.parse_to_xml_tree(input)?;
+        let root = tree.root().expect("parse_to_xml_tree sets the root");
+        events::emit(root, &self.fixed_attributes, handler);
+        Ok(())
+    }
 
-                // Convert node to XML string
-                if let Some(mut node) = result.node {
-                    // If grammar is potentially ambiguous, add ixml:state="ambiguous" to root element
-                    if self.analysis.is_potentially_ambiguous {
-                        node = self.add_ambiguity_marker(node);
-                    }
-                    Ok(node.to_xml())
-                } else {
-                    Err("Parse succeeded but produced no output (fully suppressed)".to_string())
-                }
-            }
-            Err(e) => Err(e.format_with_context(input)),
-        }
+    /// Parse input text, with the error as a message
+    #[deprecated(
+        since = "0.4.0",
+        note = "use `parse`, whose `ParseError` displays as the same message"
+    )]
+    pub fn parse_with_string_error(&self, input: &str) -> Result<String, String> {
+        self.parse(input).map_err(|e| e.to_string())
     }
 
-    /// Parse a complete rule
-    fn parse_rule(
-        &self,
-        stream: &mut InputStream,
-        rule: &Rule,
-        ctx: &mut ParseContext,
-    ) -> Result<ParseResult, ParseError> {
-        let start_pos = stream.position();
-        let memo_key = (rule.name.clone(), start_pos);
+    /// Parse input text into an [`XmlNode`] tree
+    ///
+    /// Same as [`NativeParser::parse`] without the serialization step, so the
+    /// result can be walked or transformed directly.
+    pub fn parse_to_tree(&self, input: &str) -> Result<XmlNode, ParseError> {
+        self.parse_outcome(input)
+            .into_result()
+            .map_err(|e| e.located(&InputStream::new(input)))
+    }
 
-        // Check memoization cache first
-        if let Some(cached_result) = ctx.memo_cache.get(&memo_key) {
-            // Clone the result and restore stream position
-            let result = cached_result.clone();
-            if let Ok(ref parse_result) = result {
-                stream.set_position(start_pos + parse_result.consumed);
+    /// Parse input text into an arena-backed [`XmlTree`]
+    ///
+    /// Like [`NativeParser::parse_to_tree`], but returns the tree as the
+    /// parser built it rather than copying every node into an [`XmlNode`].
+    /// The tree borrows `input`, and the text it matched is held as spans of
+    /// it rather than copied. The node limit of the parser's
+    /// [`OutputLimits`] applies; the byte limit only affects serialization.
+    ///
+    /// ```
+    /// use rustixml::fixtures::CSV;
+    ///
+    /// let tree = CSV.parser().parse_to_xml_tree(CSV.input).unwrap();
+    /// let csv = tree.root().unwrap();
+    /// assert_eq!(csv.name(), Some("csv"));
+    /// assert_eq!(csv.to_node().to_xml(), CSV.expected);
+    /// ```
+    pub fn parse_to_xml_tree<'i>(&self, input: &'i str) -> Result<XmlTree<'i>, ParseError> {
+        if self.earley.is_some() {
+            return self.parse_to_tree(input).map(|node| XmlTree::from(&node));
+        }
+        let mut stream = InputStream::new(input);
+        let mut ctx = self.context().with_input(input);
+        let (root, ambiguous) = self
+            .parse_document(&mut stream, &mut ctx, 0, None)
+            .map_err(|e| e.located(&stream))?;
+        // Leave behind the nodes of abandoned alternatives
+        let mut tree = ctx.tree.extract(root);
+        for state in self.document_states(ambiguous) {
+            tree.set_ixml_state(state.as_str());
+        }
+        // The tree holds at most about max_nodes nodes, so copying it to cut
+        // it down exactly is cheap
+        if let Some(max_nodes) = self.output_limits.max_nodes {
+            if tree.len() > max_nodes {
+                let mut node = tree.root().expect("extracted trees have a root").to_node();
+                if node.truncate(max_nodes).is_some() {
+                    tree = XmlTree::from(&node);
+                }
             }
-            return result;
         }
+        Ok(tree)
+    }
 
-        // Check for left recursion at this position
-        let is_left_recursive = !ctx.enter_rule(&rule.name, start_pos);
+    /// Parse input into a [`ParseOutcome`]
+    ///
+    /// A failed parse still yields a document, marked `ixml:state="failed"`,
+    /// and the document's `ixml:state` values are available as
+    /// [`DocumentState`]s for callers that branch on them.
+    ///
+    /// ```
+    /// use rustixml::{parse_ixml_grammar, DocumentState, NativeParser};
+    ///
+    /// let grammar = parse_ixml_grammar(r#"ixml version "2.0". n: ["0"-"9"]+."#).unwrap();
+    /// let parser = NativeParser::new(grammar);
+    ///
+    /// let outcome = parser.parse_outcome("42");
+    /// assert!(outcome.is_success() && outcome.is_version_mismatch());
+    ///
+    /// let outcome = parser.parse_outcome("4x");
+    /// assert_eq!(
+    ///     outcome.states(),
+    ///     [DocumentState::Failed, DocumentState::VersionMismatch]
+    /// );
+    /// ```
+    pub fn parse_outcome(&self, input: &str) -> ParseOutcome {
+        let mut stream = InputStream::new(input);
+        let result = match self.parse_earley(input, None) {
+            Some(result) => result,
+            None => {
+                self.parse_internal(&mut stream, &mut self.context().with_input(input), 0, None)
+            }
+        };
+        self.outcome(result, &stream)
+    }
 
-        if is_left_recursive {
-            // Left-recursion detected! Use seed-growing algorithm
-            return self.parse_with_seed_growing(stream, rule, ctx, start_pos, memo_key);
+    /// Parse input as a match for `start` instead of the grammar's first rule
+    ///
+    /// Useful for testing part of a grammar or parsing fragments, e.g. just the
+    /// `date` of a log-line grammar. The whole input must match the rule.
+    ///
+    /// ```
+    /// use rustixml::fixtures::CSV;
+    ///
+    /// let parser = CSV.parser();
+    /// assert_eq!(
+    ///     parser.parse_with_start("a,b", "row").unwrap(),
+    ///     "<row><field>a</field><field>b</field></row>"
+    /// );
+    /// assert!(parser.parse_with_start("a,b", "field").is_err());
+    /// assert_eq!(
+    ///     parser.parse_with_start("a", "cell").unwrap_err().to_string(),
+    ///     "Unknown start rule 'cell'"
+    /// );
+    /// ```
+    pub fn parse_with_start(&self, input: &str, start: &str) -> Result<String, ParseError> {
+        if self.compiled.symbol(start).is_none() {
+            return Err(self.unknown_start_rule(start));
         }
+        self.parse_outcome_with_start(input, start)
+            .into_result()
+            .map(|node| self.serialize(&node))
+            .map_err(|e| e.located(&InputStream::new(input)))
+    }
 
-        // Normal (non-left-recursive) parsing
-        let result = self.parse_alternatives(stream, &rule.alternatives, ctx);
+    /// Like [`parse_with_start`](Self::parse_with_start), keeping the
+    /// details of [`parse_outcome`](Self::parse_outcome)
+    ///
+    /// An unknown start rule is a failure at the start of the input.
+    pub fn parse_outcome_with_start(&self, input: &str, start: &str) -> ParseOutcome {
+        let custom = |message| ParseError::Custom {
+            message,
+            position: 0,
+        };
+        let mut stream = InputStream::new(input);
+        let Some(id) = self.compiled.symbol(start) else {
+            return self.outcome(Err(self.unknown_start_rule(start)), &stream);
+        };
 
-        ctx.exit_rule(&rule.name, start_pos);
+        let result = if self.strategy == ParseStrategy::Earley {
+            EarleyGrammar::from_grammar(self.compiled.grammar(), start)
+                .map_err(custom)
+                .and_then(|earley| earley.parse(input))
+        } else {
+            self.parse_internal(&mut stream, &mut self.context().with_input(input), id, None)
+        };
+        self.outcome(result, &stream)
+    }
 
-        // Apply rule-level mark to result
-        let final_result = result.map(|res| self.apply_rule_mark(res, rule));
+    fn unknown_start_rule(&self, start: &str) -> ParseError {
+        ParseError::Custom {
+            message: format!("Unknown start rule '{}'{}", start, self.did_you_mean(start)),
+            position: 0,
+        }
+    }
 
-        // Store in memoization cache (clone before storing)
-        ctx.memo_cache.insert(memo_key, final_result.clone());
+    /// Parse UTF-8 input streamed from a reader
+    ///
+    /// The recursive descent engine reads input in chunks and keeps only a
+    /// bounded window of it (see [`InputStream::with_backtrack_window`]), so
+    /// inputs far larger than memory for the text itself can be parsed. The
+    /// Earley engine needs random access and reads the whole input first.
+    pub fn parse_reader<R: Read + 'static>(&self, reader: R) -> Result<String, ParseError> {
+        self.parse_stream(InputStream::from_reader(reader))
+    }
 
-        final_result
+    /// Parse a prepared input stream (e.g. one with a custom backtracking window)
+    pub fn parse_stream(&self, stream: InputStream) -> Result<String, ParseError> {
+        self.parse_stream_reporting(stream, None)
     }
 
-    /// Parse with seed-growing for left-recursive rules (Warth et al., 2008)
-    fn parse_with_seed_growing(
+    /// Parse input, reporting progress to `on_progress` and stopping when it
+    /// returns `false`
+    ///
+    /// `on_progress` gets the furthest character position the parse has
+    /// reached each time that passes another `every` characters, and also
+    /// every so many parse steps, so a parse stuck backtracking can still be
+    /// stopped. A stopped parse fails with [`ParseError::Cancelled`].
+    ///
+    /// ```
+    /// use rustixml::{parse_ixml_grammar, NativeParser};
+    ///
+    /// let parser = NativeParser::new(parse_ixml_grammar(r#"s: ["a"-"z"]*."#).unwrap());
+    /// let mut reported = Vec::new();
+    /// let xml = parser.parse_with_progress("abcdefg", 3, |position| {
+    ///     reported.push(position);
+    ///     true
+    /// });
+    /// assert_eq!(xml.unwrap(), "<s>abcdefg</s>");
+    /// assert_eq!(reported, [3, 6]);
+    ///
+    /// let err = parser.parse_with_progress("abcdefg", 3, |_| false).unwrap_err();
+    /// assert!(err.is_cancelled());
+    /// assert_eq!(err.location(), Some((1, 4)));
+    /// ```
+    pub fn parse_with_progress(
         &self,
-        stream: &mut InputStream,
-        rule: &Rule,
-        ctx: &mut ParseContext,
-        start_pos: usize,
-        memo_key: (String, usize),
-    ) -> Result<ParseResult, ParseError> {
-        // Seed with failure (base case for recursion)
-        let mut seed: Result<ParseResult, ParseError> = Err(ParseError::LeftRecursion {
-            rule: rule.name.clone(),
-            position: start_pos,
-        });
-
-        // Store failure seed in cache
-        ctx.memo_cache.insert(memo_key.clone(), seed.clone());
+        input: &str,
+        every: usize,
+        mut on_progress: impl FnMut(usize) -> bool,
+    ) -> Result<String, ParseError> {
+        let mut progress = Progress::new(every, &mut on_progress);
+        self.parse_reporting(input, &mut progress)
+    }
 
-        // Grow the seed iteratively until fixed point
-        const MAX_ITERATIONS: usize = 100; // Safety limit to prevent infinite loops
-        let mut iteration = 0;
+    /// [`NativeParser::parse`], reporting to `progress`
+    fn parse_reporting(&self, input: &str, progress: &mut Progress) -> Result<String, ParseError> {
+        let mut stream = InputStream::new(input);
+        let result = match self.parse_earley(input, Some(&mut *progress)) {
+            Some(result) => result,
+            None => self.parse_internal(
+                &mut stream,
+                &mut self.context().with_input(input),
+                0,
+                Some(progress),
+            ),
+        };
+        self.outcome(result, &stream)
+            .into_result()
+            .map(|node| self.serialize(&node))
+            .map_err(|e| e.located(&InputStream::new(input)))
+    }
 
-        loop {
-            // Check instruction limit during seed-growing (prevent DoS via deep recursion)
-            ctx.check_instruction_limit()?;
+    /// Parse a prepared input stream, reporting progress as
+    /// [`parse_with_progress`](Self::parse_with_progress) does
+    pub fn parse_stream_with_progress(
+        &self,
+        stream: InputStream,
+        every: usize,
+        mut on_progress: impl FnMut(usize) -> bool,
+    ) -> Result<String, ParseError> {
+        let mut progress = Progress::new(every, &mut on_progress);
+        self.parse_stream_reporting(stream, Some(&mut progress))
+    }
 
-            iteration += 1;
-            if iteration > MAX_ITERATIONS {
-                // Safety limit reached - return current seed
-                break;
+    fn parse_stream_reporting(
+        &self,
+        mut stream: InputStream,
+        progress: Option<&mut Progress>,
+    ) -> Result<String, ParseError> {
+        let read_error = |stream: &InputStream, message: &str| ParseError::Custom {
+            message: message.to_string(),
+            position: stream.position(),
+        };
+        if self.earley.is_some() {
+            let mut input = String::new();
+            while let Some(ch) = stream.advance() {
+                input.push(ch);
             }
+            if let Some(e) = stream.read_error() {
+                return Err(read_error(&stream, e));
+            }
+            return match progress {
+                Some(progress) => self.parse_reporting(&input, progress),
+                None => self.parse(&input),
+            };
+        }
 
-            // Reset stream position for this iteration
-            stream.set_position(start_pos);
-
-            // Temporarily remove from recursion stack to allow re-entry
-            ctx.exit_rule(&rule.name, start_pos);
+        let mut ctx = self.context();
+        let result = self.parse_internal(&mut stream, &mut ctx, 0, progress);
 
-            // Try to parse (will use cached seed for recursive calls)
-            let result = self.parse_alternatives(stream, &rule.alternatives, ctx);
+        // Reader failures and window overruns explain a failed parse better than
+        // the parse error they caused
+        if let Some(e) = stream.read_error() {
+            return Err(read_error(&stream, e));
+        }
+        if stream.window_exceeded() {
+            return Err(read_error(
+                &stream,
+                "Parser backtracked further than the streaming input window; \
+                 increase it with InputStream::with_backtrack_window",
+            ));
+        }
+        self.outcome(result, &stream)
+            .into_result()
+            .map(|node| self.serialize(&node))
+            .map_err(|e| e.located(&stream))
+    }
 
-            // Re-add to recursion stack
-            let re_entered = ctx.enter_rule(&rule.name, start_pos);
-            debug_assert!(
-                !re_entered,
-                "Should not be able to re-enter during seed-growing"
-            );
+    /// Parse a file, detecting its encoding and keeping its line endings
+    ///
+    /// Errors display starting with the file's path.
+    pub fn parse_file(&self, path: impl AsRef<Path>) -> Result<String, ParseError> {
+        self.parse_path(path, &InputOptions::default())
+    }
 
-            // Apply rule-level mark to result
-            let final_result = result.map(|res| self.apply_rule_mark(res, rule));
+    /// Parse a file, decoding it as `options` say
+    ///
+    /// ```no_run
+    /// use rustixml::fixtures::CSV;
+    /// use rustixml::input_file::{InputOptions, Newlines};
+    ///
+    /// // A CSV file saved on Windows, with a byte order mark and \r\n line endings
+    /// let options = InputOptions::new().with_newlines(Newlines::Normalize);
+    /// match CSV.parser().parse_path("data.csv", &options) {
+    ///     Ok(xml) => println!("{}", xml),
+    ///     Err(e) => eprintln!("{}", e), // "data.csv: Parse error at line 3, ..."
+    /// }
+    /// ```
+    pub fn parse_path(
+        &self,
+        path: impl AsRef<Path>,
+        options: &InputOptions,
+    ) -> Result<String, ParseError> {
+        let path = path.as_ref();
+        let input = read_file(path, options).map_err(|message| ParseError::Custom {
+            message,
+            position: 0,
+        })?;
+        self.parse(&input).map_err(|mut e| {
+            if let ParseError::Located { report, .. } = &mut e {
+                *report = format!("{}: {}", path.display(), report);
+            }
+            e
+        })
+    }
 
-            // Check if we grew the parse
-            let grew = match (&seed, &final_result) {
-                // Grew from failure to success
-                (Err(_), Ok(_new_result)) => {
-                    seed = final_result.clone();
-                    ctx.memo_cache.insert(memo_key.clone(), seed.clone());
-                    true
-                }
-                // Grew from shorter to longer parse
-                (Ok(old_result), Ok(new_result)) if new_result.consumed > old_result.consumed => {
-                    seed = final_result.clone();
-                    ctx.memo_cache.insert(memo_key.clone(), seed.clone());
-                    true
+    /// Parse with the Earley engine, or `None` if this parser uses recursive
+    /// descent
+    fn parse_earley(
+        &self,
+        input: &str,
+        progress: Option<&mut Progress>,
+    ) -> Option<Result<(XmlNode, bool), ParseError>> {
+        self.earley.as_ref().map(|earley| match earley {
+            Ok(earley) => match progress {
+                Some(progress) => {
+                    earley.parse_reporting(input, Some(&mut |position| progress.step(position)))
                 }
-                // No growth - fixed point reached
-                _ => false,
-            };
+                None => earley.parse(input),
+            },
+            Err(message) => Err(ParseError::Custom {
+                message: message.clone(),
+                position: 0,
+            }),
+        })
+    }
 
-            if !grew {
-                // No growth, we've reached fixed point
-                break;
-            }
-        }
+    /// Internal recursive descent parse (shared by the public parse entry points)
+    ///
+    /// `start` is the rule the whole input must match; the public entry points
+    /// other than [`NativeParser::parse_with_start`] use the first rule. Returns
+    /// the tree and whether it is ambiguous, like [`EarleyGrammar::parse`].
+    fn parse_internal(
+        &self,
+        stream: &mut InputStream,
+        ctx: &mut ParseContext,
+        start: SymbolId,
+        progress: Option<&mut Progress>,
+    ) -> Result<(XmlNode, bool), ParseError> {
+        let (root, ambiguous) = self.parse_document(stream, ctx, start, progress)?;
+        Ok((ctx.tree.get(root).to_node(), ambiguous))
+    }
 
-        // Cleanup: remove from recursion stack
-        ctx.exit_rule(&rule.name, start_pos);
+    /// [`NativeParser::parse_internal`], leaving the tree in `ctx.tree`
+    fn parse_document(
+        &self,
+        stream: &mut InputStream,
+        ctx: &mut ParseContext,
+        start: SymbolId,
+        progress: Option<&mut Progress>,
+    ) -> Result<(NodeId, bool), ParseError> {
+        if self.compiled.grammar().rules.is_empty() {
+            return Err(ParseError::Custom {
+                message: "Grammar has no rules".to_string(),
+                position: 0,
+            });
+        }
 
-        // Restore stream position based on final result
-        stream.set_position(start_pos);
-        if let Ok(ref parse_result) = seed {
-            stream.set_position(start_pos + parse_result.consumed);
+        let result = self
+            .run(stream, ctx, Call::Rule(start), progress)
+            .map_err(|e| match e {
+                ParseError::Cancelled { .. } => e,
+                _ => ctx.trace(e),
+            })?;
+
+        // Check if all input was consumed
+        if !stream.is_eof() {
+            let remaining = stream.remaining();
+            return Err(ctx.trace(ParseError::Custom {
+                message: format!(
+                    "Parse succeeded but input remains: {:?}",
+                    remaining.chars().take(20).collect::<String>()
+                ),
+                position: stream.position(),
+            }));
         }
 
-        seed
+        let Some(mut node) = result.node else {
+            return Err(ParseError::Custom {
+                message: "Parse succeeded but produced no output (fully suppressed)".to_string(),
+                position: stream.position(),
+            });
+        };
+        // A tree that hit the node limit is marked truncated, with an empty
+        // document element if even that was dropped
+        if ctx.tree.is_dropped(node) {
+            let name = ctx.name(start).to_string();
+            node = ctx.tree.empty_element(&name);
+        }
+        if ctx.tree.is_truncated() {
+            ctx.tree.set_root(node);
+            ctx.tree.set_ixml_state(DocumentState::Truncated.as_str());
+            node = ctx.tree.root().expect("root was just set").id();
+        }
+        // Mark the root ambiguous only if two alternatives tied during this
+        // parse; the grammar analysis' guess is a warning, not a state
+        Ok((node, result.ambiguous))
     }
 
     /// Apply rule-level mark to parse result
-    fn apply_rule_mark(&self, mut result: ParseResult, rule: &Rule) -> ParseResult {
+    fn apply_rule_mark(
+        &self,
+        mut result: ParseResult,
+        rule: &Rule,
+        tree: &mut XmlTree,
+    ) -> ParseResult {
         match rule.mark {
             Mark::Hidden => {
                 // Don't wrap in element - pass through content as-is
@@ -271,214 +873,90 @@ impl NativeParser {
             }
             Mark::Attribute => {
                 // Convert to attribute
-                let text = result.node.map(|n| n.text_content()).unwrap_or_default();
-                result.node = Some(XmlNode::Attribute {
-                    name: rule.name.clone(),
-                    value: text,
-                });
+                let text = result
+                    .node
+                    .map(|n| tree.get(n).text_content())
+                    .unwrap_or_default();
+                result.node = Some(tree.attribute(&rule.name, &text));
             }
             Mark::Promoted => {
                 // Keep node as-is (promoted)
                 // Node is already unwrapped
             }
             Mark::None => {
-                // Wrap in element
-                // If the node is a _sequence wrapper, unwrap it and use its children
-                let mut children = match result.node {
-                    Some(XmlNode::Element { name, children, .. }) if name == "_sequence" => {
-                        // Unwrap sequence and use its children directly
-                        children
-                    }
-                    Some(node) => vec![node],
-                    None => vec![], // Empty element
-                };
-
-                // Recursively flatten any nested _sequence elements
-                children = Self::flatten_sequences(children);
-
-                // Extract attributes from children
-                let (attributes, non_attrs): (Vec<_>, Vec<_>) = children
-                    .into_iter()
-                    .partition(|node| matches!(node, XmlNode::Attribute { .. }));
-
-                // Convert attribute nodes to (name, value) tuples
-                let attrs: Vec<(String, String)> = attributes
-                    .into_iter()
-                    .filter_map(|node| {
-                        if let XmlNode::Attribute { name, value } = node {
-                            Some((name, value))
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
+                // Wrap in element, using the children of a _sequence wrapper
+                // (and of any _sequence nested in it) directly
+                let children: Vec<NodeId> = result.node.into_iter().collect();
+                let children = Self::flatten_sequences(tree, &children);
 
-                children = non_attrs;
-
-                result.node = Some(XmlNode::Element {
-                    name: rule.name.clone(),
-                    attributes: attrs,
-                    children,
-                });
+                // Attribute nodes become the element's attributes
+                result.node = Some(tree.element(&rule.name, &children));
             }
         }
 
         result
     }
 
-    /// Parse alternatives (choice)
-    fn parse_alternatives(
-        &self,
-        stream: &mut InputStream,
-        alts: &Alternatives,
-        ctx: &mut ParseContext,
-    ) -> Result<ParseResult, ParseError> {
-        let start_pos = stream.position();
-        let mut best_result: Option<(ParseResult, usize)> = None; // (result, end_position)
-        let mut attempts = 0;
-
-        // Try each alternative and keep the longest match
-        for alt in alts.alts.iter() {
-            // Check instruction limit before each alternative (prevent DoS via ambiguity)
-            ctx.check_instruction_limit()?;
-
-            stream.set_position(start_pos); // Reset for each alternative
-            attempts += 1;
-
-            match self.parse_sequence(stream, alt, ctx) {
-                Ok(result) => {
-                    let end_pos = stream.position();
-
-                    // Keep this result if it's the longest match so far
-                    match &best_result {
-                        None => {
-                            best_result = Some((result, end_pos));
-                        }
-                        Some((_, best_end)) => {
-                            if end_pos > *best_end {
-                                best_result = Some((result, end_pos));
-                            }
-                        }
-                    }
-                }
-                Err(_) => {
-                    continue; // Try next alternative
-                }
-            }
-        }
-
-        // Return the longest match, or error if all failed
-        match best_result {
-            Some((result, end_pos)) => {
-                stream.set_position(end_pos); // Commit to longest match
-                Ok(result)
-            }
-            None => Err(ParseError::NoAlternativeMatched {
-                position: start_pos,
-                rule: ctx.rule_name.clone(),
-                attempts,
-            }),
-        }
-    }
-
-    /// Parse a sequence (concatenation)
-    fn parse_sequence(
+    /// Parse input while counting, per rule, how often each alternative wins
+    ///
+    /// Used by [`crate::profile::AlternativeProfile::train`].
+    pub(crate) fn parse_collecting_wins(
         &self,
-        stream: &mut InputStream,
-        seq: &Sequence,
-        ctx: &mut ParseContext,
-    ) -> Result<ParseResult, ParseError> {
-        let start_pos = stream.position();
-        let mut children = Vec::new();
-        let mut total_consumed = 0;
-
-        // Parse each factor in sequence
-        for factor in &seq.factors {
-            match self.parse_factor(stream, factor, ctx) {
-                Ok(result) => {
-                    // Collect non-suppressed nodes
-                    if let Some(node) = result.node {
-                        children.push(node);
-                    }
-                    total_consumed += result.consumed;
-                }
-                Err(e) => {
-                    // Sequence failed - backtrack
-                    stream.set_position(start_pos);
-                    return Err(e);
-                }
-            }
-        }
-
-        // Return sequence as children nodes
-        let node = if children.is_empty() {
-            None // All suppressed
-        } else if children.len() == 1 {
-            Some(children.into_iter().next().unwrap())
-        } else {
-            // Multiple children - wrap in a container element
-            Some(XmlNode::Element {
-                name: "_sequence".to_string(),
-                attributes: vec![],
-                children,
-            })
-        };
-
-        Ok(ParseResult::new(node, total_consumed))
-    }
+        input: &str,
+    ) -> Result<HashMap<String, Vec<usize>>, String> {
+        let mut stream = InputStream::new(input);
+        let mut ctx = self.context().with_input(input);
+        ctx.alternative_wins = Some(HashMap::new());
 
-    /// Parse a factor (base + repetition)
-    fn parse_factor(
-        &self,
-        stream: &mut InputStream,
-        factor: &Factor,
-        ctx: &mut ParseContext,
-    ) -> Result<ParseResult, ParseError> {
-        match &factor.repetition {
-            Repetition::None => self.parse_base_factor(stream, &factor.base, ctx),
-            Repetition::ZeroOrMore => self.parse_zero_or_more(stream, &factor.base, ctx),
-            Repetition::OneOrMore => self.parse_one_or_more(stream, &factor.base, ctx),
-            Repetition::Optional => self.parse_optional(stream, &factor.base, ctx),
-            Repetition::SeparatedZeroOrMore(sep) => {
-                self.parse_separated_zero_or_more(stream, &factor.base, sep, ctx)
-            }
-            Repetition::SeparatedOneOrMore(sep) => {
-                self.parse_separated_one_or_more(stream, &factor.base, sep, ctx)
-            }
-        }
+        self.parse_internal(&mut stream, &mut ctx, 0, None)
+            .map_err(|e| e.format_with_context(input))?;
+        Ok(ctx.alternative_wins.unwrap_or_default())
     }
 
-    /// Parse a base factor (terminal, nonterminal, charclass, group)
-    fn parse_base_factor(
-        &self,
-        stream: &mut InputStream,
-        base: &BaseFactor,
-        ctx: &mut ParseContext,
-    ) -> Result<ParseResult, ParseError> {
-        match base {
-            BaseFactor::Literal {
-                value,
-                insertion,
-                mark,
-            } => self.parse_terminal(stream, value, *mark, *insertion),
-            BaseFactor::Nonterminal { name, mark } => {
-                self.parse_nonterminal(stream, name, *mark, ctx)
-            }
-            BaseFactor::CharClass {
-                content,
-                negated,
-                mark,
-            } => self.parse_charclass(stream, content, *negated, *mark),
-            BaseFactor::Group { alternatives } => {
-                self.parse_alternatives(stream, alternatives, ctx)
-            }
-        }
+    /// Parse input with the recursive descent engine, recording each rule
+    /// entered and left and each of its alternatives tried
+    ///
+    /// For working out why a parse went the way it did, e.g. why an
+    /// alternative was not chosen; `rustixml parse --trace` prints the events.
+    ///
+    /// ```
+    /// use rustixml::{parse_ixml_grammar, NativeParser};
+    ///
+    /// let grammar = parse_ixml_grammar(r#"s: a; b. a: "x", "y". b: "x", "z"."#).unwrap();
+    /// let parser = NativeParser::new(grammar);
+    /// let (outcome, events) = parser.parse_traced("xz");
+    /// assert!(outcome.is_success());
+    /// let lines: Vec<String> = events.iter().map(|e| e.to_string()).collect();
+    /// assert_eq!(
+    ///     lines,
+    ///     [
+    ///         "enter s at 0",
+    ///         "  enter a at 0",
+    ///         "    alternative 1 of a failed, backtracking to 0",
+    ///         "  fail a at 0",
+    ///         "  alternative 1 of s failed, backtracking to 0",
+    ///         "  enter b at 0",
+    ///         "    alternative 1 of b matched 0..2",
+    ///         "  exit b at 0..2",
+    ///         "  alternative 2 of s matched 0..2",
+    ///         "exit s at 0..2",
+    ///     ]
+    /// );
+    /// ```
+    pub fn parse_traced(&self, input: &str) -> (ParseOutcome, Vec<TraceEvent>) {
+        let mut stream = InputStream::new(input);
+        let mut ctx = self.context().with_input(input);
+        ctx.trace_events = Some(Vec::new());
+        let result = self.parse_internal(&mut stream, &mut ctx, 0, None);
+        let outcome = self.outcome(result, &stream);
+        (outcome, ctx.trace_events.unwrap_or_default())
     }
 
     /// Parse a terminal literal
     fn parse_terminal(
         &self,
         stream: &mut InputStream,
+        tree: &mut XmlTree,
         value: &str,
         mark: Mark,
         insertion: bool,
@@ -489,7 +967,7 @@ impl NativeParser {
         if insertion {
             let node = match mark {
                 Mark::Hidden => None,
-                _ => Some(XmlNode::Text(value.to_string())),
+                _ => Some(tree.text(value)),
             };
             return Ok(ParseResult::new(node, 0));
         }
@@ -525,16 +1003,27 @@ impl NativeParser {
         let consumed = value_chars.len();
         let node = match mark {
             Mark::Hidden => None,
-            _ => Some(XmlNode::Text(value.to_string())),
+            _ => Some(Self::matched_text(stream, tree, start_pos, value)),
         };
 
         Ok(ParseResult::new(node, consumed))
     }
 
-    /// Parse a character class
+    /// A text node for `text`, matched at `start`; a span of the input rather
+    /// than a copy when the tree holds the input
+    fn matched_text(stream: &InputStream, tree: &mut XmlTree, start: usize, text: &str) -> NodeId {
+        match stream.byte_offset(start) {
+            Some(from) if tree.input().is_some() => tree.span(from..from + text.len()),
+            _ => tree.text(text),
+        }
+    }
+
+    /// Match one character against a character class's compiled `class`
     fn parse_charclass(
         &self,
         stream: &mut InputStream,
+        tree: &mut XmlTree,
+        class: &RangeSet,
         content: &str,
         negated: bool,
         mark: Mark,
@@ -556,10 +1045,7 @@ impl NativeParser {
             }
         };
 
-        // Convert character class to RangeSet and check if character matches
-        let rangeset = charclass_to_rangeset(content);
-        let matches = rangeset.contains(ch);
-        let actual_match = if negated { !matches } else { matches };
+        let actual_match = class.contains(ch) != negated;
 
         if !actual_match {
             return Err(ParseError::CharClassMismatch {
@@ -574,127 +1060,38 @@ impl NativeParser {
         stream.advance();
         let node = match mark {
             Mark::Hidden => None,
-            _ => Some(XmlNode::Text(ch.to_string())),
+            _ => Some(Self::matched_text(
+                stream,
+                tree,
+                start_pos,
+                ch.encode_utf8(&mut [0; 4]),
+            )),
         };
 
         Ok(ParseResult::new(node, 1))
     }
 
-    /// Parse a nonterminal (rule reference)
-    fn parse_nonterminal(
-        &self,
-        stream: &mut InputStream,
-        name: &str,
-        mark: Mark,
-        ctx: &mut ParseContext,
-    ) -> Result<ParseResult, ParseError> {
-        let start_pos = stream.position();
-
-        // Look up the rule
-        let rule = self.rules.get(name).ok_or_else(|| ParseError::Custom {
-            message: format!("Undefined rule: {}", name),
-            position: start_pos,
-        })?;
-
-        // Parse the rule
-        let result = self.parse_rule(stream, rule, ctx)?;
-
-        // Apply factor-level mark to the result
-        let node = result.node.and_then(|n| match mark {
-            Mark::Hidden => {
-                // Factor-level hiding: unwrap element and pass through children + attributes
-                // If the result is an Element, extract its children and attributes
-                match n {
-                    XmlNode::Element {
-                        children,
-                        attributes,
-                        ..
-                    } => {
-                        // Pass through both children and attributes
-                        // Convert attributes back to Attribute nodes
-                        let mut all_nodes = Vec::new();
-
-                        // Add attributes as Attribute nodes
-                        for (name, value) in attributes {
-                            all_nodes.push(XmlNode::Attribute { name, value });
-                        }
-
-                        // Add children
-                        all_nodes.extend(children);
-
-                        if all_nodes.is_empty() {
-                            None
-                        } else if all_nodes.len() == 1 {
-                            Some(all_nodes.into_iter().next().unwrap())
-                        } else {
-                            // Multiple items - wrap in _sequence for now
-                            Some(XmlNode::Element {
-                                name: "_sequence".to_string(),
-                                attributes: vec![],
-                                children: all_nodes,
-                            })
-                        }
-                    }
-                    // For non-Element nodes (Text, Attribute), keep them
-                    other => Some(other),
-                }
-            }
-            Mark::Attribute => {
-                // Convert to attribute
-                Some(XmlNode::Attribute {
-                    name: name.to_string(),
-                    value: n.text_content(),
-                })
-            }
-            Mark::Promoted => {
-                // Promote content: Override any rule-level mark and wrap in element
-                // If the result is NOT already wrapped in its rule name, wrap it
-                match n {
-                    XmlNode::Element { ref name, .. } if name == &rule.name => {
-                        // Already wrapped in rule element, keep as-is
-                        Some(n)
-                    }
-                    _ => {
-                        // Not wrapped or wrapped in different element - wrap it
-                        // First unwrap if it's a _sequence
-                        let children = match n {
-                            XmlNode::Element { name, children, .. } if name == "_sequence" => {
-                                children
-                            }
-                            other => vec![other],
-                        };
-
-                        // Wrap in rule element
-                        Some(XmlNode::Element {
-                            name: rule.name.clone(),
-                            attributes: vec![],
-                            children,
-                        })
-                    }
-                }
-            }
-            Mark::None => {
-                // Keep as-is (already wrapped by rule-level mark)
-                Some(n)
-            }
-        });
-
-        Ok(ParseResult::new(node, result.consumed))
+    /// Whether `node` is a `_sequence` wrapper
+    fn is_sequence(tree: &XmlTree, node: NodeId) -> bool {
+        let node = tree.get(node);
+        node.is_element() && node.name() == Some(SEQUENCE)
     }
 
-    /// Recursively flatten nested _sequence elements
-    fn flatten_sequences(children: Vec<XmlNode>) -> Vec<XmlNode> {
+    /// Flatten nested _sequence elements (without recursion, so nesting depth is unbounded)
+    fn flatten_sequences(tree: &XmlTree, children: &[NodeId]) -> Vec<NodeId> {
         let mut flattened = Vec::new();
+        let mut pending = vec![children.iter()];
 
-        for node in children {
-            match node {
-                XmlNode::Element { name, children, .. } if name == "_sequence" => {
-                    // Recursively flatten and add children
-                    flattened.extend(Self::flatten_sequences(children));
-                }
-                other => {
-                    flattened.push(other);
-                }
+        while let Some(iter) = pending.last_mut() {
+            let Some(&node) = iter.next() else {
+                pending.pop();
+                continue;
+            };
+            if Self::is_sequence(tree, node) {
+                // Flatten its children before continuing with our siblings
+                pending.push(tree.links(node).iter());
+            } else {
+                flattened.push(node);
             }
         }
 
@@ -702,367 +1099,829 @@ impl NativeParser {
     }
 
     /// Merge consecutive Text nodes and return an appropriate node
-    fn merge_nodes(&self, children: Vec<XmlNode>) -> Option<XmlNode> {
+    fn merge_nodes(&self, tree: &mut XmlTree, children: Vec<NodeId>) -> Option<NodeId> {
         if children.is_empty() {
             return None;
         }
 
         // Merge consecutive Text nodes
         let mut merged = Vec::new();
-        let mut text_buffer = String::new();
+        let mut texts = Vec::new();
 
         for node in children {
-            match node {
-                XmlNode::Text(s) => {
-                    text_buffer.push_str(&s);
-                }
-                other => {
-                    // Flush text buffer if not empty
-                    if !text_buffer.is_empty() {
-                        merged.push(XmlNode::Text(text_buffer.clone()));
-                        text_buffer.clear();
-                    }
-                    merged.push(other);
-                }
+            if tree.get(node).is_text() {
+                texts.push(node);
+            } else {
+                Self::flush_text(tree, &mut texts, &mut merged);
+                merged.push(node);
             }
         }
-
-        // Flush remaining text
-        if !text_buffer.is_empty() {
-            merged.push(XmlNode::Text(text_buffer));
-        }
+        Self::flush_text(tree, &mut texts, &mut merged);
 
         // Return result
-        if merged.is_empty() {
-            None
-        } else if merged.len() == 1 {
-            Some(merged.into_iter().next().unwrap())
-        } else {
+        match merged.len() {
+            0 => None,
+            1 => merged.pop(),
             // Multiple non-text nodes - wrap in sequence
-            Some(XmlNode::Element {
-                name: "_sequence".to_string(),
-                attributes: vec![],
-                children: merged,
-            })
+            _ => Some(tree.group(SEQUENCE, &merged)),
         }
     }
 
-    /// Parse zero or more repetitions (*)
-    fn parse_zero_or_more(
-        &self,
+    /// Join the text nodes `texts` onto `merged`, unless their text is empty
+    fn flush_text(tree: &mut XmlTree, texts: &mut Vec<NodeId>, merged: &mut Vec<NodeId>) {
+        let text = match texts.as_slice() {
+            [] => return,
+            [text] => *text,
+            _ => tree.join_text(texts),
+        };
+        texts.clear();
+        if tree.get(text).text() != Some("") {
+            merged.push(text);
+        }
+    }
+
+    /// Run the recursive descent engine on `call`
+    ///
+    /// Grammar constructs are suspended as [`Frame`]s on a heap-allocated stack
+    /// rather than as native calls, so deeply nested input is limited by memory,
+    /// not by the thread's stack size.
+    fn run<'g>(
+        &'g self,
         stream: &mut InputStream,
-        base: &BaseFactor,
         ctx: &mut ParseContext,
+        call: Call<'g>,
+        mut progress: Option<&mut Progress>,
     ) -> Result<ParseResult, ParseError> {
-        let _start_pos = stream.position();
-        let mut children = Vec::new();
-        let mut total_consumed = 0;
+        let mut frames: Vec<Frame<'g>> = Vec::new();
+        let mut step = Step::Call(call);
 
-        // Keep matching until we fail
         loop {
-            // Check instruction limit during repetition (prevent DoS via * or + loops)
-            ctx.check_instruction_limit()?;
+            if let Some(progress) = &mut progress {
+                progress.step(stream.position())?;
+            }
+            step = match step {
+                Step::Call(call) => self.call(call, stream, ctx, &mut frames),
+                Step::Return(result) => match frames.pop() {
+                    Some(frame) => self.resume(frame, result, stream, ctx, &mut frames),
+                    None => return result,
+                },
+            };
+        }
+    }
+
+    /// Start a sub-parse: either finish it immediately or push a frame and
+    /// request the first nested sub-parse
+    fn call<'g>(
+        &'g self,
+        call: Call<'g>,
+        stream: &mut InputStream,
+        ctx: &mut ParseContext,
+        frames: &mut Vec<Frame<'g>>,
+    ) -> Step<'g> {
+        let start = stream.position();
+        match call {
+            Call::Rule(id) => self.call_rule(id, stream, ctx, frames),
+            Call::Alternatives { alts, rule } => {
+                let frame = AlternativesFrame {
+                    alts,
+                    rule: rule.map(|id| self.compiled.rule(id)),
+                    order: rule.and_then(|id| self.compiled.alternative_order(id)),
+                    start,
+                    first_char: stream.current(),
+                    next: 0,
+                    best: None,
+                    best_at_eof: false,
+                    tied_end: None,
+                    attempts: 0,
+                };
+                self.next_alternative(frame, stream, ctx, frames)
+            }
+            Call::Sequence(seq) => {
+                let frame = SequenceFrame {
+                    seq,
+                    start,
+                    next: 0,
+                    children: Vec::new(),
+                    ambiguous: false,
+                    consumed: 0,
+                };
+                self.next_factor(frame, ctx, frames)
+            }
+            Call::Factor(factor) => {
+                let (kind, separator) = match &factor.repetition {
+                    Repetition::None => return Step::Call(Call::Base(&factor.base)),
+                    Repetition::Optional => {
+                        frames.push(Frame::Optional { start });
+                        return Step::Call(Call::Base(&factor.base));
+                    }
+                    Repetition::ZeroOrMore => (RepeatKind::Star, None),
+                    Repetition::OneOrMore => (RepeatKind::Plus, None),
+                    Repetition::SeparatedZeroOrMore(sep) => {
+                        (RepeatKind::SeparatedStar, Some(&**sep))
+                    }
+                    Repetition::SeparatedOneOrMore(sep) => {
+                        (RepeatKind::SeparatedPlus, Some(&**sep))
+                    }
+                };
+                let frame = RepeatFrame {
+                    base: &factor.base,
+                    separator,
+                    kind,
+                    phase: RepeatPhase::First,
+                    loop_start: start,
+                    separator_result: None,
+                    children: Vec::new(),
+                    ambiguous: false,
+                    consumed: 0,
+                };
+                if kind == RepeatKind::Star {
+                    // Zero matches are fine, so there is no distinguished first element
+                    self.next_repeat_element(frame, stream, ctx, frames)
+                } else {
+                    frames.push(Frame::Repeat(frame));
+                    Step::Call(Call::Base(&factor.base))
+                }
+            }
+            Call::Base(base) => match base {
+                BaseFactor::Literal {
+                    value,
+                    insertion,
+                    mark,
+                } => {
+                    let result =
+                        self.parse_terminal(stream, &mut ctx.tree, value, *mark, *insertion);
+                    if result.is_err() {
+                        ctx.record_failure(stream, start, || {
+                            format!("\"{}\"", value.replace('"', "\"\""))
+                        });
+                    }
+                    Step::Return(result)
+                }
+                BaseFactor::CharClass {
+                    content,
+                    negated,
+                    mark,
+                } => {
+                    let class = self.compiled.class(base);
+                    let result = self.parse_charclass(
+                        stream,
+                        &mut ctx.tree,
+                        &class,
+                        content,
+                        *negated,
+                        *mark,
+                    );
+                    if result.is_err() {
+                        ctx.record_failure(stream, start, || {
+                            format!("{}[{}]", if *negated { "~" } else { "" }, content)
+                        });
+                    }
+                    Step::Return(result)
+                }
+                BaseFactor::Nonterminal { name, mark } => match self.compiled.reference(base) {
+                    // The next character can't begin this rule: skip trying its alternatives
+                    Some(id) if !self.compiled.can_start(id, stream.current()) => {
+                        ctx.record_failure(stream, start, || name.clone());
+                        Step::Return(Err(ParseError::NoAlternativeMatched {
+                            position: start,
+                            rule: name.clone(),
+                            attempts: 0,
+                        }))
+                    }
+                    Some(id) => {
+                        frames.push(Frame::Nonterminal {
+                            name,
+                            mark: *mark,
+                            rule: self.compiled.rule(id),
+                        });
+                        Step::Call(Call::Rule(id))
+                    }
+                    None => {
+                        let suggestion = self.did_you_mean(name);
+                        ctx.record_failure(stream, start, || {
+                            format!("undefined rule {}{}", name, suggestion)
+                        });
+                        Step::Return(Err(ParseError::Custom {
+                            message: format!("Undefined rule: {}{}", name, suggestion),
+                            position: start,
+                        }))
+                    }
+                },
+                BaseFactor::Group { alternatives } => {
+                    let alternatives: &Alternatives = alternatives;
+                    // The grammar outlives the parse, so the group's address identifies it;
+                    // reuse the winning alternative if this group was already parsed here
+                    let key = (alternatives as *const Alternatives as usize, start);
+                    if let Some(cached) = ctx.group_cache.get(&key) {
+                        let result = cached.clone();
+                        if let Ok(ref parse_result) = result {
+                            stream.set_position(start + parse_result.consumed);
+                        }
+                        return Step::Return(result);
+                    }
+                    frames.push(Frame::Group { key });
+                    Step::Call(Call::Alternatives {
+                        alts: alternatives,
+                        rule: None,
+                    })
+                }
+                BaseFactor::NegativeLookahead { base } => {
+                    ctx.lookahead_depth += 1;
+                    frames.push(Frame::NegativeLookahead { start });
+                    Step::Call(Call::Base(base))
+                }
+            },
+        }
+    }
+
+    /// Deliver a sub-parse result to the frame that requested it
+    fn resume<'g>(
+        &'g self,
+        frame: Frame<'g>,
+        result: Result<ParseResult, ParseError>,
+        stream: &mut InputStream,
+        ctx: &mut ParseContext,
+        frames: &mut Vec<Frame<'g>>,
+    ) -> Step<'g> {
+        match frame {
+            Frame::Rule {
+                rule,
+                start,
+                memo_key,
+            } => {
+                ctx.exit_rule(memo_key.0, start);
+                let end = result.as_ref().ok().map(|_| stream.position());
+                ctx.record_trace(&rule.name, start, TraceKind::Exit { end });
 
-            let loop_start = stream.position();
+                // Apply rule-level mark to result
+                let final_result = result.map(|res| self.apply_rule_mark(res, rule, &mut ctx.tree));
 
-            // Try to match the base factor
-            match self.parse_base_factor(stream, base, ctx) {
-                Ok(result) => {
-                    // Epsilon-match detection: prevent infinite loops
-                    if result.consumed == 0 {
-                        // If we matched but consumed nothing, we'd loop forever
-                        // Break here (but keep the match if it produced a node)
-                        if let Some(node) = result.node {
-                            children.push(node);
+                // Store in memoization cache (clone before storing)
+                ctx.memo_cache.insert(memo_key, final_result.clone());
+                Step::Return(final_result)
+            }
+            Frame::SeedGrowing(seed) => self.grow_seed(seed, result, stream, ctx, frames),
+            Frame::Alternatives(mut alts) => {
+                if let Some(rule) = alts.rule {
+                    let end = result.as_ref().ok().map(|_| stream.position());
+                    let index = alts.index();
+                    ctx.record_trace(
+                        &rule.name,
+                        alts.start,
+                        TraceKind::Alternative { index, end },
+                    );
+                }
+                if let Ok(result) = result {
+                    let end_pos = stream.position();
+
+                    // Keep this result if it's the longest match so far
+                    match &alts.best {
+                        Some((_, best_end, _)) if end_pos < *best_end => {}
+                        Some((_, best_end, _)) if end_pos == *best_end => {
+                            // Two alternatives cover the same span: two parse trees
+                            alts.tied_end = Some(end_pos);
+                        }
+                        _ => {
+                            alts.best = Some((result, end_pos, alts.index()));
+                            alts.best_at_eof = stream.is_eof();
+                            alts.tied_end = None;
                         }
-                        break;
                     }
+                }
 
-                    // Collect non-suppressed nodes
+                // Nothing can beat a match that reaches end of input, but a
+                // later alternative can still tie with it; stop early once the
+                // tie is known or no later alternative could start here
+                if alts.best_at_eof
+                    && (alts.tied_end.is_some() || !self.later_alternative_can_start(&alts))
+                {
+                    return self.finish_alternatives(alts, stream, ctx);
+                }
+                alts.next += 1;
+                self.next_alternative(alts, stream, ctx, frames)
+            }
+            Frame::Sequence(mut seq) => match result {
+                Ok(result) => {
+                    seq.ambiguous |= result.ambiguous;
                     if let Some(node) = result.node {
-                        children.push(node);
+                        seq.children.push(node);
                     }
-                    total_consumed += result.consumed;
+                    seq.consumed += result.consumed;
+                    seq.next += 1;
+                    self.next_factor(seq, ctx, frames)
+                }
+                Err(e) => {
+                    // Sequence failed - backtrack
+                    stream.set_position(seq.start);
+                    Step::Return(Err(e))
                 }
+            },
+            Frame::Repeat(repeat) => self.repeat_step(repeat, result, stream, ctx, frames),
+            Frame::Optional { start } => match result {
+                Ok(result) => Step::Return(Ok(result)),
                 Err(_) => {
-                    // Failed to match - that's OK for zero-or-more
-                    stream.set_position(loop_start); // Backtrack this attempt
-                    break;
+                    // Failed - that's OK for optional
+                    stream.set_position(start);
+                    Step::Return(Ok(ParseResult::new(None, 0)))
                 }
+            },
+            Frame::Nonterminal { name, mark, rule } => Step::Return(
+                result.map(|res| self.apply_factor_mark(res, name, mark, rule, &mut ctx.tree)),
+            ),
+            Frame::Group { key } => {
+                ctx.group_cache.insert(key, result.clone());
+                Step::Return(result)
+            }
+            Frame::NegativeLookahead { start } => {
+                // Succeeds without consuming input if the factor fails to match here
+                ctx.lookahead_depth -= 1;
+                stream.set_position(start);
+                Step::Return(match result {
+                    Ok(_) => Err(ParseError::Custom {
+                        message: "Negative lookahead matched".to_string(),
+                        position: start,
+                    }),
+                    Err(_) => Ok(ParseResult::suppressed(0)),
+                })
             }
         }
+    }
+
+    /// Start parsing a rule, answering from the memo cache when possible
+    fn call_rule<'g>(
+        &'g self,
+        id: SymbolId,
+        stream: &mut InputStream,
+        ctx: &mut ParseContext,
+        frames: &mut Vec<Frame<'g>>,
+    ) -> Step<'g> {
+        let rule = self.compiled.rule(id);
+        let start = stream.position();
+        let memo_key = (id, start);
 
-        // Return collected nodes (merged if they're all text)
-        Ok(ParseResult::new(self.merge_nodes(children), total_consumed))
+        // Check memoization cache first
+        if let Some(cached_result) = ctx.memo_cache.get(&memo_key) {
+            // Clone the result and restore stream position
+            let result = cached_result.clone();
+            if let Ok(ref parse_result) = result {
+                stream.set_position(start + parse_result.consumed);
+            }
+            let end = result.as_ref().ok().map(|r| start + r.consumed);
+            ctx.record_trace(&rule.name, start, TraceKind::Memoized { end });
+            return Step::Return(result);
+        }
+
+        ctx.record_trace(&rule.name, start, TraceKind::Enter);
+
+        // Check for left recursion at this position
+        if !ctx.enter_rule(id, start) {
+            // Left-recursion detected! Seed with failure (base case for recursion)
+            let seed = Err(ParseError::LeftRecursion {
+                rule: rule.name.clone(),
+                position: start,
+            });
+            ctx.memo_cache.insert(memo_key, seed.clone());
+            let frame = SeedFrame {
+                rule,
+                start,
+                memo_key,
+                seed,
+                iteration: 0,
+            };
+            return self.next_seed_iteration(frame, stream, ctx, frames);
+        }
+
+        frames.push(Frame::Rule {
+            rule,
+            start,
+            memo_key,
+        });
+        Step::Call(Call::Alternatives {
+            alts: &rule.alternatives,
+            rule: Some(id),
+        })
     }
 
-    /// Parse one or more repetitions (+)
-    fn parse_one_or_more(
-        &self,
+    /// Re-parse a left-recursive rule's alternatives against the current seed
+    fn next_seed_iteration<'g>(
+        &'g self,
+        mut frame: SeedFrame<'g>,
         stream: &mut InputStream,
-        base: &BaseFactor,
         ctx: &mut ParseContext,
-    ) -> Result<ParseResult, ParseError> {
-        let _start_pos = stream.position();
+        frames: &mut Vec<Frame<'g>>,
+    ) -> Step<'g> {
+        // Check instruction limit during seed-growing (prevent DoS via deep recursion)
+        if let Err(e) = ctx.check_instruction_limit() {
+            return Step::Return(Err(e));
+        }
 
-        // Must match at least once
-        let first_result = self.parse_base_factor(stream, base, ctx)?;
-        let mut children = Vec::new();
-        let mut total_consumed = first_result.consumed;
+        frame.iteration += 1;
+        if frame.iteration > MAX_SEED_ITERATIONS {
+            // Safety limit reached - return current seed
+            return self.finish_seed(frame, stream, ctx);
+        }
+
+        // Reset stream position for this iteration
+        stream.set_position(frame.start);
+
+        // Groups parsed with the previous seed may have changed
+        ctx.group_cache.clear();
+
+        // Temporarily allow re-entry; the rule stays on the rule stack
+        ctx.allow_reentry(frame.memo_key.0, frame.start);
 
-        if let Some(node) = first_result.node {
-            children.push(node);
+        // Recursive calls at this position will use the cached seed
+        let (rule, id) = (frame.rule, frame.memo_key.0);
+        frames.push(Frame::SeedGrowing(frame));
+        Step::Call(Call::Alternatives {
+            alts: &rule.alternatives,
+            rule: Some(id),
+        })
+    }
+
+    /// Keep a longer parse as the new seed and iterate, or stop at the fixed point
+    fn grow_seed<'g>(
+        &'g self,
+        mut frame: SeedFrame<'g>,
+        result: Result<ParseResult, ParseError>,
+        stream: &mut InputStream,
+        ctx: &mut ParseContext,
+        frames: &mut Vec<Frame<'g>>,
+    ) -> Step<'g> {
+        // Re-add to recursion stack
+        ctx.forbid_reentry(frame.memo_key.0, frame.start);
+
+        // Apply rule-level mark to result
+        let final_result = result.map(|res| self.apply_rule_mark(res, frame.rule, &mut ctx.tree));
+
+        // Grew from failure to success, or from a shorter to a longer parse
+        let grew = match (&frame.seed, &final_result) {
+            (Err(_), Ok(_)) => true,
+            (Ok(old_result), Ok(new_result)) => new_result.consumed > old_result.consumed,
+            _ => false,
+        };
+
+        if grew {
+            frame.seed = final_result;
+            ctx.memo_cache.insert(frame.memo_key, frame.seed.clone());
+            self.next_seed_iteration(frame, stream, ctx, frames)
+        } else {
+            // No growth, we've reached fixed point
+            self.finish_seed(frame, stream, ctx)
         }
+    }
 
-        // Epsilon-match check: if first match consumed nothing, don't loop
-        if first_result.consumed == 0 {
-            let node = if children.is_empty() {
-                None
-            } else {
-                Some(children.into_iter().next().unwrap())
-            };
-            return Ok(ParseResult::new(node, total_consumed));
+    fn finish_seed<'g>(
+        &'g self,
+        frame: SeedFrame<'g>,
+        stream: &mut InputStream,
+        ctx: &mut ParseContext,
+    ) -> Step<'g> {
+        // Cleanup: remove from recursion stack (the enclosing call of this rule
+        // at this position is still on the rule stack and pops it when it returns)
+        ctx.allow_reentry(frame.memo_key.0, frame.start);
+        ctx.depth = ctx.depth.saturating_sub(1);
+
+        // Restore stream position based on final result
+        stream.set_position(frame.start);
+        if let Ok(ref parse_result) = frame.seed {
+            stream.set_position(frame.start + parse_result.consumed);
         }
+        let end = frame.seed.as_ref().ok().map(|_| stream.position());
+        ctx.record_trace(&frame.rule.name, frame.start, TraceKind::Exit { end });
 
-        // Try to match more
-        loop {
-            let loop_start = stream.position();
+        Step::Return(frame.seed)
+    }
 
-            match self.parse_base_factor(stream, base, ctx) {
-                Ok(result) => {
-                    // Epsilon-match detection
-                    if result.consumed == 0 {
-                        if let Some(node) = result.node {
-                            children.push(node);
-                        }
-                        break;
-                    }
+    /// Try the next alternative of a choice, or pick the winner once all are tried
+    fn next_alternative<'g>(
+        &'g self,
+        mut frame: AlternativesFrame<'g>,
+        stream: &mut InputStream,
+        ctx: &mut ParseContext,
+        frames: &mut Vec<Frame<'g>>,
+    ) -> Step<'g> {
+        if frame.next >= frame.alts.alts.len() {
+            return self.finish_alternatives(frame, stream, ctx);
+        }
+        let alt = &frame.alts.alts[frame.index()];
 
-                    if let Some(node) = result.node {
-                        children.push(node);
-                    }
-                    total_consumed += result.consumed;
-                }
-                Err(_) => {
-                    stream.set_position(loop_start);
-                    break;
-                }
-            }
+        // Check instruction limit before each alternative (prevent DoS via ambiguity)
+        if let Err(e) = ctx.check_instruction_limit() {
+            return Step::Return(Err(e));
         }
 
-        // Return collected nodes (merged if they're all text)
-        Ok(ParseResult::new(self.merge_nodes(children), total_consumed))
+        stream.set_position(frame.start); // Reset for each alternative
+        frame.attempts += 1;
+        frames.push(Frame::Alternatives(frame));
+        Step::Call(Call::Sequence(alt))
     }
 
-    /// Parse optional (?)
-    fn parse_optional(
-        &self,
+    /// Whether any alternative after the one being tried could match at the
+    /// frame's start position
+    fn later_alternative_can_start(&self, frame: &AlternativesFrame) -> bool {
+        (frame.next + 1..frame.alts.alts.len()).any(|next| {
+            let index = frame.order.map_or(next, |order| order[next]);
+            self.compiled
+                .can_start_sequence(&frame.alts.alts[index], frame.first_char)
+        })
+    }
+
+    /// `" (did you mean 'rule'?)"` if `name` looks like a typo of a defined rule
+    fn did_you_mean(&self, name: &str) -> String {
+        self.compiled
+            .grammar()
+            .closest_rule(name)
+            .map(|rule| format!(" (did you mean '{}'?)", rule))
+            .unwrap_or_default()
+    }
+
+    /// Commit to the longest match, recording which alternative won when profiling
+    fn finish_alternatives<'g>(
+        &'g self,
+        frame: AlternativesFrame<'g>,
         stream: &mut InputStream,
-        base: &BaseFactor,
         ctx: &mut ParseContext,
-    ) -> Result<ParseResult, ParseError> {
-        let start_pos = stream.position();
+    ) -> Step<'g> {
+        let Some((mut result, end_pos, index)) = frame.best else {
+            return Step::Return(Err(ParseError::NoAlternativeMatched {
+                position: frame.start,
+                rule: ctx.rule_name().to_string(),
+                attempts: frame.attempts,
+            }));
+        };
 
-        // Try to match once
-        match self.parse_base_factor(stream, base, ctx) {
-            Ok(result) => Ok(result),
-            Err(_) => {
-                // Failed - that's OK for optional
-                stream.set_position(start_pos);
-                Ok(ParseResult::new(None, 0))
-            }
+        stream.set_position(end_pos); // Commit to longest match
+        result.ambiguous |= frame.tied_end == Some(end_pos);
+
+        if let (Some(rule), Some(wins)) = (frame.rule, ctx.alternative_wins.as_mut()) {
+            let counts = wins
+                .entry(rule.name.clone())
+                .or_insert_with(|| vec![0; rule.alternatives.alts.len()]);
+            counts[index] += 1;
         }
+
+        Step::Return(Ok(result))
     }
 
-    /// Parse zero or more with separator (**)
-    fn parse_separated_zero_or_more(
-        &self,
+    /// Parse the next factor of a sequence, or return the finished sequence
+    fn next_factor<'g>(
+        &'g self,
+        frame: SequenceFrame<'g>,
+        ctx: &mut ParseContext,
+        frames: &mut Vec<Frame<'g>>,
+    ) -> Step<'g> {
+        if let Some(factor) = frame.seq.factors.get(frame.next) {
+            frames.push(Frame::Sequence(frame));
+            return Step::Call(Call::Factor(factor));
+        }
+
+        let SequenceFrame {
+            mut children,
+            ambiguous,
+            consumed,
+            ..
+        } = frame;
+
+        // Return sequence as children nodes
+        let node = match children.len() {
+            0 => None, // All suppressed
+            1 => children.pop(),
+            // Multiple children - wrap in a container element
+            _ => Some(ctx.tree.group(SEQUENCE, &children)),
+        };
+
+        Step::Return(Ok(
+            ParseResult::new(node, consumed).with_ambiguity(ambiguous)
+        ))
+    }
+
+    /// Handle the result of one step of a repetition
+    fn repeat_step<'g>(
+        &'g self,
+        mut frame: RepeatFrame<'g>,
+        result: Result<ParseResult, ParseError>,
         stream: &mut InputStream,
-        base: &BaseFactor,
-        separator: &Sequence,
         ctx: &mut ParseContext,
-    ) -> Result<ParseResult, ParseError> {
-        let _start_pos = stream.position();
-        let mut children = Vec::new();
-        let mut total_consumed = 0;
-
-        // Try to match first element
-        let first_pos = stream.position();
-        match self.parse_base_factor(stream, base, ctx) {
-            Ok(result) => {
-                if let Some(node) = result.node {
-                    children.push(node);
+        frames: &mut Vec<Frame<'g>>,
+    ) -> Step<'g> {
+        match (frame.phase, result) {
+            (RepeatPhase::First, Ok(first)) => {
+                frame.ambiguous |= first.ambiguous;
+                frame.consumed += first.consumed;
+                if let Some(node) = first.node {
+                    frame.children.push(node);
                 }
-                total_consumed += result.consumed;
 
-                // Epsilon-match check
-                if result.consumed == 0 {
-                    return Ok(ParseResult::new(
-                        if children.is_empty() {
-                            None
-                        } else {
-                            Some(children.into_iter().next().unwrap())
-                        },
-                        total_consumed,
+                // Epsilon-match check: if first match consumed nothing, don't loop
+                if first.consumed == 0 {
+                    let node = frame.children.pop();
+                    return Step::Return(Ok(
+                        ParseResult::new(node, frame.consumed).with_ambiguity(frame.ambiguous)
                     ));
                 }
+                self.continue_repeat(frame, stream, ctx, frames)
             }
-            Err(_) => {
-                // No elements - that's OK for zero-or-more
-                stream.set_position(first_pos);
-                return Ok(ParseResult::new(None, 0));
+            (RepeatPhase::First, Err(e)) => {
+                if frame.kind == RepeatKind::SeparatedStar {
+                    // No elements - that's OK for zero-or-more
+                    stream.set_position(frame.loop_start);
+                    Step::Return(Ok(ParseResult::new(None, 0)))
+                } else {
+                    // Must match at least once
+                    Step::Return(Err(e))
+                }
             }
-        }
-
-        // Try to match more: (separator element)*
-        loop {
-            let loop_start = stream.position();
-
-            // Try to match separator
-            match self.parse_sequence(stream, separator, ctx) {
-                Ok(sep_result) => {
-                    // Collect separator node (may be attribute)
-                    if let Some(node) = sep_result.node {
-                        children.push(node);
-                    }
+            (RepeatPhase::Element, Ok(element)) => {
+                frame.ambiguous |= element.ambiguous;
+                if let Some(node) = element.node {
+                    frame.children.push(node);
+                }
 
-                    // Separator matched, now try element
-                    match self.parse_base_factor(stream, base, ctx) {
-                        Ok(elem_result) => {
-                            // Both matched - keep going
-                            if let Some(node) = elem_result.node {
-                                children.push(node);
-                            }
-                            total_consumed += sep_result.consumed + elem_result.consumed;
-
-                            // Epsilon-match check
-                            if elem_result.consumed == 0 {
-                                break;
-                            }
-                        }
-                        Err(_) => {
-                            // Element failed after separator - backtrack separator too
-                            stream.set_position(loop_start);
-                            break;
-                        }
-                    }
+                // Epsilon-match detection: matched but consumed nothing, we'd loop forever
+                if element.consumed == 0 {
+                    return self.finish_repeat(frame, ctx);
                 }
-                Err(_) => {
-                    // Separator failed - we're done
-                    stream.set_position(loop_start);
-                    break;
+                frame.consumed += element.consumed;
+                self.continue_repeat(frame, stream, ctx, frames)
+            }
+            (RepeatPhase::Separator, Ok(separator)) => {
+                // Separator matched, now try element
+                frame.separator_result = Some(separator);
+                frame.phase = RepeatPhase::ElementAfterSeparator;
+                let base = frame.base;
+                frames.push(Frame::Repeat(frame));
+                Step::Call(Call::Base(base))
+            }
+            (RepeatPhase::ElementAfterSeparator, Ok(element)) => {
+                // Both matched - keep the separator (it may carry an attribute) and the element
+                let separator = frame
+                    .separator_result
+                    .take()
+                    .expect("separator result is set before its element");
+                frame.ambiguous |= separator.ambiguous || element.ambiguous;
+                frame.children.extend(separator.node);
+                frame.children.extend(element.node);
+                frame.consumed += separator.consumed + element.consumed;
+
+                // Epsilon-match check
+                if element.consumed == 0 {
+                    return self.finish_repeat(frame, ctx);
                 }
+                self.continue_repeat(frame, stream, ctx, frames)
+            }
+            (_, Err(_)) => {
+                // This iteration failed (separator included) - backtrack it and stop
+                stream.set_position(frame.loop_start);
+                self.finish_repeat(frame, ctx)
+            }
+        }
+    }
+
+    /// Start the next iteration of a repetition
+    fn continue_repeat<'g>(
+        &'g self,
+        mut frame: RepeatFrame<'g>,
+        stream: &mut InputStream,
+        ctx: &mut ParseContext,
+        frames: &mut Vec<Frame<'g>>,
+    ) -> Step<'g> {
+        match frame.separator {
+            Some(separator) => {
+                frame.loop_start = stream.position();
+                frame.phase = RepeatPhase::Separator;
+                frames.push(Frame::Repeat(frame));
+                Step::Call(Call::Sequence(separator))
             }
+            None => self.next_repeat_element(frame, stream, ctx, frames),
         }
-
-        // Return collected nodes (merged if they're all text)
-        Ok(ParseResult::new(self.merge_nodes(children), total_consumed))
     }
 
-    /// Parse one or more with separator (++)
-    fn parse_separated_one_or_more(
-        &self,
+    fn next_repeat_element<'g>(
+        &'g self,
+        mut frame: RepeatFrame<'g>,
         stream: &mut InputStream,
-        base: &BaseFactor,
-        separator: &Sequence,
         ctx: &mut ParseContext,
-    ) -> Result<ParseResult, ParseError> {
-        let _start_pos = stream.position();
-
-        // Must match at least one element
-        let first_result = self.parse_base_factor(stream, base, ctx)?;
-        let mut children = Vec::new();
-        let mut total_consumed = first_result.consumed;
-
-        if let Some(node) = first_result.node {
-            children.push(node);
+        frames: &mut Vec<Frame<'g>>,
+    ) -> Step<'g> {
+        // Check instruction limit during repetition (prevent DoS via * loops)
+        if frame.kind == RepeatKind::Star {
+            if let Err(e) = ctx.check_instruction_limit() {
+                return Step::Return(Err(e));
+            }
         }
 
-        // Epsilon-match check
-        if first_result.consumed == 0 {
-            return Ok(ParseResult::new(
-                if children.is_empty() {
-                    None
-                } else {
-                    Some(children.into_iter().next().unwrap())
-                },
-                total_consumed,
-            ));
-        }
+        frame.loop_start = stream.position();
+        frame.phase = RepeatPhase::Element;
+        let base = frame.base;
+        frames.push(Frame::Repeat(frame));
+        Step::Call(Call::Base(base))
+    }
 
-        // Try to match more: (separator element)*
-        loop {
-            let loop_start = stream.position();
-
-            // Try to match separator
-            match self.parse_sequence(stream, separator, ctx) {
-                Ok(sep_result) => {
-                    // Collect separator node (may be attribute)
-                    if let Some(node) = sep_result.node {
-                        children.push(node);
-                    }
+    /// Return collected repetition nodes (merged if they're all text)
+    fn finish_repeat<'g>(&'g self, frame: RepeatFrame<'g>, ctx: &mut ParseContext) -> Step<'g> {
+        let node = self.merge_nodes(&mut ctx.tree, frame.children);
+        Step::Return(Ok(
+            ParseResult::new(node, frame.consumed).with_ambiguity(frame.ambiguous)
+        ))
+    }
 
-                    // Separator matched, now try element
-                    match self.parse_base_factor(stream, base, ctx) {
-                        Ok(elem_result) => {
-                            // Both matched
-                            if let Some(node) = elem_result.node {
-                                children.push(node);
-                            }
-                            total_consumed += sep_result.consumed + elem_result.consumed;
-
-                            // Epsilon-match check
-                            if elem_result.consumed == 0 {
-                                break;
-                            }
-                        }
-                        Err(_) => {
-                            // Element failed after separator - backtrack
-                            stream.set_position(loop_start);
-                            break;
-                        }
+    /// Apply a nonterminal's use-site mark (`-name`, `@name`, `^name`) to the rule's result
+    fn apply_factor_mark(
+        &self,
+        result: ParseResult,
+        name: &str,
+        mark: Mark,
+        rule: &Rule,
+        tree: &mut XmlTree,
+    ) -> ParseResult {
+        let node = result.node.and_then(|n| match mark {
+            // A hidden rule produced no wrapper, so there is nothing to unwrap
+            Mark::Hidden if rule.mark == Mark::Hidden => Some(n),
+            // For non-Element nodes (Text, Attribute), keep them
+            Mark::Hidden if !tree.get(n).is_element() => Some(n),
+            Mark::Hidden => {
+                // Factor-level hiding: unwrap element and pass through its
+                // attribute nodes, then its children
+                match tree.links(n) {
+                    [] => None,
+                    [only] => Some(*only),
+                    // Multiple items - wrap in _sequence for now
+                    links => {
+                        let links = links.to_vec();
+                        Some(tree.group(SEQUENCE, &links))
                     }
                 }
-                Err(_) => {
-                    // Separator failed - we're done
-                    stream.set_position(loop_start);
-                    break;
+            }
+            Mark::Attribute => {
+                // Convert to attribute
+                let value = tree.get(n).text_content();
+                Some(tree.attribute(name, &value))
+            }
+            Mark::Promoted => {
+                // Promote content: Override any rule-level mark and wrap in element
+                let node = tree.get(n);
+                if node.is_element() && node.name() == Some(rule.name.as_str()) {
+                    // Already wrapped in rule element, keep as-is
+                    Some(n)
+                } else {
+                    // Not wrapped or wrapped in different element - unwrap a
+                    // _sequence, then wrap in the rule element
+                    let children = if Self::is_sequence(tree, n) {
+                        tree.links(n).to_vec()
+                    } else {
+                        vec![n]
+                    };
+                    Some(tree.group(&rule.name, &children))
                 }
             }
-        }
+            Mark::None => {
+                // Keep as-is (already wrapped by rule-level mark)
+                Some(n)
+            }
+        });
 
-        // Return collected nodes (merged if they're all text)
-        Ok(ParseResult::new(self.merge_nodes(children), total_consumed))
+        ParseResult::new(node, result.consumed).with_ambiguity(result.ambiguous)
     }
 
-    /// Add ixml:state="ambiguous" attribute to root element for ambiguous grammars
-    fn add_ambiguity_marker(&self, node: XmlNode) -> XmlNode {
-        match node {
-            XmlNode::Element {
-                name,
-                mut attributes,
-                children,
-            } => {
-                // Add ixml:state attribute first (order matters for test comparison)
-                attributes.push(("ixml:state".to_string(), "ambiguous".to_string()));
-
-                // Add xmlns:ixml namespace declaration if not already present
-                if !attributes.iter().any(|(k, _)| k == "xmlns:ixml") {
-                    attributes.push((
-                        "xmlns:ixml".to_string(),
-                        "http://invisiblexml.org/NS".to_string(),
-                    ));
-                }
+    /// Serialize a result tree with the fixed attributes and layout, up to the byte limit
+    fn serialize(&self, node: &XmlNode) -> String {
+        node.write_xml(
+            &self.fixed_attributes,
+            &self.serialize_options,
+            self.output_limits.max_bytes,
+        )
+        .0
+    }
 
-                XmlNode::Element {
-                    name,
-                    attributes,
-                    children,
-                }
-            }
-            // If not an element (shouldn't happen for root), return as-is
-            other => other,
+    /// The `ixml:state` values of a document whose parse was `ambiguous` or not
+    fn document_states(&self, ambiguous: bool) -> Vec<DocumentState> {
+        let mut states = Vec::new();
+        if ambiguous {
+            states.push(DocumentState::Ambiguous);
         }
+        if self.compiled.grammar().is_version_mismatch() {
+            states.push(DocumentState::VersionMismatch);
+        }
+        states
+    }
+
+    /// Wrap an engine result in a [`ParseOutcome`] with the spec's `ixml:state` values
+    fn outcome(
+        &self,
+        result: Result<(XmlNode, bool), ParseError>,
+        stream: &InputStream,
+    ) -> ParseOutcome {
+        let outcome = match result {
+            Ok((node, ambiguous)) => ParseOutcome::success(node, self.document_states(ambiguous)),
+            Err(error) => {
+                let (line, column) = stream.line_col(error.position());
+                ParseOutcome::failure(error, line, column, self.document_states(false))
+            }
+        };
+        outcome
+            .with_limits(self.output_limits)
+            .with_serialize_options(self.serialize_options)
     }
 }
 
@@ -1078,8 +1937,8 @@ mod tests {
         let grammar = parse_ixml_grammar(grammar_text).expect("Grammar should parse");
         let parser = NativeParser::new(grammar);
 
-        assert_eq!(parser.rules.len(), 1);
-        assert!(parser.rules.contains_key("test"));
+        assert_eq!(parser.rule_count(), 1);
+        assert!(parser.compiled().symbol("test").is_some());
     }
 
     #[test]
@@ -1089,7 +1948,7 @@ mod tests {
 
         let result = parser.parse("anything");
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("no rules"));
+        assert!(result.unwrap_err().to_string().contains("no rules"));
     }
 
     #[test]
@@ -1120,7 +1979,7 @@ mod tests {
         // Should fail on "world"
         let result = parser.parse("world");
         assert!(result.is_err());
-        let err = result.unwrap_err();
+        let err = result.unwrap_err().to_string();
         println!("Error: {}", err);
         assert!(
             err.contains("No alternative matched")
@@ -1172,4 +2031,641 @@ mod tests {
         assert!(normalized.contains("<greeting>"));
         assert!(normalized.contains("hello"));
     }
+
+    #[test]
+    fn test_longest_alternative_wins() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar_text = r#"s: ("a"; "ab"; "a", "b"), "c"?."#;
+        let grammar = parse_ixml_grammar(grammar_text).expect("Grammar should parse");
+        let parser = NativeParser::new(grammar);
+
+        assert_eq!(parser.parse("a").unwrap(), "<s>a</s>");
+        // "ab" and "a", "b" tie on the longest match, so the parse is ambiguous,
+        // whether or not the match reaches end of input
+        for input in ["ab", "abc"] {
+            assert_eq!(
+                parser.parse(input).unwrap(),
+                format!(
+                    "<s ixml:state='ambiguous' xmlns:ixml='http://invisiblexml.org/NS'>{}</s>",
+                    input
+                )
+            );
+        }
+        assert!(parser.parse("abd").is_err());
+    }
+
+    #[test]
+    fn test_parse_to_tree() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar_text = r#"pair: @key, "=", value. key: ["a"-"z"]+. value: ["0"-"9"]+."#;
+        let grammar = parse_ixml_grammar(grammar_text).expect("Grammar should parse");
+        let parser = NativeParser::new(grammar);
+
+        let tree = parser.parse_to_tree("x=42").unwrap();
+        match &tree {
+            XmlNode::Element {
+                name,
+                attributes,
+                children,
+            } => {
+                assert_eq!(name, "pair");
+                assert_eq!(attributes, &vec![("key".to_string(), "x".to_string())]);
+                assert_eq!(children.len(), 2);
+                assert_eq!(children[1].text_content(), "42");
+            }
+            other => panic!("Expected element, got {:?}", other),
+        }
+        assert_eq!(tree.to_xml(), parser.parse("x=42").unwrap());
+
+        let err = parser.parse_to_tree("x=").unwrap_err();
+        assert!(err
+            .format_with_context("x=")
+            .starts_with("Parse error at line 1"));
+        let err = parser.parse_to_tree("x=4!").unwrap_err();
+        assert_eq!(err.position(), 3);
+    }
+
+    #[test]
+    fn test_parse_reader() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar_text = r#"lines: line*. line: ["a"-"z"]*, -#a."#;
+        let grammar = parse_ixml_grammar(grammar_text).expect("Grammar should parse");
+        let input = "abc\n".repeat(500);
+
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+            let streamed = parser.parse_reader(std::io::Cursor::new(input.clone()));
+            assert_eq!(streamed, parser.parse(&input));
+        }
+
+        let parser = NativeParser::new(grammar);
+        let err = parser
+            .parse_reader(std::io::Cursor::new("abc\nab1\n"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Parse error at line 2"));
+    }
+
+    #[test]
+    fn test_parse_stream_with_progress() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar = parse_ixml_grammar(r#"lines: line*. line: ["a"-"z"]*, -#a."#).unwrap();
+        let input = "abc\n".repeat(500);
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+
+            let mut reported = Vec::new();
+            let stream = InputStream::from_reader(std::io::Cursor::new(input.clone()));
+            let xml = parser.parse_stream_with_progress(stream, 1000, |position| {
+                reported.push(position);
+                true
+            });
+            assert_eq!(xml, parser.parse(&input));
+            assert_eq!(reported, [1000, 2000], "{:?}", strategy);
+
+            // Stopping at the first report fails at the position reported
+            let stream = InputStream::from_reader(std::io::Cursor::new(input.clone()));
+            let err = parser
+                .parse_stream_with_progress(stream, 1000, |_| false)
+                .unwrap_err();
+            assert!(err.is_cancelled());
+            assert_eq!(err.position(), 1000);
+            assert!(err
+                .to_string()
+                .starts_with("Parse cancelled at line 251, column 1"));
+
+            let err = parser
+                .parse_with_progress(&input, 1000, |_| false)
+                .unwrap_err();
+            assert!(err.is_cancelled(), "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn test_runtime_ambiguity_detection() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        // Both alternatives of `a` match "x" at the same span
+        let grammar_text = r#"s: a, ".". a: "x"; ["x"]."#;
+        let grammar = parse_ixml_grammar(grammar_text).expect("Grammar should parse");
+        let parser = NativeParser::new(grammar);
+        let xml = parser.parse("x.").unwrap();
+        assert!(
+            xml.starts_with("<s ixml:state='ambiguous' xmlns:ixml='http://invisiblexml.org/NS'>")
+        );
+
+        let grammar_text = r#"s: a, ".". a: "x"; "y"."#;
+        let grammar = parse_ixml_grammar(grammar_text).expect("Grammar should parse");
+        let parser = NativeParser::new(grammar);
+        assert_eq!(parser.parse("x.").unwrap(), "<s><a>x</a>.</s>");
+
+        // Tied alternatives that reach end of input
+        for grammar_text in [r#"s: "a"; "a"."#, r#"s: x. x: "a"; "a"."#] {
+            let grammar = parse_ixml_grammar(grammar_text).expect("Grammar should parse");
+            let xml = NativeParser::new(grammar).parse("a").unwrap();
+            assert!(
+                xml.contains("ixml:state='ambiguous'"),
+                "{}: {}",
+                grammar_text,
+                xml
+            );
+        }
+
+        // The analysis flags this grammar (both alternatives match nothing),
+        // but only the empty input actually parses two ways
+        let grammar = parse_ixml_grammar(r#"s: a; b. a: "x"*. b: "y"*."#).unwrap();
+        let parser = NativeParser::new(grammar);
+        assert!(parser.compiled().analysis().is_potentially_ambiguous);
+        assert_eq!(parser.parse("xx").unwrap(), "<s><a>xx</a></s>");
+        assert!(parser.parse_outcome("").is_ambiguous());
+    }
+
+    #[test]
+    fn test_eof_skips_alternatives_that_cannot_start() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let tried = |grammar: &str, input: &str| {
+            let parser = NativeParser::new(parse_ixml_grammar(grammar).unwrap());
+            let (outcome, events) = parser.parse_traced(input);
+            assert!(outcome.is_success(), "{}: {:?}", grammar, outcome);
+            events
+                .iter()
+                .filter(|e| e.to_string().trim_start().starts_with("alternative"))
+                .count()
+        };
+
+        // Once "ab" reaches end of input, no other alternative starts with "a"
+        assert_eq!(tried(r#"s: "a", "b"; "c"; "d"; "e"."#, "ab"), 1);
+        // A later alternative that could start here is still tried
+        assert_eq!(tried(r#"s: "a", "b"; "c"; "a"; "e"."#, "ab"), 3);
+        // ...and so is one that matches nothing, at end of input
+        assert_eq!(tried(r#"s: "c"?; "d"; "e"*."#, ""), 3);
+        assert_eq!(tried(r#"s: "c"?; "d"; "e"."#, ""), 1);
+    }
+
+    #[test]
+    fn test_earley_strategy() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar_text = r#"s: a, a. a: "x"; "x", "x"."#;
+        let grammar = parse_ixml_grammar(grammar_text).expect("Grammar should parse");
+        let parser = NativeParser::with_strategy(grammar, ParseStrategy::Earley);
+        assert_eq!(parser.strategy(), ParseStrategy::Earley);
+
+        let xml = parser.parse("xxx").expect("Parse should succeed");
+        assert!(xml.contains("ixml:state='ambiguous'"));
+        assert!(parser.parse("x").is_err());
+
+        // A grammar the Earley engine can't lower fails rather than parsing
+        // with recursive descent
+        let grammar_text = r#"s: (!end, ["a"-"z"])*, end. end: "end"."#;
+        let options = crate::grammar_parser::GrammarOptions {
+            allow_extensions: true,
+            ..Default::default()
+        };
+        let grammar =
+            crate::grammar_parser::parse_ixml_grammar_with_options(grammar_text, &options)
+                .expect("Grammar should parse");
+        let parser = NativeParser::with_strategy(grammar, ParseStrategy::Earley);
+        assert_eq!(parser.strategy(), ParseStrategy::Earley);
+        let error = parser.parse("abend").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            parser
+                .parse_with_start("abend", "s")
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(parser.parse_outcome("abend").into_result().is_err());
+        assert!(parser.parse_to_xml_tree("abend").is_err());
+    }
+
+    #[test]
+    fn test_insertions() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let cases = [
+            // Hex insertion between matched characters
+            (r#"s: "a", +#a, "b"."#, "ab", "<s>a\nb</s>"),
+            // Inside an attribute, line breaks must survive XML attribute normalization
+            (
+                r#"s: @x, "b". @x: -"a", +#a, +"<"."#,
+                "ab",
+                "<s x='&#xA;&lt;'>b</s>",
+            ),
+            (r#"s: @x, "b". x: "a", +"!"."#, "ab", "<s x='a!'>b</s>"),
+            // As, or as one choice of, a separator
+            (
+                r#"s: d++(-",", +";"). d: ["a"-"z"]."#,
+                "a,b",
+                "<s><d>a</d>;<d>b</d></s>",
+            ),
+            (
+                r#"s: d**(" "; +" "). -d: ["a"-"z"]."#,
+                "a bc",
+                "<s>a b c</s>",
+            ),
+            // A rule matching nothing but an insertion
+            (r#"s: "a", x. x: +"z"."#, "a", "<s>a<x>z</x></s>"),
+        ];
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            for (grammar, input, expected) in cases {
+                let parser =
+                    NativeParser::with_strategy(parse_ixml_grammar(grammar).unwrap(), strategy);
+                assert_eq!(parser.parse(input).as_deref(), Ok(expected), "{grammar}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_version_mismatch_state() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar = parse_ixml_grammar(r#"ixml version "1.3". P: ["B"-"D"]."#).unwrap();
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+            let tree = parser.parse_to_tree("B").unwrap();
+            assert_eq!(tree.ixml_state(), Some("version-mismatch"));
+            assert!(parser.parse("b").is_err());
+        }
+
+        let grammar = parse_ixml_grammar(r#"ixml version "1.0". P: ["B"-"D"]."#).unwrap();
+        assert_eq!(NativeParser::new(grammar).parse("B").unwrap(), "<P>B</P>");
+    }
+
+    #[test]
+    fn test_parse_outcome_states() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar =
+            parse_ixml_grammar(r#"ixml version "1.3". s: a, ".". a: "x"; ["x"]."#).unwrap();
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+
+            let outcome = parser.parse_outcome("x.");
+            assert!(outcome.is_success());
+            assert_eq!(
+                outcome.states(),
+                [DocumentState::Ambiguous, DocumentState::VersionMismatch]
+            );
+            assert_eq!(
+                outcome.document().ixml_state(),
+                Some("ambiguous version-mismatch")
+            );
+
+            let outcome = parser.parse_outcome("x.y");
+            assert!(!outcome.is_success());
+            assert_eq!(
+                outcome.states(),
+                [DocumentState::Failed, DocumentState::VersionMismatch]
+            );
+            let failed = outcome.document();
+            assert_eq!(failed.ixml_state(), Some("failed version-mismatch"));
+            let XmlNode::Element { name, children, .. } = failed else {
+                panic!("expected an element, got {:?}", failed);
+            };
+            assert_eq!(name, "failed");
+            let position: Vec<_> = children[..2].iter().map(XmlNode::text_content).collect();
+            assert_eq!(position, ["1", "3"]);
+            assert!(outcome.into_result().is_err());
+        }
+
+        // The string API reports the same states as the outcome
+        let parser = NativeParser::new(parse_ixml_grammar(r#"s: "x"."#).unwrap());
+        let outcome = parser.parse_outcome("x");
+        assert!(outcome.states().is_empty());
+        assert_eq!(
+            outcome.to_xml_with(parser.fixed_attributes()),
+            parser.parse("x").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_node_limit_during_parse() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar =
+            parse_ixml_grammar(r#"list: item+. item: word, -" ". word: ["a"-"z"]+."#).unwrap();
+        let parser =
+            NativeParser::new(grammar).with_output_limits(OutputLimits::new().with_max_nodes(5));
+        let input = "ab ".repeat(10_000);
+
+        // The parser's tree stops growing at the limit instead of holding every item
+        let mut stream = InputStream::new(&input);
+        let mut ctx = parser.context().with_input(&input);
+        parser
+            .parse_document(&mut stream, &mut ctx, 0, None)
+            .unwrap();
+        assert!(ctx.tree.is_truncated());
+        assert!(ctx.tree.len() < 20, "tree holds {} nodes", ctx.tree.len());
+
+        let outcome = parser.parse_outcome(&input);
+        assert_eq!(outcome.states(), [DocumentState::Truncated]);
+        assert_eq!(outcome.warnings(), ["Output truncated to 5 nodes"]);
+
+        let tree = parser.parse_to_xml_tree(&input).unwrap();
+        assert_eq!(tree.root().unwrap().to_node(), *outcome.document());
+    }
+
+    #[test]
+    fn test_hex_terminals() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        // Newlines, NBSP and an en dash matched by code point, outside any character class
+        let grammar = parse_ixml_grammar(
+            r#"lines: line++#a, #a?. line: word++-#a0, (-#2013, +#2d, word)?. word: ["a"-"z"]+."#,
+        )
+        .unwrap();
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+            assert_eq!(
+                parser.parse("ab\u{a0}c\u{2013}d\ne\n").unwrap(),
+                "<lines><line><word>ab</word><word>c</word>-<word>d</word></line>\n\
+                 <line><word>e</word></line>\n</lines>"
+            );
+            assert!(parser.parse("ab c").is_err());
+        }
+    }
+
+    #[test]
+    fn test_charclass_exclusions() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        // Exclusions subtract from the whole class, wherever they appear in it
+        let grammar = parse_ixml_grammar(
+            r#"s: c+, v. c: [~"aeiou"; "a"-"z"; #30-#39; ~#30]. v: ~[~"e"; "a"-"z"]."#,
+        )
+        .unwrap();
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+            assert_eq!(
+                parser.parse("b9e").unwrap(),
+                "<s><c>b</c><c>9</c><v>e</v></s>"
+            );
+            assert!(parser.parse("ae").is_err());
+            assert!(parser.parse("b0e").is_err());
+            assert!(parser.parse("bx").is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "minimal-unicode"))]
+    fn test_unicode_categories_in_both_strategies() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        // Both engines match categories through charclass::unicode_category_to_rangeset
+        let grammar =
+            parse_ixml_grammar(r#"s: d+, m*, l, n. d: [Nd]. m: [Mn]. l: [Lo]. n: [Nl]."#).unwrap();
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+            assert_eq!(
+                parser.parse("\u{663}7\u{301}\u{3042}\u{216b}").unwrap(),
+                "<s><d>\u{663}</d><d>7</d><m>\u{301}</m><l>\u{3042}</l><n>\u{216b}</n></s>"
+            );
+            // Letters are not digits, and Latin letters are not Lo
+            assert!(parser.parse("a\u{3042}\u{216b}").is_err());
+            assert!(parser.parse("7x\u{216b}").is_err());
+        }
+    }
+
+    #[test]
+    fn test_hidden_reference_to_hidden_rule() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        // Hiding a reference to a rule that is itself hidden must not unwrap its content
+        let grammar = parse_ixml_grammar(r#"s: -l. -l: k, -".". k: "a"."#).unwrap();
+        let parser = NativeParser::new(grammar);
+        assert_eq!(parser.parse("a.").unwrap(), "<s><k>a</k></s>");
+    }
+
+    #[test]
+    fn test_parse_with_start() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar_text =
+            r#"line: time, " ", msg. time: d, d, ":", d, d. msg: ~[]*. -d: ["0"-"9"]."#;
+        let grammar = parse_ixml_grammar(grammar_text).expect("Grammar should parse");
+
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+            assert_eq!(
+                parser.parse_with_start("12:30", "time").unwrap(),
+                "<time>12:30</time>"
+            );
+            assert!(parser.parse_with_start("12:30 up", "time").is_err());
+            // The default start rule is unchanged
+            assert!(parser.parse("12:30 up").is_ok());
+        }
+    }
+
+    #[test]
+    fn test_negative_lookahead() {
+        use crate::grammar_parser::{parse_ixml_grammar_with_options, GrammarOptions};
+
+        // A block comment body: any characters not starting the terminator
+        let grammar_text = r#"comment: -"/*", body, -"*/". -body: (!"*/", ~[])*."#;
+        let options = GrammarOptions {
+            allow_extensions: true,
+            ..GrammarOptions::default()
+        };
+        let grammar =
+            parse_ixml_grammar_with_options(grammar_text, &options).expect("Grammar should parse");
+
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+            let xml = parser.parse("/* a * b */").expect("Parse should succeed");
+            assert_eq!(xml, "<comment> a * b </comment>");
+            assert!(parser.parse("/* a */ b */").is_err());
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        // Each level of nesting used to cost several native stack frames
+        let grammar_text = r#"expr: -"(", expr, -")"; "x"."#;
+        let grammar = parse_ixml_grammar(grammar_text).expect("Grammar should parse");
+        let depth = 2000;
+
+        for strategy in [ParseStrategy::RecursiveDescent, ParseStrategy::Earley] {
+            let parser = NativeParser::with_strategy(grammar.clone(), strategy);
+            let input = format!("{}x{}", "(".repeat(depth), ")".repeat(depth));
+
+            // Run on a small stack to show depth no longer depends on it
+            let xml = std::thread::Builder::new()
+                .stack_size(256 * 1024)
+                .spawn(move || parser.parse(&input))
+                .unwrap()
+                .join()
+                .unwrap()
+                .expect("Parse should succeed");
+            assert!(xml.starts_with("<expr><expr>"));
+            assert_eq!(xml.matches("<expr>").count(), depth + 1);
+        }
+    }
+
+    #[test]
+    fn test_separator_backtracked_with_element() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        // The trailing "," is not followed by an "a", so the final "," belongs to s
+        let grammar = parse_ixml_grammar(r#"s: "a"++",", ",". "#).expect("Grammar should parse");
+        let parser = NativeParser::new(grammar);
+        assert_eq!(parser.parse("a,a,").unwrap(), "<s>a,a,</s>");
+    }
+
+    #[test]
+    fn test_serialize_options() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar = parse_ixml_grammar(r#"list: item++-",". item: ["a"-"z"]+."#).unwrap();
+        let parser = NativeParser::new(grammar)
+            .with_serialize_options(SerializeOptions::pretty().with_declaration(true));
+        let expected = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<list>\n   \
+                        <item>ab</item>\n   <item>c</item>\n</list>";
+        assert_eq!(parser.parse("ab,c").unwrap(), expected);
+        assert_eq!(
+            parser
+                .parse_outcome("ab,c")
+                .to_xml_with(parser.fixed_attributes()),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_undefined_rule_suggests_closest_name() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar = parse_ixml_grammar(r#"word: lettter+. letter: ["a"-"z"]."#).unwrap();
+        let parser = NativeParser::new(grammar);
+        let err = parser.parse("abc").unwrap_err().to_string();
+        assert!(
+            err.contains(
+                "expected undefined rule lettter (did you mean 'letter'?) but found \"abc\""
+            ),
+            "{}",
+            err
+        );
+        assert_eq!(
+            parser
+                .parse_with_start("a", "leter")
+                .unwrap_err()
+                .to_string(),
+            "Unknown start rule 'leter' (did you mean 'letter'?)"
+        );
+        assert_eq!(
+            parser
+                .parse_with_start("a", "digit")
+                .unwrap_err()
+                .to_string(),
+            "Unknown start rule 'digit'"
+        );
+    }
+
+    #[test]
+    fn test_error_reports_farthest_failure() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar = parse_ixml_grammar(
+            r#"document: entry++-#a. entry: name, -" ", date. name: ["a"-"z"]+.
+               date: d, -"-", month. month: "Jan"; "Mar". -d: ["0"-"9"]."#,
+        )
+        .unwrap();
+        let parser = NativeParser::new(grammar);
+        let err = parser
+            .parse("bob 1-Mar\nann 3-Mxr")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.ends_with(
+                "\nFarthest failure at line 2, column 7: expected one of \"Jan\", \"Mar\" \
+                 but found \"Mxr\"\nRule stack: document > entry > date > month"
+            ),
+            "{}",
+            err
+        );
+        let failure = parser.parse_to_tree("bob 1-").unwrap_err();
+        let failure = failure.farthest_failure().unwrap();
+        assert_eq!(failure.position, 6);
+        assert_eq!(failure.expected, ["month"]);
+        assert!(failure.found.is_empty());
+
+        // A rule the next character can't start is expected as a whole, and a
+        // left-recursive rule stays on the stack while its seed grows
+        let grammar =
+            parse_ixml_grammar(r#"expr: expr, "+", term; term. term: ["0"-"9"]."#).unwrap();
+        let err = NativeParser::new(grammar)
+            .parse("1+2+x")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.ends_with(
+                "\nFarthest failure at line 1, column 5: expected term but found \"x\"\n\
+                 Rule stack: expr"
+            ),
+            "{}",
+            err
+        );
+
+        // What a negative lookahead rejects is not expected
+        use crate::grammar_parser::{parse_ixml_grammar_with_options, GrammarOptions};
+        let options = GrammarOptions {
+            allow_extensions: true,
+            ..GrammarOptions::default()
+        };
+        let grammar =
+            parse_ixml_grammar_with_options(r#"s: "a", (!"b", ["x"-"z"]; "q")."#, &options)
+                .unwrap();
+        let err = NativeParser::new(grammar).parse_to_tree("ac").unwrap_err();
+        assert_eq!(
+            err.farthest_failure().unwrap().expected,
+            [r#"["x"-"z"]"#, r#""q""#]
+        );
+    }
+
+    #[test]
+    fn test_parse_path() {
+        use crate::grammar_ast::parse_ixml_grammar;
+        use crate::input_file::Newlines;
+
+        let grammar = parse_ixml_grammar(r#"lines: line++-#a. line: ["a"-"z"]*."#).unwrap();
+        let parser = NativeParser::new(grammar);
+        let path = std::env::temp_dir().join(format!("rustixml-input-{}.txt", std::process::id()));
+        std::fs::write(&path, b"\xEF\xBB\xBFab\r\ncd").unwrap();
+
+        let normalize = InputOptions::new().with_newlines(Newlines::Normalize);
+        let xml = parser.parse_path(&path, &normalize);
+        let err = parser.parse_file(&path).unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            xml.unwrap(),
+            "<lines><line>ab</line><line>cd</line></lines>"
+        );
+        let prefix = format!("{}: Parse error at line 1, column 3", path.display());
+        assert!(err.starts_with(&prefix), "{}", err);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_parse_returns_structured_error() {
+        let parser = crate::fixtures::DATE.parser();
+        let err = parser.parse("2024-3-15").unwrap_err();
+        assert!(matches!(err, ParseError::Located { .. }));
+        assert_eq!(Some(err.line_col("2024-3-15")), err.location());
+        assert!(err.farthest_failure().is_some());
+        assert_eq!(err.format_with_context("ignored"), err.to_string());
+
+        let boxed: Box<dyn std::error::Error> = Box::new(err.clone());
+        assert_eq!(boxed.to_string(), err.to_string());
+        assert_eq!(
+            parser.parse_with_string_error("2024-3-15"),
+            Err(err.to_string())
+        );
+    }
 }
@@ -12,54 +12,359 @@
 //! - Ambiguity appears at decision points
 //! - Fewer rule lookups during parsing
 //!
+//! [`normalize_grammar_with`] takes each step only if its
+//! [`NormalizeOptions`] say so, e.g. inlining just the hidden rules, which
+//! leaves parse results unchanged. [`eliminate_left_recursion`] rewrites
+//! left-recursive rules for the recursive-descent engine.
+//!
 //! Reference: https://homepages.cwi.nl/~steven/Talks/2016/02-12-prague/data.html
 
-#[cfg(test)]
-use crate::ast::Repetition;
-use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Rule, Sequence};
+use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Repetition, Rule, Sequence};
 use std::collections::{HashMap, HashSet};
 
+/// Which steps [`normalize_grammar_with`] takes
+///
+/// The default is the specification's normalization: every rule that is not
+/// recursive is inlined and the rules left over are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NormalizeOptions {
+    /// Inline references to rules that are not recursive, whatever their
+    /// marks; the grammar then describes the same strings but its results
+    /// lose those rules' elements and attributes
+    pub inline_non_recursive: bool,
+    /// Inline references that are hidden, by their own mark or their rule's
+    /// (`-name`); results stay the same
+    pub inline_hidden: bool,
+    /// Inline references to rules marked `^`, for analysis; results lose
+    /// those elements
+    pub inline_promoted: bool,
+    /// Drop rules the start rule no longer reaches
+    pub remove_dead_rules: bool,
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        NormalizeOptions {
+            inline_non_recursive: true,
+            inline_hidden: true,
+            inline_promoted: false,
+            remove_dead_rules: true,
+        }
+    }
+}
+
+impl NormalizeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// No steps at all, to enable them one by one
+    pub fn none() -> Self {
+        NormalizeOptions {
+            inline_non_recursive: false,
+            inline_hidden: false,
+            inline_promoted: false,
+            remove_dead_rules: false,
+        }
+    }
+
+    pub fn with_inline_non_recursive(mut self, inline: bool) -> Self {
+        self.inline_non_recursive = inline;
+        self
+    }
+
+    pub fn with_inline_hidden(mut self, inline: bool) -> Self {
+        self.inline_hidden = inline;
+        self
+    }
+
+    pub fn with_inline_promoted(mut self, inline: bool) -> Self {
+        self.inline_promoted = inline;
+        self
+    }
+
+    pub fn with_remove_dead_rules(mut self, remove: bool) -> Self {
+        self.remove_dead_rules = remove;
+        self
+    }
+}
+
 /// Normalize an iXML grammar by inlining non-recursive rules
+///
+/// The same as [`normalize_grammar_with`] and the default
+/// [`NormalizeOptions`].
 pub fn normalize_grammar(grammar: &IxmlGrammar) -> IxmlGrammar {
-    // Step 1: Build a map of rule names to rules for quick lookup
+    normalize_grammar_with(grammar, &NormalizeOptions::default())
+}
+
+/// Normalize an iXML grammar, taking the steps `options` enable
+///
+/// An inlined reference becomes a bracketed group of the rule's alternatives,
+/// keeping the reference's repetition. Recursive rules are never inlined.
+///
+/// ```
+/// use rustixml::normalize::{normalize_grammar_with, NormalizeOptions};
+/// use rustixml::parse_ixml_grammar;
+///
+/// let grammar = parse_ixml_grammar(
+///     r#"list: item++-sep. item: -letter+. -sep: ",". -letter: ["a"-"z"]. unused: "u"."#,
+/// )
+/// .unwrap();
+/// let options = NormalizeOptions::none()
+///     .with_inline_hidden(true)
+///     .with_remove_dead_rules(true);
+/// assert_eq!(
+///     normalize_grammar_with(&grammar, &options).to_string(),
+///     "list: item++(\",\").\nitem: ([\"a\"-\"z\"])+.\n"
+/// );
+/// ```
+pub fn normalize_grammar_with(grammar: &IxmlGrammar, options: &NormalizeOptions) -> IxmlGrammar {
     let rule_map: HashMap<String, &Rule> =
         grammar.rules.iter().map(|r| (r.name.clone(), r)).collect();
+    let inliner = Inliner {
+        recursive: find_recursive_rules(grammar, &rule_map),
+        rule_map,
+        options,
+    };
 
-    // Step 2: Detect which rules are recursive
-    let recursive_rules = find_recursive_rules(grammar, &rule_map);
+    let rules = grammar
+        .rules
+        .iter()
+        .map(|rule| Rule {
+            alternatives: inliner.alternatives(&rule.alternatives),
+            ..rule.clone()
+        })
+        .collect();
+    let mut normalized = IxmlGrammar {
+        rules,
+        version: grammar.version.clone(),
+    };
 
-    println!(
-        "[normalize] Found {} recursive rules: {:?}",
-        recursive_rules.len(),
-        recursive_rules
-    );
+    if options.remove_dead_rules {
+        let (_, unreachable) = crate::grammar_analysis::find_unused_and_unreachable(&normalized);
+        normalized
+            .rules
+            .retain(|rule| !unreachable.contains(&rule.name));
+    }
+    normalized
+}
 
-    // Step 3: Inline non-recursive rules in all rules
-    let mut normalized_rules = Vec::new();
-    for rule in &grammar.rules {
-        let mut normalized_rule = rule.clone();
-        inline_in_alternatives(
-            &mut normalized_rule.alternatives,
-            &rule_map,
-            &recursive_rules,
-        );
-        normalized_rules.push(normalized_rule);
+/// Replaces references with the alternatives of the rules they refer to
+struct Inliner<'a> {
+    rule_map: HashMap<String, &'a Rule>,
+    recursive: HashSet<String>,
+    options: &'a NormalizeOptions,
+}
+
+impl Inliner<'_> {
+    /// The rule a reference should be replaced by, if any
+    fn target(&self, name: &str, mark: Mark) -> Option<&Rule> {
+        let rule = self.rule_map.get(name)?;
+        if self.recursive.contains(name) {
+            return None;
+        }
+        let mark = if mark == Mark::None { rule.mark } else { mark };
+        let inline = self.options.inline_non_recursive
+            || (self.options.inline_hidden && mark == Mark::Hidden)
+            || (self.options.inline_promoted && mark == Mark::Promoted);
+        inline.then_some(*rule)
     }
 
-    // Step 4: Keep only recursive rules and the start rule
-    // (The start rule is typically the first rule)
-    let start_rule_name = grammar.rules.first().map(|r| r.name.clone());
+    fn alternatives(&self, alts: &Alternatives) -> Alternatives {
+        Alternatives::new(alts.alts.iter().map(|seq| self.sequence(seq)).collect())
+    }
 
-    normalized_rules
-        .retain(|r| recursive_rules.contains(&r.name) || start_rule_name.as_ref() == Some(&r.name));
+    fn sequence(&self, seq: &Sequence) -> Sequence {
+        Sequence::new(seq.factors.iter().map(|f| self.factor(f)).collect())
+    }
 
-    println!(
-        "[normalize] Reduced from {} rules to {} rules",
-        grammar.rules.len(),
-        normalized_rules.len()
-    );
+    fn factor(&self, factor: &Factor) -> Factor {
+        let repetition = match &factor.repetition {
+            Repetition::SeparatedZeroOrMore(sep) => {
+                Repetition::SeparatedZeroOrMore(Box::new(self.sequence(sep)))
+            }
+            Repetition::SeparatedOneOrMore(sep) => {
+                Repetition::SeparatedOneOrMore(Box::new(self.sequence(sep)))
+            }
+            repetition => repetition.clone(),
+        };
+        Factor {
+            base: self.base(&factor.base),
+            repetition,
+            pragmas: factor.pragmas.clone(),
+        }
+    }
+
+    fn base(&self, base: &BaseFactor) -> BaseFactor {
+        match base {
+            BaseFactor::Nonterminal { name, mark } => match self.target(name, *mark) {
+                Some(rule) => BaseFactor::group(self.alternatives(&rule.alternatives)),
+                None => base.clone(),
+            },
+            BaseFactor::Group { alternatives } => {
+                BaseFactor::group(self.alternatives(alternatives))
+            }
+            BaseFactor::NegativeLookahead { base } => {
+                BaseFactor::negative_lookahead(self.base(base))
+            }
+            BaseFactor::Literal { .. } | BaseFactor::CharClass { .. } => base.clone(),
+        }
+    }
+}
+
+/// Rewrite left-recursive rules as iterations, for the recursive-descent engine
+///
+/// A rule whose alternatives start with the rule itself, such as
+/// `expr: expr, "+", term; term.`, becomes its other alternatives followed by
+/// any number of the recursive ones' tails: `expr: term, ("+", term)*.`
+/// Only rules whose recursive references are hidden (`-expr, "+", term`) are
+/// rewritten, so results are unchanged: an unhidden reference makes nested
+/// `expr` elements, which an iteration can't, so such rules are left alone.
+///
+/// Left recursion through other rules is exposed by substituting those rules
+/// in, which only leaves results unchanged when they are hidden; through rules
+/// that make elements it is left alone.
+///
+/// ```
+/// use rustixml::normalize::eliminate_left_recursion;
+/// use rustixml::parse_ixml_grammar;
+///
+/// let grammar = parse_ixml_grammar(r#"expr: -expr, "+", term; term. term: ["0"-"9"]."#).unwrap();
+/// assert_eq!(
+///     eliminate_left_recursion(&grammar).to_string(),
+///     "expr: term, (\"+\", term)*.\nterm: [\"0\"-\"9\"].\n"
+/// );
+/// ```
+pub fn eliminate_left_recursion(grammar: &IxmlGrammar) -> IxmlGrammar {
+    let mut rules = grammar.rules.clone();
+    for i in 0..rules.len() {
+        // Each round replaces the leading references that hide left recursion
+        for _ in 0..rules.len() {
+            let alts = substitute_leading_hidden(&rules, i);
+            if alts == rules[i].alternatives {
+                break;
+            }
+            rules[i].alternatives = alts;
+        }
+        eliminate_direct_left_recursion(&mut rules[i]);
+    }
+    IxmlGrammar {
+        rules,
+        version: grammar.version.clone(),
+    }
+}
+
+/// The rule a reference refers to (the last definition, as when parsing)
+fn rule_named<'a>(rules: &'a [Rule], name: &str) -> Option<&'a Rule> {
+    rules.iter().rev().find(|rule| rule.name == name)
+}
+
+/// The rule referred to by a sequence's first factor, with the reference's mark
+fn leading_reference(seq: &Sequence) -> Option<(&str, Mark)> {
+    match seq.factors.first() {
+        Some(Factor {
+            base: BaseFactor::Nonterminal { name, mark },
+            repetition: Repetition::None,
+            ..
+        }) => Some((name, *mark)),
+        _ => None,
+    }
+}
+
+/// Whether a match of `from` can start with a match of `to`
+fn leads_to(rules: &[Rule], from: &str, to: &str) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![from];
+    while let Some(name) = stack.pop() {
+        if !visited.insert(name) {
+            continue;
+        }
+        let Some(rule) = rule_named(rules, name) else {
+            continue;
+        };
+        for (next, _) in rule.alternatives.alts.iter().filter_map(leading_reference) {
+            if next == to {
+                return true;
+            }
+            stack.push(next);
+        }
+    }
+    false
+}
+
+/// Rule `i`'s alternatives with each leading reference to a hidden rule that
+/// leads back to rule `i` replaced by that rule's alternatives
+fn substitute_leading_hidden(rules: &[Rule], i: usize) -> Alternatives {
+    let rule = &rules[i];
+    let mut alts = Vec::new();
+    for seq in &rule.alternatives.alts {
+        let target = leading_reference(seq).and_then(|(name, mark)| {
+            let target = rule_named(rules, name)?;
+            let mark = if mark == Mark::None {
+                target.mark
+            } else {
+                mark
+            };
+            (name != rule.name && mark == Mark::Hidden && leads_to(rules, name, &rule.name))
+                .then_some(target)
+        });
+        match target {
+            Some(target) => {
+                for head in &target.alternatives.alts {
+                    let mut factors = head.factors.clone();
+                    factors.extend(seq.factors[1..].iter().cloned());
+                    alts.push(Sequence::new(factors));
+                }
+            }
+            None => alts.push(seq.clone()),
+        }
+    }
+    Alternatives::new(alts)
+}
 
-    IxmlGrammar::new(normalized_rules)
+/// `a: -a, x; -a, y; b; c.` becomes `a: (b; c), (x; y)*.`
+fn eliminate_direct_left_recursion(rule: &mut Rule) {
+    let (recursive, others): (Vec<&Sequence>, Vec<&Sequence>) =
+        rule.alternatives.alts.iter().partition(
+            |seq| matches!(leading_reference(seq), Some((name, _)) if name == rule.name),
+        );
+    // Without other alternatives the rule matches nothing either way
+    if recursive.is_empty() || others.is_empty() {
+        return;
+    }
+    // Flattening the nested elements of an unhidden reference would change
+    // results (`a: a.` has no tail to nest, so it does not count)
+    let nests = |seq: &&Sequence| match leading_reference(seq) {
+        Some((_, Mark::None)) => rule.mark != Mark::Hidden,
+        Some((_, mark)) => mark != Mark::Hidden,
+        None => false,
+    };
+    if recursive
+        .iter()
+        .any(|seq| seq.factors.len() > 1 && nests(seq))
+    {
+        return;
+    }
+    let others: Vec<Sequence> = others.into_iter().cloned().collect();
+    // `a: a.` adds nothing but more ways to match
+    let tails: Vec<Sequence> = recursive
+        .iter()
+        .filter(|seq| seq.factors.len() > 1)
+        .map(|seq| Sequence::new(seq.factors[1..].to_vec()))
+        .collect();
+    if tails.is_empty() {
+        rule.alternatives = Alternatives::new(others);
+        return;
+    }
+    let mut factors = match others.as_slice() {
+        [only] => only.factors.clone(),
+        _ => vec![Factor::simple(BaseFactor::group(Alternatives::new(others)))],
+    };
+    factors.push(Factor::new(
+        BaseFactor::group(Alternatives::new(tails)),
+        Repetition::ZeroOrMore,
+    ));
+    rule.alternatives = Alternatives::single(Sequence::new(factors));
 }
 
 /// Find all rules that are directly or indirectly recursive
@@ -162,114 +467,6 @@ fn check_factor_for_recursion(
     }
 }
 
-/// Inline non-recursive rules in alternatives
-fn inline_in_alternatives(
-    alternatives: &mut Alternatives,
-    rule_map: &HashMap<String, &Rule>,
-    recursive_rules: &HashSet<String>,
-) {
-    for seq in &mut alternatives.alts {
-        inline_in_sequence(seq, rule_map, recursive_rules);
-    }
-}
-
-/// Inline non-recursive rules in a sequence
-fn inline_in_sequence(
-    seq: &mut Sequence,
-    rule_map: &HashMap<String, &Rule>,
-    recursive_rules: &HashSet<String>,
-) {
-    let mut new_factors = Vec::new();
-
-    for factor in &seq.factors {
-        match inline_factor(factor, rule_map, recursive_rules) {
-            InlineResult::Keep(f) => new_factors.push(f),
-            InlineResult::Replace(factors) => new_factors.extend(factors),
-        }
-    }
-
-    seq.factors = new_factors;
-}
-
-/// Result of inlining a factor
-enum InlineResult {
-    Keep(Factor), // Keep the factor as-is
-    #[allow(dead_code)]
-    Replace(Vec<Factor>), // Replace with multiple factors (reserved for future use)
-}
-
-/// Inline a factor if it's a non-recursive nonterminal
-fn inline_factor(
-    factor: &Factor,
-    rule_map: &HashMap<String, &Rule>,
-    recursive_rules: &HashSet<String>,
-) -> InlineResult {
-    match &factor.base {
-        BaseFactor::Nonterminal { name, mark } => {
-            // Don't inline recursive rules
-            if recursive_rules.contains(name) {
-                return InlineResult::Keep(factor.clone());
-            }
-
-            // Look up the rule definition
-            let target_rule = match rule_map.get(name) {
-                Some(r) => r,
-                None => return InlineResult::Keep(factor.clone()), // Undefined rule
-            };
-
-            // Clone the alternatives and recursively inline within them
-            let mut inlined_alternatives = target_rule.alternatives.clone();
-            inline_in_alternatives(&mut inlined_alternatives, rule_map, recursive_rules);
-
-            // Wrap the inlined alternatives in a group
-            let mut inlined_base = BaseFactor::Group {
-                alternatives: Box::new(inlined_alternatives),
-            };
-
-            // Preserve the mark from the nonterminal reference
-            if *mark != Mark::None {
-                // If the nonterminal had a mark, we need to apply it to the group
-                // This is a simplification - a complete implementation would need
-                // to propagate marks through the inlined content
-                inlined_base = apply_mark_to_base(inlined_base, *mark);
-            }
-
-            // Create a new factor with the same repetition
-            let inlined_factor = Factor::new(inlined_base, factor.repetition.clone());
-
-            InlineResult::Keep(inlined_factor)
-        }
-        BaseFactor::Group { alternatives } => {
-            // Recursively inline within groups
-            let mut inlined_alternatives = (**alternatives).clone();
-            inline_in_alternatives(&mut inlined_alternatives, rule_map, recursive_rules);
-
-            let inlined_factor = Factor::new(
-                BaseFactor::Group {
-                    alternatives: Box::new(inlined_alternatives),
-                },
-                factor.repetition.clone(),
-            );
-
-            InlineResult::Keep(inlined_factor)
-        }
-        _ => InlineResult::Keep(factor.clone()), // Keep literals and character classes as-is
-    }
-}
-
-/// Apply a mark to a base factor (simplified - full implementation would be more complex)
-fn apply_mark_to_base(base: BaseFactor, _mark: Mark) -> BaseFactor {
-    match base {
-        BaseFactor::Group { alternatives } => {
-            // For groups, we can't directly apply the mark
-            // This is a limitation of the current simplified implementation
-            // A full implementation would need to propagate the mark through the tree
-            BaseFactor::Group { alternatives }
-        }
-        _ => base, // For other types, mark propagation is not straightforward
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -385,4 +582,102 @@ mod tests {
             _ => panic!("Expected a Group after inlining"),
         }
     }
+
+    #[test]
+    fn test_eliminate_left_recursion() {
+        use crate::grammar_ast::parse_ixml_grammar;
+        use crate::native_parser::{NativeParser, ParseStrategy};
+
+        let rewrite = |source: &str| {
+            eliminate_left_recursion(&parse_ixml_grammar(source).unwrap()).to_string()
+        };
+        assert_eq!(
+            rewrite(r#"e: -e, "+", t; -e, "-", t; t; "(", e, ")". t: "x"."#),
+            "e: (t; \"(\", e, \")\"), (\"+\", t; \"-\", t)*.\nt: \"x\".\n"
+        );
+        assert_eq!(rewrite(r#"a: a; "x"; "y"."#), "a: \"x\"; \"y\".\n");
+        // Nothing to start from, and recursion through an element
+        assert_eq!(rewrite(r#"a: a, "x"."#), "a: a, \"x\".\n");
+        assert_eq!(
+            rewrite(r#"a: b, "x"; "y". b: a, "z"."#),
+            "a: b, \"x\"; \"y\".\nb: a, \"z\".\n"
+        );
+
+        // Results match those of the Earley engine on the original grammar
+        let cases = [
+            (r#"sum: -sum, "+", n; n. n: ["0"-"9"]."#, "1+2+3"),
+            (
+                r#"sum: -more; n. -more: -sum, "+", n. n: ["0"-"9"]."#,
+                "1+2+3",
+            ),
+            (r#"list: -list, ",", @x; @x. x: ["a"-"z"]."#, "a"),
+            (r#"s: -s, "b"; "a", c. c: "c"?."#, "acbb"),
+        ];
+        for (source, input) in cases {
+            let grammar = parse_ixml_grammar(source).unwrap();
+            let expected = NativeParser::with_strategy(grammar.clone(), ParseStrategy::Earley)
+                .parse(input)
+                .unwrap();
+            let parser = NativeParser::new(grammar).with_left_recursion_eliminated();
+            assert_eq!(parser.parse(input).unwrap(), expected, "{}", source);
+        }
+
+        // Nested elements from an unhidden reference can't be iterated, so
+        // such rules are left alone, unless the rule itself is hidden
+        for source in [
+            "sum: sum, \"+\", n; n.\nn: [\"0\"-\"9\"].\n",
+            "sum: -sum, \"+\", n; ^sum, \"-\", n; n.\n",
+        ] {
+            assert_eq!(rewrite(source), source);
+        }
+        assert_eq!(
+            rewrite(r#"-sum: sum, "+", n; n."#),
+            "-sum: n, (\"+\", n)*.\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_options() {
+        use crate::grammar_ast::parse_ixml_grammar;
+
+        let grammar = parse_ixml_grammar(
+            r#"s: -a, ^b, expr. -a: "a"?. ^b: @c. c: "c". expr: "(", expr, ")"; -a. dead: "d"."#,
+        )
+        .unwrap();
+        let normalize =
+            |options: NormalizeOptions| normalize_grammar_with(&grammar, &options).to_string();
+        assert_eq!(normalize(NormalizeOptions::none()), grammar.to_string());
+        assert_eq!(
+            normalize(NormalizeOptions::none().with_inline_hidden(true)),
+            "s: (\"a\"?), ^b, expr.\n-a: \"a\"?.\n^b: @c.\nc: \"c\".\n\
+             expr: \"(\", expr, \")\"; (\"a\"?).\ndead: \"d\".\n"
+        );
+        assert_eq!(
+            normalize(
+                NormalizeOptions::none()
+                    .with_inline_promoted(true)
+                    .with_remove_dead_rules(true)
+            ),
+            "s: -a, (@c), expr.\n-a: \"a\"?.\nc: \"c\".\nexpr: \"(\", expr, \")\"; -a.\n"
+        );
+        // The recursive rule stays; everything else is inlined and dropped
+        assert_eq!(
+            normalize(NormalizeOptions::default()),
+            "s: (\"a\"?), ((\"c\")), expr.\nexpr: \"(\", expr, \")\"; (\"a\"?).\n"
+        );
+
+        // Inlining hidden rules leaves results unchanged
+        for fixture in crate::fixtures::FIXTURES {
+            let options = NormalizeOptions::none().with_inline_hidden(true);
+            let normalized = normalize_grammar_with(&fixture.grammar_ast(), &options);
+            assert_eq!(
+                crate::native_parser::NativeParser::new(normalized)
+                    .parse(fixture.input)
+                    .unwrap(),
+                fixture.expected,
+                "{}",
+                fixture.name
+            );
+        }
+    }
 }
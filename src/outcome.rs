@@ -0,0 +1,349 @@
+//! Parse outcomes and the spec's `ixml:state` values
+//!
+//! The iXML specification reports the condition of a result document in an
+//! `ixml:state` attribute on its root element: `ambiguous` when the input has
+//! more than one parse, `failed` when it has none, and `version-mismatch` when
+//! the grammar declares an ixml version this processor does not implement.
+//! [`ParseOutcome`] carries the document together with those states, so callers
+//! can branch on them without reading attributes back out of the XML.
+
+use crate::parse_context::ParseError;
+use crate::xml_node::{FixedAttributes, OutputLimits, SerializeOptions, XmlNode};
+use std::fmt;
+use std::str::FromStr;
+
+/// An `ixml:state` value defined by the iXML specification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DocumentState {
+    /// The input can be parsed in more than one way; one parse was chosen
+    Ambiguous,
+    /// The input does not match the grammar
+    Failed,
+    /// The grammar declares an ixml version this processor does not implement
+    VersionMismatch,
+    /// Not a spec state: the document was cut short by [`OutputLimits`]
+    Truncated,
+}
+
+impl DocumentState {
+    /// Every state, in the order they are written to `ixml:state`
+    pub const ALL: [DocumentState; 4] = [
+        DocumentState::Ambiguous,
+        DocumentState::Failed,
+        DocumentState::VersionMismatch,
+        DocumentState::Truncated,
+    ];
+
+    /// The attribute value, e.g. `"version-mismatch"`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DocumentState::Ambiguous => "ambiguous",
+            DocumentState::Failed => "failed",
+            DocumentState::VersionMismatch => "version-mismatch",
+            DocumentState::Truncated => "truncated",
+        }
+    }
+
+    /// Read the states from a space-separated `ixml:state` value
+    ///
+    /// Values that are not a [`DocumentState`] are skipped; `truncated`, which
+    /// is rustixml's own, is read like the spec's states.
+    pub fn parse_list(value: &str) -> Vec<DocumentState> {
+        value
+            .split_whitespace()
+            .filter_map(|state| state.parse().ok())
+            .collect()
+    }
+}
+
+impl fmt::Display for DocumentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DocumentState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DocumentState::ALL
+            .into_iter()
+            .find(|state| state.as_str() == s)
+            .ok_or_else(|| format!("Unknown ixml:state value '{}'", s))
+    }
+}
+
+/// The result document of a parse, successful or not
+///
+/// A failed parse still produces a document, as the spec requires: a
+/// `<failed ixml:state="failed">` element with the `line`, `column` and
+/// `message` of the error.
+///
+/// ```
+/// use rustixml::{fixtures, DocumentState};
+///
+/// let outcome = fixtures::AMBIGUOUS.parser().parse_outcome("x.");
+/// assert!(outcome.is_success());
+/// assert_eq!(outcome.states(), [DocumentState::Ambiguous]);
+///
+/// let outcome = fixtures::DATE.parser().parse_outcome("2024-3-15");
+/// assert!(outcome.has_state(DocumentState::Failed));
+/// assert!(outcome.error().is_some());
+/// assert!(outcome.to_xml().starts_with("<failed ixml:state='failed'"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParseOutcome {
+    document: XmlNode,
+    states: Vec<DocumentState>,
+    error: Option<ParseError>,
+    location: Option<(usize, usize)>,
+    warnings: Vec<String>,
+    max_bytes: Option<usize>,
+    serialize_options: SerializeOptions,
+}
+
+impl ParseOutcome {
+    /// A successful parse, with `states` applied to the document's root element
+    pub fn success(mut document: XmlNode, states: Vec<DocumentState>) -> Self {
+        for state in &states {
+            document.set_ixml_state(state.as_str());
+        }
+        ParseOutcome {
+            document,
+            states,
+            error: None,
+            location: None,
+            warnings: Vec::new(),
+            max_bytes: None,
+            serialize_options: SerializeOptions::default(),
+        }
+    }
+
+    /// A failed parse; `line` and `column` locate the error in the input
+    ///
+    /// `Failed` is added to `states` if it is not already there.
+    pub fn failure(
+        error: ParseError,
+        line: usize,
+        column: usize,
+        mut states: Vec<DocumentState>,
+    ) -> Self {
+        if !states.contains(&DocumentState::Failed) {
+            states.insert(0, DocumentState::Failed);
+        }
+        let document = XmlNode::element("failed")
+            .with_child(XmlNode::element("line").with_child(XmlNode::text(line.to_string())))
+            .with_child(XmlNode::element("column").with_child(XmlNode::text(column.to_string())))
+            .with_child(XmlNode::element("message").with_child(XmlNode::text(error.to_string())));
+        ParseOutcome {
+            error: Some(error),
+            location: Some((line, column)),
+            ..ParseOutcome::success(document, states)
+        }
+    }
+
+    /// Cut the document down to `limits`
+    ///
+    /// The node limit applies now; the byte limit when the document is serialized.
+    /// A document already marked `truncated`, by a parser that stopped building
+    /// it at the node limit, is reported as truncated too.
+    pub fn with_limits(mut self, limits: OutputLimits) -> Self {
+        if let Some(max_nodes) = limits.max_nodes {
+            let cut_short = self.error.is_none()
+                && self.document.ixml_state().is_some_and(|value| {
+                    DocumentState::parse_list(value).contains(&DocumentState::Truncated)
+                });
+            match self.document.truncate(max_nodes) {
+                Some(warning) => self.mark_truncated(warning),
+                None if cut_short => {
+                    self.mark_truncated(format!("Output truncated to {} nodes", max_nodes))
+                }
+                None => {}
+            }
+        }
+        self.max_bytes = limits.max_bytes;
+        self
+    }
+
+    /// Lay out the serialized document as `options` say
+    pub fn with_serialize_options(mut self, options: SerializeOptions) -> Self {
+        self.serialize_options = options;
+        self
+    }
+
+    fn mark_truncated(&mut self, warning: String) {
+        if !self.has_state(DocumentState::Truncated) {
+            self.states.push(DocumentState::Truncated);
+        }
+        self.warnings.push(warning);
+    }
+
+    /// The result document: the parse tree, or the failure report
+    pub fn document(&self) -> &XmlNode {
+        &self.document
+    }
+
+    /// Take the result document
+    pub fn into_document(self) -> XmlNode {
+        self.document
+    }
+
+    /// The document's `ixml:state` values, in the order they were set
+    pub fn states(&self) -> &[DocumentState] {
+        &self.states
+    }
+
+    /// Check whether the document has `state`
+    pub fn has_state(&self, state: DocumentState) -> bool {
+        self.states.contains(&state)
+    }
+
+    /// Check whether the input matched the grammar
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Check whether more than one parse of the input exists
+    pub fn is_ambiguous(&self) -> bool {
+        self.has_state(DocumentState::Ambiguous)
+    }
+
+    /// Check whether the grammar's ixml version is unsupported
+    pub fn is_version_mismatch(&self) -> bool {
+        self.has_state(DocumentState::VersionMismatch)
+    }
+
+    /// The parse error of a failed parse
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
+
+    /// Line and column of the error of a failed parse
+    pub fn location(&self) -> Option<(usize, usize)> {
+        self.location
+    }
+
+    /// Problems that did not stop the parse, e.g. a truncated document
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Serialize the document
+    pub fn to_xml(&self) -> String {
+        self.to_xml_with(&FixedAttributes::default())
+    }
+
+    /// Serialize the document with constant attributes, e.g. a parser's
+    /// [`NativeParser::fixed_attributes`](crate::NativeParser::fixed_attributes)
+    pub fn to_xml_with(&self, fixed: &FixedAttributes) -> String {
+        self.to_xml_with_warnings(fixed).0
+    }
+
+    /// Serialize the document, with every warning including one for output
+    /// cut short by the byte limit
+    pub fn to_xml_with_warnings(&self, fixed: &FixedAttributes) -> (String, Vec<String>) {
+        let (xml, warning) =
+            self.document
+                .write_xml(fixed, &self.serialize_options, self.max_bytes);
+        let mut warnings = self.warnings.clone();
+        warnings.extend(warning);
+        (xml, warnings)
+    }
+
+    /// The parse tree on success, or the error on failure
+    pub fn into_result(self) -> Result<XmlNode, ParseError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.document),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_names() {
+        for state in DocumentState::ALL {
+            assert_eq!(state.as_str().parse::<DocumentState>(), Ok(state));
+        }
+        assert_eq!(
+            "partial".parse::<DocumentState>().unwrap_err(),
+            "Unknown ixml:state value 'partial'"
+        );
+        assert_eq!(
+            DocumentState::parse_list(" ambiguous other version-mismatch truncated"),
+            vec![
+                DocumentState::Ambiguous,
+                DocumentState::VersionMismatch,
+                DocumentState::Truncated
+            ]
+        );
+    }
+
+    #[test]
+    fn test_success_and_failure_documents() {
+        let outcome = ParseOutcome::success(
+            XmlNode::element("a").with_child(XmlNode::text("x")),
+            vec![DocumentState::Ambiguous, DocumentState::VersionMismatch],
+        );
+        assert!(outcome.is_success() && outcome.is_ambiguous() && outcome.is_version_mismatch());
+        assert_eq!(
+            outcome.document().ixml_state(),
+            Some("ambiguous version-mismatch")
+        );
+        assert_eq!(
+            DocumentState::parse_list(outcome.document().ixml_state().unwrap()),
+            outcome.states()
+        );
+
+        let error = ParseError::Custom {
+            message: "Expected 'x'".to_string(),
+            position: 4,
+        };
+        let outcome = ParseOutcome::failure(error, 2, 3, vec![DocumentState::VersionMismatch]);
+        assert!(!outcome.is_success());
+        assert_eq!(outcome.location(), Some((2, 3)));
+        assert_eq!(
+            outcome.states(),
+            [DocumentState::Failed, DocumentState::VersionMismatch]
+        );
+        assert_eq!(
+            outcome.to_xml(),
+            "<failed ixml:state='failed version-mismatch' xmlns:ixml='http://invisiblexml.org/NS'>\
+             <line>2</line><column>3</column><message>Expected 'x'</message></failed>"
+        );
+        assert_eq!(outcome.into_result().unwrap_err().position(), 4);
+    }
+
+    #[test]
+    fn test_output_limits() {
+        let document = || {
+            XmlNode::element("a")
+                .with_child(XmlNode::element("b"))
+                .with_child(XmlNode::text("xyz"))
+        };
+        let outcome = ParseOutcome::success(document(), vec![]).with_limits(OutputLimits::new());
+        assert!(outcome.warnings().is_empty());
+
+        let outcome = ParseOutcome::success(document(), vec![DocumentState::Ambiguous])
+            .with_limits(OutputLimits::new().with_max_nodes(2));
+        assert_eq!(
+            outcome.states(),
+            [DocumentState::Ambiguous, DocumentState::Truncated]
+        );
+        assert_eq!(outcome.warnings(), ["Output truncated to 2 nodes"]);
+
+        // The byte limit applies when serializing
+        let outcome = ParseOutcome::success(document(), vec![])
+            .with_limits(OutputLimits::new().with_max_bytes(8));
+        assert!(outcome.warnings().is_empty());
+        let (xml, warnings) = outcome.to_xml_with_warnings(&FixedAttributes::new());
+        assert_eq!(
+            xml,
+            "<a ixml:state='truncated' xmlns:ixml='http://invisiblexml.org/NS'><b/>x</a>"
+        );
+        assert_eq!(warnings, ["Output truncated at 8 bytes"]);
+    }
+}
@@ -3,25 +3,57 @@
 //! Tracks parsing state during recursive descent, including rule stack
 //! for left-recursion detection and parse results with consumed counts.
 
-use crate::xml_node::XmlNode;
+use crate::compiled_grammar::SymbolId;
+use crate::input_stream::InputStream;
+use crate::xml_tree::{NodeId, XmlTree};
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// How much of the input after the furthest failure to quote
+const FOUND_CHARS: usize = 8;
 
 /// Context maintained during parsing for tracking and error reporting
 #[derive(Debug, Clone)]
-pub struct ParseContext {
-    /// Current rule being parsed (for error messages)
-    pub rule_name: String,
+pub struct ParseContext<'i> {
+    /// Rule names by [`SymbolId`], for reporting rules by name
+    pub rule_names: Arc<[String]>,
+
+    /// Rule most recently entered (for error messages)
+    pub rule: Option<SymbolId>,
 
     /// Recursion depth (for debugging and loop detection)
     pub depth: usize,
 
-    /// (rule_name, position) pairs currently on the call stack
+    /// (rule, position) pairs currently on the call stack
     /// for left-recursion detection
-    pub left_recursion: HashSet<(String, usize)>,
+    pub left_recursion: HashSet<(SymbolId, usize)>,
+
+    /// Rules currently being parsed, outermost first
+    pub rule_stack: Vec<SymbolId>,
+
+    /// Furthest position where the input stopped matching (reported by
+    /// [`ParseContext::trace`])
+    pub furthest_failure: Option<Failure>,
 
-    /// Memoization cache: (rule_name, position) -> Result<ParseResult, ParseError>
+    /// Negative lookaheads being tried; failures inside them are not recorded
+    pub lookahead_depth: usize,
+
+    /// Arena holding every node the parse builds; results refer to it by id
+    pub tree: XmlTree<'i>,
+
+    /// Memoization cache: (rule symbol, position) -> Result<ParseResult, ParseError>
     /// Stores the result of parsing a rule at a specific position to avoid re-parsing
-    pub memo_cache: HashMap<(String, usize), Result<ParseResult, ParseError>>,
+    pub memo_cache: HashMap<(SymbolId, usize), Result<ParseResult, ParseError>>,
+
+    /// Memoization cache for parenthesized groups: (group address, position) -> result
+    /// Lets backtracking reuse the winning alternative instead of re-parsing every one
+    pub group_cache: HashMap<(usize, usize), Result<ParseResult, ParseError>>,
+
+    /// Per-rule count of how often each alternative won (None = not profiling)
+    pub alternative_wins: Option<HashMap<String, Vec<usize>>>,
+
+    /// Rules entered and left and alternatives tried (None = not tracing)
+    pub trace_events: Option<Vec<TraceEvent>>,
 
     /// Instruction budget for IC canister execution (None = unlimited)
     /// Only used when compiled for IC with ic-canister feature
@@ -43,14 +75,22 @@ pub struct ParseContext {
     pub check_interval: usize,
 }
 
-impl ParseContext {
+impl<'i> ParseContext<'i> {
     /// Create a new parse context
     pub fn new() -> Self {
         ParseContext {
-            rule_name: String::new(),
+            rule_names: Arc::from([]),
+            rule: None,
             depth: 0,
             left_recursion: HashSet::new(),
+            rule_stack: Vec::new(),
+            furthest_failure: None,
+            lookahead_depth: 0,
+            tree: XmlTree::new(),
             memo_cache: HashMap::new(),
+            group_cache: HashMap::new(),
+            alternative_wins: None,
+            trace_events: None,
             #[cfg(all(target_arch = "wasm32", feature = "ic-canister"))]
             instruction_budget: None,
             #[cfg(all(target_arch = "wasm32", feature = "ic-canister"))]
@@ -62,26 +102,124 @@ impl ParseContext {
         }
     }
 
+    /// Report rules by the names in `rule_names`, indexed by [`SymbolId`]
+    pub fn with_rule_names(mut self, rule_names: Arc<[String]>) -> Self {
+        self.rule_names = rule_names;
+        self
+    }
+
+    /// Build the tree over `input`, so its text nodes can be spans of it
+    pub fn with_input(mut self, input: &'i str) -> Self {
+        let tree = XmlTree::with_input(input);
+        self.tree = match self.tree.max_nodes() {
+            Some(max_nodes) => tree.with_max_nodes(max_nodes),
+            None => tree,
+        };
+        self
+    }
+
+    /// The name of rule `id`
+    pub fn name(&self, id: SymbolId) -> &str {
+        self.rule_names.get(id).map_or("", String::as_str)
+    }
+
+    /// The name of the rule most recently entered
+    pub fn rule_name(&self) -> &str {
+        self.rule.map_or("", |id| self.name(id))
+    }
+
     /// Enter a rule at a specific position (push onto recursion stack)
-    pub fn enter_rule(&mut self, rule_name: &str, position: usize) -> bool {
+    pub fn enter_rule(&mut self, rule: SymbolId, position: usize) -> bool {
         self.depth += 1;
-        self.rule_name = rule_name.to_string();
+        self.rule = Some(rule);
 
         // Check if we're already parsing this rule at this position (left-recursion)
-        let key = (rule_name.to_string(), position);
-        if self.left_recursion.contains(&key) {
+        if !self.left_recursion.insert((rule, position)) {
             return false; // Left recursion detected
         }
 
-        self.left_recursion.insert(key);
+        self.rule_stack.push(rule);
         true
     }
 
     /// Exit a rule at a specific position (pop from recursion stack)
-    pub fn exit_rule(&mut self, rule_name: &str, position: usize) {
+    pub fn exit_rule(&mut self, rule: SymbolId, position: usize) {
         self.depth = self.depth.saturating_sub(1);
-        let key = (rule_name.to_string(), position);
-        self.left_recursion.remove(&key);
+        self.left_recursion.remove(&(rule, position));
+        if let Some(index) = self.rule_stack.iter().rposition(|&id| id == rule) {
+            self.rule_stack.remove(index);
+        }
+    }
+
+    /// Let a rule be entered again at `position` without leaving the rule stack
+    /// (while growing a left-recursive seed)
+    pub fn allow_reentry(&mut self, rule: SymbolId, position: usize) {
+        self.left_recursion.remove(&(rule, position));
+    }
+
+    /// Undo [`ParseContext::allow_reentry`]
+    pub fn forbid_reentry(&mut self, rule: SymbolId, position: usize) {
+        self.left_recursion.insert((rule, position));
+    }
+
+    /// Note that `expected` (a terminal, character class or rule, in iXML
+    /// notation) did not match `stream` at `position`
+    ///
+    /// Only the furthest failure is kept, since that is usually where the input
+    /// is wrong; everything expected there is collected.
+    pub fn record_failure(
+        &mut self,
+        stream: &InputStream,
+        position: usize,
+        expected: impl FnOnce() -> String,
+    ) {
+        if self.lookahead_depth > 0 {
+            return;
+        }
+        match &mut self.furthest_failure {
+            Some(failure) if position < failure.position => {}
+            Some(failure) if position == failure.position => {
+                let expected = expected();
+                if !failure.expected.contains(&expected) {
+                    failure.expected.push(expected);
+                }
+            }
+            _ => {
+                self.furthest_failure = Some(Failure {
+                    position,
+                    rules: self
+                        .rule_stack
+                        .iter()
+                        .map(|&id| self.name(id).to_string())
+                        .collect(),
+                    expected: vec![expected()],
+                    found: stream.substring(position, position + FOUND_CHARS),
+                })
+            }
+        }
+    }
+
+    /// Record a step of a traced parse, nested in the rules on the rule stack
+    pub fn record_trace(&mut self, rule: &str, start: usize, kind: TraceKind) {
+        if let Some(events) = &mut self.trace_events {
+            events.push(TraceEvent {
+                depth: self.rule_stack.len(),
+                rule: rule.to_string(),
+                start,
+                kind,
+            });
+        }
+    }
+
+    /// Attach the furthest failure to `error`
+    pub fn trace(&self, error: ParseError) -> ParseError {
+        match &self.furthest_failure {
+            Some(failure) => ParseError::Farthest {
+                error: Box::new(error),
+                failure: failure.clone(),
+            },
+            None => error,
+        }
     }
 
     /// Set instruction budget for IC canister execution
@@ -130,10 +268,7 @@ impl ParseContext {
             let consumed = current - self.instruction_start;
 
             if consumed > budget {
-                return Err(ParseError::InstructionLimitExceeded {
-                    consumed,
-                    budget,
-                });
+                return Err(ParseError::InstructionLimitExceeded { consumed, budget });
             }
         }
 
@@ -148,26 +283,33 @@ impl ParseContext {
     }
 }
 
-impl Default for ParseContext {
+impl Default for ParseContext<'_> {
     fn default() -> Self {
         Self::new()
     }
 }
 
 /// Result of parsing operation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 pub struct ParseResult {
-    /// The parsed XML node (None if suppressed with - mark)
-    pub node: Option<XmlNode>,
+    /// The parsed node in [`ParseContext::tree`] (None if suppressed with - mark)
+    pub node: Option<NodeId>,
 
     /// Number of characters consumed from input
     pub consumed: usize,
+
+    /// Whether more than one derivation produced this span (two alternatives tied)
+    pub ambiguous: bool,
 }
 
 impl ParseResult {
     /// Create a new parse result
-    pub fn new(node: Option<XmlNode>, consumed: usize) -> Self {
-        ParseResult { node, consumed }
+    pub fn new(node: Option<NodeId>, consumed: usize) -> Self {
+        ParseResult {
+            node,
+            consumed,
+            ambiguous: false,
+        }
     }
 
     /// Create a result with no node (suppressed) but characters consumed
@@ -175,28 +317,178 @@ impl ParseResult {
         ParseResult {
             node: None,
             consumed,
+            ambiguous: false,
         }
     }
 
     /// Create a result with a node
-    pub fn with_node(node: XmlNode, consumed: usize) -> Self {
+    pub fn with_node(node: NodeId, consumed: usize) -> Self {
         ParseResult {
             node: Some(node),
             consumed,
+            ambiguous: false,
         }
     }
 
     /// Create a result for insertion (node but no consumption)
-    pub fn insertion(node: XmlNode) -> Self {
+    pub fn insertion(node: NodeId) -> Self {
         ParseResult {
             node: Some(node),
             consumed: 0,
+            ambiguous: false,
+        }
+    }
+
+    /// Mark the result as ambiguous if `ambiguous` is set
+    pub fn with_ambiguity(mut self, ambiguous: bool) -> Self {
+        self.ambiguous |= ambiguous;
+        self
+    }
+}
+
+/// The furthest point a parse reached before the input stopped matching
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// Character offset in the input
+    pub position: usize,
+    /// Rules being parsed there, outermost first
+    pub rules: Vec<String>,
+    /// Terminals, character classes and rules that would have matched there,
+    /// in iXML notation and the order they were tried
+    pub expected: Vec<String>,
+    /// The start of the input found instead (empty at the end of the input)
+    pub found: String,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected ")?;
+        match self.expected.split_last() {
+            Some((last, [])) => write!(f, "{}", last)?,
+            Some((last, rest)) => write!(f, "one of {}, {}", rest.join(", "), last)?,
+            None => write!(f, "nothing")?,
+        }
+        if self.found.is_empty() {
+            write!(f, " but reached the end of the input")
+        } else {
+            write!(f, " but found {:?}", self.found)
+        }
+    }
+}
+
+/// One step of a parse traced with
+/// [`NativeParser::parse_traced`](crate::native_parser::NativeParser::parse_traced)
+///
+/// `Display` indents the step two spaces per enclosing rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// How many rules are being parsed around this step
+    pub depth: usize,
+    pub rule: String,
+    /// Character offset where the rule or alternative started
+    pub start: usize,
+    pub kind: TraceKind,
+}
+
+/// What happened in a [`TraceEvent`]; `end` is `None` for a failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceKind {
+    /// The rule is tried
+    Enter,
+    /// The rule matched up to `end`, or failed
+    Exit { end: Option<usize> },
+    /// The rule was already parsed at this position, with this result
+    Memoized { end: Option<usize> },
+    /// Alternative `index` (from 0) of the rule matched up to `end`, or failed
+    /// and the parser backtracked to `start`
+    Alternative { index: usize, end: Option<usize> },
+}
+
+impl std::fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let indent = "  ".repeat(self.depth);
+        let (rule, start) = (&self.rule, self.start);
+        match self.kind {
+            TraceKind::Enter => write!(f, "{}enter {} at {}", indent, rule, start),
+            TraceKind::Exit { end: Some(end) } => {
+                write!(f, "{}exit {} at {}..{}", indent, rule, start, end)
+            }
+            TraceKind::Exit { end: None } => write!(f, "{}fail {} at {}", indent, rule, start),
+            TraceKind::Memoized { end: Some(end) } => {
+                write!(f, "{}{} at {}..{} (memoized)", indent, rule, start, end)
+            }
+            TraceKind::Memoized { end: None } => {
+                write!(f, "{}{} at {} failed (memoized)", indent, rule, start)
+            }
+            TraceKind::Alternative {
+                index,
+                end: Some(end),
+            } => write!(
+                f,
+                "{}alternative {} of {} matched {}..{}",
+                indent,
+                index + 1,
+                rule,
+                start,
+                end
+            ),
+            TraceKind::Alternative { index, end: None } => write!(
+                f,
+                "{}alternative {} of {} failed, backtracking to {}",
+                indent,
+                index + 1,
+                rule,
+                start
+            ),
         }
     }
 }
 
+/// How much work a traced parse did, counted from its [`TraceEvent`]s
+///
+/// ```
+/// use rustixml::parse_context::ParseCounters;
+/// use rustixml::{parse_ixml_grammar, NativeParser};
+///
+/// let grammar = parse_ixml_grammar(r#"s: a; b. a: "x", "y". b: "x", "z"."#).unwrap();
+/// let (_, events) = NativeParser::new(grammar).parse_traced("xz");
+/// let counters = ParseCounters::from_trace(&events);
+/// assert_eq!((counters.rules_entered, counters.alternatives_tried), (3, 4));
+/// assert_eq!((counters.backtracks, counters.memo_hits), (2, 0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParseCounters {
+    /// Rules parsed at a position, memoized results aside
+    pub rules_entered: usize,
+    /// Rules answered from the memo cache
+    pub memo_hits: usize,
+    pub alternatives_tried: usize,
+    /// Alternatives that failed, sending the parser back to their start
+    pub backtracks: usize,
+}
+
+impl ParseCounters {
+    pub fn from_trace(events: &[TraceEvent]) -> Self {
+        let mut counters = ParseCounters::default();
+        for event in events {
+            match event.kind {
+                TraceKind::Enter => counters.rules_entered += 1,
+                TraceKind::Memoized { .. } => counters.memo_hits += 1,
+                TraceKind::Alternative { end, .. } => {
+                    counters.alternatives_tried += 1;
+                    if end.is_none() {
+                        counters.backtracks += 1;
+                    }
+                }
+                TraceKind::Exit { .. } => {}
+            }
+        }
+        counters
+    }
+}
+
 /// Error type for parsing failures
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParseError {
     /// Unexpected end of input
     UnexpectedEof { position: usize, expected: String },
@@ -234,6 +526,26 @@ pub enum ParseError {
 
     /// Custom error message
     Custom { message: String, position: usize },
+
+    /// The progress callback stopped the parse (see
+    /// [`NativeParser::parse_with_progress`](crate::NativeParser::parse_with_progress))
+    Cancelled { position: usize },
+
+    /// An error with the furthest point the parse reached, which may be past
+    /// the error's own position
+    Farthest {
+        error: Box<ParseError>,
+        failure: Failure,
+    },
+
+    /// An error placed in the input it was raised for, which its `Display`
+    /// describes with line, column and context (see [`ParseError::located`])
+    Located {
+        error: Box<ParseError>,
+        line: usize,
+        column: usize,
+        report: String,
+    },
 }
 
 impl ParseError {
@@ -248,12 +560,84 @@ impl ParseError {
             ParseError::LeftRecursion { position, .. } => *position,
             ParseError::InstructionLimitExceeded { .. } => 0, // No specific position
             ParseError::Custom { position, .. } => *position,
+            ParseError::Cancelled { position } => *position,
+            ParseError::Farthest { error, .. } => error.position(),
+            ParseError::Located { error, .. } => error.position(),
         }
     }
 
+    /// Place the error in the stream it was raised for, so it displays as
+    /// [`ParseError::format_with_stream`] would format it
+    pub fn located(self, stream: &crate::input_stream::InputStream) -> ParseError {
+        if let ParseError::Located { .. } = self {
+            return self;
+        }
+        let (line, column) = stream.line_col(self.position());
+        let report = self.format_with_stream(stream);
+        ParseError::Located {
+            error: Box::new(self),
+            line,
+            column,
+            report,
+        }
+    }
+
+    /// Line and column of the error, if it has been [located](ParseError::located)
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match self {
+            ParseError::Located { line, column, .. } => Some((*line, *column)),
+            _ => None,
+        }
+    }
+
+    /// Whether the parse was stopped by its progress callback
+    pub fn is_cancelled(&self) -> bool {
+        match self {
+            ParseError::Cancelled { .. } => true,
+            ParseError::Located { error, .. } | ParseError::Farthest { error, .. } => {
+                error.is_cancelled()
+            }
+            _ => false,
+        }
+    }
+
+    /// The furthest point the parse reached, if known
+    pub fn farthest_failure(&self) -> Option<&Failure> {
+        match self {
+            ParseError::Farthest { failure, .. } => Some(failure),
+            ParseError::Located { error, .. } => error.farthest_failure(),
+            _ => None,
+        }
+    }
+
+    /// The rules being parsed where the input stopped matching, outermost first
+    /// (empty if unknown)
+    pub fn rule_stack(&self) -> &[String] {
+        self.farthest_failure()
+            .map_or(&[], |failure| failure.rules.as_slice())
+    }
+
+    /// What would have matched at the furthest point the parse reached, in
+    /// iXML notation (empty if unknown)
+    pub fn expected(&self) -> &[String] {
+        self.farthest_failure()
+            .map_or(&[], |failure| failure.expected.as_slice())
+    }
+
+    /// Line and column (both counted from 1) of the error in `input`
+    pub fn line_col(&self, input: &str) -> (usize, usize) {
+        crate::input_stream::line_col(input, self.position())
+    }
+
     /// Format error with context from input
     pub fn format_with_context(&self, input: &str) -> String {
-        let stream = crate::input_stream::InputStream::new(input);
+        self.format_with_stream(&crate::input_stream::InputStream::new(input))
+    }
+
+    /// Format error with context from the stream that was parsed
+    ///
+    /// For streaming input, context is limited to the buffered window.
+    pub fn format_with_stream(&self, stream: &crate::input_stream::InputStream) -> String {
         let (line, col) = stream.line_col(self.position());
         let context = stream.substring(
             self.position().saturating_sub(20),
@@ -324,6 +708,21 @@ impl ParseError {
                     line, col, message, context
                 )
             }
+            ParseError::Cancelled { .. } => {
+                format!("Parse cancelled at line {}, column {}", line, col)
+            }
+            ParseError::Located { report, .. } => report.clone(),
+            ParseError::Farthest { error, failure } => {
+                let (line, col) = stream.line_col(failure.position);
+                format!(
+                    "{}\nFarthest failure at line {}, column {}: {}\nRule stack: {}",
+                    error.format_with_stream(stream),
+                    line,
+                    col,
+                    failure,
+                    failure.rules.join(" > ")
+                )
+            }
         }
     }
 }
@@ -371,6 +770,17 @@ impl std::fmt::Display for ParseError {
                 )
             }
             ParseError::Custom { message, .. } => write!(f, "{}", message),
+            ParseError::Cancelled { .. } => write!(f, "Parse cancelled"),
+            ParseError::Located { report, .. } => write!(f, "{}", report),
+            ParseError::Farthest { error, failure } => {
+                write!(
+                    f,
+                    "{}; {} (in {})",
+                    error,
+                    failure,
+                    failure.rules.join(" > ")
+                )
+            }
         }
     }
 }
@@ -386,52 +796,101 @@ mod tests {
         let ctx = ParseContext::new();
         assert_eq!(ctx.depth, 0);
         assert!(ctx.left_recursion.is_empty());
+        assert_eq!(ctx.rule_name(), "");
     }
 
     #[test]
     fn test_enter_exit_rule() {
-        let mut ctx = ParseContext::new();
+        let mut ctx = ParseContext::new().with_rule_names(Arc::from(["test".to_string()]));
 
-        assert!(ctx.enter_rule("test", 0));
+        assert!(ctx.enter_rule(0, 0));
         assert_eq!(ctx.depth, 1);
-        assert_eq!(ctx.rule_name, "test");
-        assert!(ctx.left_recursion.contains(&("test".to_string(), 0)));
+        assert_eq!(ctx.rule_name(), "test");
+        assert!(ctx.left_recursion.contains(&(0, 0)));
 
         // Direct left recursion at same position should fail
-        assert!(!ctx.enter_rule("test", 0));
+        assert!(!ctx.enter_rule(0, 0));
         assert_eq!(ctx.depth, 2); // Depth incremented even though recursion detected
 
-        ctx.exit_rule("test", 0);
+        ctx.exit_rule(0, 0);
         assert_eq!(ctx.depth, 1); // Back to level 1
 
-        ctx.exit_rule("test", 0);
+        ctx.exit_rule(0, 0);
         assert_eq!(ctx.depth, 0);
-        assert!(!ctx.left_recursion.contains(&("test".to_string(), 0)));
+        assert!(!ctx.left_recursion.contains(&(0, 0)));
     }
 
     #[test]
     fn test_nested_rules() {
         let mut ctx = ParseContext::new();
 
-        assert!(ctx.enter_rule("rule1", 0));
-        assert!(ctx.enter_rule("rule2", 5));
+        assert!(ctx.enter_rule(0, 0));
+        assert!(ctx.enter_rule(1, 5));
         assert_eq!(ctx.depth, 2);
-        assert!(ctx.left_recursion.contains(&("rule1".to_string(), 0)));
-        assert!(ctx.left_recursion.contains(&("rule2".to_string(), 5)));
+        assert!(ctx.left_recursion.contains(&(0, 0)));
+        assert!(ctx.left_recursion.contains(&(1, 5)));
 
-        ctx.exit_rule("rule2", 5);
+        ctx.exit_rule(1, 5);
         assert_eq!(ctx.depth, 1);
-        assert!(!ctx.left_recursion.contains(&("rule2".to_string(), 5)));
+        assert!(!ctx.left_recursion.contains(&(1, 5)));
 
-        ctx.exit_rule("rule1", 0);
+        ctx.exit_rule(0, 0);
         assert_eq!(ctx.depth, 0);
     }
 
+    #[test]
+    fn test_furthest_failure() {
+        let stream = InputStream::new("bob 15-Mxr\n");
+        let names = ["document", "date", "month"].map(String::from);
+        let mut ctx = ParseContext::new().with_rule_names(Arc::from(names));
+        let (document, date, month) = (0, 1, 2);
+        ctx.enter_rule(document, 0);
+        ctx.enter_rule(date, 4);
+        assert_eq!(ctx.rule_stack, [document, date]);
+        ctx.enter_rule(month, 7);
+        ctx.record_failure(&stream, 7, || r#""Jan""#.to_string());
+        ctx.record_failure(&stream, 7, || r#""Mar""#.to_string());
+        ctx.exit_rule(month, 7);
+        ctx.record_failure(&stream, 7, || "[L]".to_string());
+        ctx.record_failure(&stream, 7, || r#""Jan""#.to_string());
+        ctx.exit_rule(date, 4);
+        // Failing earlier, or inside a negative lookahead, is not reported
+        ctx.record_failure(&stream, 4, || "date".to_string());
+        ctx.lookahead_depth += 1;
+        ctx.record_failure(&stream, 9, || "[Nd]".to_string());
+        assert_eq!(ctx.rule_stack, [document]);
+
+        let error = ctx.trace(ParseError::Custom {
+            message: "Input remains".to_string(),
+            position: 4,
+        });
+        assert_eq!(error.position(), 4);
+        assert_eq!(error.line_col("bob\n15-Mxr"), (2, 1));
+        assert_eq!(error.rule_stack(), ["document", "date", "month"]);
+        assert_eq!(error.expected(), [r#""Jan""#, r#""Mar""#, "[L]"]);
+        assert_eq!(
+            error.to_string(),
+            r#"Input remains; expected one of "Jan", "Mar", [L] but found "Mxr\n" (in document > date > month)"#
+        );
+        assert!(error.format_with_stream(&stream).ends_with(
+            "\nFarthest failure at line 1, column 8: expected one of \"Jan\", \"Mar\", [L] \
+             but found \"Mxr\\n\"\nRule stack: document > date > month"
+        ));
+
+        // Nothing failed: the error is unchanged
+        let error = ParseContext::new().trace(ParseError::Custom {
+            message: "Input remains".to_string(),
+            position: 0,
+        });
+        assert!(error.farthest_failure().is_none() && error.rule_stack().is_empty());
+        assert!(error.expected().is_empty());
+    }
+
     #[test]
     fn test_parse_result_constructors() {
-        let node = XmlNode::Text("test".to_string());
+        let node = XmlTree::new().text("test");
 
-        let result1 = ParseResult::new(Some(node.clone()), 4);
+        let result1 = ParseResult::new(Some(node), 4);
         assert!(result1.node.is_some());
         assert_eq!(result1.consumed, 4);
 
@@ -439,7 +898,7 @@ mod tests {
         assert!(result2.node.is_none());
         assert_eq!(result2.consumed, 5);
 
-        let result3 = ParseResult::with_node(node.clone(), 3);
+        let result3 = ParseResult::with_node(node, 3);
         assert!(result3.node.is_some());
         assert_eq!(result3.consumed, 3);
 
@@ -0,0 +1,214 @@
+//! Profile-guided alternative ordering
+//!
+//! The recursive descent parser tries the alternatives of a rule one after
+//! another and keeps the longest match. Training over representative inputs
+//! records which alternative wins most often for each rule; applying the
+//! profile to a [`CompiledGrammar`] makes the parser try those alternatives
+//! first. The grammar itself is not rewritten, and every alternative is still
+//! tried, so ordering decides which alternative wins a tie rather than
+//! skipping work.
+//!
+//! Profiles persist as plain text, one rule per line:
+//!
+//! ```text
+//! rule-name: 12 0 3
+//! ```
+//!
+//! where the numbers are win counts for the rule's alternatives in source order.
+//!
+//! Reordering never changes which inputs are accepted, but when two
+//! alternatives match the same length (an ambiguous choice) the one tried
+//! first wins, so output for ambiguous input may follow the profile.
+
+use crate::ast::IxmlGrammar;
+use crate::compiled_grammar::CompiledGrammar;
+use crate::native_parser::NativeParser;
+use std::collections::HashMap;
+
+/// Per-rule alternative win counts gathered from sample inputs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AlternativeProfile {
+    wins: HashMap<String, Vec<usize>>,
+}
+
+impl AlternativeProfile {
+    /// Create an empty profile
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse each sample with `grammar` and count which alternatives win
+    ///
+    /// Samples that fail to parse are skipped. Train on the grammar in source
+    /// order: counts are recorded against alternative positions as written.
+    pub fn train(grammar: &IxmlGrammar, samples: &[&str]) -> Self {
+        let parser = NativeParser::new(grammar.clone());
+        let mut profile = Self::new();
+        for sample in samples {
+            if let Ok(wins) = parser.parse_collecting_wins(sample) {
+                profile.merge_counts(wins);
+            }
+        }
+        profile
+    }
+
+    /// Add another profile's counts to this one
+    pub fn merge(&mut self, other: &AlternativeProfile) {
+        self.merge_counts(other.wins.clone());
+    }
+
+    fn merge_counts(&mut self, wins: HashMap<String, Vec<usize>>) {
+        for (rule, counts) in wins {
+            let total = self.wins.entry(rule).or_default();
+            if total.len() < counts.len() {
+                total.resize(counts.len(), 0);
+            }
+            for (sum, count) in total.iter_mut().zip(counts) {
+                *sum += count;
+            }
+        }
+    }
+
+    /// Win counts for a rule's alternatives, in source order
+    pub fn counts(&self, rule: &str) -> Option<&[usize]> {
+        self.wins.get(rule).map(Vec::as_slice)
+    }
+
+    /// Alternative indices for a rule, most frequently successful first
+    ///
+    /// Ties keep source order.
+    pub fn ordering(&self, rule: &str) -> Option<Vec<usize>> {
+        let counts = self.wins.get(rule)?;
+        let mut order: Vec<usize> = (0..counts.len()).collect();
+        order.sort_by(|a, b| counts[*b].cmp(&counts[*a]));
+        Some(order)
+    }
+
+    /// Have the parser try each profiled rule's alternatives most successful first
+    ///
+    /// Rules whose alternative count no longer matches the profile keep
+    /// source order.
+    ///
+    /// ```
+    /// use rustixml::{compiled_grammar::CompiledGrammar, parse_ixml_grammar};
+    /// use rustixml::profile::AlternativeProfile;
+    ///
+    /// let grammar = parse_ixml_grammar(r#"s: "a"; "b"."#).unwrap();
+    /// let profile = AlternativeProfile::train(&grammar, &["b"]);
+    /// let compiled = profile.apply(CompiledGrammar::new(grammar));
+    /// assert_eq!(compiled.alternative_order(0), Some(&[1, 0][..]));
+    /// ```
+    pub fn apply(&self, mut compiled: CompiledGrammar) -> CompiledGrammar {
+        for id in 0..compiled.grammar().rules.len() {
+            if let Some(order) = self.ordering(&compiled.rule(id).name) {
+                // A count mismatch means the grammar changed since training
+                let _ = compiled.set_alternative_order(id, order);
+            }
+        }
+        compiled
+    }
+
+    /// Serialize the profile (`rule: count count ...` per line, sorted by rule)
+    pub fn to_text(&self) -> String {
+        let mut rules: Vec<_> = self.wins.iter().collect();
+        rules.sort_by(|a, b| a.0.cmp(b.0));
+        rules
+            .into_iter()
+            .map(|(rule, counts)| {
+                let counts: Vec<String> = counts.iter().map(|c| c.to_string()).collect();
+                format!("{}: {}\n", rule, counts.join(" "))
+            })
+            .collect()
+    }
+
+    /// Load a profile written by [`AlternativeProfile::to_text`]
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut wins = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (rule, counts) = line
+                .split_once(':')
+                .ok_or_else(|| format!("Line {}: expected 'rule: counts'", number + 1))?;
+            let counts = counts
+                .split_whitespace()
+                .map(|count| {
+                    count.parse::<usize>().map_err(|e| {
+                        format!("Line {}: invalid count '{}': {}", number + 1, count, e)
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            wins.insert(rule.trim().to_string(), counts);
+        }
+        Ok(AlternativeProfile { wins })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar_ast::parse_ixml_grammar;
+    use std::sync::Arc;
+
+    const GRAMMAR: &str = r#"
+        value: number; word; symbol.
+        number: ["0"-"9"]+.
+        word: ["a"-"z"]+.
+        symbol: ["+-*/"].
+    "#;
+
+    #[test]
+    fn test_train_and_order() {
+        let grammar = parse_ixml_grammar(GRAMMAR).unwrap();
+        let profile = AlternativeProfile::train(&grammar, &["abc", "xyz", "12", "+", "!!"]);
+
+        assert_eq!(profile.counts("value"), Some(&[1, 2, 1][..]));
+        assert_eq!(profile.ordering("value"), Some(vec![1, 0, 2]));
+        assert_eq!(profile.ordering("word"), Some(vec![0]));
+    }
+
+    #[test]
+    fn test_apply_preserves_output() {
+        let grammar = parse_ixml_grammar(GRAMMAR).unwrap();
+        let profile = AlternativeProfile::train(&grammar, &["abc", "xyz"]);
+        let compiled = profile.apply(CompiledGrammar::new(grammar.clone()));
+        assert_eq!(compiled.alternative_order(0), Some(&[1, 0, 2][..]));
+
+        let original = NativeParser::new(grammar);
+        let tuned = NativeParser::from_compiled(Arc::new(compiled));
+        for input in ["abc", "42", "*"] {
+            assert_eq!(original.parse(input), tuned.parse(input));
+        }
+
+        // `word` is now tried first (traces report source positions), and
+        // neither other alternative can start with "a" at end of input
+        let (_, events) = tuned.parse_traced("abc");
+        let tried: Vec<String> = events
+            .iter()
+            .map(|e| e.to_string())
+            .filter(|line| line.contains("of value"))
+            .collect();
+        assert_eq!(tried, ["  alternative 2 of value matched 0..3"]);
+    }
+
+    #[test]
+    fn test_apply_skips_stale_rules() {
+        let grammar = parse_ixml_grammar(GRAMMAR).unwrap();
+        let profile = AlternativeProfile::from_text("value: 0 5\nword: 3\n").unwrap();
+        let compiled = profile.apply(CompiledGrammar::new(grammar));
+        assert_eq!(compiled.alternative_order(0), None);
+        assert_eq!(compiled.alternative_order(2), Some(&[0][..]));
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let grammar = parse_ixml_grammar(GRAMMAR).unwrap();
+        let profile = AlternativeProfile::train(&grammar, &["abc", "7"]);
+        let text = profile.to_text();
+        assert!(text.contains("value: 1 1 0\n"));
+        assert_eq!(AlternativeProfile::from_text(&text).unwrap(), profile);
+        assert!(AlternativeProfile::from_text("value 1 2").is_err());
+    }
+}
@@ -0,0 +1,241 @@
+//! Python bindings for rustixml
+//!
+//! The `python` feature builds a `rustixml` extension module with the same
+//! shape as the WASM API: compile an [`IxmlGrammar`](PyIxmlGrammar) once,
+//! then parse with a [`Parser`](PyParser) to XML strings or nested dicts.
+//! Build it with [maturin](https://www.maturin.rs): `maturin develop --release`.
+//!
+//! ```python
+//! import rustixml
+//!
+//! grammar = rustixml.IxmlGrammar('number: ["0"-"9"]+.')
+//! parser = rustixml.Parser(grammar)
+//! parser.parse("42")          # '<number>42</number>'
+//! parser.parse_to_dict("42")  # {'name': 'number', 'attributes': {}, 'children': ['42']}
+//!
+//! try:
+//!     parser.parse("4x")
+//! except rustixml.ParseError as e:
+//!     print(e.line, e.column, e.expected)  # 1 2 ['["0"-"9"]']
+//! ```
+
+use crate::diagnostics::Diagnostic;
+use crate::input_stream::line_col;
+use crate::{parse_ixml_grammar, CompiledGrammar, NativeParser, XmlNode};
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::sync::Arc;
+
+create_exception!(
+    rustixml,
+    IxmlError,
+    PyValueError,
+    "Base class of the errors rustixml raises"
+);
+create_exception!(
+    rustixml,
+    GrammarError,
+    IxmlError,
+    "An iXML grammar that does not parse or compile"
+);
+create_exception!(
+    rustixml,
+    ParseError,
+    IxmlError,
+    "Input that does not match the grammar"
+);
+
+/// Raise `exception` with `message`, setting `fields` as its attributes
+fn raise<E: pyo3::PyTypeInfo>(
+    message: String,
+    fields: impl FnOnce(&Bound<'_, PyAny>) -> PyResult<()>,
+) -> PyErr {
+    Python::attach(|py| {
+        let error = PyErr::new::<E, _>(message);
+        match fields(error.value(py).as_any()) {
+            Ok(()) => error,
+            Err(e) => e,
+        }
+    })
+}
+
+fn grammar_error(diagnostics: &[Diagnostic], source: &str) -> PyErr {
+    let message = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let first = diagnostics.first();
+    let (line, column) = first
+        .and_then(|diagnostic| diagnostic.span)
+        .map(|span| span.line_col(source))
+        .unzip();
+    raise::<GrammarError>(message, |error| {
+        error.setattr("code", first.map(|diagnostic| diagnostic.code))?;
+        error.setattr("line", line)?;
+        error.setattr("column", column)?;
+        error.setattr(
+            "notes",
+            diagnostics
+                .iter()
+                .flat_map(|diagnostic| diagnostic.notes.clone())
+                .collect::<Vec<_>>(),
+        )
+    })
+}
+
+fn parse_error(error: &crate::ParseError, input: &str) -> PyErr {
+    let inner = match error {
+        crate::ParseError::Located { error, .. } => error,
+        _ => error,
+    };
+    let position = match (inner.farthest_failure(), inner) {
+        (Some(failure), _) => Some(failure.position),
+        (None, crate::ParseError::InstructionLimitExceeded { .. }) => None,
+        (None, _) => Some(inner.position()),
+    };
+    let (line, column) = position.map(|position| line_col(input, position)).unzip();
+    raise::<ParseError>(inner.to_string(), |e| {
+        e.setattr("line", line)?;
+        e.setattr("column", column)?;
+        e.setattr("offset", position)?;
+        e.setattr("expected", error.expected().to_vec())?;
+        e.setattr("rule_stack", error.rule_stack().to_vec())
+    })
+}
+
+/// A compiled iXML grammar
+///
+/// `IxmlGrammar(text)` raises `GrammarError` if `text` is not a valid grammar.
+#[pyclass(name = "IxmlGrammar", module = "rustixml", frozen)]
+pub struct PyIxmlGrammar {
+    compiled: Arc<CompiledGrammar>,
+}
+
+#[pymethods]
+impl PyIxmlGrammar {
+    #[new]
+    fn new(text: &str) -> PyResult<Self> {
+        let grammar = parse_ixml_grammar(text).map_err(|error| {
+            grammar_error(&[Diagnostic::from_grammar_error(&error, text)], text)
+        })?;
+        let compiled = CompiledGrammar::try_new(grammar)
+            .map_err(|error| grammar_error(&Diagnostic::from_grammar_problems(&error), text))?;
+        Ok(PyIxmlGrammar {
+            compiled: Arc::new(compiled),
+        })
+    }
+
+    /// The names of the grammar's rules, in grammar order
+    #[getter]
+    fn rule_names(&self) -> Vec<String> {
+        let grammar = self.compiled.grammar();
+        grammar.rules.iter().map(|rule| rule.name.clone()).collect()
+    }
+
+    /// The number of rules in the grammar
+    #[getter]
+    fn rule_count(&self) -> usize {
+        self.compiled.rule_count()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<IxmlGrammar with {} rules>", self.compiled.rule_count())
+    }
+}
+
+/// Parses input with a compiled grammar
+///
+/// Failed parses raise `ParseError`, whose `line` and `column` (from 1),
+/// `offset` (a character index into the input), `expected` and
+/// `rule_stack` say where and why the input did not match.
+#[pyclass(name = "Parser", module = "rustixml", frozen)]
+pub struct PyParser {
+    parser: NativeParser,
+}
+
+#[pymethods]
+impl PyParser {
+    #[new]
+    fn new(grammar: &PyIxmlGrammar) -> Self {
+        PyParser {
+            parser: NativeParser::from_compiled(grammar.compiled.clone()),
+        }
+    }
+
+    /// Parse `input` to an XML string
+    fn parse(&self, py: Python<'_>, input: &str) -> PyResult<String> {
+        py.detach(|| self.parser.parse(input))
+            .map_err(|error| parse_error(&error, input))
+    }
+
+    /// Parse `input` starting from rule `start` instead of the first rule
+    fn parse_with_start(&self, py: Python<'_>, input: &str, start: &str) -> PyResult<String> {
+        py.detach(|| self.parser.parse_with_start(input, start))
+            .map_err(|error| parse_error(&error, input))
+    }
+
+    /// Parse `input` to nested dicts
+    ///
+    /// Elements are `{"name": str, "attributes": dict, "children": list}` and
+    /// text is a `str`.
+    fn parse_to_dict<'py>(&self, py: Python<'py>, input: &str) -> PyResult<Bound<'py, PyAny>> {
+        let tree = py
+            .detach(|| self.parser.parse_to_tree(input))
+            .map_err(|error| parse_error(&error, input))?;
+        tree_to_dict(py, &tree)
+    }
+}
+
+/// `tree` as nested dicts, built without recursion so deep trees cannot
+/// overflow the stack
+fn tree_to_dict<'py>(py: Python<'py>, tree: &XmlNode) -> PyResult<Bound<'py, PyAny>> {
+    let root = PyList::empty(py);
+    let mut stack = vec![(tree, root.clone())];
+    while let Some((node, parent)) = stack.pop() {
+        match node {
+            XmlNode::Element {
+                name,
+                attributes,
+                children,
+            } => {
+                let attrs = PyDict::new(py);
+                for (name, value) in attributes {
+                    attrs.set_item(name, value)?;
+                }
+                let list = PyList::empty(py);
+                let element = PyDict::new(py);
+                element.set_item("name", name)?;
+                element.set_item("attributes", attrs)?;
+                element.set_item("children", &list)?;
+                parent.append(element)?;
+                for child in children.iter().rev() {
+                    if !matches!(child, XmlNode::Attribute { .. }) {
+                        stack.push((child, list.clone()));
+                    }
+                }
+            }
+            XmlNode::Text(text) => parent.append(text)?,
+            XmlNode::Attribute { name, value } => {
+                let attribute = PyDict::new(py);
+                attribute.set_item(name, value)?;
+                parent.append(attribute)?;
+            }
+        }
+    }
+    root.get_item(0)
+}
+
+#[pymodule]
+fn rustixml(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_class::<PyIxmlGrammar>()?;
+    m.add_class::<PyParser>()?;
+    m.add("IxmlError", py.get_type::<IxmlError>())?;
+    m.add("GrammarError", py.get_type::<GrammarError>())?;
+    m.add("ParseError", py.get_type::<ParseError>())?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
@@ -0,0 +1,245 @@
+//! Picking values out of result trees
+//!
+//! Parse results are usually read rather than serialized: a date's `year`, the
+//! fields of each CSV row. [`XmlNode::select`] takes a simple slash-separated
+//! path, and [`XmlNode::descendants`] and [`XmlNode::children_named`] walk the
+//! tree directly, so no XML library is needed for either.
+//!
+//! A path is a list of steps, the first of which matches the node itself:
+//!
+//! - `name` matches elements called `name`, `*` matches any element
+//! - `@name` matches an attribute, `@*` any attribute (only as the last step)
+//! - an empty step, as in `csv//field`, lets the next step match at any depth
+
+use crate::xml_node::XmlNode;
+use std::borrow::Cow;
+
+/// A node or attribute matched by [`XmlNode::select`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selected<'a> {
+    Node(&'a XmlNode),
+    Attribute { name: &'a str, value: &'a str },
+}
+
+impl<'a> Selected<'a> {
+    /// The matched node, if it is not an attribute
+    pub fn node(&self) -> Option<&'a XmlNode> {
+        match self {
+            Selected::Node(node) => Some(node),
+            Selected::Attribute { .. } => None,
+        }
+    }
+
+    /// The text of a node or the value of an attribute
+    pub fn text(&self) -> Cow<'a, str> {
+        match self {
+            Selected::Node(node) => Cow::Owned(node.text_content()),
+            Selected::Attribute { value, .. } => Cow::Borrowed(value),
+        }
+    }
+}
+
+/// Iterator over the descendants of a node in document order
+///
+/// Created by [`XmlNode::descendants`].
+#[derive(Debug, Clone)]
+pub struct Descendants<'a> {
+    stack: Vec<&'a XmlNode>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = &'a XmlNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children().iter().rev());
+        Some(node)
+    }
+}
+
+impl XmlNode {
+    /// An element's name
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            XmlNode::Element { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    /// An element's children (nothing for other node kinds)
+    pub fn children(&self) -> &[XmlNode] {
+        match self {
+            XmlNode::Element { children, .. } => children,
+            _ => &[],
+        }
+    }
+
+    /// The child elements called `name`
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlNode> {
+        self.children()
+            .iter()
+            .filter(move |child| child.name() == Some(name))
+    }
+
+    /// The first child element called `name`
+    pub fn child_named(&self, name: &str) -> Option<&XmlNode> {
+        self.children()
+            .iter()
+            .find(|child| child.name() == Some(name))
+    }
+
+    /// All nodes below this one, depth first in document order
+    pub fn descendants(&self) -> Descendants<'_> {
+        Descendants {
+            stack: self.children().iter().rev().collect(),
+        }
+    }
+
+    /// The nodes and attributes matching a path such as `"csv/row/field"` or
+    /// `"date/@year"`, in document order
+    ///
+    /// See the [module documentation](crate::query) for the path syntax.
+    /// Paths with an attribute step before the last step, or ending in `/`,
+    /// match nothing.
+    ///
+    /// ```
+    /// use rustixml::fixtures::{CSV, DATE};
+    ///
+    /// let date = DATE.parser().parse_to_tree(DATE.input).unwrap();
+    /// let year = date.select("date/@year");
+    /// assert_eq!(year[0].text(), "2024");
+    ///
+    /// let csv = CSV.parser().parse_to_tree("a,b\nc,d").unwrap();
+    /// let fields: Vec<_> = csv.select("csv/*/field").iter().map(|f| f.text()).collect();
+    /// assert_eq!(fields, ["a", "b", "c", "d"]);
+    /// assert_eq!(csv.select("csv//field").len(), 4);
+    /// ```
+    pub fn select<'a>(&'a self, path: &str) -> Vec<Selected<'a>> {
+        let steps: Vec<&str> = path.split('/').collect();
+        let mut nodes = vec![self];
+        // The first step tests the node itself, later ones its children
+        let mut axis = Axis::Itself;
+        for (i, step) in steps.iter().enumerate() {
+            let last = i + 1 == steps.len();
+            if step.is_empty() {
+                if last {
+                    return Vec::new();
+                }
+                axis = match axis {
+                    Axis::Itself => Axis::ItselfOrDescendants,
+                    _ => Axis::Descendants,
+                };
+                continue;
+            }
+            if let Some(wanted) = step.strip_prefix('@') {
+                if !last {
+                    return Vec::new();
+                }
+                // Attributes belong to the nodes matched so far, not their children
+                let axis = match axis {
+                    Axis::Children => Axis::Itself,
+                    axis => axis,
+                };
+                return nodes
+                    .into_iter()
+                    .flat_map(|node| axis.nodes(node))
+                    .flat_map(|node| match node {
+                        XmlNode::Element { attributes, .. } => attributes.as_slice(),
+                        _ => &[],
+                    })
+                    .filter(|(name, _)| wanted == "*" || name == wanted)
+                    .map(|(name, value)| Selected::Attribute { name, value })
+                    .collect();
+            }
+            nodes = nodes
+                .into_iter()
+                .flat_map(|node| axis.nodes(node))
+                .filter(|node| match node.name() {
+                    Some(name) => *step == "*" || name == *step,
+                    None => false,
+                })
+                .collect();
+            axis = Axis::Children;
+        }
+        nodes.into_iter().map(Selected::Node).collect()
+    }
+}
+
+/// Which nodes a path step tests, relative to the nodes matched so far
+#[derive(Clone, Copy)]
+enum Axis {
+    Itself,
+    Children,
+    Descendants,
+    ItselfOrDescendants,
+}
+
+impl Axis {
+    fn nodes(self, node: &XmlNode) -> Vec<&XmlNode> {
+        match self {
+            Axis::Itself => vec![node],
+            Axis::Children => node.children().iter().collect(),
+            Axis::Descendants => node.descendants().collect(),
+            Axis::ItselfOrDescendants => std::iter::once(node).chain(node.descendants()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> XmlNode {
+        XmlNode::element("root")
+            .with_child(
+                XmlNode::element("item")
+                    .with_attribute("name", "a")
+                    .with_child(XmlNode::text("x")),
+            )
+            .with_child(XmlNode::text(" "))
+            .with_child(
+                XmlNode::element("group").with_child(
+                    XmlNode::element("item")
+                        .with_attribute("name", "b")
+                        .with_attribute("id", "2"),
+                ),
+            )
+    }
+
+    fn texts(selected: Vec<Selected>) -> Vec<String> {
+        selected.iter().map(|s| s.text().into_owned()).collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let tree = tree();
+        assert_eq!(texts(tree.select("root/item/@name")), ["a"]);
+        assert_eq!(texts(tree.select("root//item/@name")), ["a", "b"]);
+        assert_eq!(texts(tree.select("root/*/*/@*")), ["b", "2"]);
+        assert_eq!(texts(tree.select("root/item")), ["x"]);
+        assert_eq!(tree.select("root")[0].node(), Some(&tree));
+        assert_eq!(tree.select("*").len(), 1);
+
+        assert!(tree.select("item").is_empty());
+        assert!(tree.select("root/@name/item").is_empty());
+        assert!(tree.select("root//").is_empty());
+        assert_eq!(texts(tree.select("//item/@name")), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_navigation() {
+        let tree = tree();
+        let names: Vec<_> = tree.descendants().filter_map(XmlNode::name).collect();
+        assert_eq!(names, ["item", "group", "item"]);
+        assert_eq!(tree.descendants().count(), 5);
+
+        assert_eq!(tree.children_named("item").count(), 1);
+        let group = tree.child_named("group").unwrap();
+        assert_eq!(
+            group.child_named("item").unwrap().attribute("id"),
+            Some("2")
+        );
+        assert!(tree.child_named("missing").is_none());
+        assert!(XmlNode::text("x").children().is_empty());
+    }
+}
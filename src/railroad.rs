@@ -0,0 +1,349 @@
+//! Railroad (syntax) diagrams of grammars as SVG
+//!
+//! Each rule is drawn as a track from left to right: nonterminals are square
+//! boxes, terminals (literals, character classes, insertions) rounded ones,
+//! alternatives branch below the first one, and repetitions loop back under
+//! the repeated factor, through the separator for `++` and `**`.
+//! [`railroad_svg`] stacks the diagrams of all rules into one document, for
+//! grammar documentation.
+
+use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Repetition, Rule, Sequence};
+use crate::xml_node::XmlNode;
+
+/// Width of a character of the monospace labels
+const CHAR_WIDTH: i32 = 9;
+/// Space between a label and the sides of its box
+const PADDING: i32 = 10;
+/// Half the height of a box, from the track to its top or bottom
+const HALF_BOX: i32 = 12;
+/// Track between the items of a sequence
+const GAP: i32 = 10;
+/// Room on either side of a branch or loop for the track to turn
+const RAIL: i32 = 20;
+/// Space between stacked branches
+const ROW_GAP: i32 = 12;
+/// Space around each rule's diagram
+const MARGIN: i32 = 20;
+/// Height of a rule's name above its diagram
+const TITLE_HEIGHT: i32 = 24;
+
+const STYLE: &str = "path{fill:none;stroke:#333;stroke-width:1.5}\
+                     rect{fill:#fff8dc;stroke:#333;stroke-width:1.5}\
+                     rect.nonterminal{fill:#e0ecff}\
+                     text{font:14px monospace;text-anchor:middle}\
+                     text.rule{font-weight:bold;text-anchor:start}";
+
+/// Railroad diagrams of every rule of `grammar`, one below the other
+///
+/// ```
+/// use rustixml::parse_ixml_grammar;
+/// use rustixml::railroad::railroad_svg;
+///
+/// let grammar = parse_ixml_grammar(r#"list: item++",". item: ["a"-"z"]+."#).unwrap();
+/// let svg = railroad_svg(&grammar).to_xml();
+/// assert!(svg.starts_with("<svg xmlns='http://www.w3.org/2000/svg'"));
+/// assert!(svg.contains("<text class='rule' x='20' y='36'>list</text>"));
+/// assert!(svg.contains("<rect class='nonterminal'"));
+/// assert!(svg.contains(r#">","</text>"#));
+/// ```
+pub fn railroad_svg(grammar: &IxmlGrammar) -> XmlNode {
+    let mut shapes = Vec::new();
+    let mut width = 0;
+    let mut top = 0;
+    for rule in &grammar.rules {
+        let (rule_width, height) = draw_rule(rule, top, &mut shapes);
+        width = width.max(rule_width);
+        top += height;
+    }
+
+    let mut svg = XmlNode::element("svg")
+        .attr("xmlns", "http://www.w3.org/2000/svg")
+        .attr("width", width.to_string())
+        .attr("height", top.to_string())
+        .attr("viewBox", format!("0 0 {} {}", width, top))
+        .child(XmlNode::element("style").child(XmlNode::text(STYLE)));
+    for shape in shapes {
+        svg.push_child(shape);
+    }
+    svg
+}
+
+/// Draw `rule` with its top at `top`; returns the width and height it took
+fn draw_rule(rule: &Rule, top: i32, shapes: &mut Vec<XmlNode>) -> (i32, i32) {
+    let title = format!("{}{}", rule.mark, rule.name);
+    shapes.push(
+        XmlNode::element("text")
+            .attr("class", "rule")
+            .attr("x", MARGIN.to_string())
+            .attr("y", (top + MARGIN + 16).to_string())
+            .child(XmlNode::text(title.clone())),
+    );
+
+    let diagram = Diagram::alternatives(&rule.alternatives);
+    let y = top + MARGIN + TITLE_HEIGHT + diagram.up();
+    let end = MARGIN + RAIL + diagram.width();
+    // Bars mark where the rule starts and ends
+    shapes.push(path(format!("M{} {}v16m0 -8h{}", MARGIN, y - 8, RAIL)));
+    diagram.draw(MARGIN + RAIL, y, shapes);
+    shapes.push(path(format!("M{} {}h{}m0 -8v16", end, y, RAIL)));
+
+    let width = (end + RAIL + MARGIN).max(MARGIN * 2 + text_width(&title));
+    let height = MARGIN * 2 + TITLE_HEIGHT + diagram.up() + diagram.down();
+    (width, height)
+}
+
+fn text_width(text: &str) -> i32 {
+    text.chars().count() as i32 * CHAR_WIDTH
+}
+
+fn path(d: String) -> XmlNode {
+    XmlNode::element("path").attr("d", d)
+}
+
+/// The layout of a part of a rule, with the track entering on the left and
+/// leaving on the right at the same height
+enum Diagram {
+    /// A box with rounded corners
+    Terminal(String),
+    /// A square box
+    Nonterminal(String),
+    /// Just track
+    Skip,
+    Sequence(Vec<Diagram>),
+    /// The first branch on the track, the others below it
+    Choice(Vec<Diagram>),
+    /// The first part, then back below it through the second part
+    Loop(Box<Diagram>, Box<Diagram>),
+}
+
+impl Diagram {
+    fn alternatives(alternatives: &Alternatives) -> Self {
+        let mut branches: Vec<Diagram> = alternatives.alts.iter().map(Self::sequence).collect();
+        if branches.len() == 1 {
+            branches.remove(0)
+        } else {
+            Diagram::Choice(branches)
+        }
+    }
+
+    fn sequence(sequence: &Sequence) -> Self {
+        let mut items: Vec<Diagram> = sequence.factors.iter().map(Self::factor).collect();
+        match items.len() {
+            0 => Diagram::Skip,
+            1 => items.remove(0),
+            _ => Diagram::Sequence(items),
+        }
+    }
+
+    fn factor(factor: &Factor) -> Self {
+        let base = match &factor.base {
+            BaseFactor::Nonterminal { name, mark } => {
+                Diagram::Nonterminal(format!("{}{}", mark, name))
+            }
+            BaseFactor::Group { alternatives } => Self::alternatives(alternatives),
+            base => Diagram::Terminal(base.to_string()),
+        };
+        let optional = |diagram| Diagram::Choice(vec![Diagram::Skip, diagram]);
+        let repeated = |diagram, separator| Diagram::Loop(Box::new(diagram), Box::new(separator));
+        match &factor.repetition {
+            Repetition::None => base,
+            Repetition::Optional => optional(base),
+            Repetition::OneOrMore => repeated(base, Diagram::Skip),
+            Repetition::ZeroOrMore => optional(repeated(base, Diagram::Skip)),
+            Repetition::SeparatedOneOrMore(sep) => repeated(base, Self::sequence(sep)),
+            Repetition::SeparatedZeroOrMore(sep) => optional(repeated(base, Self::sequence(sep))),
+        }
+    }
+
+    fn width(&self) -> i32 {
+        match self {
+            Diagram::Terminal(label) | Diagram::Nonterminal(label) => {
+                text_width(label) + PADDING * 2
+            }
+            Diagram::Skip => 0,
+            Diagram::Sequence(items) => {
+                items.iter().map(Self::width).sum::<i32>() + GAP * (items.len() as i32 - 1)
+            }
+            Diagram::Choice(branches) => {
+                branches.iter().map(Self::width).max().unwrap_or(0) + RAIL * 2
+            }
+            Diagram::Loop(item, back) => item.width().max(back.width()) + RAIL * 2,
+        }
+    }
+
+    /// Height above the track
+    fn up(&self) -> i32 {
+        match self {
+            Diagram::Terminal(_) | Diagram::Nonterminal(_) => HALF_BOX,
+            Diagram::Skip => 0,
+            Diagram::Sequence(items) => items.iter().map(Self::up).max().unwrap_or(0),
+            Diagram::Choice(branches) => branches[0].up(),
+            Diagram::Loop(item, _) => item.up(),
+        }
+    }
+
+    /// Height below the track
+    fn down(&self) -> i32 {
+        match self {
+            Diagram::Terminal(_) | Diagram::Nonterminal(_) => HALF_BOX,
+            Diagram::Skip => 0,
+            Diagram::Sequence(items) => items.iter().map(Self::down).max().unwrap_or(0),
+            Diagram::Choice(branches) => {
+                branches[0].down()
+                    + branches[1..]
+                        .iter()
+                        .map(|branch| ROW_GAP + branch.up() + branch.down())
+                        .sum::<i32>()
+            }
+            Diagram::Loop(item, back) => item.down() + ROW_GAP + back.up() + back.down(),
+        }
+    }
+
+    /// Draw with the track entering at (`x`, `y`)
+    fn draw(&self, x: i32, y: i32, shapes: &mut Vec<XmlNode>) {
+        match self {
+            Diagram::Terminal(label) | Diagram::Nonterminal(label) => {
+                let mut rect = XmlNode::element("rect");
+                if let Diagram::Nonterminal(_) = self {
+                    rect = rect.attr("class", "nonterminal");
+                } else {
+                    rect = rect.attr("rx", PADDING.to_string());
+                }
+                shapes.push(
+                    rect.attr("x", x.to_string())
+                        .attr("y", (y - HALF_BOX).to_string())
+                        .attr("width", self.width().to_string())
+                        .attr("height", (HALF_BOX * 2).to_string()),
+                );
+                shapes.push(
+                    XmlNode::element("text")
+                        .attr("x", (x + self.width() / 2).to_string())
+                        .attr("y", (y + 5).to_string())
+                        .child(XmlNode::text(label.clone())),
+                );
+            }
+            Diagram::Skip => {}
+            Diagram::Sequence(items) => {
+                let mut x = x;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        shapes.push(path(format!("M{} {}h{}", x, y, GAP)));
+                        x += GAP;
+                    }
+                    item.draw(x, y, shapes);
+                    x += item.width();
+                }
+            }
+            Diagram::Choice(branches) => {
+                let (left, right) = (x + RAIL, x + self.width() - RAIL);
+                let mut branch_y = y;
+                for (i, branch) in branches.iter().enumerate() {
+                    if i > 0 {
+                        branch_y += branches[i - 1].down() + ROW_GAP + branch.up();
+                    }
+                    // Turn off the track, run past the branch, and turn back
+                    shapes.push(path(format!(
+                        "M{} {}h{}V{}h{}",
+                        x,
+                        y,
+                        RAIL / 2,
+                        branch_y,
+                        RAIL / 2
+                    )));
+                    branch.draw(left, branch_y, shapes);
+                    shapes.push(path(format!(
+                        "M{} {}H{}h{}V{}h{}",
+                        left + branch.width(),
+                        branch_y,
+                        right,
+                        RAIL / 2,
+                        y,
+                        RAIL / 2
+                    )));
+                }
+            }
+            Diagram::Loop(item, back) => {
+                let (left, right) = (x + RAIL, x + self.width() - RAIL);
+                let back_y = y + item.down() + ROW_GAP + back.up();
+                shapes.push(path(format!("M{} {}h{}", x, y, RAIL)));
+                item.draw(left, y, shapes);
+                shapes.push(path(format!(
+                    "M{} {}H{}",
+                    left + item.width(),
+                    y,
+                    right + RAIL
+                )));
+                // The way back runs right to left, under the item
+                shapes.push(path(format!(
+                    "M{} {}V{}H{}",
+                    right + RAIL / 2,
+                    y,
+                    back_y,
+                    left + back.width()
+                )));
+                back.draw(left, back_y, shapes);
+                shapes.push(path(format!("M{} {}H{}V{}", left, back_y, x + RAIL / 2, y)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar_ast::parse_ixml_grammar;
+
+    fn diagram(factor: &str) -> Diagram {
+        let grammar = parse_ixml_grammar(&format!("r: {}. a: 'a'. b: 'b'.", factor)).unwrap();
+        Diagram::alternatives(&grammar.rules[0].alternatives)
+    }
+
+    #[test]
+    fn test_layout() {
+        // A box is its label plus padding wide, and half a box above and below
+        let a = diagram("a");
+        assert_eq!((a.width(), a.up(), a.down()), (29, 12, 12));
+        let abc = diagram("'abc'");
+        assert_eq!(abc.width(), 5 * 9 + 20);
+
+        let sequence = diagram("a, b");
+        assert_eq!(
+            (sequence.width(), sequence.up(), sequence.down()),
+            (68, 12, 12)
+        );
+
+        // Branches stack below the first one
+        let choice = diagram("a; b; 'abc'");
+        assert_eq!(choice.width(), 65 + 40);
+        assert_eq!((choice.up(), choice.down()), (12, 12 + 2 * (12 + 24)));
+
+        // An optional factor is a choice with an empty first branch
+        let optional = diagram("a?");
+        assert_eq!((optional.up(), optional.down()), (0, 12 + 24));
+
+        // The way back of a loop runs through the separator
+        let repeated = diagram("a+");
+        assert_eq!((repeated.width(), repeated.down()), (69, 24));
+        let separated = diagram("a++'abc'");
+        assert_eq!((separated.width(), separated.down()), (105, 12 + 12 + 24));
+    }
+
+    #[test]
+    fn test_railroad_svg() {
+        let grammar = parse_ixml_grammar("s: a, 'x'. -a: ['a'-'z']*.").unwrap();
+        let svg = railroad_svg(&grammar);
+        let text: Vec<String> = svg
+            .children()
+            .iter()
+            .filter(|node| node.name() == Some("text"))
+            .map(XmlNode::text_content)
+            .collect();
+        assert_eq!(text, ["s", "a", "\"x\"", "-a", "['a'-'z']"]);
+        let rects = svg
+            .children()
+            .iter()
+            .filter(|node| node.name() == Some("rect"));
+        assert_eq!(rects.count(), 3);
+        assert_eq!(svg.attribute("viewBox"), Some("0 0 261 200"));
+    }
+}
@@ -0,0 +1,382 @@
+//! Compare rustixml's output with a reference iXML processor
+//!
+//! For conformance debugging: [`compare`] runs the same grammar and input
+//! through rustixml and through another implementation (CoffeePot, Markup
+//! Blitz, ...) and lists where the two documents differ, element by element.
+//! Attribute order, namespace declarations and indentation are ignored.
+
+use crate::grammar_ast::parse_ixml_grammar;
+use crate::native_parser::NativeParser;
+use crate::xml_node::XmlNode;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Environment variable holding the default reference command
+pub const REFERENCE_ENV: &str = "RUSTIXML_REFERENCE";
+
+/// Placeholder for the grammar file in a reference command
+pub const GRAMMAR_PLACEHOLDER: &str = "{grammar}";
+
+/// Placeholder for the input file in a reference command
+pub const INPUT_PLACEHOLDER: &str = "{input}";
+
+/// An external iXML processor, run as a command
+///
+/// The command is split on whitespace; [`GRAMMAR_PLACEHOLDER`] and
+/// [`INPUT_PLACEHOLDER`] are replaced by the paths of temporary files holding
+/// the grammar and the input, and the document is read from standard output.
+///
+/// ```
+/// use rustixml::reference::ReferenceProcessor;
+///
+/// let coffeepot = ReferenceProcessor::new("java -jar coffeepot.jar -g:{grammar} -i:{input}");
+/// assert!(coffeepot.is_ok());
+/// assert_eq!(
+///     ReferenceProcessor::new("coffeepot {grammar}").unwrap_err(),
+///     "Reference command needs both {grammar} and {input} placeholders"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceProcessor {
+    program: String,
+    args: Vec<String>,
+}
+
+impl ReferenceProcessor {
+    /// Configure a processor from a command line with placeholders
+    pub fn new(command: &str) -> Result<Self, String> {
+        let mut words = command.split_whitespace().map(str::to_string);
+        let program = words.next().ok_or("Reference command is empty")?;
+        let args: Vec<String> = words.collect();
+        let mentions = |placeholder| args.iter().any(|arg| arg.contains(placeholder));
+        if !mentions(GRAMMAR_PLACEHOLDER) || !mentions(INPUT_PLACEHOLDER) {
+            return Err(format!(
+                "Reference command needs both {} and {} placeholders",
+                GRAMMAR_PLACEHOLDER, INPUT_PLACEHOLDER
+            ));
+        }
+        Ok(ReferenceProcessor { program, args })
+    }
+
+    /// Configure a processor from the [`REFERENCE_ENV`] environment variable
+    pub fn from_env() -> Result<Self, String> {
+        let command = std::env::var(REFERENCE_ENV).map_err(|_| {
+            format!(
+                "No reference processor configured; set {} to a command such as \
+                 \"java -jar coffeepot.jar -g:{} {}\"",
+                REFERENCE_ENV, GRAMMAR_PLACEHOLDER, INPUT_PLACEHOLDER
+            )
+        })?;
+        Self::new(&command)
+    }
+
+    /// Run the processor, returning the document it writes
+    pub fn run(&self, grammar: &str, input: &str) -> Result<String, String> {
+        let grammar_file = TempFile::new("ixml", grammar)?;
+        let input_file = TempFile::new("txt", input)?;
+        let args = self.args.iter().map(|arg| {
+            arg.replace(GRAMMAR_PLACEHOLDER, &grammar_file.path())
+                .replace(INPUT_PLACEHOLDER, &input_file.path())
+        });
+
+        let output = Command::new(&self.program)
+            .args(args)
+            .output()
+            .map_err(|e| {
+                format!(
+                    "Failed to run reference processor '{}': {}",
+                    self.program, e
+                )
+            })?;
+        if !output.status.success() {
+            return Err(format!(
+                "Reference processor exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|e| format!("Reference processor output is not UTF-8: {}", e))
+    }
+}
+
+/// A file in the temporary directory, deleted when dropped
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(extension: &str, contents: &str) -> Result<Self, String> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "rustixml-{}-{}.{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            extension
+        ));
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(TempFile(path))
+    }
+
+    fn path(&self) -> String {
+        self.0.display().to_string()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// One place where the reference document and rustixml's differ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// Location in the reference document, e.g. `/date/month[1]`
+    pub path: String,
+    /// What differs, e.g. `reference has <day>, rustixml has <month>`
+    pub message: String,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Parse `input` with rustixml and with `reference`, and diff the documents
+///
+/// Both documents reporting a failed parse counts as agreement, since
+/// processors word their error reports differently.
+pub fn compare(
+    reference: &ReferenceProcessor,
+    grammar: &str,
+    input: &str,
+) -> Result<Vec<Difference>, String> {
+    let parser = NativeParser::new(
+        parse_ixml_grammar(grammar).map_err(|e| format!("Grammar parse error: {}", e))?,
+    );
+    let ours = parser
+        .parse_outcome(input)
+        .to_xml_with(parser.fixed_attributes());
+    let ours = XmlNode::from_xml(&ours)?;
+    let theirs = XmlNode::from_xml(&reference.run(grammar, input)?)
+        .map_err(|e| format!("Reference output is not XML: {}", e))?;
+
+    let failed = |node: &XmlNode| {
+        node.ixml_state()
+            .is_some_and(|state| state.split_whitespace().any(|s| s == "failed"))
+    };
+    if failed(&ours) && failed(&theirs) {
+        return Ok(Vec::new());
+    }
+    Ok(diff(&theirs, &ours))
+}
+
+/// Structurally diff a reference document against rustixml's
+///
+/// ```
+/// use rustixml::reference::diff;
+/// use rustixml::XmlNode;
+///
+/// let reference = XmlNode::from_xml("<date>\n  <day>15</day>\n  <month>3</month>\n</date>").unwrap();
+/// let ours = XmlNode::from_xml("<date><day>15</day><month>03</month></date>").unwrap();
+/// let differences = diff(&reference, &ours);
+/// assert_eq!(differences.len(), 1);
+/// assert_eq!(
+///     differences[0].to_string(),
+///     r#"/date/month[1]/text()[1]: reference has "3", rustixml has "03""#
+/// );
+/// ```
+pub fn diff(reference: &XmlNode, ours: &XmlNode) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    let root = match reference {
+        XmlNode::Element { name, .. } => format!("/{}", name),
+        _ => "/".to_string(),
+    };
+    let mut stack = vec![(root, reference, ours)];
+
+    while let Some((path, theirs, ours)) = stack.pop() {
+        let mut differ = |message: String| {
+            differences.push(Difference {
+                path: path.clone(),
+                message,
+            })
+        };
+        match (theirs, ours) {
+            (
+                XmlNode::Element {
+                    name: their_name,
+                    attributes: their_attributes,
+                    children: their_children,
+                },
+                XmlNode::Element {
+                    name: our_name,
+                    attributes: our_attributes,
+                    children: our_children,
+                },
+            ) => {
+                if their_name != our_name {
+                    differ(format!(
+                        "reference has {}, rustixml has {}",
+                        describe(theirs),
+                        describe(ours)
+                    ));
+                    continue;
+                }
+
+                let their_attributes = significant_attributes(their_attributes);
+                let our_attributes = significant_attributes(our_attributes);
+                for (name, value) in &their_attributes {
+                    match our_attributes.get(name) {
+                        Some(ours) if ours == value => {}
+                        Some(ours) => differ(format!(
+                            "attribute {}: reference has {:?}, rustixml has {:?}",
+                            name, value, ours
+                        )),
+                        None => differ(format!("attribute {} missing from rustixml", name)),
+                    }
+                }
+                for name in our_attributes.keys() {
+                    if !their_attributes.contains_key(name) {
+                        differ(format!("attribute {} missing from reference", name));
+                    }
+                }
+
+                let their_children = significant_children(their_children);
+                let our_children = significant_children(our_children);
+                if their_children.len() != our_children.len() {
+                    differ(format!(
+                        "reference has {} children, rustixml has {}",
+                        their_children.len(),
+                        our_children.len()
+                    ));
+                }
+
+                // Compare the children both sides have, first child on top
+                let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+                let pairs: Vec<_> = their_children
+                    .iter()
+                    .zip(&our_children)
+                    .map(|(theirs, ours)| {
+                        let step = match theirs {
+                            XmlNode::Element { name, .. } => name.as_str(),
+                            _ => "text()",
+                        };
+                        let index = seen.entry(step.to_string()).or_default();
+                        *index += 1;
+                        (format!("{}/{}[{}]", path, step, index), *theirs, *ours)
+                    })
+                    .collect();
+                stack.extend(pairs.into_iter().rev());
+            }
+            (XmlNode::Text(their_text), XmlNode::Text(our_text)) => {
+                if their_text != our_text {
+                    differ(format!(
+                        "reference has {:?}, rustixml has {:?}",
+                        their_text, our_text
+                    ));
+                }
+            }
+            _ => differ(format!(
+                "reference has {}, rustixml has {}",
+                describe(theirs),
+                describe(ours)
+            )),
+        }
+    }
+
+    differences
+}
+
+/// Attributes other than namespace declarations, by name
+fn significant_attributes(attributes: &[(String, String)]) -> BTreeMap<&str, &str> {
+    attributes
+        .iter()
+        .filter(|(name, _)| name != "xmlns" && !name.starts_with("xmlns:"))
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect()
+}
+
+/// Children without the whitespace an indenting serializer adds between elements
+fn significant_children(children: &[XmlNode]) -> Vec<&XmlNode> {
+    let has_elements = children
+        .iter()
+        .any(|child| matches!(child, XmlNode::Element { .. }));
+    children
+        .iter()
+        .filter(|child| {
+            !(has_elements && matches!(child, XmlNode::Text(text) if text.trim().is_empty()))
+        })
+        .collect()
+}
+
+fn describe(node: &XmlNode) -> String {
+    match node {
+        XmlNode::Element { name, .. } => format!("<{}>", name),
+        XmlNode::Text(text) => format!("text {:?}", text),
+        XmlNode::Attribute { name, .. } => format!("attribute {}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xml(text: &str) -> XmlNode {
+        XmlNode::from_xml(text).unwrap()
+    }
+
+    #[test]
+    fn test_diff_ignores_formatting() {
+        let reference = xml(
+            "<?xml version='1.0'?>\n<a y='2' x=\"1\" xmlns:ixml='http://invisiblexml.org/NS'>\n  <b> </b>\n  <c/>\n</a>",
+        );
+        assert_eq!(
+            diff(&reference, &xml("<a x='1' y='2'><b> </b><c></c></a>")),
+            []
+        );
+    }
+
+    #[test]
+    fn test_diff_reports_each_difference() {
+        let reference = xml("<a x='1' y='2'><b>t</b><b/><c/><d/></a>");
+        let ours = xml("<a x='one' z='3'><b>u</b><b>v</b><e/></a>");
+        let found: Vec<String> = diff(&reference, &ours)
+            .iter()
+            .map(Difference::to_string)
+            .collect();
+        assert_eq!(
+            found,
+            [
+                r#"/a: attribute x: reference has "1", rustixml has "one""#,
+                "/a: attribute y missing from rustixml",
+                "/a: attribute z missing from reference",
+                "/a: reference has 4 children, rustixml has 3",
+                r#"/a/b[1]/text()[1]: reference has "t", rustixml has "u""#,
+                "/a/b[2]: reference has 0 children, rustixml has 1",
+                "/a/c[1]: reference has <c>, rustixml has <e>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reference_command() {
+        assert_eq!(
+            ReferenceProcessor::new("  ").unwrap_err(),
+            "Reference command is empty"
+        );
+        let processor = ReferenceProcessor::new("ixml -g:{grammar} {input}").unwrap();
+        assert_eq!(processor.program, "ixml");
+        assert_eq!(processor.args, ["-g:{grammar}", "{input}"]);
+
+        let missing = ReferenceProcessor::new("no-such-ixml-processor {grammar} {input}").unwrap();
+        assert!(missing
+            .run("s: 'a'.", "a")
+            .unwrap_err()
+            .starts_with("Failed to run reference processor 'no-such-ixml-processor'"));
+    }
+}
@@ -0,0 +1,552 @@
+//! Hints for regex syntax written in iXML grammars
+//!
+//! New users often reach for regular-expression syntax (`[a-z]+`, `\d`,
+//! `(?: )`, `.*`). Some of it fails to tokenize, some of it parses as
+//! something else entirely (`{2}` is a comment). This module scans grammar
+//! source for those idioms so grammar errors can explain the iXML equivalent.
+//! [`regex_to_factors`] goes further and translates simple regexes outright.
+
+use crate::ast::{Alternatives, BaseFactor, Factor, Mark, Repetition, Sequence};
+use crate::charclass::parse_regex_class;
+use std::fmt;
+
+/// A regex idiom found in grammar source, with the iXML way to write it
+///
+/// This is the diagnostic attached (as `hint:` lines) to grammar errors.
+///
+/// ```
+/// use rustixml::regex_hints::regex_hints;
+///
+/// let hints = regex_hints(r"number: \d+.");
+/// assert_eq!(hints.len(), 1);
+/// assert_eq!((hints[0].line, hints[0].column), (1, 9));
+/// assert_eq!(hints[0].snippet, r"\d");
+///
+/// // The fixture grammars are valid iXML, so they produce no hints
+/// assert!(rustixml::fixtures::FIXTURES
+///     .iter()
+///     .all(|f| regex_hints(f.grammar).is_empty()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexHint {
+    /// 1-based line of the snippet
+    pub line: usize,
+    /// 1-based column of the snippet
+    pub column: usize,
+    /// The regex-looking source text
+    pub snippet: String,
+    /// What to write instead
+    pub suggestion: String,
+}
+
+impl fmt::Display for RegexHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: `{}` looks like regex syntax; {}",
+            self.line, self.column, self.snippet, self.suggestion
+        )
+    }
+}
+
+/// Scan grammar source for regex idioms
+///
+/// String literals and comments are skipped, except comments that look like
+/// `{n}` / `{n,m}` repetition counts.
+pub fn regex_hints(source: &str) -> Vec<RegexHint> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut hints = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let hint = match chars[i] {
+            '"' | '\'' => {
+                i = skip_quoted(&chars, i);
+                continue;
+            }
+            '{' => {
+                let end = skip_comment(&chars, i);
+                let body: String = chars[i + 1..end.saturating_sub(1).max(i + 1)]
+                    .iter()
+                    .collect();
+                i = end;
+                if is_repetition_count(&body) {
+                    Some((
+                        format!("{{{}}}", body),
+                        "braces are comments in iXML, not repetition counts; \
+                         repeat the factor explicitly, e.g. `d, d, d?` for `d{2,3}`"
+                            .to_string(),
+                    ))
+                } else {
+                    None
+                }
+            }
+            '[' => {
+                let end = skip_class(&chars, i);
+                let body: String = chars[i + 1..end.saturating_sub(1).max(i + 1)]
+                    .iter()
+                    .collect();
+                i = end;
+                class_hint(&body).map(|suggestion| (format!("[{}]", body), suggestion))
+            }
+            '\\' => {
+                let escaped = chars.get(i + 1).copied();
+                i += if escaped.is_some() { 2 } else { 1 };
+                escaped.map(|c| (format!("\\{}", c), escape_suggestion(c)))
+            }
+            '(' if chars.get(i + 1) == Some(&'?') => {
+                i += 2;
+                Some((
+                    "(?".to_string(),
+                    "iXML groups are plain parentheses `( ... )`; there are no \
+                     non-capturing or lookaround groups"
+                        .to_string(),
+                ))
+            }
+            '.' if matches!(chars.get(i + 1), Some('*' | '+' | '?')) => {
+                i += 2;
+                Some((
+                    chars[start..i].iter().collect(),
+                    "`.` ends a rule in iXML; match any character with `~[]`, \
+                     e.g. `~[]*`"
+                        .to_string(),
+                ))
+            }
+            '$' => {
+                i += 1;
+                Some((
+                    "$".to_string(),
+                    "iXML always matches the whole input, so anchors are not needed".to_string(),
+                ))
+            }
+            _ => {
+                i += 1;
+                None
+            }
+        };
+
+        if let Some((snippet, suggestion)) = hint {
+            let (line, column) = line_col(&chars, start);
+            hints.push(RegexHint {
+                line,
+                column,
+                snippet,
+                suggestion,
+            });
+        }
+    }
+
+    hints
+}
+
+/// Append regex hints (if any) to a grammar error message
+pub fn with_regex_hints(error: String, source: &str) -> String {
+    regex_hints(source)
+        .into_iter()
+        .fold(error, |message, hint| {
+            format!("{}\nhint: {}", message, hint)
+        })
+}
+
+/// First unquoted range (`a-z`) among a character class's members, if any
+///
+/// In iXML, range endpoints must be quoted strings or hex characters.
+pub fn unquoted_range(class_body: &str) -> Option<&str> {
+    class_body.split([';', '|']).map(str::trim).find(|member| {
+        let member: Vec<char> = member.chars().collect();
+        member.len() == 3
+            && member[1] == '-'
+            && ![member[0], member[2]]
+                .iter()
+                .any(|c| matches!(c, '"' | '\'' | '#'))
+    })
+}
+
+/// iXML factors matching what a simple regex matches
+///
+/// Handles literal characters, classes (see
+/// [`from_regex_class`](crate::charclass::from_regex_class)), groups written
+/// `(...)` or `(?:...)`, `|`, and the quantifiers `*`, `+`, `?`, `{n}`,
+/// `{n,}` and `{n,m}`. A regex with `|` outside any group becomes a single
+/// group. Anchors, lazy quantifiers, backreferences and lookaround have no
+/// iXML equivalent and are errors.
+///
+/// ```
+/// use rustixml::ast::Sequence;
+/// use rustixml::regex_hints::regex_to_factors;
+///
+/// let factors = regex_to_factors(r"id-[A-Z]\d{2,3}").unwrap();
+/// assert_eq!(
+///     Sequence::new(factors).to_string(),
+///     r#""id-", ["A"-"Z"], ["0"-"9"], ["0"-"9"], ["0"-"9"]?"#
+/// );
+/// assert!(regex_to_factors("^a+$").is_err());
+/// ```
+pub fn regex_to_factors(regex: &str) -> Result<Vec<Factor>, String> {
+    let mut parser = RegexParser {
+        chars: regex.chars().collect(),
+        pos: 0,
+    };
+    let alternatives = parser.alternatives()?;
+    if parser.pos < parser.chars.len() {
+        return Err("Unmatched `)` in regex".to_string());
+    }
+    Ok(match <[Sequence; 1]>::try_from(alternatives.alts) {
+        Ok([sequence]) => sequence.factors,
+        Err(alts) => vec![Factor::new(
+            BaseFactor::group(Alternatives::new(alts)),
+            Repetition::None,
+        )],
+    })
+}
+
+struct RegexParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl RegexParser {
+    fn alternatives(&mut self) -> Result<Alternatives, String> {
+        let mut alts = vec![self.sequence()?];
+        while self.chars.get(self.pos) == Some(&'|') {
+            self.pos += 1;
+            alts.push(self.sequence()?);
+        }
+        Ok(Alternatives::new(alts))
+    }
+
+    fn sequence(&mut self) -> Result<Sequence, String> {
+        let mut factors: Vec<Factor> = Vec::new();
+        while !matches!(self.chars.get(self.pos), None | Some('|' | ')')) {
+            let base = self.atom()?;
+            let (min, max) = self.quantifier()?;
+            // Runs of plain characters become one string
+            if let (
+                (1, Some(1)),
+                BaseFactor::Literal { value, .. },
+                Some(Factor {
+                    base: BaseFactor::Literal { value: run, .. },
+                    repetition: Repetition::None,
+                    ..
+                }),
+            ) = ((min, max), &base, factors.last_mut())
+            {
+                run.push_str(value);
+                continue;
+            }
+            factors.extend(repeat(base, min, max));
+        }
+        Ok(Sequence::new(factors))
+    }
+
+    fn atom(&mut self) -> Result<BaseFactor, String> {
+        let start = self.pos;
+        match self.chars[start] {
+            '(' => {
+                self.pos += 1;
+                if self.chars.get(self.pos) == Some(&'?') {
+                    if self.chars.get(self.pos + 1) != Some(&':') {
+                        return Err(
+                            "Lookaround and named groups have no iXML equivalent".to_string()
+                        );
+                    }
+                    self.pos += 2;
+                }
+                let alternatives = self.alternatives()?;
+                if self.chars.get(self.pos) != Some(&')') {
+                    return Err("Unterminated group in regex".to_string());
+                }
+                self.pos += 1;
+                Ok(BaseFactor::group(alternatives))
+            }
+            '^' | '$' => Err(format!(
+                "Anchor `{}` has no iXML equivalent; a grammar always matches all of its input",
+                self.chars[start]
+            )),
+            '*' | '+' | '?' | '{' => Err(format!(
+                "Nothing to repeat before `{}` in regex",
+                self.chars[start]
+            )),
+            '\\' if self
+                .chars
+                .get(start + 1)
+                .is_some_and(|ch| ch.is_ascii_digit() && *ch != '0') =>
+            {
+                Err("Backreferences have no iXML equivalent".to_string())
+            }
+            '[' | '.' | '\\' => {
+                let (set, negated, end) = parse_regex_class(&self.chars, start)?;
+                self.pos = end;
+                // A lone escaped character is a string
+                if let (false, [(from, to)]) = (negated, set.ranges()) {
+                    if from == to && self.chars[start] == '\\' {
+                        return Ok(BaseFactor::literal(from.to_string()));
+                    }
+                }
+                Ok(BaseFactor::marked_charclass(
+                    set.to_charclass_content(),
+                    negated,
+                    Mark::None,
+                ))
+            }
+            ch => {
+                self.pos += 1;
+                Ok(BaseFactor::literal(ch.to_string()))
+            }
+        }
+    }
+
+    /// The least and most times the atom just read repeats
+    fn quantifier(&mut self) -> Result<(usize, Option<usize>), String> {
+        let counts = match self.chars.get(self.pos) {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let close = (self.pos..self.chars.len())
+                    .find(|&i| self.chars[i] == '}')
+                    .ok_or("Unterminated `{` in regex")?;
+                let body: String = self.chars[self.pos + 1..close].iter().collect();
+                let number = |text: &str| {
+                    text.trim()
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid repeat count `{{{}}}` in regex", body))
+                };
+                let counts = match body.split_once(',') {
+                    None => (number(&body)?, Some(number(&body)?)),
+                    Some((min, "")) => (number(min)?, None),
+                    Some((min, max)) => (number(min)?, Some(number(max)?)),
+                };
+                if counts.1.is_some_and(|max| max < counts.0) {
+                    return Err(format!("Repeat count `{{{}}}` is out of order", body));
+                }
+                self.pos = close;
+                counts
+            }
+            _ => return Ok((1, Some(1))),
+        };
+        self.pos += 1;
+        if matches!(self.chars.get(self.pos), Some('?' | '+')) {
+            return Err("Lazy and possessive quantifiers have no iXML equivalent".to_string());
+        }
+        Ok(counts)
+    }
+}
+
+/// `base` repeated at least `min` and at most `max` times
+fn repeat(base: BaseFactor, min: usize, max: Option<usize>) -> Vec<Factor> {
+    let once = |base: &BaseFactor| Factor::new(base.clone(), Repetition::None);
+    let mut factors: Vec<Factor> = (0..min.saturating_sub(1)).map(|_| once(&base)).collect();
+    match (min, max) {
+        (0, None) => factors.push(Factor::new(base, Repetition::ZeroOrMore)),
+        (_, None) => factors.push(Factor::new(base, Repetition::OneOrMore)),
+        (_, Some(max)) => {
+            if min > 0 {
+                factors.push(once(&base));
+            }
+            // `(a, a?)?` rather than `a?, a?`, which would be ambiguous
+            let mut optional: Option<Factor> = None;
+            for _ in min..max {
+                let inner = match optional {
+                    None => base.clone(),
+                    Some(rest) => BaseFactor::group(Alternatives::new(vec![Sequence::new(vec![
+                        once(&base),
+                        rest,
+                    ])])),
+                };
+                optional = Some(Factor::new(inner, Repetition::Optional));
+            }
+            factors.extend(optional);
+        }
+    }
+    factors
+}
+
+fn class_hint(body: &str) -> Option<String> {
+    if let Some(range) = unquoted_range(body) {
+        let mut ends = range.split('-');
+        let (from, to) = (ends.next().unwrap_or(""), ends.next().unwrap_or(""));
+        return Some(format!(
+            "quote range endpoints in iXML: `[\"{}\"-\"{}\"]`",
+            from, to
+        ));
+    }
+    if body.trim_start().starts_with('^') {
+        return Some("negate a character class with `~[...]`, not `[^...]`".to_string());
+    }
+    if body.contains('\\') {
+        return Some(
+            "character classes have no escapes in iXML; quote characters (`\"-\"`) \
+             or use hex (`#a` for newline)"
+                .to_string(),
+        );
+    }
+    None
+}
+
+fn escape_suggestion(c: char) -> String {
+    match c {
+        'd' => "iXML has no escapes; a digit is `[\"0\"-\"9\"]` or `[Nd]`".to_string(),
+        'w' => "iXML has no escapes; a word character is `[L; Nd; \"_\"]`".to_string(),
+        's' => "iXML has no escapes; whitespace is `[\" \"; #9; #a; #d]`".to_string(),
+        'n' => "iXML has no escapes; a newline is `#a`".to_string(),
+        't' => "iXML has no escapes; a tab is `#9`".to_string(),
+        'r' => "iXML has no escapes; a carriage return is `#d`".to_string(),
+        other => format!(
+            "iXML has no escapes; quote the character instead: `\"{}\"`",
+            other
+        ),
+    }
+}
+
+fn is_repetition_count(body: &str) -> bool {
+    let mut parts = body.trim().splitn(2, ',');
+    let min = parts.next().unwrap_or("");
+    let max = parts.next().unwrap_or("0");
+    !min.is_empty()
+        && min.chars().all(|c| c.is_ascii_digit())
+        && max.trim().chars().all(|c| c.is_ascii_digit())
+}
+
+fn skip_quoted(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() {
+        if chars[i] == quote {
+            // Doubled quote is an escaped quote
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    i
+}
+
+fn skip_comment(chars: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    i
+}
+
+fn skip_class(chars: &[char], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '"' | '\'' => i = skip_quoted(chars, i),
+            ']' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+fn line_col(chars: &[char], pos: usize) -> (usize, usize) {
+    let before = &chars[..pos];
+    let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+    let column = pos - before.iter().rposition(|&c| c == '\n').map_or(0, |p| p + 1) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippets(source: &str) -> Vec<String> {
+        regex_hints(source).into_iter().map(|h| h.snippet).collect()
+    }
+
+    #[test]
+    fn test_detects_regex_idioms() {
+        assert_eq!(snippets("s: [a-z]+."), vec!["[a-z]"]);
+        assert_eq!(snippets(r"s: \d+, \s."), vec![r"\d", r"\s"]);
+        assert_eq!(snippets(r#"s: (?:"a"), .*."#), vec!["(?", ".*"]);
+        assert_eq!(
+            snippets(r#"s: d{2,3}, "a"$. d: [^"x"]."#),
+            vec!["{2,3}", "$", "[^\"x\"]"]
+        );
+    }
+
+    #[test]
+    fn test_ignores_valid_ixml() {
+        let grammar = r#"
+            {A comment with [a-z] and \d in it}
+            s: ["a"-"z"; #30-#39; L]+, "\d.*$", ~[]*, 'it''s'.
+        "#;
+        assert!(regex_hints(grammar).is_empty());
+    }
+
+    #[test]
+    fn test_hint_position() {
+        let hints = regex_hints("s: x.\nx: \\w.");
+        assert_eq!((hints[0].line, hints[0].column), (2, 4));
+        assert!(hints[0].to_string().starts_with("line 2, column 4: `\\w`"));
+    }
+
+    fn translated(regex: &str) -> String {
+        Sequence::new(regex_to_factors(regex).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_regex_to_factors() {
+        assert_eq!(translated("abc"), r#""abc""#);
+        assert_eq!(translated("ab+c?"), r#""a", "b"+, "c"?"#);
+        assert_eq!(translated(r"\d+\.\d*"), r#"["0"-"9"]+, ".", ["0"-"9"]*"#);
+        assert_eq!(translated(r"[^,\n]*"), r#"~[#a; ","]*"#);
+        assert_eq!(translated(".x"), r#"~[#a], "x""#);
+        assert_eq!(translated("cat|dog"), r#"("cat"; "dog")"#);
+        assert_eq!(translated("(?:ab|c){2}"), r#"("ab"; "c"), ("ab"; "c")"#);
+        assert_eq!(translated("a{1,3}"), r#""a", ("a", "a"?)?"#);
+        assert_eq!(translated("a{2,}"), r#""a", "a"+"#);
+        assert_eq!(translated(r"\u{1F600}\x41"), "\"\u{1F600}A\"");
+
+        for (regex, error) in [
+            ("a$", "Anchor `$` has no iXML equivalent"),
+            ("a*?", "Lazy and possessive quantifiers"),
+            (r"(a)\1", "Backreferences"),
+            ("(?=a)", "Lookaround"),
+            ("+a", "Nothing to repeat"),
+            ("a{3,1}", "out of order"),
+            ("(ab", "Unterminated group"),
+            ("ab)", "Unmatched `)`"),
+            (r"\q", "Unsupported regex escape"),
+            ("[z-a]", "out of order"),
+        ] {
+            let message = regex_to_factors(regex).unwrap_err();
+            assert!(message.contains(error), "{}: {}", regex, message);
+        }
+    }
+
+    #[test]
+    fn test_regex_factors_parse() {
+        let factors = regex_to_factors(r"[A-Za-z_]\w*(-\w+)?|\d{3}").unwrap();
+        let grammar = crate::ast::IxmlGrammar::new(vec![crate::ast::Rule::new(
+            "token".to_string(),
+            Mark::None,
+            Alternatives::new(vec![Sequence::new(factors)]),
+        )]);
+        let parser = crate::NativeParser::new(grammar);
+        for input in ["x", "_a1", "name-part", "123"] {
+            assert!(parser.parse(input).is_ok(), "{}", input);
+        }
+        for input in ["1a", "12", "1234", "a-"] {
+            assert!(parser.parse(input).is_err(), "{}", input);
+        }
+    }
+}
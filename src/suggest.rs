@@ -0,0 +1,68 @@
+//! "Did you mean" suggestions for misspelled names
+//!
+//! A reference to `lettter` when the grammar defines `letter` is almost
+//! always a typo. These helpers find the defined name nearest to the one
+//! written, by Levenshtein distance.
+
+/// Number of single-character insertions, deletions and substitutions that
+/// turn `a` into `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The candidate closest to `name`, if one is close enough to be a likely typo
+///
+/// Close enough is at most one edit per three characters (and at least one).
+/// Ties go to the earliest candidate.
+///
+/// ```
+/// use rustixml::suggest::closest;
+///
+/// let rules = ["word", "letter", "digit"];
+/// assert_eq!(closest("lettter", rules), Some("letter"));
+/// assert_eq!(closest("Digit", rules), Some("digit"));
+/// assert_eq!(closest("number", rules), None);
+/// ```
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("lettter", "letter"), 1);
+        assert_eq!(edit_distance("naïve", "naive"), 1);
+    }
+
+    #[test]
+    fn test_closest_prefers_nearest_then_earliest() {
+        assert_eq!(closest("ab", ["abc", "abd", "a"]), Some("abc"));
+        assert_eq!(closest("name", ["nmae", "names"]), Some("names"));
+        assert_eq!(closest("x", ["x"]), None);
+        assert_eq!(closest("wrod", ["sentence"]), None);
+    }
+}
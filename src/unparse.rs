@@ -0,0 +1,554 @@
+//! Turning parse results back into the text they came from
+//!
+//! [`unparse`] walks a result tree against the grammar that produced it and
+//! rebuilds the input: the text of visible nodes is kept, literals the
+//! grammar hides (`-","`) are put back, and insertions (`+"x"`) are taken
+//! out again.
+//!
+//! The tree does not always determine the input. A hidden character class
+//! (`-[" "; #9]`) or an optional hidden literal leaves no trace in the
+//! output, so a choice has to be made; each such choice is reported as a
+//! [`code::UNPARSE_AMBIGUOUS`] warning. The text returned parses back to the
+//! same tree.
+
+use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Repetition, Rule, Sequence};
+use crate::charclass::{charclass_to_rangeset, RangeSet};
+use crate::diagnostics::{code, Diagnostic};
+use crate::xml_node::XmlNode;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// The text rebuilt from a tree, with the choices that had to be made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unparsed {
+    pub text: String,
+    /// Warnings about parts of the text the tree did not determine
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Rebuild the input that `grammar` turned into `document`
+///
+/// The root element's name picks the rule to start from. Fails if the tree
+/// is not one the grammar can produce.
+///
+/// ```
+/// use rustixml::{parse_ixml_grammar, NativeParser};
+/// use rustixml::unparse::unparse;
+///
+/// let grammar = parse_ixml_grammar(
+///     r#"date: day, -" ", month, +"!". day: ["0"-"9"]+. @month: "Jan"; "Feb"."#,
+/// )
+/// .unwrap();
+/// let tree = NativeParser::new(grammar.clone()).parse_to_tree("15 Feb").unwrap();
+/// assert_eq!(tree.to_xml(), "<date month='Feb'><day>15</day>!</date>");
+///
+/// let unparsed = unparse(&grammar, &tree).unwrap();
+/// assert_eq!(unparsed.text, "15 Feb");
+/// assert!(unparsed.diagnostics.is_empty());
+/// ```
+pub fn unparse(grammar: &IxmlGrammar, document: &XmlNode) -> Result<Unparsed, String> {
+    let name = document
+        .name()
+        .ok_or_else(|| "The document is not an element".to_string())?;
+    let mut unparser = Unparser {
+        rules: HashMap::new(),
+    };
+    for rule in &grammar.rules {
+        unparser.rules.entry(rule.name.as_str()).or_insert(rule);
+    }
+    let rule = *unparser
+        .rules
+        .get(name)
+        .ok_or_else(|| format!("No rule produces the root element <{}>", name))?;
+    let derivation = unparser
+        .element(rule, document)
+        .ok_or_else(|| format!("<{}> does not match rule '{}'", name, name))?;
+
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for note in derivation.notes {
+        let diagnostic = Diagnostic::warning(code::UNPARSE_AMBIGUOUS, note);
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    Ok(Unparsed {
+        text: derivation.text,
+        diagnostics,
+    })
+}
+
+/// One way of producing part of the output, and the input text it came from
+#[derive(Debug, Clone, Default)]
+struct Derivation {
+    text: String,
+    /// Choices the output did not determine
+    notes: Vec<String>,
+}
+
+impl Derivation {
+    fn text(text: impl Into<String>) -> Self {
+        Derivation {
+            text: text.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    fn then(&self, next: &Derivation) -> Derivation {
+        let mut notes = self.notes.clone();
+        notes.extend(next.notes.iter().cloned());
+        Derivation {
+            text: format!("{}{}", self.text, next.text),
+            notes,
+        }
+    }
+}
+
+/// The derivations of an item starting at one position, by end position
+type Ends = BTreeMap<usize, Derivation>;
+
+/// Add a derivation ending at `end`, noting when an earlier one already
+/// reached it from different input text
+fn add(ends: &mut Ends, end: usize, derivation: Derivation, rule: &str) {
+    match ends.get_mut(&end) {
+        None => {
+            ends.insert(end, derivation);
+        }
+        Some(existing) if existing.text != derivation.text => {
+            let note = format!(
+                "rule '{}': the output could come from {:?} or {:?}; using {:?}",
+                rule, existing.text, derivation.text, existing.text
+            );
+            if !existing.notes.contains(&note) {
+                existing.notes.push(note);
+            }
+        }
+        Some(_) => {}
+    }
+}
+
+/// What a rule's output is matched against
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'d> {
+    Char(char),
+    Element(&'d XmlNode),
+}
+
+/// The content of one element, or the value of one attribute
+struct Stream<'g, 'd> {
+    tokens: Vec<Token<'d>>,
+    attributes: &'d [(String, String)],
+    /// An attribute value, where every rule contributes only its text
+    text_only: bool,
+    memo: HashMap<(&'g str, usize), Ends>,
+    /// Inline rules being matched, to stop left recursion
+    active: HashSet<(&'g str, usize)>,
+}
+
+impl<'g, 'd> Stream<'g, 'd> {
+    fn new(tokens: Vec<Token<'d>>, attributes: &'d [(String, String)], text_only: bool) -> Self {
+        Stream {
+            tokens,
+            attributes,
+            text_only,
+            memo: HashMap::new(),
+            active: HashSet::new(),
+        }
+    }
+
+    fn content(element: &'d XmlNode) -> Self {
+        let XmlNode::Element {
+            attributes,
+            children,
+            ..
+        } = element
+        else {
+            return Stream::new(Vec::new(), &[], false);
+        };
+        let mut tokens = Vec::new();
+        for child in children {
+            match child {
+                XmlNode::Text(text) => tokens.extend(text.chars().map(Token::Char)),
+                XmlNode::Element { .. } => tokens.push(Token::Element(child)),
+                XmlNode::Attribute { .. } => {}
+            }
+        }
+        Stream::new(tokens, attributes, false)
+    }
+
+    fn value(value: &str) -> Self {
+        Stream::new(value.chars().map(Token::Char).collect(), &[], true)
+    }
+
+    /// The end of `text` if the tokens from `start` spell it
+    fn chars(&self, start: usize, text: &str) -> Option<usize> {
+        let mut pos = start;
+        for ch in text.chars() {
+            if self.tokens.get(pos) != Some(&Token::Char(ch)) {
+                return None;
+            }
+            pos += 1;
+        }
+        Some(pos)
+    }
+}
+
+struct Unparser<'g> {
+    rules: HashMap<&'g str, &'g Rule>,
+}
+
+impl<'g> Unparser<'g> {
+    /// The input `rule` read to produce `element`
+    fn element<'d>(&self, rule: &'g Rule, element: &'d XmlNode) -> Option<Derivation> {
+        let mut stream = Stream::content(element);
+        let len = stream.tokens.len();
+        self.alternatives(&rule.alternatives, 0, &mut stream, &rule.name)
+            .remove(&len)
+    }
+
+    fn alternatives<'d>(
+        &self,
+        alts: &'g Alternatives,
+        start: usize,
+        stream: &mut Stream<'g, 'd>,
+        rule: &str,
+    ) -> Ends {
+        let mut ends = Ends::new();
+        for seq in &alts.alts {
+            for (end, derivation) in self.sequence(seq, start, stream, rule) {
+                add(&mut ends, end, derivation, rule);
+            }
+        }
+        ends
+    }
+
+    fn sequence<'d>(
+        &self,
+        seq: &'g Sequence,
+        start: usize,
+        stream: &mut Stream<'g, 'd>,
+        rule: &str,
+    ) -> Ends {
+        let mut ends = Ends::from([(start, Derivation::default())]);
+        for factor in &seq.factors {
+            let mut next = Ends::new();
+            for (pos, before) in ends {
+                for (end, derivation) in self.factor(factor, pos, stream, rule) {
+                    add(&mut next, end, before.then(&derivation), rule);
+                }
+            }
+            ends = next;
+        }
+        ends
+    }
+
+    fn factor<'d>(
+        &self,
+        factor: &'g Factor,
+        start: usize,
+        stream: &mut Stream<'g, 'd>,
+        rule: &str,
+    ) -> Ends {
+        let base = &factor.base;
+        match &factor.repetition {
+            Repetition::None => self.base(base, start, stream, rule),
+            Repetition::Optional => {
+                let mut ends = Ends::from([(start, Derivation::default())]);
+                for (end, derivation) in self.base(base, start, stream, rule) {
+                    add(&mut ends, end, derivation, rule);
+                }
+                ends
+            }
+            Repetition::ZeroOrMore => {
+                let first = Ends::from([(start, Derivation::default())]);
+                self.repeat(
+                    first,
+                    &|this, pos, stream| this.base(base, pos, stream, rule),
+                    stream,
+                    rule,
+                )
+            }
+            Repetition::OneOrMore => {
+                let first = self.base(base, start, stream, rule);
+                self.repeat(
+                    first,
+                    &|this, pos, stream| this.base(base, pos, stream, rule),
+                    stream,
+                    rule,
+                )
+            }
+            Repetition::SeparatedZeroOrMore(sep) | Repetition::SeparatedOneOrMore(sep) => {
+                let first = self.base(base, start, stream, rule);
+                let mut ends = self.repeat(
+                    first,
+                    &|this, pos, stream| {
+                        let mut ends = Ends::new();
+                        for (mid, separator) in this.sequence(sep, pos, stream, rule) {
+                            for (end, item) in this.base(base, mid, stream, rule) {
+                                add(&mut ends, end, separator.then(&item), rule);
+                            }
+                        }
+                        ends
+                    },
+                    stream,
+                    rule,
+                );
+                if matches!(factor.repetition, Repetition::SeparatedZeroOrMore(_)) {
+                    add(&mut ends, start, Derivation::default(), rule);
+                }
+                ends
+            }
+        }
+    }
+
+    /// Extend each of `ends` by any number of `step`s
+    ///
+    /// A step that reads no output is taken once at most, so it is noted as a
+    /// choice when it reads input, rather than repeated forever.
+    fn repeat<'d>(
+        &self,
+        mut ends: Ends,
+        step: &dyn Fn(&Self, usize, &mut Stream<'g, 'd>) -> Ends,
+        stream: &mut Stream<'g, 'd>,
+        rule: &str,
+    ) -> Ends {
+        let mut frontier: Vec<usize> = ends.keys().copied().collect();
+        while let Some(pos) = frontier.pop() {
+            let before = ends[&pos].clone();
+            for (end, derivation) in step(self, pos, stream) {
+                let fresh = !ends.contains_key(&end);
+                add(&mut ends, end, before.then(&derivation), rule);
+                if fresh && end > pos {
+                    frontier.push(end);
+                }
+            }
+        }
+        ends
+    }
+
+    fn base<'d>(
+        &self,
+        base: &'g BaseFactor,
+        start: usize,
+        stream: &mut Stream<'g, 'd>,
+        rule: &str,
+    ) -> Ends {
+        let mut ends = Ends::new();
+        match base {
+            BaseFactor::Literal {
+                value,
+                insertion,
+                mark,
+            } => {
+                let hidden = *mark == Mark::Hidden;
+                match (insertion, hidden) {
+                    // A hidden insertion leaves no trace at all
+                    (true, true) => {
+                        ends.insert(start, Derivation::default());
+                    }
+                    (true, false) => {
+                        if let Some(end) = stream.chars(start, value) {
+                            ends.insert(end, Derivation::default());
+                        }
+                    }
+                    (false, true) => {
+                        ends.insert(start, Derivation::text(value.as_str()));
+                    }
+                    (false, false) => {
+                        if let Some(end) = stream.chars(start, value) {
+                            ends.insert(end, Derivation::text(value.as_str()));
+                        }
+                    }
+                }
+            }
+            BaseFactor::CharClass {
+                content,
+                negated,
+                mark,
+            } => {
+                let class = charclass_to_rangeset(content);
+                let matches = |ch: char| class.contains(ch) != *negated;
+                if *mark == Mark::Hidden {
+                    if let Some(ch) = choose(&class, *negated) {
+                        let mut derivation = Derivation::text(ch);
+                        if !single(&class, *negated) {
+                            derivation.notes.push(format!(
+                                "rule '{}': hidden {}[{}] could be any of several characters; using {:?}",
+                                rule,
+                                if *negated { "~" } else { "" },
+                                content,
+                                ch
+                            ));
+                        }
+                        ends.insert(start, derivation);
+                    }
+                } else if let Some(Token::Char(ch)) = stream.tokens.get(start) {
+                    if matches(*ch) {
+                        ends.insert(start + 1, Derivation::text(*ch));
+                    }
+                }
+            }
+            BaseFactor::Nonterminal { name, mark } => {
+                let Some(&target) = self.rules.get(name.as_str()) else {
+                    return ends;
+                };
+                let mark = if *mark == Mark::None {
+                    target.mark
+                } else {
+                    *mark
+                };
+                match mark {
+                    Mark::None | Mark::Promoted if !stream.text_only => {
+                        if let Some(Token::Element(element)) = stream.tokens.get(start) {
+                            if element.name() == Some(name.as_str()) {
+                                if let Some(derivation) = self.element(target, element) {
+                                    ends.insert(start + 1, derivation);
+                                }
+                            }
+                        }
+                    }
+                    Mark::Attribute if !stream.text_only => {
+                        let value = stream
+                            .attributes
+                            .iter()
+                            .find(|(k, _)| k == name)
+                            .map(|(_, v)| v.as_str());
+                        if let Some(value) = value {
+                            let mut value = Stream::value(value);
+                            let len = value.tokens.len();
+                            let derivation = self
+                                .alternatives(&target.alternatives, 0, &mut value, name)
+                                .remove(&len);
+                            if let Some(derivation) = derivation {
+                                ends.insert(start, derivation);
+                            }
+                        }
+                    }
+                    // Hidden rules, and anything in an attribute value, add only their text
+                    _ => return self.inline(target, start, stream),
+                }
+            }
+            BaseFactor::Group { alternatives } => {
+                return self.alternatives(alternatives, start, stream, rule);
+            }
+            // Lookahead reads no input and leaves nothing in the output
+            BaseFactor::NegativeLookahead { .. } => {
+                ends.insert(start, Derivation::default());
+            }
+        }
+        ends
+    }
+
+    /// Match a rule whose output is part of the enclosing element's
+    fn inline<'d>(&self, rule: &'g Rule, start: usize, stream: &mut Stream<'g, 'd>) -> Ends {
+        let key = (rule.name.as_str(), start);
+        if let Some(ends) = stream.memo.get(&key) {
+            return ends.clone();
+        }
+        if !stream.active.insert(key) {
+            return Ends::new();
+        }
+        let ends = self.alternatives(&rule.alternatives, start, stream, &rule.name);
+        stream.active.remove(&key);
+        stream.memo.insert(key, ends.clone());
+        ends
+    }
+}
+
+/// A character matched by a class: a space if it can be, else printable ASCII
+fn choose(class: &RangeSet, negated: bool) -> Option<char> {
+    let matches = |ch: char| class.contains(ch) != negated;
+    std::iter::once(' ')
+        .chain('!'..='~')
+        .chain(class.ranges().iter().map(|&(start, _)| start))
+        .chain(['\u{0}'..=char::MAX].into_iter().flatten())
+        .find(|&ch| matches(ch))
+}
+
+/// Whether a class matches exactly one character
+fn single(class: &RangeSet, negated: bool) -> bool {
+    !negated && matches!(class.ranges(), [(start, end)] if start == end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar_ast::parse_ixml_grammar;
+    use crate::native_parser::NativeParser;
+
+    /// Parse `input`, unparse the result and check the text comes back
+    fn round_trip(grammar: &str, input: &str) -> Unparsed {
+        let grammar = parse_ixml_grammar(grammar).unwrap();
+        let tree = NativeParser::new(grammar.clone())
+            .parse_to_tree(input)
+            .unwrap();
+        let unparsed = unparse(&grammar, &tree).unwrap();
+        let reparsed = NativeParser::new(grammar)
+            .parse_to_tree(&unparsed.text)
+            .unwrap();
+        assert_eq!(reparsed, tree, "{:?}", unparsed.text);
+        unparsed
+    }
+
+    #[test]
+    fn test_fixtures_round_trip() {
+        for fixture in crate::fixtures::FIXTURES {
+            let tree = fixture.parser().parse_to_tree(fixture.input).unwrap();
+            let unparsed = unparse(&fixture.grammar_ast(), &tree).unwrap();
+            assert_eq!(unparsed.text, fixture.input, "{}", fixture.name);
+        }
+    }
+
+    #[test]
+    fn test_marks_and_repetitions() {
+        let unparsed = round_trip(
+            r#"list: item++-",", -".". item: @id, -":", -name; +"empty". id: ["0"-"9"]+. name: ["a"-"z"]*."#,
+            "1:ab,2:,3:c.",
+        );
+        assert_eq!(unparsed.text, "1:ab,2:,3:c.");
+        assert!(unparsed.diagnostics.is_empty());
+
+        let unparsed = round_trip(r#"s: a**-";". a: "x", +"!"."#, "x;x");
+        assert_eq!(unparsed.text, "x;x");
+        let unparsed = round_trip(r#"s: a**-";". a: "x"."#, "");
+        assert_eq!(unparsed.text, "");
+    }
+
+    #[test]
+    fn test_undetermined_input_is_reported() {
+        let unparsed = round_trip(r#"s: w, -[" "; #9]+, w. w: ["a"-"z"]+."#, "ab\tcd");
+        assert_eq!(unparsed.text, "ab cd");
+        let messages: Vec<_> = unparsed
+            .diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                r#"rule 's': hidden [" "; #9] could be any of several characters; using ' '"#,
+                r#"rule 's': the output could come from " " or "  "; using " ""#,
+            ]
+        );
+        assert_eq!(unparsed.diagnostics[0].code, code::UNPARSE_AMBIGUOUS);
+
+        let unparsed = round_trip(r#"s: "a", -"b"?, "c"."#, "abc");
+        assert_eq!(unparsed.text, "ac");
+        assert_eq!(
+            unparsed.diagnostics[0].message,
+            r#"rule 's': the output could come from "" or "b"; using """#
+        );
+    }
+
+    #[test]
+    fn test_mismatched_tree() {
+        let grammar = parse_ixml_grammar(r#"s: a, "!". a: "x"."#).unwrap();
+        let tree = XmlNode::element("s").child(XmlNode::element("a").child(XmlNode::text("y")));
+        assert_eq!(
+            unparse(&grammar, &tree).unwrap_err(),
+            "<s> does not match rule 's'"
+        );
+        assert_eq!(
+            unparse(&grammar, &XmlNode::element("t")).unwrap_err(),
+            "No rule produces the root element <t>"
+        );
+    }
+}
@@ -0,0 +1,552 @@
+//! The XML form of grammars ("vxml")
+//!
+//! Parsing a grammar with the iXML grammar for iXML gives an XML document:
+//! `<ixml>` with a `<rule>` per rule, an `<alt>` per alternative, and
+//! elements such as `<nonterminal>`, `<literal>`, `<inclusion>` and
+//! `<repeat0>` for the factors. [`IxmlGrammar::to_vxml`] builds that document
+//! from a parsed grammar, as the iXML 1.0 specification lays it out.
+//!
+//! Two things rustixml accepts have no place in the specification's form:
+//! pragmas are left out, and a negative lookahead `!x` is written as
+//! `<not>` around `x`. Character classes that subtract members (`["a"-"z";
+//! ~"aeiou"]`) are written as the ranges they leave.
+//!
+//! [`IxmlGrammar::from_vxml`] reads such a document back, so grammars stored
+//! as XML load without going through iXML source.
+
+use crate::ast::{Alternatives, BaseFactor, Factor, IxmlGrammar, Mark, Repetition, Rule, Sequence};
+use crate::charclass::{charclass_to_rangeset, split_charclass_content, unquote};
+use crate::xml_node::XmlNode;
+
+impl IxmlGrammar {
+    /// The grammar in the specification's XML form
+    ///
+    /// ```
+    /// use rustixml::parse_ixml_grammar;
+    ///
+    /// let grammar = parse_ixml_grammar(r#"greeting: -"hi ", @name+. name: ["a"-"z"]."#).unwrap();
+    /// assert_eq!(
+    ///     grammar.to_vxml().to_xml(),
+    ///     "<ixml><rule name='greeting'><alt><literal tmark='-' string='hi '/>\
+    ///      <repeat1><nonterminal mark='@' name='name'/></repeat1></alt></rule>\
+    ///      <rule name='name'><alt><inclusion><member from='a' to='z'/></inclusion></alt></rule></ixml>"
+    /// );
+    /// ```
+    pub fn to_vxml(&self) -> XmlNode {
+        let mut ixml = XmlNode::element("ixml");
+        if let Some(version) = &self.version {
+            ixml.push_child(XmlNode::element("version").attr("string", version));
+        }
+        for rule in &self.rules {
+            ixml.push_child(rule_vxml(rule));
+        }
+        ixml
+    }
+
+    /// Read a grammar from its XML form
+    ///
+    /// Accepts what [`IxmlGrammar::to_vxml`] writes and what parsing a
+    /// grammar with the iXML grammar for iXML gives: `<prolog>` and
+    /// `<comment>` elements and whitespace between elements are allowed.
+    ///
+    /// ```
+    /// use rustixml::{IxmlGrammar, NativeParser};
+    ///
+    /// let grammar = IxmlGrammar::from_vxml_str(
+    ///     "<ixml><rule name='word'><alt><repeat1><inclusion><member from='a' to='z'/>\
+    ///      </inclusion></repeat1></alt></rule></ixml>",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(grammar.to_string(), "word: [\"a\"-\"z\"]+.\n");
+    /// assert_eq!(NativeParser::new(grammar).parse("hi").unwrap(), "<word>hi</word>");
+    /// ```
+    pub fn from_vxml(ixml: &XmlNode) -> Result<IxmlGrammar, String> {
+        if ixml.name() != Some("ixml") {
+            return Err(format!("Expected <ixml>, found {}", describe(ixml)));
+        }
+        let mut version = None;
+        let mut rules = Vec::new();
+        for child in elements(ixml) {
+            match child.name() {
+                Some("prolog") => {
+                    for item in elements(child) {
+                        version = Some(version_from(item)?);
+                    }
+                }
+                Some("version") => version = Some(version_from(child)?),
+                Some("rule") => rules.push(rule_from(child)?),
+                _ => return Err(unexpected(child, ixml)),
+            }
+        }
+        Ok(IxmlGrammar { rules, version })
+    }
+
+    /// Read a grammar from the text of its XML form
+    pub fn from_vxml_str(xml: &str) -> Result<IxmlGrammar, String> {
+        IxmlGrammar::from_vxml(&XmlNode::from_xml(xml)?)
+    }
+}
+
+fn rule_vxml(rule: &Rule) -> XmlNode {
+    let element = with_mark(XmlNode::element("rule"), "mark", rule.mark).attr("name", &rule.name);
+    alts_children(element, &rule.alternatives)
+}
+
+/// `element` with an `<alt>` child per alternative
+fn alts_children(mut element: XmlNode, alts: &Alternatives) -> XmlNode {
+    for seq in &alts.alts {
+        element.push_child(sequence_vxml(seq));
+    }
+    element
+}
+
+fn sequence_vxml(seq: &Sequence) -> XmlNode {
+    let mut alt = XmlNode::element("alt");
+    for factor in &seq.factors {
+        alt.push_child(factor_vxml(factor));
+    }
+    alt
+}
+
+fn factor_vxml(factor: &Factor) -> XmlNode {
+    let base = base_vxml(&factor.base);
+    let (name, sep) = match &factor.repetition {
+        Repetition::None => return base,
+        Repetition::Optional => ("option", None),
+        Repetition::ZeroOrMore => ("repeat0", None),
+        Repetition::OneOrMore => ("repeat1", None),
+        Repetition::SeparatedZeroOrMore(sep) => ("repeat0", Some(sep)),
+        Repetition::SeparatedOneOrMore(sep) => ("repeat1", Some(sep)),
+    };
+    let mut element = XmlNode::element(name).child(base);
+    if let Some(sep) = sep {
+        // A separator is one factor; anything else is bracketed
+        let separator = match sep.factors.as_slice() {
+            [only] if only.repetition == Repetition::None => factor_vxml(only),
+            _ => XmlNode::element("alts").child(sequence_vxml(sep)),
+        };
+        element.push_child(XmlNode::element("sep").child(separator));
+    }
+    element
+}
+
+fn base_vxml(base: &BaseFactor) -> XmlNode {
+    match base {
+        BaseFactor::Literal {
+            value,
+            insertion,
+            mark,
+        } => {
+            let literal = |part: &LiteralPart| {
+                let element = if *insertion {
+                    XmlNode::element("insertion")
+                } else {
+                    with_mark(XmlNode::element("literal"), "tmark", *mark)
+                };
+                match part {
+                    LiteralPart::String(string) => element.attr("string", string),
+                    LiteralPart::Hex(ch) => element.attr("hex", format!("{:x}", u32::from(*ch))),
+                }
+            };
+            match literal_parts(value).as_slice() {
+                [part] => literal(part),
+                parts => {
+                    let mut alt = XmlNode::element("alt");
+                    for part in parts {
+                        alt.push_child(literal(part));
+                    }
+                    XmlNode::element("alts").child(alt)
+                }
+            }
+        }
+        BaseFactor::Nonterminal { name, mark } => {
+            with_mark(XmlNode::element("nonterminal"), "mark", *mark).attr("name", name)
+        }
+        BaseFactor::CharClass {
+            content,
+            negated,
+            mark,
+        } => {
+            let name = if *negated { "exclusion" } else { "inclusion" };
+            let mut class = with_mark(XmlNode::element(name), "tmark", *mark);
+            let members = split_charclass_content(content);
+            if members.iter().any(|member| member.starts_with('~')) {
+                for &(from, to) in charclass_to_rangeset(content).ranges() {
+                    class.push_child(range_member(from, to));
+                }
+            } else {
+                for member in &members {
+                    class.push_child(member_vxml(member));
+                }
+            }
+            class
+        }
+        BaseFactor::Group { alternatives } => alts_children(XmlNode::element("alts"), alternatives),
+        BaseFactor::NegativeLookahead { base } => XmlNode::element("not").child(base_vxml(base)),
+    }
+}
+
+fn with_mark(element: XmlNode, attribute: &str, mark: Mark) -> XmlNode {
+    match mark {
+        Mark::None => element,
+        mark => element.attr(attribute, mark.to_string()),
+    }
+}
+
+enum LiteralPart {
+    String(String),
+    Hex(char),
+}
+
+/// A literal as strings and hex characters; characters that cannot appear
+/// in iXML strings, and whitespace other than a space, are hex
+fn literal_parts(value: &str) -> Vec<LiteralPart> {
+    let mut parts = Vec::new();
+    let mut string = String::new();
+    for ch in value.chars() {
+        if ch.is_control() || (ch.is_whitespace() && ch != ' ') {
+            if !string.is_empty() {
+                parts.push(LiteralPart::String(std::mem::take(&mut string)));
+            }
+            parts.push(LiteralPart::Hex(ch));
+        } else {
+            string.push(ch);
+        }
+    }
+    if !string.is_empty() || parts.is_empty() {
+        parts.push(LiteralPart::String(string));
+    }
+    parts
+}
+
+/// One member of a character class as written in the grammar
+fn member_vxml(member: &str) -> XmlNode {
+    let element = XmlNode::element("member");
+    // A range end is a quoted character or `#` and hex digits
+    let character = |text: &str| match unquote(text) {
+        Some((value, _)) => value,
+        None => text.to_string(),
+    };
+    if let Some((value, rest)) = unquote(member) {
+        return match rest.trim_start().strip_prefix('-') {
+            Some(to) => element.attr("from", value).attr("to", character(to.trim())),
+            None => element.attr("string", value),
+        };
+    }
+    if let Some(hex) = member.strip_prefix('#') {
+        return match hex.split_once('-') {
+            Some((from, to)) => element
+                .attr("from", format!("#{}", from.trim()))
+                .attr("to", character(to.trim())),
+            None => element.attr("hex", hex),
+        };
+    }
+    element.attr("code", member)
+}
+
+fn range_member(from: char, to: char) -> XmlNode {
+    let hex = |ch: char| format!("{:x}", u32::from(ch));
+    let member = XmlNode::element("member");
+    if from == to {
+        member.attr("hex", hex(from))
+    } else {
+        member
+            .attr("from", format!("#{}", hex(from)))
+            .attr("to", format!("#{}", hex(to)))
+    }
+}
+
+/// Child elements, leaving out text and comments
+fn elements(node: &XmlNode) -> impl Iterator<Item = &XmlNode> {
+    node.children()
+        .iter()
+        .filter(|child| !matches!(child.name(), None | Some("comment")))
+}
+
+fn describe(node: &XmlNode) -> String {
+    match node.name() {
+        Some(name) => format!("<{}>", name),
+        None => "text".to_string(),
+    }
+}
+
+fn unexpected(node: &XmlNode, parent: &XmlNode) -> String {
+    format!("Unexpected {} in {}", describe(node), describe(parent))
+}
+
+fn required<'a>(node: &'a XmlNode, attribute: &str) -> Result<&'a str, String> {
+    node.attribute(attribute)
+        .ok_or_else(|| format!("{} has no {} attribute", describe(node), attribute))
+}
+
+fn version_from(node: &XmlNode) -> Result<String, String> {
+    match node.name() {
+        Some("version") => Ok(required(node, "string")?.to_string()),
+        _ => Err(unexpected(node, &XmlNode::element("prolog"))),
+    }
+}
+
+fn mark_from(node: &XmlNode, attribute: &str) -> Result<Mark, String> {
+    match node.attribute(attribute) {
+        None => Ok(Mark::None),
+        Some("@") => Ok(Mark::Attribute),
+        Some("-") => Ok(Mark::Hidden),
+        Some("^") => Ok(Mark::Promoted),
+        Some(other) => Err(format!(
+            "{} has {}='{}', expected '@', '-' or '^'",
+            describe(node),
+            attribute,
+            other
+        )),
+    }
+}
+
+fn rule_from(rule: &XmlNode) -> Result<Rule, String> {
+    let name = required(rule, "name")?.to_string();
+    let mark = mark_from(rule, "mark")?;
+    Ok(Rule::new(name, mark, alts_from(rule)?))
+}
+
+/// The `<alt>` children of `element`
+fn alts_from(element: &XmlNode) -> Result<Alternatives, String> {
+    let mut alts = Vec::new();
+    for child in elements(element) {
+        match child.name() {
+            Some("alt") => alts.push(sequence_from(child)?),
+            _ => return Err(unexpected(child, element)),
+        }
+    }
+    if alts.is_empty() {
+        return Err(format!("{} has no <alt>", describe(element)));
+    }
+    Ok(Alternatives::new(alts))
+}
+
+fn sequence_from(alt: &XmlNode) -> Result<Sequence, String> {
+    Ok(Sequence::new(
+        elements(alt).map(factor_from).collect::<Result<_, _>>()?,
+    ))
+}
+
+fn factor_from(node: &XmlNode) -> Result<Factor, String> {
+    let repetition = |name: &str| match name {
+        "option" => Some(Repetition::Optional),
+        "repeat0" => Some(Repetition::ZeroOrMore),
+        "repeat1" => Some(Repetition::OneOrMore),
+        _ => None,
+    };
+    let Some(repetition) = node.name().and_then(repetition) else {
+        return Ok(Factor::simple(base_from(node)?));
+    };
+    let mut children = elements(node);
+    let base = match children.next() {
+        Some(child) => base_from(child)?,
+        None => return Err(format!("{} is empty", describe(node))),
+    };
+    let repetition = match (children.next(), repetition) {
+        (None, repetition) => repetition,
+        (Some(sep), Repetition::ZeroOrMore) if sep.name() == Some("sep") => {
+            Repetition::SeparatedZeroOrMore(Box::new(separator_from(sep)?))
+        }
+        (Some(sep), Repetition::OneOrMore) if sep.name() == Some("sep") => {
+            Repetition::SeparatedOneOrMore(Box::new(separator_from(sep)?))
+        }
+        (Some(other), _) => return Err(unexpected(other, node)),
+    };
+    if let Some(extra) = children.next() {
+        return Err(unexpected(extra, node));
+    }
+    Ok(Factor::new(base, repetition))
+}
+
+/// The factor in a `<sep>`; a bracketed single sequence is unwrapped
+fn separator_from(sep: &XmlNode) -> Result<Sequence, String> {
+    let mut children = elements(sep);
+    let (Some(factor), None) = (children.next(), children.next()) else {
+        return Err(format!("{} must hold one factor", describe(sep)));
+    };
+    let factor = factor_from(factor)?;
+    match factor {
+        Factor {
+            base: BaseFactor::Group { mut alternatives },
+            repetition: Repetition::None,
+            ..
+        } if alternatives.alts.len() == 1 => Ok(alternatives.alts.remove(0)),
+        factor => Ok(Sequence::new(vec![factor])),
+    }
+}
+
+fn base_from(node: &XmlNode) -> Result<BaseFactor, String> {
+    match node.name() {
+        Some("nonterminal") => Ok(BaseFactor::marked_nonterminal(
+            required(node, "name")?.to_string(),
+            mark_from(node, "mark")?,
+        )),
+        Some("literal") => Ok(BaseFactor::marked_literal(
+            literal_value(node)?,
+            mark_from(node, "tmark")?,
+        )),
+        Some("insertion") => Ok(BaseFactor::insertion(literal_value(node)?)),
+        Some(name @ ("inclusion" | "exclusion")) => {
+            let members = elements(node)
+                .map(|member| match member.name() {
+                    Some("member") => member_source(member),
+                    _ => Err(unexpected(member, node)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(BaseFactor::marked_charclass(
+                members.join("; "),
+                name == "exclusion",
+                mark_from(node, "tmark")?,
+            ))
+        }
+        Some("alts") => Ok(BaseFactor::group(alts_from(node)?)),
+        Some("not") => {
+            let mut children = elements(node);
+            match (children.next(), children.next()) {
+                (Some(base), None) => Ok(BaseFactor::negative_lookahead(base_from(base)?)),
+                _ => Err(format!("{} must hold one factor", describe(node))),
+            }
+        }
+        // A repetition where only a base is allowed, such as `(a*)?`
+        Some("option" | "repeat0" | "repeat1") => Ok(BaseFactor::group(Alternatives::single(
+            Sequence::new(vec![factor_from(node)?]),
+        ))),
+        _ => Err(format!("Expected a factor, found {}", describe(node))),
+    }
+}
+
+/// The value of a `string` or `hex` attribute
+fn literal_value(node: &XmlNode) -> Result<String, String> {
+    if let Some(string) = node.attribute("string") {
+        return Ok(string.to_string());
+    }
+    match node.attribute("hex") {
+        Some(hex) => hex_char(hex).map(String::from),
+        None => Err(format!("{} has no string or hex attribute", describe(node))),
+    }
+}
+
+fn hex_char(hex: &str) -> Result<char, String> {
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| format!("'{}' is not a hex character", hex))
+}
+
+/// A `<member>` as it is written between the brackets of a class
+fn member_source(member: &XmlNode) -> Result<String, String> {
+    let quoted = |value: &str| format!("\"{}\"", value.replace('"', "\"\""));
+    // A range end is one character, or `#` and hex digits
+    let end = |value: &str| match value.strip_prefix('#') {
+        Some(hex) if !hex.is_empty() => hex_char(hex).map(|_| value.to_string()),
+        _ if value.chars().count() == 1 => Ok(quoted(value)),
+        _ => Err(format!("'{}' is not a range end", value)),
+    };
+    if let Some(string) = member.attribute("string") {
+        return Ok(quoted(string));
+    }
+    if let Some(hex) = member.attribute("hex") {
+        return hex_char(hex).map(|_| format!("#{}", hex));
+    }
+    if let Some(code) = member.attribute("code") {
+        return Ok(code.to_string());
+    }
+    Ok(format!(
+        "{}-{}",
+        end(required(member, "from")?)?,
+        end(required(member, "to")?)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::IxmlGrammar;
+    use crate::grammar_parser::{parse_ixml_grammar_with_options, GrammarOptions};
+
+    #[test]
+    fn test_to_vxml() {
+        let options = GrammarOptions {
+            allow_extensions: true,
+            ..GrammarOptions::default()
+        };
+        let grammar = parse_ixml_grammar_with_options(
+            r#"ixml version "1.0". -s: (a; ^b)**(",", " "?), +#a, -"x"?. @a: ~["a"-"z"; #30-#39; L; "xy"]. b: +"in", [#30-"9"; "a"-"z"; ~"aeiou"], !"q", "a"++","."#,
+            &options,
+        )
+        .unwrap();
+        let lines = [
+            "<ixml><version string='1.0'/>",
+            "<rule mark='-' name='s'><alt>",
+            "<repeat0><alts><alt><nonterminal name='a'/></alt><alt><nonterminal mark='^' name='b'/></alt></alts>",
+            "<sep><alts><alt><literal string=','/><option><literal string=' '/></option></alt></alts></sep></repeat0>",
+            "<insertion hex='a'/><option><literal tmark='-' string='x'/></option></alt></rule>",
+            "<rule mark='@' name='a'><alt><exclusion><member from='a' to='z'/><member from='#30' to='#39'/>",
+            "<member code='L'/><member string='xy'/></exclusion></alt></rule>",
+            "<rule name='b'><alt><insertion string='in'/><inclusion>",
+            "<member from='#30' to='#39'/><member from='#62' to='#64'/><member from='#66' to='#68'/>",
+            "<member from='#6a' to='#6e'/><member from='#70' to='#74'/><member from='#76' to='#7a'/></inclusion>",
+            "<not><literal string='q'/></not><repeat1><literal string='a'/><sep><literal string=','/></sep></repeat1>",
+            "</alt></rule></ixml>",
+        ];
+        assert_eq!(grammar.to_vxml().to_xml(), lines.concat());
+    }
+
+    #[test]
+    fn test_from_vxml_round_trip() {
+        for fixture in crate::fixtures::FIXTURES {
+            let grammar = fixture.grammar_ast();
+            let vxml = grammar.to_vxml();
+            let read = IxmlGrammar::from_vxml_str(&vxml.to_xml()).unwrap();
+            assert_eq!(read.to_vxml(), vxml, "{}", fixture.name);
+            assert_eq!(
+                crate::NativeParser::new(read).parse(fixture.input).unwrap(),
+                fixture.expected
+            );
+        }
+
+        // Output of the iXML grammar for iXML has a prolog, comments and spacing
+        let read = IxmlGrammar::from_vxml_str(
+            r##"<ixml>
+  <prolog><version string="1.0"/></prolog>
+  <comment> numbers </comment>
+  <rule name="n">
+    <alt><repeat1><inclusion><member from="#30" to="9"/></inclusion><sep><literal tmark="-" hex="2c"/></sep></repeat1></alt>
+    <alt><nonterminal mark="@" name="x"/><alts><alt><literal string='a"b'/></alt><alt/></alts></alt>
+  </rule>
+</ixml>"##,
+        )
+        .unwrap();
+        assert_eq!(read.version.as_deref(), Some("1.0"));
+        assert_eq!(
+            read.to_string(),
+            "ixml version \"1.0\".\n\nn: [#30-\"9\"]++-\",\"; @x, (\"a\"\"b\"; ).\n"
+        );
+    }
+
+    #[test]
+    fn test_from_vxml_errors() {
+        let error = |xml: &str| IxmlGrammar::from_vxml_str(xml).unwrap_err();
+        assert_eq!(error("<grammar/>"), "Expected <ixml>, found <grammar>");
+        assert_eq!(
+            error("<ixml><rule/></ixml>"),
+            "<rule> has no name attribute"
+        );
+        assert_eq!(
+            error("<ixml><rule name='a'/></ixml>"),
+            "<rule> has no <alt>"
+        );
+        assert_eq!(
+            error("<ixml><rule name='a'><alt><nonterminal name='b' mark='!'/></alt></rule></ixml>"),
+            "<nonterminal> has mark='!', expected '@', '-' or '^'"
+        );
+        assert_eq!(
+            error("<ixml><rule name='a'><alt><literal hex='zz'/></alt></rule></ixml>"),
+            "'zz' is not a hex character"
+        );
+        assert_eq!(
+            error("<ixml><rule name='a'><alt><option><literal string='x'/><sep/></option></alt></rule></ixml>"),
+            "Unexpected <sep> in <option>"
+        );
+    }
+}
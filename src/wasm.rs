@@ -5,7 +5,13 @@
 
 #![cfg(all(target_arch = "wasm32", not(feature = "ic-canister")))]
 
-use crate::{parse_ixml_grammar, NativeParser};
+use crate::grammar_analysis::GrammarAnalysis;
+use crate::input_stream::InputStream;
+use crate::{parse_ixml_grammar, NativeParser, ParseError, XmlNode};
+use js_sys::{Array, Atomics, Function, Int32Array, Object, Reflect, JSON};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{self, Read};
 use wasm_bindgen::prelude::*;
 
 // Set panic hook for better error messages in browser
@@ -19,6 +25,144 @@ pub fn set_panic_hook() {
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+// TypeScript declarations for the plain objects passed to JavaScript, which
+// wasm-bindgen cannot derive; added to the generated `rustixml.d.ts`
+#[wasm_bindgen(typescript_custom_section)]
+const TS_OBJECT_TYPES: &str = r#"
+/** Where and why a parse failed (see `ParseResult.errorInfo`). */
+export interface ParseErrorInfo {
+    message: string;
+    /** Line of the failure, counted from 1 */
+    line?: number;
+    /** Column of the failure, counted from 1 in characters */
+    column?: number;
+    /** UTF-16 index of the failure into the input, as editors count it */
+    offset?: number;
+    /** What would have matched at the failure, in iXML notation */
+    expected: string[];
+    /** Whether the parse was stopped by its progress callback or cancel flag */
+    cancelled: boolean;
+}
+
+/** Static analysis of a grammar (see `analyzeGrammar`), as `rustixml analyze --json` writes it. */
+export interface GrammarAnalysisReport {
+    undefined_references: { rule: string; name: string; suggestion: string | null }[];
+    potentially_ambiguous: boolean;
+    left_recursive_rules: string[];
+    recursive_rules: string[];
+    backtracking_hazards: {
+        rule: string;
+        pattern: string;
+        kind: "nullable-element" | "nested-repetition" | "overlapping-alternatives";
+        reproduction: string | null;
+    }[];
+    alternative_overlaps: { rule: string; first: number; second: number; overlap: string }[];
+    unused_rules: string[];
+    unreachable_rules: string[];
+    /** Complexity score of every rule */
+    complexity: Record<string, number>;
+}
+
+/** Called with the furthest character position a parse has reached; returning `false` stops it. */
+export type ProgressCallback = (position: number) => boolean | void;
+
+/** An element of a result tree from `WasmGrammar.parseToObject`. */
+export interface XmlElement {
+    name: string;
+    attributes: Record<string, string>;
+    children: XmlChild[];
+}
+
+/** Child of an `XmlElement`: an element, text, or an unattached `{name: value}` attribute. */
+export type XmlChild = XmlElement | string | Record<string, string>;
+"#;
+
+/// Where and why a parse failed, passed to JavaScript as a plain object
+/// `{message, line, column, offset, expected}`
+///
+/// `line` and `column` count characters from 1. `offset` is the UTF-16 index
+/// into the input, as JavaScript strings and editors count it. The position
+/// fields are `undefined` when the failure has no place in the input.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseErrorInfo {
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub offset: Option<usize>,
+    /// What would have matched at the failure, in iXML notation
+    pub expected: Vec<String>,
+    /// Whether the parse was stopped by its progress callback
+    pub cancelled: bool,
+}
+
+impl ParseErrorInfo {
+    /// A failure with no position, such as an invalid grammar
+    pub fn message(message: impl Into<String>) -> Self {
+        ParseErrorInfo {
+            message: message.into(),
+            line: None,
+            column: None,
+            offset: None,
+            expected: Vec::new(),
+            cancelled: false,
+        }
+    }
+
+    /// Describe `error`, raised while parsing `input`
+    ///
+    /// The position is the furthest point the parse reached, where the
+    /// input stopped matching, when that is known.
+    pub fn from_parse_error(error: &ParseError, input: &str) -> Self {
+        let inner = unlocated(error);
+        let position = match (inner.farthest_failure(), inner) {
+            (Some(failure), _) => Some(failure.position),
+            (None, ParseError::InstructionLimitExceeded { .. }) => None,
+            (None, _) => Some(inner.position()),
+        };
+        let (line, column) = match position {
+            Some(position) => {
+                let (line, column) = crate::input_stream::line_col(input, position);
+                (Some(line), Some(column))
+            }
+            None => (None, None),
+        };
+        ParseErrorInfo {
+            message: inner.to_string(),
+            line,
+            column,
+            offset: position
+                .map(|position| input.chars().take(position).map(char::len_utf16).sum()),
+            expected: error.expected().to_vec(),
+            cancelled: error.is_cancelled(),
+        }
+    }
+
+    /// Describe `error`, raised while parsing streamed input
+    ///
+    /// The input is no longer available, so the position is the line and
+    /// column the stream located the error at, and `offset` is left out.
+    pub fn from_streamed_error(error: &ParseError) -> Self {
+        let (line, column) = error.location().unzip();
+        ParseErrorInfo {
+            message: unlocated(error).to_string(),
+            line,
+            column,
+            offset: None,
+            expected: error.expected().to_vec(),
+            cancelled: error.is_cancelled(),
+        }
+    }
+}
+
+/// The error a [located](ParseError::located) error wraps, whose message
+/// leaves out the position and context
+fn unlocated(error: &ParseError) -> &ParseError {
+    match error {
+        ParseError::Located { error, .. } => error,
+        _ => error,
+    }
+}
+
 /// Result type for JavaScript interop
 #[wasm_bindgen]
 #[derive(Debug)]
@@ -26,27 +170,381 @@ pub struct ParseResult {
     success: bool,
     output: String,
     error: Option<String>,
+    error_info: Option<ParseErrorInfo>,
 }
 
 #[wasm_bindgen]
 impl ParseResult {
+    /// Whether the input matched the grammar
     #[wasm_bindgen(getter)]
     pub fn success(&self) -> bool {
         self.success
     }
 
+    /// The XML result, empty on failure
     #[wasm_bindgen(getter)]
     pub fn output(&self) -> String {
         self.output.clone()
     }
 
+    /// The error message, or `undefined` on success
     #[wasm_bindgen(getter)]
     pub fn error(&self) -> Option<String> {
         self.error.clone()
     }
+
+    /// The failure as a [`ParseErrorInfo`] object, or `undefined` on success
+    #[wasm_bindgen(
+        getter,
+        js_name = errorInfo,
+        unchecked_return_type = "ParseErrorInfo | undefined"
+    )]
+    pub fn error_info(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.error_info).unwrap_or(JsValue::UNDEFINED)
+    }
+}
+
+impl ParseResult {
+    /// The result of parsing `input`, or of parsing streamed input if it is `None`
+    fn from_parse(result: Result<String, ParseError>, input: Option<&str>) -> Self {
+        match result {
+            Ok(xml) => ParseResult {
+                success: true,
+                output: xml,
+                error: None,
+                error_info: None,
+            },
+            Err(e) => ParseResult {
+                success: false,
+                output: String::new(),
+                error: Some(e.to_string()),
+                error_info: Some(match input {
+                    Some(input) => ParseErrorInfo::from_parse_error(&e, input),
+                    None => ParseErrorInfo::from_streamed_error(&e),
+                }),
+            },
+        }
+    }
+
+    /// A failure before any input was parsed
+    fn failure(error: String) -> Self {
+        ParseResult {
+            success: false,
+            output: String::new(),
+            error_info: Some(ParseErrorInfo::message(error.clone())),
+            error: Some(error),
+        }
+    }
+}
+
+/// Parse and check an iXML grammar, ready to parse inputs
+fn compile_parser(grammar: &str) -> Result<NativeParser, JsValue> {
+    #[cfg(feature = "console_error_panic_hook")]
+    set_panic_hook();
+
+    let ast = parse_ixml_grammar(grammar)
+        .map_err(|e| JsValue::from_str(&format!("Grammar parse error: {}", e)))?;
+
+    NativeParser::try_new(ast).map_err(|e| JsValue::from_str(&format!("Grammar error: {}", e)))
+}
+
+/// A grammar compiled once and reused for any number of parses
+///
+/// ```js
+/// const grammar = WasmGrammar.compile('number: ["0"-"9"]+.');
+/// grammar.parse("42").output; // "<number>42</number>"
+/// grammar.free();
+/// ```
+#[wasm_bindgen]
+pub struct WasmGrammar {
+    parser: NativeParser,
+}
+
+#[wasm_bindgen]
+impl WasmGrammar {
+    /// Compile an iXML grammar, throwing the grammar error if it is invalid
+    pub fn compile(grammar: &str) -> Result<WasmGrammar, JsValue> {
+        compile_parser(grammar).map(|parser| WasmGrammar { parser })
+    }
+
+    /// Parse input text without recompiling the grammar
+    pub fn parse(&self, input: &str) -> ParseResult {
+        ParseResult::from_parse(self.parser.parse(input), Some(input))
+    }
+
+    /// Parse UTF-8 encoded input, such as a Node.js `Buffer` or the bytes of
+    /// a fetched file, without converting it to a JS string first
+    #[wasm_bindgen(js_name = parseBytes)]
+    pub fn parse_bytes(&self, bytes: &[u8]) -> ParseResult {
+        match std::str::from_utf8(bytes) {
+            Ok(input) => self.parse(input),
+            Err(e) => ParseResult::failure(format!(
+                "Input is not valid UTF-8 (byte {})",
+                e.valid_up_to()
+            )),
+        }
+    }
+
+    /// Parse input text, reporting progress and stopping on request
+    ///
+    /// `onProgress` is called with the furthest character position reached
+    /// each time that passes another `every` characters (and periodically
+    /// while backtracking); returning `false` or throwing stops the parse.
+    /// It also stops once `cancel[0]` is non-zero, which another thread can
+    /// set with `Atomics.store` when `cancel` shares a `SharedArrayBuffer`.
+    /// A stopped parse fails with `errorInfo.cancelled` set.
+    #[wasm_bindgen(js_name = parseWithProgress)]
+    pub fn parse_with_progress(
+        &self,
+        input: &str,
+        every: usize,
+        #[wasm_bindgen(js_name = onProgress, unchecked_param_type = "ProgressCallback")]
+        on_progress: Option<Function>,
+        cancel: Option<Int32Array>,
+    ) -> ParseResult {
+        let result =
+            self.parser
+                .parse_with_progress(input, every, progress_callback(on_progress, cancel));
+        ParseResult::from_parse(result, Some(input))
+    }
+
+    /// Parse input as a match for `start` instead of the grammar's first rule
+    #[wasm_bindgen(js_name = parseWithStart)]
+    pub fn parse_with_start(&self, input: &str, start: &str) -> ParseResult {
+        ParseResult::from_parse(self.parser.parse_with_start(input, start), Some(input))
+    }
+
+    /// Parse input text into a tree of plain JS values, skipping the XML string
+    ///
+    /// Elements become `{name, attributes, children}` objects and text becomes
+    /// strings, as in [`XmlNode::to_json`]. Throws a [`ParseErrorInfo`] object
+    /// if the input does not match.
+    #[wasm_bindgen(js_name = parseToObject, unchecked_return_type = "XmlElement")]
+    pub fn parse_to_object(&self, input: &str) -> Result<JsValue, JsValue> {
+        match self.parser.parse_to_tree(input) {
+            Ok(tree) => Ok(tree_to_js(&tree)),
+            Err(e) => Err(serde_wasm_bindgen::to_value(
+                &ParseErrorInfo::from_parse_error(&e, input),
+            )?),
+        }
+    }
+
+    /// The grammar's static analysis, computed when it was compiled
+    #[wasm_bindgen(getter, unchecked_return_type = "GrammarAnalysisReport")]
+    pub fn analysis(&self) -> Result<JsValue, JsValue> {
+        analysis_to_js(self.parser.compiled().analysis())
+    }
+
+    /// Get the number of rules in the grammar
+    #[wasm_bindgen(getter, js_name = ruleCount)]
+    pub fn rule_count(&self) -> usize {
+        self.parser.rule_count()
+    }
+
+    /// Get the names of the grammar's rules, in grammar order
+    #[wasm_bindgen(getter, js_name = ruleNames)]
+    pub fn rule_names(&self) -> Vec<String> {
+        let grammar = self.parser.compiled().grammar();
+        grammar.rules.iter().map(|rule| rule.name.clone()).collect()
+    }
+}
+
+/// Parses input fed to it in chunks, so the page can yield and show
+/// progress while a large input is read
+///
+/// `feed` only stores the chunks: the whole parse runs in `finish`, reading
+/// them through a streaming [`InputStream`] rather than one joined string.
+/// Feeding in chunks keeps the page responsive while the input is read, not
+/// while it is parsed; report parse progress with `finishWithProgress`, or
+/// run the parser in a worker.
+///
+/// ```js
+/// const parser = new WasmParser(grammar);
+/// for await (const chunk of file.stream().pipeThrough(new TextDecoderStream())) {
+///     progress.value = parser.feed(chunk) / file.size;
+/// }
+/// const result = parser.finish();
+/// ```
+///
+/// In Node.js, feed a file stream's buffers with `feedBytes`:
+///
+/// ```js
+/// for await (const chunk of fs.createReadStream("big.csv")) {
+///     parser.feedBytes(chunk);
+/// }
+/// ```
+#[wasm_bindgen]
+pub struct WasmParser {
+    parser: NativeParser,
+    input: Chunks,
+    received: usize,
+}
+
+#[wasm_bindgen]
+impl WasmParser {
+    /// Create a parser for `grammar`, with its settings, which stays usable
+    /// on its own
+    #[wasm_bindgen(constructor)]
+    pub fn new(grammar: &WasmGrammar) -> WasmParser {
+        WasmParser {
+            parser: grammar.parser.clone(),
+            input: Chunks::default(),
+            received: 0,
+        }
+    }
+
+    /// Add the next chunk of input, returning the UTF-8 bytes received so far
+    pub fn feed(&mut self, chunk: &str) -> usize {
+        self.received += chunk.len();
+        self.input.chunks.push_back(chunk.as_bytes().to_vec());
+        self.received
+    }
+
+    /// Add the next chunk of UTF-8 encoded input, such as a `Buffer` from a
+    /// Node.js stream, returning the bytes received so far
+    ///
+    /// A character may be split between chunks; invalid UTF-8 fails the parse
+    /// in `finish`.
+    #[wasm_bindgen(js_name = feedBytes)]
+    pub fn feed_bytes(&mut self, chunk: Vec<u8>) -> usize {
+        self.received += chunk.len();
+        self.input.chunks.push_back(chunk);
+        self.received
+    }
+
+    /// The UTF-8 bytes received so far, comparable to the input file's size
+    #[wasm_bindgen(getter)]
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// Parse the input fed so far, leaving the parser empty for another input
+    ///
+    /// Runs the whole parse before returning. A failure's `errorInfo` has a line and column but no `offset`.
+    pub fn finish(&mut self) -> ParseResult {
+        let input = std::mem::take(&mut self.input);
+        self.received = 0;
+        let result = self.parser.parse_stream(InputStream::from_reader(input));
+        ParseResult::from_parse(result, None)
+    }
+
+    /// Like `finish`, reporting progress and stopping on request as
+    /// [`WasmGrammar::parse_with_progress`] does
+    #[wasm_bindgen(js_name = finishWithProgress)]
+    pub fn finish_with_progress(
+        &mut self,
+        every: usize,
+        #[wasm_bindgen(js_name = onProgress, unchecked_param_type = "ProgressCallback")]
+        on_progress: Option<Function>,
+        cancel: Option<Int32Array>,
+    ) -> ParseResult {
+        let input = std::mem::take(&mut self.input);
+        self.received = 0;
+        let result = self.parser.parse_stream_with_progress(
+            InputStream::from_reader(input),
+            every,
+            progress_callback(on_progress, cancel),
+        );
+        ParseResult::from_parse(result, None)
+    }
+}
+
+/// The native progress callback for a JS callback and cancel flag
+fn progress_callback(
+    on_progress: Option<Function>,
+    cancel: Option<Int32Array>,
+) -> impl FnMut(usize) -> bool {
+    move |position| {
+        if let Some(cancel) = &cancel {
+            if Atomics::load(cancel, 0).unwrap_or(0) != 0 {
+                return false;
+            }
+        }
+        match &on_progress {
+            Some(on_progress) => on_progress
+                .call1(&JsValue::NULL, &JsValue::from(position as f64))
+                .is_ok_and(|result| result.as_bool() != Some(false)),
+            None => true,
+        }
+    }
+}
+
+/// Input fed to a [`WasmParser`], read in order and freed chunk by chunk
+#[derive(Default)]
+struct Chunks {
+    chunks: VecDeque<Vec<u8>>,
+    /// Bytes of the first chunk already read
+    offset: usize,
+}
+
+impl Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(chunk) = self.chunks.front() {
+            let rest = &chunk[self.offset..];
+            if !rest.is_empty() {
+                let n = rest.len().min(buf.len());
+                buf[..n].copy_from_slice(&rest[..n]);
+                self.offset += n;
+                return Ok(n);
+            }
+            self.chunks.pop_front();
+            self.offset = 0;
+        }
+        Ok(0)
+    }
+}
+
+/// Convert a result tree to JS objects, arrays and strings
+///
+/// Works through the tree with an explicit stack, so deep trees cannot
+/// overflow the (small) WASM call stack.
+fn tree_to_js(tree: &XmlNode) -> JsValue {
+    let set = |object: &Object, key: &str, value: &JsValue| {
+        // Setting a property on a plain object cannot fail
+        let _ = Reflect::set(object, &JsValue::from_str(key), value);
+    };
+
+    let root = Array::new();
+    let mut stack = vec![(tree, root.clone())];
+    while let Some((node, parent)) = stack.pop() {
+        match node {
+            XmlNode::Element {
+                name,
+                attributes,
+                children,
+            } => {
+                let attrs = Object::new();
+                for (name, value) in attributes {
+                    set(&attrs, name, &JsValue::from_str(value));
+                }
+                let list = Array::new();
+                let element = Object::new();
+                set(&element, "name", &JsValue::from_str(name));
+                set(&element, "attributes", &attrs);
+                set(&element, "children", &list);
+                parent.push(&element);
+                for child in children.iter().rev() {
+                    if !matches!(child, XmlNode::Attribute { .. }) {
+                        stack.push((child, list.clone()));
+                    }
+                }
+            }
+            XmlNode::Text(text) => {
+                parent.push(&JsValue::from_str(text));
+            }
+            XmlNode::Attribute { name, value } => {
+                let attribute = Object::new();
+                set(&attribute, name, &JsValue::from_str(value));
+                parent.push(&attribute);
+            }
+        }
+    }
+    root.get(0)
 }
 
 /// WASM-friendly iXML parser
+///
+/// Equivalent to [`WasmGrammar`], kept for `new IxmlParser(grammar)` callers.
 #[wasm_bindgen]
 pub struct IxmlParser {
     parser: NativeParser,
@@ -57,31 +555,12 @@ impl IxmlParser {
     /// Create a new parser from an iXML grammar
     #[wasm_bindgen(constructor)]
     pub fn new(grammar: &str) -> Result<IxmlParser, JsValue> {
-        #[cfg(feature = "console_error_panic_hook")]
-        set_panic_hook();
-
-        let ast = parse_ixml_grammar(grammar)
-            .map_err(|e| JsValue::from_str(&format!("Grammar parse error: {}", e)))?;
-
-        Ok(IxmlParser {
-            parser: NativeParser::new(ast),
-        })
+        compile_parser(grammar).map(|parser| IxmlParser { parser })
     }
 
     /// Parse input text according to the grammar
     pub fn parse(&self, input: &str) -> ParseResult {
-        match self.parser.parse(input) {
-            Ok(xml) => ParseResult {
-                success: true,
-                output: xml,
-                error: None,
-            },
-            Err(e) => ParseResult {
-                success: false,
-                output: String::new(),
-                error: Some(e.to_string()),
-            },
-        }
+        ParseResult::from_parse(self.parser.parse(input), Some(input))
     }
 
     /// Get the number of rules in the grammar (for debugging)
@@ -91,18 +570,34 @@ impl IxmlParser {
 }
 
 /// Convenience function: parse grammar and input in one step
+///
+/// Compiles the grammar on every call; use [`WasmGrammar`] to parse several
+/// inputs with one grammar.
 #[wasm_bindgen]
 pub fn parse_ixml(grammar: &str, input: &str) -> ParseResult {
-    match IxmlParser::new(grammar) {
-        Ok(parser) => parser.parse(input),
-        Err(e) => ParseResult {
-            success: false,
-            output: String::new(),
-            error: Some(format!("{:?}", e)),
-        },
+    match WasmGrammar::compile(grammar) {
+        Ok(grammar) => grammar.parse(input),
+        Err(e) => ParseResult::failure(e.as_string().unwrap_or_else(|| format!("{:?}", e))),
     }
 }
 
+/// Analyze a grammar for left recursion, ambiguity, backtracking hazards,
+/// unused rules and complexity, for grammar editors
+///
+/// Unlike [`WasmGrammar::compile`] this accepts grammars with undefined rules,
+/// which the report lists; it throws only if the grammar does not parse.
+#[wasm_bindgen(js_name = analyzeGrammar, unchecked_return_type = "GrammarAnalysisReport")]
+pub fn analyze_grammar(grammar: &str) -> Result<JsValue, JsValue> {
+    let ast = parse_ixml_grammar(grammar)
+        .map_err(|e| JsValue::from_str(&format!("Grammar parse error: {}", e)))?;
+    analysis_to_js(&GrammarAnalysis::analyze(&ast))
+}
+
+/// Convert an analysis to the object its JSON describes
+fn analysis_to_js(analysis: &GrammarAnalysis) -> Result<JsValue, JsValue> {
+    JSON::parse(&analysis.to_json())
+}
+
 /// Get version information
 #[wasm_bindgen]
 pub fn version() -> String {
@@ -112,7 +607,13 @@ pub fn version() -> String {
 /// Get conformance information
 #[wasm_bindgen]
 pub fn conformance_info() -> String {
-    "83.7% conformance (41/49 tests passing)".to_string()
+    crate::capabilities().conformance_summary()
+}
+
+/// Get supported iXML features as JSON (see [`crate::capabilities::Capabilities::to_json`])
+#[wasm_bindgen]
+pub fn capabilities_json() -> String {
+    crate::capabilities().to_json()
 }
 
 // ============================================================================
@@ -2,8 +2,46 @@
 //!
 //! This module defines the XML output structure produced by the native parser.
 
+use crate::ast::IxmlGrammar;
+use crate::convert::json_string;
+use crate::outcome::DocumentState;
+use std::borrow::Cow;
+
+/// Namespace for `ixml:` attributes such as `ixml:state`
+pub const IXML_NAMESPACE: &str = "http://invisiblexml.org/NS";
+
+/// Pragma that gives a rule's elements a constant attribute: `{[fixed-attribute name "value"]}`
+pub const FIXED_ATTRIBUTE_PRAGMA: &str = "fixed-attribute";
+
+/// Pragma that puts a rule's elements in a namespace: `{[namespace svg "uri"]}`
+/// writes them as `svg:name`, `{[namespace "uri"]}` makes it the default namespace
+pub const NAMESPACE_PRAGMA: &str = "namespace";
+
 /// XML node types for parse results
-#[derive(Debug, Clone, PartialEq)]
+///
+/// ```
+/// use rustixml::{fixtures::DATE, XmlNode};
+///
+/// let tree = DATE.parser().parse_to_tree(DATE.input).unwrap();
+/// assert_eq!(tree.attribute("year"), Some("2024"));
+///
+/// let built = XmlNode::element("date")
+///     .with_attribute("year", "2024")
+///     .with_attribute("month", "03")
+///     .with_attribute("day", "15");
+/// assert_eq!(built, tree);
+///
+/// let note = XmlNode::element("note").with_child(XmlNode::text("a < b"));
+/// assert_eq!(note.to_xml(), "<note>a &lt; b</note>");
+/// ```
+///
+/// Cloning, dropping, and serializing walk the tree with an explicit stack, so
+/// arbitrarily deep trees are safe.
+///
+/// With the `serde` feature, nodes implement `Serialize` and `Deserialize`.
+/// Those recurse, so very deep trees can overflow the stack there.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, PartialEq)]
 pub enum XmlNode {
     Element {
         name: String,
@@ -17,74 +55,1750 @@ pub enum XmlNode {
     }, // For @mark - to be extracted by parent
 }
 
+/// The owned contents of an [`XmlNode`]
+///
+/// `XmlNode` implements `Drop` (to free deep trees without recursion), so its
+/// fields cannot be moved out by pattern matching. Match on
+/// [`XmlNode::into_parts`] instead when consuming a node.
+#[derive(Debug, PartialEq)]
+pub enum XmlParts {
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<XmlNode>,
+    },
+    Text(String),
+    Attribute {
+        name: String,
+        value: String,
+    },
+}
+
+impl From<XmlParts> for XmlNode {
+    fn from(parts: XmlParts) -> Self {
+        match parts {
+            XmlParts::Element {
+                name,
+                attributes,
+                children,
+            } => XmlNode::Element {
+                name,
+                attributes,
+                children,
+            },
+            XmlParts::Text(text) => XmlNode::Text(text),
+            XmlParts::Attribute { name, value } => XmlNode::Attribute { name, value },
+        }
+    }
+}
+
 impl XmlNode {
-    /// Extract text content from a node (for attributes)
-    pub fn text_content(&self) -> String {
+    /// Consume the node, returning its contents
+    pub fn into_parts(mut self) -> XmlParts {
+        use std::mem::take;
+        match &mut self {
+            XmlNode::Element {
+                name,
+                attributes,
+                children,
+            } => XmlParts::Element {
+                name: take(name),
+                attributes: take(attributes),
+                children: take(children),
+            },
+            XmlNode::Text(text) => XmlParts::Text(take(text)),
+            XmlNode::Attribute { name, value } => XmlParts::Attribute {
+                name: take(name),
+                value: take(value),
+            },
+        }
+    }
+
+    /// Create an element with no attributes or children
+    pub fn element(name: impl Into<String>) -> Self {
+        XmlNode::Element {
+            name: name.into(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Create a text node
+    pub fn text(text: impl Into<String>) -> Self {
+        XmlNode::Text(text.into())
+    }
+
+    /// Add an attribute to an element (other node kinds are returned unchanged)
+    pub fn with_attribute(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        if let XmlNode::Element { attributes, .. } = &mut self {
+            attributes.push((name.into(), value.into()));
+        }
+        self
+    }
+
+    /// Append a child to an element (other node kinds are returned unchanged)
+    pub fn with_child(mut self, child: XmlNode) -> Self {
+        if let XmlNode::Element { children, .. } = &mut self {
+            children.push(child);
+        }
+        self
+    }
+
+    /// Add an attribute to an element; the same as [`with_attribute`](Self::with_attribute)
+    ///
+    /// ```
+    /// use rustixml::XmlNode;
+    ///
+    /// let item = XmlNode::element("item")
+    ///     .attr("id", "1")
+    ///     .child(XmlNode::text("first"));
+    /// assert_eq!(item.to_xml(), "<item id='1'>first</item>");
+    /// ```
+    pub fn attr(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.with_attribute(name, value)
+    }
+
+    /// Append a child to an element; the same as [`with_child`](Self::with_child)
+    pub fn child(self, child: XmlNode) -> Self {
+        self.with_child(child)
+    }
+
+    /// Append a child to an element (nothing happens for other node kinds)
+    pub fn push_child(&mut self, child: XmlNode) {
+        if let XmlNode::Element { children, .. } = self {
+            children.push(child);
+        }
+    }
+
+    /// Insert a child at `index`, shifting later children along
+    ///
+    /// # Panics
+    ///
+    /// If `index` is greater than the number of children.
+    pub fn insert_child(&mut self, index: usize, child: XmlNode) {
+        if let XmlNode::Element { children, .. } = self {
+            children.insert(index, child);
+        }
+    }
+
+    /// Remove and return the child at `index`, if there is one
+    pub fn remove_child(&mut self, index: usize) -> Option<XmlNode> {
+        match self {
+            XmlNode::Element { children, .. } if index < children.len() => {
+                Some(children.remove(index))
+            }
+            _ => None,
+        }
+    }
+
+    /// An element's children, for editing in place
+    pub fn children_mut(&mut self) -> Option<&mut Vec<XmlNode>> {
         match self {
-            XmlNode::Text(s) => s.clone(),
-            XmlNode::Element { children, .. } => children
+            XmlNode::Element { children, .. } => Some(children),
+            _ => None,
+        }
+    }
+
+    /// Rename an element
+    pub fn set_name(&mut self, new_name: impl Into<String>) {
+        if let XmlNode::Element { name, .. } = self {
+            *name = new_name.into();
+        }
+    }
+
+    /// Set an attribute of an element, replacing its value if already present
+    ///
+    /// ```
+    /// use rustixml::XmlNode;
+    ///
+    /// let mut row = XmlNode::element("row").attr("n", "1");
+    /// row.set_attribute("n", "2");
+    /// row.set_attribute("last", "yes");
+    /// assert_eq!(row.remove_attribute("last").as_deref(), Some("yes"));
+    /// row.push_child(XmlNode::element("field"));
+    /// assert!(row.remove_child(0).is_some());
+    /// assert_eq!(row.to_xml(), "<row n='2'/>");
+    /// ```
+    pub fn set_attribute(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        if let XmlNode::Element { attributes, .. } = self {
+            let name = name.into();
+            match attributes.iter_mut().find(|(k, _)| *k == name) {
+                Some((_, old)) => *old = value.into(),
+                None => attributes.push((name, value.into())),
+            }
+        }
+    }
+
+    /// Remove an attribute from an element, returning its value
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        match self {
+            XmlNode::Element { attributes, .. } => {
+                let index = attributes.iter().position(|(k, _)| k == name)?;
+                Some(attributes.remove(index).1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get an attribute value of an element by name
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        match self {
+            XmlNode::Element { attributes, .. } => attributes
                 .iter()
-                .map(|child| child.text_content())
-                .collect::<Vec<_>>()
-                .join(""),
-            XmlNode::Attribute { value, .. } => value.clone(),
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str()),
+            _ => None,
         }
     }
 
-    fn escape_xml_attr(s: &str) -> String {
-        // We use single quotes for attribute values
-        // Per XML spec, in attributes we must escape: &, <, ' (when using single quotes)
-        s.replace('&', "&amp;")
-            .replace('<', "&lt;")
-            .replace('\'', "&apos;")
+    /// The prefix of an element's name, e.g. `svg` for `svg:rect`
+    pub fn prefix(&self) -> Option<&str> {
+        match self {
+            XmlNode::Element { name, .. } => name.split_once(':').map(|(prefix, _)| prefix),
+            _ => None,
+        }
+    }
+
+    /// An element's name without its prefix
+    pub fn local_name(&self) -> Option<&str> {
+        match self {
+            XmlNode::Element { name, .. } => Some(name.split_once(':').map_or(name, |(_, l)| l)),
+            _ => None,
+        }
     }
 
-    fn escape_xml_text(s: &str) -> String {
-        // In text content, we must escape: &, <
-        // Note: > can optionally be escaped but is not required by XML spec
-        s.replace('&', "&amp;").replace('<', "&lt;")
+    /// The namespaces an element declares with `xmlns` attributes, as
+    /// `(prefix, uri)`; the default namespace has no prefix
+    pub fn namespace_declarations(&self) -> Vec<(Option<&str>, &str)> {
+        match self {
+            XmlNode::Element { attributes, .. } => attributes
+                .iter()
+                .filter_map(|(k, v)| Some((xmlns_prefix(k)?, v.as_str())))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Put an element in namespace `uri` and declare it there, under `prefix`
+    /// or as the default namespace
+    ///
+    /// ```
+    /// use rustixml::XmlNode;
+    ///
+    /// let rect = XmlNode::element("rect").with_namespace(Some("svg"), "http://www.w3.org/2000/svg");
+    /// assert_eq!((rect.prefix(), rect.local_name()), (Some("svg"), Some("rect")));
+    /// assert_eq!(rect.namespace_declarations(), [(Some("svg"), "http://www.w3.org/2000/svg")]);
+    /// assert_eq!(rect.to_xml(), "<svg:rect xmlns:svg='http://www.w3.org/2000/svg'/>");
+    /// ```
+    pub fn with_namespace(mut self, prefix: Option<&str>, uri: impl Into<String>) -> Self {
+        if let XmlNode::Element {
+            name, attributes, ..
+        } = &mut self
+        {
+            let local = name.split_once(':').map_or(name.as_str(), |(_, l)| l);
+            *name = match prefix {
+                Some(prefix) => format!("{}:{}", prefix, local),
+                None => local.to_string(),
+            };
+            attributes.push((xmlns_attribute(prefix).into_owned(), uri.into()));
+        }
+        self
+    }
+
+    /// Extract text content from a node (for attributes)
+    pub fn text_content(&self) -> String {
+        let mut text = String::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            match node {
+                XmlNode::Text(s) => text.push_str(s),
+                XmlNode::Element { children, .. } => stack.extend(children.iter().rev()),
+                XmlNode::Attribute { value, .. } => text.push_str(value),
+            }
+        }
+        text
+    }
+
+    /// Set `ixml:state` on an element (e.g. `"ambiguous"`)
+    ///
+    /// Multiple states are space-separated, as the spec requires. The
+    /// `xmlns:ixml` declaration is added when the element is serialized.
+    pub fn set_ixml_state(&mut self, state: &str) {
+        if let XmlNode::Element { attributes, .. } = self {
+            match attributes.iter_mut().find(|(k, _)| k == "ixml:state") {
+                Some((_, value)) => {
+                    if !value.split_whitespace().any(|s| s == state) {
+                        value.push(' ');
+                        value.push_str(state);
+                    }
+                }
+                None => attributes.push(("ixml:state".to_string(), state.to_string())),
+            }
+        }
+    }
+
+    /// Get the `ixml:state` attribute of an element, if any
+    pub fn ixml_state(&self) -> Option<&str> {
+        self.attribute("ixml:state")
     }
 
     /// Convert to XML string
     pub fn to_xml(&self) -> String {
-        self.to_xml_internal(0, "")
+        self.to_xml_with(&FixedAttributes::default())
+    }
+
+    /// Convert to XML string, adding `fixed` attributes to the elements they target
+    pub fn to_xml_with(&self, fixed: &FixedAttributes) -> String {
+        self.to_xml_limited(fixed, None).0
+    }
+
+    /// Convert to XML string laid out as `options` say
+    pub fn to_xml_with_options(&self, options: &SerializeOptions) -> String {
+        self.write_xml(&FixedAttributes::default(), options, None).0
+    }
+
+    /// Convert to JSON
+    ///
+    /// Elements become `{"name", "attributes", "children"}` objects, with the
+    /// attributes as an object in document order; text becomes a string.
+    ///
+    /// ```
+    /// use rustixml::XmlNode;
+    ///
+    /// let tree = XmlNode::element("day")
+    ///     .attr("n", "15")
+    ///     .child(XmlNode::text("Mon\"day\""))
+    ///     .child(XmlNode::element("br"));
+    /// assert_eq!(
+    ///     tree.to_json(),
+    ///     r#"{"name":"day","attributes":{"n":"15"},"children":["Mon\"day\"",{"name":"br","attributes":{},"children":[]}]}"#
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        enum Item<'a> {
+            Node(&'a XmlNode),
+            Raw(&'static str),
+        }
+
+        let mut json = String::new();
+        let mut stack = vec![Item::Node(self)];
+        while let Some(item) = stack.pop() {
+            let node = match item {
+                Item::Node(node) => node,
+                Item::Raw(text) => {
+                    json.push_str(text);
+                    continue;
+                }
+            };
+            match node {
+                XmlNode::Element {
+                    name,
+                    attributes,
+                    children,
+                } => {
+                    json.push_str("{\"name\":");
+                    json.push_str(&json_string(name));
+                    json.push_str(",\"attributes\":{");
+                    for (i, (name, value)) in attributes.iter().enumerate() {
+                        if i > 0 {
+                            json.push(',');
+                        }
+                        json.push_str(&json_string(name));
+                        json.push(':');
+                        json.push_str(&json_string(value));
+                    }
+                    json.push_str("},\"children\":[");
+                    stack.push(Item::Raw("]}"));
+                    let children: Vec<&XmlNode> = children
+                        .iter()
+                        .filter(|c| !matches!(c, XmlNode::Attribute { .. }))
+                        .collect();
+                    for (i, child) in children.into_iter().enumerate().rev() {
+                        stack.push(Item::Node(child));
+                        if i > 0 {
+                            stack.push(Item::Raw(","));
+                        }
+                    }
+                }
+                XmlNode::Text(s) => json.push_str(&json_string(s)),
+                XmlNode::Attribute { name, value } => {
+                    json.push('{');
+                    json.push_str(&json_string(name));
+                    json.push(':');
+                    json.push_str(&json_string(value));
+                    json.push('}');
+                }
+            }
+        }
+        json
+    }
+
+    /// Convert to XML string, stopping once it grows past `max_bytes`
+    ///
+    /// The elements still open are closed and the root is marked
+    /// `ixml:state="truncated"`, so the output stays well-formed and slightly
+    /// exceeds the limit. Returns a warning if the output was cut short.
+    pub fn to_xml_limited(
+        &self,
+        fixed: &FixedAttributes,
+        max_bytes: Option<usize>,
+    ) -> (String, Option<String>) {
+        self.write_xml(fixed, &SerializeOptions::default(), max_bytes)
+    }
+
+    /// Convert to XML string with `fixed` attributes, laid out as `options`
+    /// say and cut short after `max_bytes` as in [`to_xml_limited`](Self::to_xml_limited)
+    ///
+    /// The XML declaration does not count towards `max_bytes`.
+    pub fn write_xml(
+        &self,
+        fixed: &FixedAttributes,
+        options: &SerializeOptions,
+        max_bytes: Option<usize>,
+    ) -> (String, Option<String>) {
+        /// Where a node goes when pretty-printing
+        #[derive(Clone, Copy, PartialEq)]
+        enum Place {
+            Root,
+            /// On a line of its own
+            Line,
+            /// Inside mixed content, where whitespace would be content
+            Inline,
+        }
+        enum Item<'a> {
+            Open(&'a XmlNode, usize, Place),
+            /// An end tag, on a line of its own if `true`
+            Close(Cow<'a, str>, usize, bool),
+        }
+
+        let escape_text = |s: &str| options.escaping.escape(s, None);
+        let max_bytes = max_bytes.unwrap_or(usize::MAX);
+        let mut xml = String::new();
+        let mut root_tag_end = 0;
+        let mut truncated = false;
+        let mut scope = NamespaceScope::default();
+        let mut stack = vec![Item::Open(self, 0, Place::Root)];
+        while let Some(item) = stack.pop() {
+            let (node, depth, place) = match item {
+                Item::Open(node, depth, place) => {
+                    if place == Place::Line && !(truncated || xml.len() >= max_bytes) {
+                        options.write_line_start(&mut xml, depth);
+                    }
+                    (node, depth, place)
+                }
+                Item::Close(name, depth, own_line) => {
+                    if own_line {
+                        options.write_line_start(&mut xml, depth);
+                    }
+                    xml.push_str("</");
+                    xml.push_str(&name);
+                    xml.push('>');
+                    continue;
+                }
+            };
+            // The root is always written, so there is an element to mark
+            let is_root = std::ptr::eq(node, self);
+            if !is_root && (truncated || xml.len() >= max_bytes) {
+                truncated = true;
+                continue;
+            }
+            match node {
+                XmlNode::Element {
+                    name,
+                    attributes,
+                    children,
+                } => {
+                    let name =
+                        scope.start_tag(depth, name, attributes, fixed, is_root, options, &mut xml);
+                    if children.is_empty() && options.canonical {
+                        xml.push_str("></");
+                        xml.push_str(&name);
+                        xml.push('>');
+                    } else if children.is_empty() {
+                        xml.push_str("/>");
+                    } else {
+                        xml.push('>');
+                        if is_root {
+                            root_tag_end = xml.len();
+                        }
+                        // Mixed content is kept inline, since indenting would add text
+                        let block = options.pretty
+                            && place != Place::Inline
+                            && children.iter().all(|c| !matches!(c, XmlNode::Text(_)));
+                        let child_place = if block { Place::Line } else { Place::Inline };
+                        stack.push(Item::Close(name, depth, block));
+                        stack.extend(
+                            children
+                                .iter()
+                                .rev()
+                                .map(|child| Item::Open(child, depth + 1, child_place)),
+                        );
+                    }
+                }
+                XmlNode::Text(s) if !is_root => {
+                    let escaped = escape_text(s);
+                    if xml.len() + escaped.len() <= max_bytes {
+                        xml.push_str(&escaped);
+                        continue;
+                    }
+                    // Keep the characters that fit, without splitting an escape
+                    let mut end = 0;
+                    let mut len = xml.len();
+                    for (i, ch) in s.char_indices() {
+                        len += escape_text(ch.encode_utf8(&mut [0; 4])).len();
+                        if len > max_bytes {
+                            break;
+                        }
+                        end = i + ch.len_utf8();
+                    }
+                    xml.push_str(&escape_text(&s[..end]));
+                    truncated = true;
+                }
+                XmlNode::Text(s) => xml.push_str(&escape_text(s)),
+                XmlNode::Attribute { .. } => {
+                    // Attributes should have been extracted by parent
+                }
+            }
+        }
+
+        if !truncated {
+            return (options.prepend_declaration(xml), None);
+        }
+        // Rewrite the root's start tag with the truncation marker
+        let XmlNode::Element {
+            name, attributes, ..
+        } = self
+        else {
+            return (options.prepend_declaration(xml), None);
+        };
+        let mut root = XmlNode::Element {
+            name: name.clone(),
+            attributes: attributes.clone(),
+            children: Vec::new(),
+        };
+        root.set_ixml_state(DocumentState::Truncated.as_str());
+        let XmlNode::Element { attributes, .. } = &root else {
+            unreachable!("root is an element");
+        };
+        let mut marked = String::with_capacity(xml.len() + 64);
+        NamespaceScope::default().start_tag(0, name, attributes, fixed, true, options, &mut marked);
+        marked.push('>');
+        marked.push_str(&xml[root_tag_end..]);
+        let warning = format!("Output truncated at {} bytes", max_bytes);
+        (options.prepend_declaration(marked), Some(warning))
+    }
+
+    /// Keep the first `max_nodes` nodes of the tree, in document order, and
+    /// drop the rest
+    ///
+    /// The root is always kept. If anything was dropped, the root is marked
+    /// `ixml:state="truncated"` and a warning is returned.
+    pub fn truncate(&mut self, max_nodes: usize) -> Option<String> {
+        // Find the child indices leading to the first node past the limit
+        let mut path = Vec::new();
+        let mut cut = None;
+        let mut seen = 0;
+        let mut stack = vec![(&*self, 0, 0)];
+        while let Some((node, depth, index)) = stack.pop() {
+            path.truncate(depth);
+            path.push(index);
+            seen += 1;
+            if seen > max_nodes.max(1) {
+                cut = Some(path);
+                break;
+            }
+            if let XmlNode::Element { children, .. } = node {
+                let children = children.iter().enumerate().rev();
+                stack.extend(children.map(|(i, child)| (child, depth + 1, i)));
+            }
+        }
+        let path = cut?;
+
+        // Everything after that node is dropped: its later siblings, and the
+        // later siblings of each of its ancestors
+        let mut node = &mut *self;
+        for (level, &index) in path.iter().enumerate().skip(1) {
+            let XmlNode::Element { children, .. } = node else {
+                unreachable!("only elements have children");
+            };
+            if level == path.len() - 1 {
+                children.truncate(index);
+                break;
+            }
+            children.truncate(index + 1);
+            node = &mut children[index];
+        }
+        self.set_ixml_state(DocumentState::Truncated.as_str());
+        Some(format!("Output truncated to {} nodes", seen - 1))
     }
+}
+
+impl XmlNode {
+    /// Read an XML document back into a tree
+    ///
+    /// Covers what iXML processors write: elements, attributes, text,
+    /// character and predefined entity references, and CDATA sections. The
+    /// XML declaration, comments, processing instructions and doctype are
+    /// skipped, as is whitespace around the root element.
+    ///
+    /// ```
+    /// use rustixml::XmlNode;
+    ///
+    /// let tree = XmlNode::from_xml("<?xml version='1.0'?>\n<a x=\"1\">b &amp; <c/></a>\n").unwrap();
+    /// assert_eq!(tree.to_xml(), "<a x='1'>b &amp; <c/></a>");
+    /// assert_eq!(
+    ///     XmlNode::from_xml("<a><b></a>").unwrap_err(),
+    ///     "Malformed XML at byte 6: </a> closes <b>"
+    /// );
+    /// ```
+    pub fn from_xml(xml: &str) -> Result<XmlNode, String> {
+        let error = |pos: usize, what: String| format!("Malformed XML at byte {}: {}", pos, what);
+        let mut open: Vec<XmlNode> = Vec::new();
+        let mut root = None;
+        let mut pos = 0;
+
+        while pos < xml.len() {
+            let rest = &xml[pos..];
+            let skip_to = |end: &str| {
+                rest.find(end)
+                    .map(|i| pos + i + end.len())
+                    .ok_or_else(|| error(pos, format!("missing '{}'", end)))
+            };
 
-    fn to_xml_internal(&self, _depth: usize, _indent: &str) -> String {
+            if rest.starts_with("<?") {
+                pos = skip_to("?>")?;
+            } else if rest.starts_with("<!--") {
+                pos = skip_to("-->")?;
+            } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let end = skip_to("]]>")?;
+                let text = &cdata[..end - pos - "<![CDATA[]]>".len()];
+                match open.last_mut() {
+                    Some(parent) => push_text(parent, text),
+                    None => return Err(error(pos, "CDATA outside the root element".into())),
+                }
+                pos = end;
+            } else if rest.starts_with("<!") {
+                pos = skip_to(">")?;
+            } else if let Some(tag) = rest.strip_prefix("</") {
+                let end = skip_to(">")?;
+                let name = tag[..end - pos - 3].trim();
+                let element = match open.pop() {
+                    Some(element) => element,
+                    None => return Err(error(pos, format!("</{}> closes nothing", name))),
+                };
+                if let XmlNode::Element {
+                    name: open_name, ..
+                } = &element
+                {
+                    if open_name != name {
+                        return Err(error(pos, format!("</{}> closes <{}>", name, open_name)));
+                    }
+                }
+                match open.last_mut() {
+                    Some(parent) => parent.push_child(element),
+                    None => root = Some(element),
+                }
+                pos = end;
+            } else if rest.starts_with('<') {
+                if root.is_some() {
+                    return Err(error(pos, "more than one root element".into()));
+                }
+                let (end, element, empty) = read_start_tag(xml, pos).map_err(|e| error(pos, e))?;
+                if !empty {
+                    open.push(element);
+                } else if let Some(parent) = open.last_mut() {
+                    parent.push_child(element);
+                } else {
+                    root = Some(element);
+                }
+                pos = end;
+            } else {
+                let end = rest.find('<').map_or(xml.len(), |i| pos + i);
+                let text = unescape_xml(&xml[pos..end]).map_err(|e| error(pos, e))?;
+                match open.last_mut() {
+                    Some(parent) => push_text(parent, &text),
+                    None if text.trim().is_empty() => {}
+                    None => return Err(error(pos, "text outside the root element".into())),
+                }
+                pos = end;
+            }
+        }
+
+        if let Some(XmlNode::Element { name, .. }) = open.last() {
+            return Err(error(xml.len(), format!("<{}> is not closed", name)));
+        }
+        root.ok_or_else(|| error(xml.len(), "no root element".into()))
+    }
+}
+
+/// Append text to an element, merging it with a preceding text node
+fn push_text(element: &mut XmlNode, text: &str) {
+    if let XmlNode::Element { children, .. } = element {
+        match children.last_mut() {
+            Some(XmlNode::Text(previous)) => previous.push_str(text),
+            _ => children.push(XmlNode::text(text)),
+        }
+    }
+}
+
+/// Read the tag starting at `start`; returns the position after it, the
+/// element, and whether it is an empty-element tag (`<a/>`)
+fn read_start_tag(xml: &str, start: usize) -> Result<(usize, XmlNode, bool), String> {
+    let mut quote = None;
+    let end = xml[start..]
+        .char_indices()
+        .find(|&(_, ch)| match quote {
+            Some(q) => {
+                if ch == q {
+                    quote = None;
+                }
+                false
+            }
+            None if ch == '"' || ch == '\'' => {
+                quote = Some(ch);
+                false
+            }
+            None => ch == '>',
+        })
+        .map(|(i, _)| start + i)
+        .ok_or("missing '>'")?;
+
+    let tag = &xml[start + 1..end];
+    let (tag, empty) = match tag.strip_suffix('/') {
+        Some(tag) => (tag, true),
+        None => (tag, false),
+    };
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    if name_end == 0 {
+        return Err("tag without a name".into());
+    }
+    let mut element = XmlNode::element(&tag[..name_end]);
+
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let (name, value) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("attribute without a value in <{}>", tag))?;
+        let value = value.trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|&q| q == '"' || q == '\'')
+            .ok_or_else(|| format!("unquoted attribute value in <{}>", tag))?;
+        let close = value[1..]
+            .find(quote)
+            .ok_or_else(|| format!("unterminated attribute value in <{}>", tag))?;
+        element = element.with_attribute(name.trim(), unescape_xml(&value[1..close + 1])?);
+        rest = value[close + 2..].trim_start();
+    }
+    Ok((end + 1, element, empty))
+}
+
+/// Resolve character references and the predefined entities
+fn unescape_xml(text: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let semi = rest[amp..]
+            .find(';')
+            .ok_or_else(|| format!("unterminated reference '{}'", &rest[amp..]))?;
+        let reference = &rest[amp + 1..amp + semi];
+        let ch = match reference {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match reference.strip_prefix('#') {
+                Some(hex) if hex.starts_with('x') => u32::from_str_radix(&hex[1..], 16)
+                    .ok()
+                    .and_then(char::from_u32),
+                Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                None => None,
+            },
+        };
+        out.push(ch.ok_or_else(|| format!("unknown reference '&{};'", reference))?);
+        rest = &rest[amp + semi + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Caps on the size of result documents, for untrusted input
+///
+/// A document over a limit is cut short rather than built or serialized in
+/// full: its root gets `ixml:state="truncated"` and the parse reports a
+/// warning. Unlimited by default.
+///
+/// ```
+/// use rustixml::{parse_ixml_grammar, xml_node::OutputLimits, NativeParser};
+///
+/// let grammar = parse_ixml_grammar(r#"list: item+. item: ["a"-"z"]."#).unwrap();
+/// let parser = NativeParser::new(grammar)
+///     .with_output_limits(OutputLimits::new().with_max_nodes(3));
+/// let outcome = parser.parse_outcome("abc");
+/// assert_eq!(outcome.warnings(), ["Output truncated to 3 nodes"]);
+/// assert_eq!(
+///     outcome.to_xml(),
+///     "<list ixml:state='truncated' xmlns:ixml='http://invisiblexml.org/NS'>\
+///      <item>a</item></list>"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputLimits {
+    /// Most nodes (elements and text) kept in the tree
+    pub max_nodes: Option<usize>,
+    /// Size in bytes past which serialization stops
+    pub max_bytes: Option<usize>,
+}
+
+impl OutputLimits {
+    /// No limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep at most `max_nodes` nodes of the tree
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    /// Stop serializing once the output reaches `max_bytes`
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+}
+
+/// Line ending written between lines of pretty-printed output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Newline {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl Newline {
+    pub fn as_str(self) -> &'static str {
         match self {
-            XmlNode::Element {
+            Newline::Lf => "\n",
+            Newline::CrLf => "\r\n",
+        }
+    }
+}
+
+/// Which characters are written as references
+///
+/// `&` and `<` always are, and so are the attribute's quote character and
+/// tabs and line breaks in attribute values, which XML parsers would
+/// otherwise normalize to spaces.
+///
+/// ```
+/// use rustixml::xml_node::{Escaping, SerializeOptions};
+/// use rustixml::XmlNode;
+///
+/// let tree = XmlNode::element("t").attr("v", "é").child(XmlNode::text("a > b\r"));
+/// let xml = |escaping| tree.to_xml_with_options(&SerializeOptions::new().with_escaping(escaping));
+/// assert_eq!(xml(Escaping::Minimal), "<t v='é'>a > b\r</t>");
+/// assert_eq!(xml(Escaping::Strict), "<t v='é'>a &gt; b&#xD;</t>");
+/// assert_eq!(xml(Escaping::Ascii), "<t v='&#xE9;'>a &gt; b&#xD;</t>");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Escaping {
+    /// Only what XML requires
+    #[default]
+    Minimal,
+    /// Also `>` and carriage returns in text, as Canonical XML does
+    Strict,
+    /// As `Strict`, and every non-ASCII character as a numeric reference
+    Ascii,
+}
+
+impl Escaping {
+    /// Escape text, or an attribute value delimited by `quote`
+    pub fn escape(self, s: &str, quote: Option<char>) -> String {
+        let strict = self != Escaping::Minimal;
+        let attribute = quote.is_some();
+        let mut out = String::with_capacity(s.len());
+        for ch in s.chars() {
+            match ch {
+                '&' => out.push_str("&amp;"),
+                '<' => out.push_str("&lt;"),
+                '>' if strict && !attribute => out.push_str("&gt;"),
+                '"' if quote == Some('"') => out.push_str("&quot;"),
+                '\'' if quote == Some('\'') => out.push_str("&apos;"),
+                '\t' if attribute => out.push_str("&#x9;"),
+                '\n' if attribute => out.push_str("&#xA;"),
+                '\r' if strict || attribute => out.push_str("&#xD;"),
+                ch if !ch.is_ascii() && self == Escaping::Ascii => {
+                    out.push_str(&format!("&#x{:X};", u32::from(ch)))
+                }
+                ch => out.push(ch),
+            }
+        }
+        out
+    }
+}
+
+/// How serialized XML is laid out
+///
+/// The default is what [`XmlNode::to_xml`] writes: everything on one line,
+/// with no XML declaration. Pretty-printing puts each element on a line of
+/// its own, indented by depth, except inside elements that contain text,
+/// where added whitespace would change the content.
+///
+/// ```
+/// use rustixml::xml_node::{Newline, SerializeOptions};
+/// use rustixml::XmlNode;
+///
+/// let tree = XmlNode::element("date")
+///     .with_child(XmlNode::element("day").with_child(XmlNode::text("15")))
+///     .with_child(XmlNode::element("month").with_child(XmlNode::text("March")));
+/// assert_eq!(
+///     tree.to_xml_with_options(&SerializeOptions::pretty().with_indent('\t', 1)),
+///     "<date>\n\t<day>15</day>\n\t<month>March</month>\n</date>"
+/// );
+///
+/// let options = SerializeOptions::new()
+///     .with_declaration(true)
+///     .with_newline(Newline::CrLf);
+/// assert_eq!(
+///     tree.to_xml_with_options(&options),
+///     "<?xml version=\"1.0\" encoding=\"utf-8\"?><date><day>15</day><month>March</month></date>"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerializeOptions {
+    /// Put elements on lines of their own, indented by depth
+    pub pretty: bool,
+    /// Character repeated to indent when pretty-printing
+    pub indent_char: char,
+    /// Indent characters per level of depth
+    pub indent_width: usize,
+    /// Start with `<?xml version="1.0" encoding="utf-8"?>`
+    pub declaration: bool,
+    /// Line ending for pretty-printing and after the declaration
+    pub newline: Newline,
+    /// Which characters are written as references
+    pub escaping: Escaping,
+    /// Sort and double-quote attributes and write empty elements as Canonical XML does
+    pub canonical: bool,
+}
+
+impl Default for SerializeOptions {
+    fn default() -> Self {
+        SerializeOptions {
+            pretty: false,
+            indent_char: ' ',
+            indent_width: 3,
+            declaration: false,
+            newline: Newline::Lf,
+            escaping: Escaping::Minimal,
+            canonical: false,
+        }
+    }
+}
+
+impl SerializeOptions {
+    /// Compact output without a declaration
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pretty-printed output, indented three spaces per level
+    pub fn pretty() -> Self {
+        Self {
+            pretty: true,
+            ..Self::default()
+        }
+    }
+
+    /// Output in the style of Canonical XML, for comparing documents
+    ///
+    /// Namespace declarations come first, then the other attributes, each
+    /// sorted by name; values are double-quoted; `>` and carriage returns in
+    /// text, and whitespace in attribute values, are written as references;
+    /// empty elements get end tags. There is no declaration or added
+    /// whitespace. Unlike full C14N, attributes sort by name rather than
+    /// namespace URI.
+    ///
+    /// ```
+    /// use rustixml::xml_node::SerializeOptions;
+    /// use rustixml::XmlNode;
+    ///
+    /// let tree = XmlNode::element("p")
+    ///     .attr("z", "a\tb")
+    ///     .attr("ixml:state", "ambiguous")
+    ///     .child(XmlNode::element("br"))
+    ///     .child(XmlNode::text("x > y"));
+    /// assert_eq!(
+    ///     tree.to_xml_with_options(&SerializeOptions::canonical()),
+    ///     "<p xmlns:ixml=\"http://invisiblexml.org/NS\" ixml:state=\"ambiguous\" z=\"a&#x9;b\">\
+    ///      <br></br>x &gt; y</p>"
+    /// );
+    /// ```
+    pub fn canonical() -> Self {
+        Self {
+            escaping: Escaping::Strict,
+            canonical: true,
+            ..Self::default()
+        }
+    }
+
+    /// Indent with `width` copies of `ch` per level
+    pub fn with_indent(mut self, ch: char, width: usize) -> Self {
+        self.indent_char = ch;
+        self.indent_width = width;
+        self
+    }
+
+    /// Write an XML declaration first
+    pub fn with_declaration(mut self, declaration: bool) -> Self {
+        self.declaration = declaration;
+        self
+    }
+
+    pub fn with_newline(mut self, newline: Newline) -> Self {
+        self.newline = newline;
+        self
+    }
+
+    pub fn with_escaping(mut self, escaping: Escaping) -> Self {
+        self.escaping = escaping;
+        self
+    }
+
+    /// Start a line for a node at `depth`
+    fn write_line_start(&self, xml: &mut String, depth: usize) {
+        xml.push_str(self.newline.as_str());
+        xml.extend(std::iter::repeat_n(
+            self.indent_char,
+            depth * self.indent_width,
+        ));
+    }
+
+    /// `xml` after the declaration, if one is wanted
+    fn prepend_declaration(&self, xml: String) -> String {
+        if !self.declaration {
+            return xml;
+        }
+        let newline = if self.pretty {
+            self.newline.as_str()
+        } else {
+            ""
+        };
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>{}{}",
+            newline, xml
+        )
+    }
+}
+
+/// The namespace prefixes bound at the current point of serialization
+///
+/// Start tags declare the namespaces their element needs (the `ixml`
+/// namespace for `ixml:` attributes, a [`NAMESPACE_PRAGMA`] namespace) unless
+/// an enclosing element already did.
+#[derive(Debug, Default)]
+pub(crate) struct NamespaceScope {
+    /// Depth of the declaring element, prefix, URI
+    bindings: Vec<(usize, Option<String>, String)>,
+}
+
+impl NamespaceScope {
+    fn lookup(&self, prefix: Option<&str>) -> Option<&str> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(_, p, _)| p.as_deref() == prefix)
+            .map(|(_, _, uri)| uri.as_str())
+    }
+
+    /// The output name and attributes of an element at `depth`, with the
+    /// namespace declarations it needs
+    pub(crate) fn element<'a>(
+        &mut self,
+        depth: usize,
+        name: &'a str,
+        attributes: &'a [(String, String)],
+        fixed: &'a FixedAttributes,
+        is_root: bool,
+    ) -> (Cow<'a, str>, Vec<(Cow<'a, str>, &'a str)>) {
+        self.bindings.retain(|(d, _, _)| *d < depth);
+        let mut all: Vec<(Cow<str>, &str)> = attributes
+            .iter()
+            .map(|(k, v)| (Cow::Borrowed(k.as_str()), v.as_str()))
+            .chain(
+                fixed
+                    .for_element(name, is_root, attributes)
+                    .into_iter()
+                    .map(|(k, v)| (Cow::Borrowed(k), v)),
+            )
+            .collect();
+        let declared: Vec<Option<&str>> = all.iter().filter_map(|(k, _)| xmlns_prefix(k)).collect();
+        for (k, v) in &all {
+            if let Some(prefix) = xmlns_prefix(k) {
+                self.bindings
+                    .push((depth, prefix.map(str::to_string), v.to_string()));
+            }
+        }
+
+        let namespace = fixed.namespace_of(name);
+        let mut needed = Vec::new();
+        needed.extend(namespace);
+        if all.iter().any(|(k, _)| k.starts_with("ixml:")) {
+            needed.push((Some("ixml"), IXML_NAMESPACE));
+        }
+        let mut declarations = Vec::new();
+        for (prefix, uri) in needed {
+            // An element's own declaration is never overridden
+            if self.lookup(prefix) != Some(uri) && !declared.contains(&prefix) {
+                declarations.push((xmlns_attribute(prefix), uri));
+                self.bindings
+                    .push((depth, prefix.map(str::to_string), uri.to_string()));
+            }
+        }
+        all.extend(declarations);
+
+        let name = match namespace {
+            Some((Some(prefix), _)) => Cow::Owned(format!("{}:{}", prefix, name)),
+            _ => Cow::Borrowed(name),
+        };
+        (name, all)
+    }
+
+    /// Write `<name` and the attributes of an element; returns the name written
+    #[allow(clippy::too_many_arguments)]
+    fn start_tag<'a>(
+        &mut self,
+        depth: usize,
+        name: &'a str,
+        attributes: &'a [(String, String)],
+        fixed: &'a FixedAttributes,
+        is_root: bool,
+        options: &SerializeOptions,
+        xml: &mut String,
+    ) -> Cow<'a, str> {
+        let (name, mut attributes) = self.element(depth, name, attributes, fixed, is_root);
+        xml.push('<');
+        xml.push_str(&name);
+        let quote = if options.canonical {
+            attributes.sort_by(|(a, _), (b, _)| {
+                (xmlns_prefix(a).is_none(), a).cmp(&(xmlns_prefix(b).is_none(), b))
+            });
+            '"'
+        } else {
+            '\''
+        };
+        for (k, v) in attributes {
+            let value = options.escaping.escape(v, Some(quote));
+            xml.push_str(&format!(" {}={}{}{}", k, quote, value, quote));
+        }
+        name
+    }
+}
+
+/// The prefix an `xmlns` attribute declares: `Some(None)` for the default namespace
+fn xmlns_prefix(attribute: &str) -> Option<Option<&str>> {
+    match attribute.strip_prefix("xmlns") {
+        Some("") => Some(None),
+        Some(rest) => rest.strip_prefix(':').map(Some),
+        None => None,
+    }
+}
+
+/// The attribute that declares `prefix`
+fn xmlns_attribute(prefix: Option<&str>) -> Cow<'static, str> {
+    match prefix {
+        Some(prefix) => Cow::Owned(format!("xmlns:{}", prefix)),
+        None => Cow::Borrowed("xmlns"),
+    }
+}
+
+/// Which elements a fixed attribute is added to
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Root,
+    Element(String),
+}
+
+/// Constant attributes added to elements when a tree is serialized
+///
+/// For output-only values such as a schema location or a format version,
+/// which should not change what the grammar matches. An attribute the element
+/// already has is never overridden, and the first fixed attribute of a given
+/// name wins.
+///
+/// ```
+/// use rustixml::{parse_ixml_grammar, xml_node::FixedAttributes, NativeParser};
+///
+/// let grammar = parse_ixml_grammar(r#"{[fixed-attribute version "2"]} doc: item+. item: ["a"-"z"]."#)
+///     .unwrap();
+/// let parser = NativeParser::new(grammar);
+/// assert_eq!(parser.parse("ab").unwrap(), "<doc version='2'><item>a</item><item>b</item></doc>");
+///
+/// let fixed = FixedAttributes::from_grammar(parser.compiled().grammar())
+///     .on_root("xmlns", "urn:example")
+///     .on_element("item", "kind", "letter");
+/// let parser = parser.with_fixed_attributes(fixed);
+/// assert_eq!(
+///     parser.parse("a").unwrap(),
+///     "<doc version='2' xmlns='urn:example'><item kind='letter'>a</item></doc>"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FixedAttributes {
+    entries: Vec<(Target, String, String)>,
+    /// Element name, prefix, namespace URI
+    namespaces: Vec<(String, Option<String>, String)>,
+}
+
+impl FixedAttributes {
+    /// Create an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an attribute to the root element, whatever its name
+    pub fn on_root(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.entries.push((Target::Root, name.into(), value.into()));
+        self
+    }
+
+    /// Add an attribute to every element named `element`
+    pub fn on_element(
+        mut self,
+        element: impl Into<String>,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.entries
+            .push((Target::Element(element.into()), name.into(), value.into()));
+        self
+    }
+
+    /// Write every element named `element` in namespace `uri`, as
+    /// `prefix:element` or (without a prefix) in the default namespace
+    ///
+    /// The namespace is declared on the first such element of each subtree.
+    pub fn in_namespace(
+        mut self,
+        element: impl Into<String>,
+        prefix: Option<&str>,
+        uri: impl Into<String>,
+    ) -> Self {
+        self.namespaces
+            .push((element.into(), prefix.map(str::to_string), uri.into()));
+        self
+    }
+
+    /// Collect the `{[fixed-attribute name "value"]}` and
+    /// `{[namespace prefix "uri"]}` pragmas on the grammar's rules
+    ///
+    /// Each one targets the elements named after its rule. Like other pragmas a
+    /// processor cannot use, ones on factors or without a quoted value are ignored.
+    ///
+    /// ```
+    /// use rustixml::{parse_ixml_grammar, NativeParser};
+    ///
+    /// let grammar = parse_ixml_grammar(
+    ///     r#"{[namespace svg "http://www.w3.org/2000/svg"]} g: rect+. {[namespace svg "http://www.w3.org/2000/svg"]} rect: "r"."#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(
+    ///     NativeParser::new(grammar).parse("rr").unwrap(),
+    ///     "<svg:g xmlns:svg='http://www.w3.org/2000/svg'><svg:rect>r</svg:rect><svg:rect>r</svg:rect></svg:g>"
+    /// );
+    /// ```
+    pub fn from_grammar(grammar: &IxmlGrammar) -> Self {
+        let mut fixed = Self::new();
+        grammar.visit_pragmas(|rule, factor, pragma| {
+            if factor.is_some() {
+                return;
+            }
+            let taken = std::mem::take(&mut fixed);
+            fixed = match pragma.name.as_str() {
+                FIXED_ATTRIBUTE_PRAGMA => match parse_fixed_attribute(&pragma.data) {
+                    Some((name, value)) => taken.on_element(&rule.name, name, value),
+                    None => taken,
+                },
+                NAMESPACE_PRAGMA => match parse_namespace(&pragma.data) {
+                    Some((prefix, uri)) => taken.in_namespace(&rule.name, prefix, uri),
+                    None => taken,
+                },
+                _ => taken,
+            };
+        });
+        fixed
+    }
+
+    /// Check whether no attributes or namespaces would be added
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.namespaces.is_empty()
+    }
+
+    /// The namespace elements named `element` are written in
+    fn namespace_of(&self, element: &str) -> Option<(Option<&str>, &str)> {
+        self.namespaces
+            .iter()
+            .find(|(e, _, _)| e == element)
+            .map(|(_, prefix, uri)| (prefix.as_deref(), uri.as_str()))
+    }
+
+    /// The attributes to add to an element that already has `existing`
+    fn for_element<'a>(
+        &'a self,
+        element: &str,
+        is_root: bool,
+        existing: &[(String, String)],
+    ) -> Vec<(&'a str, &'a str)> {
+        let mut extra: Vec<(&str, &str)> = Vec::new();
+        for (target, name, value) in &self.entries {
+            let applies = match target {
+                Target::Root => is_root,
+                Target::Element(e) => e == element,
+            };
+            if applies
+                && !existing.iter().any(|(k, _)| k == name)
+                && !extra.iter().any(|(k, _)| k == name)
+            {
+                extra.push((name, value));
+            }
+        }
+        extra
+    }
+}
+
+/// Split pragma data of the form `name "value"` (or `'value'`)
+fn parse_fixed_attribute(data: &str) -> Option<(&str, &str)> {
+    let (name, rest) = data.split_once(char::is_whitespace)?;
+    Some((name, parse_quoted(rest)?))
+}
+
+/// Split pragma data of the form `prefix "uri"` or just `"uri"`
+fn parse_namespace(data: &str) -> Option<(Option<&str>, &str)> {
+    let data = data.trim();
+    match data.split_once(char::is_whitespace) {
+        Some((prefix, rest)) if !prefix.starts_with(['"', '\'']) => {
+            Some((Some(prefix), parse_quoted(rest)?))
+        }
+        _ => Some((None, parse_quoted(data)?)),
+    }
+}
+
+/// The text of a `"quoted"` (or `'quoted'`) value
+pub(crate) fn parse_quoted(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let quote = text.chars().next().filter(|&q| q == '"' || q == '\'')?;
+    text[1..].strip_suffix(quote)
+}
+
+impl Clone for XmlNode {
+    fn clone(&self) -> Self {
+        /// An element whose children are still being copied
+        struct Partial<'a> {
+            source: &'a XmlNode,
+            next: usize,
+            children: Vec<XmlNode>,
+        }
+
+        fn shallow(node: &XmlNode) -> Result<XmlNode, &[XmlNode]> {
+            match node {
+                XmlNode::Element { children, .. } if !children.is_empty() => Err(children),
+                XmlNode::Element {
+                    name, attributes, ..
+                } => Ok(XmlNode::Element {
+                    name: name.clone(),
+                    attributes: attributes.clone(),
+                    children: Vec::new(),
+                }),
+                XmlNode::Text(s) => Ok(XmlNode::Text(s.clone())),
+                XmlNode::Attribute { name, value } => Ok(XmlNode::Attribute {
+                    name: name.clone(),
+                    value: value.clone(),
+                }),
+            }
+        }
+
+        let children = match shallow(self) {
+            Ok(leaf) => return leaf,
+            Err(children) => children,
+        };
+        let mut stack = vec![Partial {
+            source: self,
+            next: 0,
+            children: Vec::with_capacity(children.len()),
+        }];
+
+        loop {
+            let top = stack
+                .last_mut()
+                .expect("stack holds the root until it completes");
+            let XmlNode::Element {
                 name,
                 attributes,
-                children,
-            } => {
-                let attrs_str = if attributes.is_empty() {
-                    String::new()
-                } else {
-                    format!(
-                        " {}",
-                        attributes
-                            .iter()
-                            .map(|(k, v)| format!("{}='{}'", k, Self::escape_xml_attr(v)))
-                            .collect::<Vec<_>>()
-                            .join(" ")
-                    )
-                };
+                children: source_children,
+            } = top.source
+            else {
+                unreachable!("only elements with children are pushed");
+            };
 
-                if children.is_empty() {
-                    format!("<{}{}/>", name, attrs_str)
-                } else {
-                    let content: String = children
-                        .iter()
-                        .map(|child| child.to_xml_internal(_depth + 1, _indent))
-                        .collect();
-                    format!("<{}{}>{}</{}>", name, attrs_str, content, name)
+            if let Some(child) = source_children.get(top.next) {
+                top.next += 1;
+                match shallow(child) {
+                    Ok(leaf) => top.children.push(leaf),
+                    Err(grandchildren) => stack.push(Partial {
+                        source: child,
+                        next: 0,
+                        children: Vec::with_capacity(grandchildren.len()),
+                    }),
                 }
+                continue;
+            }
+
+            let done = XmlNode::Element {
+                name: name.clone(),
+                attributes: attributes.clone(),
+                children: std::mem::take(&mut top.children),
+            };
+            stack.pop();
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(done),
+                None => return done,
             }
-            XmlNode::Text(s) => Self::escape_xml_text(s),
-            XmlNode::Attribute { .. } => {
-                // Attributes should have been extracted by parent
-                String::new()
+        }
+    }
+}
+
+impl Drop for XmlNode {
+    fn drop(&mut self) {
+        // Detach descendants onto a heap stack so dropping never recurses deeply
+        let XmlNode::Element { children, .. } = self else {
+            return;
+        };
+        if children.is_empty() {
+            return;
+        }
+        let mut stack = std::mem::take(children);
+        while let Some(mut node) = stack.pop() {
+            if let XmlNode::Element { children, .. } = &mut node {
+                stack.append(children);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ixml_state_serialization() {
+        let mut node = XmlNode::Element {
+            name: "a".to_string(),
+            attributes: vec![("id".to_string(), "1".to_string())],
+            children: vec![XmlNode::Text("x".to_string())],
+        };
+        node.set_ixml_state("ambiguous");
+        node.set_ixml_state("ambiguous");
+        assert_eq!(node.ixml_state(), Some("ambiguous"));
+        assert_eq!(
+            node.to_xml(),
+            "<a id='1' ixml:state='ambiguous' xmlns:ixml='http://invisiblexml.org/NS'>x</a>"
+        );
+    }
+
+    #[test]
+    fn test_fixed_attributes() {
+        let tree = XmlNode::element("a")
+            .with_attribute("id", "1")
+            .with_child(XmlNode::element("b"))
+            .with_child(XmlNode::element("a"));
+        let fixed = FixedAttributes::new()
+            .on_element("a", "id", "fixed")
+            .on_element("a", "v", "1")
+            .on_element("a", "v", "2")
+            .on_root("root", "yes");
+        assert_eq!(
+            tree.to_xml_with(&fixed),
+            "<a id='1' v='1' root='yes'><b/><a id='fixed' v='1'/></a>"
+        );
+        assert_eq!(tree.to_xml(), "<a id='1'><b/><a/></a>");
+
+        assert_eq!(parse_fixed_attribute(r#"v "1 2""#), Some(("v", "1 2")));
+        assert_eq!(parse_fixed_attribute("v  'x'"), Some(("v", "x")));
+        assert_eq!(parse_fixed_attribute("v x"), None);
+        assert_eq!(parse_fixed_attribute(r#"v "x"#), None);
+        assert_eq!(parse_fixed_attribute("v"), None);
+    }
+
+    #[test]
+    fn test_namespaces() {
+        const SVG: &str = "http://www.w3.org/2000/svg";
+        let tree = XmlNode::element("g")
+            .with_child(XmlNode::element("rect").with_attribute("ixml:state", "ambiguous"))
+            .with_child(XmlNode::element("rect"))
+            .with_child(XmlNode::element("title"));
+        let fixed = FixedAttributes::new()
+            .in_namespace("g", Some("svg"), SVG)
+            .in_namespace("rect", Some("svg"), SVG)
+            .in_namespace("title", None, "urn:t");
+        assert_eq!(
+            tree.to_xml_with(&fixed),
+            "<svg:g xmlns:svg='http://www.w3.org/2000/svg'>\
+             <svg:rect ixml:state='ambiguous' xmlns:ixml='http://invisiblexml.org/NS'/>\
+             <svg:rect/><title xmlns='urn:t'/></svg:g>"
+        );
+
+        // Declarations in scope are not repeated; an element's own win
+        let tree = XmlNode::element("a")
+            .with_attribute("xmlns:ixml", IXML_NAMESPACE)
+            .with_child(XmlNode::element("b").with_attribute("ixml:state", "ambiguous"))
+            .with_child(XmlNode::element("rect").with_attribute("xmlns:svg", "urn:other"));
+        assert_eq!(
+            tree.to_xml_with(&fixed),
+            "<a xmlns:ixml='http://invisiblexml.org/NS'><b ixml:state='ambiguous'/>\
+             <svg:rect xmlns:svg='urn:other'/></a>"
+        );
+
+        let node = XmlNode::element("svg:rect").with_namespace(None, SVG);
+        assert_eq!((node.prefix(), node.local_name()), (None, Some("rect")));
+        assert_eq!(node.namespace_declarations(), [(None, SVG)]);
+        assert_eq!(XmlNode::text("x").local_name(), None);
+
+        assert_eq!(
+            parse_namespace(r#"svg "urn:s""#),
+            Some((Some("svg"), "urn:s"))
+        );
+        assert_eq!(parse_namespace(r#" "urn:s""#), Some((None, "urn:s")));
+        assert_eq!(parse_namespace("'urn:s'"), Some((None, "urn:s")));
+        assert_eq!(parse_namespace("svg urn:s"), None);
+    }
+
+    #[test]
+    fn test_mutation() {
+        let mut tree = XmlNode::element("list")
+            .attr("n", "2")
+            .child(XmlNode::element("a"))
+            .child(XmlNode::element("c"));
+        tree.insert_child(1, XmlNode::element("b"));
+        tree.push_child(XmlNode::text("!"));
+        tree.set_attribute("n", "3");
+        tree.set_name("items");
+        assert_eq!(tree.to_xml(), "<items n='3'><a/><b/><c/>!</items>");
+
+        assert_eq!(tree.remove_child(3), Some(XmlNode::text("!")));
+        assert_eq!(tree.remove_child(3), None);
+        assert_eq!(tree.remove_attribute("n").as_deref(), Some("3"));
+        assert_eq!(tree.remove_attribute("n"), None);
+        tree.children_mut()
+            .unwrap()
+            .retain(|c| c.name() != Some("b"));
+        assert_eq!(tree.to_xml(), "<items><a/><c/></items>");
+
+        // Other node kinds are left alone
+        let mut text = XmlNode::text("x");
+        text.push_child(XmlNode::element("a"));
+        text.set_attribute("k", "v");
+        text.set_name("y");
+        assert_eq!(text, XmlNode::text("x"));
+        assert!(text.children_mut().is_none());
+        assert_eq!(text.remove_child(0), None);
+    }
+
+    #[test]
+    fn test_from_xml_round_trip() {
+        let tree = XmlNode::element("doc")
+            .with_attribute("q", "it's <\"x\"> & \t\n")
+            .with_child(XmlNode::text("a < b & c > d"))
+            .with_child(XmlNode::element("empty"))
+            .with_child(XmlNode::element("x").with_child(XmlNode::text("\u{a0}")));
+        let mut state = tree.clone();
+        state.set_ixml_state("ambiguous");
+        for tree in [tree, state] {
+            assert_eq!(
+                XmlNode::from_xml(&tree.to_xml()).unwrap().to_xml(),
+                tree.to_xml()
+            );
+        }
+
+        let tree = XmlNode::from_xml(
+            "<!DOCTYPE a>\n<!-- c --><a b = \"1\" c='>'>x<![CDATA[<&>]]>&#65;&#x42;<?pi?></a>",
+        )
+        .unwrap();
+        assert_eq!(
+            tree,
+            XmlNode::element("a")
+                .with_attribute("b", "1")
+                .with_attribute("c", ">")
+                .with_child(XmlNode::text("x<&>AB"))
+        );
+    }
+
+    #[test]
+    fn test_from_xml_errors() {
+        for (xml, error) in [
+            ("", "at byte 0: no root element"),
+            ("<a>", "at byte 3: <a> is not closed"),
+            ("<a/><b/>", "at byte 4: more than one root element"),
+            ("x<a/>", "at byte 0: text outside the root element"),
+            ("<a>&nbsp;</a>", "at byte 3: unknown reference '&nbsp;'"),
+            ("<a b=1/>", "at byte 0: unquoted attribute value in <a b=1>"),
+            ("<a></b>", "at byte 3: </b> closes <a>"),
+        ] {
+            assert_eq!(
+                XmlNode::from_xml(xml).unwrap_err(),
+                format!("Malformed XML {}", error)
+            );
+        }
+    }
+
+    #[test]
+    fn test_truncate_nodes() {
+        let tree = || {
+            XmlNode::element("a")
+                .with_child(XmlNode::element("b").with_child(XmlNode::text("x")))
+                .with_child(XmlNode::element("c").with_child(XmlNode::text("y")))
+                .with_child(XmlNode::text("z"))
+        };
+        let mut small = tree();
+        assert_eq!(small.truncate(6), None);
+        assert_eq!(small, tree());
+
+        let mut small = tree();
+        assert_eq!(
+            small.truncate(4).as_deref(),
+            Some("Output truncated to 4 nodes")
+        );
+        assert_eq!(
+            small.to_xml(),
+            "<a ixml:state='truncated' xmlns:ixml='http://invisiblexml.org/NS'><b>x</b><c/></a>"
+        );
+
+        // The root is always kept
+        let mut small = tree();
+        assert!(small.truncate(0).is_some());
+        assert_eq!(
+            small.to_xml_with(&FixedAttributes::new().on_root("v", "1")),
+            "<a ixml:state='truncated' v='1' xmlns:ixml='http://invisiblexml.org/NS'/>"
+        );
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        let tree = XmlNode::element("doc")
+            .with_child(
+                XmlNode::element("p")
+                    .with_child(XmlNode::text("a "))
+                    .with_child(XmlNode::element("b").with_child(XmlNode::element("i"))),
+            )
+            .with_child(XmlNode::element("list").with_child(XmlNode::element("item")));
+        let options = SerializeOptions::pretty()
+            .with_indent(' ', 2)
+            .with_newline(Newline::CrLf)
+            .with_declaration(true);
+        assert_eq!(
+            tree.to_xml_with_options(&options),
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\r\n<doc>\r\n  \
+             <p>a <b><i/></b></p>\r\n  <list>\r\n    <item/>\r\n  </list>\r\n</doc>"
+        );
+        assert_eq!(
+            tree.to_xml_with_options(&SerializeOptions::new()),
+            tree.to_xml()
+        );
+
+        // Truncation still closes every open element on its own line
+        let (xml, warning) = tree.write_xml(
+            &FixedAttributes::new(),
+            &SerializeOptions::pretty(),
+            Some(14),
+        );
+        assert_eq!(
+            xml,
+            "<doc ixml:state='truncated' xmlns:ixml='http://invisiblexml.org/NS'>\n   \
+             <p>a </p>\n</doc>"
+        );
+        assert!(warning.is_some());
+    }
+
+    #[test]
+    fn test_escaping() {
+        for escaping in [Escaping::Minimal, Escaping::Strict, Escaping::Ascii] {
+            assert_eq!(escaping.escape("a&b<c", None), "a&amp;b&lt;c");
+            assert_eq!(escaping.escape("'\"\t\n", Some('\'')), "&apos;\"&#x9;&#xA;");
+            assert_eq!(escaping.escape("'\"", Some('"')), "'&quot;");
+            assert_eq!(escaping.escape(">", Some('"')), ">");
+        }
+        assert_eq!(Escaping::Minimal.escape(">\r😀", None), ">\r😀");
+        assert_eq!(Escaping::Strict.escape(">\r😀", None), "&gt;&#xD;😀");
+        assert_eq!(Escaping::Ascii.escape(">\r😀", None), "&gt;&#xD;&#x1F600;");
+
+        // Whatever the policy, the output parses back to the same tree
+        let tree = XmlNode::element("a")
+            .attr("v", "x'\"\t\r\né")
+            .child(XmlNode::text("<&>\r\nü"));
+        for escaping in [Escaping::Minimal, Escaping::Strict, Escaping::Ascii] {
+            let options = SerializeOptions::new().with_escaping(escaping);
+            let xml = tree.to_xml_with_options(&options);
+            assert_eq!(XmlNode::from_xml(&xml).unwrap(), tree, "{}", xml);
+        }
+    }
+
+    #[test]
+    fn test_canonical() {
+        let options = SerializeOptions::canonical();
+        let tree = XmlNode::element("a")
+            .attr("b", "1")
+            .attr("a", "'\"\r")
+            .attr("xmlns", "urn:x")
+            .child(XmlNode::element("e").child(XmlNode::element("f")))
+            .child(XmlNode::text("&<>\r\n"));
+        assert_eq!(
+            tree.to_xml_with_options(&options),
+            "<a xmlns=\"urn:x\" a=\"'&quot;&#xD;\" b=\"1\"><e><f></f></e>&amp;&lt;&gt;&#xD;\n</a>"
+        );
+        // Documents that differ only in attribute order serialize the same
+        let reordered = XmlNode::from_xml(
+            "<a xmlns='urn:x' b='1' a=\"'&quot;&#xD;\"><e><f/></e>&amp;&lt;&gt;&#xD;\n</a>",
+        )
+        .unwrap();
+        assert_eq!(
+            reordered.to_xml_with_options(&options),
+            tree.to_xml_with_options(&options)
+        );
+
+        // Truncation keeps the canonical form
+        let (xml, _) = tree.write_xml(&FixedAttributes::new(), &options, Some(0));
+        assert_eq!(
+            xml,
+            "<a xmlns=\"urn:x\" xmlns:ixml=\"http://invisiblexml.org/NS\" a=\"'&quot;&#xD;\" b=\"1\" ixml:state=\"truncated\"></a>"
+        );
+    }
+
+    #[test]
+    fn test_to_xml_limited() {
+        let tree = XmlNode::element("a")
+            .with_attribute("ixml:state", "ambiguous")
+            .with_child(XmlNode::element("b").with_child(XmlNode::text("x<y")))
+            .with_child(XmlNode::element("c"));
+        let fixed = FixedAttributes::new();
+        let full = tree.to_xml();
+        assert_eq!(tree.to_xml_limited(&fixed, Some(full.len())), (full, None));
+
+        // Text is cut between characters, never inside an escape
+        let (xml, warning) = tree.to_xml_limited(&fixed, Some(72));
+        assert_eq!(
+            xml,
+            "<a ixml:state='ambiguous truncated' xmlns:ixml='http://invisiblexml.org/NS'><b>x</b></a>"
+        );
+        assert_eq!(warning.as_deref(), Some("Output truncated at 72 bytes"));
+
+        let (xml, _) = tree.to_xml_limited(&fixed, Some(0));
+        assert_eq!(
+            xml,
+            "<a ixml:state='ambiguous truncated' xmlns:ixml='http://invisiblexml.org/NS'></a>"
+        );
+    }
+}
@@ -0,0 +1,823 @@
+//! Arena-backed result trees
+//!
+//! An [`XmlTree`] keeps all of a document's nodes in one `Vec`, its names
+//! interned once and its text in one buffer, so building a tree costs a few
+//! growing allocations rather than several per node. Nodes are addressed by
+//! [`NodeId`] and read through [`XmlNodeRef`] views.
+//!
+//! A tree can borrow the text it was parsed from ([`XmlTree::with_input`]);
+//! its text nodes are then spans of that input rather than copies, so a
+//! large document's text is not held twice. [`XmlNode`] owns its text, so
+//! converting a tree with [`XmlNodeRef::to_node`] copies it again; of the
+//! parser's entry points only `parse_to_xml_tree` avoids that.
+//!
+//! The native parser builds its results this way: backtracking and the memo
+//! cache share subtrees by id instead of copying them.
+//! [`NativeParser::parse_to_xml_tree`](crate::NativeParser::parse_to_xml_tree)
+//! returns the arena itself.
+//!
+//! ```
+//! use rustixml::fixtures::DATE;
+//!
+//! let parser = DATE.parser();
+//! let tree = parser.parse_to_xml_tree(DATE.input).unwrap();
+//! let root = tree.root().unwrap();
+//! assert_eq!(root.name(), Some("date"));
+//! assert_eq!(root.attribute("year"), Some("2024"));
+//! assert_eq!(root.to_node(), parser.parse_to_tree(DATE.input).unwrap());
+//! ```
+//!
+//! ```
+//! use rustixml::fixtures::CSV;
+//!
+//! let tree = CSV.parser().parse_to_xml_tree(CSV.input).unwrap();
+//! let field = tree.root().unwrap().children().next().unwrap().children().next().unwrap();
+//! let text = field.children().next().unwrap().text().unwrap();
+//! // The field's text points into the input
+//! assert_eq!(text.as_ptr(), CSV.input.as_ptr());
+//! ```
+
+use crate::xml_node::XmlNode;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+/// A node's index in its [`XmlTree`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Node {
+    /// `links[start..end]` holds the element's `attributes` attribute nodes,
+    /// then its children
+    Element {
+        name: u32,
+        attributes: u32,
+        start: usize,
+        end: usize,
+    },
+    /// `text[start..end]`
+    Text { start: usize, end: usize },
+    /// `input[start..end]`
+    Span { start: usize, end: usize },
+    /// Named `name`, with the value `text[start..end]`
+    Attribute { name: u32, start: usize, end: usize },
+}
+
+/// Stands in for a node whose contents are filled in later
+const PLACEHOLDER: Node = Node::Text { start: 0, end: 0 };
+
+/// A document tree stored in one arena
+///
+/// Nodes are never changed once added, so a subtree can appear in several
+/// parents. Nodes that end up outside the root's subtree (such as those of
+/// abandoned parses) stay allocated until [`XmlTree::extract`] copies the
+/// subtree out.
+///
+/// ```
+/// use rustixml::xml_tree::XmlTree;
+///
+/// let mut tree = XmlTree::new();
+/// let year = tree.attribute("year", "2024");
+/// let text = tree.text("a < b");
+/// let root = tree.element("note", &[text, year]);
+/// tree.set_root(root);
+///
+/// let note = tree.root().unwrap();
+/// assert_eq!(note.attribute("year"), Some("2024"));
+/// assert_eq!(note.to_node().to_xml(), "<note year='2024'>a &lt; b</note>");
+/// ```
+#[derive(Debug, Clone)]
+pub struct XmlTree<'i> {
+    /// The text parsed, which [`Node::Span`]s point into
+    input: Option<&'i str>,
+    nodes: Vec<Node>,
+    /// Each element's attributes and children, one run per element
+    links: Vec<NodeId>,
+    names: Vec<Box<str>>,
+    name_ids: HashMap<Box<str>, u32>,
+    text: String,
+    root: Option<NodeId>,
+    /// Most nodes added before the tree starts dropping them
+    max_nodes: Option<usize>,
+    /// Stands in for every node dropped once the tree was full
+    dropped: Option<NodeId>,
+}
+
+impl Default for XmlTree<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'i> XmlTree<'i> {
+    /// Create an empty tree
+    pub fn new() -> Self {
+        XmlTree {
+            input: None,
+            nodes: Vec::new(),
+            links: Vec::new(),
+            names: Vec::new(),
+            name_ids: HashMap::new(),
+            text: String::new(),
+            root: None,
+            max_nodes: None,
+            dropped: None,
+        }
+    }
+
+    /// Create an empty tree whose text nodes can be spans of `input`
+    pub fn with_input(input: &'i str) -> Self {
+        XmlTree {
+            input: Some(input),
+            ..XmlTree::new()
+        }
+    }
+
+    /// Stop adding nodes once the tree holds `max_nodes` of them
+    ///
+    /// Past that, new text and attribute nodes are dropped, and so are
+    /// elements left with no content; elements holding nodes that were kept
+    /// are still added, so those stay reachable from the root. Nodes of
+    /// abandoned parses count toward the limit too.
+    ///
+    /// ```
+    /// use rustixml::xml_tree::XmlTree;
+    ///
+    /// let mut tree = XmlTree::new().with_max_nodes(2);
+    /// let a = tree.text("a");
+    /// let item = tree.element("item", &[a]);
+    /// let b = tree.text("b");
+    /// let dropped = tree.element("item", &[b]);
+    /// let root = tree.element("list", &[item, dropped]);
+    /// tree.set_root(root);
+    ///
+    /// assert!(tree.is_truncated());
+    /// assert_eq!(tree.root().unwrap().to_node().to_xml(), "<list><item>a</item></list>");
+    /// ```
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    /// The limit set by [`XmlTree::with_max_nodes`]
+    pub fn max_nodes(&self) -> Option<usize> {
+        self.max_nodes
+    }
+
+    /// Whether nodes were dropped because the tree was full
+    pub fn is_truncated(&self) -> bool {
+        self.dropped.is_some()
+    }
+
+    /// The text [`XmlTree::span`] text nodes point into
+    pub fn input(&self) -> Option<&'i str> {
+        self.input
+    }
+
+    /// Number of nodes, including any outside the root's subtree
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the tree has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The document element, once set
+    pub fn root(&self) -> Option<XmlNodeRef<'_>> {
+        self.root.map(|id| self.get(id))
+    }
+
+    /// Make `id` the document element
+    pub fn set_root(&mut self, id: NodeId) {
+        self.root = Some(id);
+    }
+
+    /// A view of the node `id`
+    ///
+    /// Panics if `id` is not a node of this tree.
+    pub fn get(&self, id: NodeId) -> XmlNodeRef<'_> {
+        assert!(id.index() < self.nodes.len(), "no node {:?} in tree", id);
+        XmlNodeRef { tree: self, id }
+    }
+
+    /// Add a text node
+    pub fn text(&mut self, text: &str) -> NodeId {
+        if self.is_full() {
+            return self.drop_node();
+        }
+        let (start, end) = self.push_text(text);
+        self.push(Node::Text { start, end })
+    }
+
+    /// Add a text node for `input[range]`, without copying it
+    ///
+    /// Panics if the tree has no input or `range` is not a range of it on
+    /// character boundaries.
+    pub fn span(&mut self, range: Range<usize>) -> NodeId {
+        let input = self.input.expect("span added to an XmlTree without input");
+        assert!(
+            input.get(range.clone()).is_some(),
+            "span {:?} is not in the input",
+            range
+        );
+        if self.is_full() {
+            return self.drop_node();
+        }
+        self.push(Node::Span {
+            start: range.start,
+            end: range.end,
+        })
+    }
+
+    /// Add an attribute node, for [`XmlTree::element`] to attach
+    pub fn attribute(&mut self, name: &str, value: &str) -> NodeId {
+        if self.is_full() {
+            return self.drop_node();
+        }
+        self.push_attribute(name, value)
+    }
+
+    /// Add an element
+    ///
+    /// Attribute nodes among `nodes` become the element's attributes and the
+    /// rest its children, each in the order given.
+    pub fn element(&mut self, name: &str, nodes: &[NodeId]) -> NodeId {
+        let nodes = self.kept(nodes);
+        if nodes.is_empty() && self.is_full() {
+            return self.drop_node();
+        }
+        let name = self.intern(name);
+        let start = self.links.len();
+        let is_attribute = |id: &NodeId| matches!(self.nodes[id.index()], Node::Attribute { .. });
+        let attributes: Vec<NodeId> = nodes.iter().copied().filter(is_attribute).collect();
+        let children = nodes.iter().copied().filter(|id| !is_attribute(id));
+        self.links
+            .extend(attributes.iter().copied().chain(children));
+        self.push(Node::Element {
+            name,
+            attributes: count(attributes.len()),
+            start,
+            end: self.links.len(),
+        })
+    }
+
+    /// Add an element whose children are exactly `nodes`, attribute nodes
+    /// included, like the parser's `_sequence` groups
+    pub(crate) fn group(&mut self, name: &str, nodes: &[NodeId]) -> NodeId {
+        let nodes = self.kept(nodes);
+        if nodes.is_empty() && self.is_full() {
+            return self.drop_node();
+        }
+        let name = self.intern(name);
+        let start = self.links.len();
+        self.links.extend_from_slice(&nodes);
+        self.push(Node::Element {
+            name,
+            attributes: 0,
+            start,
+            end: self.links.len(),
+        })
+    }
+
+    /// A text node holding the text of the text nodes `texts`, in order
+    ///
+    /// Texts that already lie next to each other in the input or the buffer,
+    /// such as characters matched one after another, are joined without
+    /// copying.
+    pub(crate) fn join_text(&mut self, texts: &[NodeId]) -> NodeId {
+        if self.is_full() {
+            return self.drop_node();
+        }
+        let nodes: Vec<Node> = texts.iter().map(|id| self.nodes[id.index()]).collect();
+        let adjacent = nodes.windows(2).all(|pair| match pair {
+            [Node::Text { end, .. }, Node::Text { start, .. }]
+            | [Node::Span { end, .. }, Node::Span { start, .. }] => end == start,
+            _ => false,
+        });
+        match (nodes.first(), nodes.last()) {
+            (Some(Node::Text { start, .. }), Some(Node::Text { end, .. })) if adjacent => self
+                .push(Node::Text {
+                    start: *start,
+                    end: *end,
+                }),
+            (Some(Node::Span { start, .. }), Some(Node::Span { end, .. })) if adjacent => self
+                .push(Node::Span {
+                    start: *start,
+                    end: *end,
+                }),
+            _ => {
+                let joined: String = texts
+                    .iter()
+                    .map(|&id| self.get(id).text().expect("join_text is given text nodes"))
+                    .collect();
+                self.text(&joined)
+            }
+        }
+    }
+
+    /// An element's attribute nodes followed by its children; empty for
+    /// other nodes
+    pub(crate) fn links(&self, id: NodeId) -> &[NodeId] {
+        match self.nodes[id.index()] {
+            Node::Element { start, end, .. } => &self.links[start..end],
+            _ => &[],
+        }
+    }
+
+    /// A tree holding just `id` and its descendants, with `id` as the root
+    ///
+    /// Useful to drop the nodes a parse abandoned, or to keep part of a
+    /// document.
+    ///
+    /// Spans of the input stay spans.
+    pub fn extract(&self, id: NodeId) -> XmlTree<'i> {
+        let mut tree = XmlTree {
+            input: self.input,
+            ..XmlTree::new()
+        };
+        let root = tree.push(PLACEHOLDER);
+        let mut pending = vec![(id, root)];
+        while let Some((old, new)) = pending.pop() {
+            tree.nodes[new.index()] = match self.nodes[old.index()] {
+                Node::Element {
+                    name,
+                    attributes,
+                    start,
+                    end,
+                } => {
+                    let name = tree.intern(&self.names[name as usize]);
+                    let links_start = tree.links.len();
+                    let first = pending.len();
+                    for &link in &self.links[start..end] {
+                        let copy = tree.push(PLACEHOLDER);
+                        tree.links.push(copy);
+                        pending.push((link, copy));
+                    }
+                    // Copy in document order
+                    pending[first..].reverse();
+                    Node::Element {
+                        name,
+                        attributes,
+                        start: links_start,
+                        end: tree.links.len(),
+                    }
+                }
+                Node::Text { start, end } => {
+                    let (start, end) = tree.push_text(&self.text[start..end]);
+                    Node::Text { start, end }
+                }
+                span @ Node::Span { .. } => span,
+                Node::Attribute { name, start, end } => {
+                    let name = tree.intern(&self.names[name as usize]);
+                    let (start, end) = tree.push_text(&self.text[start..end]);
+                    Node::Attribute { name, start, end }
+                }
+            };
+        }
+        tree.root = Some(root);
+        tree
+    }
+
+    /// Set `ixml:state` on the root element, like [`XmlNode::set_ixml_state`]
+    pub fn set_ixml_state(&mut self, state: &str) {
+        let Some(root) = self.root else {
+            return;
+        };
+        let Node::Element {
+            name,
+            attributes,
+            start,
+            end,
+        } = self.nodes[root.index()]
+        else {
+            return;
+        };
+        let mut links = self.links[start..end].to_vec();
+        let existing = links[..attributes as usize]
+            .iter()
+            .position(|&id| self.get(id).name() == Some("ixml:state"));
+        let value = match existing {
+            Some(index) => {
+                let value = self.get(links[index]).text().unwrap_or_default();
+                if value.split_whitespace().any(|s| s == state) {
+                    return;
+                }
+                format!("{} {}", value, state)
+            }
+            None => state.to_string(),
+        };
+        let attribute = self.push_attribute("ixml:state", &value);
+        let attributes = match existing {
+            Some(index) => {
+                links[index] = attribute;
+                attributes
+            }
+            None => {
+                links.insert(attributes as usize, attribute);
+                attributes + 1
+            }
+        };
+        let start = self.links.len();
+        self.links.extend(links);
+        self.root = Some(self.push(Node::Element {
+            name,
+            attributes,
+            start,
+            end: self.links.len(),
+        }));
+    }
+
+    /// Add an element with no content, even to a full tree
+    ///
+    /// Stands in for a document element whose content was all dropped.
+    pub(crate) fn empty_element(&mut self, name: &str) -> NodeId {
+        let name = self.intern(name);
+        let start = self.links.len();
+        self.push(Node::Element {
+            name,
+            attributes: 0,
+            start,
+            end: start,
+        })
+    }
+
+    /// Whether `id` stands in for dropped nodes
+    pub(crate) fn is_dropped(&self, id: NodeId) -> bool {
+        self.dropped == Some(id)
+    }
+
+    fn is_full(&self) -> bool {
+        self.max_nodes
+            .is_some_and(|max_nodes| self.nodes.len() >= max_nodes)
+    }
+
+    fn drop_node(&mut self) -> NodeId {
+        match self.dropped {
+            Some(id) => id,
+            None => {
+                let id = self.push(PLACEHOLDER);
+                self.dropped = Some(id);
+                id
+            }
+        }
+    }
+
+    /// `nodes` without the ones that were dropped
+    fn kept(&self, nodes: &[NodeId]) -> Vec<NodeId> {
+        nodes
+            .iter()
+            .copied()
+            .filter(|&id| !self.is_dropped(id))
+            .collect()
+    }
+
+    fn push_attribute(&mut self, name: &str, value: &str) -> NodeId {
+        let name = self.intern(name);
+        let (start, end) = self.push_text(value);
+        self.push(Node::Attribute { name, start, end })
+    }
+
+    fn push(&mut self, node: Node) -> NodeId {
+        let id = u32::try_from(self.nodes.len()).expect("an XmlTree holds at most u32::MAX nodes");
+        self.nodes.push(node);
+        NodeId(id)
+    }
+
+    fn push_text(&mut self, text: &str) -> (usize, usize) {
+        let start = self.text.len();
+        self.text.push_str(text);
+        (start, self.text.len())
+    }
+
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.name_ids.get(name) {
+            return id;
+        }
+        let id = count(self.names.len());
+        self.names.push(name.into());
+        self.name_ids.insert(name.into(), id);
+        id
+    }
+}
+
+fn count(len: usize) -> u32 {
+    u32::try_from(len).expect("an XmlTree holds at most u32::MAX nodes")
+}
+
+impl From<&XmlNode> for XmlTree<'_> {
+    /// Copy `node` into a new tree, as its root
+    fn from(node: &XmlNode) -> Self {
+        let mut tree = XmlTree::new();
+        let root = tree.push(PLACEHOLDER);
+        let mut pending = vec![(node, root)];
+        while let Some((node, id)) = pending.pop() {
+            tree.nodes[id.index()] = match node {
+                XmlNode::Element {
+                    name,
+                    attributes,
+                    children,
+                } => {
+                    let name = tree.intern(name);
+                    let attribute_ids: Vec<NodeId> = attributes
+                        .iter()
+                        .map(|(name, value)| tree.attribute(name, value))
+                        .collect();
+                    let start = tree.links.len();
+                    tree.links.extend(attribute_ids);
+                    for child in children.iter().rev() {
+                        let copy = tree.push(PLACEHOLDER);
+                        pending.push((child, copy));
+                    }
+                    // Copied in reverse, so the newest entries are the first children
+                    let first = pending.len() - children.len();
+                    tree.links
+                        .extend(pending[first..].iter().rev().map(|&(_, id)| id));
+                    Node::Element {
+                        name,
+                        attributes: count(attributes.len()),
+                        start,
+                        end: tree.links.len(),
+                    }
+                }
+                XmlNode::Text(text) => {
+                    let (start, end) = tree.push_text(text);
+                    Node::Text { start, end }
+                }
+                XmlNode::Attribute { name, value } => {
+                    let name = tree.intern(name);
+                    let (start, end) = tree.push_text(value);
+                    Node::Attribute { name, start, end }
+                }
+            };
+        }
+        tree.root = Some(root);
+        tree
+    }
+}
+
+/// A node of an [`XmlTree`]
+#[derive(Clone, Copy)]
+pub struct XmlNodeRef<'a> {
+    tree: &'a XmlTree<'a>,
+    id: NodeId,
+}
+
+impl fmt::Debug for XmlNodeRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("XmlNodeRef")
+            .field("id", &self.id)
+            .field("name", &self.name())
+            .finish()
+    }
+}
+
+impl<'a> XmlNodeRef<'a> {
+    /// The node's id in its tree
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn node(&self) -> Node {
+        self.tree.nodes[self.id.index()]
+    }
+
+    pub fn is_element(&self) -> bool {
+        matches!(self.node(), Node::Element { .. })
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self.node(), Node::Text { .. } | Node::Span { .. })
+    }
+
+    pub fn is_attribute(&self) -> bool {
+        matches!(self.node(), Node::Attribute { .. })
+    }
+
+    /// The element's or attribute's name
+    pub fn name(&self) -> Option<&'a str> {
+        match self.node() {
+            Node::Element { name, .. } | Node::Attribute { name, .. } => {
+                Some(&self.tree.names[name as usize])
+            }
+            Node::Text { .. } | Node::Span { .. } => None,
+        }
+    }
+
+    /// A text node's text, or an attribute's value
+    pub fn text(&self) -> Option<&'a str> {
+        match self.node() {
+            Node::Text { start, end } | Node::Attribute { start, end, .. } => {
+                Some(&self.tree.text[start..end])
+            }
+            Node::Span { start, end } => self.tree.input.map(|input| &input[start..end]),
+            Node::Element { .. } => None,
+        }
+    }
+
+    /// An element's attributes as `(name, value)` pairs
+    pub fn attributes(&self) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        let tree = self.tree;
+        let attributes = match self.node() {
+            Node::Element {
+                attributes, start, ..
+            } => &tree.links[start..start + attributes as usize],
+            _ => &[],
+        };
+        attributes.iter().map(move |&id| {
+            let attribute = tree.get(id);
+            (
+                attribute.name().unwrap_or_default(),
+                attribute.text().unwrap_or_default(),
+            )
+        })
+    }
+
+    /// The value of an element's attribute `name`
+    pub fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes()
+            .find(|&(attribute, _)| attribute == name)
+            .map(|(_, value)| value)
+    }
+
+    /// An element's children
+    pub fn children(&self) -> impl Iterator<Item = XmlNodeRef<'a>> + 'a {
+        let tree = self.tree;
+        let children = match self.node() {
+            Node::Element {
+                attributes,
+                start,
+                end,
+                ..
+            } => &tree.links[start + attributes as usize..end],
+            _ => &[],
+        };
+        children.iter().map(move |&id| XmlNodeRef { tree, id })
+    }
+
+    /// The text of the node and its descendants, like [`XmlNode::text_content`]
+    pub fn text_content(&self) -> String {
+        let mut text = String::new();
+        let mut stack = vec![*self];
+        while let Some(node) = stack.pop() {
+            match node.text() {
+                Some(s) => text.push_str(s),
+                None => {
+                    let first = stack.len();
+                    stack.extend(node.children());
+                    stack[first..].reverse();
+                }
+            }
+        }
+        text
+    }
+
+    /// Copy the node and its descendants into an [`XmlNode`]
+    pub fn to_node(&self) -> XmlNode {
+        let mut built: Vec<XmlNode> = Vec::new();
+        let mut stack = vec![(*self, false)];
+        while let Some((node, children_built)) = stack.pop() {
+            match node.name() {
+                Some(name) if node.is_element() => {
+                    if !children_built {
+                        stack.push((node, true));
+                        let first = stack.len();
+                        stack.extend(node.children().map(|child| (child, false)));
+                        stack[first..].reverse();
+                        continue;
+                    }
+                    let children = built.split_off(built.len() - node.children().count());
+                    built.push(XmlNode::Element {
+                        name: name.to_string(),
+                        attributes: node
+                            .attributes()
+                            .map(|(name, value)| (name.to_string(), value.to_string()))
+                            .collect(),
+                        children,
+                    });
+                }
+                Some(name) => built.push(XmlNode::Attribute {
+                    name: name.to_string(),
+                    value: node.text().unwrap_or_default().to_string(),
+                }),
+                None => built.push(XmlNode::Text(node.text().unwrap_or_default().to_string())),
+            }
+        }
+        built.pop().expect("the node itself is built last")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> XmlNode {
+        XmlNode::element("doc")
+            .with_attribute("id", "1")
+            .with_child(XmlNode::element("a").with_child(XmlNode::text("x")))
+            .with_child(XmlNode::text("y"))
+            .with_child(XmlNode::element("b").with_attribute("k", "v"))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let node = sample();
+        let tree = XmlTree::from(&node);
+        let root = tree.root().unwrap();
+        assert_eq!(root.to_node(), node);
+        assert_eq!(root.text_content(), "xy");
+
+        let names: Vec<_> = root.children().map(|child| child.name()).collect();
+        assert_eq!(names, [Some("a"), None, Some("b")]);
+        assert_eq!(root.children().nth(1).unwrap().text(), Some("y"));
+    }
+
+    #[test]
+    fn test_extract_drops_other_nodes() {
+        let mut tree = XmlTree::from(&sample());
+        let unused = tree.text("unused");
+        let kept = tree.element("wrapper", &[unused]);
+        tree.text("garbage");
+
+        let extracted = tree.extract(tree.get(kept).id());
+        assert_eq!(extracted.len(), 2);
+        assert_eq!(
+            extracted.root().unwrap().to_node().to_xml(),
+            "<wrapper>unused</wrapper>"
+        );
+
+        let whole = tree.extract(tree.root().unwrap().id());
+        assert_eq!(whole.root().unwrap().to_node(), sample());
+        assert!(whole.len() < tree.len());
+    }
+
+    #[test]
+    fn test_join_text() {
+        let mut tree = XmlTree::new();
+        let a = tree.text("a");
+        let b = tree.text("b");
+        let joined = tree.join_text(&[a, b]);
+        assert_eq!(tree.get(joined).text(), Some("ab"));
+        // Adjacent in the buffer: no copy
+        assert_eq!(tree.text.len(), 2);
+
+        let joined = tree.join_text(&[b, a]);
+        assert_eq!(tree.get(joined).text(), Some("ba"));
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = "héllo world";
+        let mut tree = XmlTree::with_input(input);
+        let hello = tree.span(0..6);
+        let space = tree.span(6..7);
+        let joined = tree.join_text(&[hello, space]);
+        assert_eq!(tree.get(joined).text(), Some("héllo "));
+        assert!(tree.text.is_empty());
+
+        let bang = tree.text("!");
+        let mixed = tree.join_text(&[hello, bang]);
+        assert_eq!(tree.get(mixed).text(), Some("héllo!"));
+
+        let root = tree.element("greeting", &[joined]);
+        let extracted = tree.extract(root);
+        let text = extracted.root().unwrap().children().next().unwrap().text();
+        assert_eq!(text.map(str::as_ptr), Some(input.as_ptr()));
+        assert!(extracted.text.is_empty());
+    }
+
+    #[test]
+    fn test_group_keeps_attribute_children() {
+        let mut tree = XmlTree::new();
+        let attribute = tree.attribute("k", "v");
+        let text = tree.text("t");
+        let group = tree.group("_sequence", &[text, attribute]);
+        assert_eq!(tree.links(group), [text, attribute]);
+        assert_eq!(tree.get(group).attributes().count(), 0);
+
+        let element = tree.element("e", &[text, attribute]);
+        assert_eq!(tree.links(element), [attribute, text]);
+        assert_eq!(tree.get(element).attribute("k"), Some("v"));
+    }
+
+    #[test]
+    fn test_set_ixml_state() {
+        let mut tree = XmlTree::from(&sample());
+        tree.set_ixml_state("ambiguous");
+        tree.set_ixml_state("version-mismatch");
+        tree.set_ixml_state("ambiguous");
+
+        let mut expected = sample();
+        expected.set_ixml_state("ambiguous");
+        expected.set_ixml_state("version-mismatch");
+        assert_eq!(tree.root().unwrap().to_node(), expected);
+    }
+}
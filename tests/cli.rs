@@ -0,0 +1,468 @@
+//! The `rustixml parse` subcommand and its exit statuses
+
+use std::process::{Command, Output};
+
+fn rustixml(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rustixml"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+const GRAMMAR: &str = "!s: n++','. n: ['0'-'9']+.";
+
+#[test]
+fn test_parse() {
+    let output = rustixml(&["parse", GRAMMAR, "!1,22"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?><s><n>1</n>,<n>22</n></s>\n"
+    );
+}
+
+#[test]
+fn test_parse_exit_statuses() {
+    let output = rustixml(&["parse", GRAMMAR, "!1,x"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("<error type='parse'>"));
+
+    let output = rustixml(&["parse", "!s: 'a", "!a"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("<error type='grammar'>"));
+
+    // Without the subcommand, an error document is a successful result
+    let output = rustixml(&[GRAMMAR, "!1,x"]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = rustixml(&["parse", GRAMMAR]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_parse_output_file() {
+    let path = std::env::temp_dir().join(format!("rustixml-cli-{}.xml", std::process::id()));
+    let output = rustixml(&["parse", "-o", path.to_str().unwrap(), GRAMMAR, "!7"]);
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        written,
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?><s><n>7</n></s>\n"
+    );
+}
+
+fn rustixml_with_stdin(args: &[&str], stdin: &str) -> Output {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rustixml"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_stdin() {
+    let expected = "<?xml version=\"1.0\" encoding=\"utf-8\"?><s><n>1</n>,<n>2</n></s>\n";
+    for args in [
+        &["parse", GRAMMAR, "-"][..],
+        &["parse", "--stream", GRAMMAR, "-"],
+    ] {
+        let output = rustixml_with_stdin(args, "1,2");
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+
+    let output = rustixml_with_stdin(&["parse", "-", "!1,2"], &GRAMMAR[1..]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+
+    let output = rustixml_with_stdin(&["parse", "-", "-"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("only one argument can be read from standard input"));
+}
+
+#[test]
+fn test_quiet_when_piped() {
+    let output = rustixml(&["diff", GRAMMAR, GRAMMAR]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_formats() {
+    let stdout = |args: &[&str]| String::from_utf8(rustixml(args).stdout).unwrap();
+
+    assert_eq!(
+        stdout(&["parse", "--compact", GRAMMAR, "!1"]),
+        "<s><n>1</n></s>\n"
+    );
+    assert_eq!(
+        stdout(&[
+            "parse",
+            "--format",
+            "compact-xml",
+            "--indent",
+            GRAMMAR,
+            "!1"
+        ]),
+        "<s><n>1</n></s>\n"
+    );
+    assert_eq!(
+        stdout(&["parse", "--format", "json", GRAMMAR, "!1,2"]),
+        concat!(
+            r#"{"name":"s","attributes":{},"children":["#,
+            r#"{"name":"n","attributes":{},"children":["1"]},",","#,
+            r#"{"name":"n","attributes":{},"children":["2"]}]}"#,
+            "\n"
+        )
+    );
+    assert!(stdout(&["parse", "--format", "json", GRAMMAR, "!x"])
+        .starts_with(r#"{"name":"error","attributes":{"type":"parse"},"children":["Parse error"#));
+
+    let output = rustixml(&["parse", "--format", "yaml", GRAMMAR, "!1"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_analyze() {
+    let grammar = "!e: e, '+', n; n. n: ['0'-'9']+. old: 'x'.";
+    let output = rustixml(&["analyze", grammar]);
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("Left-recursive rules (may cause infinite loops):\n   - e\n"));
+    assert!(report.contains("Unreachable rules (never used from the start rule):\n   - old\n"));
+
+    let output = rustixml(&["analyze", "--json", grammar]);
+    let json = String::from_utf8(output.stdout).unwrap();
+    assert!(json.starts_with(r#"{"undefined_references":[],"#));
+    assert!(json.contains(r#""unused_rules":["old"]"#));
+
+    // An invalid grammar is status 2 for every command that reads one
+    for args in [
+        &["analyze", "!e: "][..],
+        &["stats", "!e: "],
+        &["visualize", "!e: "],
+        &["diff", "!e: 'a'.", "!e: "],
+        &["convert", "--to", "ixml", "!e: "],
+    ] {
+        let output = rustixml(args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+    }
+    let output = rustixml(&["convert", "--to", "ebnf", "!e: 'a'."]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_lint() {
+    let grammar = "!s: s, 'a'; 'b'. old: 'x'.";
+    let output = rustixml(&["lint", grammar]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "warning[unused-rule]: old: rule 'old' is never used\n\
+         warning[left-recursion]: Rule 's' is left-recursive\n\
+         2 finding(s) of severity warning or above\n"
+    );
+
+    // Only errors count at --severity error
+    let output = rustixml(&["lint", "--severity", "error", grammar]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let output = rustixml(&["lint", "--severity", "error", "!s: t."]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = rustixml(&["lint", "!s: 'a"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("error[grammar-syntax]"));
+}
+
+#[test]
+fn test_start_rule() {
+    let output = rustixml(&["parse", "--start", "n", GRAMMAR, "!42"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?><n>42</n>\n"
+    );
+
+    let output = rustixml(&["parse", "--start", "m", GRAMMAR, "!42"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Unknown start rule 'm'"));
+}
+
+#[test]
+fn test_repl() {
+    let session = "1,2\nx\n<<END\n3,\n4\nEND\n:start n\n5\n:start\n6\n:quit\n7\n";
+    let output = rustixml_with_stdin(&["repl", "!s: n++(',', #a?). n: ['0'-'9']+."], session);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "<s><n>1</n>,<n>2</n></s>\n\
+         <s><n>3</n>,\n<n>4</n></s>\n\
+         <n>5</n>\n\
+         <s>\n   <n>6</n>\n</s>\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let errors = stderr.lines().filter(|l| l.starts_with("Parse error:"));
+    assert_eq!(errors.count(), 1, "{}", stderr);
+}
+
+#[test]
+fn test_watch() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let dir = std::env::temp_dir().join(format!("rustixml-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let grammar = dir.join("g.ixml");
+    let input = dir.join("input.txt");
+    std::fs::write(&grammar, &GRAMMAR[1..]).unwrap();
+    std::fs::write(&input, "1").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rustixml"))
+        .args(["watch", "--interval", "20"])
+        .arg(&grammar)
+        .arg(&input)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let (sender, lines) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            let _ = sender.send(line);
+        }
+    });
+    let next_result = || loop {
+        let line = lines.recv_timeout(Duration::from_secs(10)).unwrap();
+        if line.contains("<n>") {
+            return line;
+        }
+    };
+
+    assert_eq!(next_result(), "   <n>1</n>");
+    std::fs::write(&input, "2,3").unwrap();
+    assert_eq!(next_result(), "<s><n>2</n>,<n>3</n></s>");
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_trace() {
+    let output = rustixml(&[
+        "parse",
+        "--trace",
+        "!s: a; b. a: 'x', 'y'. b: 'x', 'z'.",
+        "!xz",
+    ]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("  alternative 1 of s failed, backtracking to 0\n  enter b at 0\n"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("exit s at 0..2\n"), "{}", stderr);
+}
+
+#[test]
+fn test_bench() {
+    let output = rustixml(&["bench", "--iterations", "3", GRAMMAR, "!1,2,3"]);
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    for label in [
+        "Iterations:         3\n",
+        "Median:",
+        "P95:",
+        "Rules entered:",
+        "Backtracks:",
+    ] {
+        assert!(report.contains(label), "{}", report);
+    }
+
+    let output = rustixml(&["bench", GRAMMAR, "!x"]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_vxml_round_trip() {
+    let output = rustixml(&["to-vxml", GRAMMAR]);
+    assert!(output.status.success());
+    let vxml = String::from_utf8(output.stdout).unwrap();
+    assert!(vxml.contains("<rule name='s'>"), "{}", vxml);
+
+    let output = rustixml_with_stdin(&["from-vxml"], &vxml);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "s: n++\",\".\nn: [\"0\"-\"9\"]+.\n"
+    );
+}
+
+#[test]
+fn test_generate() {
+    let output = rustixml(&["generate", "--count", "5", "--seed", "7", GRAMMAR]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let inputs: Vec<&str> = stdout.lines().collect();
+    assert_eq!(inputs.len(), 5);
+    for input in &inputs {
+        let parsed = rustixml(&["parse", "--compact", GRAMMAR, &format!("!{}", input)]);
+        assert!(parsed.status.success(), "{}", input);
+    }
+    // The same seed gives the same inputs
+    let again = rustixml(&["generate", "--count", "5", "--seed", "7", GRAMMAR]);
+    assert_eq!(String::from_utf8(again.stdout).unwrap(), stdout);
+
+    let dir = std::env::temp_dir().join(format!("rustixml-generate-{}", std::process::id()));
+    let output = rustixml(&["generate", "-n", "3", "-o", dir.to_str().unwrap(), GRAMMAR]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    for i in 1..=3 {
+        let input = std::fs::read_to_string(dir.join(format!("{}.txt", i))).unwrap();
+        assert!(!input.is_empty());
+    }
+    assert!(!dir.join("4.txt").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_visualize() {
+    let output = rustixml(&["visualize", GRAMMAR]);
+    assert!(output.status.success());
+    let dot = String::from_utf8(output.stdout).unwrap();
+    assert!(dot.starts_with("digraph grammar {"));
+    assert!(dot.contains("\"s\" -> \"n\";"));
+
+    let output = rustixml(&["visualize", "--format", "svg", GRAMMAR]);
+    assert!(output.status.success());
+    let svg = String::from_utf8(output.stdout).unwrap();
+    assert!(svg.starts_with("<?xml"));
+    assert!(svg.contains("<svg xmlns='http://www.w3.org/2000/svg'"));
+    assert!(svg.contains(">\",\"</text>"));
+
+    let output = rustixml(&["visualize", "--format", "png", GRAMMAR]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_help() {
+    let output = rustixml(&["--help"]);
+    assert!(output.status.success());
+    let help = String::from_utf8(output.stdout).unwrap();
+    assert!(help.contains("    --indent         generate resulting xml with indentation.\n"));
+    assert!(help.contains("    generate         Write random inputs <GRAMMAR> accepts"));
+
+    let output = rustixml(&["bench", "-h"]);
+    let help = String::from_utf8(output.stdout).unwrap();
+    assert!(help.starts_with("Usage: "));
+    assert!(help.contains(" bench [-n <N>] <GRAMMAR> <INPUT>\n"));
+    assert!(help.contains("    -n, --iterations <N>\n"));
+
+    let output = rustixml(&["lint", "--severity", "fatal", GRAMMAR]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with(
+        "Error: Invalid value 'fatal' for --severity (expected error, warning, info)\n"
+    ));
+}
+
+#[test]
+fn test_completions() {
+    for shell in ["bash", "zsh", "fish"] {
+        let output = rustixml(&["completions", shell]);
+        assert!(output.status.success(), "{}", shell);
+        let script = String::from_utf8(output.stdout).unwrap();
+        assert!(script.contains("generate"), "{}", shell);
+        assert!(script.contains("max-depth"), "{}", shell);
+    }
+    let output = rustixml(&["completions", "tcsh"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = rustixml(&["man"]);
+    let page = String::from_utf8(output.stdout).unwrap();
+    assert!(page.starts_with(".TH RUSTIXML 1\n"));
+    assert!(page.contains(".SS generate\n"));
+}
+
+#[test]
+fn test_error_format_json() {
+    let grammar = "!s: n++(','; #a). n: ['0'-'9']+.";
+    let output = rustixml(&["parse", "--error-format", "json", grammar, "!1\n2x"]);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with(
+            "{\"file\":null,\"line\":2,\"column\":2,\"severity\":\"error\",\"code\":\"parse-error\","
+        ),
+        "{}",
+        stderr
+    );
+    assert_eq!(stderr.lines().count(), 1);
+
+    let output = rustixml(&["parse", "--error-format", "json", "!s: a.", "!x"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("\"code\":\"undefined-rule\""), "{}", stderr);
+
+    // Analysis findings are diagnostics too, not free text
+    let grammar = r#"!expr: expr, "+", n; n. n: ["0"-"9"]+."#;
+    let output = rustixml(&["parse", "--error-format", "json", grammar, "!1+x"]);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let codes: Vec<String> = stderr
+        .lines()
+        .map(|line| {
+            let json: serde_json::Value = serde_json::from_str(line).expect(line);
+            json["code"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(codes, ["left-recursion", "parse-error"]);
+
+    let output = rustixml(&["parse", grammar, "!1"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "warning[left-recursion]: Rule 'expr' is left-recursive\n"
+    );
+
+    let output = rustixml(&["lint", "--error-format", "json", "!s: a. a: 'a'. b: 'b'."]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for line in stderr.lines() {
+        assert!(
+            line.starts_with("{\"file\":null,") && line.ends_with("]}"),
+            "{}",
+            line
+        );
+    }
+    assert!(stderr.contains("\"code\":\"unused-rule\""), "{}", stderr);
+}
@@ -0,0 +1,101 @@
+//! Thread-safety tests
+//!
+//! Parsers, compiled grammars and parse results are meant to be shared across
+//! threads, and the Unicode category tables are process-global. These tests
+//! check the `Send`/`Sync` guarantees at compile time and hammer the shared
+//! state from parallel threads, including after a thread has panicked.
+
+use rustixml::charclass::unicode_category_to_rangeset;
+use rustixml::fixtures::FIXTURES;
+use rustixml::{
+    capabilities::Capabilities, CompiledGrammar, IxmlGrammar, NativeParser, ParseError,
+    ParseOutcome, XmlNode,
+};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+const THREADS: usize = 8;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn test_public_types_are_send_and_sync() {
+    assert_send_sync::<IxmlGrammar>();
+    assert_send_sync::<CompiledGrammar>();
+    assert_send_sync::<NativeParser>();
+    assert_send_sync::<XmlNode>();
+    assert_send_sync::<ParseError>();
+    assert_send_sync::<ParseOutcome>();
+    assert_send_sync::<Capabilities>();
+    assert_send_sync::<Result<String, String>>();
+}
+
+#[test]
+fn test_unicode_categories_under_contention() {
+    // Start every thread at once so they race to read the same categories
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let handles: Vec<_> = (0..THREADS)
+        .map(|i| {
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                let category = if i % 2 == 0 { "Zs" } else { "Nd" };
+                unicode_category_to_rangeset(category).unwrap()
+            })
+        })
+        .collect();
+
+    let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    for (i, result) in results.iter().enumerate() {
+        assert_eq!(result, &results[i % 2]);
+    }
+    #[cfg(not(feature = "minimal-unicode"))]
+    assert!(results[0].contains('\u{3000}') && results[1].contains('\u{663}'));
+}
+
+#[test]
+fn test_shared_compiled_grammars_in_parallel() {
+    let compiled: Vec<_> = FIXTURES
+        .iter()
+        .map(|fixture| Arc::new(CompiledGrammar::new(fixture.grammar_ast())))
+        .collect();
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let compiled = compiled.clone();
+            thread::spawn(move || {
+                for _ in 0..20 {
+                    for (fixture, grammar) in FIXTURES.iter().zip(&compiled) {
+                        let parser = NativeParser::from_compiled(Arc::clone(grammar));
+                        assert_eq!(parser.parse(fixture.input).as_deref(), Ok(fixture.expected));
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn test_panicking_thread_leaves_shared_parser_usable() {
+    let fixture = &FIXTURES[0];
+    let parser = Arc::new(fixture.parser());
+
+    let panicked = {
+        let parser = Arc::clone(&parser);
+        thread::spawn(move || {
+            parser.parse(fixture.input).unwrap();
+            unicode_category_to_rangeset("Zs").unwrap();
+            panic!("worker failed after parsing");
+        })
+        .join()
+    };
+    assert!(panicked.is_err());
+
+    // The parser and the category tables hold no locks, so the panic leaves
+    // nothing behind for other threads to trip over
+    assert_eq!(parser.parse(fixture.input).as_deref(), Ok(fixture.expected));
+    assert!(unicode_category_to_rangeset("Zs").unwrap().contains(' '));
+}
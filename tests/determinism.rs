@@ -0,0 +1,213 @@
+//! Cross-target determinism tests
+//!
+//! Parse output must be byte-for-byte identical whether rustixml runs natively
+//! or as WebAssembly. The expected outputs in `determinism_vectors.txt` are
+//! generated by a native build and checked on every target:
+//!
+//! ```text
+//! cargo test --test determinism                                 # native
+//! wasm-pack test --node -- --test determinism                   # wasm32
+//! RUSTIXML_BLESS=1 cargo test --test determinism                # regenerate
+//! ```
+//!
+//! Each vector line is `name<TAB>ok|err<TAB>output`, with `\\`, `\n`, `\r`
+//! and `\t` escaped so outputs containing line breaks stay on one line.
+//!
+//! The vectors rely on the full Unicode tables, so builds with the
+//! `minimal-unicode` feature skip them.
+
+#![cfg(not(feature = "minimal-unicode"))]
+
+use rustixml::{parse_ixml_grammar, NativeParser, ParseStrategy};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const VECTORS: &str = include_str!("determinism_vectors.txt");
+
+/// (name, grammar, input, strategy)
+const CASES: &[(&str, &str, &str, ParseStrategy)] = &[
+    (
+        "letters-non-bmp",
+        "word: [L]+.",
+        "Grüße\u{1D538}ñ",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "upper-lower",
+        "s: (u; l)+. u: [Lu]. l: [Ll].",
+        "ΑβÇdЖ",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "decimal-digits",
+        "num: [Nd]+.",
+        "\u{663}\u{664}5\u{1D7D8}",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "marks-and-spaces",
+        "s: (l; m; z; p)*. l: [L]. m: [M]. z: [Zs]. p: [P].",
+        "e\u{301} a\u{3000}!",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "negated-class",
+        r#"s: ~["a"-"z"]+."#,
+        "\u{1F600}€ß\u{10FFFD}",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "hex-chars",
+        "s: #1F600, d+. d: [#30-#39].",
+        "\u{1F600}42",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "text-escaping",
+        "s: [Zs; L; P; S]*.",
+        r#"<a & 'b' "c">"#,
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "attribute-escaping",
+        r#"s: @v, ".". v: ~["."]+."#,
+        "a<'&\">\u{E9}.",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "marks",
+        r#"s: -a, ^b, c. a: "x". -b: "y". @c: "z"."#,
+        "xyz",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "line-breaks",
+        "lines: line++nl. line: ~[#a]*. -nl: #a.",
+        "one\ntwo\r\n\tthree",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "ambiguous",
+        r#"s: a, ".". a: "x"; ["x"]."#,
+        "x.",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "parse-error",
+        r#"s: "a", [Nd]."#,
+        "a\u{1F600}",
+        ParseStrategy::RecursiveDescent,
+    ),
+    (
+        "earley-marks-and-spaces",
+        "s: (l; m; z; p)*. l: [L]. m: [M]. z: [Zs]. p: [P].",
+        "e\u{301} a\u{3000}!",
+        ParseStrategy::Earley,
+    ),
+    (
+        "earley-ambiguous",
+        r#"s: a, ".". a: "x"; ["x"]."#,
+        "x.",
+        ParseStrategy::Earley,
+    ),
+];
+
+fn run_case(grammar: &str, input: &str, strategy: ParseStrategy) -> (bool, String) {
+    let grammar = parse_ixml_grammar(grammar).expect("vector grammar should parse");
+    match NativeParser::with_strategy(grammar, strategy).parse(input) {
+        Ok(xml) => (true, xml),
+        Err(e) => (false, e.to_string()),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Expected (success, output) for a vector, by name
+fn expected(name: &str) -> (bool, String) {
+    let line = VECTORS
+        .lines()
+        .find(|line| line.split('\t').next() == Some(name))
+        .unwrap_or_else(|| panic!("no vector for '{}'; regenerate with RUSTIXML_BLESS=1", name));
+    let mut fields = line.splitn(3, '\t').skip(1);
+    let status = fields.next().expect("vector status");
+    let output = fields.next().unwrap_or("");
+    (status == "ok", unescape(output))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn bless() {
+    let vectors: String = CASES
+        .iter()
+        .map(|(name, grammar, input, strategy)| {
+            let (ok, output) = run_case(grammar, input, *strategy);
+            let status = if ok { "ok" } else { "err" };
+            format!("{}\t{}\t{}\n", name, status, escape(&output))
+        })
+        .collect();
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/determinism_vectors.txt");
+    std::fs::write(path, vectors).expect("failed to write determinism vectors");
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn test_outputs_match_vectors() {
+    #[cfg(not(target_arch = "wasm32"))]
+    if std::env::var_os("RUSTIXML_BLESS").is_some() {
+        bless();
+        return;
+    }
+
+    for (name, grammar, input, strategy) in CASES {
+        let actual = run_case(grammar, input, *strategy);
+        assert_eq!(actual, expected(name), "vector '{}' differs", name);
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+fn test_vector_escaping_round_trips() {
+    let tricky = "a\\nb\n\r\t\\";
+    assert_eq!(unescape(&escape(tricky)), tricky);
+}
+
+/// The JS-facing binding must agree with the Rust API it wraps
+#[cfg(all(target_arch = "wasm32", not(feature = "ic-canister")))]
+#[wasm_bindgen_test]
+fn test_wasm_binding_matches_vectors() {
+    for (name, grammar, input, strategy) in CASES {
+        if *strategy != ParseStrategy::RecursiveDescent {
+            continue;
+        }
+        let (ok, output) = expected(name);
+        let result = rustixml::wasm::parse_ixml(grammar, input);
+        assert_eq!(result.success(), ok, "vector '{}' differs", name);
+        if ok {
+            assert_eq!(result.output(), output, "vector '{}' differs", name);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+"""Smoke test for the Python bindings (run after `maturin develop`)."""
+
+import rustixml
+
+grammar = rustixml.IxmlGrammar('doc: item+. item: @id, "-". id: ["a"-"z"].')
+assert grammar.rule_names == ["doc", "item", "id"]
+
+parser = rustixml.Parser(grammar)
+assert parser.parse("a-") == "<doc><item id='a'>-</item></doc>"
+assert parser.parse_with_start("b-", "item") == "<item id='b'>-</item>"
+assert parser.parse_to_dict("a-b-") == {
+    "name": "doc",
+    "attributes": {},
+    "children": [
+        {"name": "item", "attributes": {"id": "a"}, "children": ["-"]},
+        {"name": "item", "attributes": {"id": "b"}, "children": ["-"]},
+    ],
+}
+
+try:
+    parser.parse("a-1")
+except rustixml.ParseError as e:
+    assert (e.line, e.column, e.offset) == (1, 3, 2), e
+    assert e.expected == ["item"], e.expected
+else:
+    raise AssertionError("expected ParseError")
+
+try:
+    rustixml.IxmlGrammar("doc: item.")
+except rustixml.GrammarError as e:
+    assert e.code == "undefined-rule", e
+    assert isinstance(e, ValueError)
+else:
+    raise AssertionError("expected GrammarError")
+
+print("Python bindings OK")
@@ -0,0 +1,66 @@
+//! The `rustixml compare` subcommand against stand-in reference processors
+
+#![cfg(unix)]
+
+use std::process::{Command, Output};
+
+fn compare(reference: &str, grammar: &str, input: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rustixml"))
+        .args(["compare", "--reference", reference, grammar, input])
+        .env_remove("RUSTIXML_REFERENCE")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_compare_with_itself() {
+    // rustixml's own command line tool is a reference that always agrees
+    let reference = format!("{} {{grammar}} {{input}}", env!("CARGO_BIN_EXE_rustixml"));
+    let output = compare(&reference, "!s: n++','. n: ['0'-'9']+.", "!1,22");
+    assert!(output.status.success());
+    // "Outputs match" is only for a terminal; piped, the status says it
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_compare_reports_differences() {
+    // A stand-in processor that ignores its arguments and writes a fixed document
+    let script = std::env::temp_dir().join(format!("rustixml-reference-{}.sh", std::process::id()));
+    std::fs::write(&script, "echo '<s>\n  <n>1</n>\n  <n>2</n>\n</s>'\n").unwrap();
+    let reference = format!("sh {} {{grammar}} {{input}}", script.display());
+
+    let grammar = "!s: n++-','. n: ['0'-'9']+.";
+    let output = compare(&reference, grammar, "!1,2");
+    assert!(output.status.success());
+
+    let output = compare(&reference, grammar, "!1,3,4");
+    std::fs::remove_file(&script).unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "/s: reference has 2 children, rustixml has 3\n\
+         /s/n[2]/text()[1]: reference has \"2\", rustixml has \"3\"\n"
+    );
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("2 difference(s) from the reference"));
+}
+
+#[test]
+fn test_compare_needs_a_reference() {
+    let output = compare("cat {input}", "!s: 'a'.", "!a");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("needs both {grammar} and {input} placeholders"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rustixml"))
+        .args(["compare", "!s: 'a'.", "!a"])
+        .env_remove("RUSTIXML_REFERENCE")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("set RUSTIXML_REFERENCE"));
+}
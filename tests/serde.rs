@@ -0,0 +1,45 @@
+//! Round trips of grammars and result trees through serde
+//!
+//! Needs the `serde` feature: `cargo test --features serde --test serde`.
+
+#![cfg(feature = "serde")]
+
+use rustixml::fixtures::FIXTURES;
+use rustixml::{parse_ixml_grammar, IxmlGrammar, NativeParser, XmlNode};
+
+#[test]
+fn grammars_round_trip_through_json() {
+    for fixture in FIXTURES {
+        let grammar = parse_ixml_grammar(fixture.grammar).unwrap();
+        let json = serde_json::to_string(&grammar).unwrap();
+        let cached: IxmlGrammar = serde_json::from_str(&json).unwrap();
+        assert_eq!(cached, grammar, "{}", fixture.name);
+
+        // A grammar loaded from the cache parses like the original
+        let parser = NativeParser::new(cached);
+        assert_eq!(
+            parser.parse(fixture.input).unwrap(),
+            fixture.expected,
+            "{}",
+            fixture.name
+        );
+    }
+}
+
+#[test]
+fn result_trees_round_trip_through_json() {
+    for fixture in FIXTURES {
+        let tree = fixture.parser().parse_to_tree(fixture.input).unwrap();
+        let json = serde_json::to_string(&tree).unwrap();
+        let restored: XmlNode = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, tree, "{}", fixture.name);
+    }
+
+    let node = XmlNode::element("day")
+        .with_attribute("n", "15")
+        .with_child(XmlNode::text("Monday"));
+    assert_eq!(
+        serde_json::to_string(&node).unwrap(),
+        r#"{"Element":{"name":"day","attributes":[["n","15"]],"children":[{"Text":"Monday"}]}}"#
+    );
+}
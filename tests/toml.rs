@@ -0,0 +1,157 @@
+//! End-to-end test of the TOML-to-XML example
+//!
+//! Runs `examples/toml_to_xml` through every layer: the grammar (marks,
+//! separators, character classes, attributes), the typed mapping, JSON and
+//! TOML output, and the `rustixml` command line tool.
+
+#[path = "../examples/toml_to_xml/mapping.rs"]
+mod mapping;
+
+use mapping::{Document, Table, Value};
+use std::process::Command;
+
+const SAMPLE: &str = include_str!("../examples/toml_to_xml/sample.toml");
+
+const SAMPLE_XML: &str = "<toml>\
+<root><keyval key='title'><string>TOML \\\"example\\\"</string></keyval></root>\
+<table name='owner'>\
+<keyval key='name'><string>Tom</string></keyval>\
+<keyval key='dob'><date>1979-05-27</date></keyval>\
+</table>\
+<table name='database.primary'>\
+<keyval key='ports'><array><integer>8000</integer><integer>8001</integer><integer>8002</integer></array></keyval>\
+<keyval key='limits'><array>\
+<array><integer>1</integer><integer>2</integer></array>\
+<array><float>3.5</float><float>-4e2</float></array>\
+</array></keyval>\
+<keyval key='enabled'><boolean>true</boolean></keyval>\
+<keyval key='connection max'><integer>5000</integer></keyval>\
+</table></toml>";
+
+fn sample_document() -> Document {
+    let entry = |key: &str, value| (key.to_string(), value);
+    Document {
+        tables: vec![
+            Table {
+                name: None,
+                entries: vec![entry("title", Value::String("TOML \"example\"".into()))],
+            },
+            Table {
+                name: Some("owner".into()),
+                entries: vec![
+                    entry("name", Value::String("Tom".into())),
+                    entry("dob", Value::Date("1979-05-27".into())),
+                ],
+            },
+            Table {
+                name: Some("database.primary".into()),
+                entries: vec![
+                    entry(
+                        "ports",
+                        Value::Array(vec![
+                            Value::Integer(8000),
+                            Value::Integer(8001),
+                            Value::Integer(8002),
+                        ]),
+                    ),
+                    entry(
+                        "limits",
+                        Value::Array(vec![
+                            Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+                            Value::Array(vec![Value::Float(3.5), Value::Float(-400.0)]),
+                        ]),
+                    ),
+                    entry("enabled", Value::Boolean(true)),
+                    entry("connection max", Value::Integer(5000)),
+                ],
+            },
+        ],
+    }
+}
+
+#[test]
+fn test_sample_to_xml() {
+    assert_eq!(mapping::parser().parse(SAMPLE).unwrap(), SAMPLE_XML);
+}
+
+#[test]
+fn test_typed_mapping_and_json() {
+    let document = Document::parse(&mapping::parser(), SAMPLE).unwrap();
+    assert_eq!(document, sample_document());
+    assert_eq!(
+        document.to_json(),
+        r#"{"title":"TOML \"example\"","owner":{"name":"Tom","dob":"1979-05-27"},"database.primary":{"ports":[8000,8001,8002],"limits":[[1,2],[3.5,-400]],"enabled":true,"connection max":5000}}"#
+    );
+}
+
+#[test]
+fn test_toml_round_trip() {
+    let parser = mapping::parser();
+    let document = Document::parse(&parser, SAMPLE).unwrap();
+    let written = document.to_toml();
+    assert!(written.contains("limits = [[1, 2], [3.5, -400.0]]\n"));
+
+    let reparsed = Document::parse(&parser, &written).unwrap();
+    assert_eq!(reparsed, document);
+    assert_eq!(reparsed.to_toml(), written);
+
+    // Escapes survive the trip in both directions
+    let escapes = "s = \"tab\\tquote\\\"slash\\\\\"\n";
+    let document = Document::parse(&parser, escapes).unwrap();
+    assert_eq!(
+        document.tables[0].entries[0].1,
+        Value::String("tab\tquote\"slash\\".into())
+    );
+    assert_eq!(document.to_toml(), escapes);
+}
+
+#[test]
+fn test_invalid_toml_reports_position() {
+    let parser = mapping::parser();
+    let err = Document::parse(&parser, "[server]\nports = [80, 443\n").unwrap_err();
+    assert!(err.starts_with("Parse error at line 2"), "{}", err);
+    assert!(Document::parse(&parser, "key = 'single'\n").is_err());
+}
+
+#[test]
+fn test_cli_matches_library() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/toml_to_xml");
+    let output = Command::new(env!("CARGO_BIN_EXE_rustixml"))
+        .arg(format!("{}/toml.ixml", dir))
+        .arg(format!("{}/sample.toml", dir))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>{}\n", SAMPLE_XML)
+    );
+}
+
+#[test]
+fn test_cli_streaming_input() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/toml_to_xml");
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rustixml"))
+            .args(args)
+            .arg(format!("{}/toml.ixml", dir))
+            .arg(format!("{}/sample.toml", dir))
+            .output()
+            .unwrap()
+    };
+    let expected = format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>{}\n", SAMPLE_XML);
+
+    let output = run(&["--stream"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+
+    // Memory-mapped input needs the `mmap` feature
+    let output = run(&["--mmap", "--fail-on-error"]);
+    if cfg!(feature = "mmap") {
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    } else {
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("`mmap` feature"));
+    }
+}